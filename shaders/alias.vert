@@ -1,10 +1,17 @@
 #version 450
 
+// position in the current pose
 layout(location = 0) in vec3 a_position1;
-// layout(location = 1) in vec3 a_position2;
+// position in the previous pose
+layout(location = 1) in vec3 a_position2;
 layout(location = 2) in vec3 a_normal;
 layout(location = 3) in vec2 a_diffuse;
 
+layout(push_constant) uniform PushConstants {
+  // how far to blend from the previous pose to the current one
+  float blend;
+} push_constants;
+
 layout(location = 0) out vec3 f_normal;
 layout(location = 1) out vec2 f_diffuse;
 
@@ -21,6 +28,7 @@ vec3 convert(vec3 from) {
 void main() {
   f_normal = mat3(transpose(inverse(entity_uniforms.u_model))) * convert(a_normal);
   f_diffuse = a_diffuse;
+  vec3 position = mix(a_position2, a_position1, push_constants.blend);
   gl_Position = entity_uniforms.u_transform
-      * vec4(convert(a_position1), 1.0);
+      * vec4(convert(position), 1.0);
 }
@@ -4,15 +4,22 @@
 const uint TEXTURE_KIND_REGULAR = 0;
 const uint TEXTURE_KIND_WARP = 1;
 const uint TEXTURE_KIND_SKY = 2;
+const uint TEXTURE_KIND_SKYBOX = 3;
 
 const float WARP_AMPLITUDE = 0.15;
 const float WARP_FREQUENCY = 0.25;
 const float WARP_SCALE = 1.0;
 
+// sky layer scroll speeds, in texels per second
+const float SKY_BACK_SPEED = 8.0;
+const float SKY_FRONT_SPEED = 16.0;
+const float SKY_TEXTURE_SIZE = 128.0;
+
 layout(location = 0) in vec3 f_normal;
 layout(location = 1) in vec2 f_diffuse; // also used for fullbright
 layout(location = 2) in vec2 f_lightmap;
 flat layout(location = 3) in uvec4 f_lightmap_anim;
+layout(location = 4) in vec3 f_sky_dir;
 
 layout(push_constant) uniform PushConstants {
   layout(offset = 128) uint texture_kind;
@@ -24,6 +31,8 @@ layout(set = 0, binding = 0) uniform FrameUniforms {
     vec4 camera_pos;
     float time;
     bool r_lightmap;
+    bool r_fullbright;
+    bool r_waterwarp;
 } frame_uniforms;
 
 // set 1: per-entity
@@ -31,11 +40,11 @@ layout(set = 1, binding = 1) uniform sampler u_diffuse_sampler; // also used for
 layout(set = 1, binding = 2) uniform sampler u_lightmap_sampler;
 
 // set 2: per-texture
+// for sky textures, these hold the back and front layers respectively
 layout(set = 2, binding = 0) uniform texture2D u_diffuse_texture;
 layout(set = 2, binding = 1) uniform texture2D u_fullbright_texture;
-layout(set = 2, binding = 2) uniform TextureUniforms {
-    uint kind;
-} texture_uniforms;
+// layers face +X, -X, +Y, -Y, +Z and -Z in Quake coordinates
+layout(set = 2, binding = 2) uniform texture2DArray u_skybox_texture;
 
 // set 3: per-face
 layout(set = 3, binding = 0) uniform texture2D u_lightmap_texture[4];
@@ -61,6 +70,36 @@ vec4 calc_light() {
     return light / 4.0;
 }
 
+// project the view direction onto the sky dome, scrolling at the given speed
+vec2 sky_texcoord(float speed) {
+    vec3 dir = f_sky_dir;
+    dir.z *= 3.0;
+
+    // the coefficients here are magic taken from the Quake source
+    float len = 6.0 * 63.0 / length(dir);
+    float scroll = mod(speed * frame_uniforms.time, SKY_TEXTURE_SIZE);
+    return (scroll + dir.xy * len) / SKY_TEXTURE_SIZE;
+}
+
+// find the sky box face the view direction passes through and the texcoords on
+// that face, oriented as in Quake 2
+vec3 skybox_texcoord() {
+    vec3 dir = f_sky_dir;
+    vec3 mag = abs(dir);
+
+    // (s, t) in [-1, 1] and the face's layer
+    vec3 stl;
+    if (mag.x >= mag.y && mag.x >= mag.z) {
+        stl = vec3(-dir.y / dir.x, dir.z / mag.x, dir.x > 0.0 ? 0.0 : 1.0);
+    } else if (mag.y >= mag.z) {
+        stl = vec3(dir.x / dir.y, dir.z / mag.y, dir.y > 0.0 ? 2.0 : 3.0);
+    } else {
+        stl = vec3(-dir.y / mag.z, -dir.x / dir.z, dir.z > 0.0 ? 4.0 : 5.0);
+    }
+
+    return vec3((stl.x + 1.0) * 0.5, (1.0 - stl.y) * 0.5, stl.z);
+}
+
 void main() {
     switch (push_constants.texture_kind) {
         case TEXTURE_KIND_REGULAR:
@@ -76,6 +115,9 @@ void main() {
 
             if (fullbright != 0.0) {
                 light_attachment = vec4(1.0, 1.0, 1.0, 1.0);
+            } else if (frame_uniforms.r_fullbright) {
+                // unlit texture color, same scale as calc_light()
+                light_attachment = vec4(0.25, 0.0, 0.0, 0.0);
             } else {
                 light_attachment = calc_light();
             }
@@ -87,8 +129,11 @@ void main() {
                 * (WARP_SCALE * f_diffuse.ts
                     + WARP_FREQUENCY * frame_uniforms.time);
 
-            vec2 warp_texcoord = f_diffuse.st + WARP_AMPLITUDE
-                * vec2(sin(wave1.s), sin(wave1.t));
+            vec2 warp_texcoord = f_diffuse.st;
+            if (frame_uniforms.r_waterwarp) {
+                warp_texcoord += WARP_AMPLITUDE
+                    * vec2(sin(wave1.s), sin(wave1.t));
+            }
 
             diffuse_attachment = texture(
                 sampler2D(u_diffuse_texture, u_diffuse_sampler),
@@ -98,27 +143,26 @@ void main() {
             break;
 
         case TEXTURE_KIND_SKY:
-            vec2 base = mod(f_diffuse + frame_uniforms.time, 1.0);
-            vec2 cloud_texcoord = vec2(base.s * 0.5, base.t);
-            vec2 sky_texcoord = vec2(base.s * 0.5 + 0.5, base.t);
-
-            vec4 sky_color = texture(
+            vec4 back_color = texture(
                 sampler2D(u_diffuse_texture, u_diffuse_sampler),
-                sky_texcoord
+                sky_texcoord(SKY_BACK_SPEED)
             );
-            vec4 cloud_color = texture(
-                sampler2D(u_diffuse_texture, u_diffuse_sampler),
-                cloud_texcoord
+            vec4 front_color = texture(
+                sampler2D(u_fullbright_texture, u_diffuse_sampler),
+                sky_texcoord(SKY_FRONT_SPEED)
             );
 
-            // 0.0 if black, 1.0 otherwise
-            float cloud_factor;
-            if (cloud_color.r + cloud_color.g + cloud_color.b == 0.0) {
-                cloud_factor = 0.0;
-            } else {
-                cloud_factor = 1.0;
-            }
-            diffuse_attachment = mix(sky_color, cloud_color, cloud_factor);
+            // the front layer is transparent wherever it's palette index 0
+            diffuse_attachment = vec4(mix(back_color.rgb, front_color.rgb, front_color.a), 1.0);
+            light_attachment = vec4(1.0, 1.0, 1.0, 1.0);
+            break;
+
+        case TEXTURE_KIND_SKYBOX:
+            // the lightmap sampler clamps to the edge, so faces don't bleed into each other
+            diffuse_attachment = texture(
+                sampler2DArray(u_skybox_texture, u_lightmap_sampler),
+                skybox_texcoord()
+            );
             light_attachment = vec4(1.0, 1.0, 1.0, 1.0);
             break;
 
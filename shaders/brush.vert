@@ -3,6 +3,7 @@
 const uint TEXTURE_KIND_NORMAL = 0;
 const uint TEXTURE_KIND_WARP = 1;
 const uint TEXTURE_KIND_SKY = 2;
+const uint TEXTURE_KIND_SKYBOX = 3;
 
 layout(location = 0) in vec3 a_position;
 layout(location = 1) in vec3 a_normal;
@@ -20,6 +21,7 @@ layout(location = 0) out vec3 f_normal;
 layout(location = 1) out vec2 f_diffuse;
 layout(location = 2) out vec2 f_lightmap;
 layout(location = 3) out uvec4 f_lightmap_anim;
+layout(location = 4) out vec3 f_sky_dir;
 
 layout(set = 0, binding = 0) uniform FrameUniforms {
     float light_anim_frames[64];
@@ -33,18 +35,17 @@ vec3 convert(vec3 from) {
 }
 
 void main() {
-    if (push_constants.texture_kind == TEXTURE_KIND_SKY) {
-        vec3 dir = a_position - frame_uniforms.camera_pos.xyz;
-        dir.z *= 3.0;
-
-        // the coefficients here are magic taken from the Quake source
-        float len = 6.0 * 63.0 / length(dir);
-        dir = vec3(dir.xy * len, dir.z);
-        f_diffuse = (mod(8.0 * frame_uniforms.time, 128.0) + dir.xy) / 128.0;
+    if (push_constants.texture_kind == TEXTURE_KIND_SKY
+        || push_constants.texture_kind == TEXTURE_KIND_SKYBOX) {
+        // the direction is linear across the face, so it can be interpolated
+        // and projected onto the sky dome per-fragment
+        f_sky_dir = a_position - frame_uniforms.camera_pos.xyz;
     } else {
-        f_diffuse = a_diffuse;
+        f_sky_dir = vec3(0.0);
     }
 
+    f_diffuse = a_diffuse;
+
     f_normal = mat3(transpose(inverse(push_constants.model_view))) * convert(a_normal);
     f_lightmap = a_lightmap;
     f_lightmap_anim = a_lightmap_anim;
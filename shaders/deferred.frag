@@ -3,6 +3,10 @@
 // if this is changed, it must also be changed in client::entity
 const uint MAX_LIGHTS = 32;
 
+// if these are changed, they must also be changed in client::render::world::deferred
+const uint LIGHT_MODE_CLASSIC = 1;
+const uint LIGHT_MODE_PER_PIXEL = 2;
+
 layout(location = 0) in vec2 a_texcoord;
 
 layout(set = 0, binding = 0) uniform sampler u_sampler;
@@ -13,8 +17,8 @@ layout(set = 0, binding = 4) uniform texture2DMS u_depth;
 layout(set = 0, binding = 5) uniform DeferredUniforms {
   mat4 inv_projection;
   uint light_count;
-  uint _pad1;
-  uvec2 _pad2;
+  uint light_mode;
+  uvec2 _pad;
   vec4 lights[MAX_LIGHTS];
 } u_deferred;
 
@@ -61,8 +65,15 @@ void main() {
     float radius = dlight_radius(dlight);
 
     if (dist < radius && dot(dir, in_normal) < 0.0) {
-      // linear attenuation
-      light += (radius - dist) / radius;
+      if (u_deferred.light_mode == LIGHT_MODE_PER_PIXEL) {
+        // smooth inverse-square-like falloff, shaded by the angle of incidence.
+        // the factor of 2 keeps overall brightness close to the classic model
+        float falloff = 1.0 - (dist * dist) / (radius * radius);
+        light += 2.0 * falloff * falloff * dot(-dir, in_normal);
+      } else {
+        // linear attenuation
+        light += (radius - dist) / radius;
+      }
     }
   }
 
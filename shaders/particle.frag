@@ -2,8 +2,14 @@
 
 layout(location = 0) in vec2 f_texcoord;
 
+// these must match ParticleStyle
+const uint STYLE_SQUARE = 0;
+const uint STYLE_ROUND = 1;
+const uint STYLE_TEXTURED = 2;
+
 layout(push_constant) uniform PushConstants {
   layout(offset = 64) uint color;
+  uint style;
 } push_constants;
 
 layout(set = 0, binding = 0) uniform sampler u_sampler;
@@ -14,15 +20,36 @@ layout(location = 0) out vec4 diffuse_attachment;
 layout(location = 2) out vec4 light_attachment;
 
 void main() {
-  vec4 tex_color = texture(
-    sampler2D(u_texture[push_constants.color], u_sampler),
-    f_texcoord
-  );
+  vec4 tex_color;
+  float alpha = 1.0;
+
+  if (push_constants.style == STYLE_TEXTURED) {
+    tex_color = texture(
+      sampler2D(u_texture[push_constants.color], u_sampler),
+      f_texcoord
+    );
+
+    if (tex_color.a == 0.0) {
+      discard;
+    }
+  } else {
+    // the center of the particle texture is always filled
+    tex_color = texture(
+      sampler2D(u_texture[push_constants.color], u_sampler),
+      vec2(0.5, 0.5)
+    );
+
+    if (push_constants.style == STYLE_ROUND) {
+      float dist = length(f_texcoord * 2.0 - 1.0);
+      float edge = fwidth(dist);
+      alpha = 1.0 - smoothstep(1.0 - edge, 1.0, dist);
 
-  if (tex_color.a == 0.0) {
-    discard;
+      if (alpha == 0.0) {
+        discard;
+      }
+    }
   }
 
-  diffuse_attachment = tex_color;
-  light_attachment = vec4(1.0, 1.0, 1.0, 1.0);
+  diffuse_attachment = vec4(tex_color.rgb, alpha);
+  light_attachment = vec4(1.0, 1.0, 1.0, alpha);
 }
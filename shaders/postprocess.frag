@@ -8,11 +8,28 @@ layout(set = 0, binding = 0) uniform sampler u_sampler;
 layout(set = 0, binding = 1) uniform texture2DMS u_color;
 layout(set = 0, binding = 2) uniform PostProcessUniforms {
   vec4 color_shift;
+  vec4 view_warp;
 } postprocess_uniforms;
 
+const float PI = 3.14159265359;
+const float VIEW_WARP_AMPLITUDE = 0.004;
+const float VIEW_WARP_CYCLES = 2.0;
+
 void main() {
   ivec2 dims = textureSize(sampler2DMS(u_color, u_sampler));
-  ivec2 texcoord = ivec2(vec2(dims) * a_texcoord);
+
+  // ripple the screen while the view is inside a liquid
+  vec2 warped = a_texcoord;
+  if (postprocess_uniforms.view_warp.y != 0.0) {
+    float time = postprocess_uniforms.view_warp.x;
+    warped += VIEW_WARP_AMPLITUDE * vec2(
+      sin(2.0 * PI * (VIEW_WARP_CYCLES * a_texcoord.y + time)),
+      cos(2.0 * PI * (VIEW_WARP_CYCLES * a_texcoord.x + time))
+    );
+    warped = clamp(warped, vec2(0.0), vec2(1.0));
+  }
+
+  ivec2 texcoord = min(ivec2(vec2(dims) * warped), dims - 1);
 
   vec4 in_color = texelFetch(sampler2DMS(u_color, u_sampler), texcoord, gl_SampleID);
 
@@ -1,12 +1,10 @@
 use std::{
     cell::RefCell,
-    fs::File,
-    io::BufWriter,
     path::{Path, PathBuf},
     rc::Rc,
 };
 
-use richter::client::render::Extent2d;
+use richter::{client::render::Extent2d, common::fs_util::AtomicFile};
 
 use chrono::Utc;
 
@@ -109,15 +107,15 @@ impl Capture {
         }
         self.buffer.unmap();
 
-        let f = File::create(path).unwrap();
-        let mut png_encoder = png::Encoder::new(
-            BufWriter::new(f),
-            self.capture_size.width,
-            self.capture_size.height,
-        );
-        png_encoder.set_color(png::ColorType::RGBA);
-        png_encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = png_encoder.write_header().unwrap();
-        writer.write_image_data(&data).unwrap();
+        let mut f = AtomicFile::create(path).unwrap();
+        {
+            let mut png_encoder =
+                png::Encoder::new(&mut f, self.capture_size.width, self.capture_size.height);
+            png_encoder.set_color(png::ColorType::RGBA);
+            png_encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = png_encoder.write_header().unwrap();
+            writer.write_image_data(&data).unwrap();
+        }
+        f.commit().unwrap();
     }
 }
@@ -20,32 +20,39 @@
 
 use std::{
     cell::{Cell, RefCell},
-    path::PathBuf,
+    net::SocketAddr,
+    path::{Path, PathBuf},
     rc::Rc,
 };
 
+#[cfg(feature = "capture")]
+use crate::capture::{cmd_screenshot, Capture};
 use crate::{
-    capture::{cmd_screenshot, Capture},
+    lightdump::{cmd_lightdump, write_light_csv},
     trace::{cmd_trace_begin, cmd_trace_end},
+    watch::FileWatcher,
 };
 
 use richter::{
     client::{
+        connection::ConnectionState,
         entity::MAX_LIGHTS,
-        input::{Input, InputFocus},
+        input::{game::Action, Input, InputFocus},
         menu::Menu,
+        presence::PresenceState,
         render::{
-            Camera, DeferredRenderer, DeferredUniforms, Extent2d, GraphicsState, HudState,
-            PointLight, PostProcessRenderer, RenderTarget as _, RenderTargetResolve as _,
-            SwapChainTarget, UiOverlay, UiRenderer, UiState, WorldRenderer,
+            Camera, ConsoleSlide, DeferredRenderer, DeferredUniforms, Extent2d, GraphicsState,
+            HudLayout, HudState, MeshCache, PointLight, PostProcessRenderer, RenderTarget as _,
+            RenderTargetResolve as _, Skybox, SwapChainTarget, UiOverlay, UiRenderer, UiState,
+            WorldRenderer, LIGHT_MODE_CLASSIC, LIGHT_MODE_PER_PIXEL,
         },
         trace::TraceFrame,
         Client,
     },
     common::{
-        console::{CmdRegistry, Console, CvarRegistry},
-        math,
-        net::SignOnStage,
+        console::{CmdRegistry, Console, ConsoleOutput, CvarRegistry},
+        engine, l10n, math,
+        model::ModelKind,
     },
 };
 
@@ -53,9 +60,9 @@ use bumpalo::Bump;
 use cgmath::{self, SquareMatrix as _, Vector3, Zero as _};
 use chrono::Duration;
 use failure::Error;
-use log::info;
+use log::{debug, info, warn};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum InGameFocus {
     // active in game
     Game,
@@ -72,6 +79,10 @@ struct InGameState {
     deferred_renderer: DeferredRenderer,
     postprocess_renderer: PostProcessRenderer,
     focus: Rc<Cell<InGameFocus>>,
+    console_slide: ConsoleSlide,
+
+    // loose model and skin files used by the current map
+    asset_watcher: FileWatcher,
 }
 
 impl InGameState {
@@ -81,8 +92,10 @@ impl InGameState {
         deferred_renderer: DeferredRenderer,
         postprocess_renderer: PostProcessRenderer,
         focus: InGameFocus,
+        asset_watcher: FileWatcher,
     ) -> InGameState {
         let focus_rc = Rc::new(Cell::new(focus));
+        let console_slide = ConsoleSlide::new();
         let toggleconsole_focus = focus_rc.clone();
 
         cmds.borrow_mut()
@@ -128,6 +141,8 @@ impl InGameState {
             deferred_renderer,
             postprocess_renderer,
             focus: focus_rc,
+            console_slide,
+            asset_watcher,
         }
     }
 }
@@ -153,31 +168,80 @@ pub struct Game {
     render_pass_bump: Bump,
     state: GameState,
     input: Rc<RefCell<Input>>,
+    basedir: PathBuf,
+    hud_layout: Rc<RefCell<HudLayout>>,
+    mesh_cache: Rc<MeshCache>,
     client: Client,
+    output: Rc<RefCell<ConsoleOutput>>,
 
     // if Some(v), trace is in progress
     trace: Rc<RefCell<Option<Vec<TraceFrame>>>>,
 
     // if Some(path), take a screenshot and save it to path
+    #[cfg(feature = "capture")]
     screenshot_path: Rc<RefCell<Option<PathBuf>>>,
+
+    // if Some(path), write the world's face light levels to path
+    lightdump_path: Rc<RefCell<Option<PathBuf>>>,
+
+    // name of the sky box to draw, or empty for the scrolling sky
+    sky_name: Rc<RefCell<String>>,
+    sky_changed: Rc<Cell<bool>>,
+
+    // if true, rebuild the world renderer on the next frame
+    restart_requested: Rc<Cell<bool>>,
 }
 
 impl Game {
     pub fn new(
         cvars: Rc<RefCell<CvarRegistry>>,
         cmds: Rc<RefCell<CmdRegistry>>,
+        console: Rc<RefCell<Console>>,
         ui_renderer: Rc<UiRenderer>,
         input: Rc<RefCell<Input>>,
+        basedir: PathBuf,
+        hud_layout: Rc<RefCell<HudLayout>>,
+        mesh_cache: Rc<MeshCache>,
         client: Client,
     ) -> Result<Game, Error> {
-        input.borrow().register_cmds(&mut cmds.borrow_mut());
+        let output = console.borrow().output_handle();
 
         // set up screenshots
+        #[cfg(feature = "capture")]
         let screenshot_path = Rc::new(RefCell::new(None));
+        #[cfg(feature = "capture")]
         cmds.borrow_mut()
             .insert("screenshot", cmd_screenshot(screenshot_path.clone()))
             .unwrap();
 
+        // set up light level dumps
+        let lightdump_path = Rc::new(RefCell::new(None));
+        cmds.borrow_mut()
+            .insert(
+                "r_lightdump",
+                cmd_lightdump(output.clone(), lightdump_path.clone()),
+            )
+            .unwrap();
+
+        // set up sky box selection
+        let sky_name = Rc::new(RefCell::new(String::new()));
+        let sky_changed = Rc::new(Cell::new(false));
+        cmds.borrow_mut()
+            .insert(
+                "sky",
+                cmd_sky(output.clone(), sky_name.clone(), sky_changed.clone()),
+            )
+            .unwrap();
+
+        // set up renderer restarts
+        let restart_requested = Rc::new(Cell::new(false));
+        cmds.borrow_mut()
+            .insert(
+                "vid_restart",
+                cmd_vid_restart(output.clone(), restart_requested.clone()),
+            )
+            .unwrap();
+
         // set up frame tracing
         let trace = Rc::new(RefCell::new(None));
         cmds.borrow_mut()
@@ -195,32 +259,204 @@ impl Game {
             render_pass_bump: Bump::new(),
             state: GameState::Loading,
             input,
+            basedir,
+            hud_layout,
+            mesh_cache,
             client,
+            output,
             trace,
+            #[cfg(feature = "capture")]
             screenshot_path,
+            lightdump_path,
+            sky_name,
+            sky_changed,
+            restart_requested,
         })
     }
 
+    /// Returns the state of the client's connection to the server.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.client.connection_state()
+    }
+
+    /// Ends the connection to the server.
+    pub fn disconnect(&mut self) -> Result<(), Error> {
+        self.client.disconnect()
+    }
+
+    /// Returns the address of the server this game is connected to.
+    pub fn server_addr(&self) -> SocketAddr {
+        self.client.server_addr()
+    }
+
+    /// Returns a summary of the game state for external integrations.
+    pub fn presence_state(&self) -> PresenceState {
+        self.client.presence_state()
+    }
+
+    // Writes the light levels of the current world's faces to `path`.
+    fn write_lightdump(&self, path: &Path) {
+        let worldmodel = match self.client.models().and_then(|models| models.get(1)) {
+            Some(m) => m,
+            None => {
+                self.output.borrow_mut().print(l10n::tr("lightdump.no_map"));
+                return;
+            }
+        };
+
+        match worldmodel.kind() {
+            ModelKind::Brush(bmodel) => match write_light_csv(&bmodel.bsp_data(), path) {
+                Ok(()) => self.output.borrow_mut().print(l10n::tr_args(
+                    "lightdump.wrote",
+                    &[&path.display().to_string()],
+                )),
+                Err(e) => self
+                    .output
+                    .borrow_mut()
+                    .error(l10n::tr_args("lightdump.failed", &[&e.to_string()])),
+            },
+            _ => self
+                .output
+                .borrow_mut()
+                .error(l10n::tr("lightdump.not_brush")),
+        }
+    }
+
+    // Reloads the models and sounds which use any of the changed files. Only the
+    // renderers of the reloaded models are rebuilt.
+    fn reload_assets(&mut self, gfx_state: &GraphicsState, changed: &[String]) {
+        let models = match self.client.models() {
+            Some(m) => m,
+            None => return,
+        };
+
+        let stale_models: Vec<usize> = models
+            .iter()
+            .enumerate()
+            .filter(|(_, model)| {
+                model
+                    .source_files()
+                    .iter()
+                    .any(|file| changed.contains(file))
+            })
+            .map(|(id, _)| id)
+            .collect();
+
+        for model_id in stale_models {
+            if let Err(e) = self.client.reload_model(model_id) {
+                warn!("Couldn't reload model {}: {}", model_id, e);
+                continue;
+            }
+
+            if let GameState::InGame(ref mut state) = self.state {
+                let model = &self.client.models().unwrap()[model_id];
+                state
+                    .world_renderer
+                    .reload_model(gfx_state, model_id, model);
+            }
+        }
+
+        let stale_sounds: Vec<usize> = self
+            .client
+            .sound_files()
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| changed.contains(file))
+            .map(|(id, _)| id)
+            .collect();
+
+        for sound_id in stale_sounds {
+            if let Err(e) = self.client.reload_sound(sound_id) {
+                warn!("Couldn't reload sound {}: {}", sound_id, e);
+            }
+        }
+    }
+
+    // Recreates the world renderer and all of its GPU resources, picking up
+    // changes to load-time settings like gl_subdivide_size.
+    fn rebuild_world_renderer(&mut self, gfx_state: &GraphicsState) {
+        if let GameState::InGame(ref mut state) = self.state {
+            state.world_renderer = WorldRenderer::new(
+                gfx_state,
+                self.client.models().unwrap(),
+                1,
+                &mut self.cvars.borrow_mut(),
+                self.client
+                    .map_digest()
+                    .map(|d| (self.mesh_cache.clone(), d)),
+            );
+        }
+
+        // the new renderer starts with the scrolling sky
+        self.sky_changed.set(true);
+    }
+
+    // Loads the sky box named by `sky_name`, or restores the scrolling sky if
+    // the name is empty or the sky box can't be loaded.
+    fn update_skybox(&mut self, gfx_state: &GraphicsState) {
+        let state = match self.state {
+            GameState::InGame(ref mut s) => s,
+            GameState::Loading => return,
+        };
+
+        let name = self.sky_name.borrow().clone();
+        let skybox = if name.is_empty() {
+            None
+        } else {
+            match Skybox::load(gfx_state, &name) {
+                Ok(s) => Some(s),
+                Err(e) => {
+                    warn!("Couldn't load sky box {}: {}", name, e);
+                    self.sky_name.borrow_mut().clear();
+                    None
+                }
+            }
+        };
+
+        state.world_renderer.set_skybox(gfx_state, skybox);
+    }
+
     // advance the simulation
-    pub fn frame(&mut self, gfx_state: &GraphicsState, frame_duration: Duration) {
-        self.client.frame(frame_duration).unwrap();
+    pub fn frame(
+        &mut self,
+        gfx_state: &GraphicsState,
+        frame_duration: Duration,
+    ) -> Result<(), Error> {
+        // build and send the move command from fresh input before doing anything
+        // else, so it isn't delayed by parsing server messages or loading assets
+        self.update_input(frame_duration);
+
+        self.client.frame(frame_duration)?;
 
         // make sure we set loading state for reconnects
-        if self.client.signon_stage() != SignOnStage::Done {
+        if !self.client.connection_state().is_in_game() {
             self.state = GameState::Loading;
         }
 
+        // write face light levels if requested
+        if let Some(path) = self.lightdump_path.replace(None) {
+            self.write_lightdump(&path);
+        }
+
         if let GameState::Loading = self.state {
-            println!("loading...");
             // check if we've finished getting server info yet
-            if self.client.signon_stage() == SignOnStage::Done {
-                println!("finished loading");
+            if self.client.connection_state().is_in_game() {
+                debug!("finished loading");
+
+                // the previous map's renderers were dropped when loading began
+                if let Err(e) = gfx_state.reset_map_resources() {
+                    warn!("Resources from the previous map were not released: {}", e);
+                }
+
                 // if we have, build renderers
                 let world_renderer = WorldRenderer::new(
                     gfx_state,
                     self.client.models().unwrap(),
                     1,
                     &mut self.cvars.borrow_mut(),
+                    self.client
+                        .map_digest()
+                        .map(|d| (self.mesh_cache.clone(), d)),
                 );
 
                 let deferred_renderer = DeferredRenderer::new(
@@ -236,27 +472,113 @@ impl Game {
                     gfx_state.deferred_pass_target().color_view(),
                 );
 
+                let asset_watcher = FileWatcher::new(
+                    &self.basedir,
+                    self.client
+                        .models()
+                        .unwrap()
+                        .iter()
+                        .flat_map(|model| model.source_files())
+                        .chain(self.client.sound_files()),
+                );
+
                 self.state = GameState::InGame(InGameState::new(
                     self.cmds.clone(),
                     world_renderer,
                     deferred_renderer,
                     postprocess_renderer,
                     InGameFocus::Game,
+                    asset_watcher,
                 ));
+
+                // use the map's sky box unless one is chosen with the sky command
+                *self.sky_name.borrow_mut() = self.client.sky_name().unwrap_or("").to_owned();
+                self.sky_changed.set(true);
             }
         }
 
+        // reload models and skins that were edited
+        if let GameState::InGame(ref mut state) = self.state {
+            let watch_assets = self
+                .cvars
+                .borrow()
+                .get_bool("dev_watchassets")
+                .unwrap_or(false);
+            let changed_assets = state.asset_watcher.poll(watch_assets, frame_duration);
+            if !changed_assets.is_empty() {
+                self.output
+                    .borrow_mut()
+                    .print(format!("{} changed", changed_assets.join(", ")));
+                self.reload_assets(gfx_state, &changed_assets);
+            }
+        }
+
+        if self.restart_requested.replace(false) {
+            self.rebuild_world_renderer(gfx_state);
+        }
+
+        if self.sky_changed.replace(false) {
+            self.update_skybox(gfx_state);
+        }
+
+        // rebuild player skins whose colors or custom skin changed
+        if let GameState::InGame(ref mut state) = self.state {
+            state
+                .world_renderer
+                .update_player_skins(gfx_state, self.client.iter_visible_entities());
+        }
+
+        // if there's an active trace, record this frame
+        if let Some(ref mut trace_frames) = *self.trace.borrow_mut() {
+            trace_frames.push(self.client.trace(&[self.client.view_ent()]));
+        }
+
+        Ok(())
+    }
+
+    // Updates input focus and passes game input to the client.
+    fn update_input(&mut self, frame_duration: Duration) {
+        // the console key is handled by the input module, so follow along
+        let console_toggled = self.input.borrow_mut().take_console_toggle();
+
         // update input focus
         match self.state {
             // ignore inputs during loading
             GameState::Loading => return,
 
             GameState::InGame(ref state) => {
+                if console_toggled {
+                    state.focus.set(match state.focus.get() {
+                        InGameFocus::Console => InGameFocus::Game,
+                        _ => InGameFocus::Console,
+                    });
+                }
+
+                let conspeed = self.cvars.borrow().get_value("scr_conspeed").unwrap();
+                state.console_slide.update(
+                    frame_duration,
+                    state.focus.get() == InGameFocus::Console,
+                    conspeed,
+                );
+                self.input
+                    .borrow_mut()
+                    .set_console_open_fraction(state.console_slide.fraction());
+
+                // opening the console or menu abandons any chat message being typed
+                let chatting = match state.focus.get() {
+                    InGameFocus::Game => self.input.borrow().chatting(),
+                    _ => {
+                        self.input.borrow().cancel_chat();
+                        false
+                    }
+                };
+
                 // set the proper focus
                 self.input
                     .borrow_mut()
                     .set_focus(match state.focus.get() {
-                        InGameFocus::Game => InputFocus::Game,
+                        InGameFocus::Game if chatting => InputFocus::Chat,
+                        InGameFocus::Game => InputFocus::Game(self.client.bind_context()),
                         InGameFocus::Menu => InputFocus::Menu,
                         InGameFocus::Console => InputFocus::Console,
                     })
@@ -269,11 +591,6 @@ impl Game {
                 .handle_input(game_input, frame_duration)
                 .unwrap();
         }
-
-        // if there's an active trace, record this frame
-        if let Some(ref mut trace_frames) = *self.trace.borrow_mut() {
-            trace_frames.push(self.client.trace(&[self.client.view_ent()]));
-        }
     }
 
     pub fn render(
@@ -289,8 +606,9 @@ impl Game {
         self.render_pass_bump.reset();
 
         match self.state {
-            // TODO: loading screen
-            GameState::Loading => (),
+            GameState::Loading => {
+                self.render_loading(gfx_state, color_attachment_view, width, height)
+            }
 
             GameState::InGame(ref state) => {
                 let aspect_ratio = width as f32 / height as f32;
@@ -298,11 +616,8 @@ impl Game {
                 let fov_y = math::fov_x_to_fov_y(cgmath::Deg(fov_x), aspect_ratio).unwrap();
 
                 let projection = cgmath::perspective(fov_y, aspect_ratio, 4.0, 4096.0);
-                let camera = Camera::new(
-                    self.client.view_origin(),
-                    self.client.view_angles(self.client.time()).unwrap(),
-                    projection,
-                );
+                let view = self.client.camera().unwrap();
+                let camera = Camera::new(view.origin, view.angles, projection);
 
                 info!("Beginning render pass");
                 let mut encoder = gfx_state
@@ -322,6 +637,7 @@ impl Game {
                         &camera,
                         self.client.time(),
                         self.client.iter_visible_entities(),
+                        self.client.view_model(),
                         self.client.iter_particles(),
                         self.client.lightstyle_values().unwrap().as_slice(),
                         &self.cvars.borrow(),
@@ -340,21 +656,33 @@ impl Game {
                         radius: 0.0,
                     }; MAX_LIGHTS];
 
+                    // with r_dynamic 0 the world is lit by its lightmaps alone, with
+                    // r_dynamic 2 dynamic lights use the smoother per-pixel model
+                    let r_dynamic = self.cvars.borrow().get_value("r_dynamic").unwrap_or(1.0);
+                    let light_mode = if r_dynamic >= 2.0 {
+                        LIGHT_MODE_PER_PIXEL
+                    } else {
+                        LIGHT_MODE_CLASSIC
+                    };
+
                     let mut light_count = 0;
-                    for (light_id, light) in self.client.iter_lights().enumerate() {
-                        light_count += 1;
-                        let light_origin = light.origin();
-                        let converted_origin =
-                            Vector3::new(-light_origin.y, light_origin.z, -light_origin.x);
-                        lights[light_id].origin =
-                            (camera.view() * converted_origin.extend(1.0)).truncate();
-                        lights[light_id].radius = light.radius(self.client.time());
+                    if r_dynamic != 0.0 {
+                        for (light_id, light) in self.client.iter_lights().enumerate() {
+                            light_count += 1;
+                            let light_origin = light.origin();
+                            let converted_origin =
+                                Vector3::new(-light_origin.y, light_origin.z, -light_origin.x);
+                            lights[light_id].origin =
+                                (camera.view() * converted_origin.extend(1.0)).truncate();
+                            lights[light_id].radius = light.radius(self.client.time());
+                        }
                     }
 
                     let uniforms = DeferredUniforms {
                         inv_projection: projection.invert().unwrap().into(),
                         light_count,
-                        _pad: [0; 3],
+                        light_mode,
+                        _pad: [0; 2],
                         lights,
                     };
 
@@ -363,6 +691,17 @@ impl Game {
                         .record_draw(gfx_state, &mut deferred_pass, uniforms);
                 }
 
+                let chat_line = match state.focus.get() {
+                    InGameFocus::Game => self.input.borrow().chat_line(),
+                    _ => None,
+                };
+
+                let hud_layout = self.hud_layout.borrow();
+                let show_scores = self
+                    .input
+                    .borrow()
+                    .game_input()
+                    .map_or(false, |input| input.action_state(Action::ShowScores));
                 let ui_state = UiState::InGame {
                     hud: match self.client.intermission() {
                         Some(kind) => HudState::Intermission {
@@ -370,19 +709,64 @@ impl Game {
                             completion_duration: self.client.completion_time().unwrap()
                                 - self.client.start_time(),
                             stats: self.client.stats(),
+                            scoreboard: if self.client.deathmatch() {
+                                Some(self.client.scoreboard())
+                            } else {
+                                None
+                            },
                         },
 
-                        None => HudState::InGame {
-                            items: self.client.items(),
-                            item_pickup_time: self.client.item_get_time(),
-                            stats: self.client.stats(),
-                            face_anim_time: self.client.face_anim_time(),
-                        },
+                        None => {
+                            self.client
+                                .csqc()
+                                .draw_hud(width, height, self.client.view_contents());
+                            HudState::InGame {
+                                items: self.client.items(),
+                                item_pickup_time: self.client.item_get_time(),
+                                stats: self.client.stats(),
+                                face_anim_time: self.client.face_anim_time(),
+                                low_health_pulse: self.client.low_health_pulse(),
+                                damage_indicators: self.client.damage_indicators(),
+                                crosshair: self.client.crosshair(),
+                                crosshair_offset: self.client.crosshair_offset(),
+                                hit_confirm: self.client.hit_confirmed(),
+                                level_time: self.client.time() - self.client.start_time(),
+                                speed: self.client.speed(),
+                                speedometer: self.client.speedometer(),
+                                strafe_angles: self.client.strafe_angles(),
+                                identified: self.client.identified_entity(),
+                                layout: &hud_layout,
+                                scoreboard: if show_scores {
+                                    Some(self.client.scoreboard())
+                                } else {
+                                    None
+                                },
+                                center_print: self.client.center_print(),
+                            }
+                        }
                     },
+                    notify: match state.focus.get() {
+                        InGameFocus::Game if state.console_slide.fraction() == 0.0 => {
+                            let notify_time = self
+                                .cvars
+                                .borrow()
+                                .get_value("con_notifytime")
+                                .unwrap_or(3.0);
+                            Some((console, engine::duration_from_f32(notify_time)))
+                        }
+
+                        _ => None,
+                    },
+                    chat: chat_line.as_ref(),
                     overlay: match state.focus.get() {
-                        InGameFocus::Game => None,
-                        InGameFocus::Console => Some(UiOverlay::Console(console)),
                         InGameFocus::Menu => Some(UiOverlay::Menu(menu)),
+
+                        // keep drawing the console while it slides closed
+                        _ if state.console_slide.fraction() > 0.0 => {
+                            Some(UiOverlay::Console(console, state.console_slide.fraction()))
+                        }
+
+                        _ => None,
                     },
                 };
 
@@ -396,10 +780,18 @@ impl Game {
                     let mut final_pass =
                         encoder.begin_render_pass(&final_pass_builder.descriptor());
 
+                    let view_warp = match self.cvars.borrow().get_value("r_waterwarp") {
+                        Ok(v) if v != 0.0 && self.client.view_contents().is_liquid() => {
+                            Some(engine::duration_to_f32(self.client.time()))
+                        }
+                        _ => None,
+                    };
+
                     state.postprocess_renderer.record_draw(
                         gfx_state,
                         &mut final_pass,
                         self.client.color_shift(),
+                        view_warp,
                     );
 
                     self.ui_renderer.render_pass(
@@ -414,6 +806,7 @@ impl Game {
                 }
 
                 // screenshot setup
+                #[cfg(feature = "capture")]
                 let capture = self.screenshot_path.borrow().as_ref().map(|_| {
                     let cap = Capture::new(gfx_state.device(), Extent2d { width, height });
                     cap.copy_from_texture(
@@ -443,6 +836,7 @@ impl Game {
                 }
 
                 // write screenshot if requested and clear screenshot path
+                #[cfg(feature = "capture")]
                 self.screenshot_path.replace(None).map(|path| {
                     capture
                         .as_ref()
@@ -452,11 +846,105 @@ impl Game {
             }
         }
     }
+
+    // Draws the loading plaque and sign-on progress.
+    fn render_loading(
+        &self,
+        gfx_state: &GraphicsState,
+        color_attachment_view: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        let status = self.client.loading_status();
+        let scale = self
+            .cvars
+            .borrow()
+            .get_value("scr_loadingscale")
+            .unwrap_or(2.0);
+        let ui_state = UiState::Loading {
+            status: &status,
+            scale,
+        };
+
+        let mut encoder = gfx_state
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        {
+            // quad_commands must outlive final pass
+            let mut quad_commands = Vec::new();
+            let mut glyph_commands = Vec::new();
+
+            let final_pass_builder = gfx_state.final_pass_target().render_pass_builder();
+            let mut final_pass = encoder.begin_render_pass(&final_pass_builder.descriptor());
+
+            self.ui_renderer.render_pass(
+                gfx_state,
+                &mut final_pass,
+                Extent2d { width, height },
+                self.client.time(),
+                &ui_state,
+                &mut quad_commands,
+                &mut glyph_commands,
+            );
+        }
+
+        // blit to swap chain
+        {
+            let swap_chain_target = SwapChainTarget::with_swap_chain_view(color_attachment_view);
+            let blit_pass_builder = swap_chain_target.render_pass_builder();
+            let mut blit_pass = encoder.begin_render_pass(&blit_pass_builder.descriptor());
+            gfx_state.blit_pipeline().blit(gfx_state, &mut blit_pass);
+        }
+
+        gfx_state.queue().submit(vec![encoder.finish()]);
+    }
 }
 
 impl std::ops::Drop for Game {
     fn drop(&mut self) {
+        let _ = self.cmds.borrow_mut().remove("screenshot");
+        let _ = self.cmds.borrow_mut().remove("r_lightdump");
+        let _ = self.cmds.borrow_mut().remove("sky");
+        let _ = self.cmds.borrow_mut().remove("vid_restart");
         let _ = self.cmds.borrow_mut().remove("trace_begin");
         let _ = self.cmds.borrow_mut().remove("trace_end");
     }
 }
+
+/// Implements the `sky` command.
+///
+/// With no arguments, prints the current sky box. With an empty name, restores
+/// the scrolling sky.
+fn cmd_sky(
+    output: Rc<RefCell<ConsoleOutput>>,
+    sky_name: Rc<RefCell<String>>,
+    sky_changed: Rc<Cell<bool>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => match sky_name.borrow().as_str() {
+            "" => output.borrow_mut().print("No sky box loaded"),
+            name => output
+                .borrow_mut()
+                .print(format!("Current sky box: {}", name)),
+        },
+        1 => {
+            sky_name.replace(args[0].to_owned());
+            sky_changed.set(true);
+        }
+        _ => output.borrow_mut().print("usage: sky [name]"),
+    })
+}
+
+/// Implements the `vid_restart` command.
+///
+/// Rebuilds the world renderer so that load-time settings take effect.
+fn cmd_vid_restart(
+    output: Rc<RefCell<ConsoleOutput>>,
+    restart_requested: Rc<Cell<bool>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => restart_requested.set(true),
+        _ => output.borrow_mut().print("usage: vid_restart"),
+    })
+}
@@ -0,0 +1,66 @@
+use std::{
+    cell::RefCell,
+    io::Write as _,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use richter::common::{bsp::BspData, console::ConsoleOutput, fs_util::AtomicFile, l10n};
+
+use failure::Error;
+
+const DEFAULT_LIGHTDUMP_PATH: &'static str = "richter-light.csv";
+
+/// Implements the `r_lightdump` command.
+///
+/// This function returns a boxed closure which sets the `lightdump_path`
+/// argument to `Some` when called.
+pub fn cmd_lightdump(
+    output: Rc<RefCell<ConsoleOutput>>,
+    lightdump_path: Rc<RefCell<Option<PathBuf>>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        let path = match args.len() {
+            0 => PathBuf::from(DEFAULT_LIGHTDUMP_PATH),
+            1 => PathBuf::from(args[0]),
+            _ => {
+                output
+                    .borrow_mut()
+                    .print(l10n::tr_args("console.usage", &["r_lightdump [PATH]"]));
+                return;
+            }
+        };
+
+        lightdump_path.replace(Some(path));
+    })
+}
+
+/// Writes the average light level of every lit face in `bsp` to a CSV file.
+///
+/// Each row holds one light style of one face. Faces without lightmaps (sky,
+/// liquids) are omitted.
+pub fn write_light_csv<P>(bsp: &BspData, path: P) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let mut writer = AtomicFile::create(path)?;
+    writeln!(writer, "face,texture,style,average")?;
+
+    for face_id in 0..bsp.faces().len() {
+        let texture = &bsp.textures()[bsp.face_texinfo(face_id).tex_id];
+        for (style, average) in bsp.face_light_levels(face_id) {
+            writeln!(
+                writer,
+                "{},{},{},{:.2}",
+                face_id,
+                texture.name(),
+                style,
+                average
+            )?;
+        }
+    }
+
+    writer.commit()?;
+
+    Ok(())
+}
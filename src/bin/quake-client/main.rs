@@ -18,50 +18,96 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[cfg(feature = "capture")]
 mod capture;
 mod game;
+mod lightdump;
 mod menu;
 mod trace;
+mod watch;
 
 use std::{
     cell::{Cell, Ref, RefCell, RefMut},
-    net::ToSocketAddrs,
-    path::Path,
-    rc::Rc,
+    fs,
+    io::{self, Write},
+    net::{SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    rc::{Rc, Weak},
 };
 
 use game::Game;
+use watch::FileWatcher;
 
 use chrono::Duration;
+use failure::{ensure, Error};
 use richter::{
     client::{
         self,
-        input::{Input, InputFocus},
+        connection::ConnectionState,
+        input::{
+            game::BindContext,
+            gamepad::{self, StickVars},
+            Input, InputFocus,
+        },
         menu::Menu,
-        render::{self, Extent2d, GraphicsState, UiRenderer, DIFFUSE_ATTACHMENT_FORMAT},
-        Client,
+        presence::{Presence, PresenceState},
+        render::{
+            self, mesh_cache, Extent2d, GraphicsState, HudLayout, MeshCache, RenderTarget as _,
+            SwapChainTarget, UiOverlay, UiRenderer, UiState, DIFFUSE_ATTACHMENT_FORMAT,
+            HUD_LAYOUT_FILE,
+        },
+        Client, RconRequest,
     },
     common::{
         self,
-        console::{CmdRegistry, Console, CvarRegistry},
+        console::{
+            self, CmdRegistry, CmdSource, Console, ConsoleOutput, CvarRegistry, LogFileSink,
+        },
+        fs_util::AtomicFile,
         host::{Host, Program},
+        l10n,
+        mods::{self, ModDir},
+        parse::console::{needs_quotes, quote},
         vfs::Vfs,
     },
+    server,
 };
 use structopt::StructOpt;
+
+#[cfg(feature = "qw")]
+use richter::{client::OobQuery, common::net::oob::OobRequest};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
     window::{Window, WindowBuilder},
 };
 
+const WINDOW_TITLE: &str = "Richter client";
+
+// written on shutdown and by `host_writeconfig`, executed by quake.rc at startup
+const CONFIG_FILE: &str = "config.cfg";
+
+// console output is mirrored here when `condebug` is set
+const CONSOLE_LOG_FILE: &str = "qconsole.log";
+
+// built map meshes are cached here, keyed by a digest of the map file
+const MESH_CACHE_DIR: &str = "cache";
+
+// config files re-executed on change when `dev_watchcfg` is set
+const WATCHED_CONFIGS: [&str; 2] = ["autoexec.cfg", "dev.cfg"];
+
+// what the title screen shows, set by `togglemenu` and `toggleconsole`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum TitleState {
     Menu,
     Console,
 }
 
 enum ProgramState {
+    // not connected to a server, only the console is available
     Title,
+
+    // connected to a server
     Game(Game),
 }
 
@@ -71,9 +117,11 @@ struct ClientProgram {
     cmds: Rc<RefCell<CmdRegistry>>,
     console: Rc<RefCell<Console>>,
     menu: Rc<RefCell<Menu>>,
+    hud_layout: Rc<RefCell<HudLayout>>,
 
     window: Window,
     window_dimensions_changed: Cell<bool>,
+    presence: Presence,
 
     instance: wgpu::Instance,
     surface: wgpu::Surface,
@@ -86,31 +134,104 @@ struct ClientProgram {
 
     state: RefCell<ProgramState>,
     input: Rc<RefCell<Input>>,
+
+    // whether the title screen shows the menu or the console
+    title_state: Rc<Cell<TitleState>>,
+
+    // set by the `quit` command
+    quit: Rc<Cell<bool>>,
+
+    // time spent at the title screen, used to animate the console
+    title_time: Cell<Duration>,
+
+    // whether the cursor is currently captured by the window
+    cursor_grabbed: Cell<bool>,
+
+    // server address given to the `connect` command, if any
+    pending_connect: Rc<RefCell<Option<String>>>,
+
+    // command given to the `rcon` command, if any
+    pending_rcon: Rc<RefCell<Option<String>>>,
+
+    // rcon command waiting for the server's reply, if any
+    rcon_request: Option<RconRequest>,
+
+    // address and request given to the `packet` command, if any
+    #[cfg(feature = "qw")]
+    pending_packet: Rc<RefCell<Option<(String, String)>>>,
+
+    // connectionless request waiting for the server's response, if any
+    #[cfg(feature = "qw")]
+    oob_query: Option<OobQuery>,
+
+    // mod directory mounted over basedir, if any
+    game: Rc<RefCell<Option<String>>>,
+
+    // mod directory given to the `game` command, if any
+    pending_game: Rc<RefCell<Option<String>>>,
+
+    // observer reporting shadowed files in the current filesystem
+    fs_report_id: usize,
+
+    // base game directory; mod directories are its siblings
+    basedir: PathBuf,
+
+    // built map meshes, shared by every map load
+    mesh_cache: Rc<MeshCache>,
+
+    // re-executes config files edited while the game is running
+    cfg_watcher: FileWatcher,
+
+    // reloads shaders edited while the game is running
+    shader_watcher: FileWatcher,
+
+    // set by the `r_reloadshaders` command
+    reload_shaders: Rc<Cell<bool>>,
 }
 
 impl ClientProgram {
-    pub async fn new(window: Window, audio_device: rodio::Device, trace: bool) -> ClientProgram {
-        let mut vfs = Vfs::new();
-
-        // add basedir first
-        vfs.add_directory(common::DEFAULT_BASEDIR).unwrap();
-
-        // then add PAK archives
-        for vfs_id in 0..common::MAX_PAKFILES {
-            // TODO: check `-basedir` command line argument
-            let basedir = common::DEFAULT_BASEDIR;
-            let path_string = format!("{}/pak{}.pak", basedir, vfs_id);
-            let path = Path::new(&path_string);
-
-            // keep adding PAKs until we don't find one or we hit MAX_PAKFILES
-            if !path.exists() {
-                break;
+    pub async fn new(
+        window: Window,
+        audio_device: rodio::Device,
+        trace: bool,
+        language: &str,
+        quake_args: QuakeArgs,
+    ) -> ClientProgram {
+        let basedir = PathBuf::from(
+            quake_args
+                .basedir
+                .as_ref()
+                .map(String::as_str)
+                .unwrap_or(common::DEFAULT_BASEDIR),
+        );
+        let game = Rc::new(RefCell::new(None));
+        let vfs = match quake_args
+            .game
+            .as_ref()
+            .map_or(Ok(None), |g| resolve_game(&basedir, g))
+            .and_then(|g| {
+                let vfs = mount_game(&basedir, g.as_deref())?;
+                game.replace(g);
+                Ok(vfs)
+            }) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{}", e);
+                mount_game(&basedir, None).unwrap()
             }
+        };
 
-            vfs.add_pakfile(path).unwrap();
+        // load localized strings before anything is built from them
+        match l10n::set_language(&vfs, language) {
+            Ok(()) => (),
+            // English is built in, so a missing string table is expected
+            Err(e) if language == "en" => log::debug!("{}", e),
+            Err(e) => log::warn!("Couldn't load strings for {}: {}", language, e),
         }
 
         let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        // the client shares some cvars with the server, so register the server's first
+        server::register_cvars(&cvars.borrow()).unwrap();
         client::register_cvars(&cvars.borrow()).unwrap();
         render::register_cvars(&cvars.borrow());
 
@@ -118,14 +239,42 @@ impl ClientProgram {
         // TODO: register commands as other subsystems come online
 
         let console = Rc::new(RefCell::new(Console::new(cmds.clone(), cvars.clone())));
-        let menu = Rc::new(RefCell::new(menu::build_main_menu().unwrap()));
+        cvars
+            .borrow()
+            .add_observer(
+                "condebug",
+                condebug_observer(basedir.join(CONSOLE_LOG_FILE), console.clone()),
+            )
+            .unwrap();
+        if quake_args.condebug {
+            cvars.borrow().set("condebug", "1").unwrap();
+        }
+
+        // -set values are also stuffed with the + commands, but some are read during startup
+        for (name, value) in quake_args.sets.iter() {
+            if let Err(e) = cvars.borrow().set(name.as_str(), value.as_str()) {
+                log::warn!("Couldn't set {}: {}", name, e);
+            }
+        }
+        let menu = Rc::new(RefCell::new(
+            menu::build_main_menu(
+                console.clone(),
+                cvars.clone(),
+                &basedir.file_name().unwrap_or_default().to_string_lossy(),
+                &mods::discover(&basedir),
+            )
+            .unwrap(),
+        ));
 
         let input = Rc::new(RefCell::new(Input::new(
-            InputFocus::Game,
+            InputFocus::Game(BindContext::Game),
             console.clone(),
             menu.clone(),
         )));
         input.borrow_mut().bind_defaults();
+        input.borrow().register_cmds(&mut cmds.borrow_mut());
+        let winit::dpi::PhysicalSize { width, height } = window.inner_size();
+        input.borrow_mut().set_display_size(width, height);
 
         let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
         let surface = unsafe { instance.create_surface(&window) };
@@ -173,6 +322,16 @@ impl ClientProgram {
 
         let vfs = Rc::new(vfs);
 
+        // report shadowed files whenever fs_report is turned on, including
+        // from config.cfg at startup
+        let fs_report_id = cvars
+            .borrow()
+            .add_observer(
+                "fs_report",
+                fs_report_observer(console.borrow().output_handle(), vfs.clone()),
+            )
+            .unwrap();
+
         // TODO: warn user if r_msaa_samples is invalid
         let mut sample_count = cvars.borrow().get_value("r_msaa_samples").unwrap_or(2.0) as u32;
         if !&[2, 4].contains(&sample_count) {
@@ -182,6 +341,146 @@ impl ClientProgram {
         let gfx_state = GraphicsState::new(device, queue, size, sample_count, vfs.clone()).unwrap();
         let ui_renderer = Rc::new(UiRenderer::new(&gfx_state, &menu.borrow()));
 
+        let config_dir = game_dir(&basedir, game.borrow().as_deref());
+        cmds.borrow_mut()
+            .insert(
+                "exec",
+                cmd_exec(
+                    config_dir.join(CONFIG_FILE),
+                    vfs.clone(),
+                    Rc::downgrade(&console),
+                ),
+            )
+            .unwrap();
+
+        cmds.borrow_mut()
+            .insert(
+                "host_writeconfig",
+                cmd_host_writeconfig(
+                    console.borrow().output_handle(),
+                    config_dir.clone(),
+                    cvars.clone(),
+                    input.clone(),
+                ),
+            )
+            .unwrap();
+
+        cmds.borrow_mut()
+            .insert(
+                "joy_preset",
+                gamepad::cmd_joy_preset(console.borrow().output_handle(), cvars.clone()),
+            )
+            .unwrap();
+
+        let mesh_cache = Rc::new(MeshCache::new(basedir.join(MESH_CACHE_DIR)));
+        cmds.borrow_mut()
+            .insert(
+                "cache_flush",
+                mesh_cache::cmd_cache_flush(console.borrow().output_handle(), mesh_cache.clone()),
+            )
+            .unwrap();
+
+        client::register_offline_cmds(&mut cmds.borrow_mut(), console.borrow().output_handle())
+            .unwrap();
+
+        let pending_connect = Rc::new(RefCell::new(None));
+        cmds.borrow_mut()
+            .insert(
+                "connect",
+                cmd_connect(console.borrow().output_handle(), pending_connect.clone()),
+            )
+            .unwrap();
+
+        let pending_rcon = Rc::new(RefCell::new(None));
+        cmds.borrow_mut()
+            .insert(
+                "rcon",
+                cmd_rcon(console.borrow().output_handle(), pending_rcon.clone()),
+            )
+            .unwrap();
+
+        #[cfg(feature = "qw")]
+        let pending_packet = Rc::new(RefCell::new(None));
+        #[cfg(feature = "qw")]
+        cmds.borrow_mut()
+            .insert(
+                "packet",
+                cmd_packet(console.borrow().output_handle(), pending_packet.clone()),
+            )
+            .unwrap();
+
+        let pending_game = Rc::new(RefCell::new(None));
+        cmds.borrow_mut()
+            .insert(
+                "game",
+                cmd_game(
+                    console.borrow().output_handle(),
+                    basedir.clone(),
+                    game.clone(),
+                    pending_game.clone(),
+                ),
+            )
+            .unwrap();
+        cmds.borrow_mut()
+            .insert(
+                "mods",
+                cmd_mods(console.borrow().output_handle(), basedir.clone()),
+            )
+            .unwrap();
+
+        let hud_layout = Rc::new(RefCell::new(match HudLayout::load(&vfs) {
+            Ok(l) => l,
+            Err(e) => {
+                console
+                    .borrow()
+                    .output_mut()
+                    .warn(format!("Couldn't load {}: {}", HUD_LAYOUT_FILE, e));
+                HudLayout::default()
+            }
+        }));
+        cmds.borrow_mut()
+            .insert(
+                "hud_reload",
+                cmd_hud_reload(
+                    console.borrow().output_handle(),
+                    vfs.clone(),
+                    hud_layout.clone(),
+                ),
+            )
+            .unwrap();
+
+        let reload_shaders = Rc::new(Cell::new(false));
+        let cmd_reload_shaders = reload_shaders.clone();
+        cmds.borrow_mut()
+            .insert(
+                "r_reloadshaders",
+                Box::new(move |_| cmd_reload_shaders.set(true)),
+            )
+            .unwrap();
+
+        let title_state = Rc::new(Cell::new(TitleState::Console));
+        register_title_cmds(&mut cmds.borrow_mut(), title_state.clone());
+
+        let quit = Rc::new(Cell::new(false));
+        cmds.borrow_mut()
+            .insert(
+                "quit",
+                cmd_quit(console.borrow().output_handle(), quit.clone()),
+            )
+            .unwrap();
+
+        // quake.rc runs this after the configs, so command-line arguments take precedence
+        cmds.borrow_mut()
+            .insert(
+                "stuffcmds",
+                cmd_stuffcmds(console.clone(), quake_args.commands),
+            )
+            .unwrap();
+
+        // external integrations (launchers, rich presence) hook in here
+        let presence = Presence::new();
+        presence.add_callback(Box::new(|state| log::info!("{}", state)));
+
         // this will also execute config.cfg and autoexec.cfg (assuming an unmodified quake.rc)
         console.borrow().stuff_text("exec quake.rc\n");
 
@@ -191,8 +490,10 @@ impl ClientProgram {
             cmds,
             console,
             menu,
+            hud_layout,
             window,
             window_dimensions_changed: Cell::new(false),
+            presence,
             instance,
             surface,
             adapter,
@@ -202,6 +503,28 @@ impl ClientProgram {
             audio_device: Rc::new(audio_device),
             state: RefCell::new(ProgramState::Title),
             input,
+            title_state,
+            quit,
+            title_time: Cell::new(Duration::zero()),
+            cursor_grabbed: Cell::new(false),
+            pending_connect,
+            pending_rcon,
+            rcon_request: None,
+            #[cfg(feature = "qw")]
+            pending_packet,
+            #[cfg(feature = "qw")]
+            oob_query: None,
+            game,
+            pending_game,
+            fs_report_id,
+            cfg_watcher: FileWatcher::new(&config_dir, WATCHED_CONFIGS.iter().cloned()),
+            shader_watcher: FileWatcher::new(
+                config_dir.join("shaders"),
+                render::SHADER_NAMES.iter().cloned(),
+            ),
+            reload_shaders,
+            basedir,
+            mesh_cache,
         }
     }
 
@@ -209,15 +532,31 @@ impl ClientProgram {
     where
         A: ToSocketAddrs,
     {
-        let cl = Client::connect(
+        // drop any existing connection first
+        self.disconnect();
+
+        let cl = match Client::connect(
             server_addrs,
             self.vfs.clone(),
             self.cvars.clone(),
             self.cmds.clone(),
             self.console.clone(),
             self.audio_device.clone(),
-        )
-        .unwrap();
+        ) {
+            Ok(cl) => cl,
+            Err(e) => {
+                self.console
+                    .borrow()
+                    .output_mut()
+                    .error(l10n::tr_args("connect.failed", &[&e.to_string()]));
+                self.title_state.set(TitleState::Console);
+                self.input
+                    .borrow_mut()
+                    .set_focus(InputFocus::Console)
+                    .unwrap();
+                return;
+            }
+        };
 
         cl.register_cmds(&mut self.cmds.borrow_mut());
 
@@ -225,14 +564,345 @@ impl ClientProgram {
             Game::new(
                 self.cvars.clone(),
                 self.cmds.clone(),
+                self.console.clone(),
                 self.ui_renderer.clone(),
                 self.input.clone(),
+                self.basedir.clone(),
+                self.hud_layout.clone(),
+                self.mesh_cache.clone(),
                 cl,
             )
             .unwrap(),
         ));
     }
 
+    /// Remounts the filesystem with a different mod directory over basedir.
+    ///
+    /// An empty name or the name of basedir itself returns to the base game.
+    /// Any current connection is dropped, and the palette, colormap and UI
+    /// graphics are reloaded from the new filesystem. The current settings are
+    /// saved to the old game's directory first, then the configs are executed
+    /// again from the new game.
+    fn switch_game(&mut self, game: String) {
+        let output = self.console.borrow().output_handle();
+        let base_name = self
+            .basedir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned());
+        let game = match resolve_game(&self.basedir, &game) {
+            Ok(g) => g,
+            Err(e) => {
+                output
+                    .borrow_mut()
+                    .warn(format!("Couldn't switch game: {}", e));
+                return;
+            }
+        };
+
+        if game == *self.game.borrow() {
+            output.borrow_mut().print(format!(
+                "Already using {}",
+                game_name(&self.basedir, game.as_ref())
+            ));
+            return;
+        }
+
+        let vfs = match mount_game(&self.basedir, game.as_ref().map(String::as_str)) {
+            Ok(v) => Rc::new(v),
+            Err(e) => {
+                output
+                    .borrow_mut()
+                    .warn(format!("Couldn't switch game: {}", e));
+                return;
+            }
+        };
+
+        // the renderer needs the new game's gfx.wad, so load it before leaving the old game
+        if let Err(e) = self.gfx_state.borrow_mut().set_vfs(vfs.clone()) {
+            output
+                .borrow_mut()
+                .warn(format!("Couldn't switch game: {}", e));
+            return;
+        }
+
+        self.disconnect();
+
+        let old_config_dir = game_dir(&self.basedir, self.game.borrow().as_deref());
+        if let Err(e) = write_config(&old_config_dir, &self.cvars.borrow(), &self.input.borrow()) {
+            log::error!("Couldn't write {}: {}", CONFIG_FILE, e);
+        }
+
+        // pick up mods installed since the menu was built
+        match menu::build_main_menu(
+            self.console.clone(),
+            self.cvars.clone(),
+            base_name.as_deref().unwrap_or_default(),
+            &mods::discover(&self.basedir),
+        ) {
+            Ok(m) => {
+                self.menu.replace(m);
+            }
+            Err(e) => log::error!("Couldn't rebuild the main menu: {}", e),
+        }
+        self.ui_renderer = Rc::new(UiRenderer::new(
+            &self.gfx_state.borrow(),
+            &self.menu.borrow(),
+        ));
+
+        match HudLayout::load(&vfs) {
+            Ok(l) => self.hud_layout.replace(l),
+            Err(e) => {
+                output
+                    .borrow_mut()
+                    .warn(format!("Couldn't load {}: {}", HUD_LAYOUT_FILE, e));
+                self.hud_layout.replace(HudLayout::default())
+            }
+        };
+
+        // commands and observers which use the game directory or read from the filesystem
+        let config_dir = game_dir(&self.basedir, game.as_deref());
+        let mut cmds = self.cmds.borrow_mut();
+        cmds.insert_or_replace(
+            "exec",
+            cmd_exec(
+                config_dir.join(CONFIG_FILE),
+                vfs.clone(),
+                Rc::downgrade(&self.console),
+            ),
+        )
+        .unwrap();
+        cmds.insert_or_replace(
+            "host_writeconfig",
+            cmd_host_writeconfig(
+                output.clone(),
+                config_dir.clone(),
+                self.cvars.clone(),
+                self.input.clone(),
+            ),
+        )
+        .unwrap();
+        cmds.insert_or_replace(
+            "hud_reload",
+            cmd_hud_reload(output.clone(), vfs.clone(), self.hud_layout.clone()),
+        )
+        .unwrap();
+        drop(cmds);
+
+        let cvars = self.cvars.borrow();
+        cvars.remove_observer("fs_report", self.fs_report_id);
+        self.fs_report_id = cvars
+            .add_observer("fs_report", fs_report_observer(output.clone(), vfs.clone()))
+            .unwrap();
+        drop(cvars);
+
+        // watch the new game's configs and shaders
+        self.cfg_watcher = FileWatcher::new(&config_dir, WATCHED_CONFIGS.iter().cloned());
+        self.shader_watcher = FileWatcher::new(
+            config_dir.join("shaders"),
+            render::SHADER_NAMES.iter().cloned(),
+        );
+
+        self.vfs = vfs;
+        output.borrow_mut().print(format!(
+            "Switched to {}",
+            game_name(&self.basedir, game.as_ref())
+        ));
+        self.game.replace(game);
+
+        // the mod may bring its own defaults and autoexec
+        self.console.borrow().stuff_text(format!(
+            "exec default.cfg\nexec {}\nexec autoexec.cfg\n",
+            CONFIG_FILE
+        ));
+    }
+
+    /// Drops the current connection, if any, and returns to the console.
+    fn disconnect(&mut self) {
+        if let ProgramState::Game(ref mut game) = *self.state.borrow_mut() {
+            if let Err(e) = game.disconnect() {
+                log::warn!("Error while disconnecting: {}", e);
+            }
+        } else {
+            return;
+        }
+
+        self.state.replace(ProgramState::Title);
+        self.title_time.set(Duration::zero());
+
+        // the game's versions of these were left behind
+        register_title_cmds(&mut self.cmds.borrow_mut(), self.title_state.clone());
+        self.title_state.set(TitleState::Console);
+        self.input
+            .borrow_mut()
+            .set_focus(InputFocus::Console)
+            .unwrap();
+    }
+
+    /// Sends `command` to the remote console of `rcon_address`, or of the
+    /// current server if `rcon_address` is unset.
+    ///
+    /// The reply is printed by `poll_rcon` when it arrives. Any command still
+    /// waiting for a reply is abandoned.
+    fn rcon(&mut self, command: String) {
+        let password = self.cvars.borrow().get("rcon_password").unwrap();
+        if password.is_empty() {
+            self.console
+                .borrow()
+                .output_mut()
+                .warn(l10n::tr("rcon.no_password"));
+            return;
+        }
+
+        let address = self.cvars.borrow().get("rcon_address").unwrap();
+        let server_addr = if !address.is_empty() {
+            match resolve_server(&address) {
+                Some(a) => a,
+                None => {
+                    self.console
+                        .borrow()
+                        .output_mut()
+                        .error(l10n::tr_args("rcon.bad_address", &[&address]));
+                    return;
+                }
+            }
+        } else {
+            match *self.state.borrow() {
+                ProgramState::Game(ref game) => game.server_addr(),
+                ProgramState::Title => {
+                    self.console
+                        .borrow()
+                        .output_mut()
+                        .warn(l10n::tr("rcon.no_server"));
+                    return;
+                }
+            }
+        };
+
+        match RconRequest::send(server_addr, password, command) {
+            Ok(request) => self.rcon_request = Some(request),
+            Err(e) => self
+                .console
+                .borrow()
+                .output_mut()
+                .error(l10n::tr_args("rcon.failed", &[&e.to_string()])),
+        }
+    }
+
+    /// Prints the reply to the last rcon command if it has arrived.
+    fn poll_rcon(&mut self, frame_time: Duration) {
+        let result = match self.rcon_request {
+            Some(ref mut request) => request.poll(frame_time),
+            None => return,
+        };
+
+        let console = self.console.borrow();
+        let mut output = console.output_mut();
+        match result {
+            Ok(None) => return,
+            Ok(Some(reply)) => output.print(&reply),
+            Err(e) => output.error(l10n::tr_args("rcon.failed", &[&e.to_string()])),
+        }
+
+        self.rcon_request = None;
+    }
+
+    /// Sends the connectionless request `text` to the server at `address`.
+    #[cfg(feature = "qw")]
+    ///
+    /// The response is printed by `poll_packet` when it arrives. Any request
+    /// still waiting for a response is abandoned.
+    fn packet(&mut self, address: String, text: String) {
+        let output = self.console.borrow().output_handle();
+
+        let request = match OobRequest::parse(&text) {
+            Ok(r) => r,
+            Err(e) => {
+                output
+                    .borrow_mut()
+                    .error(l10n::tr_args("oob.failed", &[&e.to_string()]));
+                return;
+            }
+        };
+
+        let server_addr = match resolve_server(&address) {
+            Some(a) => a,
+            None => {
+                output
+                    .borrow_mut()
+                    .error(l10n::tr_args("oob.bad_address", &[&address]));
+                return;
+            }
+        };
+
+        match OobQuery::send(server_addr, &request) {
+            Ok(query) => self.oob_query = Some(query),
+            Err(e) => output
+                .borrow_mut()
+                .error(l10n::tr_args("oob.failed", &[&e.to_string()])),
+        }
+    }
+
+    /// Prints the response to the last connectionless request if it has arrived.
+    #[cfg(feature = "qw")]
+    fn poll_packet(&mut self, frame_time: Duration) {
+        let result = match self.oob_query {
+            Some(ref mut query) => query.poll(frame_time),
+            None => return,
+        };
+
+        let console = self.console.borrow();
+        let mut output = console.output_mut();
+        match result {
+            Ok(None) => return,
+            Ok(Some(response)) => output.print(client::oob_response_text(&response)),
+            Err(e) => output.error(l10n::tr_args("oob.failed", &[&e.to_string()])),
+        }
+
+        self.oob_query = None;
+    }
+
+    /// Captures and hides the cursor while the game has focus and releases it otherwise.
+    fn update_cursor_grab(&self) {
+        let grab = {
+            let input = self.input.borrow();
+            let game_focus = match input.current_focus() {
+                InputFocus::Game(_) | InputFocus::Chat => true,
+                InputFocus::Console | InputFocus::Menu => false,
+            };
+
+            game_focus && input.window_focused()
+        };
+
+        if grab == self.cursor_grabbed.get() {
+            return;
+        }
+
+        // some platforms don't support grabbing, in which case aiming still works through raw
+        // device motion as long as the cursor stays inside the window
+        if let Err(e) = self.window.set_cursor_grab(grab) {
+            log::warn!(
+                "Couldn't {} cursor: {}",
+                if grab { "grab" } else { "release" },
+                e
+            );
+        }
+        self.window.set_cursor_visible(!grab);
+        self.cursor_grabbed.set(grab);
+    }
+
+    /// Updates the presence state and the window title to match.
+    fn update_presence(&self) {
+        let state = match *self.state.borrow() {
+            ProgramState::Title => PresenceState::Menu,
+            ProgramState::Game(ref game) => game.presence_state(),
+        };
+
+        if state != self.presence.state() {
+            self.window.set_title(&state.window_title(WINDOW_TITLE));
+            self.presence.update(state);
+        }
+    }
+
     /// Builds a new swap chain with the specified present mode and the window's current dimensions.
     fn recreate_swap_chain(&self, present_mode: wgpu::PresentMode) {
         let winit::dpi::PhysicalSize { width, height } = self.window.inner_size();
@@ -253,7 +923,7 @@ impl ClientProgram {
         let swap_chain_output = self.swap_chain.borrow_mut().get_next_frame().unwrap();
 
         match *self.state.borrow_mut() {
-            ProgramState::Title => unimplemented!(),
+            ProgramState::Title => self.render_title(&swap_chain_output.output.view),
             ProgramState::Game(ref mut game) => {
                 let winit::dpi::PhysicalSize { width, height } = self.window.inner_size();
                 game.render(
@@ -267,6 +937,53 @@ impl ClientProgram {
             }
         }
     }
+
+    /// Renders the console over a blank screen.
+    fn render_title(&self, color_attachment_view: &wgpu::TextureView) {
+        let winit::dpi::PhysicalSize { width, height } = self.window.inner_size();
+        let gfx_state = self.gfx_state.borrow();
+        let console = self.console.borrow();
+        let menu = self.menu.borrow();
+        let ui_state = UiState::Title {
+            overlay: match self.title_state.get() {
+                TitleState::Menu => UiOverlay::Menu(&menu),
+                TitleState::Console => UiOverlay::Console(&console, 1.0),
+            },
+        };
+
+        let mut encoder = gfx_state
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        {
+            // quad_commands must outlive final pass
+            let mut quad_commands = Vec::new();
+            let mut glyph_commands = Vec::new();
+
+            let final_pass_builder = gfx_state.final_pass_target().render_pass_builder();
+            let mut final_pass = encoder.begin_render_pass(&final_pass_builder.descriptor());
+
+            self.ui_renderer.render_pass(
+                &gfx_state,
+                &mut final_pass,
+                Extent2d { width, height },
+                self.title_time.get(),
+                &ui_state,
+                &mut quad_commands,
+                &mut glyph_commands,
+            );
+        }
+
+        // blit to swap chain
+        {
+            let swap_chain_target = SwapChainTarget::with_swap_chain_view(color_attachment_view);
+            let blit_pass_builder = swap_chain_target.render_pass_builder();
+            let mut blit_pass = encoder.begin_render_pass(&blit_pass_builder.descriptor());
+            gfx_state.blit_pipeline().blit(&gfx_state, &mut blit_pass);
+        }
+
+        gfx_state.queue().submit(vec![encoder.finish()]);
+    }
 }
 
 impl Program for ClientProgram {
@@ -278,10 +995,13 @@ impl Program for ClientProgram {
     ) {
         match event {
             Event::WindowEvent {
-                event: WindowEvent::Resized(_),
+                event: WindowEvent::Resized(size),
                 ..
             } => {
                 self.window_dimensions_changed.set(true);
+                self.input
+                    .borrow_mut()
+                    .set_display_size(size.width, size.height);
             }
 
             e => self.input.borrow_mut().handle_event(e).unwrap(),
@@ -310,34 +1030,144 @@ impl Program for ClientProgram {
         // recreate attachments and rebuild pipelines if necessary
         self.gfx_state.borrow_mut().update(size, sample_count);
 
-        match *self.state.borrow_mut() {
-            ProgramState::Title => unimplemented!(),
+        // re-execute watched configs that were edited
+        let watch_cfg = self
+            .cvars
+            .borrow()
+            .get_bool("dev_watchcfg")
+            .unwrap_or(false);
+        for name in self.cfg_watcher.poll(watch_cfg, frame_duration) {
+            let console = self.console.borrow();
+            console.output_mut().print(format!("{} changed", name));
+            console.stuff_text_from(CmdSource::Config, format!("exec {}", name));
+        }
 
-            ProgramState::Game(ref mut game) => {
-                game.frame(&self.gfx_state.borrow(), frame_duration);
+        // reload shaders if requested or if any were edited
+        let watch_shaders = self
+            .cvars
+            .borrow()
+            .get_bool("dev_watchshaders")
+            .unwrap_or(false);
+        let changed_shaders = self.shader_watcher.poll(watch_shaders, frame_duration);
+        if !changed_shaders.is_empty() {
+            self.console
+                .borrow()
+                .output_mut()
+                .print(format!("{} changed", changed_shaders.join(", ")));
+            self.reload_shaders.set(true);
+        }
+
+        if self.reload_shaders.replace(false) {
+            let loaded = self.gfx_state.borrow_mut().reload_shaders();
+            self.console
+                .borrow()
+                .output_mut()
+                .print(format!("Reloaded shaders ({} from shaders/)", loaded));
+        }
+
+        // gamepad input is polled rather than delivered through the event loop
+        let joy_enable = self.cvars.borrow().get_bool("joy_enable").unwrap_or(true);
+        let stick_vars = StickVars::from_cvars(&self.cvars.borrow());
+        self.input
+            .borrow_mut()
+            .poll_gamepads(joy_enable, stick_vars);
+
+        // run console commands, including those bound to keys pressed since the
+        // last frame, before the game samples its input
+        self.console.borrow().execute();
+
+        // connect if requested
+        let pending_connect = self.pending_connect.borrow_mut().take();
+        if let Some(server) = pending_connect {
+            self.connect(server);
+        }
+
+        // forward remote console commands if requested
+        let pending_rcon = self.pending_rcon.borrow_mut().take();
+        if let Some(command) = pending_rcon {
+            self.rcon(command);
+        }
+        self.poll_rcon(frame_duration);
+
+        // send connectionless requests if requested
+        #[cfg(feature = "qw")]
+        {
+            let pending_packet = self.pending_packet.borrow_mut().take();
+            if let Some((address, text)) = pending_packet {
+                self.packet(address, text);
             }
+            self.poll_packet(frame_duration);
         }
 
-        match self.input.borrow().current_focus() {
-            InputFocus::Game => {
-                self.window.set_cursor_grab(true).unwrap();
-                self.window.set_cursor_visible(false);
+        // switch game directories if requested
+        let pending_game = self.pending_game.borrow_mut().take();
+        if let Some(game) = pending_game {
+            self.switch_game(game);
+        }
+
+        let disconnected = match *self.state.borrow_mut() {
+            ProgramState::Title => {
+                // there's no game behind the console to switch to
+                if self.input.borrow_mut().take_console_toggle() {
+                    self.title_state.set(TitleState::Console);
+                }
+
+                // the title console is always fully open
+                self.input.borrow_mut().set_console_open_fraction(1.0);
+                self.input
+                    .borrow_mut()
+                    .set_focus(match self.title_state.get() {
+                        TitleState::Menu => InputFocus::Menu,
+                        TitleState::Console => InputFocus::Console,
+                    })
+                    .unwrap();
+
+                self.title_time.set(self.title_time.get() + frame_duration);
+                false
             }
 
-            _ => {
-                self.window.set_cursor_grab(false).unwrap();
-                self.window.set_cursor_visible(true);
+            ProgramState::Game(ref mut game) => {
+                match game.frame(&self.gfx_state.borrow(), frame_duration) {
+                    Ok(()) => game.connection_state() == ConnectionState::Disconnected,
+                    Err(e) => {
+                        self.console
+                            .borrow()
+                            .output_handle()
+                            .borrow_mut()
+                            .warn(l10n::tr_args("connect.lost", &[&e.to_string()]));
+                        true
+                    }
+                }
             }
+        };
+
+        // return to the console if the connection was dropped
+        if disconnected {
+            self.disconnect();
         }
 
-        // run console commands
-        self.console.borrow().execute();
+        self.update_presence();
+
+        self.update_cursor_grab();
+
+        self.console.borrow().output_mut().update(frame_duration);
 
         self.render();
+
+        if let Err(e) = self.gfx_state.borrow().end_frame() {
+            log::warn!("{}", e);
+        }
+    }
+
+    fn quit_requested(&self) -> bool {
+        self.quit.get()
     }
 
     fn shutdown(&mut self) {
-        // TODO: do cleanup things here
+        let config_dir = game_dir(&self.basedir, self.game.borrow().as_deref());
+        if let Err(e) = write_config(&config_dir, &self.cvars.borrow(), &self.input.borrow()) {
+            log::error!("Couldn't write {}: {}", CONFIG_FILE, e);
+        }
     }
 
     fn cvars(&self) -> Ref<CvarRegistry> {
@@ -349,18 +1179,507 @@ impl Program for ClientProgram {
     }
 }
 
+/// Writes the key bindings and archived cvars to the config file in the given game directory.
+fn write_config(game_dir: &Path, cvars: &CvarRegistry, input: &Input) -> Result<(), Error> {
+    let path = game_dir.join(CONFIG_FILE);
+    let mut file = AtomicFile::create(&path)?;
+    file.write_all(input.write_bindings().as_bytes())?;
+    file.write_all(cvars.write_archive().as_bytes())?;
+    file.commit()?;
+
+    Ok(())
+}
+
+/// Mounts basedir and its PAK archives, followed by those of the given mod
+/// directory, so that the mod's files take precedence.
+///
+/// Mod directories are siblings of basedir, as `hipnotic` is of `id1`.
+fn mount_game(basedir: &Path, game: Option<&str>) -> Result<Vfs, Error> {
+    let mut vfs = Vfs::new();
+    add_game_directory(&mut vfs, basedir)?;
+
+    if let Some(game) = game {
+        check_game_name(game)?;
+        let game_dir = game_dir(basedir, Some(game));
+        ensure!(
+            game_dir.is_dir(),
+            "No such game directory: {}",
+            game_dir.display()
+        );
+        add_game_directory(&mut vfs, &game_dir)?;
+    }
+
+    Ok(vfs)
+}
+
+// Checks that a game name is a single directory name, so that it can't
+// reach outside the parent of basedir (as with `game ../../etc`).
+fn check_game_name(game: &str) -> Result<(), Error> {
+    ensure!(
+        !game.is_empty()
+            && game != "."
+            && game != ".."
+            && !game.contains(|c| c == '/' || c == '\\' || c == ':'),
+        "Invalid game directory: {}",
+        game
+    );
+
+    Ok(())
+}
+
+// Resolves the argument of `game` or `-game` to a mod directory name.
+//
+// An empty name or the name of basedir itself means the base game.
+fn resolve_game(basedir: &Path, game: &str) -> Result<Option<String>, Error> {
+    if game.is_empty() || basedir.file_name().map_or(false, |n| n == game) {
+        return Ok(None);
+    }
+
+    check_game_name(game)?;
+    Ok(Some(game.to_owned()))
+}
+
+// Returns the directory of the given game, where its config.cfg is kept.
+fn game_dir(basedir: &Path, game: Option<&str>) -> PathBuf {
+    match game {
+        Some(g) => basedir.with_file_name(g),
+        None => basedir.to_owned(),
+    }
+}
+
+// Adds a game directory and its numbered PAK archives to the filesystem.
+fn add_game_directory(vfs: &mut Vfs, dir: &Path) -> Result<(), Error> {
+    // add the directory first
+    vfs.add_directory(dir)?;
+
+    // then add PAK archives
+    for vfs_id in 0..common::MAX_PAKFILES {
+        let path = dir.join(format!("pak{}.pak", vfs_id));
+
+        // keep adding PAKs until we don't find one or we hit MAX_PAKFILES
+        if !path.exists() {
+            break;
+        }
+
+        vfs.add_pakfile(path)?;
+    }
+
+    Ok(())
+}
+
+// Returns the name of the active game directory.
+fn game_name(basedir: &Path, game: Option<&String>) -> String {
+    match game {
+        Some(g) => g.clone(),
+        None => basedir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| basedir.display().to_string()),
+    }
+}
+
+/// Implements the `game` command.
+///
+/// With no arguments, prints the active game directory. Otherwise, switches to
+/// the given mod directory on the next frame.
+fn cmd_game(
+    output: Rc<RefCell<ConsoleOutput>>,
+    basedir: PathBuf,
+    game: Rc<RefCell<Option<String>>>,
+    pending_game: Rc<RefCell<Option<String>>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => match *game.borrow() {
+            Some(ref g) => output
+                .borrow_mut()
+                .print(format!("Game directory is {}", g)),
+            None => output.borrow_mut().print("Using the base game"),
+        },
+        1 => match resolve_game(&basedir, args[0]) {
+            Ok(_) => {
+                pending_game.replace(Some(args[0].to_owned()));
+            }
+            Err(e) => output.borrow_mut().warn(e.to_string()),
+        },
+        _ => output.borrow_mut().print("usage: game [<directory>]"),
+    })
+}
+
+/// Implements the `mods` command.
+///
+/// Lists the mod directories installed alongside basedir.
+fn cmd_mods(output: Rc<RefCell<ConsoleOutput>>, basedir: PathBuf) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        let mut output = output.borrow_mut();
+        match args.len() {
+            0 => {
+                let found = mods::discover(&basedir);
+                if found.is_empty() {
+                    output.print(format!("No mods found next to {}", basedir.display()));
+                }
+
+                for ModDir { name, info, .. } in found.iter() {
+                    match info.title {
+                        Some(ref title) => output.print(format!("{}: {}", name, title)),
+                        None => output.print(name),
+                    }
+
+                    if let Some(ref author) = info.author {
+                        output.print(format!("  by {}", author));
+                    }
+
+                    if let Some(ref description) = info.description {
+                        output.print(format!("  {}", description));
+                    }
+                }
+            }
+            _ => output.print("usage: mods"),
+        }
+    })
+}
+
+/// Implements the `exec` command.
+///
+/// `config.cfg` is read from the active game directory, where it is written, so a
+/// config.cfg shipped by a mod can't shadow the user's settings. Other scripts are
+/// read from the virtual filesystem.
+fn cmd_exec(
+    config_path: PathBuf,
+    vfs: Rc<Vfs>,
+    console: Weak<RefCell<Console>>,
+) -> Box<dyn Fn(&[&str])> {
+    let exec = console::cmd_exec(vfs, console.clone());
+    Box::new(move |args| match args {
+        [name] if name.eq_ignore_ascii_case(CONFIG_FILE) => {
+            let console = match console.upgrade() {
+                Some(c) => c,
+                None => return,
+            };
+            let console = console.borrow();
+
+            match fs::read(&config_path) {
+                Ok(script) => {
+                    console
+                        .output_mut()
+                        .print(format!("execing {}", CONFIG_FILE));
+                    console.insert_text(CmdSource::Config, String::from_utf8_lossy(&script));
+                }
+
+                // nothing has been saved for this game yet
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+
+                Err(e) => console.output_mut().warn(format!(
+                    "Couldn't read {}: {}",
+                    config_path.display(),
+                    e
+                )),
+            }
+        }
+
+        _ => exec(args),
+    })
+}
+
+/// Implements the `host_writeconfig` command.
+fn cmd_host_writeconfig(
+    output: Rc<RefCell<ConsoleOutput>>,
+    game_dir: PathBuf,
+    cvars: Rc<RefCell<CvarRegistry>>,
+    input: Rc<RefCell<Input>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        let mut output = output.borrow_mut();
+        match args.len() {
+            0 => match write_config(&game_dir, &cvars.borrow(), &input.borrow()) {
+                Ok(()) => output.print(format!("Wrote {}", CONFIG_FILE)),
+                Err(e) => output.warn(format!("Couldn't write {}: {}", CONFIG_FILE, e)),
+            },
+            _ => output.print("usage: host_writeconfig"),
+        }
+    })
+}
+
+/// Starts or stops mirroring console output to `path` when `condebug` changes.
+fn condebug_observer(path: PathBuf, console: Rc<RefCell<Console>>) -> Rc<dyn Fn(&str)> {
+    Rc::new(move |val| {
+        let console = console.borrow();
+        let mut output = console.output_mut();
+        match val.parse::<f32>() {
+            Ok(v) if v != 0.0 => match LogFileSink::append(&path) {
+                Ok(sink) => output.insert_sink("condebug", Box::new(sink)),
+                Err(e) => output.error(format!("Couldn't open {}: {}", path.display(), e)),
+            },
+            _ => output.remove_sink("condebug"),
+        }
+    })
+}
+
+fn fs_report_observer(output: Rc<RefCell<ConsoleOutput>>, vfs: Rc<Vfs>) -> Rc<dyn Fn(&str)> {
+    Rc::new(move |val| match val.parse::<f32>() {
+        Ok(v) if v != 0.0 => {
+            let mut output = output.borrow_mut();
+            let conflicts = vfs.conflicts();
+            if conflicts.is_empty() {
+                output.print("No files are shadowed");
+                return;
+            }
+
+            output.print(format!(
+                "{} files are provided by more than one source:",
+                conflicts.len()
+            ));
+            for conflict in conflicts {
+                output.print(conflict.to_string().trim_end());
+            }
+        }
+        _ => (),
+    })
+}
+
+// Registers the title screen's `togglemenu` and `toggleconsole`. The game replaces these
+// with its own while connected.
+fn register_title_cmds(cmds: &mut CmdRegistry, title_state: Rc<Cell<TitleState>>) {
+    let togglemenu_state = title_state.clone();
+    cmds.insert_or_replace(
+        "togglemenu",
+        Box::new(move |_| {
+            togglemenu_state.set(match togglemenu_state.get() {
+                TitleState::Menu => TitleState::Console,
+                TitleState::Console => TitleState::Menu,
+            })
+        }),
+    )
+    .unwrap();
+
+    // there's no game behind the console to switch to, so this only closes the menu
+    cmds.insert_or_replace(
+        "toggleconsole",
+        Box::new(move |_| title_state.set(TitleState::Console)),
+    )
+    .unwrap();
+}
+
+/// Implements the `quit` command.
+fn cmd_quit(output: Rc<RefCell<ConsoleOutput>>, quit: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => quit.set(true),
+        _ => output.borrow_mut().print("usage: quit"),
+    })
+}
+
+/// Implements the `stuffcmds` command.
+///
+/// Inserts the `+` commands from the command line into the console buffer.
+fn cmd_stuffcmds(console: Rc<RefCell<Console>>, commands: Vec<String>) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => {
+            if !commands.is_empty() {
+                console
+                    .borrow()
+                    .insert_text(CmdSource::User, commands.join("\n"));
+            }
+        }
+        _ => console.borrow().output_mut().print("usage: stuffcmds"),
+    })
+}
+
+/// Implements the `connect` command.
+fn cmd_connect(
+    output: Rc<RefCell<ConsoleOutput>>,
+    pending_connect: Rc<RefCell<Option<String>>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        1 => {
+            pending_connect.replace(Some(args[0].to_owned()));
+        }
+        _ => output
+            .borrow_mut()
+            .print(l10n::tr_args("console.usage", &["connect <server>"])),
+    })
+}
+
+/// Implements the `rcon` command.
+fn cmd_rcon(
+    output: Rc<RefCell<ConsoleOutput>>,
+    pending_rcon: Rc<RefCell<Option<String>>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => output
+            .borrow_mut()
+            .print(l10n::tr_args("console.usage", &["rcon <command>"])),
+        _ => {
+            // quote the arguments so the server parses them as they were given
+            let quoted: Vec<String> = args.iter().map(quote).collect();
+            pending_rcon.replace(Some(quoted.join(" ")));
+        }
+    })
+}
+
+/// Implements the `packet` command.
+///
+/// As in QuakeWorld, everything after the address is sent as the request, e.g.
+/// `packet localhost status`.
+#[cfg(feature = "qw")]
+fn cmd_packet(
+    output: Rc<RefCell<ConsoleOutput>>,
+    pending_packet: Rc<RefCell<Option<(String, String)>>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 | 1 => output.borrow_mut().print(l10n::tr_args(
+            "console.usage",
+            &["packet <server> <request>"],
+        )),
+        _ => {
+            pending_packet.replace(Some((args[0].to_owned(), args[1..].join(" "))));
+        }
+    })
+}
+
+// Resolves a server address, using the default port if none is given.
+fn resolve_server(address: &str) -> Option<SocketAddr> {
+    let address = if address.contains(':') {
+        address.to_owned()
+    } else {
+        format!("{}:{}", address, common::net::DEFAULT_PORT)
+    };
+
+    address.to_socket_addrs().ok()?.next()
+}
+
+/// Implements the `hud_reload` command.
+fn cmd_hud_reload(
+    output: Rc<RefCell<ConsoleOutput>>,
+    vfs: Rc<Vfs>,
+    hud_layout: Rc<RefCell<HudLayout>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => match HudLayout::load(&vfs) {
+            Ok(l) => {
+                hud_layout.replace(l);
+            }
+            Err(e) => output
+                .borrow_mut()
+                .error(format!("Couldn't load {}: {}", HUD_LAYOUT_FILE, e)),
+        },
+        _ => output.borrow_mut().print("usage: hud_reload"),
+    })
+}
+
 #[derive(StructOpt, Debug)]
 struct Opt {
     #[structopt(long)]
     trace: bool,
 
+    #[structopt(long, default_value = "en")]
+    language: String,
+
     #[structopt(name = "SERVER")]
-    server: String,
+    server: Option<String>,
+}
+
+/// Quake-style command-line arguments, which don't follow structopt's conventions.
+#[derive(Debug, Default)]
+struct QuakeArgs {
+    /// Console commands given as `+command [args...]`, followed by a command
+    /// for each `-set`.
+    commands: Vec<String>,
+
+    /// Cvar values given as `-set <cvar> <value>`.
+    sets: Vec<(String, String)>,
+
+    /// Game directory given as `-basedir <dir>`.
+    basedir: Option<String>,
+
+    /// Mod directory given as `-game <dir>`, mounted over basedir.
+    game: Option<String>,
+
+    /// Whether `-condebug` was given.
+    condebug: bool,
+}
+
+// Returns whether a command-line argument starts a new `+` command or `-` option,
+// rather than being an argument to the previous one. Negative numbers are arguments.
+fn is_switch(arg: &str) -> bool {
+    match arg.chars().next() {
+        Some('+') => arg.len() > 1,
+        Some('-') => {
+            arg.len() > 1 && !arg[1..].starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        }
+        _ => false,
+    }
+}
+
+// Returns a command-line argument as a console argument, quoted if necessary.
+fn console_arg(arg: &str) -> String {
+    if needs_quotes(arg) {
+        quote(arg)
+    } else {
+        arg.to_owned()
+    }
+}
+
+/// Separates Quake-style arguments from the ones handled by `Opt`.
+///
+/// A `+` starts a console command which takes every following argument up to the next one
+/// starting with `+` or `-`, other than negative numbers. `-set <cvar> <value>` sets a cvar.
+/// `-basedir` and `-game` select the game and mod directories. `-condebug` sets `condebug`
+/// at startup. `-window` is accepted for compatibility; the client always runs in a window.
+fn split_quake_args<I>(args: I) -> (QuakeArgs, Vec<String>)
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut quake_args = QuakeArgs::default();
+    let mut rest = Vec::new();
+    let mut args = args.into_iter().peekable();
+
+    // program name
+    rest.extend(args.next());
+
+    while let Some(arg) = args.next() {
+        if arg.starts_with('+') && arg.len() > 1 {
+            let mut command = arg[1..].to_owned();
+            while let Some(next) = args.peek() {
+                if is_switch(next) {
+                    break;
+                }
+
+                command.push(' ');
+                command.push_str(&console_arg(next));
+                args.next();
+            }
+
+            quake_args.commands.push(command);
+        } else if arg == "-set" {
+            if let (Some(name), Some(value)) = (args.next(), args.next()) {
+                quake_args.sets.push((name, value));
+            }
+        } else if arg == "-basedir" {
+            quake_args.basedir = args.next();
+        } else if arg == "-game" {
+            quake_args.game = args.next();
+        } else if arg == "-condebug" {
+            quake_args.condebug = true;
+        } else if arg == "-window" {
+            // windowed is the only mode
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    // set again after the configs have run, so the command line wins
+    for (name, value) in quake_args.sets.iter() {
+        quake_args
+            .commands
+            .push(format!("{} {}", console_arg(name), console_arg(value)));
+    }
+
+    (quake_args, rest)
 }
 
 fn main() {
     env_logger::init();
-    let opt = Opt::from_args();
+    let (quake_args, args) = split_quake_args(std::env::args());
+    let opt = Opt::from_iter(args);
 
     let audio_device = rodio::default_output_device().unwrap();
 
@@ -372,7 +1691,7 @@ fn main() {
             winit::window::WindowBuilder::new()
                 // disable file drag-and-drop so cpal and winit play nice
                 .with_drag_and_drop(false)
-                .with_title("Richter client")
+                .with_title(WINDOW_TITLE)
                 .with_inner_size(winit::dpi::PhysicalSize::<u32>::from((1366u32, 768)))
                 .build(&event_loop)
                 .unwrap()
@@ -381,19 +1700,136 @@ fn main() {
         #[cfg(not(target_os = "windows"))]
         {
             winit::window::WindowBuilder::new()
-                .with_title("Richter client")
+                .with_title(WINDOW_TITLE)
                 .with_inner_size(winit::dpi::PhysicalSize::<u32>::from((1366u32, 768)))
                 .build(&event_loop)
                 .unwrap()
         }
     };
 
-    let mut client_program =
-        futures::executor::block_on(ClientProgram::new(window, audio_device, opt.trace));
-    client_program.connect(opt.server);
+    let mut client_program = futures::executor::block_on(ClientProgram::new(
+        window,
+        audio_device,
+        opt.trace,
+        &opt.language,
+        quake_args,
+    ));
+    if let Some(server) = opt.server {
+        client_program.connect(server);
+    }
     let mut host = Host::new(client_program);
 
     event_loop.run(move |event, _target, control_flow| {
         host.handle_event(event, _target, control_flow);
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{env, io::Read};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn test_split_quake_args() {
+        let cases: &[(Vec<String>, &[&str], &[&str])] = &[
+            (args("quake"), &[], &["quake"]),
+            (
+                args("quake +connect localhost --trace"),
+                &["connect localhost"],
+                &["quake", "--trace"],
+            ),
+            (
+                args("quake +map e1m1 +m_pitch -0.022 -window +exec autoexec.cfg"),
+                &["map e1m1", "m_pitch -0.022", "exec autoexec.cfg"],
+                &["quake"],
+            ),
+            (
+                args("quake -set sensitivity -4.5 -set r_particles 1"),
+                &["sensitivity -4.5", "r_particles 1"],
+                &["quake"],
+            ),
+            (args("quake -set sensitivity"), &[], &["quake"]),
+            (
+                vec![
+                    "quake".to_owned(),
+                    "+name".to_owned(),
+                    "Lord \"Ranger\"".to_owned(),
+                    "-set".to_owned(),
+                    "_cl_name".to_owned(),
+                    "two words".to_owned(),
+                ],
+                &["name \"Lord \\\"Ranger\\\"\"", "_cl_name \"two words\""],
+                &["quake"],
+            ),
+        ];
+
+        for (line, commands, rest) in cases {
+            let (quake_args, other) = split_quake_args(line.clone());
+            assert_eq!(quake_args.commands, *commands, "{:?}", line);
+            assert_eq!(other, *rest, "{:?}", line);
+        }
+
+        let (quake_args, _) = split_quake_args(args(
+            "quake -basedir /games/quake/id1 -game hipnotic -condebug -set sensitivity 5",
+        ));
+        assert_eq!(quake_args.basedir.as_deref(), Some("/games/quake/id1"));
+        assert_eq!(quake_args.game.as_deref(), Some("hipnotic"));
+        assert!(quake_args.condebug);
+        assert_eq!(
+            quake_args.sets,
+            vec![("sensitivity".to_owned(), "5".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_resolve_game() {
+        let basedir = Path::new("quake/id1");
+        assert_eq!(resolve_game(basedir, "").unwrap(), None);
+        assert_eq!(resolve_game(basedir, "id1").unwrap(), None);
+        assert_eq!(
+            resolve_game(basedir, "hipnotic").unwrap(),
+            Some("hipnotic".to_owned())
+        );
+
+        for bad in &[".", "..", "../etc", "a/b", "a\\b", "/etc", "C:mod"] {
+            assert!(resolve_game(basedir, bad).is_err(), "{} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn test_mount_game() {
+        let root = env::temp_dir().join(format!("richter-mount-game-{}", std::process::id()));
+        let basedir = root.join("id1");
+        for dir in &["id1", "hipnotic", "id1/maps"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(basedir.join("default.cfg"), "base").unwrap();
+        fs::write(root.join("hipnotic/default.cfg"), "mod").unwrap();
+
+        let base = mount_game(&basedir, None).unwrap();
+        assert!(base.open("default.cfg").is_ok());
+
+        // the mod's files take precedence over basedir's
+        let hipnotic = mount_game(&basedir, Some("hipnotic")).unwrap();
+        let mut contents = String::new();
+        hipnotic
+            .open("default.cfg")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "mod");
+
+        // missing directories and anything outside basedir's parent are refused
+        assert!(mount_game(&basedir, Some("rogue")).is_err());
+        assert!(mount_game(&basedir, Some("..")).is_err());
+        assert!(mount_game(&basedir, Some("id1/maps")).is_err());
+        assert!(mount_game(&basedir, Some("../richter-mount-game")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -18,17 +18,166 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use richter::client::menu::{Menu, MenuBodyView, MenuBuilder, MenuView};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use richter::{
+    client::{
+        input::gamepad,
+        menu::{EnumItem, Menu, MenuBodyView, MenuBuilder, MenuView},
+    },
+    common::{
+        console::{Console, CvarRegistry},
+        l10n::tr,
+        mods::ModDir,
+    },
+};
 
 use failure::Error;
 
-pub fn build_main_menu() -> Result<Menu, Error> {
+const HOST_SKILLS: &[&str] = &[
+    "menu.mp.host.skill_easy",
+    "menu.mp.host.skill_normal",
+    "menu.mp.host.skill_hard",
+    "menu.mp.host.skill_nightmare",
+];
+
+/// Stick layouts offered by the options menu, as arguments to `joy_preset`.
+const JOY_PRESETS: &[(&str, &str)] = &[
+    ("default", "menu.options.joy_preset_default"),
+    ("southpaw", "menu.options.joy_preset_southpaw"),
+];
+
+/// Stick response curves offered by the options menu, as values of `joy_exponent`.
+///
+/// A custom curve follows these, and restores the last other exponent seen.
+const JOY_CURVES: &[(f32, &str)] = &[
+    (1.0, "menu.options.joy_curve_linear"),
+    (2.0, "menu.options.joy_curve_squared"),
+];
+
+/// The exponent chosen by the custom curve until `joy_exponent` is set to something else.
+const DEFAULT_CUSTOM_JOY_EXPONENT: f32 = 1.5;
+
+const HOST_MAX_PLAYERS: &[usize] = &[2, 4, 8, 16];
+
+// 0 means no limit
+const HOST_FRAG_LIMITS: &[usize] = &[0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100];
+const HOST_TIME_LIMITS: &[usize] = &[0, 5, 10, 15, 20, 25, 30, 45, 60];
+
+// Builds enum items which store the corresponding value in a setting when chosen.
+fn setting_items<T, N, F>(values: &[T], name: N, on_select: F) -> Result<Vec<EnumItem>, Error>
+where
+    T: Clone + 'static,
+    N: Fn(&T) -> String,
+    F: Fn(T) + Clone + 'static,
+{
+    values
+        .iter()
+        .map(|value| {
+            let value = value.clone();
+            let on_select = on_select.clone();
+            EnumItem::new(name(&value), Box::new(move || on_select(value.clone())))
+        })
+        .collect()
+}
+
+// Builds a menu action which runs `commands` in the console.
+fn stuff_action<S>(console: Rc<RefCell<Console>>, commands: S) -> Box<dyn Fn()>
+where
+    S: Into<String>,
+{
+    let commands = commands.into();
+    Box::new(move || console.borrow().stuff_text(&commands))
+}
+
+// Builds a toggle callback which sets the named cvars to the first value when
+// switched on and the second when switched off.
+fn cvar_toggle(
+    console: Rc<RefCell<Console>>,
+    cvars: &'static [(&'static str, &'static str, &'static str)],
+) -> Box<dyn Fn(bool)> {
+    Box::new(move |on| {
+        let console = console.borrow();
+        for (name, on_value, off_value) in cvars {
+            let value = if on { on_value } else { off_value };
+            console.stuff_text(format!("{} {}\n", name, value));
+        }
+    })
+}
+
+// Builds the source of a synced menu item from the numeric value of a cvar.
+fn cvar_source<T, F>(
+    cvars: Rc<RefCell<CvarRegistry>>,
+    name: &'static str,
+    f: F,
+) -> Box<dyn Fn() -> T>
+where
+    F: Fn(f32) -> T + 'static,
+{
+    Box::new(move || f(cvars.borrow().get_value(name).unwrap_or(0.0)))
+}
+
+// Builds enum items which set the named cvar to the corresponding value when chosen.
+fn cvar_items<N>(
+    console: Rc<RefCell<Console>>,
+    name: &'static str,
+    values: &[usize],
+    item_name: N,
+) -> Result<Vec<EnumItem>, Error>
+where
+    N: Fn(&usize) -> String,
+{
+    setting_items(values, item_name, move |value| {
+        console.borrow().stuff_text(format!("{} {}\n", name, value))
+    })
+}
+
+// Builds the source of a synced enum from the position of a cvar's value in `values`.
+fn cvar_index(
+    cvars: Rc<RefCell<CvarRegistry>>,
+    name: &'static str,
+    values: &'static [usize],
+) -> Box<dyn Fn() -> Option<usize>> {
+    cvar_source(cvars, name, move |value| {
+        values.iter().position(|v| *v as f32 == value)
+    })
+}
+
+fn limit_name(limit: &usize) -> String {
+    match limit {
+        0 => tr("menu.mp.host.none"),
+        l => l.to_string(),
+    }
+}
+
+/// Builds the main menu.
+///
+/// `base_game` is the name of the base game directory, and `mods` are the mods
+/// installed alongside it.
+pub fn build_main_menu(
+    console: Rc<RefCell<Console>>,
+    cvars: Rc<RefCell<CvarRegistry>>,
+    base_game: &str,
+    mods: &[ModDir],
+) -> Result<Menu, Error> {
     Ok(MenuBuilder::new()
-        .add_submenu("Single Player", build_menu_sp()?)
-        .add_submenu("Multiplayer", build_menu_mp()?)
-        .add_submenu("Options", build_menu_options()?)
-        .add_action("Help/Ordering", Box::new(|| ()))
-        .add_action("Quit", Box::new(|| ()))
+        .add_submenu(
+            tr("menu.main.single_player"),
+            build_menu_sp(console.clone())?,
+        )
+        .add_submenu(
+            tr("menu.main.multiplayer"),
+            build_menu_mp(console.clone(), cvars.clone())?,
+        )
+        .add_submenu(
+            tr("menu.main.options"),
+            build_menu_options(console.clone(), cvars, base_game, mods)?,
+        )
+        .add_action(tr("menu.main.help"), Box::new(|| ()))
+        .add_submenu(tr("menu.main.quit"), build_menu_quit(console)?)
         .build(MenuView {
             draw_plaque: true,
             title_path: "gfx/ttl_main.lmp".to_string(),
@@ -38,9 +187,20 @@ pub fn build_main_menu() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_sp() -> Result<Menu, Error> {
+fn build_menu_sp(console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
     Ok(MenuBuilder::new()
-        .add_action("New Game", Box::new(|| ()))
+        // there's no local server to run the game on, so say so rather than doing nothing
+        .add_action(
+            tr("menu.sp.new_game"),
+            Box::new(move || {
+                let console = console.borrow();
+                console
+                    .output_handle()
+                    .borrow_mut()
+                    .warn(tr("menu.sp.no_server"));
+                console.stuff_text("togglemenu\ntoggleconsole\n");
+            }),
+        )
         // .add_submenu("Load", unimplemented!())
         // .add_submenu("Save", unimplemented!())
         .build(MenuView {
@@ -52,10 +212,13 @@ fn build_menu_sp() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_mp() -> Result<Menu, Error> {
+fn build_menu_mp(
+    console: Rc<RefCell<Console>>,
+    cvars: Rc<RefCell<CvarRegistry>>,
+) -> Result<Menu, Error> {
     Ok(MenuBuilder::new()
-        .add_submenu("Join a Game", build_menu_mp_join()?)
-        // .add_submenu("New Game", unimplemented!())
+        .add_submenu(tr("menu.mp.join_game"), build_menu_mp_join()?)
+        .add_submenu(tr("menu.mp.new_game"), build_menu_mp_host(console, cvars)?)
         // .add_submenu("Setup", unimplemented!())
         .build(MenuView {
             draw_plaque: true,
@@ -68,7 +231,7 @@ fn build_menu_mp() -> Result<Menu, Error> {
 
 fn build_menu_mp_join() -> Result<Menu, Error> {
     Ok(MenuBuilder::new()
-        .add_submenu("TCP", build_menu_mp_join_tcp()?)
+        .add_submenu(tr("menu.mp.tcp"), build_menu_mp_join_tcp()?)
         // .add_textbox // description
         .build(MenuView {
             draw_plaque: true,
@@ -100,25 +263,266 @@ fn build_menu_mp_join_tcp() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_options() -> Result<Menu, Error> {
+fn build_menu_mp_host(
+    console: Rc<RefCell<Console>>,
+    cvars: Rc<RefCell<CvarRegistry>>,
+) -> Result<Menu, Error> {
+    // Host Game                     // title
+    //
+    //  Game Type    Cooperative     // enum
+    //  Skill        Normal          // enum
+    //  Max players  4               // enum
+    //  Frag Limit   None            // enum
+    //  Time Limit   None            // enum
+    //
+    // Settings are written to the server cvars as they're chosen. There's no map selection or
+    // "Begin game" action yet, since the client can't run a local server to start a game on.
+    let c = console.clone();
+    let game_types = setting_items(
+        &[false, true],
+        |&deathmatch| {
+            if deathmatch {
+                tr("menu.mp.host.deathmatch")
+            } else {
+                tr("menu.mp.host.coop")
+            }
+        },
+        move |deathmatch| {
+            let deathmatch = deathmatch as u8;
+            c.borrow().stuff_text(format!(
+                "coop {}\ndeathmatch {}\n",
+                1 - deathmatch,
+                deathmatch
+            ));
+        },
+    )?;
+
+    Ok(MenuBuilder::new()
+        .add_synced_enum(
+            tr("menu.mp.host.game_type"),
+            game_types,
+            cvar_source(cvars.clone(), "deathmatch", |dm| Some((dm != 0.0) as usize)),
+        )?
+        .add_synced_enum(
+            tr("menu.mp.host.skill"),
+            cvar_items(console.clone(), "skill", &[0, 1, 2, 3], |&skill| {
+                tr(HOST_SKILLS[skill])
+            })?,
+            cvar_index(cvars.clone(), "skill", &[0, 1, 2, 3]),
+        )?
+        .add_synced_enum(
+            tr("menu.mp.host.max_players"),
+            cvar_items(console.clone(), "maxplayers", HOST_MAX_PLAYERS, |max| {
+                max.to_string()
+            })?,
+            cvar_index(cvars.clone(), "maxplayers", HOST_MAX_PLAYERS),
+        )?
+        .add_synced_enum(
+            tr("menu.mp.host.frag_limit"),
+            cvar_items(console.clone(), "fraglimit", HOST_FRAG_LIMITS, limit_name)?,
+            cvar_index(cvars.clone(), "fraglimit", HOST_FRAG_LIMITS),
+        )?
+        .add_synced_enum(
+            tr("menu.mp.host.time_limit"),
+            cvar_items(console, "timelimit", HOST_TIME_LIMITS, limit_name)?,
+            cvar_index(cvars, "timelimit", HOST_TIME_LIMITS),
+        )?
+        .build(MenuView {
+            draw_plaque: true,
+            title_path: "gfx/p_multi.lmp".to_string(),
+            body: MenuBodyView::Dynamic,
+        }))
+}
+
+fn build_menu_quit(console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
+    // backing out of the menu cancels
+    Ok(MenuBuilder::new()
+        .add_action(tr("menu.quit.confirm"), stuff_action(console, "quit\n"))
+        .build(MenuView {
+            draw_plaque: true,
+            title_path: "gfx/ttl_main.lmp".to_string(),
+            body: MenuBodyView::Dynamic,
+        }))
+}
+
+fn build_menu_options(
+    console: Rc<RefCell<Console>>,
+    cvars: Rc<RefCell<CvarRegistry>>,
+    base_game: &str,
+    mods: &[ModDir],
+) -> Result<Menu, Error> {
+    let c = console.clone();
+    let joy_presets = setting_items(
+        JOY_PRESETS,
+        |(_, name)| tr(name),
+        move |(preset, _)| {
+            c.borrow().stuff_text(format!("joy_preset {}\n", preset));
+        },
+    )?;
+
+    let joy_preset_cvars = cvars.clone();
+    let joy_preset_source = Box::new(move || {
+        let preset = gamepad::current_axis_preset(&joy_preset_cvars.borrow())?;
+        JOY_PRESETS.iter().position(|(name, _)| *name == preset)
+    });
+
+    let c = console.clone();
+    let mut joy_curves = setting_items(
+        JOY_CURVES,
+        |(_, name)| tr(name),
+        move |(exponent, _)| {
+            c.borrow()
+                .stuff_text(format!("joy_exponent {}\n", exponent));
+        },
+    )?;
+
+    let custom_exponent = Rc::new(Cell::new(DEFAULT_CUSTOM_JOY_EXPONENT));
+    let c = console.clone();
+    let custom = custom_exponent.clone();
+    joy_curves.push(EnumItem::new(
+        tr("menu.options.joy_curve_custom"),
+        Box::new(move || {
+            c.borrow()
+                .stuff_text(format!("joy_exponent {}\n", custom.get()));
+        }),
+    )?);
+
+    let joy_curve_source = cvar_source(cvars.clone(), "joy_exponent", move |exponent| {
+        let preset = JOY_CURVES.iter().position(|(e, _)| *e == exponent);
+        if preset.is_none() {
+            custom_exponent.set(exponent);
+        }
+        Some(preset.unwrap_or(JOY_CURVES.len()))
+    });
+
     Ok(MenuBuilder::new()
         // .add_submenu("Customize controls", unimplemented!())
-        .add_action("Go to console", Box::new(|| ()))
-        .add_action("Reset to defaults", Box::new(|| ()))
-        .add_slider("Render scale", 0.25, 1.0, 2, 0, Box::new(|_| ()))?
-        .add_slider("Screen Size", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("Brightness", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("Mouse Speed", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("CD music volume", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("Sound volume", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_toggle("Always run", true, Box::new(|_| ()))
-        .add_toggle("Invert mouse", false, Box::new(|_| ()))
-        .add_toggle("Lookspring", false, Box::new(|_| ()))
-        .add_toggle("Lookstrafe", false, Box::new(|_| ()))
+        .add_action(
+            tr("menu.options.go_to_console"),
+            stuff_action(console.clone(), "togglemenu\ntoggleconsole\n"),
+        )
+        .add_action(
+            tr("menu.options.reset_defaults"),
+            stuff_action(console.clone(), "exec default.cfg\n"),
+        )
+        .add_slider(
+            tr("menu.options.render_scale"),
+            0.25,
+            1.0,
+            2,
+            0,
+            Box::new(|_| ()),
+        )?
+        .add_slider(
+            tr("menu.options.screen_size"),
+            0.0,
+            1.0,
+            10,
+            9,
+            Box::new(|_| ()),
+        )?
+        .add_slider(
+            tr("menu.options.brightness"),
+            0.0,
+            1.0,
+            10,
+            9,
+            Box::new(|_| ()),
+        )?
+        .add_synced_slider(
+            tr("menu.options.mouse_speed"),
+            1.0,
+            11.0,
+            11,
+            cvar_source(cvars.clone(), "sensitivity", |speed| speed),
+            {
+                let c = console.clone();
+                Box::new(move |speed| {
+                    c.borrow().stuff_text(format!("sensitivity {}\n", speed));
+                })
+            },
+        )?
+        .add_slider(
+            tr("menu.options.cd_volume"),
+            0.0,
+            1.0,
+            10,
+            9,
+            Box::new(|_| ()),
+        )?
+        .add_slider(
+            tr("menu.options.sound_volume"),
+            0.0,
+            1.0,
+            10,
+            9,
+            Box::new(|_| ()),
+        )?
+        .add_synced_toggle(
+            tr("menu.options.always_run"),
+            cvar_source(cvars.clone(), "cl_forwardspeed", |speed| speed > 200.0),
+            cvar_toggle(
+                console.clone(),
+                &[
+                    ("cl_forwardspeed", "400", "200"),
+                    ("cl_backspeed", "400", "200"),
+                ],
+            ),
+        )
+        .add_synced_toggle(
+            tr("menu.options.invert_mouse"),
+            cvar_source(cvars.clone(), "m_invert", |invert| invert != 0.0),
+            cvar_toggle(console.clone(), &[("m_invert", "1", "0")]),
+        )
+        .add_synced_toggle(
+            tr("menu.options.lookspring"),
+            cvar_source(cvars.clone(), "lookspring", |lookspring| lookspring != 0.0),
+            cvar_toggle(console.clone(), &[("lookspring", "1", "0")]),
+        )
+        .add_synced_toggle(
+            tr("menu.options.lookstrafe"),
+            cvar_source(cvars, "lookstrafe", |lookstrafe| lookstrafe != 0.0),
+            cvar_toggle(console.clone(), &[("lookstrafe", "1", "0")]),
+        )
+        .add_synced_enum(
+            tr("menu.options.joy_preset"),
+            joy_presets,
+            joy_preset_source,
+        )?
+        .add_synced_enum(tr("menu.options.joy_curve"), joy_curves, joy_curve_source)?
         // .add_submenu("Video options", unimplemented!())
+        .add_submenu(
+            tr("menu.options.mods"),
+            build_menu_mods(console, base_game, mods)?,
+        )
         .build(MenuView {
             draw_plaque: true,
             title_path: "gfx/p_option.lmp".to_string(),
             body: MenuBodyView::Dynamic,
         }))
 }
+
+fn build_menu_mods(
+    console: Rc<RefCell<Console>>,
+    base_game: &str,
+    mods: &[ModDir],
+) -> Result<Menu, Error> {
+    // the switch happens on the next frame, so close the menu first
+    let mut builder = MenuBuilder::new().add_action(
+        tr("menu.mods.base_game"),
+        stuff_action(console.clone(), format!("togglemenu\ngame {}\n", base_game)),
+    );
+
+    for m in mods {
+        builder = builder.add_action(
+            m.title(),
+            stuff_action(console.clone(), format!("togglemenu\ngame {}\n", m.name)),
+        );
+    }
+
+    Ok(builder.build(MenuView {
+        draw_plaque: true,
+        title_path: "gfx/p_option.lmp".to_string(),
+        body: MenuBodyView::Dynamic,
+    }))
+}
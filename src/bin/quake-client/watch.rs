@@ -0,0 +1,151 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::Duration;
+
+/// How often watched files are checked for changes.
+const POLL_INTERVAL_MS: i64 = 500;
+
+/// Watches a set of files in a directory for changes.
+///
+/// Files are checked by polling their modification times, so this works the
+/// same on every platform and doesn't need a background thread.
+pub struct FileWatcher {
+    dir: PathBuf,
+    names: Vec<String>,
+    mtimes: Vec<Option<SystemTime>>,
+    since_poll: Duration,
+
+    // set while watching is disabled, so the next enabled poll only records
+    // the current modification times
+    stale: bool,
+}
+
+impl FileWatcher {
+    /// Creates a watcher for the named files in `dir`.
+    ///
+    /// Files which already exist are not reported until they change again.
+    pub fn new<P, I, S>(dir: P, names: I) -> FileWatcher
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let dir = dir.as_ref().to_owned();
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        let mtimes = names.iter().map(|name| modified(&dir.join(name))).collect();
+
+        FileWatcher {
+            dir,
+            names,
+            mtimes,
+            since_poll: Duration::zero(),
+            stale: false,
+        }
+    }
+
+    /// Returns the names of the files which changed since the last poll.
+    ///
+    /// The filesystem is only checked every `POLL_INTERVAL_MS` milliseconds,
+    /// and not at all while `enabled` is false. Changes made while disabled are
+    /// not reported once the watcher is enabled again.
+    pub fn poll(&mut self, enabled: bool, frame_duration: Duration) -> Vec<String> {
+        if !enabled {
+            self.stale = true;
+            return Vec::new();
+        }
+
+        if self.stale {
+            self.stale = false;
+            self.since_poll = Duration::zero();
+            for (name, mtime) in self.names.iter().zip(self.mtimes.iter_mut()) {
+                *mtime = modified(&self.dir.join(name));
+            }
+            return Vec::new();
+        }
+
+        self.since_poll = self.since_poll + frame_duration;
+        if self.since_poll < Duration::milliseconds(POLL_INTERVAL_MS) {
+            return Vec::new();
+        }
+        self.since_poll = Duration::zero();
+
+        let mut changed = Vec::new();
+        for (name, mtime) in self.names.iter().zip(self.mtimes.iter_mut()) {
+            let new_mtime = modified(&self.dir.join(name));
+
+            // deleting a file isn't a change we can act on
+            if new_mtime.is_some() && new_mtime != *mtime {
+                changed.push(name.clone());
+            }
+
+            *mtime = new_mtime;
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{env, fs::File, time::Duration as StdDuration};
+
+    fn touch(path: &Path, secs: u64) {
+        let file = File::create(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + StdDuration::from_secs(secs))
+            .unwrap();
+    }
+
+    fn poll_interval() -> Duration {
+        Duration::milliseconds(POLL_INTERVAL_MS)
+    }
+
+    #[test]
+    fn test_file_watcher() {
+        let dir = env::temp_dir().join(format!("richter-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        touch(&dir.join("autoexec.cfg"), 1000);
+
+        let mut watcher = FileWatcher::new(&dir, vec!["autoexec.cfg", "dev.cfg"]);
+
+        // existing files aren't reported, and nothing is checked between polls
+        assert!(watcher.poll(true, poll_interval()).is_empty());
+        touch(&dir.join("autoexec.cfg"), 2000);
+        assert!(watcher.poll(true, Duration::milliseconds(1)).is_empty());
+        assert_eq!(
+            watcher.poll(true, poll_interval()),
+            vec!["autoexec.cfg".to_owned()]
+        );
+
+        // creating a file counts as a change, deleting one doesn't
+        touch(&dir.join("dev.cfg"), 1000);
+        fs::remove_file(dir.join("autoexec.cfg")).unwrap();
+        assert_eq!(
+            watcher.poll(true, poll_interval()),
+            vec!["dev.cfg".to_owned()]
+        );
+
+        // changes made while disabled are dropped
+        touch(&dir.join("dev.cfg"), 2000);
+        assert!(watcher.poll(false, poll_interval()).is_empty());
+        assert!(watcher.poll(true, poll_interval()).is_empty());
+        assert!(watcher.poll(true, poll_interval()).is_empty());
+
+        touch(&dir.join("dev.cfg"), 3000);
+        assert_eq!(
+            watcher.poll(true, poll_interval()),
+            vec!["dev.cfg".to_owned()]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Named camera bookmarks.
+//!
+//! `cam_save <name>` records the current camera position and angles.
+//! `cam_goto <name> [seconds]` detaches the camera from the player and moves
+//! it to a bookmark, gliding there over the given time if one is provided.
+//! `cam_release` returns the camera to the player.
+//!
+//! Camera movement runs on frame time rather than the client clock, so the
+//! camera can still be repositioned while the game is paused.
+//!
+//! Bookmarks are for studying demos, so the commands are only available
+//! during demo playback.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use crate::{
+    client::connection::ConnectionState,
+    common::{console::ConsoleOutput, engine, l10n, math::Angles},
+};
+
+use cgmath::{Deg, Vector3, VectorSpace as _};
+use chrono::Duration;
+
+/// A camera position and orientation.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraBookmark {
+    pub origin: Vector3<f32>,
+    pub angles: Angles,
+}
+
+impl CameraBookmark {
+    /// Returns the camera `t` of the way from `self` to `other`.
+    ///
+    /// Angles turn the short way around, so a yaw of 350° moves to 10° through 0°.
+    pub fn lerp(&self, other: &CameraBookmark, t: f32) -> CameraBookmark {
+        let t = t.max(0.0).min(1.0);
+        let lerp_angle = |from: Deg<f32>, to: Deg<f32>| {
+            let mut delta = (to.0 - from.0) % 360.0;
+            if delta > 180.0 {
+                delta -= 360.0;
+            } else if delta < -180.0 {
+                delta += 360.0;
+            }
+
+            Deg(from.0 + delta * t)
+        };
+
+        CameraBookmark {
+            origin: self.origin.lerp(other.origin, t),
+            angles: Angles {
+                pitch: lerp_angle(self.angles.pitch, other.angles.pitch),
+                roll: lerp_angle(self.angles.roll, other.angles.roll),
+                yaw: lerp_angle(self.angles.yaw, other.angles.yaw),
+            },
+        }
+    }
+}
+
+// a camera move in progress
+#[derive(Clone, Copy, Debug)]
+struct CameraPath {
+    from: CameraBookmark,
+    to: CameraBookmark,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl CameraPath {
+    fn position(&self) -> CameraBookmark {
+        if self.duration <= Duration::zero() {
+            return self.to;
+        }
+
+        // ease in and out so the camera doesn't jerk at either end
+        let t = engine::duration_to_f32(self.elapsed) / engine::duration_to_f32(self.duration);
+        let t = t.max(0.0).min(1.0);
+        self.from.lerp(&self.to, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+/// Saved camera bookmarks and the state of the detached camera.
+#[derive(Debug, Default)]
+pub struct CameraBookmarks {
+    bookmarks: HashMap<String, CameraBookmark>,
+
+    // where the camera was last drawn from
+    current: Option<CameraBookmark>,
+
+    // set while the camera is detached from the player
+    path: Option<CameraPath>,
+}
+
+impl CameraBookmarks {
+    pub fn new() -> CameraBookmarks {
+        CameraBookmarks::default()
+    }
+
+    /// Returns the bookmark with the given name.
+    pub fn get(&self, name: &str) -> Option<&CameraBookmark> {
+        self.bookmarks.get(name)
+    }
+
+    /// Saves the current camera under `name`, replacing any bookmark with the same name.
+    ///
+    /// Returns `false` if no camera has been drawn yet.
+    pub fn save<S>(&mut self, name: S) -> bool
+    where
+        S: Into<String>,
+    {
+        match self.current {
+            Some(camera) => {
+                self.bookmarks.insert(name.into(), camera);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Detaches the camera and moves it to the named bookmark over `duration`.
+    ///
+    /// Returns `false` if there is no such bookmark.
+    pub fn goto(&mut self, name: &str, duration: Duration) -> bool {
+        let to = match self.bookmarks.get(name) {
+            Some(b) => *b,
+            None => return false,
+        };
+
+        self.path = Some(CameraPath {
+            from: self.current.unwrap_or(to),
+            to,
+            elapsed: Duration::zero(),
+            duration,
+        });
+
+        true
+    }
+
+    /// Returns the camera as of the last call to `update`.
+    pub fn current(&self) -> Option<CameraBookmark> {
+        self.current
+    }
+
+    /// Returns `true` if the camera has been detached from the player.
+    pub fn detached(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Returns the camera to the player.
+    pub fn release(&mut self) {
+        self.path = None;
+    }
+
+    /// Returns the names and positions of all bookmarks, sorted by name.
+    pub fn list(&self) -> BTreeMap<&str, &CameraBookmark> {
+        self.bookmarks
+            .iter()
+            .map(|(name, bookmark)| (name.as_str(), bookmark))
+            .collect()
+    }
+
+    /// Advances the current camera move and returns the camera to draw from.
+    ///
+    /// `player` is the player's own view, used while the camera isn't detached.
+    pub fn update(&mut self, player: CameraBookmark, frame_time: Duration) -> CameraBookmark {
+        let camera = match self.path {
+            Some(ref mut path) => {
+                path.elapsed = (path.elapsed + frame_time).min(path.duration);
+                path.position()
+            }
+            None => player,
+        };
+
+        self.current = Some(camera);
+        camera
+    }
+}
+
+// Returns `true` if a demo is playing, and prints a message otherwise.
+fn playing_demo(conn_state: &Cell<ConnectionState>, output: &RefCell<ConsoleOutput>) -> bool {
+    if conn_state.get() == ConnectionState::DemoPlayback {
+        return true;
+    }
+
+    output.borrow_mut().print(l10n::tr("bookmark.demo_only"));
+    false
+}
+
+/// Implements the `cam_save` command.
+pub fn cmd_cam_save(
+    conn_state: Rc<Cell<ConnectionState>>,
+    output: Rc<RefCell<ConsoleOutput>>,
+    bookmarks: Rc<RefCell<CameraBookmarks>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        if !playing_demo(&conn_state, &output) {
+            return;
+        }
+
+        match args.len() {
+            1 => {
+                if !bookmarks.borrow_mut().save(args[0]) {
+                    output.borrow_mut().print(l10n::tr("bookmark.no_camera"));
+                }
+            }
+            _ => output
+                .borrow_mut()
+                .print(l10n::tr_args("console.usage", &["cam_save <name>"])),
+        }
+    })
+}
+
+/// Implements the `cam_goto` command.
+///
+/// With a duration in seconds, the camera glides to the bookmark; otherwise it cuts there.
+pub fn cmd_cam_goto(
+    conn_state: Rc<Cell<ConnectionState>>,
+    output: Rc<RefCell<ConsoleOutput>>,
+    bookmarks: Rc<RefCell<CameraBookmarks>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        if !playing_demo(&conn_state, &output) {
+            return;
+        }
+
+        let (name, seconds) = match args.len() {
+            1 => (args[0], 0.0),
+            2 => match args[1].parse::<f32>() {
+                Ok(s) if s >= 0.0 => (args[0], s),
+                _ => {
+                    output
+                        .borrow_mut()
+                        .print(l10n::tr_args("bookmark.invalid_duration", &[args[1]]));
+                    return;
+                }
+            },
+            _ => {
+                output.borrow_mut().print(l10n::tr_args(
+                    "console.usage",
+                    &["cam_goto <name> [seconds]"],
+                ));
+                return;
+            }
+        };
+
+        if !bookmarks
+            .borrow_mut()
+            .goto(name, engine::duration_from_f32(seconds))
+        {
+            output
+                .borrow_mut()
+                .print(l10n::tr_args("bookmark.not_found", &[name]));
+        }
+    })
+}
+
+/// Implements the `cam_release` command.
+pub fn cmd_cam_release(
+    conn_state: Rc<Cell<ConnectionState>>,
+    output: Rc<RefCell<ConsoleOutput>>,
+    bookmarks: Rc<RefCell<CameraBookmarks>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        if !playing_demo(&conn_state, &output) {
+            return;
+        }
+
+        match args.len() {
+            0 => bookmarks.borrow_mut().release(),
+            _ => output
+                .borrow_mut()
+                .print(l10n::tr_args("console.usage", &["cam_release"])),
+        }
+    })
+}
+
+/// Implements the `cam_list` command.
+pub fn cmd_cam_list(
+    conn_state: Rc<Cell<ConnectionState>>,
+    output: Rc<RefCell<ConsoleOutput>>,
+    bookmarks: Rc<RefCell<CameraBookmarks>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        if !playing_demo(&conn_state, &output) {
+            return;
+        }
+
+        match args.len() {
+            0 => {
+                let mut output = output.borrow_mut();
+                for (name, b) in bookmarks.borrow().list() {
+                    let origin = format!("{:.0} {:.0} {:.0}", b.origin.x, b.origin.y, b.origin.z);
+                    output.print(l10n::tr_args(
+                        "bookmark.entry",
+                        &[
+                            name,
+                            &origin,
+                            &format!("{:.0}", b.angles.pitch.0),
+                            &format!("{:.0}", b.angles.yaw.0),
+                        ],
+                    ));
+                }
+            }
+            _ => output
+                .borrow_mut()
+                .print(l10n::tr_args("console.usage", &["cam_list"])),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bookmark(x: f32, yaw: f32) -> CameraBookmark {
+        CameraBookmark {
+            origin: Vector3::new(x, 0.0, 0.0),
+            angles: Angles {
+                pitch: Deg(0.0),
+                roll: Deg(0.0),
+                yaw: Deg(yaw),
+            },
+        }
+    }
+
+    #[test]
+    fn test_lerp_short_way_around() {
+        let mid = bookmark(0.0, 350.0).lerp(&bookmark(100.0, 10.0), 0.5);
+        assert!((mid.origin.x - 50.0).abs() < 1e-4);
+        assert!((mid.angles.yaw.0 - 360.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_save_requires_camera() {
+        let mut bookmarks = CameraBookmarks::new();
+        assert!(!bookmarks.save("spot1"));
+
+        bookmarks.update(bookmark(0.0, 0.0), Duration::zero());
+        assert!(bookmarks.save("spot1"));
+        assert!(bookmarks.get("spot1").is_some());
+        assert!(!bookmarks.goto("spot2", Duration::zero()));
+    }
+
+    #[test]
+    fn test_goto_and_release() {
+        let mut bookmarks = CameraBookmarks::new();
+        bookmarks.update(bookmark(100.0, 90.0), Duration::zero());
+        bookmarks.save("spot1");
+
+        // the player moves away, then the camera glides back over one second
+        let player = bookmark(0.0, 0.0);
+        bookmarks.update(player, Duration::zero());
+        assert!(bookmarks.goto("spot1", Duration::seconds(1)));
+
+        let halfway = bookmarks.update(player, Duration::milliseconds(500));
+        assert!((halfway.origin.x - 50.0).abs() < 1e-4);
+        assert!((halfway.angles.yaw.0 - 45.0).abs() < 1e-4);
+
+        let end = bookmarks.update(player, Duration::seconds(5));
+        assert!((end.origin.x - 100.0).abs() < 1e-4);
+
+        // the camera stays put until released
+        let held = bookmarks.update(player, Duration::milliseconds(100));
+        assert!((held.origin.x - 100.0).abs() < 1e-4);
+
+        bookmarks.release();
+        let released = bookmarks.update(player, Duration::milliseconds(100));
+        assert!(released.origin.x.abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_goto_cuts_without_duration() {
+        let mut bookmarks = CameraBookmarks::new();
+        bookmarks.update(bookmark(100.0, 0.0), Duration::zero());
+        bookmarks.save("spot1");
+        bookmarks.update(bookmark(0.0, 0.0), Duration::zero());
+
+        bookmarks.goto("spot1", Duration::zero());
+        let camera = bookmarks.update(bookmark(0.0, 0.0), Duration::zero());
+        assert!((camera.origin.x - 100.0).abs() < 1e-4);
+    }
+}
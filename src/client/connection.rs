@@ -0,0 +1,190 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The client's connection state machine.
+
+use std::fmt;
+
+use crate::{
+    client::{ClientError, ClientErrorKind},
+    common::net::SignOnStage,
+};
+
+/// The state of the client's connection to a server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected to a server. The console is the only interface.
+    Disconnected,
+
+    /// The server has accepted the connection but hasn't begun the sign-on
+    /// process yet.
+    Connecting,
+
+    /// Signing on to the server. Level resources are loaded during this state.
+    SignOn(SignOnStage),
+
+    /// Fully signed on and in game.
+    Active,
+
+    /// Playing back a recorded demo.
+    DemoPlayback,
+}
+
+impl ConnectionState {
+    /// Returns the state corresponding to the given sign-on stage.
+    ///
+    /// `SignOnStage::Done` maps to `ConnectionState::Active`.
+    pub fn from_signon(stage: SignOnStage) -> ConnectionState {
+        match stage {
+            SignOnStage::Done => ConnectionState::Active,
+            s => ConnectionState::SignOn(s),
+        }
+    }
+
+    /// Returns `true` if it is legal to move from this state to `next`.
+    ///
+    /// Remaining in the same state is always legal.
+    pub fn can_transition_to(self, next: ConnectionState) -> bool {
+        use ConnectionState::*;
+
+        if self == next {
+            return true;
+        }
+
+        match (self, next) {
+            (Disconnected, Connecting) | (Disconnected, DemoPlayback) => true,
+
+            // the server begins the sign-on process as soon as it accepts us
+            (Connecting, SignOn(_)) => true,
+
+            // sign-on stages only advance, unless the server restarts sign-on
+            (SignOn(_), SignOn(SignOnStage::Not)) => true,
+            (SignOn(cur), SignOn(next)) => next > cur,
+            (SignOn(SignOnStage::Begin), Active) => true,
+
+            // the server sends `reconnect` on level changes
+            (Active, SignOn(SignOnStage::Not)) => true,
+
+            // any connection can be dropped
+            (Connecting, Disconnected)
+            | (SignOn(_), Disconnected)
+            | (Active, Disconnected)
+            | (DemoPlayback, Disconnected) => true,
+
+            _ => false,
+        }
+    }
+
+    /// Moves from this state to `next`, returning the new state.
+    ///
+    /// Returns an error if the transition is not legal.
+    pub fn transition(self, next: ConnectionState) -> Result<ConnectionState, ClientError> {
+        if !self.can_transition_to(next) {
+            Err(ClientErrorKind::InvalidStateTransition {
+                from: self,
+                to: next,
+            })?;
+        }
+
+        Ok(next)
+    }
+
+    /// Returns `true` if the client is connected to a server.
+    pub fn is_connected(self) -> bool {
+        match self {
+            ConnectionState::Connecting | ConnectionState::SignOn(_) | ConnectionState::Active => {
+                true
+            }
+            ConnectionState::Disconnected | ConnectionState::DemoPlayback => false,
+        }
+    }
+
+    /// Returns `true` if the world should be simulated and rendered.
+    pub fn is_in_game(self) -> bool {
+        match self {
+            ConnectionState::Active | ConnectionState::DemoPlayback => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ConnectionState::Disconnected => write!(f, "disconnected"),
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::SignOn(stage) => write!(f, "signing on ({:?})", stage),
+            ConnectionState::Active => write!(f, "active"),
+            ConnectionState::DemoPlayback => write!(f, "playing demo"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signon_sequence() {
+        let mut state = ConnectionState::Disconnected;
+        for next in &[
+            ConnectionState::Connecting,
+            ConnectionState::SignOn(SignOnStage::Prespawn),
+            ConnectionState::SignOn(SignOnStage::ClientInfo),
+            ConnectionState::SignOn(SignOnStage::Begin),
+            ConnectionState::from_signon(SignOnStage::Done),
+            ConnectionState::SignOn(SignOnStage::Not),
+            ConnectionState::SignOn(SignOnStage::Prespawn),
+            ConnectionState::Disconnected,
+        ] {
+            state = state.transition(*next).unwrap();
+        }
+
+        assert_eq!(state, ConnectionState::Disconnected);
+    }
+
+    #[test]
+    fn test_demo_playback() {
+        let state = ConnectionState::Disconnected
+            .transition(ConnectionState::DemoPlayback)
+            .unwrap();
+        assert!(state.is_in_game());
+        assert!(!state.is_connected());
+        assert!(state.transition(ConnectionState::Active).is_err());
+        assert_eq!(
+            state.transition(ConnectionState::Disconnected).unwrap(),
+            ConnectionState::Disconnected
+        );
+    }
+
+    #[test]
+    fn test_illegal_transitions() {
+        assert!(!ConnectionState::Disconnected.can_transition_to(ConnectionState::Active));
+        assert!(!ConnectionState::Connecting.can_transition_to(ConnectionState::Active));
+        assert!(!ConnectionState::DemoPlayback.can_transition_to(ConnectionState::Connecting));
+        assert!(!ConnectionState::Disconnected
+            .can_transition_to(ConnectionState::SignOn(SignOnStage::Prespawn)));
+        assert!(!ConnectionState::SignOn(SignOnStage::Begin)
+            .can_transition_to(ConnectionState::SignOn(SignOnStage::Prespawn)));
+        assert!(ConnectionState::Active
+            .transition(ConnectionState::Connecting)
+            .is_err());
+    }
+}
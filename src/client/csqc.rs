@@ -0,0 +1,409 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Experimental support for client-side QuakeC (CSQC).
+//!
+//! Mods may ship a `csprogs.dat` alongside their server progs to take over parts of the client,
+//! such as HUD drawing. The client can't execute QuakeC yet, so for now `Csqc` only loads the
+//! progs and records which of the `EXT_CSQC` entry points it defines. Events are also dispatched
+//! to registered callbacks, which lets native code hook the same points in the meantime.
+
+use std::{
+    cell::RefCell,
+    io::{Cursor, Read, Seek, SeekFrom},
+};
+
+use crate::{
+    client::entity::ClientEntity,
+    common::{bsp::BspLeafContents, vfs::Vfs},
+    server::progs::ProgsError,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+const CSPROGS_NAME: &str = "csprogs.dat";
+const VERSION: i32 = 6;
+
+// lump indices, as in `progs.dat`
+const FUNCTIONS_LUMP: usize = 3;
+const STRINGS_LUMP: usize = 4;
+const LUMP_COUNT: usize = 6;
+
+// the on-disk size of a function declaration
+const FUNCTION_SIZE: usize = 36;
+
+// the offset of the name field within a function declaration
+const FUNCTION_NAME_OFFSET: usize = 16;
+
+/// A function the client calls in `csprogs.dat`, if it is defined.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EntryPoint {
+    Init,
+    Shutdown,
+    UpdateView,
+    DrawHud,
+    EntUpdate,
+    EntRemove,
+    ConsoleCommand,
+}
+
+impl EntryPoint {
+    pub const ALL: [EntryPoint; 7] = [
+        EntryPoint::Init,
+        EntryPoint::Shutdown,
+        EntryPoint::UpdateView,
+        EntryPoint::DrawHud,
+        EntryPoint::EntUpdate,
+        EntryPoint::EntRemove,
+        EntryPoint::ConsoleCommand,
+    ];
+
+    /// Returns the name of the QuakeC function for this entry point.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EntryPoint::Init => "CSQC_Init",
+            EntryPoint::Shutdown => "CSQC_Shutdown",
+            EntryPoint::UpdateView => "CSQC_UpdateView",
+            EntryPoint::DrawHud => "CSQC_DrawHud",
+            EntryPoint::EntUpdate => "CSQC_Ent_Update",
+            EntryPoint::EntRemove => "CSQC_Ent_Remove",
+            EntryPoint::ConsoleCommand => "CSQC_ConsoleCommand",
+        }
+    }
+}
+
+/// The entry points defined by a `csprogs.dat`.
+#[derive(Debug)]
+pub struct CsqcProgs {
+    entry_points: Vec<EntryPoint>,
+}
+
+impl CsqcProgs {
+    /// Reads the function table of a `csprogs.dat`.
+    ///
+    /// Unlike the server progs, the CRC isn't checked, since it depends on the mod's `csdefs.qc`.
+    pub fn load(data: &[u8]) -> Result<CsqcProgs, ProgsError> {
+        let mut src = Cursor::new(data);
+
+        let version = src.read_i32::<LittleEndian>()?;
+        if version != VERSION {
+            return Err(ProgsError::with_msg(format!(
+                "Bad csprogs version (got {}, should be {})",
+                version, VERSION
+            )));
+        }
+
+        // skip CRC
+        let _ = src.read_i32::<LittleEndian>()?;
+
+        let mut lumps = [(0, 0); LUMP_COUNT];
+        for lump in lumps.iter_mut() {
+            let offset = src.read_i32::<LittleEndian>()?;
+            let count = src.read_i32::<LittleEndian>()?;
+            if offset < 0 || count < 0 {
+                return Err(ProgsError::with_msg(format!(
+                    "Bad csprogs lump (offset {}, count {})",
+                    offset, count
+                )));
+            }
+
+            *lump = (offset as usize, count as usize);
+        }
+
+        // make sure the lumps we read are inside the file before allocating anything
+        let (string_ofs, string_count) = lumps[STRINGS_LUMP];
+        let (function_ofs, function_count) = lumps[FUNCTIONS_LUMP];
+        let string_end = string_ofs.checked_add(string_count);
+        let function_end = function_count
+            .checked_mul(FUNCTION_SIZE)
+            .and_then(|len| function_ofs.checked_add(len));
+        for end in [string_end, function_end].iter() {
+            if end.map_or(true, |end| end > data.len()) {
+                return Err(ProgsError::with_msg(
+                    "csprogs lump extends past end of file",
+                ));
+            }
+        }
+
+        src.seek(SeekFrom::Start(string_ofs as u64))?;
+        let mut strings = Vec::with_capacity(string_count);
+        (&mut src)
+            .take(string_count as u64)
+            .read_to_end(&mut strings)?;
+
+        let mut names = Vec::with_capacity(function_count);
+        for i in 0..function_count {
+            let name_ofs = function_ofs + i * FUNCTION_SIZE + FUNCTION_NAME_OFFSET;
+            src.seek(SeekFrom::Start(name_ofs as u64))?;
+
+            let name_id = src.read_i32::<LittleEndian>()?;
+            if name_id < 0 || name_id as usize >= strings.len() {
+                return Err(ProgsError::with_msg(format!(
+                    "no string with ID {}",
+                    name_id
+                )));
+            }
+
+            let name = &strings[name_id as usize..];
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            names.push(String::from_utf8_lossy(&name[..len]).into_owned());
+        }
+
+        let entry_points = EntryPoint::ALL
+            .iter()
+            .cloned()
+            .filter(|ep| names.iter().any(|n| n == ep.name()))
+            .collect();
+
+        Ok(CsqcProgs { entry_points })
+    }
+
+    /// Returns true if the progs define the given entry point.
+    pub fn has_entry_point(&self, entry_point: EntryPoint) -> bool {
+        self.entry_points.contains(&entry_point)
+    }
+
+    /// Returns the entry points defined by the progs.
+    pub fn entry_points(&self) -> &[EntryPoint] {
+        &self.entry_points
+    }
+}
+
+/// An event sent to client-side code.
+#[derive(Debug)]
+pub enum CsqcEvent<'a> {
+    /// An entity was updated by the server.
+    EntityUpdate { id: usize, entity: &'a ClientEntity },
+
+    /// The HUD is about to be drawn.
+    ///
+    /// `view_contents` lets mods draw effects like an air timer while the view is
+    /// underwater.
+    DrawHud {
+        width: u32,
+        height: u32,
+        view_contents: BspLeafContents,
+    },
+}
+
+impl<'a> CsqcEvent<'a> {
+    /// Returns the entry point that handles this event.
+    pub fn entry_point(&self) -> EntryPoint {
+        match *self {
+            CsqcEvent::EntityUpdate { .. } => EntryPoint::EntUpdate,
+            CsqcEvent::DrawHud { .. } => EntryPoint::DrawHud,
+        }
+    }
+}
+
+/// Dispatches client events to `csprogs.dat` and registered callbacks.
+pub struct Csqc {
+    progs: Option<CsqcProgs>,
+    callbacks: RefCell<Vec<Box<dyn Fn(&CsqcEvent)>>>,
+}
+
+impl Csqc {
+    /// Creates a new `Csqc` with no progs and no callbacks.
+    pub fn new() -> Csqc {
+        Csqc {
+            progs: None,
+            callbacks: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Loads `csprogs.dat` from the virtual filesystem, if present.
+    ///
+    /// An invalid `csprogs.dat` is logged and otherwise ignored.
+    pub fn load(vfs: &Vfs) -> Csqc {
+        let mut csqc = Csqc::new();
+
+        let mut file = match vfs.open(CSPROGS_NAME) {
+            Ok(f) => f,
+            Err(_) => return csqc,
+        };
+
+        let mut data = Vec::new();
+        if let Err(e) = file.read_to_end(&mut data) {
+            warn!("Couldn't read {}: {}", CSPROGS_NAME, e);
+            return csqc;
+        }
+
+        match CsqcProgs::load(&data) {
+            Ok(progs) => {
+                info!(
+                    "Loaded {} with entry points {:?}",
+                    CSPROGS_NAME,
+                    progs.entry_points()
+                );
+                csqc.progs = Some(progs);
+            }
+
+            Err(e) => warn!("Couldn't load {}: {}", CSPROGS_NAME, e),
+        }
+
+        csqc
+    }
+
+    /// Returns the loaded progs, if any.
+    pub fn progs(&self) -> Option<&CsqcProgs> {
+        self.progs.as_ref()
+    }
+
+    /// Registers a callback to be invoked for every event.
+    pub fn add_callback(&self, callback: Box<dyn Fn(&CsqcEvent)>) {
+        self.callbacks.borrow_mut().push(callback);
+    }
+
+    /// Notifies client-side code that an entity was updated.
+    pub fn entity_update(&self, id: usize, entity: &ClientEntity) {
+        self.dispatch(CsqcEvent::EntityUpdate { id, entity });
+    }
+
+    /// Notifies client-side code that the HUD is about to be drawn.
+    pub fn draw_hud(&self, width: u32, height: u32, view_contents: BspLeafContents) {
+        self.dispatch(CsqcEvent::DrawHud {
+            width,
+            height,
+            view_contents,
+        });
+    }
+
+    fn dispatch(&self, event: CsqcEvent) {
+        if let Some(ref progs) = self.progs {
+            let entry_point = event.entry_point();
+            if progs.has_entry_point(entry_point) {
+                // TODO: execute once the QuakeC VM is decoupled from the server
+                trace!("Skipping {}", entry_point.name());
+            }
+        }
+
+        for callback in self.callbacks.borrow().iter() {
+            callback(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use byteorder::WriteBytesExt;
+
+    // builds a csprogs.dat containing only the named functions
+    fn build_progs(version: i32, function_names: &[&str]) -> Vec<u8> {
+        let header_size = 8 + LUMP_COUNT * 8;
+
+        let mut strings = vec![0u8];
+        let mut name_ids = Vec::new();
+        for name in function_names {
+            name_ids.push(strings.len() as i32);
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+
+        let function_ofs = header_size;
+        let string_ofs = function_ofs + function_names.len() * FUNCTION_SIZE;
+
+        let mut lumps = [(0, 0); LUMP_COUNT];
+        lumps[FUNCTIONS_LUMP] = (function_ofs as i32, function_names.len() as i32);
+        lumps[STRINGS_LUMP] = (string_ofs as i32, strings.len() as i32);
+
+        let mut data = Vec::new();
+        data.write_i32::<LittleEndian>(version).unwrap();
+        data.write_i32::<LittleEndian>(0).unwrap();
+        for (ofs, count) in lumps.iter() {
+            data.write_i32::<LittleEndian>(*ofs).unwrap();
+            data.write_i32::<LittleEndian>(*count).unwrap();
+        }
+
+        for name_id in name_ids {
+            let mut function = [0u8; FUNCTION_SIZE];
+            (&mut function[FUNCTION_NAME_OFFSET..])
+                .write_i32::<LittleEndian>(name_id)
+                .unwrap();
+            data.extend_from_slice(&function);
+        }
+
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    #[test]
+    fn test_load_entry_points() {
+        let data = build_progs(VERSION, &["CSQC_Init", "helper", "CSQC_DrawHud"]);
+        let progs = CsqcProgs::load(&data).unwrap();
+        assert_eq!(
+            progs.entry_points(),
+            &[EntryPoint::Init, EntryPoint::DrawHud]
+        );
+        assert!(!progs.has_entry_point(EntryPoint::EntUpdate));
+    }
+
+    #[test]
+    fn test_load_bad_version() {
+        let data = build_progs(7, &["CSQC_Init"]);
+        assert!(CsqcProgs::load(&data).is_err());
+    }
+
+    #[test]
+    fn test_load_bad_lumps() {
+        let data = build_progs(VERSION, &["CSQC_Init"]);
+        let lump_ofs = |lump: usize| 8 + lump * 8;
+
+        // a function count far larger than the file
+        let mut huge = data.clone();
+        let count_ofs = lump_ofs(FUNCTIONS_LUMP) + 4;
+        (&mut huge[count_ofs..])
+            .write_i32::<LittleEndian>(i32::max_value())
+            .unwrap();
+        assert!(CsqcProgs::load(&huge).is_err());
+
+        // a negative string lump offset
+        let mut negative = data.clone();
+        (&mut negative[lump_ofs(STRINGS_LUMP)..])
+            .write_i32::<LittleEndian>(-1)
+            .unwrap();
+        assert!(CsqcProgs::load(&negative).is_err());
+
+        // a string lump running past the end of the file
+        let mut truncated = data.clone();
+        truncated.pop();
+        assert!(CsqcProgs::load(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_callbacks() {
+        use std::{cell::Cell, rc::Rc};
+
+        let csqc = Csqc::new();
+        let hud_draws = Rc::new(Cell::new(0));
+        let hud_draws_cb = hud_draws.clone();
+        csqc.add_callback(Box::new(move |event| {
+            if let CsqcEvent::DrawHud { view_contents, .. } = event {
+                assert_eq!(*view_contents, BspLeafContents::Water);
+                hud_draws_cb.set(hud_draws_cb.get() + 1);
+            }
+        }));
+
+        csqc.draw_hud(640, 480, BspLeafContents::Water);
+        csqc.entity_update(1, &ClientEntity::uninitialized());
+        assert_eq!(hud_draws.get(), 1);
+    }
+}
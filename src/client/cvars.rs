@@ -18,32 +18,94 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use crate::common::console::{CvarRegistry, ConsoleError};
+use crate::common::console::{ConsoleError, CvarRegistry};
 
 pub fn register_cvars(cvars: &CvarRegistry) -> Result<(), ConsoleError> {
+    cvars.register_archive("bgmvolume", "1")?;
+    cvars.register_archive("cl_allowstuffedcmds", "0")?;
     cvars.register("cl_anglespeedkey", "1.5")?;
     cvars.register_archive("cl_backspeed", "200")?;
     cvars.register("cl_bob", "0.02")?;
     cvars.register("cl_bobcycle", "0.6")?;
     cvars.register("cl_bobup", "0.5")?;
+    cvars.register("cl_cmdlog", "0")?;
     cvars.register_archive("_cl_color", "0")?;
     cvars.register("cl_crossx", "0")?;
     cvars.register("cl_crossy", "0")?;
     cvars.register_archive("cl_forwardspeed", "400")?;
+    cvars.register("cl_inputtiming", "0")?;
+    cvars.register_archive("cl_maxparticles", "16384")?;
     cvars.register("cl_movespeedkey", "2.0")?;
+    cvars.register_archive("cl_netfps", "72")?;
     cvars.register_archive("_cl_name", "player")?;
     cvars.register("cl_nolerp", "0")?;
+    cvars.register_archive("cl_onconnect", "")?;
+    cvars.register_archive("cl_ondisconnect", "")?;
+    cvars.register_archive("cl_onmapstart", "")?;
     cvars.register("cl_pitchspeed", "150")?;
+    cvars.register_archive("cl_predict_projectiles", "0")?;
     cvars.register("cl_rollangle", "2.0")?;
     cvars.register("cl_rollspeed", "200")?;
     cvars.register("cl_shownet", "0")?;
     cvars.register("cl_sidespeed", "350")?;
+    cvars.register_archive("cl_skin", "")?;
     cvars.register("cl_upspeed", "200")?;
     cvars.register("cl_yawspeed", "140")?;
+    cvars.register_archive("cl_freelook", "0")?;
+    cvars.register("condebug", "0")?;
+    cvars.register_archive("crosshair", "1")?;
+    cvars.register("dev_watchassets", "0")?;
+    cvars.register("dev_watchcfg", "0")?;
+    cvars.register("dev_watchshaders", "0")?;
     cvars.register("fov", "90")?;
+    cvars.register_archive("fs_report", "0")?;
+    cvars.register_archive("hud_damageindicator", "0")?;
+    cvars.register_archive("hud_hitconfirm", "0")?;
+    cvars.register_archive("hud_lowhealth", "0")?;
+    cvars.register_archive("hud_lowhealth_sound", "")?;
+    cvars.register_archive("hud_lowhealth_threshold", "25")?;
+    cvars.register_archive("joy_axis_leftx", "side")?;
+    cvars.register_archive("joy_axis_lefty", "forward")?;
+    cvars.register_archive("joy_axis_rightx", "yaw")?;
+    cvars.register_archive("joy_axis_righty", "pitch")?;
+    cvars.register_archive("joy_curve_leftx", "")?;
+    cvars.register_archive("joy_curve_lefty", "")?;
+    cvars.register_archive("joy_curve_rightx", "")?;
+    cvars.register_archive("joy_curve_righty", "")?;
+    cvars.register_archive("joy_deadzone", "0.2")?;
+    cvars.register_archive("joy_deadzone_leftx", "0")?;
+    cvars.register_archive("joy_deadzone_lefty", "0")?;
+    cvars.register_archive("joy_deadzone_rightx", "0")?;
+    cvars.register_archive("joy_deadzone_righty", "0")?;
+    cvars.register_archive("joy_enable", "1")?;
+    cvars.register_archive("joy_exponent", "2")?;
+    cvars.register_archive("joy_scale_leftx", "1")?;
+    cvars.register_archive("joy_scale_lefty", "1")?;
+    cvars.register_archive("joy_scale_rightx", "1")?;
+    cvars.register_archive("joy_scale_righty", "1")?;
+    cvars.register_archive("joy_sensitivity_pitch", "150")?;
+    cvars.register_archive("joy_sensitivity_yaw", "300")?;
+    cvars.register_archive("lookspring", "0")?;
+    cvars.register_archive("lookstrafe", "0")?;
+    cvars.register_archive("m_filter", "0")?;
+    cvars.register_archive("m_forward", "1")?;
+    cvars.register_archive("m_invert", "0")?;
     cvars.register_archive("m_pitch", "0.022")?;
+    cvars.register_archive("m_side", "0.8")?;
     cvars.register_archive("m_yaw", "0.022")?;
+    cvars.register("rcon_address", "")?;
+    cvars.register("rcon_password", "")?;
+    cvars.register("scr_centertime", "2")?;
+    cvars.register("scr_conspeed", "300")?;
+    cvars.register_archive("scr_loadingscale", "2")?;
+    cvars.register_archive("scr_speed", "0")?;
     cvars.register_archive("sensitivity", "3")?;
+    cvars.register_archive("snd_distance_model", "0")?;
+    cvars.register_archive("snd_occlusion", "0")?;
+    cvars.register_archive("snd_rolloff", "1")?;
+    cvars.register("v_centermove", "0.15")?;
+    cvars.register("v_centerspeed", "500")?;
+    cvars.register_archive("v_colorblind", "0")?;
     cvars.register("v_idlescale", "0")?;
     cvars.register("v_ipitch_cycle", "1")?;
     cvars.register("v_ipitch_level", "0.3")?;
@@ -60,5 +122,18 @@ pub fn register_cvars(cvars: &CvarRegistry) -> Result<(), ConsoleError> {
     // the duplicate cvar error
     let _ = cvars.register("sv_gravity", "800");
 
+    // the server mustn't be able to change its own policy, set the event hooks, which run with
+    // config privileges, or redirect rcon to capture the password
+    for name in &[
+        "cl_allowstuffedcmds",
+        "cl_onconnect",
+        "cl_ondisconnect",
+        "cl_onmapstart",
+        "rcon_address",
+        "rcon_password",
+    ] {
+        cvars.set_user_only(name)?;
+    }
+
     Ok(())
 }
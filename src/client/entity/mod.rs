@@ -35,6 +35,10 @@ pub const MAX_BEAMS: usize = 24;
 pub const MAX_TEMP_ENTITIES: usize = 64;
 pub const MAX_STATIC_ENTITIES: usize = 128;
 
+/// Models animate at 10 frames per second, so frame changes are blended over
+/// this many seconds.
+pub const FRAME_LERP_TIME: f32 = 0.1;
+
 #[derive(Debug)]
 pub struct ClientEntity {
     pub force_link: bool,
@@ -47,11 +51,15 @@ pub struct ClientEntity {
     pub model_id: usize,
     model_changed: bool,
     pub frame_id: usize,
+    // the frame shown before frame_id, and when the change began
+    pub prev_frame_id: usize,
+    pub frame_change_time: Duration,
     pub skin_id: usize,
     colormap: Option<u8>,
     pub sync_base: Duration,
     pub effects: EntityEffects,
     pub light_id: Option<usize>,
+    pub player_skin: Option<PlayerSkin>,
     // vis_frame: usize,
 }
 
@@ -71,11 +79,14 @@ impl ClientEntity {
             model_id: baseline.model_id,
             model_changed: false,
             frame_id: baseline.frame_id,
+            prev_frame_id: baseline.frame_id,
+            frame_change_time: Duration::zero(),
             skin_id: baseline.skin_id,
             colormap: None,
             sync_base: Duration::zero(),
             effects: baseline.effects,
             light_id: None,
+            player_skin: None,
         }
     }
 
@@ -94,11 +105,14 @@ impl ClientEntity {
             model_id: 0,
             model_changed: false,
             frame_id: 0,
+            prev_frame_id: 0,
+            frame_change_time: Duration::zero(),
             skin_id: 0,
             colormap: None,
             sync_base: Duration::zero(),
             effects: EntityEffects::empty(),
             light_id: None,
+            player_skin: None,
         }
     }
 
@@ -130,12 +144,19 @@ impl ClientEntity {
             self.model_id = new_state.model_id;
         }
 
+        // blend to the new frame over the same interval the origin is lerped
+        if self.frame_id != new_state.frame_id {
+            self.prev_frame_id = self.frame_id;
+            self.frame_change_time = msg_times[1];
+        }
+
         self.frame_id = new_state.frame_id;
         self.skin_id = new_state.skin_id;
         self.effects = new_state.effects;
         self.colormap = update.colormap;
 
         if self.force_link {
+            self.prev_frame_id = self.frame_id;
             self.msg_origins[1] = self.msg_origins[0];
             self.origin = self.msg_origins[0];
             self.msg_angles[1] = self.msg_angles[0];
@@ -183,11 +204,41 @@ impl ClientEntity {
         self.frame_id
     }
 
+    /// Returns how far this entity's model has blended from its previous frame
+    /// to its current one at `time`, from 0 to 1.
+    pub fn frame_blend(&self, time: Duration) -> f32 {
+        if self.prev_frame_id == self.frame_id {
+            return 1.0;
+        }
+
+        let elapsed = engine::duration_to_f32(time - self.frame_change_time);
+        (elapsed / FRAME_LERP_TIME).max(0.0).min(1.0)
+    }
+
     pub fn get_skin_id(&self) -> usize {
         self.skin_id
     }
 }
 
+/// Describes how to draw a player's skin.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PlayerSkin {
+    /// The player's index in the scoreboard.
+    pub player_id: usize,
+
+    /// The name of a custom skin in `skins/`, or `None` to use the model's own skin.
+    pub name: Option<String>,
+
+    /// The model skin to recolor if there's no custom skin.
+    pub skin_id: usize,
+
+    /// The palette row used for the shirt.
+    pub top: u8,
+
+    /// The palette row used for the pants.
+    pub bottom: u8,
+}
+
 /// A descriptor used to spawn dynamic lights.
 #[derive(Clone, Debug)]
 pub struct LightDesc {
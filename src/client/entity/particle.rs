@@ -18,7 +18,7 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use std::ops::RangeInclusive;
+use std::{mem::size_of, ops::RangeInclusive};
 
 use crate::{
     client::ClientEntity,
@@ -54,12 +54,21 @@ lazy_static! {
     static ref EXPLOSION_VELOCITY_DISTRIBUTION: Uniform<f32> = Uniform::new(-256.0, 256.0);
 }
 
-// TODO: make max configurable
 pub const MIN_PARTICLES: usize = 512;
 
 // should be possible to get the whole particle list in cache at once
 pub const MAX_PARTICLES: usize = 16384;
 
+/// Returns the number of particles to allocate for the given value of
+/// `cl_maxparticles`, clamped to [`MIN_PARTICLES`, `MAX_PARTICLES`].
+pub fn particle_capacity(max_particles: f32) -> usize {
+    if max_particles.is_nan() || max_particles < MIN_PARTICLES as f32 {
+        MIN_PARTICLES
+    } else {
+        (max_particles as usize).min(MAX_PARTICLES)
+    }
+}
+
 /// An animated color ramp.
 ///
 /// Colors are specified using 8-bit indexed values, which should be translated
@@ -283,6 +292,11 @@ impl Particles {
         self.slab.iter()
     }
 
+    /// Returns the number of bytes reserved for particle storage.
+    pub fn memory_usage(&self) -> usize {
+        self.slab.capacity() * size_of::<Particle>()
+    }
+
     /// Update all live particles, deleting any that are expired.
     ///
     /// Particles are updated with [Particle::update]. That
@@ -631,7 +645,7 @@ impl Particles {
             let scatter = self.random_vector3(&SCATTER_DISTRIBUTION);
 
             let origin = start
-                + direction * interval
+                + direction * interval * step as f32
                 + match kind {
                     // vore scatter is [-16, 15] in original
                     // this gives range of ~[-16, 16]
@@ -715,4 +729,53 @@ mod tests {
             .zip(expected.iter())
             .for_each(|(p1, p2)| assert!(particles_eq(p1, p2)));
     }
+
+    #[test]
+    fn test_particle_capacity() {
+        assert_eq!(particle_capacity(2048.0), 2048);
+        assert_eq!(particle_capacity(0.0), MIN_PARTICLES);
+        assert_eq!(particle_capacity(-1.0), MIN_PARTICLES);
+        assert_eq!(particle_capacity(std::f32::NAN), MIN_PARTICLES);
+        assert_eq!(particle_capacity(1e9), MAX_PARTICLES);
+    }
+
+    #[test]
+    fn test_particle_list_full() {
+        let mut list = Particles::with_capacity(2);
+        let particle = Particle {
+            kind: ParticleKind::Static,
+            origin: Vector3::zero(),
+            velocity: Vector3::zero(),
+            color: 0,
+            spawned: Duration::zero(),
+            expire: Duration::seconds(1),
+        };
+
+        // like the original engine, new particles are dropped once the pool is full
+        assert!(list.insert(particle));
+        assert!(list.insert(particle));
+        assert!(!list.insert(particle));
+        assert_eq!(list.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_trail_spans_path() {
+        let mut list = Particles::with_capacity(1000);
+        list.create_trail(
+            Duration::zero(),
+            Vector3::zero(),
+            Vector3::new(100.0, 0.0, 0.0),
+            TrailKind::Rocket,
+            false,
+        );
+
+        let xs: Vec<f32> = list.iter().map(|p| p.origin().x).collect();
+        assert_eq!(xs.len(), 100);
+
+        // particles are scattered by at most 3 units around their step
+        let min = xs.iter().cloned().fold(std::f32::INFINITY, f32::min);
+        let max = xs.iter().cloned().fold(std::f32::NEG_INFINITY, f32::max);
+        assert!(min < 5.0);
+        assert!(max > 95.0);
+    }
 }
@@ -1,3 +1,5 @@
+use crate::client::connection::ConnectionState;
+
 use failure::{Backtrace, Context, Fail};
 use std::{
     convert::From,
@@ -57,6 +59,11 @@ pub enum ClientErrorKind {
     InvalidConnectResponse,
     #[fail(display = "Invalid server address")]
     InvalidServerAddress,
+    #[fail(display = "Invalid connection state transition ({} -> {})", from, to)]
+    InvalidStateTransition {
+        from: ConnectionState,
+        to: ConnectionState,
+    },
     #[fail(display = "No response from server")]
     NoResponse,
     #[fail(display = "No client with ID {}", id)]
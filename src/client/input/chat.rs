@@ -0,0 +1,226 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::common::console::{CmdRegistry, Console};
+
+use failure::Error;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode as Key, WindowEvent};
+
+// maximum length of a chat message, matching the original engine's buffer
+const MAX_CHAT_LEN: usize = 31;
+
+/// The recipients of a chat message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatTarget {
+    /// Sent with `say` (`messagemode`).
+    All,
+
+    /// Sent with `say_team` (`messagemode2`).
+    Team,
+}
+
+impl ChatTarget {
+    /// Returns the command used to send a message to this target.
+    pub fn command(&self) -> &'static str {
+        match self {
+            ChatTarget::All => "say",
+            ChatTarget::Team => "say_team",
+        }
+    }
+}
+
+/// A chat message being typed.
+#[derive(Clone, Debug)]
+pub struct ChatLine {
+    target: ChatTarget,
+    text: String,
+}
+
+impl ChatLine {
+    pub fn new(target: ChatTarget) -> ChatLine {
+        ChatLine {
+            target,
+            text: String::new(),
+        }
+    }
+
+    pub fn target(&self) -> ChatTarget {
+        self.target
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the line as it should be drawn on screen, e.g. `say: hello`.
+    pub fn prompt(&self) -> String {
+        format!("{}: {}", self.target.command(), self.text)
+    }
+
+    fn insert(&mut self, c: char) {
+        if !c.is_control() && self.text.chars().count() < MAX_CHAT_LEN {
+            self.text.push(c);
+        }
+    }
+
+    fn backspace(&mut self) {
+        self.text.pop();
+    }
+
+    // returns the console command which sends this message, or None if it's empty
+    fn to_command(&self) -> Option<String> {
+        if self.text.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "{} \"{}\"\n",
+            self.target.command(),
+            self.text.replace('"', "'")
+        ))
+    }
+}
+
+/// Captures a line of chat text while in `messagemode` or `messagemode2`.
+pub struct ChatInput {
+    console: Rc<RefCell<Console>>,
+
+    // the message being typed, if any
+    line: Rc<RefCell<Option<ChatLine>>>,
+}
+
+impl ChatInput {
+    pub fn new(console: Rc<RefCell<Console>>) -> ChatInput {
+        ChatInput {
+            console,
+            line: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Returns true if a chat message is being typed.
+    pub fn active(&self) -> bool {
+        self.line.borrow().is_some()
+    }
+
+    /// Returns the chat message being typed, if any.
+    pub fn line(&self) -> Option<ChatLine> {
+        self.line.borrow().clone()
+    }
+
+    /// Stops typing without sending the message.
+    pub fn cancel(&self) {
+        self.line.replace(None);
+    }
+
+    pub fn handle_event<T>(&self, event: Event<T>) -> Result<(), Error> {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::ReceivedCharacter(c) => {
+                    if let Some(ref mut line) = *self.line.borrow_mut() {
+                        line.insert(c);
+                    }
+                }
+
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => match key {
+                    Key::Return | Key::NumpadEnter => {
+                        let line = self.line.replace(None);
+                        if let Some(cmd) = line.as_ref().and_then(ChatLine::to_command) {
+                            self.console.borrow().stuff_text(cmd);
+                        }
+                    }
+
+                    Key::Escape => self.cancel(),
+
+                    Key::Back => {
+                        if let Some(ref mut line) = *self.line.borrow_mut() {
+                            line.backspace();
+                        }
+                    }
+
+                    _ => (),
+                },
+
+                _ => (),
+            },
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    pub fn register_cmds(&self, cmds: &mut CmdRegistry) {
+        let line = self.line.clone();
+        cmds.insert_or_replace(
+            "messagemode",
+            Box::new(move |_| {
+                line.replace(Some(ChatLine::new(ChatTarget::All)));
+            }),
+        )
+        .unwrap();
+        let line = self.line.clone();
+        cmds.insert_or_replace(
+            "messagemode2",
+            Box::new(move |_| {
+                line.replace(Some(ChatLine::new(ChatTarget::Team)));
+            }),
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chat_line_command() {
+        let mut line = ChatLine::new(ChatTarget::Team);
+        assert_eq!(line.to_command(), None);
+
+        for c in "say \"hi\"\r".chars() {
+            line.insert(c);
+        }
+        assert_eq!(line.prompt(), "say_team: say \"hi\"");
+        assert_eq!(
+            line.to_command(),
+            Some(String::from("say_team \"say 'hi'\"\n"))
+        );
+
+        line.backspace();
+        assert_eq!(line.text(), "say \"hi");
+    }
+
+    #[test]
+    fn test_chat_line_max_len() {
+        let mut line = ChatLine::new(ChatTarget::All);
+        for _ in 0..2 * MAX_CHAT_LEN {
+            line.insert('a');
+        }
+        assert_eq!(line.text().len(), MAX_CHAT_LEN);
+    }
+}
@@ -15,27 +15,121 @@
 // DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
-use crate::common::console::Console;
+use crate::{
+    client::render,
+    common::console::{Console, OutputPosition},
+};
 
+use copypasta::{ClipboardContext, ClipboardProvider};
 use failure::Error;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode as Key, WindowEvent};
+use winit::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+    VirtualKeyCode as Key, WindowEvent,
+};
+
+// number of lines scrolled by PgUp/PgDn
+const PAGE_LINES: usize = 8;
+
+// number of lines scrolled by one notch of the mouse wheel
+const WHEEL_LINES: usize = 2;
 
 pub struct ConsoleInput {
     console: Rc<RefCell<Console>>,
+
+    // window size and cursor position in physical pixels, used to hit-test the console output
+    display_size: Cell<(u32, u32)>,
+    cursor_pos: Cell<(f64, f64)>,
+
+    // how far the console is open, which moves the output while it slides
+    open: Cell<f32>,
+
+    modifiers: Cell<ModifiersState>,
+
+    // true while the left mouse button is held down over the console output
+    selecting: Cell<bool>,
 }
 
 impl ConsoleInput {
     pub fn new(console: Rc<RefCell<Console>>) -> ConsoleInput {
-        ConsoleInput { console }
+        ConsoleInput {
+            console,
+            display_size: Cell::new((0, 0)),
+            cursor_pos: Cell::new((0.0, 0.0)),
+            open: Cell::new(1.0),
+            modifiers: Cell::new(ModifiersState::empty()),
+            selecting: Cell::new(false),
+        }
+    }
+
+    /// Sets the window dimensions used to map the mouse cursor to console text.
+    pub fn set_display_size(&self, width: u32, height: u32) {
+        self.display_size.set((width, height));
+    }
+
+    /// Sets how far the console is open, from 0 (closed) to 1 (fully open).
+    pub fn set_open_fraction(&self, open: f32) {
+        self.open.set(open);
     }
 
     pub fn handle_event<T>(&self, event: Event<T>) -> Result<(), Error> {
         match event {
             Event::WindowEvent { event, .. } => match event {
+                // don't insert control characters generated by shortcuts
+                WindowEvent::ReceivedCharacter(c) if self.modifiers.get().ctrl() => {
+                    trace!("Ignoring {:?} with ctrl held", c)
+                }
+
                 WindowEvent::ReceivedCharacter(c) => self.console.borrow_mut().send_char(c)?,
 
+                WindowEvent::ModifiersChanged(modifiers) => self.modifiers.set(modifiers),
+
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.cursor_pos.set((position.x, position.y));
+
+                    if self.selecting.get() {
+                        if let Some(pos) = self.output_position() {
+                            self.console.borrow().output_mut().select_extend(pos);
+                        }
+                    }
+                }
+
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => match state {
+                    ElementState::Pressed => match self.output_position() {
+                        Some(pos) => {
+                            self.console.borrow().output_mut().select_start(pos);
+                            self.selecting.set(true);
+                        }
+
+                        None => self.console.borrow().output_mut().clear_selection(),
+                    },
+
+                    ElementState::Released => self.selecting.set(false),
+                },
+
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let up = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y > 0.0,
+                        MouseScrollDelta::PixelDelta(pos) => pos.y > 0.0,
+                    };
+
+                    let console = self.console.borrow();
+                    let mut output = console.output_mut();
+                    if up {
+                        output.scroll_up(WHEEL_LINES);
+                    } else {
+                        output.scroll_down(WHEEL_LINES);
+                    }
+                }
+
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
@@ -45,11 +139,19 @@ impl ConsoleInput {
                         },
                     ..
                 } => match key {
+                    Key::C if self.modifiers.get().ctrl() => self.copy_selection(),
                     Key::Up => self.console.borrow_mut().history_up(),
                     Key::Down => self.console.borrow_mut().history_down(),
                     Key::Left => self.console.borrow_mut().cursor_left(),
                     Key::Right => self.console.borrow_mut().cursor_right(),
-                    Key::Grave => self.console.borrow_mut().stuff_text("toggleconsole\n"),
+                    Key::PageUp => self.console.borrow().output_mut().scroll_up(PAGE_LINES),
+                    Key::PageDown => self.console.borrow().output_mut().scroll_down(PAGE_LINES),
+                    Key::End if self.modifiers.get().ctrl() => {
+                        self.console.borrow().output_mut().scroll_to_bottom()
+                    }
+
+                    // as in the game, escape brings up the menu
+                    Key::Escape => self.console.borrow().stuff_text("togglemenu\n"),
                     _ => (),
                 },
 
@@ -61,4 +163,24 @@ impl ConsoleInput {
 
         Ok(())
     }
+
+    // returns the position in the console output under the mouse cursor
+    fn output_position(&self) -> Option<OutputPosition> {
+        let (width, height) = self.display_size.get();
+        let (x, y) = self.cursor_pos.get();
+        render::output_position_at(&self.console.borrow(), self.open.get(), width, height, x, y)
+    }
+
+    // copies the selected console output to the system clipboard
+    fn copy_selection(&self) {
+        let text = match self.console.borrow().output().selected_text() {
+            Some(t) => t,
+            None => return,
+        };
+
+        match ClipboardContext::new().and_then(|mut ctx| ctx.set_contents(text)) {
+            Ok(()) => debug!("Copied console selection to clipboard"),
+            Err(e) => warn!("Failed to copy console selection to clipboard: {}", e),
+        }
+    }
 }
@@ -23,12 +23,18 @@ use std::{
     string::ToString,
 };
 
-use crate::common::{
-    console::{CmdRegistry, Console},
-    parse,
+use crate::{
+    client::input::gamepad::GamepadButton,
+    common::{
+        console::{CmdRegistry, Console, ConsoleOutput},
+        l10n,
+        parse::{self, console::quote},
+    },
 };
 
+use chrono::{DateTime, Utc};
 use failure::Error;
+use num::FromPrimitive;
 use winit::{
     dpi::LogicalPosition,
     event::{
@@ -39,7 +45,33 @@ use winit::{
 
 const ACTION_COUNT: usize = 19;
 
-static INPUT_NAMES: [&'static str; 79] = [
+// controls bound in the `Game` context by `GameInput::bind_defaults`
+static DEFAULT_BINDINGS: [(Key, &'static str); 22] = [
+    (Key::W, "+forward"),
+    (Key::A, "+moveleft"),
+    (Key::S, "+back"),
+    (Key::D, "+moveright"),
+    (Key::Space, "+jump"),
+    (Key::Up, "+lookup"),
+    (Key::Left, "+left"),
+    (Key::Down, "+lookdown"),
+    (Key::Right, "+right"),
+    (Key::LControl, "+attack"),
+    (Key::E, "+use"),
+    (Key::T, "messagemode"),
+    (Key::Tab, "+showscores"),
+    (Key::Key1, "impulse 1"),
+    (Key::Key2, "impulse 2"),
+    (Key::Key3, "impulse 3"),
+    (Key::Key4, "impulse 4"),
+    (Key::Key5, "impulse 5"),
+    (Key::Key6, "impulse 6"),
+    (Key::Key7, "impulse 7"),
+    (Key::Key8, "impulse 8"),
+    (Key::Key9, "impulse 9"),
+];
+
+static INPUT_NAMES: [&'static str; 95] = [
     ",",
     ".",
     "/",
@@ -54,14 +86,21 @@ static INPUT_NAMES: [&'static str; 79] = [
     "8",
     "9",
     "A",
+    "ABUTTON",
     "ALT",
     "B",
+    "BACK",
     "BACKSPACE",
+    "BBUTTON",
     "C",
     "CTRL",
     "D",
     "DEL",
     "DOWNARROW",
+    "DPAD_DOWN",
+    "DPAD_LEFT",
+    "DPAD_RIGHT",
+    "DPAD_UP",
     "E",
     "END",
     "ENTER",
@@ -88,6 +127,9 @@ static INPUT_NAMES: [&'static str; 79] = [
     "K",
     "L",
     "LEFTARROW",
+    "LSHOULDER",
+    "LTHUMB",
+    "LTRIGGER",
     "M",
     "MOUSE1",
     "MOUSE2",
@@ -102,10 +144,14 @@ static INPUT_NAMES: [&'static str; 79] = [
     "Q",
     "R",
     "RIGHTARROW",
+    "RSHOULDER",
+    "RTHUMB",
+    "RTRIGGER",
     "S",
     "SEMICOLON",
     "SHIFT",
     "SPACE",
+    "START",
     "T",
     "TAB",
     "U",
@@ -113,7 +159,9 @@ static INPUT_NAMES: [&'static str; 79] = [
     "V",
     "W",
     "X",
+    "XBUTTON",
     "Y",
+    "YBUTTON",
     "Z",
     "[",
     "\\",
@@ -121,7 +169,7 @@ static INPUT_NAMES: [&'static str; 79] = [
     "`",
 ];
 
-static INPUT_VALUES: [BindInput; 79] = [
+static INPUT_VALUES: [BindInput; 95] = [
     BindInput::Key(Key::Comma),
     BindInput::Key(Key::Period),
     BindInput::Key(Key::Slash),
@@ -136,14 +184,21 @@ static INPUT_VALUES: [BindInput; 79] = [
     BindInput::Key(Key::Key8),
     BindInput::Key(Key::Key9),
     BindInput::Key(Key::A),
+    BindInput::GamepadButton(GamepadButton::A),
     BindInput::Key(Key::LAlt),
     BindInput::Key(Key::B),
+    BindInput::GamepadButton(GamepadButton::Back),
     BindInput::Key(Key::Back),
+    BindInput::GamepadButton(GamepadButton::B),
     BindInput::Key(Key::C),
     BindInput::Key(Key::LControl),
     BindInput::Key(Key::D),
     BindInput::Key(Key::Delete),
     BindInput::Key(Key::Down),
+    BindInput::GamepadButton(GamepadButton::DPadDown),
+    BindInput::GamepadButton(GamepadButton::DPadLeft),
+    BindInput::GamepadButton(GamepadButton::DPadRight),
+    BindInput::GamepadButton(GamepadButton::DPadUp),
     BindInput::Key(Key::E),
     BindInput::Key(Key::End),
     BindInput::Key(Key::Return),
@@ -170,6 +225,9 @@ static INPUT_VALUES: [BindInput; 79] = [
     BindInput::Key(Key::K),
     BindInput::Key(Key::L),
     BindInput::Key(Key::Left),
+    BindInput::GamepadButton(GamepadButton::LeftShoulder),
+    BindInput::GamepadButton(GamepadButton::LeftThumb),
+    BindInput::GamepadButton(GamepadButton::LeftTrigger),
     BindInput::Key(Key::M),
     BindInput::MouseButton(MouseButton::Left),
     BindInput::MouseButton(MouseButton::Right),
@@ -184,10 +242,14 @@ static INPUT_VALUES: [BindInput; 79] = [
     BindInput::Key(Key::Q),
     BindInput::Key(Key::R),
     BindInput::Key(Key::Right),
+    BindInput::GamepadButton(GamepadButton::RightShoulder),
+    BindInput::GamepadButton(GamepadButton::RightThumb),
+    BindInput::GamepadButton(GamepadButton::RightTrigger),
     BindInput::Key(Key::S),
     BindInput::Key(Key::Semicolon),
     BindInput::Key(Key::LShift),
     BindInput::Key(Key::Space),
+    BindInput::GamepadButton(GamepadButton::Start),
     BindInput::Key(Key::T),
     BindInput::Key(Key::Tab),
     BindInput::Key(Key::U),
@@ -195,7 +257,9 @@ static INPUT_VALUES: [BindInput; 79] = [
     BindInput::Key(Key::V),
     BindInput::Key(Key::W),
     BindInput::Key(Key::X),
+    BindInput::GamepadButton(GamepadButton::X),
     BindInput::Key(Key::Y),
+    BindInput::GamepadButton(GamepadButton::Y),
     BindInput::Key(Key::Z),
     BindInput::Key(Key::LBracket),
     BindInput::Key(Key::Backslash),
@@ -321,6 +385,157 @@ impl ToString for Action {
     }
 }
 
+// Looks up the binding for `input` in `context`, falling back to the `Game` context.
+fn resolve_binding(
+    bindings: &HashMap<BindContext, HashMap<BindInput, BindTarget>>,
+    context: BindContext,
+    input: BindInput,
+) -> Option<BindTarget> {
+    bindings
+        .get(&context)
+        .and_then(|layer| layer.get(&input))
+        .or_else(|| {
+            bindings
+                .get(&BindContext::Game)
+                .and_then(|layer| layer.get(&input))
+        })
+        .cloned()
+}
+
+// Prints what `key` is bound to in `context`.
+fn query_binding(
+    output: &mut ConsoleOutput,
+    bindings: &HashMap<BindContext, HashMap<BindInput, BindTarget>>,
+    context: BindContext,
+    key: &str,
+) {
+    match BindInput::from_str(key) {
+        Ok(i) => match bindings.get(&context).and_then(|layer| layer.get(&i)) {
+            Some(t) => output.print(l10n::tr_args(
+                "bind.binding",
+                &[&i.to_string(), &t.to_string()],
+            )),
+            None => output.print(l10n::tr_args("bind.not_bound", &[&i.to_string()])),
+        },
+
+        Err(_) => output.print(l10n::tr_args("bind.invalid_key", &[key])),
+    }
+}
+
+// Binds `key` to `target` in `context`.
+fn insert_binding(
+    output: &mut ConsoleOutput,
+    bindings: &RefCell<HashMap<BindContext, HashMap<BindInput, BindTarget>>>,
+    context: BindContext,
+    key: &str,
+    target: &str,
+) {
+    match BindInput::from_str(key) {
+        Ok(i) => {
+            let target = BindTarget::from_str(target).unwrap();
+            debug!("Bound {:?} to {:?} in {:?}", i, target, context);
+            bindings
+                .borrow_mut()
+                .entry(context)
+                .or_insert_with(HashMap::new)
+                .insert(i, target);
+        }
+
+        Err(_) => output.print(l10n::tr_args("bind.invalid_key", &[key])),
+    }
+}
+
+// Removes the binding for `key` in `context`.
+fn remove_binding(
+    output: &mut ConsoleOutput,
+    bindings: &RefCell<HashMap<BindContext, HashMap<BindInput, BindTarget>>>,
+    context: BindContext,
+    key: &str,
+) {
+    match BindInput::from_str(key) {
+        Ok(i) => {
+            let removed = bindings
+                .borrow_mut()
+                .get_mut(&context)
+                .and_then(|layer| layer.remove(&i));
+
+            if removed.is_none() {
+                output.print(l10n::tr_args("bind.not_bound", &[&i.to_string()]));
+            }
+        }
+
+        Err(_) => output.print(l10n::tr_args("bind.invalid_key", &[key])),
+    }
+}
+
+/// Returns true if `input` is bound to `target` in `context` by default.
+pub fn is_default_binding(context: BindContext, input: BindInput, target: &BindTarget) -> bool {
+    context == BindContext::Game
+        && DEFAULT_BINDINGS.iter().any(|(key, default)| {
+            BindInput::from(*key) == input
+                && BindTarget::from_str(default).unwrap().to_string() == target.to_string()
+        })
+}
+
+// Writes the bindings which differ from the defaults as a script which restores them when
+// executed after `GameInput::bind_defaults`.
+fn write_bindings(bindings: &HashMap<BindContext, HashMap<BindInput, BindTarget>>) -> String {
+    let mut script = String::new();
+
+    // defaults which have since been removed
+    let game = bindings.get(&BindContext::Game);
+    let mut unbound: Vec<String> = DEFAULT_BINDINGS
+        .iter()
+        .map(|(key, _)| BindInput::from(*key))
+        .filter(|input| game.map_or(true, |layer| !layer.contains_key(input)))
+        .map(|input| format!("unbind {}", quote(input.to_string())))
+        .collect();
+    unbound.sort();
+
+    for line in unbound {
+        script.push_str(&line);
+        script.push('\n');
+    }
+
+    for context in [BindContext::Game, BindContext::Spectator, BindContext::Demo].iter() {
+        let layer = match bindings.get(context) {
+            Some(l) => l,
+            None => continue,
+        };
+
+        let mut lines: Vec<String> = layer
+            .iter()
+            .filter(|(input, target)| !is_default_binding(*context, **input, target))
+            .map(|(input, target)| {
+                let target = match target {
+                    BindTarget::ConsoleInput { text } => text.to_owned(),
+                    t => t.to_string(),
+                };
+
+                match context {
+                    BindContext::Game => {
+                        format!("bind {} {}", quote(input.to_string()), quote(target))
+                    }
+                    c => format!(
+                        "cbind {} {} {}",
+                        c.to_string(),
+                        quote(input.to_string()),
+                        quote(target)
+                    ),
+                }
+            })
+            .collect();
+        lines.sort();
+
+        for line in lines {
+            script.push_str(&line);
+            script.push('\n');
+        }
+    }
+
+    script
+}
+
 // for game input, we only care about the direction the mouse wheel moved, not how far it went in
 // one event
 /// A movement of the mouse wheel up or down.
@@ -364,6 +579,9 @@ pub enum BindInput {
 
     /// A direction scrolled on the mouse wheel.
     MouseWheel(MouseWheel),
+
+    /// A button pressed on a gamepad.
+    GamepadButton(GamepadButton),
 }
 
 impl ::std::convert::From<Key> for BindInput {
@@ -384,6 +602,12 @@ impl ::std::convert::From<MouseWheel> for BindInput {
     }
 }
 
+impl ::std::convert::From<GamepadButton> for BindInput {
+    fn from(src: GamepadButton) -> BindInput {
+        BindInput::GamepadButton(src)
+    }
+}
+
 impl ::std::convert::From<MouseScrollDelta> for BindInput {
     fn from(src: MouseScrollDelta) -> BindInput {
         BindInput::MouseWheel(MouseWheel::from(src))
@@ -419,6 +643,46 @@ impl ToString for BindInput {
     }
 }
 
+/// A layer of bindings which is active in a particular game mode.
+///
+/// Bindings in the `Game` context apply in every mode unless the active
+/// context binds the same input to something else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BindContext {
+    /// Regular play.
+    Game,
+
+    /// Observing other players.
+    Spectator,
+
+    /// Watching a recorded demo.
+    Demo,
+}
+
+impl FromStr for BindContext {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<BindContext, Error> {
+        match src.to_lowercase().as_str() {
+            "game" => Ok(BindContext::Game),
+            "spectator" => Ok(BindContext::Spectator),
+            "demo" => Ok(BindContext::Demo),
+            _ => bail!("\"{}\" isn't a valid bind context", src),
+        }
+    }
+}
+
+impl ToString for BindContext {
+    fn to_string(&self) -> String {
+        match *self {
+            BindContext::Game => "game",
+            BindContext::Spectator => "spectator",
+            BindContext::Demo => "demo",
+        }
+        .to_owned()
+    }
+}
+
 /// An operation to perform when a `BindInput` is received.
 #[derive(Clone, Debug)]
 pub enum BindTarget {
@@ -475,10 +739,22 @@ impl ToString for BindTarget {
 #[derive(Clone)]
 pub struct GameInput {
     console: Rc<RefCell<Console>>,
-    bindings: Rc<RefCell<HashMap<BindInput, BindTarget>>>,
+    bindings: Rc<RefCell<HashMap<BindContext, HashMap<BindInput, BindTarget>>>>,
+    context: Rc<Cell<BindContext>>,
     action_states: Rc<RefCell<[bool; ACTION_COUNT]>>,
     mouse_delta: (f64, f64),
+
+    // mouse motion from the previous frame, for m_filter
+    prev_mouse_delta: (f64, f64),
+
     impulse: Rc<Cell<u8>>,
+
+    // gamepad stick positions after the deadzone and response curve are applied
+    move_axes: (f32, f32),
+    look_axes: (f32, f32),
+
+    // arrival time of the oldest input not yet sent to the server, for cl_inputtiming
+    input_time: Option<DateTime<Utc>>,
 }
 
 impl GameInput {
@@ -486,67 +762,184 @@ impl GameInput {
         GameInput {
             console,
             bindings: Rc::new(RefCell::new(HashMap::new())),
+            context: Rc::new(Cell::new(BindContext::Game)),
             action_states: Rc::new(RefCell::new([false; ACTION_COUNT])),
             mouse_delta: (0.0, 0.0),
+            prev_mouse_delta: (0.0, 0.0),
             impulse: Rc::new(Cell::new(0)),
+            move_axes: (0.0, 0.0),
+            look_axes: (0.0, 0.0),
+            input_time: None,
+        }
+    }
+
+    /// Returns the active binding context.
+    pub fn context(&self) -> BindContext {
+        self.context.get()
+    }
+
+    /// Switches to a different binding context.
+    ///
+    /// All actions are released so that nothing held in the old context stays
+    /// active in the new one.
+    pub fn set_context(&mut self, context: BindContext) {
+        if self.context.get() != context {
+            debug!("Switching bind context to {}", context.to_string());
+            self.context.set(context);
+            *self.action_states.borrow_mut() = [false; ACTION_COUNT];
         }
     }
 
+    /// Releases all actions and discards any accumulated mouse motion.
+    pub fn release_all(&mut self) {
+        *self.action_states.borrow_mut() = [false; ACTION_COUNT];
+        self.mouse_delta = (0.0, 0.0);
+        self.prev_mouse_delta = (0.0, 0.0);
+    }
+
+    /// Releases all held actions and asks the host to open the menu.
+    pub fn open_menu(&mut self) {
+        // we won't see releases for keys held while the menu is open
+        self.release_all();
+        self.console.borrow().stuff_text("togglemenu\n");
+    }
+
     pub fn mouse_delta(&self) -> (f64, f64) {
         self.mouse_delta
     }
 
+    /// Returns the mouse motion for this frame.
+    ///
+    /// If `filter` is true, the motion is averaged with that of the previous frame to smooth out
+    /// jitter from uneven mouse polling.
+    pub fn filtered_mouse_delta(&self, filter: bool) -> (f64, f64) {
+        if filter {
+            (
+                (self.mouse_delta.0 + self.prev_mouse_delta.0) / 2.0,
+                (self.mouse_delta.1 + self.prev_mouse_delta.1) / 2.0,
+            )
+        } else {
+            self.mouse_delta
+        }
+    }
+
+    /// Returns the position of the movement stick, with each axis in [-1, 1].
+    ///
+    /// Positive values are to the right and forward.
+    pub fn move_axes(&self) -> (f32, f32) {
+        self.move_axes
+    }
+
+    /// Returns the position of the look stick, with each axis in [-1, 1].
+    ///
+    /// Positive values are to the right and up.
+    pub fn look_axes(&self) -> (f32, f32) {
+        self.look_axes
+    }
+
+    /// Updates the positions of the gamepad sticks.
+    pub fn set_stick_axes(&mut self, move_axes: (f32, f32), look_axes: (f32, f32)) {
+        self.move_axes = move_axes;
+        self.look_axes = look_axes;
+    }
+
     pub fn impulse(&self) -> u8 {
         self.impulse.get()
     }
 
     /// Bind the default controls.
     pub fn bind_defaults(&mut self) {
-        self.bind(Key::W, BindTarget::from_str("+forward").unwrap());
-        self.bind(Key::A, BindTarget::from_str("+moveleft").unwrap());
-        self.bind(Key::S, BindTarget::from_str("+back").unwrap());
-        self.bind(Key::D, BindTarget::from_str("+moveright").unwrap());
-        self.bind(Key::Space, BindTarget::from_str("+jump").unwrap());
-        self.bind(Key::Up, BindTarget::from_str("+lookup").unwrap());
-        self.bind(Key::Left, BindTarget::from_str("+left").unwrap());
-        self.bind(Key::Down, BindTarget::from_str("+lookdown").unwrap());
-        self.bind(Key::Right, BindTarget::from_str("+right").unwrap());
-        self.bind(Key::LControl, BindTarget::from_str("+attack").unwrap());
-        self.bind(Key::E, BindTarget::from_str("+use").unwrap());
-        self.bind(Key::Grave, BindTarget::from_str("toggleconsole").unwrap());
-        self.bind(Key::Key1, BindTarget::from_str("impulse 1").unwrap());
-        self.bind(Key::Key2, BindTarget::from_str("impulse 2").unwrap());
-        self.bind(Key::Key3, BindTarget::from_str("impulse 3").unwrap());
-        self.bind(Key::Key4, BindTarget::from_str("impulse 4").unwrap());
-        self.bind(Key::Key5, BindTarget::from_str("impulse 5").unwrap());
-        self.bind(Key::Key6, BindTarget::from_str("impulse 6").unwrap());
-        self.bind(Key::Key7, BindTarget::from_str("impulse 7").unwrap());
-        self.bind(Key::Key8, BindTarget::from_str("impulse 8").unwrap());
-        self.bind(Key::Key9, BindTarget::from_str("impulse 9").unwrap());
-    }
-
-    /// Bind a `BindInput` to a `BindTarget`.
+        for (key, target) in DEFAULT_BINDINGS.iter() {
+            self.bind(*key, BindTarget::from_str(target).unwrap());
+        }
+    }
+
+    /// Bind a `BindInput` to a `BindTarget` in the `Game` context.
     pub fn bind<I, T>(&mut self, input: I, target: T) -> Option<BindTarget>
+    where
+        I: Into<BindInput>,
+        T: Into<BindTarget>,
+    {
+        self.bind_in(BindContext::Game, input, target)
+    }
+
+    /// Bind a `BindInput` to a `BindTarget` in the specified context.
+    pub fn bind_in<I, T>(&mut self, context: BindContext, input: I, target: T) -> Option<BindTarget>
     where
         I: Into<BindInput>,
         T: Into<BindTarget>,
     {
         self.bindings
             .borrow_mut()
+            .entry(context)
+            .or_insert_with(HashMap::new)
             .insert(input.into(), target.into())
     }
 
-    /// Return the `BindTarget` that `input` is bound to, or `None` if `input` is not present.
+    /// Remove the binding for `input` in the `Game` context, returning the old target if there
+    /// was one.
+    pub fn unbind<I>(&mut self, input: I) -> Option<BindTarget>
+    where
+        I: Into<BindInput>,
+    {
+        self.bindings
+            .borrow_mut()
+            .get_mut(&BindContext::Game)
+            .and_then(|layer| layer.remove(&input.into()))
+    }
+
+    /// Return the `BindTarget` that `input` is bound to in the active context, or `None` if
+    /// `input` is not present.
     pub fn binding<I>(&self, input: I) -> Option<BindTarget>
     where
         I: Into<BindInput>,
     {
-        self.bindings.borrow().get(&input.into()).map(|t| t.clone())
+        resolve_binding(&self.bindings.borrow(), self.context.get(), input.into())
+    }
+
+    /// Returns every binding as a `(context, input, target)` triple, in no particular order.
+    pub fn iter_bindings(&self) -> impl Iterator<Item = (BindContext, BindInput, BindTarget)> {
+        let bindings: Vec<_> = self
+            .bindings
+            .borrow()
+            .iter()
+            .flat_map(|(context, layer)| {
+                layer
+                    .iter()
+                    .map(move |(input, target)| (*context, *input, target.clone()))
+            })
+            .collect();
+
+        bindings.into_iter()
+    }
+
+    /// Returns a script which restores the current bindings when executed after the defaults
+    /// have been bound.
+    ///
+    /// Only bindings which differ from the defaults are written.
+    pub fn write_bindings(&self) -> String {
+        write_bindings(&self.bindings.borrow())
     }
 
     pub fn handle_event<T>(&mut self, outer_event: Event<T>) -> Result<(), Error> {
         let (input, state): (BindInput, _) = match outer_event {
             Event::WindowEvent { event, .. } => match event {
+                // escape always opens the menu so it can't be bound away
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(Key::Escape),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    if state == ElementState::Pressed {
+                        self.open_menu();
+                    }
+                    return Ok(());
+                }
+
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
@@ -567,6 +960,7 @@ impl GameInput {
                 DeviceEvent::MouseMotion { delta } => {
                     self.mouse_delta.0 += delta.0;
                     self.mouse_delta.1 += delta.1;
+                    self.input_time.get_or_insert_with(Utc::now);
                     return Ok(());
                 }
 
@@ -578,6 +972,7 @@ impl GameInput {
             _ => return Ok(()),
         };
 
+        self.input_time.get_or_insert_with(Utc::now);
         self.handle_input(input, state)?;
 
         Ok(())
@@ -590,8 +985,8 @@ impl GameInput {
         let bind_input = input.into();
 
         // debug!("handle input {:?}: {:?}", &bind_input, state);
-        if let Some(target) = self.bindings.borrow().get(&bind_input) {
-            match *target {
+        if let Some(target) = self.binding(bind_input) {
+            match target {
                 BindTarget::Action { trigger, action } => {
                     self.action_states.borrow_mut()[action as usize] = state == trigger;
                     debug!(
@@ -601,7 +996,7 @@ impl GameInput {
                     );
                 }
 
-                BindTarget::ConsoleInput { ref text } => {
+                BindTarget::ConsoleInput { text } => {
                     if state == ElementState::Pressed {
                         self.console.borrow_mut().stuff_text(text);
                     }
@@ -616,313 +1011,106 @@ impl GameInput {
         self.action_states.borrow()[action as usize]
     }
 
-    // TODO: roll actions into a loop
+    /// Returns true if vertical mouse movement should pitch the view.
+    ///
+    /// This is always the case with `cl_freelook`; otherwise `+mlook` must be held.
+    pub fn mouse_look(&self, freelook: bool) -> bool {
+        freelook || self.action_state(Action::MLook)
+    }
+
     pub fn register_cmds(&self, cmds: &mut CmdRegistry) {
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+forward",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Forward as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-forward",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Forward as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+back",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Back as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-back",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Back as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+moveright",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::MoveRight as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-moveright",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::MoveRight as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+moveleft",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::MoveLeft as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-moveleft",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::MoveLeft as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+lookup",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::LookUp as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-lookup",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::LookUp as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+lookdown",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::LookDown as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-lookdown",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::LookDown as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+Left",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Left as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-Left",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Left as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+right",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Right as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-right",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Right as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+speed",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Speed as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-speed",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Speed as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+jump",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Jump as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-jump",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Jump as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+strafe",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Strafe as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-strafe",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Strafe as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+attack",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Attack as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-attack",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Attack as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+use",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Use as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-use",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::Use as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+klook",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::KLook as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-klook",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::KLook as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+mlook",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::MLook as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-mlook",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::MLook as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "+showscores",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::ShowScores as usize] = true;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
-        cmds.insert_or_replace(
-            "-showscores",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::ShowScores as usize] = false;
-            }),
-        )
-        .unwrap();
-        let states = self.action_states.clone();
+        // "+action" and "-action" for every action, so they can be used in scripts as well as
+        // bindings
+        for action_id in 0..ACTION_COUNT {
+            let action = Action::from_usize(action_id).unwrap();
+
+            let states = self.action_states.clone();
+            cmds.insert_or_replace(
+                format!("+{}", action.to_string()),
+                Box::new(move |_| {
+                    states.borrow_mut()[action as usize] = true;
+                }),
+            )
+            .unwrap();
+
+            let states = self.action_states.clone();
+            cmds.insert_or_replace(
+                format!("-{}", action.to_string()),
+                Box::new(move |_| {
+                    states.borrow_mut()[action as usize] = false;
+                }),
+            )
+            .unwrap();
+        }
+
+        let output = self.console.borrow().output_handle();
+
+        // "bind"
+        let bindings = self.bindings.clone();
+        let bind_output = output.clone();
         cmds.insert_or_replace(
-            "+showteamscores",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::ShowTeamScores as usize] = true;
+            "bind",
+            Box::new(move |args| {
+                let mut output = bind_output.borrow_mut();
+                match args.len() {
+                    // bind (key)
+                    // queries what (key) is bound to, if anything
+                    1 => query_binding(&mut output, &bindings.borrow(), BindContext::Game, args[0]),
+
+                    // bind (key) [command...]
+                    // unquoted commands may span several arguments, as in the original
+                    n if n >= 2 => insert_binding(
+                        &mut output,
+                        &bindings,
+                        BindContext::Game,
+                        args[0],
+                        &args[1..].join(" "),
+                    ),
+
+                    _ => output.print(l10n::tr("bind.bind_help")),
+                }
             }),
         )
         .unwrap();
-        let states = self.action_states.clone();
+
+        // "cbind"
+        let bindings = self.bindings.clone();
+        let cbind_output = output.clone();
         cmds.insert_or_replace(
-            "-showteamscores",
-            Box::new(move |_| {
-                states.borrow_mut()[Action::ShowTeamScores as usize] = false;
+            "cbind",
+            Box::new(move |args| {
+                let mut output = cbind_output.borrow_mut();
+                if args.len() != 2 && args.len() != 3 {
+                    output.print(l10n::tr("bind.cbind_help"));
+                    return;
+                }
+
+                let context = match BindContext::from_str(args[0]) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        output.print(e.to_string());
+                        return;
+                    }
+                };
+
+                match args.len() {
+                    2 => query_binding(&mut output, &bindings.borrow(), context, args[1]),
+                    _ => insert_binding(&mut output, &bindings, context, args[1], args[2]),
+                }
             }),
         )
         .unwrap();
 
-        // "bind"
+        // "unbind"
         let bindings = self.bindings.clone();
+        let unbind_output = output.clone();
         cmds.insert_or_replace(
-            "bind",
+            "unbind",
             Box::new(move |args| {
-                println!("args: {}", args.len());
+                let mut output = unbind_output.borrow_mut();
                 match args.len() {
-                    // bind (key)
-                    // queries what (key) is bound to, if anything
-                    1 => match BindInput::from_str(args[0]) {
-                        Ok(i) => match bindings.borrow().get(&i) {
-                            Some(t) => println!("\"{}\" = \"{}\"", i.to_string(), t.to_string()),
-                            None => println!("\"{}\" is not bound", i.to_string()),
-                        },
-
-                        Err(_) => println!("\"{}\" isn't a valid key", args[0]),
-                    },
-
-                    // bind (key) [command]
-                    2 => match BindInput::from_str(args[0]) {
-                        Ok(i) => {
-                            let target = BindTarget::from_str(args[1]).unwrap();
-                            bindings
-                                .borrow_mut()
-                                .insert(i, BindTarget::from_str(args[1]).unwrap());
-                            debug!("Bound {:?} to {:?}", i, target);
-                        }
-
-                        Err(_) => println!("\"{}\" isn't a valid key", args[0]),
-                    },
-
-                    _ => println!("bind [key] (command): attach a command to a key"),
+                    1 => remove_binding(&mut output, &bindings, BindContext::Game, args[0]),
+                    _ => output.print(l10n::tr("bind.unbind_help")),
                 }
             }),
         )
@@ -960,6 +1148,13 @@ impl GameInput {
         .unwrap();
     }
 
+    /// Returns when the oldest input since the last call arrived, if any input has.
+    ///
+    /// The client calls this when it sends a move command to measure input latency.
+    pub fn take_input_time(&mut self) -> Option<DateTime<Utc>> {
+        self.input_time.take()
+    }
+
     // must be called every frame!
     pub fn refresh(&mut self) -> Result<(), Error> {
         self.clear_mouse()?;
@@ -971,6 +1166,7 @@ impl GameInput {
     fn clear_mouse(&mut self) -> Result<(), Error> {
         self.handle_input(MouseWheel::Up, ElementState::Released)?;
         self.handle_input(MouseWheel::Down, ElementState::Released)?;
+        self.prev_mouse_delta = self.mouse_delta;
         self.mouse_delta = (0.0, 0.0);
 
         Ok(())
@@ -986,6 +1182,54 @@ impl GameInput {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::common::console::CvarRegistry;
+
+    #[test]
+    fn test_gamepad_button_names() {
+        for button in GamepadButton::ALL.iter() {
+            let input = BindInput::from(*button);
+            let name = input.to_string();
+            assert!(!name.is_empty(), "{:?} has no name", button);
+            assert_eq!(BindInput::from_str(&name).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_filtered_mouse_delta() {
+        let console = Rc::new(RefCell::new(Console::new(
+            Rc::new(RefCell::new(CmdRegistry::new())),
+            Rc::new(RefCell::new(CvarRegistry::new())),
+        )));
+        let mut input = GameInput::new(console);
+
+        input.mouse_delta = (4.0, -2.0);
+        input.refresh().unwrap();
+        input.mouse_delta = (2.0, 0.0);
+
+        assert_eq!(input.filtered_mouse_delta(false), (2.0, 0.0));
+        assert_eq!(input.filtered_mouse_delta(true), (3.0, -1.0));
+    }
+
+    #[test]
+    fn test_action_cmds() {
+        let console = Rc::new(RefCell::new(Console::new(
+            Rc::new(RefCell::new(CmdRegistry::new())),
+            Rc::new(RefCell::new(CvarRegistry::new())),
+        )));
+        let input = GameInput::new(console);
+        let mut cmds = CmdRegistry::new();
+        input.register_cmds(&mut cmds);
+
+        for action_id in 0..ACTION_COUNT {
+            let action = Action::from_usize(action_id).unwrap();
+            let name = action.to_string();
+
+            cmds.exec(format!("+{}", name), &[]).unwrap();
+            assert!(input.action_state(action), "+{} did not press", name);
+            cmds.exec(format!("-{}", name), &[]).unwrap();
+            assert!(!input.action_state(action), "-{} did not release", name);
+        }
+    }
 
     #[test]
     fn test_action_to_string() {
@@ -993,6 +1237,35 @@ mod test {
         assert_eq!(act.to_string(), "forward");
     }
 
+    #[test]
+    fn test_bind_context_fallback() {
+        let mut bindings = HashMap::new();
+        let mut game = HashMap::new();
+        game.insert(
+            BindInput::from(Key::Space),
+            BindTarget::from_str("+jump").unwrap(),
+        );
+        game.insert(
+            BindInput::from(Key::LControl),
+            BindTarget::from_str("+attack").unwrap(),
+        );
+        bindings.insert(BindContext::Game, game);
+        let mut demo = HashMap::new();
+        demo.insert(
+            BindInput::from(Key::Space),
+            BindTarget::from_str("pause").unwrap(),
+        );
+        bindings.insert(BindContext::Demo, demo);
+
+        // overridden in the demo context
+        let space = resolve_binding(&bindings, BindContext::Demo, Key::Space.into()).unwrap();
+        assert_eq!(space.to_string(), "\"pause\"");
+
+        // falls back to the game context
+        let ctrl = resolve_binding(&bindings, BindContext::Demo, Key::LControl.into()).unwrap();
+        assert_eq!(ctrl.to_string(), "+attack");
+    }
+
     #[test]
     fn test_bind_target_action_to_string() {
         let target = BindTarget::Action {
@@ -1002,4 +1275,73 @@ mod test {
 
         assert_eq!(target.to_string(), "+forward");
     }
+
+    #[test]
+    fn test_remove_binding() {
+        let mut output = ConsoleOutput::new();
+        let bindings = RefCell::new(HashMap::new());
+        insert_binding(
+            &mut output,
+            &bindings,
+            BindContext::Game,
+            "MOUSE1",
+            "+attack",
+        );
+        insert_binding(&mut output, &bindings, BindContext::Game, "F1", "help");
+        remove_binding(&mut output, &bindings, BindContext::Game, "mouse1");
+
+        let bindings = bindings.borrow();
+        assert!(resolve_binding(&bindings, BindContext::Game, MouseButton::Left.into()).is_none());
+        assert!(resolve_binding(&bindings, BindContext::Game, Key::F1.into()).is_some());
+    }
+
+    #[test]
+    fn test_write_bindings() {
+        let mut game: HashMap<BindInput, BindTarget> = DEFAULT_BINDINGS
+            .iter()
+            .map(|(key, target)| (BindInput::from(*key), BindTarget::from_str(target).unwrap()))
+            .collect();
+        game.remove(&BindInput::from(Key::T));
+        game.insert(
+            BindInput::from(Key::Key1),
+            BindTarget::from_str("impulse 7").unwrap(),
+        );
+        game.insert(
+            BindInput::from(Key::F1),
+            BindTarget::from_str("help").unwrap(),
+        );
+
+        let mut bindings = HashMap::new();
+        bindings.insert(BindContext::Game, game);
+        let mut demo = HashMap::new();
+        demo.insert(
+            BindInput::from(Key::Space),
+            BindTarget::from_str("pause").unwrap(),
+        );
+        bindings.insert(BindContext::Demo, demo);
+
+        assert_eq!(
+            write_bindings(&bindings),
+            "unbind \"T\"\n\
+             bind \"1\" \"impulse 7\"\n\
+             bind \"F1\" \"help\"\n\
+             cbind demo \"SPACE\" \"pause\"\n"
+        );
+    }
+
+    #[test]
+    fn test_is_default_binding() {
+        let jump = BindTarget::from_str("+jump").unwrap();
+        assert!(is_default_binding(
+            BindContext::Game,
+            Key::Space.into(),
+            &jump
+        ));
+        assert!(!is_default_binding(
+            BindContext::Demo,
+            Key::Space.into(),
+            &jump
+        ));
+        assert!(!is_default_binding(BindContext::Game, Key::E.into(), &jump));
+    }
 }
@@ -0,0 +1,722 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    client::input::{game::GameInput, menu::MenuInput},
+    common::console::{ConsoleOutput, CvarRegistry},
+};
+
+use failure::Error;
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use winit::event::ElementState;
+
+/// A button on a gamepad, named after the Xbox controller layout.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftShoulder,
+    RightShoulder,
+    LeftTrigger,
+    RightTrigger,
+    Back,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    pub const ALL: [GamepadButton; 16] = [
+        GamepadButton::A,
+        GamepadButton::B,
+        GamepadButton::X,
+        GamepadButton::Y,
+        GamepadButton::LeftShoulder,
+        GamepadButton::RightShoulder,
+        GamepadButton::LeftTrigger,
+        GamepadButton::RightTrigger,
+        GamepadButton::Back,
+        GamepadButton::Start,
+        GamepadButton::LeftThumb,
+        GamepadButton::RightThumb,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+        GamepadButton::DPadRight,
+    ];
+
+    fn from_gilrs(button: Button) -> Option<GamepadButton> {
+        Some(match button {
+            Button::South => GamepadButton::A,
+            Button::East => GamepadButton::B,
+            Button::West => GamepadButton::X,
+            Button::North => GamepadButton::Y,
+            Button::LeftTrigger => GamepadButton::LeftShoulder,
+            Button::RightTrigger => GamepadButton::RightShoulder,
+            Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+            Button::RightTrigger2 => GamepadButton::RightTrigger,
+            Button::Select => GamepadButton::Back,
+            Button::Start => GamepadButton::Start,
+            Button::LeftThumb => GamepadButton::LeftThumb,
+            Button::RightThumb => GamepadButton::RightThumb,
+            Button::DPadUp => GamepadButton::DPadUp,
+            Button::DPadDown => GamepadButton::DPadDown,
+            Button::DPadLeft => GamepadButton::DPadLeft,
+            Button::DPadRight => GamepadButton::DPadRight,
+            _ => return None,
+        })
+    }
+}
+
+/// An axis of one of the analog sticks.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+impl GamepadAxis {
+    pub const ALL: [GamepadAxis; 4] = [
+        GamepadAxis::LeftX,
+        GamepadAxis::LeftY,
+        GamepadAxis::RightX,
+        GamepadAxis::RightY,
+    ];
+
+    /// Returns the name used for this axis in cvar names, e.g. `joy_axis_leftx`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            GamepadAxis::LeftX => "leftx",
+            GamepadAxis::LeftY => "lefty",
+            GamepadAxis::RightX => "rightx",
+            GamepadAxis::RightY => "righty",
+        }
+    }
+}
+
+/// What an analog axis controls, as set by its `joy_axis_*` cvar.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AxisTarget {
+    None,
+
+    /// Move forward (positive) or back.
+    Forward,
+
+    /// Move right (positive) or left.
+    Side,
+
+    /// Turn right (positive) or left.
+    Yaw,
+
+    /// Look up (positive) or down.
+    Pitch,
+}
+
+impl AxisTarget {
+    /// Parses the value of a `joy_axis_*` cvar.
+    ///
+    /// Unrecognized values leave the axis unbound.
+    pub fn from_name(name: &str) -> AxisTarget {
+        match name {
+            "forward" => AxisTarget::Forward,
+            "side" => AxisTarget::Side,
+            "yaw" => AxisTarget::Yaw,
+            "pitch" => AxisTarget::Pitch,
+            _ => AxisTarget::None,
+        }
+    }
+}
+
+/// The response curve of a single analog axis, as set by its `joy_curve_*` cvar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AxisCurve {
+    /// Use the stick's curve (`joy_exponent`).
+    Stick,
+
+    /// Output is proportional to deflection.
+    Linear,
+
+    /// Output is the square of deflection, for finer control near the center.
+    Squared,
+
+    /// Output is deflection raised to the given exponent.
+    Custom(f32),
+}
+
+impl AxisCurve {
+    /// Parses the value of a `joy_curve_*` cvar.
+    ///
+    /// Accepts `linear`, `squared` or a positive exponent. Anything else, including the empty
+    /// string, falls back to the stick's curve.
+    pub fn from_name(name: &str) -> AxisCurve {
+        match name.trim() {
+            "linear" => AxisCurve::Linear,
+            "squared" => AxisCurve::Squared,
+            other => match other.parse::<f32>() {
+                Ok(e) if e > 0.0 => AxisCurve::Custom(e),
+                _ => AxisCurve::Stick,
+            },
+        }
+    }
+
+    /// Returns the exponent of this curve, or `None` if it follows the stick's curve.
+    pub fn exponent(&self) -> Option<f32> {
+        match *self {
+            AxisCurve::Stick => None,
+            AxisCurve::Linear => Some(1.0),
+            AxisCurve::Squared => Some(2.0),
+            AxisCurve::Custom(e) => Some(e),
+        }
+    }
+}
+
+/// The names of the stick layouts accepted by `axis_preset`.
+pub const AXIS_PRESETS: &[&str] = &["default", "southpaw"];
+
+/// Returns the `joy_axis_*` values for a named stick layout, in the order of `GamepadAxis::ALL`.
+///
+/// `default` moves with the left stick and looks with the right; `southpaw` swaps the two.
+pub fn axis_preset(name: &str) -> Option<[&'static str; 4]> {
+    match name {
+        "default" => Some(["side", "forward", "yaw", "pitch"]),
+        "southpaw" => Some(["yaw", "pitch", "side", "forward"]),
+        _ => None,
+    }
+}
+
+/// Implements the `joy_preset` command.
+///
+/// With no arguments, prints the available presets. Otherwise, sets the `joy_axis_*` cvars to
+/// the named layout.
+pub fn cmd_joy_preset(
+    output: Rc<RefCell<ConsoleOutput>>,
+    cvars: Rc<RefCell<CvarRegistry>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        1 => match axis_preset(args[0]) {
+            Some(targets) => {
+                let cvars = cvars.borrow();
+                for (axis, target) in GamepadAxis::ALL.iter().zip(targets.iter()) {
+                    let name = format!("joy_axis_{}", axis.name());
+                    if let Err(e) = cvars.set(name.as_str(), *target) {
+                        output
+                            .borrow_mut()
+                            .warn(format!("Couldn't set {}: {}", name, e));
+                    }
+                }
+            }
+            None => output
+                .borrow_mut()
+                .warn(format!("Unknown preset \"{}\"", args[0])),
+        },
+        _ => output
+            .borrow_mut()
+            .print("usage: joy_preset <default|southpaw>"),
+    })
+}
+
+/// Returns the name of the preset matching the current `joy_axis_*` cvars, if any.
+pub fn current_axis_preset(cvars: &CvarRegistry) -> Option<&'static str> {
+    AXIS_PRESETS.iter().cloned().find(|name| {
+        let targets = axis_preset(name).unwrap();
+        GamepadAxis::ALL
+            .iter()
+            .zip(targets.iter())
+            .all(|(axis, target)| {
+                cvars
+                    .get_str(format!("joy_axis_{}", axis.name()))
+                    .map(|s| s.trim() == *target)
+                    .unwrap_or(false)
+            })
+    })
+}
+
+/// Values of the cvars which control a single analog axis.
+#[derive(Clone, Copy, Debug)]
+pub struct AxisVars {
+    /// What the axis controls (`joy_axis_*`).
+    pub target: AxisTarget,
+
+    /// Multiplier applied to the axis, after the deadzone (`joy_scale_*`). Negative values invert
+    /// the axis.
+    pub scale: f32,
+
+    /// Deflections along this axis smaller than this (in [0, 1]) are ignored, in addition to the
+    /// stick's deadzone (`joy_deadzone_*`).
+    pub deadzone: f32,
+
+    /// The response curve of the axis (`joy_curve_*`).
+    pub curve: AxisCurve,
+}
+
+impl AxisVars {
+    /// Reads the cvars for `axis`.
+    pub fn from_cvars(cvars: &CvarRegistry, axis: GamepadAxis) -> AxisVars {
+        let name = axis.name();
+        AxisVars {
+            target: cvars
+                .get_str(format!("joy_axis_{}", name))
+                .map(|s| AxisTarget::from_name(&s))
+                .unwrap_or(AxisTarget::None),
+            scale: cvars
+                .get_value(format!("joy_scale_{}", name))
+                .unwrap_or(1.0),
+            deadzone: cvars
+                .get_value(format!("joy_deadzone_{}", name))
+                .unwrap_or(0.0),
+            curve: cvars
+                .get_str(format!("joy_curve_{}", name))
+                .map(|s| AxisCurve::from_name(&s))
+                .unwrap_or(AxisCurve::Stick),
+        }
+    }
+}
+
+/// Values of the cvars which control the analog sticks.
+#[derive(Clone, Copy, Debug)]
+pub struct StickVars {
+    /// Stick deflections smaller than this (in [0, 1]) are ignored.
+    pub joy_deadzone: f32,
+
+    /// The exponent of the response curve. Higher values give finer control near the center.
+    pub joy_exponent: f32,
+
+    /// Per-axis settings, in the order of `GamepadAxis::ALL`.
+    pub axes: [AxisVars; 4],
+}
+
+impl StickVars {
+    /// Reads the stick and axis cvars.
+    pub fn from_cvars(cvars: &CvarRegistry) -> StickVars {
+        let mut axes = [AxisVars {
+            target: AxisTarget::None,
+            scale: 1.0,
+            deadzone: 0.0,
+            curve: AxisCurve::Stick,
+        }; 4];
+        for (i, axis) in GamepadAxis::ALL.iter().enumerate() {
+            axes[i] = AxisVars::from_cvars(cvars, *axis);
+        }
+
+        StickVars {
+            joy_deadzone: cvars.get_value("joy_deadzone").unwrap_or(0.2),
+            joy_exponent: cvars.get_value("joy_exponent").unwrap_or(2.0),
+            axes,
+        }
+    }
+}
+
+/// Applies a radial deadzone and response curve to the position of an analog stick.
+///
+/// Both axes are in [-1, 1]. The result is rescaled so that it starts from zero at the edge of
+/// the deadzone.
+pub fn apply_stick_curve(x: f32, y: f32, vars: StickVars) -> (f32, f32) {
+    let magnitude = (x * x + y * y).sqrt();
+    let deadzone = vars.joy_deadzone.max(0.0).min(0.99);
+
+    if magnitude <= deadzone {
+        return (0.0, 0.0);
+    }
+
+    let scaled = ((magnitude.min(1.0) - deadzone) / (1.0 - deadzone)).powf(vars.joy_exponent);
+    (x / magnitude * scaled, y / magnitude * scaled)
+}
+
+/// Applies the radial deadzone and each axis's response curve to the positions of both sticks.
+///
+/// Axes which follow the stick's curve get the result of `apply_stick_curve`. Axes with their own
+/// curve start from the linear response and apply their exponent individually, keeping the sign.
+/// The result is in the order of `GamepadAxis::ALL`.
+pub fn apply_axis_curves(left: (f32, f32), right: (f32, f32), vars: StickVars) -> [f32; 4] {
+    let linear_vars = StickVars {
+        joy_exponent: 1.0,
+        ..vars
+    };
+
+    let mut values = [0.0; 4];
+    for (i, (x, y)) in [left, right].iter().enumerate() {
+        let (curved_x, curved_y) = apply_stick_curve(*x, *y, vars);
+        let (linear_x, linear_y) = apply_stick_curve(*x, *y, linear_vars);
+
+        for (j, (curved, linear)) in [(curved_x, linear_x), (curved_y, linear_y)]
+            .iter()
+            .enumerate()
+        {
+            let axis = 2 * i + j;
+            values[axis] = match vars.axes[axis].curve.exponent() {
+                Some(e) => linear.signum() * linear.abs().powf(e),
+                None => *curved,
+            };
+        }
+    }
+
+    values
+}
+
+/// Applies a single axis's deadzone, rescaling so the result starts from zero at its edge.
+pub fn apply_axis_deadzone(value: f32, deadzone: f32) -> f32 {
+    let deadzone = deadzone.max(0.0).min(0.99);
+    if value.abs() <= deadzone {
+        return 0.0;
+    }
+
+    value.signum() * (value.abs().min(1.0) - deadzone) / (1.0 - deadzone)
+}
+
+/// Combines the axis positions into movement and look input.
+///
+/// `values` are in the order of `GamepadAxis::ALL`, with the stick curve already applied. Returns
+/// `((side, forward), (yaw, pitch))` in the form expected by `GameInput::set_stick_axes`.
+/// Movement is clamped to [-1, 1] so several axes bound to the same target can't move the player
+/// faster than a keypress would.
+pub fn resolve_axes(values: [f32; 4], axes: &[AxisVars; 4]) -> ((f32, f32), (f32, f32)) {
+    let (mut side, mut forward, mut yaw, mut pitch) = (0.0, 0.0, 0.0, 0.0);
+
+    for (value, vars) in values.iter().zip(axes.iter()) {
+        let value = apply_axis_deadzone(*value, vars.deadzone) * vars.scale;
+        match vars.target {
+            AxisTarget::None => (),
+            AxisTarget::Forward => forward += value,
+            AxisTarget::Side => side += value,
+            AxisTarget::Yaw => yaw += value,
+            AxisTarget::Pitch => pitch += value,
+        }
+    }
+
+    (
+        (side.max(-1.0).min(1.0), forward.max(-1.0).min(1.0)),
+        (yaw, pitch),
+    )
+}
+
+/// Reads buttons and analog sticks from the connected gamepads.
+///
+/// Only one gamepad is used at a time. When it's unplugged, the next connected gamepad takes its
+/// place.
+pub struct Gamepads {
+    gilrs: Gilrs,
+    active: Option<GamepadId>,
+    output: Rc<RefCell<ConsoleOutput>>,
+}
+
+impl Gamepads {
+    /// Starts listening for gamepads, reporting connections to the console through `output`.
+    pub fn new(output: Rc<RefCell<ConsoleOutput>>) -> Result<Gamepads, Error> {
+        let gilrs = match Gilrs::new() {
+            Ok(g) => g,
+            Err(e) => bail!("Failed to initialize gamepad support: {}", e),
+        };
+
+        let active = gilrs.gamepads().next().map(|(id, gamepad)| {
+            output
+                .borrow_mut()
+                .print(format!("Using gamepad \"{}\"", gamepad.name()));
+            id
+        });
+
+        Ok(Gamepads {
+            gilrs,
+            active,
+            output,
+        })
+    }
+
+    /// Processes gamepad events since the last call.
+    ///
+    /// Button events and stick positions are sent to `game_input` if it's `Some`, and button
+    /// presses are sent to `menu_input` if it's `Some`; otherwise the events are discarded.
+    ///
+    /// Like Escape on the keyboard, Start always opens the menu from the game.
+    pub fn poll(
+        &mut self,
+        mut game_input: Option<&mut GameInput>,
+        menu_input: Option<&MenuInput>,
+        vars: StickVars,
+    ) {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    if self.active.is_none() {
+                        self.output.borrow_mut().print(format!(
+                            "Using gamepad \"{}\"",
+                            self.gilrs.gamepad(event.id).name()
+                        ));
+                        self.active = Some(event.id);
+                    }
+                }
+
+                EventType::Disconnected if self.active == Some(event.id) => {
+                    self.output.borrow_mut().print("Gamepad disconnected");
+
+                    // release anything held on the old gamepad
+                    if let Some(ref mut input) = game_input {
+                        for button in GamepadButton::ALL.iter() {
+                            if let Err(e) = input.handle_input(*button, ElementState::Released) {
+                                warn!("Failed to release {:?}: {}", button, e);
+                            }
+                        }
+                    }
+
+                    let output = &self.output;
+                    self.active = self.gilrs.gamepads().find(|(id, _)| *id != event.id).map(
+                        |(id, gamepad)| {
+                            output
+                                .borrow_mut()
+                                .print(format!("Using gamepad \"{}\"", gamepad.name()));
+                            id
+                        },
+                    );
+                }
+
+                EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _)
+                    if self.active == Some(event.id) =>
+                {
+                    let state = match event.event {
+                        EventType::ButtonPressed(..) => ElementState::Pressed,
+                        _ => ElementState::Released,
+                    };
+
+                    let button = match GamepadButton::from_gilrs(button) {
+                        Some(b) => b,
+                        None => continue,
+                    };
+
+                    if let Some(input) = game_input.as_mut() {
+                        if button == GamepadButton::Start {
+                            if state == ElementState::Pressed {
+                                input.open_menu();
+                            }
+                        } else if let Err(e) = input.handle_input(button, state) {
+                            warn!("Failed to handle {:?}: {}", button, e);
+                        }
+                    }
+
+                    if let Some(input) = menu_input {
+                        if state == ElementState::Pressed {
+                            if let Err(e) = input.handle_button(button) {
+                                warn!("Failed to handle {:?}: {}", button, e);
+                            }
+                        }
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        let (move_axes, look_axes) = match self.active {
+            Some(id) => {
+                let gamepad = self.gilrs.gamepad(id);
+                let values = apply_axis_curves(
+                    (
+                        gamepad.value(Axis::LeftStickX),
+                        gamepad.value(Axis::LeftStickY),
+                    ),
+                    (
+                        gamepad.value(Axis::RightStickX),
+                        gamepad.value(Axis::RightStickY),
+                    ),
+                    vars,
+                );
+
+                resolve_axes(values, &vars.axes)
+            }
+
+            None => ((0.0, 0.0), (0.0, 0.0)),
+        };
+
+        if let Some(input) = game_input {
+            input.set_stick_axes(move_axes, look_axes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DEFAULT_AXES: [AxisVars; 4] = [
+        AxisVars {
+            target: AxisTarget::Side,
+            scale: 1.0,
+            deadzone: 0.0,
+            curve: AxisCurve::Stick,
+        },
+        AxisVars {
+            target: AxisTarget::Forward,
+            scale: 1.0,
+            deadzone: 0.0,
+            curve: AxisCurve::Stick,
+        },
+        AxisVars {
+            target: AxisTarget::Yaw,
+            scale: 1.0,
+            deadzone: 0.0,
+            curve: AxisCurve::Stick,
+        },
+        AxisVars {
+            target: AxisTarget::Pitch,
+            scale: 1.0,
+            deadzone: 0.0,
+            curve: AxisCurve::Stick,
+        },
+    ];
+
+    const VARS: StickVars = StickVars {
+        joy_deadzone: 0.2,
+        joy_exponent: 2.0,
+        axes: DEFAULT_AXES,
+    };
+
+    #[test]
+    fn test_stick_curve_deadzone() {
+        assert_eq!(apply_stick_curve(0.1, -0.1, VARS), (0.0, 0.0));
+        assert_eq!(apply_stick_curve(0.2, 0.0, VARS), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_stick_curve_full_deflection() {
+        let (x, y) = apply_stick_curve(0.0, -1.0, VARS);
+        assert!(x.abs() < 1e-6);
+        assert!((y + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_stick_curve_exponent() {
+        // halfway between the deadzone and the edge
+        let (x, _) = apply_stick_curve(0.6, 0.0, VARS);
+        assert!((x - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_axis_deadzone() {
+        assert_eq!(apply_axis_deadzone(0.1, 0.2), 0.0);
+        assert!((apply_axis_deadzone(-0.6, 0.2) + 0.5).abs() < 1e-6);
+        assert_eq!(apply_axis_deadzone(1.0, 0.2), 1.0);
+    }
+
+    #[test]
+    fn test_resolve_default_axes() {
+        let (move_axes, look_axes) = resolve_axes([0.5, -1.0, 0.25, 0.75], &DEFAULT_AXES);
+        assert_eq!(move_axes, (0.5, -1.0));
+        assert_eq!(look_axes, (0.25, 0.75));
+    }
+
+    #[test]
+    fn test_resolve_remapped_axes() {
+        // turn with the left stick, strafe with the right, invert pitch on the left
+        let mut axes = DEFAULT_AXES;
+        axes[0].target = AxisTarget::Yaw;
+        axes[1].target = AxisTarget::Pitch;
+        axes[1].scale = -2.0;
+        axes[2].target = AxisTarget::Side;
+        axes[3].target = AxisTarget::None;
+
+        let (move_axes, look_axes) = resolve_axes([0.5, 0.25, -0.75, 1.0], &axes);
+        assert_eq!(move_axes, (-0.75, 0.0));
+        assert_eq!(look_axes, (0.5, -0.5));
+    }
+
+    #[test]
+    fn test_resolve_clamps_movement() {
+        let mut axes = DEFAULT_AXES;
+        axes[3].target = AxisTarget::Forward;
+
+        let (move_axes, _) = resolve_axes([0.0, 0.75, 0.0, 0.75], &axes);
+        assert_eq!(move_axes, (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_axis_curves() {
+        let mut vars = VARS;
+        vars.axes[0].curve = AxisCurve::Linear;
+        vars.axes[2].curve = AxisCurve::Custom(3.0);
+
+        // halfway between the deadzone and the edge
+        let values = apply_axis_curves((0.6, 0.0), (-0.6, 0.0), vars);
+        assert!((values[0] - 0.5).abs() < 1e-6);
+        assert!((values[2] + 0.125).abs() < 1e-6);
+
+        // axes following the stick curve are unchanged
+        let values = apply_axis_curves((0.0, 0.6), (0.0, -0.6), vars);
+        assert!((values[1] - 0.25).abs() < 1e-6);
+        assert!((values[3] + 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_axis_curve_from_name() {
+        assert_eq!(AxisCurve::from_name(""), AxisCurve::Stick);
+        assert_eq!(AxisCurve::from_name("linear"), AxisCurve::Linear);
+        assert_eq!(AxisCurve::from_name("squared"), AxisCurve::Squared);
+        assert_eq!(AxisCurve::from_name("1.5"), AxisCurve::Custom(1.5));
+        assert_eq!(AxisCurve::from_name("-2"), AxisCurve::Stick);
+    }
+
+    #[test]
+    fn test_axis_presets() {
+        let southpaw = axis_preset("southpaw").unwrap();
+        let axes: Vec<AxisTarget> = southpaw.iter().map(|t| AxisTarget::from_name(t)).collect();
+        assert_eq!(
+            axes,
+            vec![
+                AxisTarget::Yaw,
+                AxisTarget::Pitch,
+                AxisTarget::Side,
+                AxisTarget::Forward
+            ]
+        );
+        assert!(axis_preset("lefty").is_none());
+    }
+
+    #[test]
+    fn test_current_axis_preset() {
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let targets = axis_preset("default").unwrap();
+        for (axis, target) in GamepadAxis::ALL.iter().zip(targets.iter()) {
+            cvars
+                .borrow()
+                .register(format!("joy_axis_{}", axis.name()), target.to_string())
+                .unwrap();
+        }
+        assert_eq!(current_axis_preset(&cvars.borrow()), Some("default"));
+
+        cvars.borrow().set("joy_axis_leftx", "yaw").unwrap();
+        assert_eq!(current_axis_preset(&cvars.borrow()), None);
+
+        let output = Rc::new(RefCell::new(ConsoleOutput::new()));
+        cmd_joy_preset(output, cvars.clone())(&["southpaw"]);
+        assert_eq!(current_axis_preset(&cvars.borrow()), Some("southpaw"));
+    }
+
+    #[test]
+    fn test_axis_target_from_name() {
+        assert_eq!(AxisTarget::from_name("forward"), AxisTarget::Forward);
+        assert_eq!(AxisTarget::from_name("pitch"), AxisTarget::Pitch);
+        assert_eq!(AxisTarget::from_name("none"), AxisTarget::None);
+        assert_eq!(AxisTarget::from_name("roll"), AxisTarget::None);
+    }
+}
@@ -17,7 +17,10 @@
 
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{client::menu::Menu, common::console::Console};
+use crate::{
+    client::{input::gamepad::GamepadButton, menu::Menu},
+    common::console::Console,
+};
 
 use failure::Error;
 use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode as Key, WindowEvent};
@@ -46,14 +49,7 @@ impl MenuInput {
                         },
                     ..
                 } => match key {
-                    Key::Escape => {
-                        if self.menu.borrow().at_root() {
-                            self.console.borrow().stuff_text("togglemenu\n");
-                        } else {
-                            self.menu.borrow().back()?;
-                        }
-                    }
-
+                    Key::Escape => self.back()?,
                     Key::Up => self.menu.borrow().prev()?,
                     Key::Down => self.menu.borrow().next()?,
                     Key::Return => self.menu.borrow().activate()?,
@@ -71,4 +67,33 @@ impl MenuInput {
 
         Ok(())
     }
+
+    /// Handles a gamepad button press.
+    ///
+    /// The d-pad moves through the menu, A activates the selected item, and B or Start backs
+    /// out, closing the menu from the root.
+    pub fn handle_button(&self, button: GamepadButton) -> Result<(), Error> {
+        match button {
+            GamepadButton::DPadUp => self.menu.borrow().prev()?,
+            GamepadButton::DPadDown => self.menu.borrow().next()?,
+            GamepadButton::DPadLeft => self.menu.borrow().left()?,
+            GamepadButton::DPadRight => self.menu.borrow().right()?,
+            GamepadButton::A => self.menu.borrow().activate()?,
+            GamepadButton::B | GamepadButton::Start => self.back()?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    // Leaves the active submenu, or closes the menu if it's at the root.
+    fn back(&self) -> Result<(), Error> {
+        if self.menu.borrow().at_root() {
+            self.console.borrow().stuff_text("togglemenu\n");
+        } else {
+            self.menu.borrow().back()?;
+        }
+
+        Ok(())
+    }
 }
@@ -15,11 +15,13 @@
 // DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+pub mod chat;
 pub mod console;
 pub mod game;
+pub mod gamepad;
 pub mod menu;
 
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, mem, rc::Rc};
 
 use crate::{
     client::menu::Menu,
@@ -27,28 +29,41 @@ use crate::{
 };
 
 use failure::Error;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode as Key, WindowEvent};
 
 use self::{
+    chat::{ChatInput, ChatLine},
     console::ConsoleInput,
-    game::{BindInput, BindTarget, GameInput},
+    game::{BindContext, BindInput, BindTarget, GameInput},
+    gamepad::{Gamepads, StickVars},
     menu::MenuInput,
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InputFocus {
-    Game,
+    /// Input is sent to the game, using bindings from the given context.
+    Game(BindContext),
     Console,
     Menu,
+
+    /// Input is captured as a chat message (`messagemode` or `messagemode2`).
+    Chat,
 }
 
 pub struct Input {
     window_focused: bool,
     current_focus: InputFocus,
 
+    // set when the console key is pressed, cleared by `take_console_toggle`
+    console_toggled: bool,
+
     game_input: GameInput,
     console_input: ConsoleInput,
     menu_input: MenuInput,
+    chat_input: ChatInput,
+
+    // None if gamepad support couldn't be initialized
+    gamepads: Option<Gamepads>,
 }
 
 impl Input {
@@ -57,13 +72,27 @@ impl Input {
         console: Rc<RefCell<Console>>,
         menu: Rc<RefCell<Menu>>,
     ) -> Input {
+        let mut game_input = GameInput::new(console.clone());
+        if let InputFocus::Game(context) = init_focus {
+            game_input.set_context(context);
+        }
+
         Input {
             window_focused: true,
             current_focus: init_focus,
+            console_toggled: false,
 
-            game_input: GameInput::new(console.clone()),
+            game_input,
             console_input: ConsoleInput::new(console.clone()),
             menu_input: MenuInput::new(menu.clone(), console.clone()),
+            chat_input: ChatInput::new(console.clone()),
+            gamepads: match Gamepads::new(console.borrow().output_handle()) {
+                Ok(g) => Some(g),
+                Err(e) => {
+                    console.borrow().output_mut().warn(e.to_string());
+                    None
+                }
+            },
         }
     }
 
@@ -73,14 +102,43 @@ impl Input {
             Event::WindowEvent {
                 event: WindowEvent::Focused(focused),
                 ..
-            } => self.window_focused = focused,
+            } => {
+                self.window_focused = focused;
+
+                // we won't see key releases while unfocused, so don't leave anything held down
+                if !focused {
+                    self.game_input.release_all();
+                }
+            }
+
+            // the console key is handled here so it works regardless of focus or bindings
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(Key::Grave),
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if self.window_focused => self.toggle_console()?,
+
+            // ...and never types a character
+            Event::WindowEvent {
+                event: WindowEvent::ReceivedCharacter(c),
+                ..
+            } if c == '`' || c == '~' => (),
 
             _ => {
                 if self.window_focused {
                     match self.current_focus {
-                        InputFocus::Game => self.game_input.handle_event(event)?,
+                        InputFocus::Game(_) => self.game_input.handle_event(event)?,
                         InputFocus::Console => self.console_input.handle_event(event)?,
                         InputFocus::Menu => self.menu_input.handle_event(event)?,
+                        InputFocus::Chat => self.chat_input.handle_event(event)?,
                     }
                 }
             }
@@ -89,6 +147,45 @@ impl Input {
         Ok(())
     }
 
+    /// Processes gamepad input since the last frame.
+    ///
+    /// Gamepad buttons and sticks affect the game while it has focus, and buttons navigate the
+    /// menu while it's open. If `enabled` is false, gamepad input is discarded.
+    pub fn poll_gamepads(&mut self, enabled: bool, vars: StickVars) {
+        let active = enabled && self.window_focused;
+        let game_input = match self.current_focus {
+            InputFocus::Game(_) if active => Some(&mut self.game_input),
+            _ => {
+                self.game_input.set_stick_axes((0.0, 0.0), (0.0, 0.0));
+                None
+            }
+        };
+
+        let menu_input = match self.current_focus {
+            InputFocus::Menu if active => Some(&self.menu_input),
+            _ => None,
+        };
+
+        if let Some(ref mut gamepads) = self.gamepads {
+            gamepads.poll(game_input, menu_input, vars);
+        }
+    }
+
+    /// Updates the window dimensions used to map mouse coordinates to on-screen elements.
+    pub fn set_display_size(&mut self, width: u32, height: u32) {
+        self.console_input.set_display_size(width, height);
+    }
+
+    /// Updates how far the console is open, used to map mouse coordinates to console output.
+    pub fn set_console_open_fraction(&mut self, open: f32) {
+        self.console_input.set_open_fraction(open);
+    }
+
+    /// Returns true if the window has keyboard focus.
+    pub fn window_focused(&self) -> bool {
+        self.window_focused
+    }
+
     pub fn current_focus(&self) -> InputFocus {
         self.current_focus
     }
@@ -96,9 +193,39 @@ impl Input {
     pub fn set_focus(&mut self, new_focus: InputFocus) -> Result<(), Error> {
         self.current_focus = new_focus;
 
+        if let InputFocus::Game(context) = new_focus {
+            self.game_input.set_context(context);
+        }
+
         Ok(())
     }
 
+    /// Switches between the console and the game.
+    ///
+    /// The focus changes immediately, so the rest of this frame's events are
+    /// routed correctly. Owners of the focus state should check
+    /// `take_console_toggle` once per frame to keep in sync.
+    pub fn toggle_console(&mut self) -> Result<(), Error> {
+        let new_focus = match self.current_focus {
+            InputFocus::Console => InputFocus::Game(self.game_input.context()),
+            _ => {
+                // we won't see releases for keys held when the console opens
+                self.game_input.release_all();
+                InputFocus::Console
+            }
+        };
+
+        self.set_focus(new_focus)?;
+        self.console_toggled = true;
+
+        Ok(())
+    }
+
+    /// Returns true if the console key was pressed since the last call.
+    pub fn take_console_toggle(&mut self) -> bool {
+        mem::replace(&mut self.console_toggled, false)
+    }
+
     /// Bind a `BindInput` to a `BindTarget`.
     pub fn bind<I, T>(&mut self, input: I, target: T) -> Option<BindTarget>
     where
@@ -112,8 +239,28 @@ impl Input {
         self.game_input.bind_defaults();
     }
 
+    /// Returns a script which restores the current bindings when executed.
+    pub fn write_bindings(&self) -> String {
+        self.game_input.write_bindings()
+    }
+
+    /// Returns true if a chat message is being typed.
+    pub fn chatting(&self) -> bool {
+        self.chat_input.active()
+    }
+
+    /// Returns the chat message being typed, if any.
+    pub fn chat_line(&self) -> Option<ChatLine> {
+        self.chat_input.line()
+    }
+
+    /// Discards the chat message being typed, if any.
+    pub fn cancel_chat(&self) {
+        self.chat_input.cancel();
+    }
+
     pub fn game_input(&self) -> Option<&GameInput> {
-        if let InputFocus::Game = self.current_focus {
+        if let InputFocus::Game(_) = self.current_focus {
             Some(&self.game_input)
         } else {
             None
@@ -121,7 +268,7 @@ impl Input {
     }
 
     pub fn game_input_mut(&mut self) -> Option<&mut GameInput> {
-        if let InputFocus::Game = self.current_focus {
+        if let InputFocus::Game(_) = self.current_focus {
             Some(&mut self.game_input)
         } else {
             None
@@ -130,5 +277,6 @@ impl Input {
 
     pub fn register_cmds(&self, cmds: &mut CmdRegistry) {
         self.game_input.register_cmds(cmds);
+        self.chat_input.register_cmds(cmds);
     }
 }
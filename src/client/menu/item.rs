@@ -35,20 +35,37 @@ pub enum Item {
 
 pub struct Toggle {
     state: Cell<bool>,
+    source: Option<Box<dyn Fn() -> bool>>,
     on_toggle: Box<dyn Fn(bool)>,
 }
 
 impl Toggle {
+    /// Creates a toggle in the given state. `on_toggle` only runs when the
+    /// toggle is changed, not on creation.
     pub fn new(init: bool, on_toggle: Box<dyn Fn(bool)>) -> Toggle {
-        let t = Toggle {
+        Toggle {
             state: Cell::new(init),
+            source: None,
             on_toggle,
-        };
+        }
+    }
 
-        // initialize with default
-        (t.on_toggle)(init);
+    /// Creates a toggle whose state is read from `source`, such as a cvar,
+    /// when it is created and again whenever it is synced.
+    pub fn synced(source: Box<dyn Fn() -> bool>, on_toggle: Box<dyn Fn(bool)>) -> Toggle {
+        Toggle {
+            state: Cell::new(source()),
+            source: Some(source),
+            on_toggle,
+        }
+    }
 
-        t
+    /// Reads the state from this toggle's source, if it has one, without
+    /// running the callback.
+    pub fn sync(&self) {
+        if let Some(ref source) = self.source {
+            self.state.set(source());
+        }
     }
 
     pub fn set_false(&self) {
@@ -75,25 +92,50 @@ impl Toggle {
 // e.g. resolution enum wraps, texture filtering does not
 pub struct Enum {
     selected: Cell<usize>,
+    source: Option<Box<dyn Fn() -> Option<usize>>>,
     items: Vec<EnumItem>,
 }
 
 impl Enum {
+    /// Creates an enum with the given item selected. Item callbacks only run
+    /// when the selection is changed, not on creation.
     pub fn new(init: usize, items: Vec<EnumItem>) -> Result<Enum, Error> {
         ensure!(items.len() > 0, "Enum element must have at least one item");
         ensure!(init < items.len(), "Invalid initial item ID");
 
-        let e = Enum {
+        Ok(Enum {
             selected: Cell::new(init),
+            source: None,
             items,
-        };
-
-        // initialize with the default choice
-        (e.items[e.selected.get()].on_select)();
+        })
+    }
 
+    /// Creates an enum whose selection is read from `source` when it is
+    /// created and again whenever it is synced.
+    ///
+    /// If `source` returns `None` or an invalid index, the selection is left
+    /// alone, or set to the first item on creation.
+    pub fn synced(
+        items: Vec<EnumItem>,
+        source: Box<dyn Fn() -> Option<usize>>,
+    ) -> Result<Enum, Error> {
+        let mut e = Enum::new(0, items)?;
+        e.source = Some(source);
+        e.sync();
         Ok(e)
     }
 
+    /// Reads the selection from this enum's source, if it has one, without
+    /// running the callback.
+    pub fn sync(&self) {
+        if let Some(ref source) = self.source {
+            match source() {
+                Some(s) if s < self.items.len() => self.selected.set(s),
+                _ => (),
+            }
+        }
+    }
+
     pub fn selected_name(&self) -> &str {
         self.items[self.selected.get()].name.as_str()
     }
@@ -143,6 +185,7 @@ pub struct Slider {
     steps: usize,
 
     selected: Cell<usize>,
+    source: Option<Box<dyn Fn() -> f32>>,
     on_select: Box<dyn Fn(f32)>,
 }
 
@@ -167,10 +210,39 @@ impl Slider {
             increment: (max - min) / (steps - 1) as f32,
             steps,
             selected: Cell::new(init),
+            source: None,
             on_select,
         })
     }
 
+    /// Creates a slider whose position is read from `source` when it is
+    /// created and again whenever it is synced.
+    pub fn synced(
+        min: f32,
+        max: f32,
+        steps: usize,
+        source: Box<dyn Fn() -> f32>,
+        on_select: Box<dyn Fn(f32)>,
+    ) -> Result<Slider, Error> {
+        let mut slider = Slider::new(min, max, steps, 0, on_select)?;
+        slider.source = Some(source);
+        slider.sync();
+        Ok(slider)
+    }
+
+    /// Moves this slider to the step nearest the value of its source, if it
+    /// has one, without running the callback.
+    pub fn sync(&self) {
+        if let Some(ref source) = self.source {
+            let step = ((source() - self.min) / self.increment).round();
+            if step.is_nan() || step <= 0.0 {
+                self.selected.set(0);
+            } else {
+                self.selected.set((step as usize).min(self.steps - 1));
+            }
+        }
+    }
+
     pub fn increase(&self) {
         let old = self.selected.get();
 
@@ -306,16 +378,41 @@ mod test {
 
         let s2 = s.clone();
         let item = Toggle::new(
-            false,
+            true,
             Box::new(move |state| {
                 s2.replace(format!("{}", state));
             }),
         );
-        item.toggle();
 
+        // creation doesn't run the callback
+        assert_eq!(*s.borrow(), "false");
+        assert!(item.get());
+
+        item.toggle();
+        assert_eq!(*s.borrow(), "false");
+        item.toggle();
         assert_eq!(*s.borrow(), "true");
     }
 
+    #[test]
+    fn test_toggle_synced() {
+        let source = Rc::new(Cell::new(true));
+        let toggled = Rc::new(Cell::new(0));
+
+        let src = source.clone();
+        let t = toggled.clone();
+        let item = Toggle::synced(
+            Box::new(move || src.get()),
+            Box::new(move |_| t.set(t.get() + 1)),
+        );
+        assert!(item.get());
+
+        source.set(false);
+        item.sync();
+        assert!(!item.get());
+        assert_eq!(toggled.get(), 0);
+    }
+
     #[test]
     fn test_enum() {
         let target = Rc::new(RefCell::new("null".to_string()));
@@ -335,7 +432,10 @@ mod test {
             .collect();
 
         let e = Enum::new(0, enum_items).unwrap();
-        assert_eq!(*target.borrow(), "option_0");
+        assert_eq!(e.selected_name(), "option_0");
+
+        // creation doesn't run the callback
+        assert_eq!(*target.borrow(), "null");
 
         // wrap under
         e.select_prev();
@@ -351,6 +451,73 @@ mod test {
         assert_eq!(*target.borrow(), "option_0");
     }
 
+    #[test]
+    fn test_enum_synced() {
+        let source = Rc::new(Cell::new(Some(2)));
+        let selected = Rc::new(Cell::new(None));
+
+        let enum_items = (0..3)
+            .map(|i| {
+                let selected = selected.clone();
+                EnumItem::new(
+                    format!("option_{}", i),
+                    Box::new(move || selected.set(Some(i))),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let src = source.clone();
+        let e = Enum::synced(enum_items, Box::new(move || src.get())).unwrap();
+        assert_eq!(e.selected_name(), "option_2");
+
+        // unknown values leave the selection alone
+        source.set(None);
+        e.sync();
+        assert_eq!(e.selected_name(), "option_2");
+        source.set(Some(3));
+        e.sync();
+        assert_eq!(e.selected_name(), "option_2");
+
+        source.set(Some(1));
+        e.sync();
+        assert_eq!(e.selected_name(), "option_1");
+        assert_eq!(selected.get(), None);
+    }
+
+    #[test]
+    fn test_slider_synced() {
+        let source = Rc::new(Cell::new(3.0f32));
+        let f = Rc::new(Cell::new(-1.0f32));
+
+        let src = source.clone();
+        let f2 = f.clone();
+        let item = Slider::synced(
+            1.0,
+            11.0,
+            11,
+            Box::new(move || src.get()),
+            Box::new(move |f| f2.set(f)),
+        )
+        .unwrap();
+        assert_eq!(item.selected.get(), 2);
+
+        // values snap to the nearest step and are clamped to the slider
+        for &(value, step) in &[
+            (3.4, 2),
+            (3.6, 3),
+            (-5.0, 0),
+            (50.0, 10),
+            (std::f32::NAN, 0),
+        ] {
+            source.set(value);
+            item.sync();
+            assert_eq!(item.selected.get(), step, "value {}", value);
+        }
+
+        assert_eq!(f.get(), -1.0);
+    }
+
     #[test]
     fn test_slider() {
         let f = Rc::new(Cell::new(0.0f32));
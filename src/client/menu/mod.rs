@@ -134,7 +134,7 @@ impl Menu {
         let s = m.state.get().clone();
         if let MenuState::Active { index } = s {
             m.state.replace(MenuState::Active {
-                index: (index - 1) % m.items.len(),
+                index: (index + m.items.len() - 1) % m.items.len(),
             });
         } else {
             bail!("Selected menu is inactive (invariant violation)");
@@ -161,7 +161,7 @@ impl Menu {
     /// `MenuState::Active`.
     ///
     /// If this item is an `Action`, executes the function contained in the
-    /// `Action`.
+    /// `Action`. If it is a `Toggle`, flips it.
     ///
     /// Otherwise, this has no effect.
     pub fn activate(&self) -> Result<(), Error> {
@@ -172,10 +172,13 @@ impl Menu {
                 Item::Submenu(ref submenu) => {
                     m.state.replace(MenuState::InSubMenu { index });
                     submenu.state.replace(MenuState::Active { index: 0 });
+                    submenu.sync();
                 }
 
                 Item::Action(ref action) => (action)(),
 
+                Item::Toggle(ref toggle) => toggle.toggle(),
+
                 _ => (),
             }
         }
@@ -183,6 +186,18 @@ impl Menu {
         Ok(())
     }
 
+    /// Reads the state of each of this menu's synced items from its source.
+    fn sync(&self) {
+        for item in self.items.iter() {
+            match item.item {
+                Item::Toggle(ref toggle) => toggle.sync(),
+                Item::Enum(ref e) => e.sync(),
+                Item::Slider(ref slider) => slider.sync(),
+                _ => (),
+            }
+        }
+    }
+
     pub fn left(&self) -> Result<(), Error> {
         let m = self.active_submenu()?;
 
@@ -317,6 +332,24 @@ impl MenuBuilder {
         self
     }
 
+    /// Adds a toggle whose state is read from `source` each time this menu
+    /// is entered.
+    pub fn add_synced_toggle<S>(
+        mut self,
+        name: S,
+        source: Box<dyn Fn() -> bool>,
+        on_toggle: Box<dyn Fn(bool)>,
+    ) -> MenuBuilder
+    where
+        S: AsRef<str>,
+    {
+        self.items.push(NamedMenuItem::new(
+            name,
+            Item::Toggle(Toggle::synced(source, on_toggle)),
+        ));
+        self
+    }
+
     pub fn add_enum<S, E>(mut self, name: S, items: E, init: usize) -> Result<MenuBuilder, Error>
     where
         S: AsRef<str>,
@@ -329,6 +362,25 @@ impl MenuBuilder {
         Ok(self)
     }
 
+    /// Adds an enum whose selection is read from `source` each time this menu
+    /// is entered.
+    pub fn add_synced_enum<S, E>(
+        mut self,
+        name: S,
+        items: E,
+        source: Box<dyn Fn() -> Option<usize>>,
+    ) -> Result<MenuBuilder, Error>
+    where
+        S: AsRef<str>,
+        E: Into<Vec<EnumItem>>,
+    {
+        self.items.push(NamedMenuItem::new(
+            name,
+            Item::Enum(Enum::synced(items.into(), source)?),
+        ));
+        Ok(self)
+    }
+
     pub fn add_slider<S>(
         mut self,
         name: S,
@@ -348,6 +400,27 @@ impl MenuBuilder {
         Ok(self)
     }
 
+    /// Adds a slider whose position is read from `source` each time this menu
+    /// is entered.
+    pub fn add_synced_slider<S>(
+        mut self,
+        name: S,
+        min: f32,
+        max: f32,
+        steps: usize,
+        source: Box<dyn Fn() -> f32>,
+        on_select: Box<dyn Fn(f32)>,
+    ) -> Result<MenuBuilder, Error>
+    where
+        S: AsRef<str>,
+    {
+        self.items.push(NamedMenuItem::new(
+            name,
+            Item::Slider(Slider::synced(min, max, steps, source, on_select)?),
+        ));
+        Ok(self)
+    }
+
     pub fn add_text_field<S>(
         mut self,
         name: S,
@@ -487,4 +560,51 @@ mod test {
         assert!(is_inactive(&m1.state.get()));
         assert!(is_active(&m2.state.get()));
     }
+
+    #[test]
+    fn test_menu_syncs_submenu() {
+        let source = Rc::new(Cell::new(false));
+        let src = source.clone();
+        let menu = MenuBuilder::new()
+            .add_submenu(
+                "options",
+                MenuBuilder::new()
+                    .add_synced_toggle("toggle", Box::new(move || src.get()), Box::new(|_| ()))
+                    .build(view()),
+            )
+            .build(view());
+
+        let toggle = || match menu.items[0].item {
+            Item::Submenu(ref m) => match m.items[0].item {
+                Item::Toggle(ref t) => t.get(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        // the toggle is only synced when its menu is entered
+        source.set(true);
+        assert!(!toggle());
+        menu.activate().unwrap();
+        assert!(toggle());
+    }
+
+    #[test]
+    fn test_menu_wraps() {
+        let menu = MenuBuilder::new()
+            .add_action("action_1", Box::new(|| ()))
+            .add_action("action_2", Box::new(|| ()))
+            .add_action("action_3", Box::new(|| ()))
+            .build(view());
+
+        let index = |m: &Menu| match m.state() {
+            MenuState::Active { index } => index,
+            _ => unreachable!(),
+        };
+
+        menu.prev().unwrap();
+        assert_eq!(index(&menu), 2);
+        menu.next().unwrap();
+        assert_eq!(index(&menu), 0);
+    }
 }
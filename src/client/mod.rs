@@ -18,11 +18,16 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+pub mod bookmark;
+pub mod connection;
+pub mod csqc;
 mod cvars;
 pub mod entity;
 pub mod error;
 pub mod input;
 pub mod menu;
+pub mod presence;
+pub mod projectile;
 pub mod render;
 pub mod sound;
 pub mod trace;
@@ -35,44 +40,58 @@ pub use self::{
 
 use std::{
     cell::{Cell, RefCell},
-    collections::HashMap,
-    io::{BufReader, Read},
-    net::ToSocketAddrs,
+    collections::{HashMap, VecDeque},
+    io::{BufReader, Cursor, Read as _},
+    net::{SocketAddr, ToSocketAddrs},
     rc::Rc,
+    time::Instant,
 };
 
 use crate::{
     client::{
+        bookmark::{CameraBookmark, CameraBookmarks},
+        connection::ConnectionState,
+        csqc::Csqc,
         entity::{
-            particle::{Particle, Particles, TrailKind, MAX_PARTICLES},
-            Beam, ClientEntity, Light, LightDesc, Lights, MAX_BEAMS, MAX_LIGHTS,
+            particle::{self, Particle, Particles, TrailKind},
+            Beam, ClientEntity, Light, LightDesc, Lights, PlayerSkin, MAX_BEAMS, MAX_LIGHTS,
             MAX_STATIC_ENTITIES, MAX_TEMP_ENTITIES,
         },
-        input::game::{Action, GameInput},
-        sound::{AudioSource, Channel, Listener, StaticSound},
+        input::game::{Action, BindContext, GameInput},
+        presence::PresenceState,
+        projectile::{ProjectileKind, ProjectilePredictor},
+        render::ColorblindMode,
+        sound::{AudioSource, Channel, DistanceModel, Listener, StaticSound},
         trace::{TraceEntity, TraceFrame},
-        view::{IdleVars, KickVars, MouseVars, RollVars, View},
+        view::{BobVars, IdleVars, JoyVars, KickVars, MouseVars, RollVars, View},
     },
     common::{
-        bsp,
-        console::{CmdRegistry, Console, CvarRegistry},
-        engine,
+        bsp::{self, MapDigest},
+        console::{CmdRegistry, CmdSource, Console, ConsoleError, ConsoleOutput, CvarRegistry},
+        engine, l10n,
         math::Angles,
         model::{Model, ModelFlags, ModelKind, SyncType},
         net::{
             self,
-            connect::{ConnectSocket, Request, Response, CONNECT_PROTOCOL_VERSION},
+            connect::{ConnectSocket, Request, Response, ResponseRcon, CONNECT_PROTOCOL_VERSION},
             BeamEntityKind, BlockingMode, ButtonFlags, ClientCmd, ClientStat, ColorShift,
             EntityEffects, EntityState, GameType, ItemFlags, NetError, PlayerColor,
-            PointEntityKind, QSocket, ServerCmd, SignOnStage, TempEntity,
+            PointEntityKind, Protocol, QSocket, ServerCmd, SignOnStage, TempEntity,
         },
+        parse,
         vfs::Vfs,
     },
 };
 
+#[cfg(feature = "music")]
+use crate::client::sound::MusicPlayer;
+#[cfg(feature = "qw")]
+use crate::common::net::oob::{OobRequest, OobResponse, OobSocket};
+
 use cgmath::{Angle, Deg, InnerSpace, Matrix4, Vector3, Zero};
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use failure::{Error, ResultExt};
+use num::FromPrimitive as _;
 use rand::{
     distributions::{Distribution as _, Uniform},
     Rng,
@@ -88,6 +107,30 @@ const DEFAULT_SOUND_PACKET_ATTENUATION: f32 = 1.0;
 
 const MAX_CHANNELS: usize = 128;
 
+// played by rocket, tarbaby and colored explosions
+const EXPLOSION_SOUND: &str = "weapons/r_exp3.wav";
+
+// how long a damage indicator remains on screen, in milliseconds
+const DAMAGE_INDICATOR_DURATION_MS: i64 = 1000;
+
+// older damage indicators are dropped to make room for new ones
+const MAX_DAMAGE_INDICATORS: usize = 8;
+
+// how long the hit confirmation remains on screen, in milliseconds
+const HIT_CONFIRM_DURATION_MS: i64 = 150;
+
+// time spent loading precached resources each frame, so the window keeps
+// responding while a map loads
+const PRECACHE_FRAME_BUDGET_MS: u128 = 50;
+
+// Quake caps the speed the player can add while in the air at this, which
+// determines the best angle to strafe at
+const AIR_WISH_SPEED: f32 = 30.0;
+
+// position of the muzzle flash light relative to the origin of the entity firing
+const MUZZLE_FLASH_HEIGHT: f32 = 16.0;
+const MUZZLE_FLASH_DISTANCE: f32 = 18.0;
+
 #[derive(Debug, FromPrimitive)]
 enum ColorShiftCode {
     Contents = 0,
@@ -101,6 +144,38 @@ struct ServerInfo {
     game_type: GameType,
 }
 
+// resources named by the server info which haven't been loaded yet
+#[derive(Debug, Default)]
+struct Precache {
+    models: VecDeque<String>,
+    sounds: VecDeque<String>,
+    total: usize,
+
+    // set when the server asks for prespawn, which is answered once loading is done
+    prespawn_requested: bool,
+}
+
+impl Precache {
+    fn remaining(&self) -> usize {
+        self.models.len() + self.sounds.len()
+    }
+}
+
+/// Progress through signing on to a server, shown on the loading screen.
+#[derive(Clone, Debug)]
+pub struct LoadingStatus {
+    pub state: ConnectionState,
+
+    /// The short name of the map being loaded, if known.
+    pub map_name: String,
+
+    /// The level name sent by the server, if known.
+    pub level_name: String,
+
+    /// The number of precached resources loaded so far and the total, while they're loading.
+    pub precache: Option<(usize, usize)>,
+}
+
 struct PlayerInfo {
     name: String,
     frags: i32,
@@ -108,6 +183,108 @@ struct PlayerInfo {
     // translations: [u8; VID_GRADES],
 }
 
+/// A player's line on the scoreboard.
+#[derive(Clone, Debug)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub frags: i32,
+    pub colors: PlayerColor,
+
+    /// Whether this is the local player.
+    pub local: bool,
+}
+
+// Lists connected players by frags, most first. Tied players stay in player
+// order. `local_id` is the player number of the local player, if known.
+fn ranked_scores(player_info: &[Option<PlayerInfo>], local_id: Option<usize>) -> Vec<ScoreEntry> {
+    let mut scores: Vec<ScoreEntry> = player_info
+        .iter()
+        .enumerate()
+        .filter_map(|(id, info)| info.as_ref().map(|info| (id, info)))
+        // players who leave keep their slot but lose their name
+        .filter(|(_, info)| !info.name.is_empty())
+        .map(|(id, info)| ScoreEntry {
+            name: info.name.clone(),
+            frags: info.frags,
+            colors: info.colors,
+            local: Some(id) == local_id,
+        })
+        .collect();
+
+    scores.sort_by(|a, b| b.frags.cmp(&a.frags));
+    scores
+}
+
+/// A marker pointing toward the source of recent damage.
+#[derive(Clone, Copy, Debug)]
+pub struct DamageIndicator {
+    /// The direction of the damage source relative to the view, measured
+    /// counterclockwise from straight ahead.
+    pub angle: Deg<f32>,
+
+    /// The fraction of the indicator's lifetime remaining, in `(0, 1]`.
+    pub remaining: f32,
+}
+
+/// Strafing angles shown under the speedometer with `scr_speed 2`.
+#[derive(Clone, Copy, Debug)]
+pub struct StrafeAngles {
+    /// The direction of travel relative to the view, measured counterclockwise
+    /// from straight ahead.
+    pub current: Deg<f32>,
+
+    /// The angle between the view and the direction of travel at which air
+    /// strafing gains speed fastest.
+    pub optimal: Deg<f32>,
+}
+
+/// How the crosshair is drawn, selected by `crosshair`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum Crosshair {
+    /// No crosshair.
+    None = 0,
+
+    /// The `+` character from the console font.
+    Character = 1,
+
+    /// The image in `gfx/crosshair.lmp`, or the character if there is none.
+    Image = 2,
+}
+
+impl Crosshair {
+    /// Returns the crosshair corresponding to the given value of `crosshair`.
+    ///
+    /// Unknown values draw the character.
+    pub fn from_cvar(value: f32) -> Crosshair {
+        Crosshair::from_i32(value as i32).unwrap_or(Crosshair::Character)
+    }
+}
+
+/// The entity under the crosshair, reported by `identify` and `+id`.
+#[derive(Clone, Debug)]
+pub struct EntityInfo {
+    /// The entity's index on the server.
+    pub entity_id: usize,
+    pub model_name: String,
+    pub frame_id: usize,
+    pub skin_id: usize,
+    pub effects: EntityEffects,
+}
+
+impl std::fmt::Display for EntityInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "#{} {} frame {} skin {} effects {:#x}",
+            self.entity_id,
+            self.model_name,
+            self.frame_id,
+            self.skin_id,
+            self.effects.bits()
+        )
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum IntermissionKind {
     Intermission,
@@ -120,6 +297,9 @@ struct ClientChannel {
     ent_id: usize,
     ent_channel: i8,
     channel: Channel,
+
+    // fixed position for sounds that don't follow an entity
+    origin: Option<Vector3<f32>>,
 }
 
 struct Mixer {
@@ -208,6 +388,33 @@ impl Mixer {
             ent_id,
             ent_channel,
             channel: new_channel,
+            origin: None,
+        })
+    }
+
+    /// Starts a sound at a fixed point rather than on an entity.
+    ///
+    /// Used for temp entity effects like impacts and explosions.
+    pub fn start_sound_at(
+        &mut self,
+        src: AudioSource,
+        time: Duration,
+        origin: Vector3<f32>,
+        volume: f32,
+        attenuation: f32,
+        listener: &Listener,
+    ) {
+        // attached to the world on the automatic channel, so it never replaces another sound
+        let chan_id = self.find_free_channel(0, 0);
+        let new_channel = Channel::new(self.audio_device.clone());
+
+        new_channel.play(src, origin, listener, volume, attenuation);
+        self.channels[chan_id] = Some(ClientChannel {
+            start_time: time,
+            ent_id: 0,
+            ent_channel: 0,
+            channel: new_channel,
+            origin: Some(origin),
         })
     }
 }
@@ -223,6 +430,8 @@ struct ClientState {
 
     // audio source precache
     sounds: Vec<AudioSource>,
+    // names of the precached sounds, by ID
+    sound_names: Vec<String>,
 
     // ambient sounds (infinite looping, static position)
     static_sounds: Vec<StaticSound>,
@@ -237,6 +446,10 @@ struct ClientState {
     beams: [Option<Beam>; MAX_BEAMS],
     // particle effects
     particles: Particles,
+    // locally predicted nails and rockets (see client::projectile)
+    projectiles: ProjectilePredictor,
+    // the player's weapon, drawn over the view
+    view_model: ClientEntity,
 
     // visible entities, rebuilt per-frame
     visible_entity_ids: Vec<usize>,
@@ -247,6 +460,7 @@ struct ClientState {
     stats: [i32; MAX_STATS],
 
     max_players: usize,
+    game_type: GameType,
     player_info: [Option<PlayerInfo>; net::MAX_CLIENTS],
 
     // the last two timestamps sent by the server (for lerping)
@@ -260,6 +474,9 @@ struct ClientState {
     items: ItemFlags,
     item_get_time: [Duration; net::MAX_ITEMS],
     face_anim_time: Duration,
+    damage_sources: Vec<(Duration, Vector3<f32>)>,
+    hit_confirm_time: Option<Duration>,
+    center_print: Option<(String, Duration)>,
     color_shifts: [Rc<RefCell<ColorShift>>; 4],
     // prev_color_shifts: [ColorShift; 4],
     view: View,
@@ -273,9 +490,17 @@ struct ClientState {
     // drift_move: f32,
     // last_stop: f64,
 
-    // paused: bool,
+    // set by the server with svc_setpause
+    paused: bool,
     on_ground: bool,
     in_water: bool,
+
+    // contents of the BSP leaf containing the view origin
+    view_contents: bsp::BspLeafContents,
+
+    // the first entity the server set the view to, which is the local player
+    player_entity_id: Option<usize>,
+
     intermission: Option<IntermissionKind>,
     start_time: Duration,
     completion_time: Option<Duration>,
@@ -283,14 +508,41 @@ struct ClientState {
     // level_name: String,
     // server_info: ServerInfo,
 
+    // short name of the current map and the level name sent by the server
+    map_name: String,
+    level_name: String,
+
+    // sky box named by the worldspawn entity, if any
+    sky_name: Option<String>,
+
+    // digest of the map file, if it was computed for the mesh cache
+    map_digest: Option<MapDigest>,
+
+    // encoding of coordinates and angles, as given by the server info
+    protocol: Protocol,
+
     // worldmodel: Model,
     mixer: Mixer,
     listener: Listener,
+
+    // sounds played by temp entities, loaded on first use (None if loading failed)
+    temp_entity_sounds: HashMap<&'static str, Option<AudioSource>>,
+
+    // low health heartbeat (see Client::update_heartbeat). The sound is loaded
+    // on first use and kept with its name so a change of hud_lowhealth_sound
+    // reloads it (None if loading failed).
+    heartbeat_sound: Option<(String, Option<AudioSource>)>,
+    heartbeat_time: Option<Duration>,
+    heartbeat_period: Duration,
 }
 
 impl ClientState {
     // TODO: add parameter for number of player slots and reserve them in entity list
-    pub fn new(vfs: Rc<Vfs>, audio_device: Rc<rodio::Device>) -> Result<ClientState, ClientError> {
+    pub fn new(
+        vfs: Rc<Vfs>,
+        audio_device: Rc<rodio::Device>,
+        max_particles: usize,
+    ) -> Result<ClientState, ClientError> {
         Ok(ClientState {
             vfs: vfs.clone(),
             models: vec![Model::none()],
@@ -300,17 +552,21 @@ impl ClientState {
                     name: "misc/null.wav".to_string(),
                 },
             )?],
+            sound_names: vec!["misc/null.wav".to_string()],
             static_sounds: Vec::new(),
             entities: Vec::new(),
             static_entities: Vec::new(),
             temp_entities: Vec::new(),
             lights: Lights::with_capacity(MAX_LIGHTS),
             beams: [None; MAX_BEAMS],
-            particles: Particles::with_capacity(MAX_PARTICLES),
+            particles: Particles::with_capacity(max_particles),
+            projectiles: ProjectilePredictor::new(),
+            view_model: ClientEntity::uninitialized(),
             visible_entity_ids: Vec::new(),
             light_styles: HashMap::new(),
             stats: [0; MAX_STATS],
             max_players: 0,
+            game_type: GameType::CoOp,
             // TODO: for the love of god can the lang team hurry up (https://github.com/rust-lang/rfcs/pull/2203)
             // this might make more sense as a different data structure anyway who knows
             player_info: [
@@ -376,15 +632,30 @@ impl ClientState {
             ],
             view: View::new(),
             face_anim_time: Duration::zero(),
+            damage_sources: Vec::new(),
+            hit_confirm_time: None,
+            center_print: None,
             msg_velocity: [Vector3::zero(), Vector3::zero()],
             velocity: Vector3::zero(),
+            paused: false,
             on_ground: false,
             in_water: false,
+            view_contents: bsp::BspLeafContents::Empty,
+            player_entity_id: None,
             intermission: None,
             start_time: Duration::zero(),
             completion_time: None,
+            map_name: String::new(),
+            level_name: String::new(),
+            sky_name: None,
+            map_digest: None,
+            protocol: Protocol::NETQUAKE,
             mixer: Mixer::new(audio_device.clone()),
             listener: Listener::new(),
+            temp_entity_sounds: HashMap::new(),
+            heartbeat_sound: None,
+            heartbeat_time: None,
+            heartbeat_period: Duration::seconds(1),
         })
     }
 
@@ -406,24 +677,198 @@ impl ClientState {
         self.listener.set_right_ear(right);
     }
 
-    fn update_sound_spatialization(&self) {
+    // Returns the point where the line from `start` to `end` first enters solid
+    // world geometry, or `None` if it doesn't within `max_steps` non-solid leaf
+    // transitions. Trace errors count as unobstructed.
+    fn trace_solid(
+        &self,
+        start: Vector3<f32>,
+        end: Vector3<f32>,
+        max_steps: usize,
+    ) -> Option<Vector3<f32>> {
+        let hull = match self.models.get(1).map(|m| m.kind()) {
+            Some(ModelKind::Brush(ref bmodel)) => bmodel.hull(0).ok()?,
+            _ => return None,
+        };
+
+        let mut start = start;
+        for _ in 0..max_steps {
+            let trace = hull.trace(start, end).ok()?;
+            if trace.is_terminal() {
+                return None;
+            }
+
+            // the trace stopped at a leaf boundary; see what's on the other side
+            let remaining = end - trace.end_point();
+            if remaining.magnitude() < 0.1 {
+                return None;
+            }
+
+            let next = trace.end_point() + remaining.normalize() * 0.1;
+            match hull.contents_at_point(next).ok()? {
+                bsp::BspLeafContents::Solid => return Some(trace.end_point()),
+                _ => start = next,
+            }
+        }
+
+        None
+    }
+
+    /// Returns true if solid world geometry lies between the listener and `origin`.
+    fn sound_occluded(&self, origin: Vector3<f32>) -> bool {
+        // number of non-solid leaf transitions to follow before giving up
+        const MAX_TRACE_STEPS: usize = 8;
+
+        self.trace_solid(self.listener.origin(), origin, MAX_TRACE_STEPS)
+            .is_some()
+    }
+
+    // Returns the point where the line from `start` to `end` first enters
+    // solid world geometry, or `end` if it doesn't.
+    fn trace_world(&self, start: Vector3<f32>, end: Vector3<f32>) -> Vector3<f32> {
+        // number of non-solid leaf transitions to follow before giving up
+        const MAX_TRACE_STEPS: usize = 64;
+
+        self.trace_solid(start, end, MAX_TRACE_STEPS).unwrap_or(end)
+    }
+
+    // Finds the nearest visible entity whose bounding box is hit by a ray from
+    // the view along the view direction, stopping at world geometry.
+    fn identify_target(&self) -> Option<EntityInfo> {
+        // how far to look for entities
+        const MAX_IDENTIFY_DISTANCE: f32 = 4096.0;
+
+        let view_id = self.view.entity_id();
+        let view_ent = self.entities.get(view_id)?;
+        let start = view_ent.origin
+            + Vector3::new(0.0, 0.0, self.view.view_height() + self.view.step_offset());
+        let angles = self.view.input_angles();
+        let dir = projectile::forward_vector(angles.pitch, angles.yaw);
+        let end = self.trace_world(start, start + dir * MAX_IDENTIFY_DISTANCE);
+        let max_dist = (end - start).magnitude();
+
+        let mut nearest: Option<(f32, usize)> = None;
+        for &id in self.visible_entity_ids.iter() {
+            if id == view_id {
+                continue;
+            }
+
+            let ent = &self.entities[id];
+            let model = match self.models.get(ent.model_id) {
+                Some(m) if ent.model_id != 0 => m,
+                _ => continue,
+            };
+
+            let dist = match ray_box_distance(
+                start,
+                dir,
+                ent.origin + model.min(),
+                ent.origin + model.max(),
+            ) {
+                Some(d) if d <= max_dist => d,
+                _ => continue,
+            };
+
+            if nearest.map_or(true, |(d, _)| dist < d) {
+                nearest = Some((dist, id));
+            }
+        }
+
+        let (_, id) = nearest?;
+        let ent = &self.entities[id];
+        Some(EntityInfo {
+            entity_id: id,
+            model_name: self.models[ent.model_id].name().to_string(),
+            frame_id: ent.frame_id,
+            skin_id: ent.skin_id,
+            effects: ent.effects,
+        })
+    }
+
+    fn update_sound_spatialization(&self, occlusion: bool) {
         self.update_listener();
 
         // update entity sounds
         for opt_chan in self.mixer.channels.iter() {
             if let Some(ref chan) = opt_chan {
                 if chan.channel.in_use() {
-                    chan.channel
-                        .update(self.entities[chan.ent_id].origin, &self.listener);
+                    let origin = chan
+                        .origin
+                        .unwrap_or_else(|| self.entities[chan.ent_id].origin);
+                    let occluded = occlusion && self.sound_occluded(origin);
+                    chan.channel.update(origin, &self.listener, occluded);
                 }
             }
         }
 
         // update static sounds
         for ss in self.static_sounds.iter() {
-            ss.update(&self.listener);
+            let occluded = occlusion && self.sound_occluded(ss.origin());
+            ss.update(&self.listener, occluded);
+        }
+    }
+}
+
+/// Input latency measurements reported by `cl_inputtiming`.
+#[derive(Default)]
+struct InputTiming {
+    // start of the current reporting interval
+    start: Option<DateTime<Utc>>,
+    moves: u32,
+    total_ms: f32,
+    max_ms: f32,
+}
+
+impl InputTiming {
+    /// Records the latency of a move command sent at `now`.
+    ///
+    /// Returns a summary of the interval once a second has passed since it began.
+    fn record(&mut self, now: DateTime<Utc>, latency: Duration) -> Option<String> {
+        let start = *self.start.get_or_insert(now);
+        let ms = engine::duration_to_f32(latency) * 1000.0;
+        self.moves += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+
+        if now.signed_duration_since(start) < Duration::seconds(1) {
+            return None;
         }
+
+        let summary = format!(
+            "input latency: {:.1} ms avg, {:.1} ms max ({} moves)",
+            self.total_ms / self.moves as f32,
+            self.max_ms,
+            self.moves
+        );
+        *self = InputTiming::default();
+        Some(summary)
+    }
+}
+
+/// Commands whose text is sent to the server for it to handle.
+const FORWARD_CMDS: &[&str] = &["say", "say_team"];
+
+/// Registers placeholders for the commands a connected client forwards to the server.
+///
+/// These report that there is no connection. Connecting replaces them, and dropping the client
+/// puts them back.
+pub fn register_offline_cmds(
+    cmds: &mut CmdRegistry,
+    output: Rc<RefCell<ConsoleOutput>>,
+) -> Result<(), ConsoleError> {
+    for &name in FORWARD_CMDS {
+        let output = output.clone();
+        cmds.insert_or_replace(
+            name,
+            Box::new(move |_| {
+                output
+                    .borrow_mut()
+                    .print(l10n::tr_args("client.not_connected", &[name]))
+            }),
+        )?;
     }
+
+    Ok(())
 }
 
 pub struct Client {
@@ -433,17 +878,388 @@ pub struct Client {
     console: Rc<RefCell<Console>>,
     audio_device: Rc<rodio::Device>,
 
+    server_addr: SocketAddr,
     qsock: QSocket,
     compose: Vec<u8>,
-    signon: Rc<Cell<SignOnStage>>,
+    conn_state: Rc<Cell<ConnectionState>>,
+    disconnect_requested: Rc<Cell<bool>>,
+    center_view_requested: Rc<Cell<bool>>,
+    stats_requested: Rc<Cell<bool>>,
+    meminfo_requested: Rc<Cell<bool>>,
+    identify_requested: Rc<Cell<bool>>,
+    identify_held: Rc<Cell<bool>>,
+    camera_bookmarks: Rc<RefCell<CameraBookmarks>>,
+
+    // string commands waiting to be sent to the server
+    forward_cmds: Rc<RefCell<Vec<String>>>,
+
+    // time since the last move command was sent, and buttons and impulses
+    // seen since then, for sending at cl_netfps
+    move_accum: Duration,
+    pending_buttons: ButtonFlags,
+    pending_impulse: u8,
+
+    input_timing: InputTiming,
+
+    csqc: Csqc,
+
+    #[cfg(feature = "music")]
+    music: MusicPlayer,
+
+    // resources still to be loaded for the current map, if any
+    precache: Option<Precache>,
 
     state: ClientState,
 }
 
+/// How long to wait for the reply to an rcon command.
+const RCON_TIMEOUT_MS: i64 = 2500;
+
+/// How long to wait for the response to a connectionless request.
+#[cfg(feature = "qw")]
+const OOB_TIMEOUT_MS: i64 = 2500;
+
+/// An rcon command sent to a server, waiting for its reply.
+///
+/// Replies are checked for once per frame with `poll`, so the client keeps running while the
+/// server responds.
+pub struct RconRequest {
+    con_sock: ConnectSocket,
+    server_addr: SocketAddr,
+    elapsed: Duration,
+}
+
+impl RconRequest {
+    /// Sends a console command to the server at `server_addr`.
+    ///
+    /// The server only executes the command if `password` matches its `rcon_password`.
+    pub fn send<S, T>(
+        server_addr: SocketAddr,
+        password: S,
+        command: T,
+    ) -> Result<RconRequest, Error>
+    where
+        S: AsRef<str>,
+        T: AsRef<str>,
+    {
+        let mut con_sock = ConnectSocket::bind("0.0.0.0:0")?;
+        con_sock.set_nonblocking(true)?;
+        con_sock.send_request(Request::rcon(password, command), server_addr)?;
+
+        Ok(RconRequest {
+            con_sock,
+            server_addr,
+            elapsed: Duration::zero(),
+        })
+    }
+
+    /// Checks for the server's reply without blocking.
+    ///
+    /// Returns `None` while the reply hasn't arrived, and an error if it doesn't arrive within
+    /// `RCON_TIMEOUT_MS`.
+    pub fn poll(&mut self, frame_time: Duration) -> Result<Option<String>, Error> {
+        self.elapsed = self.elapsed + frame_time;
+
+        loop {
+            match self.con_sock.recv_response(None)? {
+                Some((Response::Rcon(ResponseRcon { message }), remote))
+                    if remote == self.server_addr =>
+                {
+                    return Ok(Some(message))
+                }
+
+                // ignore stray packets
+                Some((_, remote)) => debug!("Ignoring unexpected packet from {}", remote),
+
+                None if self.elapsed > Duration::milliseconds(RCON_TIMEOUT_MS) => {
+                    Err(ClientErrorKind::NoResponse)?
+                }
+
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// A connectionless request sent to a server, waiting for its response.
+///
+/// Like `RconRequest`, the response is checked for once per frame with `poll`.
+#[cfg(feature = "qw")]
+pub struct OobQuery {
+    oob_sock: OobSocket,
+    server_addr: SocketAddr,
+    elapsed: Duration,
+}
+
+#[cfg(feature = "qw")]
+impl OobQuery {
+    /// Sends `request` to the server at `server_addr`.
+    pub fn send(server_addr: SocketAddr, request: &OobRequest) -> Result<OobQuery, Error> {
+        let oob_sock = OobSocket::bind("0.0.0.0:0")?;
+        oob_sock.set_nonblocking(true)?;
+        oob_sock.send_request(request, server_addr)?;
+
+        Ok(OobQuery {
+            oob_sock,
+            server_addr,
+            elapsed: Duration::zero(),
+        })
+    }
+
+    /// Checks for the server's response without blocking.
+    ///
+    /// Returns `None` while the response hasn't arrived, and an error if it doesn't arrive within
+    /// `OOB_TIMEOUT_MS`.
+    pub fn poll(&mut self, frame_time: Duration) -> Result<Option<OobResponse>, Error> {
+        self.elapsed = self.elapsed + frame_time;
+
+        loop {
+            match self.oob_sock.recv_response(None)? {
+                Some((response, remote)) if remote == self.server_addr => {
+                    return Ok(Some(response))
+                }
+
+                // ignore stray packets
+                Some((_, remote)) => debug!("Ignoring unexpected packet from {}", remote),
+
+                None if self.elapsed > Duration::milliseconds(OOB_TIMEOUT_MS) => {
+                    Err(ClientErrorKind::NoResponse)?
+                }
+
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Returns the console text for a connectionless response.
+#[cfg(feature = "qw")]
+pub fn oob_response_text(response: &OobResponse) -> String {
+    match *response {
+        OobResponse::Ack => l10n::tr("oob.ack"),
+        OobResponse::Print(ref text) => text.trim_end().to_owned(),
+        OobResponse::Info { ref info, .. } => info
+            .iter()
+            .map(|(key, value)| l10n::tr_args("oob.info_entry", &[key, value]))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Returns the sky box named by the worldspawn entity's `sky` or `skyname` key.
+fn worldspawn_sky(ent_string: &str) -> Option<String> {
+    let (_, entities) = parse::entities(ent_string).ok()?;
+    let worldspawn = entities
+        .into_iter()
+        .find(|ent| ent.get("classname") == Some(&"worldspawn"))?;
+
+    worldspawn
+        .get("sky")
+        .or_else(|| worldspawn.get("skyname"))
+        .map(|name| name.to_string())
+}
+
+/// Returns the distance along the ray from `origin` in direction `dir` at which it first enters
+/// the axis-aligned box from `min` to `max`, or `None` if it misses.
+///
+/// A ray starting inside the box has distance 0.
+fn ray_box_distance(
+    origin: Vector3<f32>,
+    dir: Vector3<f32>,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+) -> Option<f32> {
+    let mut near = 0.0f32;
+    let mut far = std::f32::INFINITY;
+
+    for i in 0..3 {
+        if dir[i] == 0.0 {
+            if origin[i] < min[i] || origin[i] > max[i] {
+                return None;
+            }
+            continue;
+        }
+
+        let t0 = (min[i] - origin[i]) / dir[i];
+        let t1 = (max[i] - origin[i]) / dir[i];
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+        if near > far {
+            return None;
+        }
+    }
+
+    Some(near)
+}
+
+/// Returns the sound made by a nail hitting a wall.
+///
+/// As in the original engine, nails usually tink, but one time in five they
+/// ricochet instead.
+fn spike_impact_sound<R>(rng: &mut R) -> &'static str
+where
+    R: Rng,
+{
+    if rng.gen_range(0, 5) != 0 {
+        return "weapons/tink1.wav";
+    }
+
+    match rng.gen_range(0, 4) {
+        1 => "weapons/ric1.wav",
+        2 => "weapons/ric2.wav",
+        _ => "weapons/ric3.wav",
+    }
+}
+
+/// Formats a byte count with a binary unit suffix, e.g. `1.5 MiB`.
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KiB", "MiB", "GiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} {}", bytes, UNITS[0]),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// Returns the offset added to the client clock when animating an entity's framegroups.
+///
+/// Models with `SyncType::Rand` start their animations at a random point within the first
+/// second so that e.g. neighboring torches don't flicker in unison.
+fn sync_base<R>(sync_type: SyncType, rng: &mut R) -> Duration
+where
+    R: Rng,
+{
+    match sync_type {
+        SyncType::Sync => Duration::zero(),
+        SyncType::Rand => Duration::milliseconds(rng.gen_range(0, 1000)),
+    }
+}
+
+/// Returns the dynamic light an entity's effects attach to it, if any.
+///
+/// As in the original engine, an entity has a single effect light, so a dim light overrides a
+/// bright light, which overrides a muzzle flash.
+fn effect_light<R>(
+    effects: EntityEffects,
+    origin: Vector3<f32>,
+    angles: Vector3<Deg<f32>>,
+    rng: &mut R,
+) -> Option<LightDesc>
+where
+    R: Rng,
+{
+    lazy_static! {
+        static ref MFLASH_DIMLIGHT_DISTRIBUTION: Uniform<f32> = Uniform::new(200.0, 232.0);
+        static ref BRIGHTLIGHT_DISTRIBUTION: Uniform<f32> = Uniform::new(400.0, 432.0);
+    }
+
+    if effects.contains(EntityEffects::DIM_LIGHT) {
+        Some(LightDesc {
+            origin,
+            init_radius: MFLASH_DIMLIGHT_DISTRIBUTION.sample(rng),
+            decay_rate: 0.0,
+            min_radius: None,
+            ttl: Duration::milliseconds(1),
+        })
+    } else if effects.contains(EntityEffects::BRIGHT_LIGHT) {
+        Some(LightDesc {
+            origin,
+            init_radius: BRIGHTLIGHT_DISTRIBUTION.sample(rng),
+            decay_rate: 0.0,
+            min_radius: None,
+            ttl: Duration::milliseconds(1),
+        })
+    } else if effects.contains(EntityEffects::MUZZLE_FLASH) {
+        // move the light up to the weapon and out to the muzzle
+        let (pitch, yaw) = (angles[0], angles[1]);
+        let forward = Vector3::new(
+            pitch.cos() * yaw.cos(),
+            pitch.cos() * yaw.sin(),
+            -pitch.sin(),
+        );
+
+        Some(LightDesc {
+            origin: origin
+                + Vector3::new(0.0, 0.0, MUZZLE_FLASH_HEIGHT)
+                + MUZZLE_FLASH_DISTANCE * forward,
+            init_radius: MFLASH_DIMLIGHT_DISTRIBUTION.sample(rng),
+            decay_rate: 0.0,
+            min_radius: Some(32.0),
+            ttl: Duration::milliseconds(100),
+        })
+    } else {
+        None
+    }
+}
+
 impl Client {
     /// Implements the `reconnect` command.
-    fn cmd_reconnect(signon: Rc<Cell<SignOnStage>>) -> Box<dyn Fn(&[&str])> {
-        Box::new(move |_| signon.set(SignOnStage::Not))
+    fn cmd_reconnect(
+        conn_state: Rc<Cell<ConnectionState>>,
+        output: Rc<RefCell<ConsoleOutput>>,
+    ) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |_| {
+            match conn_state
+                .get()
+                .transition(ConnectionState::SignOn(SignOnStage::Not))
+            {
+                Ok(next) => conn_state.set(next),
+                Err(e) => output
+                    .borrow_mut()
+                    .print(l10n::tr_args("connect.reconnect_failed", &[&e.to_string()])),
+            }
+        })
+    }
+
+    /// Implements the `disconnect` command.
+    fn cmd_disconnect(disconnect_requested: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |_| disconnect_requested.set(true))
+    }
+
+    /// Implements the `centerview` command.
+    fn cmd_centerview(center_view_requested: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |_| center_view_requested.set(true))
+    }
+
+    /// Implements the `stats` command.
+    fn cmd_stats(stats_requested: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |_| stats_requested.set(true))
+    }
+
+    /// Implements the `meminfo` command.
+    fn cmd_meminfo(meminfo_requested: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |_| meminfo_requested.set(true))
+    }
+
+    /// Implements the `identify` command.
+    fn cmd_identify(identify_requested: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |_| identify_requested.set(true))
+    }
+
+    /// Implements the `+id` and `-id` commands.
+    fn cmd_id(identify_held: Rc<Cell<bool>>, held: bool) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |_| identify_held.set(held))
+    }
+
+    /// Implements commands which are executed by the server, such as `say`.
+    fn cmd_forward(
+        name: &'static str,
+        forward_cmds: Rc<RefCell<Vec<String>>>,
+        output: Rc<RefCell<ConsoleOutput>>,
+    ) -> Box<dyn Fn(&[&str])> {
+        Box::new(move |args| match args.len() {
+            0 => output.borrow_mut().print(format!("usage: {} <text>", name)),
+            _ => forward_cmds
+                .borrow_mut()
+                .push(format!("{} \"{}\"", name, args.join(" "))),
+        })
     }
 
     pub fn connect<A>(
@@ -457,10 +1273,62 @@ impl Client {
     where
         A: ToSocketAddrs,
     {
-        // set up reconnect
-        let signon = Rc::new(Cell::new(SignOnStage::Not));
+        let output = console.borrow().output_handle();
+
+        // set up reconnect and disconnect
+        let conn_state = Rc::new(Cell::new(ConnectionState::Disconnected));
+        let disconnect_requested = Rc::new(Cell::new(false));
+        cmds.borrow_mut().insert_or_replace(
+            "reconnect",
+            Client::cmd_reconnect(conn_state.clone(), output.clone()),
+        )?;
+        cmds.borrow_mut().insert_or_replace(
+            "disconnect",
+            Client::cmd_disconnect(disconnect_requested.clone()),
+        )?;
+        let center_view_requested = Rc::new(Cell::new(false));
+        cmds.borrow_mut().insert_or_replace(
+            "centerview",
+            Client::cmd_centerview(center_view_requested.clone()),
+        )?;
+        let stats_requested = Rc::new(Cell::new(false));
+        cmds.borrow_mut()
+            .insert_or_replace("stats", Client::cmd_stats(stats_requested.clone()))?;
+        let meminfo_requested = Rc::new(Cell::new(false));
+        cmds.borrow_mut()
+            .insert_or_replace("meminfo", Client::cmd_meminfo(meminfo_requested.clone()))?;
+        let identify_requested = Rc::new(Cell::new(false));
         cmds.borrow_mut()
-            .insert_or_replace("reconnect", Client::cmd_reconnect(signon.clone()))?;
+            .insert_or_replace("identify", Client::cmd_identify(identify_requested.clone()))?;
+        let identify_held = Rc::new(Cell::new(false));
+        cmds.borrow_mut()
+            .insert_or_replace("+id", Client::cmd_id(identify_held.clone(), true))?;
+        cmds.borrow_mut()
+            .insert_or_replace("-id", Client::cmd_id(identify_held.clone(), false))?;
+        let camera_bookmarks = Rc::new(RefCell::new(CameraBookmarks::new()));
+        cmds.borrow_mut().insert_or_replace(
+            "cam_save",
+            bookmark::cmd_cam_save(conn_state.clone(), output.clone(), camera_bookmarks.clone()),
+        )?;
+        cmds.borrow_mut().insert_or_replace(
+            "cam_goto",
+            bookmark::cmd_cam_goto(conn_state.clone(), output.clone(), camera_bookmarks.clone()),
+        )?;
+        cmds.borrow_mut().insert_or_replace(
+            "cam_release",
+            bookmark::cmd_cam_release(conn_state.clone(), output.clone(), camera_bookmarks.clone()),
+        )?;
+        cmds.borrow_mut().insert_or_replace(
+            "cam_list",
+            bookmark::cmd_cam_list(conn_state.clone(), output.clone(), camera_bookmarks.clone()),
+        )?;
+        let forward_cmds = Rc::new(RefCell::new(Vec::new()));
+        for &name in FORWARD_CMDS {
+            cmds.borrow_mut().insert_or_replace(
+                name,
+                Client::cmd_forward(name, forward_cmds.clone(), output.clone()),
+            )?;
+        }
 
         let mut con_sock = ConnectSocket::bind("0.0.0.0:0")?;
         let server_addr = server_addrs
@@ -539,25 +1407,95 @@ impl Client {
         // we're done with the connection socket, so turn it into a QSocket with the new address
         let qsock = con_sock.into_qsocket(new_addr);
 
-        Ok(Client {
+        let max_particles =
+            particle::particle_capacity(cvars.borrow().get_value("cl_maxparticles")?);
+        let client = Client {
             vfs: vfs.clone(),
             cvars,
             cmds,
             console,
             audio_device: audio_device.clone(),
+            server_addr,
             qsock,
             compose: Vec::new(),
-            signon,
-            state: ClientState::new(vfs.clone(), audio_device.clone())?,
-        })
+            conn_state,
+            disconnect_requested,
+            center_view_requested,
+            stats_requested,
+            meminfo_requested,
+            identify_requested,
+            identify_held,
+            camera_bookmarks,
+            forward_cmds,
+            move_accum: Duration::zero(),
+            pending_buttons: ButtonFlags::empty(),
+            pending_impulse: 0,
+            input_timing: InputTiming::default(),
+            csqc: Csqc::load(&vfs),
+            #[cfg(feature = "music")]
+            music: MusicPlayer::new(audio_device.clone()),
+            precache: None,
+            state: ClientState::new(vfs.clone(), audio_device.clone(), max_particles)?,
+        };
+
+        client.set_connection_state(ConnectionState::Connecting)?;
+        client.run_event_hook("cl_onconnect")?;
+
+        Ok(client)
+    }
+
+    /// Ends the connection to the server.
+    ///
+    /// Does nothing if the client is already disconnected.
+    pub fn disconnect(&mut self) -> Result<(), Error> {
+        // there's no server to notify when stopping a demo
+        if self.conn_state.get() == ConnectionState::DemoPlayback {
+            #[cfg(feature = "music")]
+            self.music.stop();
+            self.set_connection_state(ConnectionState::Disconnected)?;
+            return Ok(());
+        }
+
+        if !self.conn_state.get().is_connected() {
+            return Ok(());
+        }
+
+        // let the server know we're leaving. this is sent unreliably since
+        // we won't be around to retransmit it
+        let mut msg = Vec::new();
+        ClientCmd::Disconnect.serialize(&mut msg, self.state.protocol)?;
+        if let Err(e) = self.qsock.send_msg_unreliable(&msg) {
+            warn!("Failed to send disconnect message: {}", e);
+        }
+
+        self.precache = None;
+        #[cfg(feature = "music")]
+        self.music.stop();
+        self.set_connection_state(ConnectionState::Disconnected)?;
+        self.run_event_hook("cl_ondisconnect")?;
+
+        Ok(())
     }
 
-    pub fn disconnect(&self) {
-        unimplemented!();
+    /// Returns the state of the client's connection to the server.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.conn_state.get()
+    }
+
+    fn set_connection_state(&self, next: ConnectionState) -> Result<(), ClientError> {
+        let current = self.conn_state.get();
+        let next = current.transition(next)?;
+
+        if current != next {
+            debug!("Connection state: {} -> {}", current, next);
+            self.conn_state.set(next);
+        }
+
+        Ok(())
     }
 
     pub fn add_cmd(&mut self, cmd: ClientCmd) -> Result<(), Error> {
-        cmd.serialize(&mut self.compose)?;
+        cmd.serialize(&mut self.compose, self.state.protocol)?;
 
         Ok(())
     }
@@ -575,12 +1513,51 @@ impl Client {
             })?)
     }
 
+    /// Stuffs the contents of an event hook cvar into the command buffer.
+    ///
+    /// Hooks allow users to run commands automatically when the client
+    /// connects, disconnects or starts a new map.
+    fn run_event_hook<S>(&self, name: S) -> Result<(), ClientError>
+    where
+        S: AsRef<str>,
+    {
+        let hook = self
+            .cvars
+            .borrow()
+            .get(name.as_ref())
+            .context(ClientErrorKind::Cvar {
+                name: name.as_ref().to_string(),
+            })?;
+
+        if !hook.trim().is_empty() {
+            debug!("{}: {}", name.as_ref(), hook);
+            self.console.borrow().stuff_hook_text(hook);
+        }
+
+        Ok(())
+    }
+
+    /// Passes server-stuffed text to the console.
+    ///
+    /// The console decides which of the commands the server may run (see `CmdSource::allows`).
+    fn handle_stuff_text(&self, text: String) -> Result<(), ClientError> {
+        self.console
+            .borrow()
+            .stuff_text_from(CmdSource::Server, text);
+
+        Ok(())
+    }
+
     pub fn handle_input(
         &mut self,
         game_input: &mut GameInput,
         frame_time: Duration,
     ) -> Result<(), Error> {
-        let mlook = game_input.action_state(Action::MLook);
+        let mlook = game_input.mouse_look(self.cvar_value("cl_freelook")? != 0.0);
+        if self.center_view_requested.replace(false) {
+            self.state.view.start_pitch_drift();
+        }
+
         self.state.view.handle_input(
             frame_time,
             game_input,
@@ -589,7 +1566,9 @@ impl Client {
             self.cvar_value("cl_anglespeedkey")?,
             self.cvar_value("cl_pitchspeed")?,
             self.cvar_value("cl_yawspeed")?,
+            self.cvar_value("v_centerspeed")?,
             self.mouse_vars()?,
+            self.joy_vars()?,
         );
 
         let cl_sidespeed = self.cvar_value("cl_sidespeed")?;
@@ -609,13 +1588,23 @@ impl Client {
                 - game_input.action_state(Action::MoveDown) as i32) as f32;
 
         let mut forwardmove = 0.0;
+        let cl_forwardspeed = self.cvar_value("cl_forwardspeed")?;
+        let cl_backspeed = self.cvar_value("cl_backspeed")?;
         if !game_input.action_state(Action::KLook) {
-            let cl_forwardspeed = self.cvar_value("cl_forwardspeed")?;
-            let cl_backspeed = self.cvar_value("cl_backspeed")?;
             forwardmove += cl_forwardspeed * game_input.action_state(Action::Forward) as i32 as f32;
             forwardmove -= cl_backspeed * game_input.action_state(Action::Back) as i32 as f32;
         }
 
+        // the movement stick is analog, so partial deflection moves more slowly
+        let (stick_x, stick_y) = game_input.move_axes();
+        sidemove += cl_sidespeed * stick_x;
+        forwardmove += stick_y
+            * if stick_y > 0.0 {
+                cl_forwardspeed
+            } else {
+                cl_backspeed
+            };
+
         if game_input.action_state(Action::Speed) {
             let cl_movespeedkey = self.cvar_value("cl_movespeedkey")?;
             sidemove *= cl_movespeedkey;
@@ -623,22 +1612,66 @@ impl Client {
             forwardmove *= cl_movespeedkey;
         }
 
+        // mouse movement that doesn't turn the view moves the player instead
+        let mouse_vars = self.mouse_vars()?;
+        let (mouse_x, mouse_y) = game_input.filtered_mouse_delta(mouse_vars.m_filter);
+        let strafe = game_input.action_state(Action::Strafe);
+        if strafe || (mouse_vars.lookstrafe && mlook) {
+            sidemove += mouse_vars.m_side * mouse_vars.sensitivity * mouse_x as f32;
+        }
+
+        if !mlook || strafe {
+            forwardmove -= mouse_vars.m_forward * mouse_vars.sensitivity * mouse_y as f32;
+        }
+
+        self.state.view.spring_pitch(
+            frame_time,
+            forwardmove.abs() >= cl_forwardspeed,
+            self.state.on_ground,
+            mlook,
+            mouse_vars.lookspring,
+            self.cvar_value("v_centermove")?,
+        );
+
         let mut button_flags = ButtonFlags::empty();
 
         if game_input.action_state(Action::Attack) {
             button_flags |= ButtonFlags::ATTACK;
+
+            if self.cvar_value("cl_predict_projectiles")? != 0.0 {
+                self.predict_projectile();
+            }
         }
 
         if game_input.action_state(Action::Jump) {
             button_flags |= ButtonFlags::JUMP;
         }
 
-        if !mlook {
-            // TODO: IN_Move (mouse / joystick / gamepad)
+        // hold on to brief button presses and impulses until the next move is sent
+        self.pending_buttons |= button_flags;
+        if game_input.impulse() != 0 {
+            self.pending_impulse = game_input.impulse();
+        }
+
+        // with cl_netfps set, moves are sent at that rate no matter how fast we render
+        self.move_accum = self.move_accum + frame_time;
+        let cl_netfps = self.cvar_value("cl_netfps")?;
+        if cl_netfps > 0.0 {
+            let interval = engine::duration_from_f32(1.0 / cl_netfps);
+            if self.move_accum < interval {
+                game_input.refresh()?;
+                return Ok(());
+            }
+
+            // carry over the remainder, but don't try to catch up after a long frame
+            self.move_accum = (self.move_accum - interval).min(interval);
+        } else {
+            self.move_accum = Duration::zero();
         }
 
         let send_time = self.state.msg_times[0];
-        // send "raw" angles without any pitch/roll from movement or damage
+        // send "raw" angles without any pitch/roll from movement or damage, sampled after this
+        // frame's input has been applied
         let angles = self.state.view.input_angles();
         let move_cmd = ClientCmd::Move {
             send_time,
@@ -646,15 +1679,28 @@ impl Client {
             fwd_move: forwardmove as i16,
             side_move: sidemove as i16,
             up_move: upmove as i16,
-            button_flags,
-            impulse: game_input.impulse(),
+            button_flags: self.pending_buttons,
+            impulse: self.pending_impulse,
         };
+        self.pending_buttons = ButtonFlags::empty();
+        self.pending_impulse = 0;
         // debug!("Sending move command: {:?}", move_cmd);
 
         let mut msg = Vec::new();
-        move_cmd.serialize(&mut msg)?;
+        move_cmd.serialize(&mut msg, self.state.protocol)?;
         self.qsock.send_msg_unreliable(&msg)?;
 
+        // measure how long the oldest input in this move waited to be sent
+        if let Some(input_time) = game_input.take_input_time() {
+            if self.cvar_value("cl_inputtiming")? != 0.0 {
+                let now = Utc::now();
+                let latency = now.signed_duration_since(input_time);
+                if let Some(summary) = self.input_timing.record(now, latency) {
+                    self.console.borrow().output_mut().print(summary);
+                }
+            }
+        }
+
         // clear mouse and impulse
         game_input.refresh()?;
 
@@ -750,9 +1796,9 @@ impl Client {
     }
 
     pub fn parse_server_msg(&mut self) -> Result<(), Error> {
-        let msg = self.qsock.recv_msg(match self.signon.get() {
+        let msg = self.qsock.recv_msg(match self.conn_state.get() {
             // if we're in the game, don't block waiting for messages
-            SignOnStage::Done => BlockingMode::NonBlocking,
+            ConnectionState::Active => BlockingMode::NonBlocking,
 
             // otherwise, give the server some time to respond
             // TODO: might make sense to make this a future or something
@@ -766,7 +1812,7 @@ impl Client {
 
         let mut reader = BufReader::new(msg.as_slice());
 
-        while let Some(cmd) = ServerCmd::deserialize(&mut reader)? {
+        while let Some(cmd) = ServerCmd::deserialize(&mut reader, self.state.protocol)? {
             match cmd {
                 // TODO: have an error for this instead of panicking
                 // once all other commands have placeholder handlers, just error
@@ -775,15 +1821,22 @@ impl Client {
 
                 ServerCmd::NoOp => (),
 
+                #[cfg(feature = "music")]
+                ServerCmd::CdTrack { track, loop_ } => {
+                    if let Err(e) = self.music.play(&self.vfs, track, loop_ != 0) {
+                        warn!("Couldn't play CD track {}: {}", track, e);
+                    }
+                }
+
+                #[cfg(not(feature = "music"))]
                 ServerCmd::CdTrack { .. } => {
-                    // TODO: play CD track
-                    warn!("CD tracks not yet implemented");
+                    debug!("Ignoring CD track (built without music support)");
                 }
 
                 ServerCmd::CenterPrint { text } => {
-                    // TODO: print to center of screen
-                    warn!("Center print not yet implemented!");
-                    println!("{}", text);
+                    // keep a copy in the console once it's gone from the screen
+                    self.console.borrow().output_mut().print(&text);
+                    self.state.center_print = Some((text, self.state.time));
                 }
 
                 ServerCmd::ClientData {
@@ -871,6 +1924,15 @@ impl Client {
                 } => {
                     self.state.face_anim_time = self.state.time + Duration::milliseconds(200);
 
+                    let time = self.state.time;
+                    let lifetime = Duration::milliseconds(DAMAGE_INDICATOR_DURATION_MS);
+                    let sources = &mut self.state.damage_sources;
+                    sources.retain(|(t, _)| time - *t < lifetime);
+                    if sources.len() >= MAX_DAMAGE_INDICATORS {
+                        sources.remove(0);
+                    }
+                    sources.push((time, source));
+
                     let dmg_factor = (armor + blood).min(20) as f32 / 2.0;
                     let mut cshift =
                         self.state.color_shifts[ColorShiftCode::Damage as usize].borrow_mut();
@@ -904,14 +1966,17 @@ impl Client {
                     );
                 }
 
-                ServerCmd::Disconnect => self.disconnect(),
+                ServerCmd::Disconnect => {
+                    self.disconnect()?;
+
+                    // anything after this is meaningless
+                    return Ok(());
+                }
 
                 ServerCmd::FastUpdate(ent_update) => {
                     // first update signals the last sign-on stage
-                    if self.signon.get() == SignOnStage::Begin {
-                        self.signon.set(SignOnStage::Done);
-                        let signon = self.signon.get();
-                        self.handle_signon(signon)?;
+                    if self.conn_state.get() == ConnectionState::SignOn(SignOnStage::Begin) {
+                        self.handle_signon(SignOnStage::Done)?;
                     }
 
                     let ent_id = ent_update.ent_id as usize;
@@ -936,6 +2001,8 @@ impl Client {
                     }
 
                     self.state.entities[ent_id].update(self.state.msg_times, ent_update);
+                    self.csqc
+                        .entity_update(ent_id, &self.state.entities[ent_id]);
 
                     if self.state.entities[ent_id].model_changed() {
                         let model = &self.state.models[self.state.entities[ent_id].model_id()];
@@ -943,10 +2010,8 @@ impl Client {
                             // don't bother updating if it has no model
                             &ModelKind::None => (),
                             _ => {
-                                self.state.entities[ent_id].sync_base = match model.sync_type() {
-                                    SyncType::Sync => Duration::zero(),
-                                    SyncType::Rand => unimplemented!(), // TODO
-                                }
+                                self.state.entities[ent_id].sync_base =
+                                    sync_base(model.sync_type(), &mut rand::thread_rng());
                             }
                         }
                     }
@@ -959,7 +2024,7 @@ impl Client {
                             ent_id,
                         );
 
-                        // TODO: set player custom colormaps
+                        // player skins are recolored in relink_entities
                     }
                 }
 
@@ -974,7 +2039,10 @@ impl Client {
                     self.state.completion_time = Some(self.state.time);
                 }
                 ServerCmd::KilledMonster => {
-                    self.state.stats[ClientStat::KilledMonsters as usize] += 1
+                    self.state.stats[ClientStat::KilledMonsters as usize] += 1;
+
+                    // NQ doesn't report hits, so treat kills as confirmation
+                    self.state.hit_confirm_time = Some(self.state.time);
                 }
 
                 ServerCmd::LightStyle { id, value } => {
@@ -1007,8 +2075,7 @@ impl Client {
                 }
 
                 ServerCmd::Print { text } => {
-                    // TODO: print to in-game console
-                    println!("{}", text);
+                    self.console.borrow().output_mut().print(text);
                 }
 
                 ServerCmd::ServerInfo {
@@ -1054,8 +2121,13 @@ impl Client {
 
                     debug!("Set view entity to {}", ent_id);
                     self.state.view.set_entity_id(new_id);
+                    if self.state.player_entity_id.is_none() {
+                        self.state.player_entity_id = Some(new_id);
+                    }
                 }
 
+                ServerCmd::SetPause { paused } => self.state.paused = paused,
+
                 ServerCmd::SignOnStage { stage } => self.handle_signon(stage)?,
 
                 ServerCmd::Sound {
@@ -1122,17 +2194,29 @@ impl Client {
                         self.state.static_entities.len() < MAX_STATIC_ENTITIES,
                         "too many static entities"
                     );
-                    self.state
-                        .static_entities
-                        .push(ClientEntity::from_baseline(EntityState {
-                            origin,
-                            angles,
-                            model_id: model_id as usize,
-                            frame_id: frame_id as usize,
-                            colormap,
-                            skin_id: skin_id as usize,
-                            effects: EntityEffects::empty(),
-                        }));
+                    let mut ent = ClientEntity::from_baseline(EntityState {
+                        origin,
+                        angles,
+                        model_id: model_id as usize,
+                        frame_id: frame_id as usize,
+                        colormap,
+                        skin_id: skin_id as usize,
+                        effects: EntityEffects::empty(),
+                    });
+
+                    // static entities never receive updates, so framegroups (torches, flames)
+                    // animate from the client clock alone
+                    if let Some(model) = self.state.models.get(ent.model_id) {
+                        match model.kind() {
+                            ModelKind::None => (),
+                            _ => {
+                                ent.sync_base =
+                                    sync_base(model.sync_type(), &mut rand::thread_rng())
+                            }
+                        }
+                    }
+
+                    self.state.static_entities.push(ent);
                 }
 
                 ServerCmd::SpawnStaticSound {
@@ -1155,7 +2239,7 @@ impl Client {
                     self.spawn_temp_entity(self.state.time, &temp_entity)
                 }
 
-                ServerCmd::StuffText { text } => self.console.borrow_mut().stuff_text(text),
+                ServerCmd::StuffText { text } => self.handle_stuff_text(text)?,
 
                 ServerCmd::Time { time } => {
                     self.state.msg_times[1] = self.state.msg_times[0];
@@ -1206,6 +2290,14 @@ impl Client {
                                 info.frags,
                                 new_frags
                             );
+
+                            // player entities are numbered from 1
+                            if player_id + 1 == self.state.view.entity_id()
+                                && new_frags as i32 > info.frags
+                            {
+                                self.state.hit_confirm_time = Some(self.state.time);
+                            }
+
                             info.frags = new_frags as i32;
                         }
                         None => {
@@ -1250,7 +2342,7 @@ impl Client {
                 }
 
                 ServerCmd::Version { version } => {
-                    if version != net::PROTOCOL_VERSION as i32 {
+                    if Protocol::from_version(version).is_none() {
                         // TODO: handle with an error
                         error!(
                             "Incompatible server version: server's is {}, client's is {}",
@@ -1274,11 +2366,14 @@ impl Client {
     fn handle_signon(&mut self, stage: SignOnStage) -> Result<(), Error> {
         match stage {
             SignOnStage::Not => (), // TODO this is an error (invalid value)
-            SignOnStage::Prespawn => {
-                self.add_cmd(ClientCmd::StringCmd {
+            SignOnStage::Prespawn => match self.precache {
+                // the server starts sending the level once we answer, so wait
+                // until everything is loaded
+                Some(ref mut precache) => precache.prespawn_requested = true,
+                None => self.add_cmd(ClientCmd::StringCmd {
                     cmd: String::from("prespawn"),
-                })?;
-            }
+                })?,
+            },
             SignOnStage::ClientInfo => {
                 // TODO: fill in client info here
                 self.add_cmd(ClientCmd::StringCmd {
@@ -1301,10 +2396,11 @@ impl Client {
                 debug!("Signon complete");
                 // TODO: end load screen
                 self.state.start_time = self.state.time;
+                self.run_event_hook("cl_onmapstart")?;
             }
         }
 
-        self.signon.set(stage);
+        self.set_connection_state(ConnectionState::from_signon(stage))?;
 
         Ok(())
     }
@@ -1318,94 +2414,242 @@ impl Client {
         model_precache: Vec<String>,
         sound_precache: Vec<String>,
     ) -> Result<(), Error> {
-        let mut new_client_state = ClientState::new(self.vfs.clone(), self.audio_device.clone())?;
+        let mut new_client_state = ClientState::new(
+            self.vfs.clone(),
+            self.audio_device.clone(),
+            particle::particle_capacity(self.cvar_value("cl_maxparticles")?),
+        )?;
 
         // check protocol version
-        ensure!(
-            protocol_version == net::PROTOCOL_VERSION as i32,
-            "Incompatible protocol version (got {}, should be {})",
-            protocol_version,
-            net::PROTOCOL_VERSION,
-        );
+        new_client_state.protocol = match Protocol::from_version(protocol_version) {
+            Some(p) => p,
+            None => bail!(
+                "Incompatible protocol version (got {}, should be {})",
+                protocol_version,
+                net::PROTOCOL_VERSION,
+            ),
+        };
 
         // TODO: print sign-on message to in-game console
         println!("{}", message);
+        new_client_state.level_name = message;
+
+        // the first model is always the world
+        if let Some(world) = model_precache.first() {
+            new_client_state.map_name = world
+                .trim_start_matches("maps/")
+                .trim_end_matches(".bsp")
+                .to_owned();
+        }
 
-        // parse model precache
-        // TODO: validate submodel names
-        for mod_name in model_precache {
-            if mod_name.ends_with(".bsp") {
-                let bsp_data = self.vfs.open(&mod_name)?;
-                let (mut brush_models, _) = bsp::load(bsp_data).unwrap();
-                new_client_state.models.append(&mut brush_models);
-            } else if !mod_name.starts_with("*") {
-                debug!("Loading model {}", mod_name);
-                new_client_state
-                    .models
-                    .push(Model::load(&self.vfs, mod_name)?);
-            }
+        let server_info = ServerInfo {
+            max_clients,
+            game_type,
+        };
 
-            // TODO: send keepalive message?
-        }
+        new_client_state.max_players = server_info.max_clients as usize;
+        new_client_state.game_type = server_info.game_type;
 
-        for (id, model) in self.state.models.iter().enumerate() {
-            new_client_state
-                .model_names
-                .insert(model.name().to_owned(), id);
-        }
+        // TODO: set up rest of client state (R_NewMap)
+
+        self.state = new_client_state;
+
+        // resources are loaded over the next few frames by `load_precache`
+        self.precache = Some(Precache {
+            total: model_precache.len() + sound_precache.len(),
+            models: model_precache.into(),
+            sounds: sound_precache.into(),
+            prespawn_requested: false,
+        });
 
-        // parse sound precache
-        for ref snd_name in sound_precache {
-            debug!("Loading sound {}", snd_name);
+        // TODO: replace console commands holding `Rc`s to the old ClientState
+
+        Ok(())
+    }
 
-            new_client_state
-                .sounds
-                .push(AudioSource::load(&self.vfs, snd_name).context(
+    // Loads precached resources until this frame's budget is spent. Once
+    // everything is loaded, answers the server's prespawn request.
+    fn load_precache(&mut self) -> Result<(), Error> {
+        let mut precache = match self.precache.take() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let start = Instant::now();
+        while precache.remaining() > 0 && start.elapsed().as_millis() < PRECACHE_FRAME_BUDGET_MS {
+            if let Some(mod_name) = precache.models.pop_front() {
+                self.load_model(mod_name)?;
+            } else if let Some(snd_name) = precache.sounds.pop_front() {
+                debug!("Loading sound {}", snd_name);
+                let sound = AudioSource::load(&self.vfs, &snd_name).context(
                     ClientErrorKind::ResourceNotLoaded {
-                        name: snd_name.to_owned(),
+                        name: snd_name.clone(),
                     },
-                )?);
+                )?;
+                self.state.sounds.push(sound);
+                self.state.sound_names.push(snd_name);
+            }
 
             // TODO: send keepalive message?
         }
 
-        let server_info = ServerInfo {
-            max_clients,
-            game_type,
-        };
+        if precache.remaining() > 0 {
+            self.precache = Some(precache);
+            return Ok(());
+        }
 
-        new_client_state.max_players = server_info.max_clients as usize;
+        for (id, model) in self.state.models.iter().enumerate() {
+            self.state.model_names.insert(model.name().to_owned(), id);
+        }
 
-        // TODO: set up rest of client state (R_NewMap)
+        if precache.prespawn_requested {
+            self.add_cmd(ClientCmd::StringCmd {
+                cmd: String::from("prespawn"),
+            })?;
+        }
 
-        self.state = new_client_state;
+        Ok(())
+    }
 
-        // TODO: replace console commands holding `Rc`s to the old ClientState
+    // Loads a model named in the server info.
+    fn load_model(&mut self, mod_name: String) -> Result<(), Error> {
+        // TODO: validate submodel names
+        if mod_name.ends_with(".bsp") {
+            let mut bsp_data = self.vfs.open(&mod_name)?;
+
+            // the world follows the null model; its digest keys the mesh cache, so
+            // only read the whole file up front when the cache is in use
+            let worldmodel = self.state.models.len() == 1;
+            let use_mesh_cache = self.cvars.borrow().get_value("r_meshcache").unwrap_or(0.0) != 0.0;
+            let (mut brush_models, ent_string) = if worldmodel && use_mesh_cache {
+                let mut data = Vec::new();
+                bsp_data.read_to_end(&mut data)?;
+                self.state.map_digest = Some(MapDigest::new(&data));
+                bsp::load(Cursor::new(data))?
+            } else {
+                bsp::load(bsp_data)?
+            };
+
+            // the world names the sky
+            if worldmodel {
+                self.state.sky_name = worldspawn_sky(&ent_string);
+            }
+
+            self.state.models.append(&mut brush_models);
+        } else if !mod_name.starts_with("*") {
+            debug!("Loading model {}", mod_name);
+            self.state.models.push(Model::load(&self.vfs, mod_name)?);
+        }
 
         Ok(())
     }
 
-    pub fn signon_stage(&self) -> SignOnStage {
-        self.signon.get()
+    /// Returns the progress of signing on to the server, for the loading screen.
+    pub fn loading_status(&self) -> LoadingStatus {
+        LoadingStatus {
+            state: self.conn_state.get(),
+            map_name: self.state.map_name.clone(),
+            level_name: self.state.level_name.clone(),
+            precache: self
+                .precache
+                .as_ref()
+                .map(|p| (p.total - p.remaining(), p.total)),
+        }
+    }
+
+    /// Returns the address of the server's control socket.
+    pub fn server_addr(&self) -> SocketAddr {
+        self.server_addr
+    }
+
+    /// Returns the client-side QuakeC extension point.
+    pub fn csqc(&self) -> &Csqc {
+        &self.csqc
+    }
+
+    /// Returns a summary of the client's state for external integrations.
+    pub fn presence_state(&self) -> PresenceState {
+        let server = self.server_addr.to_string();
+
+        if self.conn_state.get() != ConnectionState::Active {
+            return PresenceState::Connecting { server };
+        }
+
+        PresenceState::InGame {
+            server,
+            map: self.state.map_name.clone(),
+            level_name: self.state.level_name.clone(),
+            players: self
+                .state
+                .player_info
+                .iter()
+                .filter(|info| info.is_some())
+                .count(),
+            max_players: self.state.max_players,
+        }
     }
 
     pub fn entities(&self) -> Option<&[ClientEntity]> {
-        match self.signon.get() {
-            SignOnStage::Done => Some(&self.state.entities),
+        match self.conn_state.get() {
+            s if s.is_in_game() => Some(&self.state.entities),
             _ => None,
         }
     }
 
+    /// Reloads a model from the virtual filesystem, replacing the loaded copy.
+    ///
+    /// Brush models are part of the map and can't be reloaded on their own.
+    pub fn reload_model(&mut self, model_id: usize) -> Result<(), Error> {
+        let name = self.state.models[model_id].name().to_owned();
+        match self.state.models[model_id].kind() {
+            ModelKind::Alias(_) | ModelKind::Md3(_) | ModelKind::Sprite(_) => {
+                self.state.models[model_id] = Model::load(&self.vfs, &name)?;
+                Ok(())
+            }
+
+            _ => bail!("Can't reload {}", name),
+        }
+    }
+
+    /// Returns the paths of the precached sound files, by sound ID.
+    pub fn sound_files(&self) -> Vec<String> {
+        self.state
+            .sound_names
+            .iter()
+            .map(|name| format!("sound/{}", name))
+            .collect()
+    }
+
+    /// Reloads a sound from the virtual filesystem, replacing the loaded copy.
+    ///
+    /// Sounds which are already playing are not affected.
+    pub fn reload_sound(&mut self, sound_id: usize) -> Result<(), Error> {
+        self.state.sounds[sound_id] =
+            AudioSource::load(&self.vfs, &self.state.sound_names[sound_id])?;
+        Ok(())
+    }
+
+    /// Returns the sky box requested by the current map, if any.
+    pub fn sky_name(&self) -> Option<&str> {
+        self.state.sky_name.as_deref()
+    }
+
+    /// Returns the digest of the current map file, if the mesh cache is enabled.
+    pub fn map_digest(&self) -> Option<MapDigest> {
+        self.state.map_digest
+    }
+
     pub fn models(&self) -> Option<&[Model]> {
-        match self.signon.get() {
-            SignOnStage::Done => Some(&self.state.models),
+        match self.conn_state.get() {
+            s if s.is_in_game() => Some(&self.state.models),
             _ => None,
         }
     }
 
     pub fn view_origin(&self) -> Vector3<f32> {
-        self.state.entities[self.state.view.entity_id()].origin
-            + Vector3::new(0.0, 0.0, self.state.view.view_height())
+        self.state
+            .view
+            .origin(self.state.entities[self.state.view.entity_id()].origin)
     }
 
     pub fn view_angles(&self, time: Duration) -> Result<Angles, ClientError> {
@@ -1419,6 +2663,20 @@ impl Client {
         ))
     }
 
+    /// Returns the position and angles to draw the world from.
+    ///
+    /// This is the player's view unless the camera has been sent to a bookmark
+    /// with `cam_goto`.
+    pub fn camera(&self) -> Result<CameraBookmark, ClientError> {
+        match self.camera_bookmarks.borrow().current() {
+            Some(camera) => Ok(camera),
+            None => Ok(CameraBookmark {
+                origin: self.view_origin(),
+                angles: self.view_angles(self.state.time)?,
+            }),
+        }
+    }
+
     pub fn view_ent(&self) -> usize {
         self.state.view.entity_id()
     }
@@ -1428,6 +2686,11 @@ impl Client {
     }
 
     pub fn update_time(&mut self, frame_time: Duration) {
+        // hold the clock and interpolation where they are until the server unpauses
+        if self.state.paused {
+            return;
+        }
+
         // advance client time by frame duration
         self.state.time = self.state.time + frame_time;
 
@@ -1518,10 +2781,12 @@ impl Client {
                 let forward = (vec.x.powf(2.0) + vec.y.powf(2.0)).sqrt();
                 let pitch = Deg::from(cgmath::Rad(vec.z.atan2(forward))).normalize();
 
+                // a segment is drawn for every 30 units, including any partial one at the end
                 let len = vec.magnitude();
                 let direction = vec.normalize();
-                for interval in 0..(len / 30.0) as i32 {
+                for interval in 0..(len / 30.0).ceil() as i32 {
                     let mut ent = ClientEntity::uninitialized();
+                    ent.model_id = beam.model_id;
                     ent.origin = beam.start + 30.0 * interval as f32 * direction;
                     ent.angles = Vector3::new(
                         pitch,
@@ -1540,11 +2805,6 @@ impl Client {
     }
 
     pub fn relink_entities(&mut self) {
-        lazy_static! {
-            static ref MFLASH_DIMLIGHT_DISTRIBUTION: Uniform<f32> = Uniform::new(200.0, 232.0);
-            static ref BRIGHTLIGHT_DISTRIBUTION: Uniform<f32> = Uniform::new(400.0, 432.0);
-        }
-
         let lerp_factor = self.get_lerp_factor();
 
         self.state.velocity = self.state.msg_velocity[1]
@@ -1563,6 +2823,15 @@ impl Client {
             return;
         }
 
+        // the player's muzzle flash follows the view rather than the player model
+        let view_ent_id = self.state.view.entity_id();
+        let view_angles = self.state.view.input_angles();
+
+        // the custom skin drawn on players, if any
+        let custom_skin = self.cvars.borrow().get("cl_skin").unwrap_or_default();
+        let colorblind = ColorblindMode::from_cvar(self.cvar_value("v_colorblind").unwrap_or(0.0));
+        let player_info = &self.state.player_info;
+
         // NOTE that we start at entity 1 since we don't need to link the world entity
         for (ent_id, ent) in self.state.entities.iter_mut().enumerate().skip(1) {
             if ent.model_id == 0 {
@@ -1607,6 +2876,25 @@ impl Client {
                 ent.angles[1] = obj_rotate;
             }
 
+            // players are drawn in their own colors. The protocol doesn't
+            // carry skin names, so cl_skin picks the custom skin for everyone
+            let colormap = ent.colormap().unwrap_or(ent.baseline.colormap) as usize;
+            let skin_id = ent.get_skin_id();
+            ent.player_skin = colormap.checked_sub(1).and_then(|player_id| {
+                let info = player_info.get(player_id)?.as_ref()?;
+                Some(PlayerSkin {
+                    player_id,
+                    name: if custom_skin.is_empty() {
+                        None
+                    } else {
+                        Some(custom_skin.clone())
+                    },
+                    skin_id,
+                    top: colorblind.remap_player_color(info.colors.top()),
+                    bottom: colorblind.remap_player_color(info.colors.bottom()),
+                })
+            });
+
             if ent.effects.contains(EntityEffects::BRIGHT_FIELD) {
                 self.state
                     .particles
@@ -1616,48 +2904,18 @@ impl Client {
             // TODO: cache a SmallRng in Client
             let mut rng = rand::thread_rng();
 
-            // TODO: factor out EntityEffects->LightDesc mapping
-            if ent.effects.contains(EntityEffects::MUZZLE_FLASH) {
-                // TODO: angle and move origin to muzzle
-                ent.light_id = Some(self.state.lights.insert(
-                    self.state.time,
-                    LightDesc {
-                        origin: ent.origin + Vector3::new(0.0, 0.0, 16.0),
-                        init_radius: MFLASH_DIMLIGHT_DISTRIBUTION.sample(&mut rng),
-                        decay_rate: 0.0,
-                        min_radius: Some(32.0),
-                        ttl: Duration::milliseconds(100),
-                    },
-                    ent.light_id,
-                ));
-            }
-
-            if ent.effects.contains(EntityEffects::BRIGHT_LIGHT) {
-                ent.light_id = Some(self.state.lights.insert(
-                    self.state.time,
-                    LightDesc {
-                        origin: ent.origin,
-                        init_radius: BRIGHTLIGHT_DISTRIBUTION.sample(&mut rng),
-                        decay_rate: 0.0,
-                        min_radius: None,
-                        ttl: Duration::milliseconds(1),
-                    },
-                    ent.light_id,
-                ));
-            }
+            let facing = if ent_id == view_ent_id {
+                Vector3::new(view_angles.pitch, view_angles.yaw, view_angles.roll)
+            } else {
+                ent.angles
+            };
 
-            if ent.effects.contains(EntityEffects::DIM_LIGHT) {
-                ent.light_id = Some(self.state.lights.insert(
-                    self.state.time,
-                    LightDesc {
-                        origin: ent.origin,
-                        init_radius: MFLASH_DIMLIGHT_DISTRIBUTION.sample(&mut rng),
-                        decay_rate: 0.0,
-                        min_radius: None,
-                        ttl: Duration::milliseconds(1),
-                    },
-                    ent.light_id,
-                ));
+            if let Some(desc) = effect_light(ent.effects, ent.origin, facing, &mut rng) {
+                ent.light_id = Some(
+                    self.state
+                        .lights
+                        .insert(self.state.time, desc, ent.light_id),
+                );
             }
 
             // check if this entity leaves a trail
@@ -1712,48 +2970,123 @@ impl Client {
         for ent in self.state.static_entities.iter_mut() {
             let mut rng = rand::thread_rng();
 
-            if ent.effects.contains(EntityEffects::BRIGHT_LIGHT) {
-                debug!("spawn bright light on static entity");
-                ent.light_id = Some(self.state.lights.insert(
-                    self.state.time,
-                    LightDesc {
-                        origin: ent.origin,
-                        init_radius: BRIGHTLIGHT_DISTRIBUTION.sample(&mut rng),
-                        decay_rate: 0.0,
-                        min_radius: None,
-                        ttl: Duration::milliseconds(1),
-                    },
-                    ent.light_id,
-                ));
+            if let Some(desc) = effect_light(ent.effects, ent.origin, ent.angles, &mut rng) {
+                ent.light_id = Some(
+                    self.state
+                        .lights
+                        .insert(self.state.time, desc, ent.light_id),
+                );
             }
+        }
+    }
 
-            if ent.effects.contains(EntityEffects::DIM_LIGHT) {
-                debug!("spawn dim light on static entity");
-                ent.light_id = Some(self.state.lights.insert(
-                    self.state.time,
-                    LightDesc {
-                        origin: ent.origin,
-                        init_radius: MFLASH_DIMLIGHT_DISTRIBUTION.sample(&mut rng),
-                        decay_rate: 0.0,
-                        min_radius: None,
-                        ttl: Duration::milliseconds(1),
-                    },
-                    ent.light_id,
-                ));
+    // Spawns a locally predicted projectile if the active weapon fires one and
+    // the player has ammo for it.
+    fn predict_projectile(&mut self) {
+        if self.state.intermission.is_some() || self.state.stats[ClientStat::Ammo as usize] <= 0 {
+            return;
+        }
+
+        let weapon = ItemFlags::from_bits_truncate(self.active_weapon() as u32);
+        let kind = match ProjectileKind::from_weapon(weapon) {
+            Some(k) => k,
+            None => return,
+        };
+
+        // the server only precaches projectile models it uses
+        let model_id = match self.state.model_names.get(kind.model_name()) {
+            Some(id) => *id,
+            None => return,
+        };
+
+        let origin = match self.state.entities.get(self.state.view.entity_id()) {
+            Some(ent) => ent.origin,
+            None => return,
+        };
+
+        let known_entities = self.server_projectiles().into_iter().map(|(id, _, _)| id);
+        self.state.projectiles.fire(
+            self.state.time,
+            kind,
+            model_id,
+            origin,
+            self.state.view.input_angles(),
+            known_entities,
+        );
+    }
+
+    // Returns the entity ID, model ID and origin of each visible projectile
+    // sent by the server.
+    fn server_projectiles(&self) -> Vec<(usize, usize, Vector3<f32>)> {
+        let model_ids: Vec<usize> = [
+            ProjectileKind::Nail,
+            ProjectileKind::SuperNail,
+            ProjectileKind::Rocket,
+        ]
+        .iter()
+        .filter_map(|kind| self.state.model_names.get(kind.model_name()).copied())
+        .collect();
+
+        self.state
+            .visible_entity_ids
+            .iter()
+            .map(|id| (*id, &self.state.entities[*id]))
+            .filter(|(_, ent)| model_ids.contains(&ent.model_id))
+            .map(|(id, ent)| (id, ent.model_id, ent.origin))
+            .collect()
+    }
+
+    // Lets the view lag behind the view entity when it steps up while on the
+    // ground, so stairs and lifts don't snap the camera.
+    fn smooth_view_step(&mut self, frame_time: Duration) {
+        let view_ent_id = self.state.view.entity_id();
+        match self.state.entities.get(view_ent_id) {
+            Some(ent) => {
+                let origin_z = ent.origin.z;
+                let on_ground = self.state.on_ground;
+                self.state.view.smooth_step(origin_z, on_ground, frame_time);
             }
+
+            None => self.state.view.reset_step(),
+        }
+    }
+
+    /// Returns the binding context for the current view.
+    ///
+    /// Demo playback uses the `Demo` context. If the server has moved the view
+    /// off the local player, e.g. to follow another player, the `Spectator`
+    /// context is used.
+    pub fn bind_context(&self) -> BindContext {
+        if self.conn_state.get() == ConnectionState::DemoPlayback {
+            return BindContext::Demo;
+        }
+
+        match self.state.player_entity_id {
+            Some(id) if id != self.state.view.entity_id() => BindContext::Spectator,
+            _ => BindContext::Game,
         }
     }
 
-    fn view_leaf_contents(&self) -> bsp::BspLeafContents {
-        match self.state.models[1].kind() {
-            ModelKind::Brush(ref bmodel) => {
+    /// Returns the contents of the BSP leaf containing the view origin.
+    ///
+    /// This is updated once per frame, so the renderer, audio and HUD can check
+    /// whether the view is underwater without doing their own leaf lookup.
+    pub fn view_contents(&self) -> bsp::BspLeafContents {
+        self.state.view_contents
+    }
+
+    fn update_view_contents(&mut self) -> Result<(), Error> {
+        self.state.view_contents = match self.state.models.get(1).map(|m| m.kind()) {
+            Some(ModelKind::Brush(bmodel)) => {
                 let bsp_data = bmodel.bsp_data();
                 let leaf_id = bsp_data.find_leaf(self.view_origin());
                 let leaf = &bsp_data.leaves()[leaf_id];
                 leaf.contents
             }
-            _ => panic!("non-brush worldmodel"),
-        }
+            _ => bail!("World model is not a brush model"),
+        };
+
+        Ok(())
     }
 
     fn update_color_shifts(&self, frame_time: Duration) {
@@ -1761,7 +3094,7 @@ impl Client {
 
         // set color for leaf contents
         self.state.color_shifts[ColorShiftCode::Contents as usize].replace(
-            match self.view_leaf_contents() {
+            match self.state.view_contents {
                 bsp::BspLeafContents::Empty => ColorShift {
                     dest_color: [0, 0, 0],
                     percent: 0,
@@ -1821,45 +3154,382 @@ impl Client {
         );
     }
 
+    /// Plays a heartbeat when the player's health is low.
+    ///
+    /// This is an accessibility option controlled by `hud_lowhealth`. The
+    /// heartbeat speeds up as health approaches zero and pulses the HUD on
+    /// each beat. The game data has no heartbeat sound, so beats are silent
+    /// unless `hud_lowhealth_sound` names one to play.
+    fn update_heartbeat(&mut self) -> Result<(), Error> {
+        let enabled = self.cvar_value("hud_lowhealth")? != 0.0;
+        let threshold = self.cvar_value("hud_lowhealth_threshold")?;
+        let health = self.state.stats[ClientStat::Health as usize];
+
+        if !enabled || health <= 0 || health as f32 > threshold || self.state.intermission.is_some()
+        {
+            self.state.heartbeat_time = None;
+            return Ok(());
+        }
+
+        // beat once per second at the threshold, down to 2.5 times per second near death
+        let ratio = (health as f32 / threshold).min(1.0);
+        self.state.heartbeat_period = Duration::milliseconds((400.0 + 600.0 * ratio) as i64);
+
+        let beat_due = match self.state.heartbeat_time {
+            Some(t) => self.state.time - t >= self.state.heartbeat_period,
+            None => true,
+        };
+
+        if !beat_due {
+            return Ok(());
+        }
+
+        self.state.heartbeat_time = Some(self.state.time);
+
+        let sound_name = self
+            .cvars
+            .borrow()
+            .get("hud_lowhealth_sound")
+            .unwrap_or_default();
+        if sound_name.is_empty() {
+            return Ok(());
+        }
+
+        // a missing sound is logged once and the heartbeat stays silent
+        let loaded = match self.state.heartbeat_sound {
+            Some((ref name, _)) => *name == sound_name,
+            None => false,
+        };
+        if !loaded {
+            let src = match AudioSource::load(&self.vfs, &sound_name) {
+                Ok(src) => Some(src),
+                Err(e) => {
+                    warn!("Couldn't load {}: {}", sound_name, e);
+                    None
+                }
+            };
+            self.state.heartbeat_sound = Some((sound_name, src));
+        }
+
+        if let Some((_, Some(ref src))) = self.state.heartbeat_sound {
+            self.state.mixer.start_sound(
+                src.clone(),
+                self.state.time,
+                self.state.view.entity_id(),
+                0,
+                1.0,
+                0.0,
+                &self.state.entities,
+                &self.state.listener,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns markers pointing toward the sources of recent damage.
+    ///
+    /// This is controlled by `hud_damageindicator`. Damage from directly
+    /// above or below the player has no direction and is ignored.
+    pub fn damage_indicators(&self) -> Vec<DamageIndicator> {
+        if self.cvar_value("hud_damageindicator").unwrap_or(0.0) == 0.0 {
+            return Vec::new();
+        }
+
+        let origin = self.view_origin();
+        let yaw = self.state.view.input_angles().yaw;
+        let lifetime = Duration::milliseconds(DAMAGE_INDICATOR_DURATION_MS);
+
+        self.state
+            .damage_sources
+            .iter()
+            .filter_map(|(time, source)| {
+                let age = self.state.time - *time;
+                let dir = *source - origin;
+                if age >= lifetime || (dir.x == 0.0 && dir.y == 0.0) {
+                    return None;
+                }
+
+                let source_yaw = Deg::from(cgmath::Rad(dir.y.atan2(dir.x)));
+                Some(DamageIndicator {
+                    angle: (source_yaw - yaw).normalize(),
+                    remaining: 1.0
+                        - engine::duration_to_f32(age) / engine::duration_to_f32(lifetime),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns `true` if a hit confirmation should be displayed.
+    ///
+    /// This is controlled by `hud_hitconfirm`. NQ servers don't report hits,
+    /// so the client infers them from kills and frag increases.
+    pub fn hit_confirmed(&self) -> bool {
+        if self.cvar_value("hud_hitconfirm").unwrap_or(0.0) == 0.0 {
+            return false;
+        }
+
+        match self.state.hit_confirm_time {
+            Some(t) => self.state.time - t < Duration::milliseconds(HIT_CONFIRM_DURATION_MS),
+            None => false,
+        }
+    }
+
+    /// Returns the most recent centerprint message, if it's still on screen.
+    ///
+    /// Messages stay up for `scr_centertime` seconds.
+    pub fn center_print(&self) -> Option<&str> {
+        let center_time = self.cvar_value("scr_centertime").unwrap_or(2.0);
+        match self.state.center_print {
+            Some((ref text, t)) if self.state.time - t < engine::duration_from_f32(center_time) => {
+                Some(text.as_str())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the phase of the current low health heartbeat.
+    ///
+    /// The phase is in the range `[0, 1]`, where 0 is the start of the beat.
+    /// Returns `None` if no heartbeat is active.
+    pub fn low_health_pulse(&self) -> Option<f32> {
+        self.state.heartbeat_time.map(|t| {
+            let elapsed = engine::duration_to_f32(self.state.time - t);
+            let period = engine::duration_to_f32(self.state.heartbeat_period);
+            (elapsed / period).min(1.0)
+        })
+    }
+
     pub fn frame(&mut self, frame_time: Duration) -> Result<(), Error> {
         debug!("frame time: {}ms", frame_time.num_milliseconds());
+
+        if self.disconnect_requested.replace(false) {
+            self.disconnect()?;
+        }
+
+        // nothing to do until we connect again
+        if self.conn_state.get() == ConnectionState::Disconnected {
+            return Ok(());
+        }
+
         self.parse_server_msg()?;
+        self.load_precache()?;
 
         // update timing information
+        let prev_time = self.state.time;
         self.update_time(frame_time);
 
+        // effects run on the client clock rather than the frame duration, so
+        // they stop while the game is paused
+        let effect_time = (self.state.time - prev_time).max(Duration::zero());
+
         // interpolate entity data
         self.relink_entities();
 
+        // smooth out stairs and lifts
+        self.smooth_view_step(frame_time);
+
+        // bob the view while walking
+        let bob_vars = self.bob_vars()?;
+        self.state.view.update_bob(
+            self.state.time,
+            self.state.velocity,
+            self.state.intermission.as_ref(),
+            bob_vars,
+        );
+
+        // move the camera if it's been sent to a bookmark
+        let player_camera = CameraBookmark {
+            origin: self.view_origin(),
+            angles: self.view_angles(self.state.time)?,
+        };
+        self.camera_bookmarks
+            .borrow_mut()
+            .update(player_camera, frame_time);
+
         // update temp entities (lightning, etc.)
         self.update_temp_entities();
 
+        // move predicted projectiles and retire those the server has caught up with
+        let server_projectiles = self.server_projectiles();
+        self.state
+            .projectiles
+            .update(self.state.time, server_projectiles);
+
         // remove expired lights
         self.state.lights.update(self.state.time);
 
         // apply physics and remove expired particles
         self.state
             .particles
-            .update(self.state.time, frame_time, self.cvar_value("sv_gravity")?);
+            .update(self.state.time, effect_time, self.cvar_value("sv_gravity")?);
+
+        // pass along commands the server should execute
+        let forward_cmds: Vec<String> = self.forward_cmds.borrow_mut().drain(..).collect();
+        for cmd in forward_cmds {
+            self.add_cmd(ClientCmd::StringCmd { cmd })?;
+        }
 
         // respond to the server
         self.send()?;
 
         // these all require the player entity to have spawned
-        if self.signon.get() == SignOnStage::Done {
+        if self.conn_state.get().is_in_game() {
+            // find out what the camera is inside of
+            self.update_view_contents()?;
+
             // update ear positions
             self.state.update_listener();
+            self.state
+                .listener
+                .set_underwater(self.state.view_contents.is_liquid());
 
             // spatialize sounds for new ear positions
-            self.state.update_sound_spatialization();
+            self.state.listener.set_distance_model(
+                DistanceModel::from_cvar(self.cvar_value("snd_distance_model")?),
+                self.cvar_value("snd_rolloff")?.max(0.0),
+            );
+            let occlusion = self.cvar_value("snd_occlusion")? != 0.0;
+            self.state.update_sound_spatialization(occlusion);
+
+            #[cfg(feature = "music")]
+            self.music.set_volume(self.cvar_value("bgmvolume")?);
+
+            // move the weapon along with the view
+            self.update_view_model()?;
 
             // update camera color shifts for new position/effects
-            self.update_color_shifts(frame_time);
+            self.update_color_shifts(effect_time);
+
+            // play low health heartbeat if enabled
+            self.update_heartbeat()?;
+
+            if self.stats_requested.replace(false) {
+                self.print_stats();
+            }
+
+            if self.identify_requested.replace(false) {
+                let msg = match self.state.identify_target() {
+                    Some(info) => info.to_string(),
+                    None => "No entity under crosshair".to_string(),
+                };
+                self.console.borrow().output_mut().print(msg);
+            }
+        }
+
+        if self.meminfo_requested.replace(false) {
+            self.print_meminfo();
+        }
+
+        Ok(())
+    }
+
+    // Prints the approximate memory used by each subsystem.
+    fn print_meminfo(&self) {
+        // every brush model shares the worldmodel's BSP data
+        let bsp_data = self.state.models.iter().find_map(|m| match m.kind() {
+            ModelKind::Brush(ref bmodel) => Some(bmodel.bsp_data()),
+            _ => None,
+        });
+        let (bsp, textures) = match bsp_data {
+            Some(ref b) => (b.memory_usage(), b.texture_memory_usage()),
+            None => (0, 0),
+        };
+
+        let console = self.console.borrow();
+        let usage = [
+            ("PAK files", self.vfs.memory_usage()),
+            ("BSP data", bsp),
+            ("Textures", textures),
+            (
+                "Sounds",
+                self.state.sounds.iter().map(|s| s.memory_usage()).sum(),
+            ),
+            ("Particles", self.state.particles.memory_usage()),
+            ("Console", console.output().memory_usage()),
+        ];
+
+        let mut report = String::new();
+        for (name, bytes) in usage.iter() {
+            report += &format!("{:<10} {}\n", name, format_bytes(*bytes));
+        }
+        let total = usage.iter().map(|(_, bytes)| bytes).sum();
+        report += &format!("{:<10} {}", "Total", format_bytes(total));
+
+        console.output_mut().print(report);
+    }
+
+    // Prints the monster and secret tallies and the time spent on the level.
+    fn print_stats(&self) {
+        let stats = &self.state.stats;
+        let elapsed = self.state.completion_time.unwrap_or(self.state.time) - self.state.start_time;
+        let minutes = elapsed.num_minutes();
+        let seconds = elapsed.num_seconds() - 60 * minutes;
+
+        self.console.borrow().output_mut().print(format!(
+            "Monsters: {}/{}\nSecrets: {}/{}\nTime: {}:{:02}",
+            stats[ClientStat::KilledMonsters as usize],
+            stats[ClientStat::TotalMonsters as usize],
+            stats[ClientStat::FoundSecrets as usize],
+            stats[ClientStat::TotalSecrets as usize],
+            minutes,
+            seconds,
+        ));
+    }
+
+    // Updates the player's weapon model from the weapon stats and places it at
+    // the view, offset by view bob and turned with the view angles, including
+    // damage kick and punch.
+    fn update_view_model(&mut self) -> Result<(), Error> {
+        let model_id = self.state.stats[ClientStat::Weapon as usize].max(0) as usize;
+        let frame_id = self.state.stats[ClientStat::WeaponFrame as usize].max(0) as usize;
+
+        // the view origin already bobs up and down, the weapon also bobs forward
+        let angles = self.view_angles(self.state.time)?;
+        let forward = projectile::forward_vector(angles.pitch, angles.yaw);
+        let origin = self.view_origin() + forward * self.state.view.bob() * 0.4;
+
+        let msg_time = self.state.msg_times[1];
+        let vm = &mut self.state.view_model;
+        if vm.model_id != model_id {
+            // don't blend between the frames of different weapons
+            vm.model_id = model_id;
+            vm.prev_frame_id = frame_id;
+        } else if vm.frame_id != frame_id {
+            vm.prev_frame_id = vm.frame_id;
+            vm.frame_change_time = msg_time;
         }
+        vm.frame_id = frame_id;
+
+        vm.origin = origin;
+        // alias models pitch the opposite way from the view
+        vm.set_angles(Vector3::new(-angles.pitch, angles.yaw, angles.roll));
 
         Ok(())
     }
 
+    /// Returns the player's weapon model, if it should be drawn.
+    ///
+    /// The weapon is hidden by `r_drawviewmodel 0`, during intermission, while
+    /// the player is invisible or dead, and when no weapon is held.
+    pub fn view_model(&self) -> Option<&ClientEntity> {
+        if self.cvar_value("r_drawviewmodel").unwrap_or(1.0) == 0.0
+            || self.state.intermission.is_some()
+            || self.camera_bookmarks.borrow().detached()
+            || self.state.items.contains(ItemFlags::INVISIBILITY)
+            || self.state.stats[ClientStat::Health as usize] <= 0
+        {
+            return None;
+        }
+
+        let vm = &self.state.view_model;
+        match vm.model_id {
+            0 => None,
+            id if id >= self.state.models.len() => None,
+            _ => Some(vm),
+        }
+    }
+
     pub fn iter_visible_entities(&self) -> impl Iterator<Item = &ClientEntity> + Clone {
         self.state
             .visible_entity_ids
@@ -1867,6 +3537,7 @@ impl Client {
             .map(move |i| &self.state.entities[*i])
             .chain(self.state.temp_entities.iter())
             .chain(self.state.static_entities.iter())
+            .chain(self.state.projectiles.iter_entities())
     }
 
     pub fn iter_lights(&self) -> impl Iterator<Item = &Light> {
@@ -1889,34 +3560,6 @@ impl Client {
             }),
         )
         .unwrap();
-
-        let vfs = self.vfs.clone();
-        let console = self.console.clone();
-        cmds.insert_or_replace(
-            "exec",
-            Box::new(move |args| {
-                match args.len() {
-                    // exec (filename): execute a script file
-                    1 => {
-                        let mut script_file = match vfs.open(args[0]) {
-                            Ok(s) => s,
-                            Err(e) => {
-                                println!("Couldn't exec {}: {:?}", args[0], e);
-                                return;
-                            }
-                        };
-
-                        let mut script = String::new();
-                        script_file.read_to_string(&mut script).unwrap();
-
-                        console.borrow().stuff_text(script);
-                    }
-
-                    _ => println!("exec (filename): execute a script file"),
-                }
-            }),
-        )
-        .unwrap();
     }
 
     pub fn spawn_beam(
@@ -1956,6 +3599,34 @@ impl Client {
         }
     }
 
+    // Plays a temp entity's sound at its origin, loading the sound on first use.
+    fn start_temp_entity_sound(&mut self, name: &'static str, origin: Vector3<f32>) {
+        let vfs = self.vfs.clone();
+        let src = self
+            .state
+            .temp_entity_sounds
+            .entry(name)
+            .or_insert_with(|| match AudioSource::load(&vfs, name) {
+                Ok(src) => Some(src),
+                Err(e) => {
+                    warn!("Couldn't load {}: {}", name, e);
+                    None
+                }
+            })
+            .clone();
+
+        if let Some(src) = src {
+            self.state.mixer.start_sound_at(
+                src,
+                self.state.time,
+                origin,
+                1.0,
+                1.0,
+                &self.state.listener,
+            );
+        }
+    }
+
     pub fn spawn_temp_entity(&mut self, time: Duration, temp_entity: &TempEntity) {
         match temp_entity {
             TempEntity::Point { kind, origin } => {
@@ -1964,25 +3635,24 @@ impl Client {
                     // projectile impacts
                     WizSpike | KnightSpike | Spike | SuperSpike | Gunshot => {
                         let (color, count) = match kind {
-                            // TODO: start wizard/hit.wav
                             WizSpike => (20, 30),
-
-                            // TODO: start hknight/hit.wav
                             KnightSpike => (226, 20),
-
-                            // TODO: for Spike and SuperSpike, start one of:
-                            // - 26.67%: weapons/tink1.wav
-                            // - 20.0%: weapons/ric1.wav
-                            // - 20.0%: weapons/ric2.wav
-                            // - 20.0%: weapons/ric3.wav
                             Spike => (0, 10),
                             SuperSpike => (0, 20),
-
-                            // no sound
                             Gunshot => (0, 20),
                             _ => unreachable!(),
                         };
 
+                        let sound = match kind {
+                            WizSpike => Some("wizard/hit.wav"),
+                            KnightSpike => Some("hknight/hit.wav"),
+                            Spike | SuperSpike => Some(spike_impact_sound(&mut rand::thread_rng())),
+                            _ => None,
+                        };
+                        if let Some(name) = sound {
+                            self.start_temp_entity_sound(name, *origin);
+                        }
+
                         self.state.particles.create_projectile_impact(
                             self.state.time,
                             *origin,
@@ -2005,7 +3675,7 @@ impl Client {
                             },
                             None,
                         );
-                        // TODO: start weapons/r_exp3
+                        self.start_temp_entity_sound(EXPLOSION_SOUND, *origin);
                     }
 
                     ColorExplosion {
@@ -2028,12 +3698,12 @@ impl Client {
                             },
                             None,
                         );
-                        // TODO: start weapons/r_exp3
+                        self.start_temp_entity_sound(EXPLOSION_SOUND, *origin);
                     }
 
                     TarExplosion => {
                         self.state.particles.create_spawn_explosion(time, *origin);
-                        // TODO: start weapons/r_exp3 (same sound as rocket explosion)
+                        self.start_temp_entity_sound(EXPLOSION_SOUND, *origin);
                     }
 
                     LavaSplash => self.state.particles.create_lava_splash(time, *origin),
@@ -2076,10 +3746,89 @@ impl Client {
         self.state.intermission.as_ref()
     }
 
+    /// Returns true if the current game is a deathmatch.
+    pub fn deathmatch(&self) -> bool {
+        self.state.game_type == GameType::Deathmatch
+    }
+
+    /// Returns the connected players ranked by frags, most first.
+    ///
+    /// Player colors are remapped according to `v_colorblind`.
+    pub fn scoreboard(&self) -> Vec<ScoreEntry> {
+        // player entities are numbered from 1
+        let local_id = self.state.view.entity_id().checked_sub(1);
+        let colorblind = ColorblindMode::from_cvar(self.cvar_value("v_colorblind").unwrap_or(0.0));
+
+        let mut scores = ranked_scores(&self.state.player_info, local_id);
+        for score in scores.iter_mut() {
+            score.colors = PlayerColor::new(
+                colorblind.remap_player_color(score.colors.top()),
+                colorblind.remap_player_color(score.colors.bottom()),
+            );
+        }
+
+        scores
+    }
+
     pub fn start_time(&self) -> Duration {
         self.state.start_time
     }
 
+    /// Returns the horizontal speed of the player in units per second.
+    pub fn speed(&self) -> f32 {
+        self.state.velocity.truncate().magnitude()
+    }
+
+    /// Returns true if the speedometer is enabled by `scr_speed`.
+    pub fn speedometer(&self) -> bool {
+        self.cvar_value("scr_speed").unwrap_or(0.0) != 0.0
+    }
+
+    /// Returns how the crosshair should be drawn, according to `crosshair`.
+    pub fn crosshair(&self) -> Crosshair {
+        Crosshair::from_cvar(self.cvar_value("crosshair").unwrap_or(1.0))
+    }
+
+    /// Returns the offset of the crosshair from the center of the screen, as
+    /// given by `cl_crossx` and `cl_crossy`.
+    pub fn crosshair_offset(&self) -> (i32, i32) {
+        (
+            self.cvar_value("cl_crossx").unwrap_or(0.0) as i32,
+            self.cvar_value("cl_crossy").unwrap_or(0.0) as i32,
+        )
+    }
+
+    /// Returns the entity under the crosshair while `+id` is held.
+    pub fn identified_entity(&self) -> Option<EntityInfo> {
+        if !self.identify_held.get() {
+            return None;
+        }
+
+        self.state.identify_target()
+    }
+
+    /// Returns the current and optimal strafing angles.
+    ///
+    /// This is controlled by `scr_speed 2`. Returns `None` if the player is
+    /// moving too slowly for air strafing to gain speed.
+    pub fn strafe_angles(&self) -> Option<StrafeAngles> {
+        if self.cvar_value("scr_speed").unwrap_or(0.0) < 2.0 {
+            return None;
+        }
+
+        let speed = self.speed();
+        if speed <= AIR_WISH_SPEED {
+            return None;
+        }
+
+        let velocity = self.state.velocity;
+        let travel_yaw = Deg::atan2(velocity.y, velocity.x);
+        Some(StrafeAngles {
+            current: (travel_yaw - self.state.view.input_angles().yaw).normalize_signed(),
+            optimal: Deg::acos(AIR_WISH_SPEED / speed),
+        })
+    }
+
     pub fn completion_time(&self) -> Option<Duration> {
         self.state.completion_time
     }
@@ -2136,6 +3885,8 @@ impl Client {
     }
 
     pub fn color_shift(&self) -> [f32; 4] {
+        let colorblind = ColorblindMode::from_cvar(self.cvar_value("v_colorblind").unwrap_or(0.0));
+
         self.state
             .color_shifts
             .iter()
@@ -2149,9 +3900,10 @@ impl Client {
                 let color_factor = elem_a / out_a;
 
                 let mut out = [0.0; 4];
+                let dest_color = colorblind.remap_rgb(elem.borrow().dest_color);
                 for i in 0..3 {
                     out[i] = accum[i] * (1.0 - color_factor)
-                        + elem.borrow().dest_color[i] as f32 / 255.0 * color_factor;
+                        + dest_color[i] as f32 / 255.0 * color_factor;
                 }
                 out[3] = out_a.min(1.0).max(0.0);
                 out
@@ -2170,6 +3922,14 @@ impl Client {
         })
     }
 
+    fn bob_vars(&self) -> Result<BobVars, ClientError> {
+        Ok(BobVars {
+            cl_bob: self.cvar_value("cl_bob")?,
+            cl_bobcycle: self.cvar_value("cl_bobcycle")?,
+            cl_bobup: self.cvar_value("cl_bobup")?,
+        })
+    }
+
     fn kick_vars(&self) -> Result<KickVars, ClientError> {
         Ok(KickVars {
             v_kickpitch: self.cvar_value("v_kickpitch")?,
@@ -2180,12 +3940,25 @@ impl Client {
 
     fn mouse_vars(&self) -> Result<MouseVars, ClientError> {
         Ok(MouseVars {
+            m_filter: self.cvar_value("m_filter")? != 0.0,
+            m_invert: self.cvar_value("m_invert")? != 0.0,
             m_pitch: self.cvar_value("m_pitch")?,
             m_yaw: self.cvar_value("m_yaw")?,
+            m_forward: self.cvar_value("m_forward")?,
+            m_side: self.cvar_value("m_side")?,
+            lookstrafe: self.cvar_value("lookstrafe")? != 0.0,
+            lookspring: self.cvar_value("lookspring")? != 0.0,
             sensitivity: self.cvar_value("sensitivity")?,
         })
     }
 
+    fn joy_vars(&self) -> Result<JoyVars, ClientError> {
+        Ok(JoyVars {
+            joy_sensitivity_pitch: self.cvar_value("joy_sensitivity_pitch")?,
+            joy_sensitivity_yaw: self.cvar_value("joy_sensitivity_yaw")?,
+        })
+    }
+
     fn roll_vars(&self) -> Result<RollVars, ClientError> {
         Ok(RollVars {
             cl_rollangle: self.cvar_value("cl_rollangle")?,
@@ -2240,7 +4013,123 @@ impl Client {
 
 impl std::ops::Drop for Client {
     fn drop(&mut self) {
-        // if this errors, it was already removed so we don't care
-        let _ = self.cmds.borrow_mut().remove("reconnect");
+        // if these error, they were already removed so we don't care
+        let mut cmds = self.cmds.borrow_mut();
+        let _ = cmds.remove("reconnect");
+        let _ = cmds.remove("disconnect");
+        let _ = cmds.remove("centerview");
+        let _ = cmds.remove("stats");
+        let _ = cmds.remove("meminfo");
+        let _ = cmds.remove("identify");
+        let _ = cmds.remove("+id");
+        let _ = cmds.remove("-id");
+        for name in &["cam_save", "cam_goto", "cam_release", "cam_list"] {
+            let _ = cmds.remove(name);
+        }
+
+        // say and friends stay available, but only to report there's no connection
+        let output = self.console.borrow().output_handle();
+        if let Err(e) = register_offline_cmds(&mut cmds, output) {
+            warn!("Failed to restore offline commands: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn player(name: &str, frags: i32) -> Option<PlayerInfo> {
+        Some(PlayerInfo {
+            name: name.to_owned(),
+            frags,
+            colors: PlayerColor::new(0, 0),
+        })
+    }
+
+    fn names(scores: &[ScoreEntry]) -> Vec<&str> {
+        scores.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_ranked_scores_empty() {
+        assert!(ranked_scores(&[], None).is_empty());
+        assert!(ranked_scores(&[None, None], Some(0)).is_empty());
+
+        // a player who left keeps their slot but isn't listed
+        assert!(ranked_scores(&[player("", 5)], None).is_empty());
+    }
+
+    #[test]
+    fn test_ranked_scores_ties() {
+        let players = [
+            player("ranger", 3),
+            player("shambler", 10),
+            None,
+            player("fiend", 3),
+            player("ogre", -1),
+        ];
+
+        // tied players stay in player order
+        assert_eq!(
+            names(&ranked_scores(&players, None)),
+            vec!["shambler", "ranger", "fiend", "ogre"]
+        );
+    }
+
+    #[test]
+    fn test_ranked_scores_local() {
+        let players = [
+            player("ranger", 3),
+            player("shambler", 10),
+            player("fiend", 3),
+        ];
+
+        let scores = ranked_scores(&players, Some(2));
+        assert_eq!(names(&scores), vec!["shambler", "ranger", "fiend"]);
+        assert_eq!(
+            scores.iter().map(|s| s.local).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+
+        // the local player is ranked by frags like everyone else
+        let scores = ranked_scores(&players, Some(1));
+        assert!(scores[0].local);
+
+        assert!(ranked_scores(&players, None).iter().all(|s| !s.local));
+    }
+
+    #[cfg(feature = "qw")]
+    #[test]
+    fn test_oob_response_text() {
+        assert_eq!(
+            oob_response_text(&OobResponse::Print(String::from("executed quit\n"))),
+            "executed quit"
+        );
+        assert_eq!(
+            oob_response_text(&OobResponse::Info {
+                challenge: None,
+                info: vec![
+                    (String::from("hostname"), String::from("richter")),
+                    (String::from("mapname"), String::from("e1m1")),
+                ],
+            }),
+            "hostname: richter\nmapname: e1m1"
+        );
+    }
+
+    #[test]
+    fn test_offline_cmds() {
+        let mut cmds = CmdRegistry::new();
+        let output = Rc::new(RefCell::new(ConsoleOutput::new()));
+        register_offline_cmds(&mut cmds, output.clone()).unwrap();
+
+        cmds.exec("say", &["hello"]).unwrap();
+        let lines: Vec<String> = output
+            .borrow()
+            .lines()
+            .map(|l| l.iter().collect())
+            .collect();
+        assert_eq!(lines, vec!["Can't \"say\", not connected"]);
     }
 }
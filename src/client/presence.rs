@@ -0,0 +1,142 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Reporting of engine state to external integrations.
+//!
+//! Launchers and "rich presence" services want to show what the player is
+//! doing. `Presence` tracks a `PresenceState` and notifies registered
+//! callbacks whenever it changes.
+
+use std::{cell::RefCell, fmt};
+
+/// A summary of what the player is currently doing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PresenceState {
+    /// The player is at the title screen or main menu.
+    Menu,
+
+    /// The client is connecting to or signing on with a server.
+    Connecting {
+        /// The address of the server.
+        server: String,
+    },
+
+    /// The player is in a game.
+    InGame {
+        /// The address of the server.
+        server: String,
+
+        /// The short name of the map (e.g. `e1m1`).
+        map: String,
+
+        /// The full name of the level, as sent by the server.
+        level_name: String,
+
+        /// The number of connected players.
+        players: usize,
+
+        /// The maximum number of players the server allows.
+        max_players: usize,
+    },
+}
+
+impl fmt::Display for PresenceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PresenceState::Menu => write!(f, "In menus"),
+            PresenceState::Connecting { ref server } => write!(f, "Connecting to {}", server),
+            PresenceState::InGame {
+                ref server,
+                ref map,
+                players,
+                max_players,
+                ..
+            } => write!(
+                f,
+                "Playing {} on {} ({}/{})",
+                map, server, players, max_players
+            ),
+        }
+    }
+}
+
+impl PresenceState {
+    /// Returns a window title describing this state.
+    ///
+    /// `base` is the title shown when the player is not in a game.
+    pub fn window_title(&self, base: &str) -> String {
+        match *self {
+            PresenceState::Menu => base.to_owned(),
+            PresenceState::Connecting { ref server } => {
+                format!("{} - connecting to {}", base, server)
+            }
+            PresenceState::InGame {
+                ref server,
+                ref map,
+                ..
+            } => format!("{} - {} ({})", base, map, server),
+        }
+    }
+}
+
+/// Tracks the current `PresenceState` and notifies callbacks when it changes.
+pub struct Presence {
+    state: RefCell<PresenceState>,
+    callbacks: RefCell<Vec<Box<dyn Fn(&PresenceState)>>>,
+}
+
+impl Presence {
+    /// Creates a new `Presence` in the `Menu` state with no callbacks.
+    pub fn new() -> Presence {
+        Presence {
+            state: RefCell::new(PresenceState::Menu),
+            callbacks: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Registers a callback to be invoked whenever the state changes.
+    ///
+    /// The callback is invoked immediately with the current state.
+    pub fn add_callback(&self, callback: Box<dyn Fn(&PresenceState)>) {
+        callback(&self.state.borrow());
+        self.callbacks.borrow_mut().push(callback);
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> PresenceState {
+        self.state.borrow().clone()
+    }
+
+    /// Updates the current state.
+    ///
+    /// Callbacks are only invoked if `new_state` differs from the current state.
+    pub fn update(&self, new_state: PresenceState) {
+        if *self.state.borrow() == new_state {
+            return;
+        }
+
+        self.state.replace(new_state);
+
+        let state = self.state.borrow();
+        for callback in self.callbacks.borrow().iter() {
+            callback(&state);
+        }
+    }
+}
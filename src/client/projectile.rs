@@ -0,0 +1,328 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Client-side prediction of simple projectiles.
+//!
+//! With `cl_predict_projectiles` enabled, the client spawns nails and rockets
+//! locally as soon as the attack button is pressed, rather than waiting a full
+//! round trip for the server to send them. Predicted projectiles are purely
+//! visual: they don't collide with anything, and each one is removed as soon
+//! as the server's projectile appears near its path, or after a short timeout.
+
+use crate::{
+    client::entity::ClientEntity,
+    common::{engine, math::Angles, net::ItemFlags},
+};
+
+use cgmath::{Deg, InnerSpace as _, Vector3};
+use chrono::Duration;
+
+/// The longest a predicted projectile is shown without a matching server projectile.
+const PREDICTION_TIMEOUT_MS: i64 = 500;
+
+/// How far a server projectile may be from a predicted path and still match it.
+const MATCH_DISTANCE: f32 = 48.0;
+
+/// Projectiles are launched from this height above the player's origin.
+const LAUNCH_HEIGHT: f32 = 16.0;
+
+/// The kinds of projectile the client can predict.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProjectileKind {
+    Nail,
+    SuperNail,
+    Rocket,
+}
+
+impl ProjectileKind {
+    /// Returns the kind of projectile fired by the given weapon, if it is predictable.
+    pub fn from_weapon(weapon: ItemFlags) -> Option<ProjectileKind> {
+        if weapon == ItemFlags::NAILGUN {
+            Some(ProjectileKind::Nail)
+        } else if weapon == ItemFlags::SUPER_NAILGUN {
+            Some(ProjectileKind::SuperNail)
+        } else if weapon == ItemFlags::ROCKET_LAUNCHER {
+            Some(ProjectileKind::Rocket)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of the model the server uses for this projectile.
+    pub fn model_name(&self) -> &'static str {
+        match *self {
+            ProjectileKind::Nail => "progs/spike.mdl",
+            ProjectileKind::SuperNail => "progs/s_spike.mdl",
+            ProjectileKind::Rocket => "progs/missile.mdl",
+        }
+    }
+
+    /// Returns the projectile's speed in units per second.
+    pub fn speed(&self) -> f32 {
+        // nails and rockets happen to fly at the same speed
+        1000.0
+    }
+
+    /// Returns the time between shots of the weapon that fires this projectile.
+    pub fn refire_interval(&self) -> Duration {
+        match *self {
+            ProjectileKind::Nail | ProjectileKind::SuperNail => Duration::milliseconds(100),
+            ProjectileKind::Rocket => Duration::milliseconds(800),
+        }
+    }
+}
+
+/// Returns the forward vector for the given view angles.
+///
+/// As in the original engine, positive pitch looks down.
+pub fn forward_vector(pitch: Deg<f32>, yaw: Deg<f32>) -> Vector3<f32> {
+    let (sp, cp) = pitch.0.to_radians().sin_cos();
+    let (sy, cy) = yaw.0.to_radians().sin_cos();
+    Vector3::new(cp * cy, cp * sy, -sp)
+}
+
+#[derive(Debug)]
+struct PredictedProjectile {
+    model_id: usize,
+    spawn_time: Duration,
+    start: Vector3<f32>,
+    velocity: Vector3<f32>,
+    entity: ClientEntity,
+}
+
+impl PredictedProjectile {
+    fn origin_at(&self, time: Duration) -> Vector3<f32> {
+        self.start + self.velocity * engine::duration_to_f32(time - self.spawn_time)
+    }
+
+    // Returns the distance from `point` to the part of this projectile's path
+    // traveled so far.
+    fn distance_to_path(&self, point: Vector3<f32>, time: Duration) -> f32 {
+        let end = self.origin_at(time);
+        let path = end - self.start;
+        let len2 = path.magnitude2();
+        let t = if len2 == 0.0 {
+            0.0
+        } else {
+            ((point - self.start).dot(path) / len2).max(0.0).min(1.0)
+        };
+
+        (self.start + path * t - point).magnitude()
+    }
+}
+
+/// Spawns, moves and retires locally predicted projectiles.
+#[derive(Debug)]
+pub struct ProjectilePredictor {
+    projectiles: Vec<PredictedProjectile>,
+    next_fire: Duration,
+
+    // server entities that were already in flight when the newest projectile
+    // was predicted, and so can't be its server counterpart
+    known_entities: Vec<usize>,
+}
+
+impl ProjectilePredictor {
+    pub fn new() -> ProjectilePredictor {
+        ProjectilePredictor {
+            projectiles: Vec::new(),
+            next_fire: Duration::zero(),
+            known_entities: Vec::new(),
+        }
+    }
+
+    /// Spawns a predicted projectile if the weapon is ready to fire.
+    ///
+    /// `origin` and `angles` are the player's origin and view angles, and
+    /// `known_entities` lists the server projectiles currently in flight. Returns `true` if a projectile was
+    /// spawned.
+    pub fn fire<I>(
+        &mut self,
+        time: Duration,
+        kind: ProjectileKind,
+        model_id: usize,
+        origin: Vector3<f32>,
+        angles: Angles,
+        known_entities: I,
+    ) -> bool
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        if time < self.next_fire {
+            return false;
+        }
+
+        self.next_fire = time + kind.refire_interval();
+        self.known_entities.clear();
+        self.known_entities.extend(known_entities);
+
+        let start = origin + Vector3::new(0.0, 0.0, LAUNCH_HEIGHT);
+        let velocity = forward_vector(angles.pitch, angles.yaw) * kind.speed();
+
+        let mut entity = ClientEntity::uninitialized();
+        entity.model_id = model_id;
+        entity.origin = start;
+        // models are pitched up for positive values, the view the other way
+        entity.angles = Vector3::new(-angles.pitch, angles.yaw, Deg(0.0));
+
+        self.projectiles.push(PredictedProjectile {
+            model_id,
+            spawn_time: time,
+            start,
+            velocity,
+            entity,
+        });
+
+        true
+    }
+
+    /// Moves predicted projectiles to their positions at `time` and removes
+    /// those that have a server counterpart or have timed out.
+    ///
+    /// `server_projectiles` yields the entity ID, model ID and origin of each
+    /// projectile entity sent by the server.
+    pub fn update<I>(&mut self, time: Duration, server_projectiles: I)
+    where
+        I: IntoIterator<Item = (usize, usize, Vector3<f32>)>,
+    {
+        let timeout = Duration::milliseconds(PREDICTION_TIMEOUT_MS);
+        self.projectiles
+            .retain(|p| time >= p.spawn_time && time - p.spawn_time < timeout);
+
+        for (ent_id, model_id, origin) in server_projectiles {
+            if self.known_entities.contains(&ent_id) {
+                continue;
+            }
+
+            // each server projectile replaces the oldest prediction it matches
+            let matched = self.projectiles.iter().position(|p| {
+                p.model_id == model_id && p.distance_to_path(origin, time) < MATCH_DISTANCE
+            });
+
+            if let Some(i) = matched {
+                self.projectiles.remove(i);
+                self.known_entities.push(ent_id);
+            }
+        }
+
+        for p in self.projectiles.iter_mut() {
+            p.entity.origin = p.origin_at(time);
+        }
+    }
+
+    /// Removes all predicted projectiles.
+    pub fn clear(&mut self) {
+        self.projectiles.clear();
+        self.known_entities.clear();
+        self.next_fire = Duration::zero();
+    }
+
+    /// Returns the entities to draw for the predicted projectiles.
+    pub fn iter_entities(&self) -> impl Iterator<Item = &ClientEntity> {
+        self.projectiles.iter().map(|p| &p.entity)
+    }
+
+    /// Returns the number of projectiles currently predicted.
+    pub fn len(&self) -> usize {
+        self.projectiles.len()
+    }
+
+    /// Returns `true` if no projectiles are currently predicted.
+    pub fn is_empty(&self) -> bool {
+        self.projectiles.is_empty()
+    }
+}
+
+impl Default for ProjectilePredictor {
+    fn default() -> ProjectilePredictor {
+        ProjectilePredictor::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fire_nail(predictor: &mut ProjectilePredictor, time_ms: i64) -> bool {
+        predictor.fire(
+            Duration::milliseconds(time_ms),
+            ProjectileKind::Nail,
+            3,
+            Vector3::new(0.0, 0.0, 0.0),
+            Angles::zero(),
+            vec![7],
+        )
+    }
+
+    #[test]
+    fn test_forward_vector() {
+        let fwd = forward_vector(Deg(0.0), Deg(90.0));
+        assert!((fwd - Vector3::new(0.0, 1.0, 0.0)).magnitude() < 1e-6);
+
+        // looking down
+        let down = forward_vector(Deg(90.0), Deg(0.0));
+        assert!((down - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn test_fire_respects_refire_interval() {
+        let mut predictor = ProjectilePredictor::new();
+        assert!(fire_nail(&mut predictor, 0));
+        assert!(!fire_nail(&mut predictor, 50));
+        assert!(fire_nail(&mut predictor, 100));
+        assert_eq!(predictor.len(), 2);
+    }
+
+    #[test]
+    fn test_update_moves_and_expires() {
+        let mut predictor = ProjectilePredictor::new();
+        fire_nail(&mut predictor, 0);
+
+        predictor.update(Duration::milliseconds(100), vec![]);
+        let origin = predictor.iter_entities().next().unwrap().origin;
+        assert!((origin - Vector3::new(100.0, 0.0, LAUNCH_HEIGHT)).magnitude() < 1e-3);
+
+        predictor.update(Duration::milliseconds(PREDICTION_TIMEOUT_MS), vec![]);
+        assert!(predictor.is_empty());
+    }
+
+    #[test]
+    fn test_update_reconciles_with_server() {
+        let mut predictor = ProjectilePredictor::new();
+        fire_nail(&mut predictor, 0);
+        let time = Duration::milliseconds(100);
+
+        // entity 7 was already in flight, a different model doesn't match
+        // and entity 9 is too far from the path
+        predictor.update(
+            time,
+            vec![
+                (7, 3, Vector3::new(20.0, 0.0, LAUNCH_HEIGHT)),
+                (8, 4, Vector3::new(20.0, 0.0, LAUNCH_HEIGHT)),
+                (9, 3, Vector3::new(20.0, 200.0, LAUNCH_HEIGHT)),
+            ],
+        );
+        assert_eq!(predictor.len(), 1);
+
+        // the server's nail trails the prediction along the same path
+        predictor.update(time, vec![(10, 3, Vector3::new(20.0, 4.0, LAUNCH_HEIGHT))]);
+        assert_eq!(predictor.len(), 0);
+    }
+}
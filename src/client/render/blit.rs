@@ -1,4 +1,8 @@
-use crate::client::render::{pipeline::Pipeline, ui::quad::QuadPipeline, GraphicsState};
+use crate::client::render::{
+    pipeline::{Pipeline, ShaderCompiler},
+    ui::quad::QuadPipeline,
+    GraphicsState,
+};
 
 lazy_static! {
     pub static ref BIND_GROUP_LAYOUT_DESCRIPTOR_BINDINGS: [Vec<wgpu::BindGroupLayoutEntry>; 1] = [
@@ -33,7 +37,7 @@ pub struct BlitPipeline {
 impl BlitPipeline {
     pub fn new(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         input: &wgpu::TextureView,
     ) -> BlitPipeline {
         let (pipeline, bind_group_layouts) = BlitPipeline::create(device, compiler, &[], 1);
@@ -76,7 +80,7 @@ impl BlitPipeline {
         }
     }
 
-    pub fn rebuild(&mut self, device: &wgpu::Device, compiler: &mut shaderc::Compiler) {
+    pub fn rebuild(&mut self, device: &wgpu::Device, compiler: &mut ShaderCompiler) {
         let layout_refs: Vec<_> = self.bind_group_layouts.iter().collect();
         let pipeline = BlitPipeline::recreate(device, compiler, &layout_refs, 1);
         self.pipeline = pipeline;
@@ -21,6 +21,15 @@
 use crate::common::console::CvarRegistry;
 
 pub fn register_cvars(cvars: &CvarRegistry) {
+    cvars.register_archive("gl_subdivide_size", "32").unwrap();
+    cvars.register("r_drawviewmodel", "1").unwrap();
+    cvars.register_archive("r_dynamic", "1").unwrap();
+    cvars.register("r_fullbright", "0").unwrap();
+    cvars.register_archive("r_lerpmodels", "1").unwrap();
+    cvars.register_archive("r_meshcache", "1").unwrap();
     cvars.register("r_lightmap", "0").unwrap();
     cvars.register("r_msaa_samples", "4").unwrap();
+    cvars.register_archive("r_particles", "2").unwrap();
+    cvars.register_archive("r_wateralpha", "1").unwrap();
+    cvars.register_archive("r_waterwarp", "1").unwrap();
 }
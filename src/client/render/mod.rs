@@ -57,19 +57,29 @@ mod world;
 
 pub use cvars::register_cvars;
 pub use error::{RenderError, RenderErrorKind};
-pub use palette::Palette;
-pub use pipeline::Pipeline;
+pub use palette::{translate_player_colors, ColorblindMode, Palette};
+pub use pipeline::{Pipeline, ShaderCompiler, SHADER_NAMES};
 pub use postprocess::PostProcessRenderer;
 pub use target::{RenderTarget, RenderTargetResolve, SwapChainTarget};
-pub use ui::{hud::HudState, UiOverlay, UiRenderer, UiState};
+pub use ui::{
+    console::{output_position_at, ConsoleSlide},
+    hud::HudState,
+    hud_layout::{HudLayout, HUD_LAYOUT_FILE},
+    UiOverlay, UiRenderer, UiState,
+};
 pub use world::{
-    deferred::{DeferredRenderer, DeferredUniforms, PointLight},
+    cache::{self as mesh_cache, MeshCache},
+    deferred::{
+        DeferredRenderer, DeferredUniforms, PointLight, LIGHT_MODE_CLASSIC, LIGHT_MODE_PER_PIXEL,
+    },
+    skybox::Skybox,
     Camera, WorldRenderer,
 };
 
 use std::{
     borrow::Cow,
     cell::{Cell, Ref, RefCell, RefMut},
+    io::Read,
     mem::size_of,
     rc::Rc,
 };
@@ -86,6 +96,7 @@ use crate::{
             deferred::DeferredPipeline,
             particle::ParticlePipeline,
             postprocess::{self, PostProcessPipeline},
+            skybox,
             sprite::SpritePipeline,
             EntityUniforms,
         },
@@ -163,6 +174,49 @@ pub fn create_texture<'a>(
     texture
 }
 
+// Loads the palette of the active game directory, with fullbrights taken from
+// its colormap if it has one.
+fn load_palette(vfs: &Vfs) -> Palette {
+    let mut palette = Palette::load(vfs, "gfx/palette.lmp");
+
+    let mut colormap = Vec::new();
+    match vfs
+        .open("gfx/colormap.lmp")
+        .map_err(Error::from)
+        .and_then(|mut f| Ok(f.read_to_end(&mut colormap)?))
+    {
+        Ok(_) => palette.set_colormap(&colormap),
+        Err(e) => warn!("Couldn't load gfx/colormap.lmp: {}", e),
+    }
+
+    palette
+}
+
+// Replaces the compiler's shader overrides with the sources found under `shaders/`, returning the
+// number of shaders loaded. Shaders which aren't found there use the built-in sources.
+fn load_shader_overrides(vfs: &Vfs, compiler: &mut ShaderCompiler) -> usize {
+    compiler.clear_overrides();
+
+    let mut loaded = 0;
+    for name in SHADER_NAMES {
+        let mut file = match vfs.open(format!("shaders/{}", name)) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+
+        let mut source = String::new();
+        match file.read_to_string(&mut source) {
+            Ok(_) => {
+                compiler.set_override(name, source);
+                loaded += 1;
+            }
+            Err(e) => warn!("Couldn't read shaders/{}: {}", name, e),
+        }
+    }
+
+    loaded
+}
+
 pub struct DiffuseData<'a> {
     pub rgba: Cow<'a, [u8]>,
 }
@@ -266,10 +320,14 @@ pub struct GraphicsState {
     default_lightmap: wgpu::Texture,
     default_lightmap_view: wgpu::TextureView,
 
+    // bound to sky textures when no sky box is loaded
+    default_skybox: wgpu::Texture,
+    default_skybox_view: wgpu::TextureView,
+
     vfs: Rc<Vfs>,
     palette: Palette,
     gfx_wad: Wad,
-    compiler: RefCell<shaderc::Compiler>,
+    compiler: RefCell<ShaderCompiler>,
 }
 
 impl GraphicsState {
@@ -280,9 +338,13 @@ impl GraphicsState {
         sample_count: u32,
         vfs: Rc<Vfs>,
     ) -> Result<GraphicsState, Error> {
-        let palette = Palette::load(&vfs, "gfx/palette.lmp");
+        let palette = load_palette(&vfs);
         let gfx_wad = Wad::load(vfs.open("gfx.wad")?).unwrap();
-        let mut compiler = shaderc::Compiler::new().unwrap();
+        let mut compiler = ShaderCompiler::new();
+        let overrides = load_shader_overrides(&vfs, &mut compiler);
+        if overrides > 0 {
+            info!("Loaded {} shaders from shaders/", overrides);
+        }
 
         let initial_pass_target = InitialPassTarget::new(&device, size, sample_count);
         let deferred_pass_target = DeferredPassTarget::new(&device, size, sample_count);
@@ -407,6 +469,14 @@ impl GraphicsState {
         );
         let default_lightmap_view = default_lightmap.create_default_view();
 
+        let default_skybox = skybox::create_skybox_texture(
+            &device,
+            &queue,
+            1,
+            &[0, 0, 0, 0xFF].repeat(skybox::SKYBOX_SUFFIXES.len()),
+        );
+        let default_skybox_view = default_skybox.create_default_view();
+
         Ok(GraphicsState {
             device,
             queue,
@@ -435,6 +505,8 @@ impl GraphicsState {
             lightmap_sampler,
             default_lightmap,
             default_lightmap_view,
+            default_skybox,
+            default_skybox_view,
             vfs,
             palette,
             gfx_wad,
@@ -502,6 +574,8 @@ impl GraphicsState {
         );
         self.deferred_pipeline
             .rebuild(&self.device, &mut self.compiler.borrow_mut(), sample_count);
+        self.particle_pipeline
+            .rebuild(&self.device, &mut self.compiler.borrow_mut(), sample_count);
         self.postprocess_pipeline.rebuild(
             &self.device,
             &mut self.compiler.borrow_mut(),
@@ -515,6 +589,35 @@ impl GraphicsState {
             .rebuild(&self.device, &mut self.compiler.borrow_mut());
     }
 
+    /// Reloads shader sources from the `shaders` directory and rebuilds all render pipelines.
+    ///
+    /// Shaders which aren't found there use the sources built into the binary. Returns the number
+    /// of shaders loaded.
+    pub fn reload_shaders(&mut self) -> usize {
+        let loaded = load_shader_overrides(&self.vfs, &mut self.compiler.borrow_mut());
+        self.recreate_pipelines(self.sample_count.get());
+        loaded
+    }
+
+    /// Switches to a new virtual filesystem, such as after a change of game directory.
+    ///
+    /// This reloads the palette, colormap and `gfx.wad`, so anything built from
+    /// them (such as the UI renderer) must be recreated afterwards. If `gfx.wad`
+    /// can't be loaded, nothing is changed.
+    pub fn set_vfs(&mut self, vfs: Rc<Vfs>) -> Result<(), Error> {
+        self.gfx_wad = Wad::load(vfs.open("gfx.wad")?)?;
+        self.palette = load_palette(&vfs);
+        self.particle_pipeline = ParticlePipeline::new(
+            &self.device,
+            &self.queue,
+            &mut self.compiler.borrow_mut(),
+            self.sample_count.get(),
+            &self.palette,
+        );
+        self.vfs = vfs;
+        Ok(())
+    }
+
     pub fn device(&self) -> &wgpu::Device {
         &self.device
     }
@@ -547,6 +650,25 @@ impl GraphicsState {
         self.entity_uniform_buffer.borrow_mut()
     }
 
+    /// Checks that every resource belonging to the previous map has been
+    /// released and resets the allocators they were drawn from.
+    ///
+    /// This should be called after the previous map's `WorldRenderer` has been
+    /// dropped and before the next one is created.
+    pub fn reset_map_resources(&self) -> Result<(), Error> {
+        self.entity_uniform_buffer.borrow().clear()
+    }
+
+    /// Releases this frame's transient resources and checks that none outlived it.
+    ///
+    /// This should be called once per frame, after the frame has been submitted.
+    /// Uniform blocks released during the frame become available for reuse.
+    pub fn end_frame(&self) -> Result<(), Error> {
+        self.quad_pipeline.uniform_buffer_blocks_mut().clear();
+        self.quad_pipeline.uniform_buffer().end_frame()?;
+        self.entity_uniform_buffer.borrow().end_frame()
+    }
+
     pub fn diffuse_sampler(&self) -> &wgpu::Sampler {
         &self.diffuse_sampler
     }
@@ -559,6 +681,10 @@ impl GraphicsState {
         &self.default_lightmap_view
     }
 
+    pub fn default_skybox_view(&self) -> &wgpu::TextureView {
+        &self.default_skybox_view
+    }
+
     pub fn lightmap_sampler(&self) -> &wgpu::Sampler {
         &self.lightmap_sampler
     }
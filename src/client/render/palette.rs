@@ -6,9 +6,155 @@ use crate::{
 };
 
 use byteorder::ReadBytesExt;
+use num::FromPrimitive;
+
+// palette rows (groups of 16 colors) relevant to colorblind remapping
+const ROW_LIGHT_BLUE: u8 = 2;
+const ROW_GREEN: u8 = 3;
+const ROW_RED: u8 = 4;
+const ROW_PEACH: u8 = 7;
+const ROW_MAGENTA: u8 = 9;
+const ROW_YELLOW: u8 = 12;
+const ROW_BLUE: u8 = 13;
+
+// light levels in gfx/colormap.lmp, from brightest to darkest
+const COLORMAP_LEVELS: usize = 64;
+
+// palette rows recolored to match a player's shirt and pants colors
+const ROW_SHIRT: u8 = 1;
+const ROW_PANTS: u8 = 6;
+
+// Moves a palette index to the same shade in a different row.
+//
+// Rows 8 and above run from light to dark rather than dark to light, so the
+// column has to be flipped when moving between the two halves of the palette.
+fn move_to_row(index: u8, row: u8) -> u8 {
+    let col = index % 16;
+    let col = if (index < 128) == (row < 8) {
+        col
+    } else {
+        15 - col
+    };
+
+    row * 16 + col
+}
+
+/// Recolors the shirt and pants of a player skin to the given palette rows.
+pub fn translate_player_colors(indices: &[u8], top: u8, bottom: u8) -> Vec<u8> {
+    indices
+        .iter()
+        .map(|&index| match index / 16 {
+            ROW_SHIRT => move_to_row(index, top & 0x0F),
+            ROW_PANTS => move_to_row(index, bottom & 0x0F),
+            _ => index,
+        })
+        .collect()
+}
+
+/// A colorblind-friendly color remapping mode, selected by `v_colorblind`.
+///
+/// Remapping is applied only when colors are displayed, so network data and
+/// game logic are unaffected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum ColorblindMode {
+    /// No remapping.
+    None = 0,
+
+    /// Red-blind. Reds are shifted toward yellow and greens toward blue.
+    Protanopia = 1,
+
+    /// Green-blind. Reds are shifted toward magenta and greens toward blue.
+    Deuteranopia = 2,
+
+    /// Blue-blind. Blues are shifted toward magenta and yellows toward red.
+    Tritanopia = 3,
+}
+
+impl ColorblindMode {
+    /// Returns the mode corresponding to the given value of `v_colorblind`.
+    ///
+    /// Unknown values disable remapping.
+    pub fn from_cvar(value: f32) -> ColorblindMode {
+        ColorblindMode::from_i32(value as i32).unwrap_or(ColorblindMode::None)
+    }
+
+    /// Remaps a palette index to a distinguishable alternative.
+    ///
+    /// Fullbright colors are never remapped.
+    pub fn remap_index(&self, index: u8) -> u8 {
+        use ColorblindMode::*;
+
+        if index > 223 {
+            return index;
+        }
+
+        match (*self, index / 16) {
+            (Protanopia, ROW_RED) => move_to_row(index, ROW_YELLOW),
+            (Deuteranopia, ROW_RED) => move_to_row(index, ROW_MAGENTA),
+            (Protanopia, ROW_GREEN) | (Deuteranopia, ROW_GREEN) => move_to_row(index, ROW_BLUE),
+            (Tritanopia, ROW_BLUE) => move_to_row(index, ROW_MAGENTA),
+            (Tritanopia, ROW_LIGHT_BLUE) => move_to_row(index, ROW_PEACH),
+            (Tritanopia, ROW_YELLOW) => move_to_row(index, ROW_RED),
+            _ => index,
+        }
+    }
+
+    /// Remaps a player's shirt or pants color, given as a palette row.
+    ///
+    /// Unlike `remap_index`, each pair of confusable rows is swapped, so that
+    /// teams which had different colors still do after remapping.
+    pub fn remap_player_color(&self, row: u8) -> u8 {
+        use ColorblindMode::*;
+
+        let swaps: &[(u8, u8)] = match *self {
+            None => &[],
+            Protanopia => &[(ROW_RED, ROW_YELLOW), (ROW_GREEN, ROW_BLUE)],
+            Deuteranopia => &[(ROW_RED, ROW_MAGENTA), (ROW_GREEN, ROW_BLUE)],
+            Tritanopia => &[
+                (ROW_BLUE, ROW_MAGENTA),
+                (ROW_LIGHT_BLUE, ROW_PEACH),
+                (ROW_YELLOW, ROW_RED),
+            ],
+        };
+
+        let row = row & 0x0F;
+        swaps
+            .iter()
+            .find_map(|&(a, b)| match row {
+                r if r == a => Some(b),
+                r if r == b => Some(a),
+                _ => Option::None,
+            })
+            .unwrap_or(row)
+    }
+
+    /// Remaps an RGB color, such as a screen tint, to a distinguishable alternative.
+    pub fn remap_rgb(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let [r, g, b] = rgb;
+        match *self {
+            ColorblindMode::None => rgb,
+
+            // swap green and blue so that green tints don't resemble red ones
+            ColorblindMode::Protanopia | ColorblindMode::Deuteranopia => [r, b, g],
+
+            // swap red and blue so that blue tints don't resemble yellow ones
+            ColorblindMode::Tritanopia => [b, g, r],
+        }
+    }
+}
 
 pub struct Palette {
     rgb: [[u8; 3]; 256],
+    fullbright: [bool; 256],
+}
+
+// the last two rows of the stock palette are unaffected by lighting
+fn default_fullbrights() -> [bool; 256] {
+    let mut fullbright = [false; 256];
+    for (i, f) in fullbright.iter_mut().enumerate() {
+        *f = i > 223;
+    }
+    fullbright
 }
 
 impl Palette {
@@ -24,7 +170,10 @@ impl Palette {
             }
         }
 
-        Palette { rgb }
+        Palette {
+            rgb,
+            fullbright: default_fullbrights(),
+        }
     }
 
     pub fn load<S>(vfs: &Vfs, path: S) -> Palette
@@ -41,7 +190,31 @@ impl Palette {
             }
         }
 
-        Palette { rgb }
+        Palette {
+            rgb,
+            fullbright: default_fullbrights(),
+        }
+    }
+
+    /// Takes the set of fullbright colors from a colormap such as `gfx/colormap.lmp`.
+    ///
+    /// A color is fullbright if every light level of the colormap maps it to
+    /// itself. Black maps to itself at every level too, but lighting has no
+    /// effect on it, so it is never marked. Mods with custom colormaps can
+    /// change which colors these are.
+    pub fn set_colormap(&mut self, colormap: &[u8]) {
+        if colormap.len() < COLORMAP_LEVELS * 256 {
+            warn!(
+                "Colormap too short ({} bytes), keeping fullbrights",
+                colormap.len()
+            );
+            return;
+        }
+
+        for (i, f) in self.fullbright.iter_mut().enumerate() {
+            *f = self.rgb[i] != [0, 0, 0]
+                && (0..COLORMAP_LEVELS).all(|level| colormap[level * 256 + i] as usize == i);
+        }
     }
 
     // TODO: this will not render console characters correctly, as they use index 0 (black) to
@@ -65,7 +238,7 @@ impl Palette {
                         rgba.push(self.rgb[*index as usize][component]);
                     }
                     rgba.push(0xFF);
-                    fullbright.push(if i > 223 { 0xFF } else { 0 });
+                    fullbright.push(if self.fullbright[i as usize] { 0xFF } else { 0 });
                 }
             }
         }
@@ -80,3 +253,88 @@ impl Palette {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translate_player_colors() {
+        // shirt, pants and a few colors that aren't recolored
+        let indices = [16, 19, 31, 96, 100, 0, 50, 255];
+
+        // red shirt, green pants (both dark to light, like the originals)
+        assert_eq!(
+            translate_player_colors(&indices, 4, 3),
+            vec![64, 67, 79, 48, 52, 0, 50, 255]
+        );
+
+        // yellow shirt, blue pants (both light to dark, so shades are flipped)
+        assert_eq!(
+            translate_player_colors(&indices, 12, 13),
+            vec![207, 204, 192, 223, 219, 0, 50, 255]
+        );
+
+        // only the low four bits select a row
+        assert_eq!(
+            translate_player_colors(&indices, 0x14, 0x23),
+            translate_player_colors(&indices, 4, 3)
+        );
+
+        // the default colors leave the skin unchanged
+        assert_eq!(translate_player_colors(&indices, 1, 6), indices.to_vec());
+    }
+
+    #[test]
+    fn test_remap_player_color() {
+        use ColorblindMode::*;
+
+        assert_eq!(Protanopia.remap_player_color(ROW_RED), ROW_YELLOW);
+        assert_eq!(Protanopia.remap_player_color(ROW_YELLOW), ROW_RED);
+        assert_eq!(Deuteranopia.remap_player_color(ROW_GREEN), ROW_BLUE);
+        assert_eq!(Tritanopia.remap_player_color(ROW_BLUE), ROW_MAGENTA);
+
+        // only the low four bits select a row
+        assert_eq!(Protanopia.remap_player_color(0x10 | ROW_RED), ROW_YELLOW);
+
+        for &mode in &[None, Protanopia, Deuteranopia, Tritanopia] {
+            let mut rows: Vec<u8> = (0..16).map(|row| mode.remap_player_color(row)).collect();
+
+            // the default colors are left alone
+            assert_eq!(rows[ROW_SHIRT as usize], ROW_SHIRT, "{:?}", mode);
+            assert_eq!(rows[ROW_PANTS as usize], ROW_PANTS, "{:?}", mode);
+
+            // different teams never end up with the same color
+            rows.sort();
+            assert_eq!(rows, (0..16).collect::<Vec<u8>>(), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_set_colormap() {
+        // a grayscale ramp, so that only index 0 is black
+        let data: Vec<u8> = (0..768).map(|i| (i / 3) as u8).collect();
+        let mut palette = Palette::new(&data);
+
+        // every level darkens to black except for a few colors
+        let mut colormap = vec![0; COLORMAP_LEVELS * 256];
+        for level in 0..COLORMAP_LEVELS {
+            for &i in &[0, 5, 240] {
+                colormap[level * 256 + i] = i as u8;
+            }
+        }
+
+        // one level is enough to make a color respond to light
+        colormap[256 + 5] = 4;
+        colormap[256 + 7] = 7;
+
+        palette.set_colormap(&colormap);
+        let fullbrights: Vec<_> = (0..256).filter(|&i| palette.fullbright[i]).collect();
+        assert_eq!(fullbrights, vec![240]);
+
+        // a truncated colormap leaves the fullbrights alone
+        palette.set_colormap(&colormap[..256]);
+        let fullbrights: Vec<_> = (0..256).filter(|&i| palette.fullbright[i]).collect();
+        assert_eq!(fullbrights, vec![240]);
+    }
+}
@@ -18,27 +18,97 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use std::mem::size_of;
+use std::{collections::HashMap, mem::size_of};
 
 use crate::common::util::{any_as_u32_slice, Pod};
 
-/// The `Pipeline` trait, which allows render pipelines to be defined more-or-less declaratively.
-
-fn create_shader<S>(
-    device: &wgpu::Device,
-    compiler: &mut shaderc::Compiler,
-    name: S,
-    kind: shaderc::ShaderKind,
-    source: S,
-) -> wgpu::ShaderModule
-where
-    S: AsRef<str>,
-{
-    log::debug!("creating shader {}", name.as_ref());
-    let spirv = compiler
-        .compile_into_spirv(source.as_ref(), kind, name.as_ref(), "main", None)
-        .unwrap();
-    device.create_shader_module(wgpu::ShaderModuleSource::SpirV(spirv.as_binary()))
+/// The file names of the shaders which may be replaced at runtime.
+pub const SHADER_NAMES: &[&str] = &[
+    "alias.frag",
+    "alias.vert",
+    "blit.frag",
+    "blit.vert",
+    "brush.frag",
+    "brush.vert",
+    "deferred.frag",
+    "deferred.vert",
+    "glyph.frag",
+    "glyph.vert",
+    "particle.frag",
+    "particle.vert",
+    "postprocess.frag",
+    "postprocess.vert",
+    "quad.frag",
+    "quad.vert",
+    "sprite.frag",
+    "sprite.vert",
+];
+
+/// Compiles GLSL shaders to SPIR-V, preferring sources loaded at runtime over the built-in ones.
+pub struct ShaderCompiler {
+    compiler: shaderc::Compiler,
+
+    // shader sources loaded at runtime, keyed by file name
+    overrides: HashMap<String, String>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> ShaderCompiler {
+        ShaderCompiler {
+            compiler: shaderc::Compiler::new().unwrap(),
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Replaces the built-in source of the named shader for pipelines created from now on.
+    pub fn set_override<S>(&mut self, name: S, source: String)
+    where
+        S: AsRef<str>,
+    {
+        self.overrides.insert(name.as_ref().to_owned(), source);
+    }
+
+    /// Restores the built-in sources of all shaders for pipelines created from now on.
+    pub fn clear_overrides(&mut self) {
+        self.overrides.clear();
+    }
+
+    fn create_shader<S>(
+        &mut self,
+        device: &wgpu::Device,
+        name: S,
+        kind: shaderc::ShaderKind,
+        source: S,
+    ) -> wgpu::ShaderModule
+    where
+        S: AsRef<str>,
+    {
+        log::debug!("creating shader {}", name.as_ref());
+
+        // a broken override shouldn't take down the renderer, so fall back to the built-in source
+        if let Some(src) = self.overrides.get(name.as_ref()) {
+            match self
+                .compiler
+                .compile_into_spirv(src, kind, name.as_ref(), "main", None)
+            {
+                Ok(spirv) => {
+                    return device
+                        .create_shader_module(wgpu::ShaderModuleSource::SpirV(spirv.as_binary()))
+                }
+                Err(e) => log::error!(
+                    "Couldn't compile {}, using built-in shader: {}",
+                    name.as_ref(),
+                    e
+                ),
+            }
+        }
+
+        let spirv = self
+            .compiler
+            .compile_into_spirv(source.as_ref(), kind, name.as_ref(), "main", None)
+            .unwrap();
+        device.create_shader_module(wgpu::ShaderModuleSource::SpirV(spirv.as_binary()))
+    }
 }
 
 pub enum PushConstantUpdate<T> {
@@ -169,7 +239,7 @@ pub trait Pipeline {
     /// `RenderPipeline`. This permits the reuse of `BindGroupLayout`s between pipelines.
     fn create(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         bind_group_layout_prefix: &[wgpu::BindGroupLayout],
         sample_count: u32,
     ) -> (wgpu::RenderPipeline, Vec<wgpu::BindGroupLayout>) {
@@ -201,16 +271,14 @@ pub trait Pipeline {
             device.create_pipeline_layout(&desc)
         };
 
-        let vertex_shader = create_shader(
+        let vertex_shader = compiler.create_shader(
             device,
-            compiler,
             format!("{}.vert", Self::name()).as_str(),
             shaderc::ShaderKind::Vertex,
             Self::vertex_shader(),
         );
-        let fragment_shader = create_shader(
+        let fragment_shader = compiler.create_shader(
             device,
-            compiler,
             format!("{}.frag", Self::name()).as_str(),
             shaderc::ShaderKind::Fragment,
             Self::fragment_shader(),
@@ -248,7 +316,7 @@ pub trait Pipeline {
     /// Pipelines must be reconstructed when the MSAA sample count is changed.
     fn recreate(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         bind_group_layouts: &[&wgpu::BindGroupLayout],
         sample_count: u32,
     ) -> wgpu::RenderPipeline {
@@ -261,16 +329,14 @@ pub trait Pipeline {
                 Self::fragment_push_constant_range(),
             ],
         });
-        let vertex_shader = create_shader(
+        let vertex_shader = compiler.create_shader(
             device,
-            compiler,
             format!("{}.vert", Self::name()).as_str(),
             shaderc::ShaderKind::Vertex,
             Self::vertex_shader(),
         );
-        let fragment_shader = create_shader(
+        let fragment_shader = compiler.create_shader(
             device,
-            compiler,
             format!("{}.frag", Self::name()).as_str(),
             shaderc::ShaderKind::Fragment,
             Self::fragment_shader(),
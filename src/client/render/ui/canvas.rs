@@ -0,0 +1,250 @@
+use crate::client::render::ui::{
+    glyph::{GlyphRendererCommand, GLYPH_HEIGHT, GLYPH_WIDTH},
+    layout::{Anchor, Layout, ScreenPosition, Size},
+    quad::{QuadRendererCommand, QuadTexture},
+};
+
+/// The width of the original minimum Quake resolution.
+pub const VIRTUAL_WIDTH: i32 = 320;
+
+/// The height of the original minimum Quake resolution.
+pub const VIRTUAL_HEIGHT: i32 = 200;
+
+/// A virtual screen placed on the display, for drawing text and pictures in 2D.
+///
+/// Coordinates are in virtual pixels from the top-left corner of the canvas, with y increasing
+/// downward, as in the original software renderer. Everything drawn on the canvas is scaled by
+/// the same factor, so layouts written for a 320x200 screen keep their proportions at any
+/// resolution.
+///
+/// A new canvas is centered on the display; use `anchored` to pin it elsewhere.
+#[derive(Clone, Copy, Debug)]
+pub struct Canvas {
+    width: i32,
+    height: i32,
+    scale: f32,
+    screen_anchor: Anchor,
+    canvas_anchor: Anchor,
+}
+
+impl Canvas {
+    /// Creates a canvas of the given virtual size drawn at `scale`.
+    pub fn new(width: i32, height: i32, scale: f32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            scale,
+            screen_anchor: Anchor::CENTER,
+            canvas_anchor: Anchor::CENTER,
+        }
+    }
+
+    /// Returns this canvas moved so that `canvas_anchor` on the canvas sits at `screen_anchor`
+    /// on the display.
+    ///
+    /// For example, `anchored(Anchor::BOTTOM_CENTER, Anchor::BOTTOM_CENTER)` lines the bottom
+    /// edge of the canvas up with the bottom of the display.
+    pub fn anchored(self, screen_anchor: Anchor, canvas_anchor: Anchor) -> Canvas {
+        Canvas {
+            screen_anchor,
+            canvas_anchor,
+            ..self
+        }
+    }
+
+    /// Creates a 320x200 canvas drawn at `scale`.
+    pub fn classic(scale: f32) -> Canvas {
+        Canvas::new(VIRTUAL_WIDTH, VIRTUAL_HEIGHT, scale)
+    }
+
+    /// Creates a canvas of the given virtual size at the largest whole scale that fits the
+    /// display.
+    ///
+    /// The scale is never less than 1, so very small displays crop the canvas instead.
+    pub fn fit(width: i32, height: i32, display_width: u32, display_height: u32) -> Canvas {
+        let scale_x = display_width as i32 / width.max(1);
+        let scale_y = display_height as i32 / height.max(1);
+        Canvas::new(width, height, scale_x.min(scale_y).max(1) as f32)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Returns the screen position of the virtual coordinates `(x, y)`.
+    pub fn position(&self, x: i32, y: i32) -> ScreenPosition {
+        // anchor coordinates are measured up from the bottom of the canvas
+        let (anchor_x, anchor_y) = self
+            .canvas_anchor
+            .to_xy(self.width.max(0) as u32, self.height.max(0) as u32);
+
+        ScreenPosition::Relative {
+            anchor: self.screen_anchor,
+            x_ofs: x - anchor_x,
+            y_ofs: self.height - y - anchor_y,
+        }
+    }
+
+    /// Returns the width of `text` in virtual pixels.
+    pub fn text_width<S>(text: S) -> i32
+    where
+        S: AsRef<str>,
+    {
+        (text.as_ref().chars().count() * GLYPH_WIDTH) as i32
+    }
+
+    /// Returns a command drawing a single character with its `anchor` at `(x, y)`.
+    pub fn glyph(&self, glyph_id: u8, x: i32, y: i32, anchor: Anchor) -> GlyphRendererCommand {
+        GlyphRendererCommand::Glyph {
+            glyph_id,
+            position: self.position(x, y),
+            anchor,
+            scale: self.scale,
+        }
+    }
+
+    /// Returns a command drawing a line of text with its `anchor` at `(x, y)`.
+    pub fn text<S>(&self, text: S, x: i32, y: i32, anchor: Anchor) -> GlyphRendererCommand
+    where
+        S: Into<String>,
+    {
+        GlyphRendererCommand::Text {
+            text: text.into(),
+            position: self.position(x, y),
+            anchor,
+            scale: self.scale,
+        }
+    }
+
+    /// Returns commands drawing each line of `text` centered horizontally, starting at `y`.
+    pub fn centered_lines<S>(&self, text: S, y: i32) -> Vec<GlyphRendererCommand>
+    where
+        S: AsRef<str>,
+    {
+        text.as_ref()
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                self.text(
+                    line,
+                    self.width / 2,
+                    y + (i * GLYPH_HEIGHT) as i32,
+                    Anchor::TOP_CENTER,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns a command drawing a picture at its natural size with its `anchor` at `(x, y)`.
+    pub fn quad<'a>(
+        &self,
+        texture: &'a QuadTexture,
+        x: i32,
+        y: i32,
+        anchor: Anchor,
+    ) -> QuadRendererCommand<'a> {
+        QuadRendererCommand {
+            texture,
+            layout: Layout {
+                position: self.position(x, y),
+                anchor,
+                size: Size::Scale { factor: self.scale },
+            },
+        }
+    }
+
+    /// Returns a command stretching a picture over `width` by `height` virtual pixels, with its
+    /// top-left corner at `(x, y)`.
+    pub fn fill<'a>(
+        &self,
+        texture: &'a QuadTexture,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> QuadRendererCommand<'a> {
+        QuadRendererCommand {
+            texture,
+            layout: Layout {
+                position: self.position(x, y),
+                anchor: Anchor::TOP_LEFT,
+                size: Size::Absolute {
+                    width: (width.max(0) as f32 * self.scale) as u32,
+                    height: (height.max(0) as f32 * self.scale) as u32,
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn offsets(position: ScreenPosition) -> (i32, i32) {
+        match position {
+            ScreenPosition::Relative { x_ofs, y_ofs, .. } => (x_ofs, y_ofs),
+            ScreenPosition::Absolute(_) => panic!("expected a relative position"),
+        }
+    }
+
+    #[test]
+    fn test_position() {
+        let canvas = Canvas::classic(2.0);
+        assert_eq!(offsets(canvas.position(0, 0)), (-160, 100));
+        assert_eq!(offsets(canvas.position(160, 100)), (0, 0));
+        assert_eq!(offsets(canvas.position(320, 200)), (160, -100));
+
+        // display coordinates are scaled, virtual coordinates are not
+        assert_eq!(canvas.position(0, 0).to_xy(640, 400, 2.0), (0, 400));
+    }
+
+    #[test]
+    fn test_anchored() {
+        let canvas = Canvas::classic(2.0).anchored(Anchor::BOTTOM_CENTER, Anchor::BOTTOM_CENTER);
+        assert_eq!(offsets(canvas.position(0, 200)), (-160, 0));
+        assert_eq!(offsets(canvas.position(160, 0)), (0, 200));
+        assert_eq!(canvas.position(0, 200).to_xy(800, 600, 2.0), (80, 0));
+
+        let canvas = Canvas::classic(1.0).anchored(Anchor::TOP_LEFT, Anchor::TOP_LEFT);
+        assert_eq!(offsets(canvas.position(8, 16)), (8, -16));
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(Canvas::fit(320, 200, 640, 480).scale(), 2.0);
+        assert_eq!(Canvas::fit(320, 200, 1920, 1080).scale(), 5.0);
+        assert_eq!(Canvas::fit(320, 200, 200, 100).scale(), 1.0);
+    }
+
+    #[test]
+    fn test_centered_lines() {
+        let canvas = Canvas::classic(1.0);
+        let cmds = canvas.centered_lines("one\ntwo", 70);
+        assert_eq!(cmds.len(), 2);
+
+        match cmds[1] {
+            GlyphRendererCommand::Text {
+                ref text, position, ..
+            } => {
+                assert_eq!(text, "two");
+                assert_eq!(offsets(position), (0, 22));
+            }
+            _ => panic!("expected text"),
+        }
+    }
+
+    #[test]
+    fn test_text_width() {
+        assert_eq!(Canvas::text_width(""), 0);
+        assert_eq!(Canvas::text_width("quake"), 40);
+    }
+}
@@ -1,19 +1,126 @@
 use crate::{
-    client::render::{
-        ui::{
-            glyph::{GlyphRendererCommand, GLYPH_HEIGHT, GLYPH_WIDTH},
-            layout::{Anchor, AnchorCoord, Layout, ScreenPosition, Size},
-            quad::{QuadRendererCommand, QuadTexture},
+    client::{
+        input::chat::ChatLine,
+        render::{
+            ui::{
+                canvas::{Canvas, VIRTUAL_WIDTH},
+                glyph::{GlyphRendererCommand, GLYPH_HEIGHT, GLYPH_WIDTH},
+                layout::{Anchor, AnchorCoord, Layout, ScreenPosition, Size},
+                quad::{QuadRendererCommand, QuadTexture},
+            },
+            GraphicsState,
         },
-        GraphicsState,
     },
-    common::{console::Console, engine, wad::QPic},
+    common::{
+        console::{Console, OutputLevel, OutputPosition},
+        engine,
+        wad::QPic,
+    },
 };
 
+use std::cell::Cell;
+
 use chrono::Duration;
 
 const PAD_LEFT: i32 = GLYPH_WIDTH as i32;
 
+// TODO: take screen proportion as a parameter or cvar
+const PROPORTION: f32 = 0.33;
+
+// TODO: take scale as cvar
+const SCALE: f32 = 2.0;
+
+// maximum number of output lines to draw
+const MAX_OUTPUT_LINES: usize = 100;
+
+// maximum number of recent lines to draw while the console is closed
+const MAX_NOTIFY_LINES: usize = 4;
+
+// height of the canvas holding the input line, the scrollback indicator and the output lines
+const CANVAS_HEIGHT: i32 = ((MAX_OUTPUT_LINES + 3) * GLYPH_HEIGHT) as i32;
+
+// height of the fully open console in units of `scr_conspeed`. the original engine measures the
+// console in lines of a 200-line screen, half of which it covers in game.
+const CONSPEED_HEIGHT: f32 = 100.0;
+
+/// Tracks how far the console is open as it slides down and back up.
+pub struct ConsoleSlide {
+    fraction: Cell<f32>,
+}
+
+impl ConsoleSlide {
+    /// Creates a new `ConsoleSlide` with the console closed.
+    pub fn new() -> ConsoleSlide {
+        ConsoleSlide {
+            fraction: Cell::new(0.0),
+        }
+    }
+
+    /// Moves the console toward fully open if `open` is true and fully closed otherwise.
+    ///
+    /// `speed` is the value of `scr_conspeed`.
+    pub fn update(&self, frame_time: Duration, open: bool, speed: f32) {
+        let step = engine::duration_to_f32(frame_time) * speed / CONSPEED_HEIGHT;
+        let fraction = if open {
+            (self.fraction.get() + step).min(1.0)
+        } else {
+            (self.fraction.get() - step).max(0.0)
+        };
+
+        self.fraction.set(fraction);
+    }
+
+    /// Returns how far the console is open, from 0 (closed) to 1 (fully open).
+    pub fn fraction(&self) -> f32 {
+        self.fraction.get()
+    }
+}
+
+/// Returns the position in the console output under the given screen coordinates.
+///
+/// `open` is how far the console is open, as passed to `ConsoleRenderer::generate_commands`.
+/// `x` and `y` are in physical pixels relative to the top-left corner of the window. Returns
+/// `None` if the coordinates do not fall on a line of output.
+pub fn output_position_at(
+    console: &Console,
+    open: f32,
+    display_width: u32,
+    display_height: u32,
+    x: f64,
+    y: f64,
+) -> Option<OutputPosition> {
+    if x < 0.0 || y < 0.0 || x >= display_width as f64 || y >= display_height as f64 {
+        return None;
+    }
+
+    // screen coordinates for rendering start at the bottom of the window
+    let console_y = AnchorCoord::Proportion(1.0 - PROPORTION * open).to_value(display_height);
+    let y_ofs = (display_height as f64 - y) as f32 / SCALE - console_y as f32 / SCALE;
+    if y_ofs < GLYPH_HEIGHT as f32 {
+        // below the output area (the input line or the game view)
+        return None;
+    }
+
+    let row = (y_ofs / GLYPH_HEIGHT as f32) as usize - 1;
+    if row > MAX_OUTPUT_LINES {
+        return None;
+    }
+
+    let output = console.output();
+    let line = match output.scroll() {
+        0 => row,
+
+        // the first row is taken up by the scrollback indicator
+        _ if row == 0 => return None,
+        scroll => scroll + row - 1,
+    };
+    let line_len = output.lines().nth(line)?.len();
+    let x_ofs = (x as f32 / SCALE - (PAD_LEFT + 1) as f32).max(0.0);
+    let column = ((x_ofs / GLYPH_WIDTH as f32) as usize).min(line_len.saturating_sub(1));
+
+    Some(OutputPosition { line, column })
+}
+
 pub struct ConsoleRenderer {
     conback: QuadTexture,
 }
@@ -28,21 +135,67 @@ impl ConsoleRenderer {
         ConsoleRenderer { conback }
     }
 
+    /// Draws the output lines printed within the last `notify_time` at the top of the screen.
+    pub fn generate_notify_commands(
+        &self,
+        console: &Console,
+        notify_time: Duration,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        let canvas = Canvas::classic(SCALE).anchored(Anchor::TOP_LEFT, Anchor::TOP_LEFT);
+        let output = console.output();
+        let lines: Vec<(&[char], OutputLevel)> = output
+            .recent(notify_time)
+            .zip(output.levels())
+            .take(MAX_NOTIFY_LINES)
+            .collect();
+
+        // oldest line at the top
+        for (row, (line, level)) in lines.iter().rev().enumerate() {
+            let highlight = level.highlight_len(line.len());
+            let text: String = line
+                .iter()
+                .enumerate()
+                .map(|(i, &c)| if i < highlight { alternate(c) } else { c })
+                .collect();
+            glyph_cmds.push(canvas.text(
+                text,
+                PAD_LEFT,
+                (row * GLYPH_HEIGHT) as i32,
+                Anchor::TOP_LEFT,
+            ));
+        }
+    }
+
+    /// Draws the chat message being typed below the notify lines.
+    pub fn generate_chat_commands(
+        &self,
+        line: &ChatLine,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        let canvas = Canvas::classic(SCALE).anchored(Anchor::TOP_LEFT, Anchor::TOP_LEFT);
+
+        // trailing cursor
+        glyph_cmds.push(canvas.text(
+            format!("{}_", line.prompt()),
+            PAD_LEFT,
+            (MAX_NOTIFY_LINES * GLYPH_HEIGHT) as i32,
+            Anchor::TOP_LEFT,
+        ));
+    }
+
     pub fn generate_commands<'a>(
         &'a self,
         console: &Console,
+        open: f32,
         time: Duration,
         quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
         glyph_cmds: &mut Vec<GlyphRendererCommand>,
     ) {
-        // TODO: take screen proportion as a parameter or cvar
-        let proportion = 0.33;
-
-        // TODO: take scale as cvar
-        let scale = 2.0;
+        let scale = SCALE;
         let console_anchor = Anchor {
             x: AnchorCoord::Zero,
-            y: AnchorCoord::Proportion(1.0 - proportion),
+            y: AnchorCoord::Proportion(1.0 - PROPORTION * open),
         };
 
         // draw console background
@@ -64,56 +217,58 @@ impl ConsoleRenderer {
             scale,
         });
 
+        // text is laid out on a canvas whose bottom edge is the bottom of the console
+        let canvas = Canvas::new(VIRTUAL_WIDTH, CANVAS_HEIGHT, scale)
+            .anchored(console_anchor, Anchor::BOTTOM_LEFT);
+        let row_y = |row: usize| CANVAS_HEIGHT - (row * GLYPH_HEIGHT) as i32;
+
         // draw input line
-        glyph_cmds.push(GlyphRendererCommand::Glyph {
-            glyph_id: ']' as u8,
-            position: ScreenPosition::Relative {
-                anchor: console_anchor,
-                x_ofs: PAD_LEFT,
-                y_ofs: 0,
-            },
-            anchor: Anchor::BOTTOM_LEFT,
-            scale,
-        });
+        glyph_cmds.push(canvas.glyph(']' as u8, PAD_LEFT, row_y(0), Anchor::BOTTOM_LEFT));
         let input_text = console.get_string();
-        glyph_cmds.push(GlyphRendererCommand::Text {
-            text: input_text,
-            position: ScreenPosition::Relative {
-                anchor: console_anchor,
-                x_ofs: PAD_LEFT + GLYPH_WIDTH as i32,
-                y_ofs: 0,
-            },
-            anchor: Anchor::BOTTOM_LEFT,
-            scale,
-        });
+        glyph_cmds.push(canvas.text(
+            input_text,
+            PAD_LEFT + GLYPH_WIDTH as i32,
+            row_y(0),
+            Anchor::BOTTOM_LEFT,
+        ));
         // blink cursor in half-second intervals
         if engine::duration_to_f32(time).fract() > 0.5 {
-            glyph_cmds.push(GlyphRendererCommand::Glyph {
-                glyph_id: 11,
-                position: ScreenPosition::Relative {
-                    anchor: console_anchor,
-                    x_ofs: PAD_LEFT + (GLYPH_WIDTH * (console.cursor() + 1)) as i32,
-                    y_ofs: 0,
-                },
-                anchor: Anchor::BOTTOM_LEFT,
-                scale,
-            });
+            glyph_cmds.push(canvas.glyph(
+                11,
+                PAD_LEFT + (GLYPH_WIDTH * (console.cursor() + 1)) as i32,
+                row_y(0),
+                Anchor::BOTTOM_LEFT,
+            ));
         }
 
         // draw previous output
-        for (line_id, line) in console.output().lines().enumerate() {
-            // TODO: implement scrolling
-            if line_id > 100 {
+        let output = console.output();
+        let selection = output.selection();
+        let scroll = output.scroll();
+
+        // when scrolled back, mark that there are more lines below
+        let first_row = if scroll > 0 {
+            glyph_cmds.push(canvas.text(
+                "^   ".repeat(MAX_OUTPUT_LINES / 4),
+                PAD_LEFT + 1,
+                row_y(1),
+                Anchor::BOTTOM_LEFT,
+            ));
+            2
+        } else {
+            1
+        };
+
+        let lines = output.lines().zip(output.levels());
+        for (row, (line_id, (line, level))) in lines.enumerate().skip(scroll).enumerate() {
+            if row > MAX_OUTPUT_LINES {
                 break;
             }
 
-            for (chr_id, chr) in line.iter().enumerate() {
-                let position = ScreenPosition::Relative {
-                    anchor: console_anchor,
-                    x_ofs: PAD_LEFT + (1 + chr_id * GLYPH_WIDTH) as i32,
-                    y_ofs: ((line_id + 1) * GLYPH_HEIGHT) as i32,
-                };
+            // warnings and errors are highlighted in the alternate character set
+            let highlight = level.highlight_len(line.len());
 
+            for (chr_id, chr) in line.iter().enumerate() {
                 let c = if *chr as u32 > std::u8::MAX as u32 {
                     warn!(
                         "char \"{}\" (U+{:4}) cannot be displayed in the console",
@@ -124,13 +279,31 @@ impl ConsoleRenderer {
                     *chr
                 };
 
-                glyph_cmds.push(GlyphRendererCommand::Glyph {
-                    glyph_id: c as u8,
-                    position,
-                    anchor: Anchor::BOTTOM_LEFT,
-                    scale,
+                // draw selected text using the opposite character set
+                let selected = selection.map_or(false, |sel| {
+                    sel.contains(OutputPosition {
+                        line: line_id,
+                        column: chr_id,
+                    })
                 });
+                let glyph_id = if selected != (chr_id < highlight) {
+                    c as u8 ^ 0x80
+                } else {
+                    c as u8
+                };
+
+                glyph_cmds.push(canvas.glyph(
+                    glyph_id,
+                    PAD_LEFT + (1 + chr_id * GLYPH_WIDTH) as i32,
+                    row_y(row + first_row),
+                    Anchor::BOTTOM_LEFT,
+                ));
             }
         }
     }
 }
+
+// Switches a console character between the normal and alternate character sets.
+fn alternate(c: char) -> char {
+    (c as u8 ^ 0x80) as char
+}
@@ -7,7 +7,7 @@ use crate::{
             quad::{QuadPipeline, QuadVertex},
             screen_space_vertex_scale, screen_space_vertex_translate,
         },
-        Extent2d, GraphicsState, Pipeline, TextureData,
+        Extent2d, GraphicsState, Pipeline, ShaderCompiler, TextureData,
     },
     common::util::any_slice_as_bytes,
 };
@@ -72,7 +72,7 @@ pub struct GlyphPipeline {
 impl GlyphPipeline {
     pub fn new(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) -> GlyphPipeline {
         let (pipeline, bind_group_layouts) =
@@ -95,7 +95,7 @@ impl GlyphPipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) {
         let layout_refs = self.bind_group_layouts.iter().collect::<Vec<_>>();
@@ -375,6 +375,6 @@ impl GlyphRenderer {
         pass.set_vertex_buffer(0, state.quad_pipeline().vertex_buffer().slice(..));
         pass.set_vertex_buffer(1, state.glyph_pipeline().instance_buffer().slice(..));
         pass.set_bind_group(0, &self.const_bind_group, &[]);
-        pass.draw(0..6, 0..commands.len() as u32);
+        pass.draw(0..6, 0..instances.len() as u32);
     }
 }
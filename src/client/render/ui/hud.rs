@@ -4,25 +4,55 @@ use crate::{
     client::{
         render::{
             ui::{
-                glyph::GlyphRendererCommand,
-                layout::{Anchor, Layout, ScreenPosition, Size},
+                canvas::Canvas,
+                glyph::{GlyphRendererCommand, GLYPH_HEIGHT},
+                hud_layout::{ElementLayout, HudElement, HudLayout},
+                layout::{Anchor, AnchorCoord, Layout, ScreenPosition, Size},
                 quad::{QuadRendererCommand, QuadTexture},
             },
             GraphicsState,
         },
-        IntermissionKind,
+        Crosshair, DamageIndicator, EntityInfo, IntermissionKind, ScoreEntry, StrafeAngles,
     },
     common::{
+        l10n,
         net::{ClientStat, ItemFlags},
         wad::QPic,
     },
 };
 
+use cgmath::Rad;
 use chrono::Duration;
 use num::FromPrimitive as _;
 use strum::IntoEnumIterator as _;
 use strum_macros::EnumIter;
 
+// where `scr_speed` draws the speedometer if `hud.cfg` doesn't place it: just
+// under the crosshair
+const SPEEDOMETER_LAYOUT: ElementLayout = ElementLayout {
+    anchor: Anchor::CENTER,
+    x_ofs: 0,
+    y_ofs: -24,
+    scale: 1.0,
+};
+
+// where `+id` describes the entity under the crosshair: just above it
+const IDENTIFY_LAYOUT: ElementLayout = ElementLayout {
+    anchor: Anchor::CENTER,
+    x_ofs: 0,
+    y_ofs: 32,
+    scale: 1.0,
+};
+
+// custom crosshair drawn with `crosshair 2`, if present
+const CROSSHAIR_IMAGE: &str = "gfx/crosshair.lmp";
+
+// scoreboard rows, in overlay coordinates measured down from the top
+const SCOREBOARD_X: i32 = 80;
+const SCOREBOARD_Y: i32 = 40;
+const SCOREBOARD_ROW_HEIGHT: i32 = 10;
+const SCOREBOARD_MAX_ROWS: usize = 15;
+
 // intermission overlay size
 const OVERLAY_WIDTH: i32 = 320;
 const OVERLAY_HEIGHT: i32 = 200;
@@ -32,17 +62,93 @@ const OVERLAY_Y_OFS: i32 = -OVERLAY_HEIGHT / 2;
 
 const OVERLAY_ANCHOR: Anchor = Anchor::CENTER;
 
+// palette index of the low health border (bright red)
+const LOW_HEALTH_BORDER_COLOR: u8 = 251;
+
+// maximum thickness of the low health border in unscaled pixels
+const LOW_HEALTH_BORDER_WIDTH: f32 = 3.0;
+
+// palette index of damage indicators (bright red)
+const DAMAGE_INDICATOR_COLOR: u8 = 251;
+
+// distance of damage indicators from the center of the screen in unscaled pixels
+const DAMAGE_INDICATOR_RADIUS: f32 = 48.0;
+
+// size of a new damage indicator in unscaled pixels
+const DAMAGE_INDICATOR_SIZE: f32 = 6.0;
+
+// Returns the brightness of the low health pulse at the given phase of a heartbeat.
+//
+// Each beat is a strong pulse followed shortly by a weaker one.
+fn heartbeat_intensity(phase: f32) -> f32 {
+    let pulse =
+        |center: f32, strength: f32| strength * (1.0 - (phase - center).abs() * 10.0).max(0.0);
+
+    pulse(0.05, 1.0).max(pulse(0.3, 0.6))
+}
+
+// `cl_crossy` moves the crosshair down, as in the original engine.
+fn crosshair_position((x_ofs, y_ofs): (i32, i32)) -> ScreenPosition {
+    ScreenPosition::Relative {
+        anchor: Anchor::CENTER,
+        x_ofs,
+        y_ofs: -y_ofs,
+    }
+}
+
 pub enum HudState<'a> {
     InGame {
         items: ItemFlags,
         item_pickup_time: &'a [Duration],
         stats: &'a [i32],
         face_anim_time: Duration,
+
+        /// The phase of the current low health heartbeat in `[0, 1]`, if one is active.
+        low_health_pulse: Option<f32>,
+
+        /// Markers pointing toward the sources of recent damage.
+        damage_indicators: Vec<DamageIndicator>,
+
+        /// How to draw the crosshair.
+        crosshair: Crosshair,
+
+        /// Offset of the crosshair from the center of the screen.
+        crosshair_offset: (i32, i32),
+
+        /// Whether to draw the hit confirmation over the crosshair.
+        hit_confirm: bool,
+
+        /// Time spent on the current level, shown by the clock.
+        level_time: Duration,
+
+        /// Horizontal speed of the player, shown by the speedometer.
+        speed: f32,
+
+        /// Whether `scr_speed` is set.
+        speedometer: bool,
+
+        /// Strafing angles shown under the speedometer, if enabled.
+        strafe_angles: Option<StrafeAngles>,
+
+        /// The entity under the crosshair while `+id` is held.
+        identified: Option<EntityInfo>,
+
+        /// Placement of elements moved by `hud.cfg`.
+        layout: &'a HudLayout,
+
+        /// Ranked players, while `+showscores` is held.
+        scoreboard: Option<Vec<ScoreEntry>>,
+
+        /// The most recent centerprint message, while it's on screen.
+        center_print: Option<&'a str>,
     },
     Intermission {
         kind: &'a IntermissionKind,
         completion_duration: Duration,
         stats: &'a [i32],
+
+        /// Ranked players, shown instead of the level statistics in deathmatch.
+        scoreboard: Option<Vec<ScoreEntry>>,
     },
 }
 
@@ -58,6 +164,7 @@ enum HudTextureId {
     Item { id: ItemId },
     Sigil { id: usize },
     Face { id: FaceId },
+    Disc,
     StatusBar,
     InvBar,
     ScoreBar,
@@ -65,6 +172,7 @@ enum HudTextureId {
     // these are not in gfx.wad
     Complete,
     Intermission,
+    Ranking,
 }
 
 impl std::fmt::Display for HudTextureId {
@@ -81,6 +189,7 @@ impl std::fmt::Display for HudTextureId {
             Item { id } => write!(f, "SB_{}", id),
             Sigil { id } => write!(f, "SB_SIGIL{}", id + 1),
             Face { id } => write!(f, "{}", id),
+            Disc => write!(f, "DISC"),
             StatusBar => write!(f, "SBAR"),
             InvBar => write!(f, "IBAR"),
             ScoreBar => write!(f, "SCOREBAR"),
@@ -88,6 +197,7 @@ impl std::fmt::Display for HudTextureId {
             // these are not in gfx.wad
             Complete => write!(f, "gfx/complete.lmp"),
             Intermission => write!(f, "gfx/inter.lmp"),
+            Ranking => write!(f, "gfx/ranking.lmp"),
         }
     }
 }
@@ -187,6 +297,12 @@ impl std::fmt::Display for FaceId {
 
 pub struct HudRenderer {
     textures: HashMap<HudTextureId, QuadTexture>,
+    crosshair_image: Option<QuadTexture>,
+
+    // one solid color for each player color row
+    player_colors: Vec<QuadTexture>,
+    low_health_border: QuadTexture,
+    damage_indicator: QuadTexture,
 }
 
 impl HudRenderer {
@@ -241,7 +357,7 @@ impl HudRenderer {
         );
 
         // unit variants
-        ids.extend(vec![Colon, Slash, StatusBar, InvBar, ScoreBar].into_iter());
+        ids.extend(vec![Colon, Slash, Disc, StatusBar, InvBar, ScoreBar].into_iter());
 
         let mut textures = HashMap::new();
         for id in ids.into_iter() {
@@ -252,28 +368,47 @@ impl HudRenderer {
         }
 
         // new id list for textures not in gfx.wad
-        let ids = vec![Complete, Intermission];
+        let ids = vec![Complete, Intermission, Ranking];
         for id in ids.into_iter() {
             debug!("Opening {}", id);
             let qpic = QPic::load(state.vfs().open(&format!("{}", id)).unwrap()).unwrap();
             textures.insert(id, QuadTexture::from_qpic(state, &qpic));
         }
 
-        HudRenderer { textures }
+        // custom crosshairs are optional
+        let crosshair_image = state
+            .vfs()
+            .open(CROSSHAIR_IMAGE)
+            .ok()
+            .and_then(|f| QPic::load(f).ok())
+            .map(|qpic| QuadTexture::from_qpic(state, &qpic));
+
+        // a bright shade from the middle of each row, as in the original engine
+        let player_colors = (0..16)
+            .map(|row| QuadTexture::solid(state, row * 16 + 8))
+            .collect();
+
+        let low_health_border = QuadTexture::solid(state, LOW_HEALTH_BORDER_COLOR);
+        let damage_indicator = QuadTexture::solid(state, DAMAGE_INDICATOR_COLOR);
+
+        HudRenderer {
+            textures,
+            crosshair_image,
+            player_colors,
+            low_health_border,
+            damage_indicator,
+        }
     }
 
-    fn cmd_number<'a>(
-        &'a self,
+    // Look up the digit textures for `number`, paired with each digit's x
+    // offset from the left edge of a `max_digits`-wide field. Numbers are
+    // right-aligned, and only the last `max_digits` digits are shown.
+    fn number_digits(
+        &self,
         number: i32,
         alt_color: bool,
         max_digits: usize,
-        screen_anchor: Anchor,
-        screen_x_ofs: i32,
-        screen_y_ofs: i32,
-        quad_anchor: Anchor,
-        scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
-    ) {
+    ) -> Vec<(i32, &QuadTexture)> {
         use HudTextureId::*;
 
         let number_str = format!("{}", number);
@@ -287,22 +422,47 @@ impl HudRenderer {
             place_ofs = (max_digits - number_chars.len()) as i32 * 24;
         }
 
-        for (chr_id, chr) in number_chars.into_iter().skip(skip).enumerate() {
-            let tex_id = match chr {
-                '-' => Minus { alt: alt_color },
-                '0'..='9' => Digit {
-                    alt: alt_color,
-                    value: chr as usize - '0' as usize,
-                },
-                _ => unreachable!(),
-            };
+        number_chars
+            .into_iter()
+            .skip(skip)
+            .enumerate()
+            .map(|(chr_id, chr)| {
+                let tex_id = match chr {
+                    '-' => Minus { alt: alt_color },
+                    '0'..='9' => Digit {
+                        alt: alt_color,
+                        value: chr as usize - '0' as usize,
+                    },
+                    _ => unreachable!(),
+                };
+
+                (
+                    place_ofs + 24 * chr_id as i32,
+                    self.textures.get(&tex_id).unwrap(),
+                )
+            })
+            .collect()
+    }
 
+    fn cmd_number<'a>(
+        &'a self,
+        number: i32,
+        alt_color: bool,
+        max_digits: usize,
+        screen_anchor: Anchor,
+        screen_x_ofs: i32,
+        screen_y_ofs: i32,
+        quad_anchor: Anchor,
+        scale: f32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+    ) {
+        for (x_ofs, texture) in self.number_digits(number, alt_color, max_digits) {
             quad_cmds.push(QuadRendererCommand {
-                texture: self.textures.get(&tex_id).unwrap(),
+                texture,
                 layout: Layout {
                     position: ScreenPosition::Relative {
                         anchor: screen_anchor,
-                        x_ofs: screen_x_ofs + place_ofs + 24 * chr_id as i32,
+                        x_ofs: screen_x_ofs + x_ofs,
                         y_ofs: screen_y_ofs,
                     },
                     anchor: quad_anchor,
@@ -318,53 +478,178 @@ impl HudRenderer {
     // the status bar.
     fn cmd_sbar_quad<'a>(
         &'a self,
+        canvas: &Canvas,
         texture_id: HudTextureId,
         x_ofs: i32,
         y_ofs: i32,
-        scale: f32,
         quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
     ) {
-        quad_cmds.push(QuadRendererCommand {
-            texture: self.textures.get(&texture_id).unwrap(),
-            layout: Layout {
-                position: ScreenPosition::Relative {
-                    anchor: Anchor::BOTTOM_CENTER,
-                    x_ofs: OVERLAY_X_OFS + x_ofs,
-                    y_ofs,
-                },
-                anchor: Anchor::BOTTOM_LEFT,
-                size: Size::Scale { factor: scale },
-            },
-        });
+        quad_cmds.push(canvas.quad(
+            self.textures.get(&texture_id).unwrap(),
+            x_ofs,
+            canvas.height() - y_ofs,
+            Anchor::BOTTOM_LEFT,
+        ));
     }
 
-    // Draw a quad on the status bar.
+    // Draw a number on the status bar.
     //
     // `x_ofs` and `y_ofs` are specified relative to the bottom-left corner of
     // the status bar.
     fn cmd_sbar_number<'a>(
         &'a self,
+        canvas: &Canvas,
         number: i32,
         alt_color: bool,
         max_digits: usize,
         x_ofs: i32,
         y_ofs: i32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+    ) {
+        for (digit_x_ofs, texture) in self.number_digits(number, alt_color, max_digits) {
+            quad_cmds.push(canvas.quad(
+                texture,
+                x_ofs + digit_x_ofs,
+                canvas.height() - y_ofs,
+                Anchor::BOTTOM_LEFT,
+            ));
+        }
+    }
+
+    // Draw a number positioned by `hud.cfg`.
+    fn cmd_layout_number<'a>(
+        &'a self,
+        number: i32,
+        alt_color: bool,
+        max_digits: usize,
+        layout: &ElementLayout,
         scale: f32,
         quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
     ) {
+        // digits are laid out left to right, so align the whole number to the anchor
+        let width = 24 * max_digits as u32;
+        let anchor_x = layout.anchor.x.to_value(width);
+
         self.cmd_number(
             number,
             alt_color,
             max_digits,
-            Anchor::BOTTOM_CENTER,
-            OVERLAY_X_OFS + x_ofs,
-            y_ofs,
-            Anchor::BOTTOM_LEFT,
-            scale,
+            layout.anchor,
+            layout.x_ofs - anchor_x,
+            layout.y_ofs,
+            Anchor {
+                x: AnchorCoord::Zero,
+                y: layout.anchor.y,
+            },
+            scale * layout.scale,
             quad_cmds,
         );
     }
 
+    // Draw text positioned by `hud.cfg`.
+    fn cmd_layout_text(
+        &self,
+        text: String,
+        layout: &ElementLayout,
+        scale: f32,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        glyph_cmds.push(GlyphRendererCommand::Text {
+            text,
+            position: ScreenPosition::Relative {
+                anchor: layout.anchor,
+                x_ofs: layout.x_ofs,
+                y_ofs: layout.y_ofs,
+            },
+            anchor: layout.anchor,
+            scale: scale * layout.scale,
+        });
+    }
+
+    // Draw the elements moved off the status bar or added by `hud.cfg`.
+    fn cmd_layout_elements<'a>(
+        &'a self,
+        layout: &HudLayout,
+        stats: &'a [i32],
+        level_time: Duration,
+        speed: f32,
+        speedometer: bool,
+        strafe_angles: Option<StrafeAngles>,
+        scale: f32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        if let Some(l) = layout.element(HudElement::Health) {
+            let health = stats[ClientStat::Health as usize];
+            self.cmd_layout_number(health, health <= 25, 3, l, scale, quad_cmds);
+        }
+
+        if let Some(l) = layout.element(HudElement::Ammo) {
+            let ammo = stats[ClientStat::Ammo as usize];
+            self.cmd_layout_number(ammo, ammo <= 10, 3, l, scale, quad_cmds);
+        }
+
+        if let Some(l) = layout.element(HudElement::Clock) {
+            let minutes = level_time.num_minutes();
+            let seconds = level_time.num_seconds() - 60 * minutes;
+            self.cmd_layout_text(format!("{}:{:02}", minutes, seconds), l, scale, glyph_cmds);
+        }
+
+        let speed_layout = match layout.element(HudElement::Speed) {
+            Some(l) => Some(*l),
+            None if speedometer => Some(SPEEDOMETER_LAYOUT),
+            None => None,
+        };
+
+        if let Some(l) = speed_layout {
+            self.cmd_layout_text(format!("{}", speed.round() as i32), &l, scale, glyph_cmds);
+
+            // one line below the speed
+            if let Some(angles) = strafe_angles {
+                let angles_layout = ElementLayout {
+                    y_ofs: l.y_ofs - GLYPH_HEIGHT as i32,
+                    ..l
+                };
+                self.cmd_layout_text(
+                    format!("{:.0} / {:.0}", angles.current.0, angles.optimal.0),
+                    &angles_layout,
+                    scale,
+                    glyph_cmds,
+                );
+            }
+        }
+    }
+
+    // Describe the entity under the crosshair, model name above its index,
+    // frame, skin and effects.
+    fn cmd_identify(
+        &self,
+        info: &EntityInfo,
+        scale: f32,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        self.cmd_layout_text(
+            l10n::tr_args(
+                "hud.identify",
+                &[
+                    &info.entity_id.to_string(),
+                    &info.frame_id.to_string(),
+                    &info.skin_id.to_string(),
+                    &format!("{:#x}", info.effects.bits()),
+                ],
+            ),
+            &IDENTIFY_LAYOUT,
+            scale,
+            glyph_cmds,
+        );
+
+        let name_layout = ElementLayout {
+            y_ofs: IDENTIFY_LAYOUT.y_ofs + GLYPH_HEIGHT as i32,
+            ..IDENTIFY_LAYOUT
+        };
+        self.cmd_layout_text(info.model_name.clone(), &name_layout, scale, glyph_cmds);
+    }
+
     // Draw the status bar.
     fn cmd_sbar<'a>(
         &'a self,
@@ -373,6 +658,7 @@ impl HudRenderer {
         item_pickup_time: &'a [Duration],
         stats: &'a [i32],
         face_anim_time: Duration,
+        layout: &HudLayout,
         scale: f32,
         quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
         glyph_cmds: &mut Vec<GlyphRendererCommand>,
@@ -380,13 +666,14 @@ impl HudRenderer {
         use HudTextureId::*;
 
         let sbar = self.textures.get(&StatusBar).unwrap();
-        let sbar_x_ofs = -(sbar.width() as i32) / 2;
+        let canvas = Canvas::new(OVERLAY_WIDTH, OVERLAY_HEIGHT, scale)
+            .anchored(Anchor::BOTTOM_CENTER, Anchor::BOTTOM_CENTER);
 
         // status bar background
-        self.cmd_sbar_quad(StatusBar, 0, 0, scale, quad_cmds);
+        self.cmd_sbar_quad(&canvas, StatusBar, 0, 0, quad_cmds);
 
         // inventory bar background
-        self.cmd_sbar_quad(InvBar, 0, sbar.height() as i32, scale, quad_cmds);
+        self.cmd_sbar_quad(&canvas, InvBar, 0, sbar.height() as i32, quad_cmds);
 
         // weapon slots
         for i in 0..7 {
@@ -394,7 +681,9 @@ impl HudRenderer {
                 let id = WeaponId::from_usize(i).unwrap();
                 let pickup_time = item_pickup_time[i];
                 let delta = time - pickup_time;
-                let frame = if delta >= Duration::milliseconds(100) {
+
+                // newly picked up weapons flash for a second at 10 frames per second
+                let frame = if delta >= Duration::seconds(1) {
                     if stats[ClientStat::ActiveWeapon as usize] as u32
                         == ItemFlags::SHOTGUN.bits() << i
                     {
@@ -404,15 +693,15 @@ impl HudRenderer {
                     }
                 } else {
                     WeaponFrame::Pickup {
-                        frame: (delta.num_milliseconds() * 100) as usize % 5,
+                        frame: (delta.num_milliseconds() / 100) as usize % 5,
                     }
                 };
 
                 self.cmd_sbar_quad(
+                    &canvas,
                     Weapon { id, frame },
                     24 * i as i32,
                     sbar.height() as i32,
-                    scale,
                     quad_cmds,
                 );
             }
@@ -423,16 +712,12 @@ impl HudRenderer {
             let ammo_str = format!("{: >3}", stats[ClientStat::Shells as usize + i]);
             for (chr_id, chr) in ammo_str.chars().enumerate() {
                 if chr != ' ' {
-                    glyph_cmds.push(GlyphRendererCommand::Glyph {
-                        glyph_id: 18 + chr as u8 - '0' as u8,
-                        position: ScreenPosition::Relative {
-                            anchor: Anchor::BOTTOM_CENTER,
-                            x_ofs: sbar_x_ofs + 8 * (6 * i + chr_id) as i32 + 10,
-                            y_ofs: sbar.height() as i32 + 16,
-                        },
-                        anchor: Anchor::BOTTOM_LEFT,
-                        scale,
-                    });
+                    glyph_cmds.push(canvas.glyph(
+                        18 + chr as u8 - '0' as u8,
+                        8 * (6 * i + chr_id) as i32 + 10,
+                        canvas.height() - sbar.height() as i32 - 16,
+                        Anchor::BOTTOM_LEFT,
+                    ));
                 }
             }
         }
@@ -440,52 +725,39 @@ impl HudRenderer {
         // items (keys and powerups)
         for i in 0..6 {
             if items.contains(ItemFlags::from_bits(ItemFlags::KEY_1.bits() << i).unwrap()) {
-                quad_cmds.push(QuadRendererCommand {
-                    texture: self
-                        .textures
-                        .get(&Item {
-                            id: ItemId::from_usize(i).unwrap(),
-                        })
-                        .unwrap(),
-                    layout: Layout {
-                        position: ScreenPosition::Relative {
-                            anchor: Anchor::BOTTOM_CENTER,
-                            x_ofs: sbar_x_ofs + 16 * i as i32 + 192,
-                            y_ofs: sbar.height() as i32,
-                        },
-                        anchor: Anchor::BOTTOM_LEFT,
-                        size: Size::Scale { factor: scale },
+                self.cmd_sbar_quad(
+                    &canvas,
+                    Item {
+                        id: ItemId::from_usize(i).unwrap(),
                     },
-                })
+                    16 * i as i32 + 192,
+                    sbar.height() as i32,
+                    quad_cmds,
+                );
             }
         }
 
         // sigils
         for i in 0..4 {
             if items.contains(ItemFlags::from_bits(ItemFlags::SIGIL_1.bits() << i).unwrap()) {
-                quad_cmds.push(QuadRendererCommand {
-                    texture: self.textures.get(&Sigil { id: i }).unwrap(),
-                    layout: Layout {
-                        position: ScreenPosition::Relative {
-                            anchor: Anchor::BOTTOM_CENTER,
-                            x_ofs: sbar_x_ofs + 8 * i as i32 + 288,
-                            y_ofs: sbar.height() as i32,
-                        },
-                        anchor: Anchor::BOTTOM_LEFT,
-                        size: Size::Scale { factor: scale },
-                    },
-                });
+                self.cmd_sbar_quad(
+                    &canvas,
+                    Sigil { id: i },
+                    8 * i as i32 + 288,
+                    sbar.height() as i32,
+                    quad_cmds,
+                );
             }
         }
 
         // armor
         let armor_width = self.textures.get(&Armor { id: 0 }).unwrap().width() as i32;
         if items.contains(ItemFlags::INVULNERABILITY) {
-            self.cmd_sbar_number(666, true, 3, armor_width, 0, scale, quad_cmds);
-        // TODO draw_disc
+            self.cmd_sbar_number(&canvas, 666, true, 3, armor_width, 0, quad_cmds);
+            self.cmd_sbar_quad(&canvas, Disc, 0, 0, quad_cmds);
         } else {
             let armor = stats[ClientStat::Armor as usize];
-            self.cmd_sbar_number(armor, armor <= 25, 3, armor_width, 0, scale, quad_cmds);
+            self.cmd_sbar_number(&canvas, armor, armor <= 25, 3, armor_width, 0, quad_cmds);
 
             let mut armor_id = None;
             for i in (0..3).rev() {
@@ -496,16 +768,29 @@ impl HudRenderer {
             }
 
             if let Some(a) = armor_id {
-                self.cmd_sbar_quad(a, 0, 0, scale, quad_cmds);
+                self.cmd_sbar_quad(&canvas, a, 0, 0, quad_cmds);
+            }
+        }
+
+        // icon for the current weapon's ammo type
+        for i in 0..4 {
+            if items.contains(ItemFlags::from_bits(ItemFlags::SHELLS.bits() << i).unwrap()) {
+                let id = AmmoId::from_usize(i).unwrap();
+                self.cmd_sbar_quad(&canvas, Ammo { id }, 224, 0, quad_cmds);
+                break;
             }
         }
 
-        // health
-        let health = stats[ClientStat::Health as usize];
-        self.cmd_sbar_number(health, health <= 25, 3, 136, 0, scale, quad_cmds);
+        // health and ammo, unless hud.cfg moves them elsewhere
+        if layout.element(HudElement::Health).is_none() {
+            let health = stats[ClientStat::Health as usize];
+            self.cmd_sbar_number(&canvas, health, health <= 25, 3, 136, 0, quad_cmds);
+        }
 
-        let ammo = stats[ClientStat::Ammo as usize];
-        self.cmd_sbar_number(ammo, ammo <= 10, 3, 248, 0, scale, quad_cmds);
+        if layout.element(HudElement::Ammo).is_none() {
+            let ammo = stats[ClientStat::Ammo as usize];
+            self.cmd_sbar_number(&canvas, ammo, ammo <= 10, 3, 248, 0, quad_cmds);
+        }
 
         let face = if items.contains(ItemFlags::INVISIBILITY | ItemFlags::INVULNERABILITY) {
             FaceId::InvisibleInvulnerable
@@ -529,15 +814,120 @@ impl HudRenderer {
             }
         };
 
-        self.cmd_sbar_quad(Face { id: face }, 112, 0, scale, quad_cmds);
+        self.cmd_sbar_quad(&canvas, Face { id: face }, 112, 0, quad_cmds);
+    }
 
-        // crosshair
-        glyph_cmds.push(GlyphRendererCommand::Glyph {
-            glyph_id: '+' as u8,
-            position: ScreenPosition::Absolute(Anchor::CENTER),
-            anchor: Anchor::TOP_LEFT,
-            scale,
-        });
+    fn cmd_crosshair<'a>(
+        &'a self,
+        crosshair: Crosshair,
+        offset: (i32, i32),
+        scale: f32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        let position = crosshair_position(offset);
+
+        match (crosshair, &self.crosshair_image) {
+            (Crosshair::None, _) => (),
+            (Crosshair::Image, Some(image)) => quad_cmds.push(QuadRendererCommand {
+                texture: image,
+                layout: Layout {
+                    position,
+                    anchor: Anchor::CENTER,
+                    size: Size::Scale { factor: scale },
+                },
+            }),
+            _ => glyph_cmds.push(GlyphRendererCommand::Glyph {
+                glyph_id: '+' as u8,
+                position,
+                anchor: Anchor::TOP_LEFT,
+                scale,
+            }),
+        }
+    }
+
+    // Draw a border around the status bar that pulses with the low health heartbeat.
+    fn cmd_low_health_border<'a>(
+        &'a self,
+        phase: f32,
+        scale: f32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+    ) {
+        let thickness = (heartbeat_intensity(phase) * LOW_HEALTH_BORDER_WIDTH * scale) as u32;
+        if thickness == 0 {
+            return;
+        }
+
+        // the border surrounds both the status bar and the inventory bar
+        let sbar = self.textures.get(&HudTextureId::StatusBar).unwrap();
+        let x_ofs = -(sbar.width() as i32) / 2;
+        let width = sbar.scale_width(scale);
+        let height = 2 * sbar.scale_height(scale);
+
+        let edges = [
+            // bottom and top
+            (x_ofs, 0, Anchor::BOTTOM_LEFT, width, thickness),
+            (
+                x_ofs,
+                2 * sbar.height() as i32,
+                Anchor::TOP_LEFT,
+                width,
+                thickness,
+            ),
+            // left and right
+            (x_ofs, 0, Anchor::BOTTOM_LEFT, thickness, height),
+            (-x_ofs, 0, Anchor::BOTTOM_RIGHT, thickness, height),
+        ];
+
+        for (x_ofs, y_ofs, anchor, width, height) in edges.iter().cloned() {
+            quad_cmds.push(QuadRendererCommand {
+                texture: &self.low_health_border,
+                layout: Layout {
+                    position: ScreenPosition::Relative {
+                        anchor: Anchor::BOTTOM_CENTER,
+                        x_ofs,
+                        y_ofs,
+                    },
+                    anchor,
+                    size: Size::Absolute { width, height },
+                },
+            });
+        }
+    }
+
+    // Draw a marker at the edge of a circle around the crosshair for each damage source.
+    //
+    // Markers shrink as they age.
+    fn cmd_damage_indicators<'a>(
+        &'a self,
+        indicators: &[DamageIndicator],
+        scale: f32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+    ) {
+        for indicator in indicators {
+            let size = (DAMAGE_INDICATOR_SIZE * indicator.remaining * scale).ceil() as u32;
+            if size == 0 {
+                continue;
+            }
+
+            // angles are counterclockwise from straight ahead, so positive angles are to the left
+            let (sin, cos) = Rad::from(indicator.angle).0.sin_cos();
+            quad_cmds.push(QuadRendererCommand {
+                texture: &self.damage_indicator,
+                layout: Layout {
+                    position: ScreenPosition::Relative {
+                        anchor: Anchor::CENTER,
+                        x_ofs: (-sin * DAMAGE_INDICATOR_RADIUS) as i32,
+                        y_ofs: (cos * DAMAGE_INDICATOR_RADIUS) as i32,
+                    },
+                    anchor: Anchor::CENTER,
+                    size: Size::Absolute {
+                        width: size,
+                        height: size,
+                    },
+                },
+            });
+        }
     }
 
     // Draw a quad on the intermission overlay.
@@ -592,6 +982,74 @@ impl HudRenderer {
         );
     }
 
+    // Draw the ranked players: a box in each player's colors holding their
+    // frags, bracketed for the local player, followed by their name.
+    fn cmd_scoreboard<'a>(
+        &'a self,
+        scores: &[ScoreEntry],
+        scale: f32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        let ranking = self.textures.get(&HudTextureId::Ranking).unwrap();
+        let ranking_x = (OVERLAY_WIDTH - ranking.width() as i32) / 2;
+        self.cmd_intermission_quad(
+            HudTextureId::Ranking,
+            ranking_x,
+            OVERLAY_HEIGHT - 8,
+            scale,
+            quad_cmds,
+        );
+
+        let canvas = Canvas::new(OVERLAY_WIDTH, OVERLAY_HEIGHT, scale);
+
+        for (row, score) in scores.iter().take(SCOREBOARD_MAX_ROWS).enumerate() {
+            let y = SCOREBOARD_Y + SCOREBOARD_ROW_HEIGHT * row as i32;
+
+            // shirt color over pants color
+            for (half, color) in [score.colors.top(), score.colors.bottom()]
+                .iter()
+                .enumerate()
+            {
+                quad_cmds.push(canvas.fill(
+                    &self.player_colors[(*color & 0x0F) as usize],
+                    SCOREBOARD_X,
+                    y + 4 * half as i32,
+                    40,
+                    4,
+                ));
+            }
+
+            let frags = if score.local {
+                // the console font's bracket characters
+                format!("\u{10}{: >3}\u{11}", score.frags)
+            } else {
+                format!(" {: >3}", score.frags)
+            };
+
+            glyph_cmds.push(canvas.text(frags, SCOREBOARD_X, y, Anchor::TOP_LEFT));
+            glyph_cmds.push(canvas.text(
+                score.name.clone(),
+                SCOREBOARD_X + 64,
+                y,
+                Anchor::TOP_LEFT,
+            ));
+        }
+    }
+
+    // Draw a centerprint message. Short messages sit a little above the
+    // crosshair; longer ones start nearer the top of the screen.
+    fn cmd_center_print(&self, text: &str, scale: f32, glyph_cmds: &mut Vec<GlyphRendererCommand>) {
+        let canvas = Canvas::new(OVERLAY_WIDTH, OVERLAY_HEIGHT, scale);
+        let y = if text.lines().count() <= 4 {
+            OVERLAY_HEIGHT * 35 / 100
+        } else {
+            48
+        };
+
+        glyph_cmds.extend(canvas.centered_lines(text, y));
+    }
+
     // Draw the intermission overlay.
     fn cmd_intermission_overlay<'a>(
         &'a self,
@@ -603,8 +1061,6 @@ impl HudRenderer {
     ) {
         use HudTextureId::*;
 
-        // TODO: check gametype
-
         self.cmd_intermission_quad(Complete, 64, OVERLAY_HEIGHT - 24, scale, quad_cmds);
         self.cmd_intermission_quad(Intermission, 0, OVERLAY_HEIGHT - 56, scale, quad_cmds);
 
@@ -650,20 +1106,83 @@ impl HudRenderer {
                 item_pickup_time,
                 stats,
                 face_anim_time,
-            } => self.cmd_sbar(
-                time,
-                *items,
-                item_pickup_time,
-                stats,
-                *face_anim_time,
-                scale,
-                quad_cmds,
-                glyph_cmds,
-            ),
+                low_health_pulse,
+                damage_indicators,
+                crosshair,
+                crosshair_offset,
+                hit_confirm,
+                level_time,
+                speed,
+                speedometer,
+                strafe_angles,
+                identified,
+                layout,
+                scoreboard,
+                center_print,
+            } => {
+                self.cmd_sbar(
+                    time,
+                    *items,
+                    item_pickup_time,
+                    stats,
+                    *face_anim_time,
+                    layout,
+                    scale,
+                    quad_cmds,
+                    glyph_cmds,
+                );
+
+                self.cmd_layout_elements(
+                    layout,
+                    stats,
+                    *level_time,
+                    *speed,
+                    *speedometer,
+                    *strafe_angles,
+                    scale,
+                    quad_cmds,
+                    glyph_cmds,
+                );
+
+                if let Some(phase) = low_health_pulse {
+                    self.cmd_low_health_border(*phase, scale, quad_cmds);
+                }
+
+                self.cmd_damage_indicators(damage_indicators, scale, quad_cmds);
+
+                if let Some(info) = identified {
+                    self.cmd_identify(info, scale, glyph_cmds);
+                }
+
+                self.cmd_crosshair(*crosshair, *crosshair_offset, scale, quad_cmds, glyph_cmds);
+
+                if *hit_confirm {
+                    // drawn over the crosshair in the alternate (red) character set
+                    glyph_cmds.push(GlyphRendererCommand::Glyph {
+                        glyph_id: 'x' as u8 | 0x80,
+                        position: crosshair_position(*crosshair_offset),
+                        anchor: Anchor::TOP_LEFT,
+                        scale,
+                    });
+                }
+
+                if let Some(text) = center_print {
+                    self.cmd_center_print(text, scale, glyph_cmds);
+                }
+
+                if let Some(scores) = scoreboard {
+                    self.cmd_scoreboard(scores, scale, quad_cmds, glyph_cmds);
+                }
+            }
+            HudState::Intermission {
+                scoreboard: Some(scores),
+                ..
+            } => self.cmd_scoreboard(scores, scale, quad_cmds, glyph_cmds),
             HudState::Intermission {
                 kind,
                 completion_duration,
                 stats,
+                scoreboard: None,
             } => self.cmd_intermission_overlay(kind, *completion_duration, stats, scale, quad_cmds),
         }
     }
@@ -0,0 +1,199 @@
+//! User-defined placement of individual HUD elements.
+//!
+//! `hud.cfg` contains one element per line in the form
+//!
+//! ```text
+//! <element> <anchor> <x offset> <y offset> [scale]
+//! ```
+//!
+//! where `element` is one of `health`, `ammo`, `clock` or `speed` and
+//! `anchor` is the point on the screen the offsets are measured from (e.g.
+//! `top_left`, `center`, `bottom_right`). Offsets are in HUD pixels, with
+//! positive y pointing up, and `scale` multiplies the HUD scale. Text after
+//! `//` is ignored.
+//!
+//! Health and ammo are drawn on the status bar unless they're listed here.
+//! The clock and speedometer are only drawn if they're listed here.
+
+use std::{collections::HashMap, io::Read};
+
+use crate::{client::render::ui::layout::Anchor, common::vfs::Vfs};
+
+use failure::{Error, ResultExt};
+
+/// The name of the layout file.
+pub const HUD_LAYOUT_FILE: &str = "hud.cfg";
+
+/// A HUD element that can be positioned by `hud.cfg`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HudElement {
+    Health,
+    Ammo,
+
+    /// Time spent on the current level.
+    Clock,
+
+    /// Horizontal speed of the player in units per second.
+    Speed,
+}
+
+impl HudElement {
+    fn from_name(name: &str) -> Option<HudElement> {
+        match name {
+            "health" => Some(HudElement::Health),
+            "ammo" => Some(HudElement::Ammo),
+            "clock" => Some(HudElement::Clock),
+            "speed" => Some(HudElement::Speed),
+            _ => None,
+        }
+    }
+}
+
+fn anchor_from_name(name: &str) -> Option<Anchor> {
+    Some(match name {
+        "bottom_left" => Anchor::BOTTOM_LEFT,
+        "center_left" => Anchor::CENTER_LEFT,
+        "top_left" => Anchor::TOP_LEFT,
+        "bottom_center" => Anchor::BOTTOM_CENTER,
+        "center" => Anchor::CENTER,
+        "top_center" => Anchor::TOP_CENTER,
+        "bottom_right" => Anchor::BOTTOM_RIGHT,
+        "center_right" => Anchor::CENTER_RIGHT,
+        "top_right" => Anchor::TOP_RIGHT,
+        _ => return None,
+    })
+}
+
+/// The placement of a single HUD element.
+#[derive(Clone, Copy, Debug)]
+pub struct ElementLayout {
+    /// The point on the screen the element is positioned relative to.
+    ///
+    /// The same point on the element is placed there, so elements anchored to
+    /// an edge of the screen stay on screen.
+    pub anchor: Anchor,
+
+    /// Offset from `anchor` in HUD pixels.
+    pub x_ofs: i32,
+    pub y_ofs: i32,
+
+    /// Multiplier applied to the HUD scale.
+    pub scale: f32,
+}
+
+/// The placement of every HUD element listed in `hud.cfg`.
+#[derive(Clone, Debug, Default)]
+pub struct HudLayout {
+    elements: HashMap<HudElement, ElementLayout>,
+}
+
+impl HudLayout {
+    /// Parses a layout from the contents of a `hud.cfg` file.
+    pub fn parse(text: &str) -> Result<HudLayout, Error> {
+        let mut elements = HashMap::new();
+
+        for (line_id, line) in text.lines().enumerate() {
+            let line = match line.find("//") {
+                Some(i) => &line[..i],
+                None => line,
+            };
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            let line_num = line_id + 1;
+            ensure!(
+                fields.len() == 4 || fields.len() == 5,
+                "line {}: expected <element> <anchor> <x> <y> [scale]",
+                line_num
+            );
+
+            let element = match HudElement::from_name(fields[0]) {
+                Some(e) => e,
+                None => bail!("line {}: unknown HUD element \"{}\"", line_num, fields[0]),
+            };
+            let anchor = match anchor_from_name(fields[1]) {
+                Some(a) => a,
+                None => bail!("line {}: unknown anchor \"{}\"", line_num, fields[1]),
+            };
+            let x_ofs = fields[2]
+                .parse::<i32>()
+                .with_context(|_| format!("line {}: bad x offset", line_num))?;
+            let y_ofs = fields[3]
+                .parse::<i32>()
+                .with_context(|_| format!("line {}: bad y offset", line_num))?;
+            let scale = match fields.get(4) {
+                Some(s) => s
+                    .parse::<f32>()
+                    .with_context(|_| format!("line {}: bad scale", line_num))?,
+                None => 1.0,
+            };
+
+            elements.insert(
+                element,
+                ElementLayout {
+                    anchor,
+                    x_ofs,
+                    y_ofs,
+                    scale,
+                },
+            );
+        }
+
+        Ok(HudLayout { elements })
+    }
+
+    /// Loads the layout from `hud.cfg`.
+    ///
+    /// If the file doesn't exist, every element keeps its default placement.
+    pub fn load(vfs: &Vfs) -> Result<HudLayout, Error> {
+        let mut file = match vfs.open(HUD_LAYOUT_FILE) {
+            Ok(f) => f,
+            Err(_) => return Ok(HudLayout::default()),
+        };
+
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        HudLayout::parse(&text)
+    }
+
+    /// Returns the placement of `element`, or `None` if it isn't listed.
+    pub fn element(&self, element: HudElement) -> Option<&ElementLayout> {
+        self.elements.get(&element)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        let layout = HudLayout::parse(
+            "// move health to the top left\n\
+             health top_left 8 -8\n\
+             \n\
+             speed center 0 -32 0.5 // under the crosshair\n",
+        )
+        .unwrap();
+
+        let health = layout.element(HudElement::Health).unwrap();
+        assert_eq!((health.x_ofs, health.y_ofs, health.scale), (8, -8, 1.0));
+
+        let speed = layout.element(HudElement::Speed).unwrap();
+        assert_eq!((speed.x_ofs, speed.y_ofs, speed.scale), (0, -32, 0.5));
+
+        assert!(layout.element(HudElement::Ammo).is_none());
+        assert!(layout.element(HudElement::Clock).is_none());
+    }
+
+    #[test]
+    fn test_parse_layout_errors() {
+        assert!(HudLayout::parse("armor top_left 0 0").is_err());
+        assert!(HudLayout::parse("health upper_left 0 0").is_err());
+        assert!(HudLayout::parse("health top_left 0").is_err());
+        assert!(HudLayout::parse("health top_left zero 0").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    client::{
+        connection::ConnectionState,
+        render::{
+            ui::{
+                canvas::{Canvas, VIRTUAL_HEIGHT},
+                glyph::{GlyphRendererCommand, GLYPH_HEIGHT, GLYPH_WIDTH},
+                layout::Anchor,
+                quad::{QuadRendererCommand, QuadTexture},
+            },
+            GraphicsState,
+        },
+        LoadingStatus,
+    },
+    common::{l10n, net::SignOnStage, wad::QPic},
+};
+
+const PLAQUE: &str = "gfx/loading.lmp";
+
+const BAR_LEFT: u8 = 128;
+const BAR_MIDDLE: u8 = 129;
+const BAR_RIGHT: u8 = 130;
+const BAR_HANDLE: u8 = 131;
+
+// width of the progress bar in characters, not counting the ends
+const BAR_WIDTH: i32 = 20;
+
+/// Draws the loading plaque and sign-on progress while connecting to a server.
+///
+/// If the plaque picture can't be loaded, the progress text is drawn without it.
+pub struct LoadingRenderer {
+    plaque: Option<QuadTexture>,
+}
+
+impl LoadingRenderer {
+    pub fn new(state: &GraphicsState) -> LoadingRenderer {
+        let plaque = match state.vfs().open(PLAQUE) {
+            Ok(f) => match QPic::load(f) {
+                Ok(pic) => Some(QuadTexture::from_qpic(state, &pic)),
+                Err(e) => {
+                    warn!("Couldn't load {}: {}", PLAQUE, e);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("Couldn't open {}: {}", PLAQUE, e);
+                None
+            }
+        };
+
+        LoadingRenderer { plaque }
+    }
+
+    /// Generates commands to draw the loading screen on a 320x200 canvas drawn at `scale`.
+    pub fn generate_commands<'a>(
+        &'a self,
+        status: &LoadingStatus,
+        scale: f32,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    ) {
+        let canvas = Canvas::classic(scale);
+        let center_x = canvas.width() / 2;
+
+        // centered above the status bar, as in the original
+        let plaque_height = self.plaque.as_ref().map_or(0, |p| p.height() as i32);
+        let plaque_y = (VIRTUAL_HEIGHT - 24 - plaque_height) / 2;
+        if let Some(ref plaque) = self.plaque {
+            quad_cmds.push(canvas.quad(plaque, center_x, plaque_y, Anchor::TOP_CENTER));
+        }
+
+        let mut y = plaque_y + plaque_height + GLYPH_HEIGHT as i32;
+        for line in &[status.level_name.clone(), map_label(&status.map_name)] {
+            if !line.is_empty() {
+                glyph_cmds.push(canvas.text(line.as_str(), center_x, y, Anchor::TOP_CENTER));
+                y += GLYPH_HEIGHT as i32;
+            }
+        }
+
+        y += GLYPH_HEIGHT as i32;
+        glyph_cmds.push(canvas.text(stage_text(status), center_x, y, Anchor::TOP_CENTER));
+
+        if let Some((loaded, total)) = status.precache {
+            y += 2 * GLYPH_HEIGHT as i32;
+            let x = center_x - (BAR_WIDTH + 2) * GLYPH_WIDTH as i32 / 2;
+            cmd_draw_bar(x, y, fraction(loaded, total), &canvas, glyph_cmds);
+        }
+    }
+}
+
+// Draws a progress bar with its handle `pos` of the way along.
+fn cmd_draw_bar(
+    x: i32,
+    y: i32,
+    pos: f32,
+    canvas: &Canvas,
+    glyph_cmds: &mut Vec<GlyphRendererCommand>,
+) {
+    let glyph_w = GLYPH_WIDTH as i32;
+    glyph_cmds.push(canvas.glyph(BAR_LEFT, x, y, Anchor::TOP_LEFT));
+    for i in 0..BAR_WIDTH {
+        glyph_cmds.push(canvas.glyph(BAR_MIDDLE, x + glyph_w * (i + 1), y, Anchor::TOP_LEFT));
+    }
+    glyph_cmds.push(canvas.glyph(
+        BAR_RIGHT,
+        x + glyph_w * (BAR_WIDTH + 1),
+        y,
+        Anchor::TOP_LEFT,
+    ));
+
+    let handle_x = x + glyph_w + ((glyph_w * (BAR_WIDTH - 1)) as f32 * pos) as i32;
+    glyph_cmds.push(canvas.glyph(BAR_HANDLE, handle_x, y, Anchor::TOP_LEFT));
+}
+
+fn fraction(loaded: usize, total: usize) -> f32 {
+    match total {
+        0 => 1.0,
+        t => (loaded as f32 / t as f32).min(1.0),
+    }
+}
+
+fn map_label(map_name: &str) -> String {
+    match map_name {
+        "" => String::new(),
+        name => format!("({})", name),
+    }
+}
+
+// Describes what the client is waiting for.
+fn stage_text(status: &LoadingStatus) -> String {
+    if let Some((loaded, total)) = status.precache {
+        return l10n::tr_args(
+            "hud.loading.resources",
+            &[&loaded.to_string(), &total.to_string()],
+        );
+    }
+
+    l10n::tr(match status.state {
+        ConnectionState::Connecting | ConnectionState::SignOn(SignOnStage::Not) => {
+            "hud.loading.server_info"
+        }
+        ConnectionState::SignOn(SignOnStage::Prespawn) => "hud.loading.entities",
+        ConnectionState::SignOn(SignOnStage::ClientInfo) => "hud.loading.player_info",
+        ConnectionState::SignOn(SignOnStage::Begin) => "hud.loading.spawning",
+        _ => "hud.loading.loading",
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn status(state: ConnectionState, precache: Option<(usize, usize)>) -> LoadingStatus {
+        LoadingStatus {
+            state,
+            map_name: String::new(),
+            level_name: String::new(),
+            precache,
+        }
+    }
+
+    #[test]
+    fn test_stage_text() {
+        assert_eq!(
+            stage_text(&status(ConnectionState::Connecting, None)),
+            "Waiting for server info"
+        );
+        assert_eq!(
+            stage_text(&status(
+                ConnectionState::SignOn(SignOnStage::Prespawn),
+                Some((3, 40))
+            )),
+            "Loading resources 3/40"
+        );
+        assert_eq!(
+            stage_text(&status(ConnectionState::SignOn(SignOnStage::Begin), None)),
+            "Spawning"
+        );
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!(fraction(0, 0), 1.0);
+        assert_eq!(fraction(5, 20), 0.25);
+        assert_eq!(fraction(30, 20), 1.0);
+    }
+
+    #[test]
+    fn test_map_label() {
+        assert_eq!(map_label(""), "");
+        assert_eq!(map_label("e1m1"), "(e1m1)");
+    }
+}
@@ -5,8 +5,9 @@ use crate::{
         menu::{Item, Menu, MenuBodyView, MenuState, NamedMenuItem},
         render::{
             ui::{
+                canvas::Canvas,
                 glyph::{GlyphRendererCommand, GLYPH_HEIGHT, GLYPH_WIDTH},
-                layout::{Anchor, Layout, ScreenPosition, Size},
+                layout::Anchor,
                 quad::{QuadRendererCommand, QuadTexture},
             },
             GraphicsState,
@@ -17,38 +18,12 @@ use crate::{
 
 use chrono::Duration;
 
-// original minimum Quake resolution
-const MENU_WIDTH: i32 = 320;
-const MENU_HEIGHT: i32 = 200;
-
 const SLIDER_LEFT: u8 = 128;
 const SLIDER_MIDDLE: u8 = 129;
 const SLIDER_RIGHT: u8 = 130;
 const SLIDER_HANDLE: u8 = 131;
 const SLIDER_WIDTH: i32 = 10;
 
-#[derive(Clone, Copy, Debug)]
-enum Align {
-    Left,
-    Center,
-}
-
-impl Align {
-    pub fn x_ofs(&self) -> i32 {
-        match *self {
-            Align::Left => -MENU_WIDTH / 2,
-            Align::Center => 0,
-        }
-    }
-
-    pub fn anchor(&self) -> Anchor {
-        match *self {
-            Align::Left => Anchor::TOP_LEFT,
-            Align::Center => Anchor::TOP_CENTER,
-        }
-    }
-}
-
 pub struct MenuRenderer {
     textures: HashMap<String, QuadTexture>,
 }
@@ -99,64 +74,25 @@ impl MenuRenderer {
         self.textures.get(name.as_ref()).unwrap()
     }
 
-    fn cmd_draw_quad<'state>(
-        &self,
-        texture: &'state QuadTexture,
-        align: Align,
-        x_ofs: i32,
-        y_ofs: i32,
-        scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'state>>,
-    ) {
-        quad_cmds.push(QuadRendererCommand {
-            texture,
-            layout: Layout {
-                position: ScreenPosition::Relative {
-                    anchor: Anchor::CENTER,
-                    x_ofs: align.x_ofs() + x_ofs,
-                    y_ofs: MENU_HEIGHT / 2 + y_ofs,
-                },
-                anchor: align.anchor(),
-                size: Size::Scale { factor: scale },
-            },
-        });
-    }
-
-    fn cmd_draw_glyph(
-        &self,
-        glyph_id: u8,
-        x_ofs: i32,
-        y_ofs: i32,
-        scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+    fn cmd_draw_plaque<'a>(
+        &'a self,
+        canvas: &Canvas,
+        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
     ) {
-        glyph_cmds.push(GlyphRendererCommand::Glyph {
-            glyph_id,
-            position: ScreenPosition::Relative {
-                anchor: Anchor::CENTER,
-                x_ofs: -MENU_WIDTH / 2 + x_ofs,
-                y_ofs: -MENU_HEIGHT / 2 + y_ofs,
-            },
-            anchor: Anchor::TOP_LEFT,
-            scale,
-        });
-    }
-
-    fn cmd_draw_plaque<'a>(&'a self, scale: f32, quad_cmds: &mut Vec<QuadRendererCommand<'a>>) {
         let plaque = self.texture("gfx/qplaque.lmp");
-        self.cmd_draw_quad(plaque, Align::Left, 16, 4, scale, quad_cmds);
+        quad_cmds.push(canvas.quad(plaque, 16, 4, Anchor::TOP_LEFT));
     }
 
     fn cmd_draw_title<'a, S>(
         &'a self,
         name: S,
-        scale: f32,
+        canvas: &Canvas,
         quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
     ) where
         S: AsRef<str>,
     {
         let title = self.texture(name.as_ref());
-        self.cmd_draw_quad(title, Align::Center, 0, 4, scale, quad_cmds);
+        quad_cmds.push(canvas.quad(title, canvas.width() / 2, 4, Anchor::TOP_CENTER));
     }
 
     fn cmd_draw_body_predef<'a, S>(
@@ -164,67 +100,21 @@ impl MenuRenderer {
         name: S,
         cursor_pos: usize,
         time: Duration,
-        scale: f32,
+        canvas: &Canvas,
         quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
     ) where
         S: AsRef<str>,
     {
         let predef = self.texture(name.as_ref());
-        self.cmd_draw_quad(predef, Align::Left, 72, -32, scale, quad_cmds);
+        quad_cmds.push(canvas.quad(predef, 72, 32, Anchor::TOP_LEFT));
         let curs_frame = (time.num_milliseconds() / 100) % 6;
         let curs = self.texture(&format!("gfx/menudot{}.lmp", curs_frame + 1));
-        self.cmd_draw_quad(
+        quad_cmds.push(canvas.quad(
             curs,
-            Align::Left,
             72 - curs.width() as i32,
-            -32 - cursor_pos as i32 * 20,
-            scale,
-            quad_cmds,
-        );
-    }
-
-    fn cmd_draw_item_name<S>(
-        &self,
-        x: i32,
-        y: i32,
-        name: S,
-        scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
-    ) where
-        S: AsRef<str>,
-    {
-        glyph_cmds.push(GlyphRendererCommand::Text {
-            text: name.as_ref().to_string(),
-            position: ScreenPosition::Relative {
-                anchor: Anchor::CENTER,
-                x_ofs: -MENU_WIDTH / 2 + x - GLYPH_WIDTH as i32,
-                y_ofs: -MENU_HEIGHT / 2 + y,
-            },
-            anchor: Anchor::TOP_RIGHT,
-            scale,
-        });
-    }
-
-    fn cmd_draw_item_text<S>(
-        &self,
-        x: i32,
-        y: i32,
-        text: S,
-        scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
-    ) where
-        S: AsRef<str>,
-    {
-        glyph_cmds.push(GlyphRendererCommand::Text {
-            text: text.as_ref().to_string(),
-            position: ScreenPosition::Relative {
-                anchor: Anchor::CENTER,
-                x_ofs: -MENU_WIDTH / 2 + x + GLYPH_WIDTH as i32,
-                y_ofs: -MENU_HEIGHT / 2 + y,
-            },
-            anchor: Anchor::TOP_LEFT,
-            scale,
-        });
+            32 + cursor_pos as i32 * 20,
+            Anchor::TOP_LEFT,
+        ));
     }
 
     fn cmd_draw_slider(
@@ -232,16 +122,16 @@ impl MenuRenderer {
         x: i32,
         y: i32,
         pos: f32,
-        scale: f32,
+        canvas: &Canvas,
         glyph_cmds: &mut Vec<GlyphRendererCommand>,
     ) {
-        self.cmd_draw_glyph(SLIDER_LEFT, x, y, scale, glyph_cmds);
+        glyph_cmds.push(canvas.glyph(SLIDER_LEFT, x, y, Anchor::TOP_LEFT));
         for i in 0..SLIDER_WIDTH {
-            self.cmd_draw_glyph(SLIDER_MIDDLE, x + 8 * (i + 1), y, scale, glyph_cmds);
+            glyph_cmds.push(canvas.glyph(SLIDER_MIDDLE, x + 8 * (i + 1), y, Anchor::TOP_LEFT));
         }
-        self.cmd_draw_glyph(SLIDER_RIGHT, x + 8 * SLIDER_WIDTH, y, scale, glyph_cmds);
+        glyph_cmds.push(canvas.glyph(SLIDER_RIGHT, x + 8 * SLIDER_WIDTH, y, Anchor::TOP_LEFT));
         let handle_x = x + ((8 * (SLIDER_WIDTH - 1)) as f32 * pos) as i32;
-        self.cmd_draw_glyph(SLIDER_HANDLE, handle_x, y, scale, glyph_cmds);
+        glyph_cmds.push(canvas.glyph(SLIDER_HANDLE, handle_x, y, Anchor::TOP_LEFT));
     }
 
     fn cmd_draw_body_dynamic(
@@ -249,27 +139,28 @@ impl MenuRenderer {
         items: &[NamedMenuItem],
         cursor_pos: usize,
         time: Duration,
-        scale: f32,
+        canvas: &Canvas,
         glyph_cmds: &mut Vec<GlyphRendererCommand>,
     ) {
+        let x = 16 + 24 * GLYPH_WIDTH as i32;
+        let value_x = x + GLYPH_WIDTH as i32;
+
         for (item_id, item) in items.iter().enumerate() {
-            let y = MENU_HEIGHT - 32 - (GLYPH_HEIGHT * item_id) as i32;
-            let x = 16 + 24 * GLYPH_WIDTH as i32;
-            self.cmd_draw_item_name(x, y, item.name(), scale, glyph_cmds);
+            let y = 32 + (GLYPH_HEIGHT * item_id) as i32;
+            glyph_cmds.push(canvas.text(item.name(), x - GLYPH_WIDTH as i32, y, Anchor::TOP_RIGHT));
 
             match item.item() {
-                Item::Toggle(toggle) => self.cmd_draw_item_text(
-                    x,
-                    y,
+                Item::Toggle(toggle) => glyph_cmds.push(canvas.text(
                     if toggle.get() { "yes" } else { "no" },
-                    scale,
-                    glyph_cmds,
-                ),
+                    value_x,
+                    y,
+                    Anchor::TOP_LEFT,
+                )),
                 Item::Enum(e) => {
-                    self.cmd_draw_item_text(x, y, e.selected_name(), scale, glyph_cmds)
+                    glyph_cmds.push(canvas.text(e.selected_name(), value_x, y, Anchor::TOP_LEFT))
                 }
                 Item::Slider(slider) => {
-                    self.cmd_draw_slider(x, y, slider.position(), scale, glyph_cmds)
+                    self.cmd_draw_slider(x, y, slider.position(), canvas, glyph_cmds)
                 }
                 Item::TextField(_) => (),
                 _ => (),
@@ -277,13 +168,7 @@ impl MenuRenderer {
         }
 
         if time.num_milliseconds() / 250 % 2 == 0 {
-            self.cmd_draw_glyph(
-                141,
-                200,
-                MENU_HEIGHT - 32 - 8 * cursor_pos as i32,
-                scale,
-                glyph_cmds,
-            );
+            glyph_cmds.push(canvas.glyph(141, 200, 32 + 8 * cursor_pos as i32, Anchor::TOP_LEFT));
         }
     }
 
@@ -298,13 +183,13 @@ impl MenuRenderer {
         let view = active_menu.view();
 
         // TODO: use cvar
-        let scale = 2.0;
+        let canvas = Canvas::classic(2.0);
 
         if view.draw_plaque() {
-            self.cmd_draw_plaque(scale, quad_cmds);
+            self.cmd_draw_plaque(&canvas, quad_cmds);
         }
 
-        self.cmd_draw_title(view.title_path(), scale, quad_cmds);
+        self.cmd_draw_title(view.title_path(), &canvas, quad_cmds);
 
         let cursor_pos = match active_menu.state() {
             MenuState::Active { index } => index,
@@ -313,14 +198,14 @@ impl MenuRenderer {
 
         match *view.body() {
             MenuBodyView::Predefined { ref path } => {
-                self.cmd_draw_body_predef(path, cursor_pos, time, scale, quad_cmds);
+                self.cmd_draw_body_predef(path, cursor_pos, time, &canvas, quad_cmds);
             }
             MenuBodyView::Dynamic => {
                 self.cmd_draw_body_dynamic(
                     &active_menu.items(),
                     cursor_pos,
                     time,
-                    scale,
+                    &canvas,
                     glyph_cmds,
                 );
             }
@@ -1,7 +1,10 @@
+pub mod canvas;
 pub mod console;
 pub mod glyph;
 pub mod hud;
+pub mod hud_layout;
 pub mod layout;
+pub mod loading;
 pub mod menu;
 pub mod quad;
 
@@ -9,18 +12,21 @@ use std::cell::RefCell;
 
 use crate::{
     client::{
+        input::chat::ChatLine,
         menu::Menu,
         render::{
             ui::{
                 console::ConsoleRenderer,
                 glyph::{GlyphRenderer, GlyphRendererCommand},
                 hud::{HudRenderer, HudState},
+                loading::LoadingRenderer,
                 menu::MenuRenderer,
                 quad::{QuadRenderer, QuadRendererCommand, QuadUniforms},
             },
             uniform::{self, DynamicUniformBufferBlock},
             Extent2d, GraphicsState,
         },
+        LoadingStatus,
     },
     common::{console::Console, util::any_slice_as_bytes},
 };
@@ -75,15 +81,30 @@ pub fn screen_space_vertex_transform(
 
 pub enum UiOverlay<'a> {
     Menu(&'a Menu),
-    Console(&'a Console),
+    /// The console, open by the given fraction of its full height.
+    Console(&'a Console, f32),
 }
 
 pub enum UiState<'a> {
     Title {
         overlay: UiOverlay<'a>,
     },
+    /// Connecting to a server and loading its map.
+    Loading {
+        status: &'a LoadingStatus,
+
+        /// The value of `scr_loadingscale`.
+        scale: f32,
+    },
     InGame {
         hud: HudState<'a>,
+
+        /// The console and how long its output lines stay on screen, if they should be shown.
+        notify: Option<(&'a Console, Duration)>,
+
+        /// The chat message being typed, drawn below the console notify lines.
+        chat: Option<&'a ChatLine>,
+
         overlay: Option<UiOverlay<'a>>,
     },
 }
@@ -92,6 +113,7 @@ pub struct UiRenderer {
     console_renderer: ConsoleRenderer,
     menu_renderer: MenuRenderer,
     hud_renderer: HudRenderer,
+    loading_renderer: LoadingRenderer,
     glyph_renderer: GlyphRenderer,
     quad_renderer: QuadRenderer,
 }
@@ -102,6 +124,7 @@ impl UiRenderer {
             console_renderer: ConsoleRenderer::new(state),
             menu_renderer: MenuRenderer::new(state, menu),
             hud_renderer: HudRenderer::new(state),
+            loading_renderer: LoadingRenderer::new(state),
             glyph_renderer: GlyphRenderer::new(state),
             quad_renderer: QuadRenderer::new(state),
         }
@@ -117,9 +140,23 @@ impl UiRenderer {
         quad_commands: &'pass mut Vec<QuadRendererCommand<'pass>>,
         glyph_commands: &'pass mut Vec<GlyphRendererCommand>,
     ) {
-        let (hud_state, overlay) = match ui_state {
-            UiState::Title { overlay } => (None, Some(overlay)),
-            UiState::InGame { hud, overlay } => (Some(hud), overlay.as_ref()),
+        let (hud_state, notify, chat, overlay) = match ui_state {
+            UiState::Title { overlay } => (None, None, None, Some(overlay)),
+            UiState::Loading { status, scale } => {
+                self.loading_renderer.generate_commands(
+                    status,
+                    *scale,
+                    quad_commands,
+                    glyph_commands,
+                );
+                (None, None, None, None)
+            }
+            UiState::InGame {
+                hud,
+                notify,
+                chat,
+                overlay,
+            } => (Some(hud), notify.as_ref(), chat.as_ref(), overlay.as_ref()),
         };
 
         if let Some(hstate) = hud_state {
@@ -127,16 +164,29 @@ impl UiRenderer {
                 .generate_commands(hstate, time, quad_commands, glyph_commands);
         }
 
+        if let Some((console, notify_time)) = notify {
+            self.console_renderer
+                .generate_notify_commands(console, *notify_time, glyph_commands);
+        }
+
+        if let Some(line) = chat {
+            self.console_renderer
+                .generate_chat_commands(line, glyph_commands);
+        }
+
         if let Some(o) = overlay {
             match o {
                 UiOverlay::Menu(menu) => {
                     self.menu_renderer
                         .generate_commands(menu, time, quad_commands, glyph_commands)
                 }
-                UiOverlay::Console(console) => {
-                    self.console_renderer
-                        .generate_commands(console, time, quad_commands, glyph_commands)
-                }
+                UiOverlay::Console(console, open) => self.console_renderer.generate_commands(
+                    console,
+                    *open,
+                    time,
+                    quad_commands,
+                    glyph_commands,
+                ),
             }
         }
 
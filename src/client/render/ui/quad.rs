@@ -10,7 +10,7 @@ use crate::{
             screen_space_vertex_transform,
         },
         uniform::{self, DynamicUniformBuffer, DynamicUniformBufferBlock},
-        Extent2d, GraphicsState, Pipeline, TextureData, DIFFUSE_ATTACHMENT_FORMAT,
+        Extent2d, GraphicsState, Pipeline, ShaderCompiler, TextureData, DIFFUSE_ATTACHMENT_FORMAT,
     },
     common::{util::any_slice_as_bytes, wad::QPic},
 };
@@ -120,7 +120,7 @@ pub struct QuadPipeline {
 impl QuadPipeline {
     pub fn new(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) -> QuadPipeline {
         let (pipeline, bind_group_layouts) =
@@ -146,7 +146,7 @@ impl QuadPipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) {
         let layout_refs = self.bind_group_layouts.iter().collect::<Vec<_>>();
@@ -276,13 +276,20 @@ pub struct QuadTexture {
 
 impl QuadTexture {
     pub fn from_qpic(state: &GraphicsState, qpic: &QPic) -> QuadTexture {
-        let (diffuse_data, _) = state.palette().translate(qpic.indices());
-        let texture = state.create_texture(
-            None,
-            qpic.width(),
-            qpic.height(),
-            &TextureData::Diffuse(diffuse_data),
-        );
+        QuadTexture::from_indices(state, qpic.width(), qpic.height(), qpic.indices())
+    }
+
+    /// Create a 1x1 texture of a single palette color.
+    ///
+    /// This is useful for drawing solid rectangles with `Size::Absolute`.
+    pub fn solid(state: &GraphicsState, color: u8) -> QuadTexture {
+        QuadTexture::from_indices(state, 1, 1, &[color])
+    }
+
+    fn from_indices(state: &GraphicsState, width: u32, height: u32, indices: &[u8]) -> QuadTexture {
+        let (diffuse_data, _) = state.palette().translate(indices);
+        let texture =
+            state.create_texture(None, width, height, &TextureData::Diffuse(diffuse_data));
         let texture_view = texture.create_default_view();
         let bind_group = state
             .device()
@@ -299,8 +306,8 @@ impl QuadTexture {
             texture,
             texture_view,
             bind_group,
-            width: qpic.width(),
-            height: qpic.height(),
+            width,
+            height,
         }
     }
 
@@ -45,22 +45,144 @@ impl UniformArrayFloat {
     }
 }
 
+/// How long a block of a dynamic uniform buffer is expected to stay allocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockLifetime {
+    /// Released before the end of the frame it was allocated in, e.g. UI quads.
+    Frame,
+
+    /// Released when the current map is unloaded, e.g. world and entity transforms.
+    Map,
+}
+
+// Tracks the blocks of a dynamic uniform buffer that are in use. Shared
+// between the buffer and its blocks so that dropped blocks can be reused.
+//
+// The buffer is uploaded once per frame, so a block released during a frame
+// may still be read by draws recorded earlier in that frame. Released blocks
+// are only reused once `end_frame` is called.
+#[derive(Debug)]
+struct BlockSlots {
+    block_size: wgpu::BufferAddress,
+    capacity: wgpu::BufferAddress,
+
+    // end of the highest block handed out so far
+    allocated: Cell<wgpu::BufferAddress>,
+
+    // addresses of blocks released in earlier frames, available for reuse
+    free: RefCell<Vec<wgpu::BufferAddress>>,
+
+    // addresses of blocks released during the current frame
+    released: RefCell<Vec<wgpu::BufferAddress>>,
+
+    // number of blocks currently allocated with each lifetime
+    live_frame: Cell<usize>,
+    live_map: Cell<usize>,
+}
+
+impl BlockSlots {
+    fn new(block_size: wgpu::BufferAddress, capacity: wgpu::BufferAddress) -> BlockSlots {
+        BlockSlots {
+            block_size,
+            capacity,
+            allocated: Cell::new(0),
+            free: RefCell::new(Vec::new()),
+            released: RefCell::new(Vec::new()),
+            live_frame: Cell::new(0),
+            live_map: Cell::new(0),
+        }
+    }
+
+    fn live_count(&self, lifetime: BlockLifetime) -> &Cell<usize> {
+        match lifetime {
+            BlockLifetime::Frame => &self.live_frame,
+            BlockLifetime::Map => &self.live_map,
+        }
+    }
+
+    // returns the address of a free block, or None if the buffer is full
+    fn alloc(&self, lifetime: BlockLifetime) -> Option<wgpu::BufferAddress> {
+        let reused = self.free.borrow_mut().pop();
+        let addr = match reused {
+            Some(addr) => addr,
+            None => {
+                let allocated = self.allocated.get();
+                if allocated + self.block_size > self.capacity {
+                    return None;
+                }
+
+                self.allocated.set(allocated + self.block_size);
+                allocated
+            }
+        };
+
+        let live = self.live_count(lifetime);
+        live.set(live.get() + 1);
+        Some(addr)
+    }
+
+    fn release(&self, addr: wgpu::BufferAddress, lifetime: BlockLifetime) {
+        self.released.borrow_mut().push(addr);
+        let live = self.live_count(lifetime);
+        live.set(live.get() - 1);
+    }
+
+    fn live(&self, lifetime: BlockLifetime) -> usize {
+        self.live_count(lifetime).get()
+    }
+
+    fn end_frame(&self) -> Result<(), Error> {
+        let released: Vec<_> = self.released.borrow_mut().drain(..).collect();
+        self.free.borrow_mut().extend(released);
+
+        let live = self.live(BlockLifetime::Frame);
+        if live > 0 {
+            bail!("{} per-frame uniform blocks outlived their frame", live);
+        }
+
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), Error> {
+        let live = self.live(BlockLifetime::Frame) + self.live(BlockLifetime::Map);
+        if live > 0 {
+            bail!(
+                "Can't clear uniform buffer: there are {} outstanding references to allocated blocks.",
+                live
+            );
+        }
+
+        let released = self.released.borrow().len();
+        if released > 0 {
+            bail!(
+                "Can't clear uniform buffer: {} blocks released this frame may still be in use.",
+                released
+            );
+        }
+
+        self.free.borrow_mut().clear();
+        self.allocated.set(0);
+        Ok(())
+    }
+}
+
 /// A handle to a dynamic uniform buffer on the GPU.
 ///
-/// Allows allocation and updating of individual blocks of memory.
+/// Allows allocation and updating of individual blocks of memory. Blocks are
+/// returned to the buffer when dropped, so resources with a limited lifetime
+/// (e.g. those belonging to a single map) don't exhaust it.
 pub struct DynamicUniformBuffer<T>
 where
     T: Pod,
 {
-    // keeps track of how many blocks are allocated so we know whether we can
-    // clear the buffer or not
-    _rc: RefCell<Rc<()>>,
+    // keeps track of which blocks are allocated so we can reuse released
+    // blocks and know whether we can clear the buffer or not
+    slots: Rc<BlockSlots>,
 
     // represents the data in the buffer, which we don't actually own
     _phantom: PhantomData<T>,
 
     inner: wgpu::Buffer,
-    allocated: Cell<u64>,
     update_buf: Vec<u8>,
 }
 
@@ -83,45 +205,44 @@ where
         update_buf.resize(DYNAMIC_UNIFORM_BUFFER_SIZE as usize, 0);
 
         DynamicUniformBuffer {
-            _rc: RefCell::new(Rc::new(())),
+            slots: Rc::new(BlockSlots::new(
+                block_size::<T>().get(),
+                DYNAMIC_UNIFORM_BUFFER_SIZE,
+            )),
             _phantom: PhantomData,
             inner,
-            allocated: Cell::new(0),
             update_buf,
         }
     }
 
     pub fn block_size(&self) -> wgpu::BufferSize {
-        std::num::NonZeroU64::new(
-            ((DYNAMIC_UNIFORM_BUFFER_ALIGNMENT / 8).max(size_of::<T>())) as u64,
-        )
-        .unwrap()
+        block_size::<T>()
     }
 
     /// Allocates a block of memory in this dynamic uniform buffer with the
     /// specified initial value.
+    ///
+    /// Blocks released in earlier frames are reused before new space is taken
+    /// from the buffer.
     #[must_use]
-    pub fn allocate(&mut self, val: T) -> DynamicUniformBufferBlock<T> {
-        let allocated = self.allocated.get();
-        let size = self.block_size().get();
+    pub fn allocate(&mut self, lifetime: BlockLifetime, val: T) -> DynamicUniformBufferBlock<T> {
         trace!(
             "Allocating dynamic uniform block (allocated: {})",
-            allocated
+            self.slots.allocated.get()
         );
-        if allocated + size > DYNAMIC_UNIFORM_BUFFER_SIZE {
-            panic!(
+        let addr = match self.slots.alloc(lifetime) {
+            Some(addr) => addr,
+            None => panic!(
                 "Not enough space to allocate {} bytes in dynamic uniform buffer",
-                size
-            );
-        }
-
-        let addr = allocated;
-        self.allocated.set(allocated + size);
+                self.block_size()
+            ),
+        };
 
         let block = DynamicUniformBufferBlock {
-            _rc: self._rc.borrow().clone(),
+            slots: self.slots.clone(),
             _phantom: PhantomData,
             addr,
+            lifetime,
         };
 
         self.write_block(&block, val);
@@ -137,21 +258,24 @@ where
 
     /// Removes all allocations from the underlying buffer.
     ///
-    /// Returns an error if the buffer is currently mapped or there are
-    /// outstanding allocated blocks.
+    /// Returns an error if there are outstanding allocated blocks, or if any
+    /// blocks were released during the current frame.
     pub fn clear(&self) -> Result<(), Error> {
-        let out = self._rc.replace(Rc::new(()));
-        match Rc::try_unwrap(out) {
-            // no outstanding blocks
-            Ok(()) => {
-                self.allocated.set(0);
-                Ok(())
-            }
-            Err(rc) => {
-                let _ = self._rc.replace(rc);
-                bail!("Can't clear uniform buffer: there are outstanding references to allocated blocks.");
-            }
-        }
+        self.slots.clear()
+    }
+
+    /// Makes blocks released during this frame available for reuse.
+    ///
+    /// Returns an error if any blocks allocated with `BlockLifetime::Frame` are
+    /// still live.
+    pub fn end_frame(&self) -> Result<(), Error> {
+        self.slots.end_frame()
+    }
+
+    /// Returns the number of blocks with the given lifetime currently
+    /// allocated from this buffer.
+    pub fn live_blocks(&self, lifetime: BlockLifetime) -> usize {
+        self.slots.live(lifetime)
     }
 
     pub fn flush(&self, queue: &wgpu::Queue) {
@@ -163,13 +287,27 @@ where
     }
 }
 
+fn block_size<T>() -> wgpu::BufferSize {
+    std::num::NonZeroU64::new(((DYNAMIC_UNIFORM_BUFFER_ALIGNMENT / 8).max(size_of::<T>())) as u64)
+        .unwrap()
+}
+
 /// An address into a dynamic uniform buffer.
+///
+/// The block is released back to its buffer when this is dropped.
 #[derive(Debug)]
 pub struct DynamicUniformBufferBlock<T> {
-    _rc: Rc<()>,
+    slots: Rc<BlockSlots>,
     _phantom: PhantomData<T>,
 
     addr: wgpu::BufferAddress,
+    lifetime: BlockLifetime,
+}
+
+impl<T> Drop for DynamicUniformBufferBlock<T> {
+    fn drop(&mut self) {
+        self.slots.release(self.addr, self.lifetime);
+    }
 }
 
 impl<T> DynamicUniformBufferBlock<T> {
@@ -178,6 +316,10 @@ impl<T> DynamicUniformBufferBlock<T> {
     }
 }
 
+/// Replaces the contents of `buffer` with `uniforms`, one per-frame block each.
+///
+/// `blocks` must be emptied before the end of the frame, since per-frame blocks
+/// may not outlive it.
 pub fn clear_and_rewrite<T>(
     queue: &wgpu::Queue,
     buffer: &mut DynamicUniformBuffer<T>,
@@ -188,13 +330,88 @@ pub fn clear_and_rewrite<T>(
 {
     blocks.clear();
     buffer.clear().unwrap();
-    for (uni_id, uni) in uniforms.iter().enumerate() {
-        if uni_id >= blocks.len() {
-            let block = buffer.allocate(*uni);
-            blocks.push(block);
-        } else {
-            buffer.write_block(&blocks[uni_id], *uni);
-        }
+    for uni in uniforms.iter() {
+        blocks.push(buffer.allocate(BlockLifetime::Frame, *uni));
     }
     buffer.flush(queue);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block(slots: &Rc<BlockSlots>, lifetime: BlockLifetime) -> DynamicUniformBufferBlock<u32> {
+        DynamicUniformBufferBlock {
+            slots: slots.clone(),
+            _phantom: PhantomData,
+            addr: slots.alloc(lifetime).unwrap(),
+            lifetime,
+        }
+    }
+
+    #[test]
+    fn test_block_slots_reuse_after_frame() {
+        let slots = Rc::new(BlockSlots::new(256, 1024));
+        let a = block(&slots, BlockLifetime::Map);
+        let b = block(&slots, BlockLifetime::Map);
+        assert_eq!((a.offset(), b.offset()), (0, 256));
+        assert_eq!(slots.live(BlockLifetime::Map), 2);
+
+        // a block released this frame isn't handed out again until the next one
+        drop(a);
+        assert_eq!(slots.live(BlockLifetime::Map), 1);
+        assert_eq!(block(&slots, BlockLifetime::Map).offset(), 512);
+
+        slots.end_frame().unwrap();
+        let mut reused = vec![
+            block(&slots, BlockLifetime::Map).offset(),
+            block(&slots, BlockLifetime::Map).offset(),
+        ];
+        reused.sort();
+        assert_eq!(reused, vec![0, 512]);
+    }
+
+    #[test]
+    fn test_block_slots_capacity() {
+        let slots = Rc::new(BlockSlots::new(256, 512));
+        let _a = block(&slots, BlockLifetime::Map);
+        let b = block(&slots, BlockLifetime::Map);
+        assert_eq!(slots.alloc(BlockLifetime::Map), None);
+
+        drop(b);
+        assert_eq!(slots.alloc(BlockLifetime::Map), None);
+        slots.end_frame().unwrap();
+        assert_eq!(slots.alloc(BlockLifetime::Map), Some(256));
+    }
+
+    #[test]
+    fn test_block_slots_frame_lifetime() {
+        let slots = Rc::new(BlockSlots::new(256, 1024));
+        let _map = block(&slots, BlockLifetime::Map);
+        let frame = block(&slots, BlockLifetime::Frame);
+
+        // per-frame blocks must be released before the frame ends
+        assert!(slots.end_frame().is_err());
+        drop(frame);
+        assert!(slots.end_frame().is_ok());
+        assert_eq!(slots.live(BlockLifetime::Frame), 0);
+        assert_eq!(slots.live(BlockLifetime::Map), 1);
+    }
+
+    #[test]
+    fn test_block_slots_clear() {
+        let slots = Rc::new(BlockSlots::new(256, 1024));
+        let a = block(&slots, BlockLifetime::Map);
+        assert!(slots.clear().is_err());
+
+        // the released block may still be read by this frame's draws
+        drop(a);
+        assert!(slots.clear().is_err());
+
+        slots.end_frame().unwrap();
+        slots.clear().unwrap();
+        let b = block(&slots, BlockLifetime::Map);
+        assert_eq!(b.offset(), 0);
+        assert_eq!(slots.free.borrow().len(), 0);
+    }
+}
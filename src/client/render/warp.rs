@@ -4,8 +4,12 @@ use crate::common::math;
 
 use cgmath::{InnerSpace, Vector2, Vector3};
 
-// TODO: make this a cvar
-const SUBDIVIDE_SIZE: f32 = 32.0;
+/// The default grid size for subdivision, used when `gl_subdivide_size` is unset.
+pub const DEFAULT_SUBDIVIDE_SIZE: f32 = 32.0;
+
+/// The smallest allowed grid size. Subdivision stops once a polygon is within 8
+/// units of the midpoint, so smaller grids would not produce finer polygons.
+pub const MIN_SUBDIVIDE_SIZE: f32 = 8.0;
 
 /// Subdivide the given polygon on a grid.
 ///
@@ -19,13 +23,13 @@ const SUBDIVIDE_SIZE: f32 = 32.0;
 ///       the next axis.
 ///    1. For each vertex *v*...
 /// TODO...
-pub fn subdivide(verts: Vec<Vector3<f32>>) -> Vec<Vector3<f32>> {
+pub fn subdivide(verts: Vec<Vector3<f32>>, size: f32) -> Vec<Vector3<f32>> {
     let mut out = Vec::new();
-    subdivide_impl(verts, &mut out);
+    subdivide_impl(verts, size.max(MIN_SUBDIVIDE_SIZE), &mut out);
     out
 }
 
-fn subdivide_impl(mut verts: Vec<Vector3<f32>>, output: &mut Vec<Vector3<f32>>) {
+fn subdivide_impl(mut verts: Vec<Vector3<f32>>, size: f32, output: &mut Vec<Vector3<f32>>) {
     let (min, max) = math::bounds(&verts);
 
     let mut front = Vec::new();
@@ -36,7 +40,7 @@ fn subdivide_impl(mut verts: Vec<Vector3<f32>>, output: &mut Vec<Vector3<f32>>)
         // find the midpoint of the polygon bounds
         let mid = {
             let m = (min[ax] + max[ax]) / 2.0;
-            SUBDIVIDE_SIZE * (m / SUBDIVIDE_SIZE).round()
+            size * (m / size).round()
         };
 
         if max[ax] - mid < 8.0 || mid - min[ax] < 8.0 {
@@ -78,12 +82,12 @@ fn subdivide_impl(mut verts: Vec<Vector3<f32>>, output: &mut Vec<Vector3<f32>>)
             }
         }
 
-        subdivide_impl(front, output);
-        subdivide_impl(back, output);
+        subdivide_impl(front, size, output);
+        subdivide_impl(back, size, output);
         return;
     }
 
-    // polygon is smaller than SUBDIVIDE_SIZE along all three axes
+    // polygon is smaller than size along all three axes
     assert!(verts.len() >= 3);
     let v1 = verts[0];
     let mut v2 = verts[1];
@@ -1,13 +1,20 @@
-use std::{mem::size_of, ops::Range};
+use std::{borrow::Cow, mem::size_of, ops::Range};
 
 use crate::{
-    client::render::{
-        world::{BindGroupLayoutId, WorldPipelineBase},
-        GraphicsState, Pipeline, TextureData,
+    client::{
+        entity::PlayerSkin,
+        render::{
+            translate_player_colors,
+            world::{BindGroupLayoutId, WorldPipelineBase},
+            DiffuseData, GraphicsState, Pipeline, ShaderCompiler, TextureData,
+        },
     },
     common::{
+        md3::{self, Md3Model},
         mdl::{self, AliasModel},
+        pcx,
         util::any_slice_as_bytes,
+        vfs::Vfs,
     },
 };
 
@@ -40,7 +47,7 @@ pub struct AliasPipeline {
 impl AliasPipeline {
     pub fn new(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         world_bind_group_layouts: &[wgpu::BindGroupLayout],
         sample_count: u32,
     ) -> AliasPipeline {
@@ -56,7 +63,7 @@ impl AliasPipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         world_bind_group_layouts: &[wgpu::BindGroupLayout],
         sample_count: u32,
     ) {
@@ -76,8 +83,15 @@ impl AliasPipeline {
     }
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VertexPushConstants {
+    /// How far to blend from the previous pose to the current one, from 0 to 1.
+    pub blend: f32,
+}
+
 impl Pipeline for AliasPipeline {
-    type VertexPushConstants = ();
+    type VertexPushConstants = VertexPushConstants;
     type SharedPushConstants = ();
     type FragmentPushConstants = ();
 
@@ -121,20 +135,30 @@ impl Pipeline for AliasPipeline {
 
     // NOTE: if the vertex format is changed, this descriptor must also be changed accordingly.
     fn vertex_buffer_descriptors() -> Vec<wgpu::VertexBufferDescriptor<'static>> {
-        vec![wgpu::VertexBufferDescriptor {
-            stride: size_of::<AliasVertex>() as u64,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![
-                // frame 0 position
-                0 => Float3,
-                // frame 1 position
-                // 1 => Float3,
-                // normal
-                2 => Float3,
-                // texcoord
-                3 => Float2,
-            ],
-        }]
+        vec![
+            // current pose
+            wgpu::VertexBufferDescriptor {
+                stride: size_of::<AliasVertex>() as u64,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![
+                    // frame 0 position
+                    0 => Float3,
+                    // normal
+                    2 => Float3,
+                    // texcoord
+                    3 => Float2,
+                ],
+            },
+            // previous pose, read from the same buffer at a different offset
+            wgpu::VertexBufferDescriptor {
+                stride: size_of::<AliasVertex>() as u64,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![
+                    // frame 1 position
+                    1 => Float3,
+                ],
+            },
+        ]
     }
 }
 
@@ -151,6 +175,28 @@ struct AliasVertex {
     diffuse_texcoord: DiffuseTexcoord,
 }
 
+/// Returns the index of the frame of a looping animation to show at `time`.
+///
+/// Frame timing is computed in microseconds, so framegroups whose intervals
+/// are shorter than a millisecond still advance. Negative times (e.g. from a
+/// random sync offset) wrap around to the end of the sequence.
+fn animation_frame(durations: &[Duration], total_duration: Duration, time: Duration) -> usize {
+    let total_us = total_duration.num_microseconds().unwrap_or(i64::MAX);
+    if total_us <= 0 {
+        return 0;
+    }
+
+    let mut time_us = time.num_microseconds().unwrap_or(0).rem_euclid(total_us);
+    for (frame_id, frame_duration) in durations.iter().enumerate() {
+        time_us -= frame_duration.num_microseconds().unwrap_or(i64::MAX);
+        if time_us < 0 {
+            return frame_id;
+        }
+    }
+
+    durations.len() - 1
+}
+
 enum Keyframe {
     Static {
         vertex_range: Range<u32>,
@@ -170,18 +216,7 @@ impl Keyframe {
                 vertex_ranges,
                 total_duration,
                 durations,
-            } => {
-                let mut time_ms = time.num_milliseconds() % total_duration.num_milliseconds();
-
-                for (frame_id, frame_duration) in durations.iter().enumerate() {
-                    time_ms -= frame_duration.num_milliseconds();
-                    if time_ms <= 0 {
-                        return vertex_ranges[frame_id].clone();
-                    }
-                }
-
-                unreachable!()
-            }
+            } => vertex_ranges[animation_frame(durations, *total_duration, time)].clone(),
         }
     }
 }
@@ -211,26 +246,85 @@ impl Texture {
                 bind_groups,
                 total_duration,
                 durations,
-            } => {
-                let mut time_ms = time.num_milliseconds() % total_duration.num_milliseconds();
+            } => &bind_groups[animation_frame(durations, *total_duration, time)],
+        }
+    }
+}
 
-                for (frame_id, frame_duration) in durations.iter().enumerate() {
-                    time_ms -= frame_duration.num_milliseconds();
-                    if time_ms <= 0 {
-                        return &bind_groups[frame_id];
-                    }
-                }
+/// Binds the vertices of two poses of the same mesh and draws them.
+///
+/// Both ranges must contain the same number of vertices; if they don't, the
+/// current pose is drawn without blending.
+fn draw_poses<'a>(
+    pass: &mut wgpu::RenderPass<'a>,
+    vertex_buffer: &'a wgpu::Buffer,
+    current: Range<u32>,
+    previous: Range<u32>,
+) {
+    let previous = if previous.len() == current.len() {
+        previous
+    } else {
+        current.clone()
+    };
+
+    let stride = size_of::<AliasVertex>() as u64;
+    pass.set_vertex_buffer(0, vertex_buffer.slice(current.start as u64 * stride..));
+    pass.set_vertex_buffer(1, vertex_buffer.slice(previous.start as u64 * stride..));
+    pass.draw(0..current.end - current.start, 0..1);
+}
 
-                unreachable!()
-            }
+/// A player skin, recolored to a particular player's colors.
+pub struct AliasSkin {
+    diffuse_texture: wgpu::Texture,
+    diffuse_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl AliasSkin {
+    fn new(state: &GraphicsState, width: u32, height: u32, indices: &[u8]) -> AliasSkin {
+        let (diffuse_data, _fullbright_data) = state.palette.translate(indices);
+        let diffuse_texture =
+            state.create_texture(None, width, height, &TextureData::Diffuse(diffuse_data));
+        let diffuse_view = diffuse_texture.create_default_view();
+        let bind_group = state
+            .device()
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &state.alias_pipeline().bind_group_layouts()
+                    [BindGroupLayoutId::PerTexture as usize - 2],
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_view),
+                }],
+            });
+
+        AliasSkin {
+            diffuse_texture,
+            diffuse_view,
+            bind_group,
         }
     }
 }
 
+// Loads a custom player skin from `skins/`.
+//
+// Skins are usually made larger than the model's own skin (320x200 for the
+// player model's 296x194), so like QuakeWorld only the top-left corner is used.
+fn load_custom_skin(vfs: &Vfs, name: &str, width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    let pcx = pcx::load(vfs.open(format!("skins/{}.pcx", name))?)?;
+    pcx.crop(width, height)
+}
+
 pub struct AliasRenderer {
     keyframes: Vec<Keyframe>,
     textures: Vec<Texture>,
     vertex_buffer: wgpu::Buffer,
+
+    // the model's skins (first frame of animated ones), kept around to be
+    // recolored for players
+    skin_width: u32,
+    skin_height: u32,
+    base_skins: Vec<Box<[u8]>>,
 }
 
 impl AliasRenderer {
@@ -401,29 +495,261 @@ impl AliasRenderer {
             }
         }
 
+        let base_skins = alias_model
+            .textures()
+            .iter()
+            .map(|texture| match *texture {
+                mdl::Texture::Static(ref tex) => tex.indices().into(),
+                mdl::Texture::Animated(ref tex) => tex.frames()[0].indices().into(),
+            })
+            .collect();
+
         Ok(AliasRenderer {
             keyframes,
             textures,
             vertex_buffer,
+            skin_width: w,
+            skin_height: h,
+            base_skins,
         })
     }
 
+    /// Builds the skin described by `player_skin` for this model.
+    ///
+    /// Custom skins are loaded from `skins/<name>.pcx` and cropped to the size
+    /// of the model's skins, falling back to the model's own skin
+    /// `player_skin.skin_id` if that fails. Either way, the shirt and pants are
+    /// recolored to the player's colors.
+    pub fn player_skin(
+        &self,
+        state: &GraphicsState,
+        player_skin: &PlayerSkin,
+    ) -> Option<AliasSkin> {
+        let custom = player_skin.name.as_ref().and_then(|name| {
+            match load_custom_skin(state.vfs(), name, self.skin_width, self.skin_height) {
+                Ok(indices) => Some(indices),
+                Err(e) => {
+                    warn!("Couldn't load skin {}: {}", name, e);
+                    None
+                }
+            }
+        });
+
+        let indices = match custom {
+            Some(ref indices) => indices.as_slice(),
+            None => self.base_skins.get(player_skin.skin_id)?.as_ref(),
+        };
+
+        let indices = translate_player_colors(indices, player_skin.top, player_skin.bottom);
+        Some(AliasSkin::new(
+            state,
+            self.skin_width,
+            self.skin_height,
+            &indices,
+        ))
+    }
+
+    /// Draws the model, using `skin` in place of the model's own skin if present.
+    ///
+    /// The model is blended from `prev_keyframe_id` to `keyframe_id` by the
+    /// amount in the pipeline's push constants.
     pub fn record_draw<'a>(
         &'a self,
         state: &'a GraphicsState,
         pass: &mut wgpu::RenderPass<'a>,
         time: Duration,
         keyframe_id: usize,
+        prev_keyframe_id: usize,
         texture_id: usize,
+        skin: Option<&'a AliasSkin>,
+    ) {
+        pass.set_pipeline(state.alias_pipeline().pipeline());
+
+        let bind_group = match skin {
+            Some(s) => &s.bind_group,
+            None => self.textures[texture_id].animate(time),
+        };
+        pass.set_bind_group(BindGroupLayoutId::PerTexture as u32, bind_group, &[]);
+
+        let current = self.keyframes[keyframe_id].animate(time);
+        let previous = match self.keyframes.get(prev_keyframe_id) {
+            Some(kf) => kf.animate(time),
+            None => current.clone(),
+        };
+        draw_poses(pass, &self.vertex_buffer, current, previous);
+    }
+}
+
+struct Md3Surface {
+    frame_ranges: Vec<Range<u32>>,
+    #[allow(dead_code)]
+    diffuse_texture: wgpu::Texture,
+    #[allow(dead_code)]
+    diffuse_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Renders MD3 models using the alias pipeline.
+pub struct Md3Renderer {
+    surfaces: Vec<Md3Surface>,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl Md3Renderer {
+    pub fn new(state: &GraphicsState, md3_model: &Md3Model) -> Result<Md3Renderer, Error> {
+        let mut vertices = Vec::new();
+        let mut surfaces = Vec::new();
+
+        for surface in md3_model.surfaces() {
+            let mut frame_ranges = Vec::new();
+            for frame in surface.frames() {
+                let vertex_start = vertices.len() as u32;
+                for triangle in surface.triangles() {
+                    for index in triangle.iter() {
+                        let vertex = &frame[*index as usize];
+                        vertices.push(AliasVertex {
+                            position: vertex.position().into(),
+                            normal: vertex.normal().into(),
+                            diffuse_texcoord: surface.texcoords()[*index as usize],
+                        });
+                    }
+                }
+                let vertex_end = vertices.len() as u32;
+                frame_ranges.push(vertex_start..vertex_end);
+            }
+
+            // use the first skin we can load, or plain white if there are none
+            let skin = surface.shaders().iter().find_map(|shader| {
+                match md3::load_skin(state.vfs(), shader) {
+                    Ok(skin) => Some(skin),
+                    Err(e) => {
+                        warn!("Couldn't load skin {}: {}", shader, e);
+                        None
+                    }
+                }
+            });
+            let (w, h, rgba) = skin.unwrap_or_else(|| (1, 1, vec![0xFF; 4]));
+
+            let diffuse_texture = state.create_texture(
+                None,
+                w,
+                h,
+                &TextureData::Diffuse(DiffuseData {
+                    rgba: Cow::Owned(rgba),
+                }),
+            );
+            let diffuse_view = diffuse_texture.create_default_view();
+            let bind_group = state
+                .device()
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &state.alias_pipeline().bind_group_layouts()
+                        [BindGroupLayoutId::PerTexture as usize - 2],
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&diffuse_view),
+                    }],
+                });
+
+            surfaces.push(Md3Surface {
+                frame_ranges,
+                diffuse_texture,
+                diffuse_view,
+                bind_group,
+            });
+        }
+
+        let vertex_buffer = state.device().create_buffer_with_data(
+            unsafe { any_slice_as_bytes(vertices.as_slice()) },
+            wgpu::BufferUsage::VERTEX,
+        );
+
+        Ok(Md3Renderer {
+            surfaces,
+            vertex_buffer,
+        })
+    }
+
+    pub fn record_draw<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        frame_id: usize,
+        prev_frame_id: usize,
     ) {
         pass.set_pipeline(state.alias_pipeline().pipeline());
-        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
-        pass.set_bind_group(
-            BindGroupLayoutId::PerTexture as u32,
-            self.textures[texture_id].animate(time),
-            &[],
+        for surface in self.surfaces.iter() {
+            // MDL frame groups don't map exactly onto MD3 frames, so clamp out-of-range frames
+            let last_frame = surface.frame_ranges.len() - 1;
+            let frame_id = frame_id.min(last_frame);
+            let prev_frame_id = prev_frame_id.min(last_frame);
+
+            pass.set_bind_group(
+                BindGroupLayoutId::PerTexture as u32,
+                &surface.bind_group,
+                &[],
+            );
+            draw_poses(
+                pass,
+                &self.vertex_buffer,
+                surface.frame_ranges[frame_id].clone(),
+                surface.frame_ranges[prev_frame_id].clone(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn us(n: i64) -> Duration {
+        Duration::microseconds(n)
+    }
+
+    #[test]
+    fn test_animation_frame_sub_millisecond() {
+        let durations = [us(300), us(300), us(300)];
+        assert_eq!(animation_frame(&durations, us(900), us(0)), 0);
+        assert_eq!(animation_frame(&durations, us(900), us(299)), 0);
+        assert_eq!(animation_frame(&durations, us(900), us(450)), 1);
+        assert_eq!(animation_frame(&durations, us(900), us(899)), 2);
+        assert_eq!(animation_frame(&durations, us(900), us(1200)), 1);
+    }
+
+    #[test]
+    fn test_animation_frame_zero_duration() {
+        let durations = [us(0), us(0)];
+        assert_eq!(animation_frame(&durations, us(0), us(12345)), 0);
+    }
+
+    #[test]
+    fn test_animation_frame_negative_time() {
+        let durations = [us(100), us(200), us(300)];
+
+        // negative times count back from the end of the sequence
+        assert_eq!(animation_frame(&durations, us(600), us(-1)), 2);
+        assert_eq!(animation_frame(&durations, us(600), us(-300)), 2);
+        assert_eq!(animation_frame(&durations, us(600), us(-301)), 1);
+        assert_eq!(animation_frame(&durations, us(600), us(-600)), 0);
+        assert_eq!(animation_frame(&durations, us(600), us(-1300)), 2);
+    }
+
+    #[test]
+    fn test_animation_frame_boundaries() {
+        let durations = [Duration::milliseconds(100), Duration::milliseconds(100)];
+        let total = Duration::milliseconds(200);
+
+        // a frame starts exactly at its interval boundary
+        assert_eq!(
+            animation_frame(&durations, total, Duration::milliseconds(100)),
+            1
+        );
+        assert_eq!(animation_frame(&durations, total, us(99_999)), 0);
+        assert_eq!(
+            animation_frame(&durations, total, Duration::milliseconds(200)),
+            0
         );
-        pass.draw(self.keyframes[keyframe_id].animate(time), 0..1)
     }
 }
@@ -22,6 +22,7 @@ use std::{
     borrow::Cow,
     cell::{Cell, RefCell},
     collections::HashMap,
+    io::Read as _,
     mem::size_of,
     ops::Range,
     rc::Rc,
@@ -31,13 +32,17 @@ use crate::{
     client::render::{
         pipeline::PushConstantUpdate,
         warp,
-        world::{BindGroupLayoutId, WorldPipelineBase},
-        Camera, GraphicsState, LightmapData, Pipeline, TextureData,
+        world::{
+            cache::{MeshCache, MeshCacheKey},
+            skybox::Skybox,
+            BindGroupLayoutId, WorldPipelineBase,
+        },
+        Camera, GraphicsState, LightmapData, Pipeline, ShaderCompiler, TextureData,
     },
     common::{
         bsp::{
             self, BspData, BspFace, BspLeaf, BspModel, BspTexInfo, BspTexture, BspTextureKind,
-            BspTextureMipmap,
+            BspTextureMipmap, MapDigest,
         },
         math,
         util::any_slice_as_bytes,
@@ -45,9 +50,10 @@ use crate::{
 };
 
 use bumpalo::Bump;
+use byteorder::{LittleEndian, ReadBytesExt as _, WriteBytesExt as _};
 use cgmath::{InnerSpace as _, Matrix4, Vector3};
 use chrono::Duration;
-use failure::Error;
+use failure::{ensure, Error};
 
 lazy_static! {
     static ref BIND_GROUP_LAYOUT_DESCRIPTOR_BINDINGS: [Vec<wgpu::BindGroupLayoutEntry>; 2] = [
@@ -72,6 +78,16 @@ lazy_static! {
                     multisampled: false,
                 },
             ),
+            // sky box faces, only used by sky textures
+            wgpu::BindGroupLayoutEntry::new(
+                2,
+                wgpu::ShaderStage::FRAGMENT,
+                wgpu::BindingType::SampledTexture {
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                    component_type: wgpu::TextureComponentType::Float,
+                    multisampled: false,
+                },
+            ),
         ],
         vec![
             // lightmap texture array
@@ -93,6 +109,7 @@ lazy_static! {
 
 pub struct BrushPipeline {
     pipeline: wgpu::RenderPipeline,
+    liquid_pipeline: wgpu::RenderPipeline,
     bind_group_layouts: Vec<wgpu::BindGroupLayout>,
 }
 
@@ -100,15 +117,24 @@ impl BrushPipeline {
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         world_bind_group_layouts: &[wgpu::BindGroupLayout],
         sample_count: u32,
     ) -> BrushPipeline {
         let (pipeline, bind_group_layouts) =
             BrushPipeline::create(device, compiler, world_bind_group_layouts, sample_count);
 
+        // the liquid pipeline shares the brush pipeline's bind group layouts
+        let layout_refs: Vec<_> = world_bind_group_layouts
+            .iter()
+            .chain(bind_group_layouts.iter())
+            .collect();
+        let liquid_pipeline =
+            BrushLiquidPipeline::recreate(device, compiler, &layout_refs, sample_count);
+
         BrushPipeline {
             pipeline,
+            liquid_pipeline,
             // TODO: pick a starting capacity
             bind_group_layouts,
         }
@@ -117,7 +143,7 @@ impl BrushPipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         world_bind_group_layouts: &[wgpu::BindGroupLayout],
         sample_count: u32,
     ) {
@@ -126,12 +152,19 @@ impl BrushPipeline {
             .chain(self.bind_group_layouts.iter())
             .collect();
         self.pipeline = BrushPipeline::recreate(device, compiler, &layout_refs, sample_count);
+        self.liquid_pipeline =
+            BrushLiquidPipeline::recreate(device, compiler, &layout_refs, sample_count);
     }
 
     pub fn pipeline(&self) -> &wgpu::RenderPipeline {
         &self.pipeline
     }
 
+    /// Returns the pipeline used to draw translucent liquid surfaces.
+    pub fn liquid_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.liquid_pipeline
+    }
+
     pub fn bind_group_layouts(&self) -> &[wgpu::BindGroupLayout] {
         &self.bind_group_layouts
     }
@@ -226,6 +259,81 @@ impl Pipeline for BrushPipeline {
     }
 }
 
+/// A variant of the brush pipeline which blends liquid surfaces over the
+/// existing contents of the G-buffer.
+///
+/// The blend factor is the render pass's blend color, which should be set to
+/// `r_wateralpha` before drawing. Liquids don't write depth or normals, so
+/// dynamic lights and anything drawn afterward see the surface beneath.
+struct BrushLiquidPipeline;
+
+impl Pipeline for BrushLiquidPipeline {
+    type VertexPushConstants = VertexPushConstants;
+    type SharedPushConstants = SharedPushConstants;
+    type FragmentPushConstants = ();
+
+    fn name() -> &'static str {
+        BrushPipeline::name()
+    }
+
+    fn vertex_shader() -> &'static str {
+        BrushPipeline::vertex_shader()
+    }
+
+    fn fragment_shader() -> &'static str {
+        BrushPipeline::fragment_shader()
+    }
+
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        BrushPipeline::bind_group_layout_descriptors()
+    }
+
+    fn rasterization_state_descriptor() -> Option<wgpu::RasterizationStateDescriptor> {
+        BrushPipeline::rasterization_state_descriptor()
+    }
+
+    fn primitive_topology() -> wgpu::PrimitiveTopology {
+        BrushPipeline::primitive_topology()
+    }
+
+    fn color_state_descriptors() -> Vec<wgpu::ColorStateDescriptor> {
+        let blend = wgpu::BlendDescriptor {
+            src_factor: wgpu::BlendFactor::BlendColor,
+            dst_factor: wgpu::BlendFactor::OneMinusBlendColor,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        BrushPipeline::color_state_descriptors()
+            .into_iter()
+            .enumerate()
+            .map(|(i, desc)| wgpu::ColorStateDescriptor {
+                alpha_blend: blend.clone(),
+                color_blend: blend.clone(),
+                // keep the normals of the surface beneath
+                write_mask: if i == 1 {
+                    wgpu::ColorWrite::empty()
+                } else {
+                    wgpu::ColorWrite::ALL
+                },
+                ..desc
+            })
+            .collect()
+    }
+
+    fn depth_stencil_state_descriptor() -> Option<wgpu::DepthStencilStateDescriptor> {
+        BrushPipeline::depth_stencil_state_descriptor().map(|desc| {
+            wgpu::DepthStencilStateDescriptor {
+                depth_write_enabled: false,
+                ..desc
+            }
+        })
+    }
+
+    fn vertex_buffer_descriptors() -> Vec<wgpu::VertexBufferDescriptor<'static>> {
+        BrushPipeline::vertex_buffer_descriptors()
+    }
+}
+
 fn calculate_lightmap_texcoords(
     position: Vector3<f32>,
     face: &BspFace,
@@ -250,7 +358,7 @@ type LightmapTexcoord = [f32; 2];
 type LightmapAnim = [u8; 4];
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct BrushVertex {
     position: Position,
     normal: Normal,
@@ -260,14 +368,20 @@ struct BrushVertex {
 }
 
 #[repr(u32)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TextureKind {
     Normal = 0,
     Warp = 1,
     Sky = 2,
+
+    /// A sky texture drawn with the current sky box.
+    Skybox = 3,
 }
 
 /// A single frame of a brush texture.
+///
+/// Sky textures store their back layer in `diffuse` and their front layer in
+/// `fullbright`.
 pub struct BrushTextureFrame {
     bind_group_id: usize,
     diffuse: wgpu::Texture,
@@ -299,6 +413,181 @@ impl BrushTexture {
             BrushTexture::Animated { ref primary, .. } => primary[0].kind,
         }
     }
+
+    fn frames(&self) -> Vec<&BrushTextureFrame> {
+        match self {
+            BrushTexture::Static(ref frame) => vec![frame],
+            BrushTexture::Animated {
+                ref primary,
+                ref alternate,
+            } => primary.iter().chain(alternate.iter().flatten()).collect(),
+        }
+    }
+}
+
+fn create_per_texture_bind_group(
+    state: &GraphicsState,
+    tex: &BrushTextureFrame,
+    skybox_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    let layout = &state
+        .brush_pipeline()
+        .bind_group_layout(BindGroupLayoutId::PerTexture);
+    let desc = wgpu::BindGroupDescriptor {
+        label: Some("per-texture bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&tex.diffuse_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&tex.fullbright_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(skybox_view),
+            },
+        ],
+    };
+    state.device().create_bind_group(&desc)
+}
+
+/// Splits the palette indices of a sky texture into its back and front layers.
+///
+/// Sky textures hold two layers side by side: the left half is the front
+/// (cloud) layer and the right half is the solid back layer. Each layer is
+/// returned as its own `width / 2` by `height` image.
+fn split_sky_texture(indices: &[u8], width: u32, height: u32) -> (Vec<u8>, Vec<u8>) {
+    let width = width as usize;
+    let half = width / 2;
+    let mut back = Vec::with_capacity(half * height as usize);
+    let mut front = Vec::with_capacity(half * height as usize);
+
+    for row in indices.chunks(width).take(height as usize) {
+        front.extend_from_slice(&row[..half]);
+        back.extend_from_slice(&row[half..half * 2]);
+    }
+
+    (back, front)
+}
+
+// encoded sizes of a face mesh and a vertex in the mesh cache
+const CACHED_FACE_SIZE: usize = 2 * 4 + 6 * 4;
+const CACHED_VERTEX_SIZE: usize = 10 * 4 + 4;
+
+// the vertices and bounds of a single face, which are stored in the mesh cache
+#[derive(Clone, Debug, PartialEq)]
+struct FaceMesh {
+    vertices: Range<u32>,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+fn write_f32s(data: &mut Vec<u8>, values: &[f32]) {
+    for v in values {
+        data.write_f32::<LittleEndian>(*v).unwrap();
+    }
+}
+
+fn read_f32s(data: &mut &[u8], values: &mut [f32]) -> Result<(), Error> {
+    for v in values.iter_mut() {
+        *v = data.read_f32::<LittleEndian>()?;
+    }
+    Ok(())
+}
+
+// Serializes a model's face meshes and vertices for the mesh cache.
+fn encode_mesh(faces: &[FaceMesh], vertices: &[BrushVertex]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.write_u32::<LittleEndian>(faces.len() as u32).unwrap();
+    data.write_u32::<LittleEndian>(vertices.len() as u32)
+        .unwrap();
+
+    for face in faces {
+        data.write_u32::<LittleEndian>(face.vertices.start).unwrap();
+        data.write_u32::<LittleEndian>(face.vertices.end).unwrap();
+        write_f32s(&mut data, &[face.min.x, face.min.y, face.min.z]);
+        write_f32s(&mut data, &[face.max.x, face.max.y, face.max.z]);
+    }
+
+    for vert in vertices {
+        write_f32s(&mut data, &vert.position);
+        write_f32s(&mut data, &vert.normal);
+        write_f32s(&mut data, &vert.diffuse_texcoord);
+        write_f32s(&mut data, &vert.lightmap_texcoord);
+        data.extend_from_slice(&vert.lightmap_anim);
+    }
+
+    data
+}
+
+// Reads back a mesh written by `encode_mesh`, checking that it matches a model
+// with `face_count` faces.
+fn decode_mesh(
+    mut data: &[u8],
+    face_count: usize,
+) -> Result<(Vec<FaceMesh>, Vec<BrushVertex>), Error> {
+    let data = &mut data;
+    let cached_face_count = data.read_u32::<LittleEndian>()? as usize;
+    ensure!(
+        cached_face_count == face_count,
+        "Cached mesh has {} faces, model has {}",
+        cached_face_count,
+        face_count
+    );
+    let vertex_count = data.read_u32::<LittleEndian>()?;
+    ensure!(
+        data.len() == face_count * CACHED_FACE_SIZE + vertex_count as usize * CACHED_VERTEX_SIZE,
+        "Cached mesh size doesn't match {} faces and {} vertices",
+        face_count,
+        vertex_count
+    );
+
+    let mut faces = Vec::with_capacity(face_count);
+    for _ in 0..face_count {
+        let start = data.read_u32::<LittleEndian>()?;
+        let end = data.read_u32::<LittleEndian>()?;
+        ensure!(
+            start <= end && end <= vertex_count,
+            "Invalid vertex range {}..{} in cached mesh",
+            start,
+            end
+        );
+
+        let mut min = [0.0; 3];
+        let mut max = [0.0; 3];
+        read_f32s(data, &mut min)?;
+        read_f32s(data, &mut max)?;
+
+        faces.push(FaceMesh {
+            vertices: start..end,
+            min: min.into(),
+            max: max.into(),
+        });
+    }
+
+    let mut vertices = Vec::with_capacity(vertex_count as usize);
+    for _ in 0..vertex_count {
+        let mut vert = BrushVertex {
+            position: [0.0; 3],
+            normal: [0.0; 3],
+            diffuse_texcoord: [0.0; 2],
+            lightmap_texcoord: [0.0; 2],
+            lightmap_anim: [0; 4],
+        };
+        read_f32s(data, &mut vert.position)?;
+        read_f32s(data, &mut vert.normal)?;
+        read_f32s(data, &mut vert.diffuse_texcoord)?;
+        read_f32s(data, &mut vert.lightmap_texcoord)?;
+        data.read_exact(&mut vert.lightmap_anim)?;
+        vertices.push(vert);
+    }
+
+    ensure!(data.is_empty(), "Trailing data in cached mesh");
+
+    Ok((faces, vertices))
 }
 
 #[derive(Debug)]
@@ -341,6 +630,8 @@ pub struct BrushRendererBuilder {
     face_range: Range<usize>,
 
     leaves: Option<Vec<BrushLeaf>>,
+    subdivide_size: f32,
+    mesh_cache: Option<(Rc<MeshCache>, MapDigest)>,
 
     per_texture_bind_groups: RefCell<Vec<wgpu::BindGroup>>,
     per_face_bind_groups: Vec<wgpu::BindGroup>,
@@ -368,6 +659,8 @@ impl BrushRendererBuilder {
             } else {
                 None
             },
+            subdivide_size: warp::DEFAULT_SUBDIVIDE_SIZE,
+            mesh_cache: None,
             per_texture_bind_groups: RefCell::new(Vec::new()),
             per_face_bind_groups: Vec::new(),
             vertices: Vec::new(),
@@ -379,7 +672,49 @@ impl BrushRendererBuilder {
         }
     }
 
-    fn create_face(&mut self, state: &GraphicsState, face_id: usize) -> BrushFace {
+    /// Sets the grid size used to tessellate warped (liquid) surfaces.
+    pub fn subdivide_size(mut self, size: f32) -> BrushRendererBuilder {
+        self.subdivide_size = size;
+        self
+    }
+
+    /// Sets the cache used to skip rebuilding meshes for maps that were loaded before,
+    /// along with the digest of the map file the model came from.
+    pub fn mesh_cache(mut self, cache: Option<(Rc<MeshCache>, MapDigest)>) -> BrushRendererBuilder {
+        self.mesh_cache = cache;
+        self
+    }
+
+    fn mesh_cache_key(&self) -> Option<MeshCacheKey> {
+        let (_, map) = self.mesh_cache.as_ref()?;
+        Some(MeshCacheKey {
+            map: *map,
+            first_face: self.face_range.start,
+            face_count: self.face_range.len(),
+            subdivide_size: self.subdivide_size,
+        })
+    }
+
+    // Loads this model's vertices from the mesh cache, returning the face meshes
+    // on success.
+    fn load_cached_mesh(&mut self) -> Option<Vec<FaceMesh>> {
+        let key = self.mesh_cache_key()?;
+        let data = self.mesh_cache.as_ref()?.0.load(&key)?;
+        match decode_mesh(&data, key.face_count) {
+            Ok((faces, vertices)) => {
+                self.vertices = vertices;
+                Some(faces)
+            }
+
+            Err(e) => {
+                warn!("Ignoring cached mesh: {}", e);
+                None
+            }
+        }
+    }
+
+    // Generates the vertices for a face and appends them to the vertex list.
+    fn create_face_mesh(&mut self, face_id: usize) -> FaceMesh {
         let face = &self.bsp_data.faces()[face_id];
         let face_vert_id = self.vertices.len();
         let texinfo = &self.bsp_data.texinfo()[face.texinfo_id];
@@ -400,7 +735,7 @@ impl BrushRendererBuilder {
 
         if tex.name().starts_with("*") {
             // tessellate the surface so we can do texcoord warping
-            let verts = warp::subdivide(no_collinear);
+            let verts = warp::subdivide(no_collinear, self.subdivide_size);
             let normal = (verts[0] - verts[1]).cross(verts[2] - verts[1]).normalize();
             for vert in verts.into_iter() {
                 self.vertices.push(BrushVertex {
@@ -454,6 +789,17 @@ impl BrushRendererBuilder {
             }
         }
 
+        FaceMesh {
+            vertices: face_vert_id as u32..self.vertices.len() as u32,
+            min,
+            max,
+        }
+    }
+
+    fn create_face(&mut self, state: &GraphicsState, face_id: usize, mesh: FaceMesh) -> BrushFace {
+        let face = &self.bsp_data.faces()[face_id];
+        let texinfo = &self.bsp_data.texinfo()[face.texinfo_id];
+
         // build the lightmaps
         let lightmaps = if !texinfo.special {
             self.bsp_data.face_lightmaps(face_id)
@@ -478,9 +824,9 @@ impl BrushRendererBuilder {
         }
 
         BrushFace {
-            vertices: face_vert_id as u32..self.vertices.len() as u32,
-            min,
-            max,
+            vertices: mesh.vertices,
+            min: mesh.min,
+            max: mesh.max,
             texture_id: texinfo.tex_id as usize,
             lightmap_ids,
             light_styles: face.light_styles,
@@ -488,31 +834,6 @@ impl BrushRendererBuilder {
         }
     }
 
-    fn create_per_texture_bind_group(
-        &self,
-        state: &GraphicsState,
-        tex: &BrushTextureFrame,
-    ) -> wgpu::BindGroup {
-        let layout = &state
-            .brush_pipeline()
-            .bind_group_layout(BindGroupLayoutId::PerTexture);
-        let desc = wgpu::BindGroupDescriptor {
-            label: Some("per-texture bind group"),
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&tex.diffuse_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&tex.fullbright_view),
-                },
-            ],
-        };
-        state.device().create_bind_group(&desc)
-    }
-
     fn create_per_face_bind_group(&self, state: &GraphicsState, face_id: usize) -> wgpu::BindGroup {
         let mut lightmap_views: Vec<_> = self.faces[face_id]
             .lightmap_ids
@@ -547,19 +868,6 @@ impl BrushRendererBuilder {
     {
         let name = name.as_ref();
 
-        let (diffuse_data, fullbright_data) = state.palette().translate(mipmap);
-        let diffuse =
-            state.create_texture(None, width, height, &TextureData::Diffuse(diffuse_data));
-        let fullbright = state.create_texture(
-            None,
-            width,
-            height,
-            &TextureData::Fullbright(fullbright_data),
-        );
-
-        let diffuse_view = diffuse.create_default_view();
-        let fullbright_view = fullbright.create_default_view();
-
         let kind = if name.starts_with("sky") {
             TextureKind::Sky
         } else if name.starts_with("*") {
@@ -568,6 +876,48 @@ impl BrushRendererBuilder {
             TextureKind::Normal
         };
 
+        let (diffuse, fullbright) = match kind {
+            TextureKind::Sky => {
+                let (back, front) = split_sky_texture(mipmap, width, height);
+                let (back_data, _) = state.palette().translate(&back);
+                let (mut front_data, _) = state.palette().translate(&front);
+
+                // index 0 is see-through in the front layer
+                let rgba = front_data.rgba.to_mut();
+                for (i, index) in front.iter().enumerate() {
+                    if *index == 0 {
+                        rgba[i * 4 + 3] = 0;
+                    }
+                }
+
+                (
+                    state.create_texture(None, width / 2, height, &TextureData::Diffuse(back_data)),
+                    state.create_texture(
+                        None,
+                        width / 2,
+                        height,
+                        &TextureData::Diffuse(front_data),
+                    ),
+                )
+            }
+
+            _ => {
+                let (diffuse_data, fullbright_data) = state.palette().translate(mipmap);
+                (
+                    state.create_texture(None, width, height, &TextureData::Diffuse(diffuse_data)),
+                    state.create_texture(
+                        None,
+                        width,
+                        height,
+                        &TextureData::Fullbright(fullbright_data),
+                    ),
+                )
+            }
+        };
+
+        let diffuse_view = diffuse.create_default_view();
+        let fullbright_view = fullbright.create_default_view();
+
         let mut frame = BrushTextureFrame {
             bind_group_id: 0,
             diffuse,
@@ -578,7 +928,8 @@ impl BrushRendererBuilder {
         };
 
         // generate texture bind group
-        let per_texture_bind_group = self.create_per_texture_bind_group(state, &frame);
+        let per_texture_bind_group =
+            create_per_texture_bind_group(state, &frame, state.default_skybox_view());
         let bind_group_id = self.per_texture_bind_groups.borrow().len();
         self.per_texture_bind_groups
             .borrow_mut()
@@ -646,12 +997,25 @@ impl BrushRendererBuilder {
             self.textures.push(self.create_brush_texture(state, tex));
         }
 
+        // reuse the vertices from the last time this model was built, if possible
+        let cached_meshes = self.load_cached_mesh();
+        let mut new_meshes = Vec::new();
+
         // generate faces, vertices and lightmaps
         // bsp_face_id is the id of the face in the bsp data
         // face_id is the new id of the face in the renderer
         for bsp_face_id in self.face_range.start..self.face_range.end {
             let face_id = self.faces.len();
-            let face = self.create_face(state, bsp_face_id);
+            let mesh = match cached_meshes {
+                Some(ref meshes) => meshes[face_id].clone(),
+                None => {
+                    let mesh = self.create_face_mesh(bsp_face_id);
+                    new_meshes.push(mesh.clone());
+                    mesh
+                }
+            };
+
+            let face = self.create_face(state, bsp_face_id, mesh);
             self.faces.push(face);
 
             let face_tex_id = self.faces[face_id].texture_id;
@@ -666,6 +1030,15 @@ impl BrushRendererBuilder {
             self.per_face_bind_groups.push(per_face_bind_group);
         }
 
+        if cached_meshes.is_none() {
+            if let (Some((cache, _)), Some(key)) = (&self.mesh_cache, self.mesh_cache_key()) {
+                let data = encode_mesh(&new_meshes, &self.vertices);
+                if let Err(e) = cache.store(&key, &data) {
+                    warn!("Couldn't cache mesh: {}", e);
+                }
+            }
+        }
+
         let vertex_buffer = state.device().create_buffer_with_data(
             unsafe { any_slice_as_bytes(self.vertices.as_slice()) },
             wgpu::BufferUsage::VERTEX,
@@ -682,6 +1055,7 @@ impl BrushRendererBuilder {
             textures: self.textures,
             lightmaps: self.lightmaps,
             //lightmap_views: self.lightmap_views,
+            skybox: false,
         })
     }
 }
@@ -702,10 +1076,36 @@ pub struct BrushRenderer {
     textures: Vec<BrushTexture>,
     lightmaps: Vec<wgpu::Texture>,
     //lightmap_views: Vec<wgpu::TextureView>,
+
+    // whether sky textures are drawn with a sky box
+    skybox: bool,
 }
 
 impl BrushRenderer {
+    /// Draws this model's sky textures with the given sky box, or with the
+    /// scrolling sky if `skybox` is `None`.
+    pub fn set_skybox(&mut self, state: &GraphicsState, skybox: Option<&Skybox>) {
+        let view = match skybox {
+            Some(s) => s.view(),
+            None => state.default_skybox_view(),
+        };
+
+        for tex in self.textures.iter() {
+            for frame in tex.frames() {
+                if let TextureKind::Sky = frame.kind {
+                    self.per_texture_bind_groups[frame.bind_group_id] =
+                        create_per_texture_bind_group(state, frame, view);
+                }
+            }
+        }
+
+        self.skybox = skybox.is_some();
+    }
+
     /// Record the draw commands for this brush model to the given `wgpu::RenderPass`.
+    ///
+    /// If `translucent_liquids` is true, liquid surfaces are skipped so they can
+    /// be drawn later with `record_draw_liquids`.
     pub fn record_draw<'a>(
         &'a self,
         state: &'a GraphicsState,
@@ -714,6 +1114,7 @@ impl BrushRenderer {
         time: Duration,
         camera: &Camera,
         frame_id: usize,
+        translucent_liquids: bool,
     ) {
         pass.set_pipeline(state.brush_pipeline().pipeline());
         pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
@@ -736,59 +1137,175 @@ impl BrushRenderer {
         }
 
         for (tex_id, face_ids) in self.texture_chains.iter() {
-            use PushConstantUpdate::*;
-            BrushPipeline::set_push_constants(
-                pass,
-                Retain,
-                Update(bump.alloc(SharedPushConstants {
-                    texture_kind: self.textures[*tex_id].kind() as u32,
-                })),
-                Retain,
-            );
+            if translucent_liquids && self.textures[*tex_id].kind() == TextureKind::Warp {
+                continue;
+            }
 
-            let bind_group_id = match &self.textures[*tex_id] {
-                BrushTexture::Static(ref frame) => frame.bind_group_id,
-                BrushTexture::Animated { primary, alternate } => {
-                    // if frame is not zero and this texture has an alternate
-                    // animation, use it
-                    let anim = if frame_id == 0 {
-                        primary
-                    } else if let Some(a) = alternate {
-                        a
-                    } else {
-                        primary
-                    };
-
-                    let time_ms = time.num_milliseconds();
-                    let total_ms = (bsp::frame_duration() * anim.len() as i32).num_milliseconds();
-                    let anim_ms = if total_ms == 0 { 0 } else { time_ms % total_ms };
-                    anim[(anim_ms / bsp::frame_duration().num_milliseconds()) as usize]
-                        .bind_group_id
-                }
-            };
+            self.record_texture_chain(pass, bump, time, frame_id, *tex_id, face_ids);
+        }
+    }
+
+    /// Record the draw commands for this brush model's liquid surfaces, blended
+    /// over the existing contents of the G-buffer with the given opacity.
+    ///
+    /// This must follow a call to `record_draw` with `translucent_liquids` set,
+    /// which marks the visible faces.
+    pub fn record_draw_liquids<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        time: Duration,
+        frame_id: usize,
+        alpha: f32,
+    ) {
+        pass.set_pipeline(state.brush_pipeline().liquid_pipeline());
+        pass.set_blend_color(wgpu::Color {
+            r: alpha as f64,
+            g: alpha as f64,
+            b: alpha as f64,
+            a: alpha as f64,
+        });
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        for (tex_id, face_ids) in self.texture_chains.iter() {
+            if self.textures[*tex_id].kind() != TextureKind::Warp {
+                continue;
+            }
+
+            self.record_texture_chain(pass, bump, time, frame_id, *tex_id, face_ids);
+        }
+    }
+
+    fn record_texture_chain<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        time: Duration,
+        frame_id: usize,
+        tex_id: usize,
+        face_ids: &[usize],
+    ) {
+        let kind = match self.textures[tex_id].kind() {
+            TextureKind::Sky if self.skybox => TextureKind::Skybox,
+            k => k,
+        };
+
+        use PushConstantUpdate::*;
+        BrushPipeline::set_push_constants(
+            pass,
+            Retain,
+            Update(bump.alloc(SharedPushConstants {
+                texture_kind: kind as u32,
+            })),
+            Retain,
+        );
+
+        let bind_group_id = match &self.textures[tex_id] {
+            BrushTexture::Static(ref frame) => frame.bind_group_id,
+            BrushTexture::Animated { primary, alternate } => {
+                // if frame is not zero and this texture has an alternate
+                // animation, use it
+                let anim = if frame_id == 0 {
+                    primary
+                } else if let Some(a) = alternate {
+                    a
+                } else {
+                    primary
+                };
+
+                let time_ms = time.num_milliseconds();
+                let total_ms = (bsp::frame_duration() * anim.len() as i32).num_milliseconds();
+                let anim_ms = if total_ms == 0 { 0 } else { time_ms % total_ms };
+                anim[(anim_ms / bsp::frame_duration().num_milliseconds()) as usize].bind_group_id
+            }
+        };
+
+        pass.set_bind_group(
+            BindGroupLayoutId::PerTexture as u32,
+            &self.per_texture_bind_groups[bind_group_id],
+            &[],
+        );
+
+        for face_id in face_ids.iter() {
+            let face = &self.faces[*face_id];
+
+            // only skip the face if we have visibility data but it's not marked
+            if self.leaves.is_some() && !face.draw_flag.replace(false) {
+                continue;
+            }
 
             pass.set_bind_group(
-                BindGroupLayoutId::PerTexture as u32,
-                &self.per_texture_bind_groups[bind_group_id],
+                BindGroupLayoutId::PerFace as u32,
+                &self.per_face_bind_groups[*face_id],
                 &[],
             );
 
-            for face_id in face_ids.iter() {
-                let face = &self.faces[*face_id];
+            pass.draw(face.vertices.clone(), 0..1);
+        }
+    }
+}
 
-                // only skip the face if we have visibility data but it's not marked
-                if self.leaves.is_some() && !face.draw_flag.replace(false) {
-                    continue;
-                }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_sky_texture() {
+        #[rustfmt::skip]
+        let indices = [
+            1, 2, 3, 4,
+            5, 6, 7, 8,
+        ];
+
+        let (back, front) = split_sky_texture(&indices, 4, 2);
+        assert_eq!(back, vec![3, 4, 7, 8]);
+        assert_eq!(front, vec![1, 2, 5, 6]);
+    }
+
+    #[test]
+    fn test_mesh_round_trip() {
+        let vertices = vec![
+            BrushVertex {
+                position: [1.0, 2.0, 3.0],
+                normal: [0.0, 0.0, 1.0],
+                diffuse_texcoord: [0.25, 0.5],
+                lightmap_texcoord: [0.125, 0.75],
+                lightmap_anim: [0, 1, 255, 255],
+            };
+            3
+        ];
+        let faces = vec![FaceMesh {
+            vertices: 0..3,
+            min: Vector3::new(-1.0, -2.0, -3.0),
+            max: Vector3::new(1.0, 2.0, 3.0),
+        }];
+
+        let data = encode_mesh(&faces, &vertices);
+        assert_eq!(decode_mesh(&data, 1).unwrap(), (faces, vertices));
+    }
 
-                pass.set_bind_group(
-                    BindGroupLayoutId::PerFace as u32,
-                    &self.per_face_bind_groups[*face_id],
-                    &[],
-                );
+    #[test]
+    fn test_decode_mesh_errors() {
+        let faces = vec![FaceMesh {
+            vertices: 0..4,
+            min: Vector3::new(0.0, 0.0, 0.0),
+            max: Vector3::new(0.0, 0.0, 0.0),
+        }];
 
-                pass.draw(face.vertices.clone(), 0..1);
-            }
-        }
+        // the model doesn't match
+        assert!(decode_mesh(&encode_mesh(&faces, &[]), 2).is_err());
+
+        // the face refers to vertices that aren't there
+        assert!(decode_mesh(&encode_mesh(&faces, &[]), 1).is_err());
+
+        // the file is truncated
+        let data = encode_mesh(&faces[..0], &[]);
+        assert!(decode_mesh(&data[..4], 0).is_err());
+
+        // the vertex count is far larger than the data
+        let mut data = encode_mesh(&faces[..0], &[]);
+        data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_mesh(&data, 0).is_err());
     }
 }
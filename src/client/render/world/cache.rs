@@ -0,0 +1,258 @@
+// Copyright © 2020 Cormac O'Brien.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! On-disk cache of built brush model meshes.
+//!
+//! Building a brush model's vertices means removing collinear points,
+//! expanding triangle fans and subdividing every liquid and sky surface. The
+//! result depends only on the map file and `gl_subdivide_size`, so it is
+//! written to the cache directory and reused the next time the same map is
+//! loaded. Entries are keyed by a digest of the map file, so an edited map
+//! never picks up a stale mesh.
+
+use std::{
+    cell::RefCell,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::common::{bsp::MapDigest, console::ConsoleOutput, fs_util};
+
+use failure::Error;
+
+/// Identifies the first bytes of a cache entry.
+const MAGIC: &[u8; 4] = b"RMSH";
+
+/// Incremented whenever the layout of a cached mesh changes.
+const VERSION: u32 = 2;
+
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+const EXTENSION: &str = "mesh";
+
+/// Identifies a single brush model's mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshCacheKey {
+    /// The digest of the BSP file containing the model.
+    pub map: MapDigest,
+
+    /// The index of the model's first face in the BSP file.
+    pub first_face: usize,
+
+    /// The number of faces in the model.
+    pub face_count: usize,
+
+    /// The grid size used to subdivide liquid and sky surfaces.
+    pub subdivide_size: f32,
+}
+
+impl MeshCacheKey {
+    fn file_name(&self) -> String {
+        format!(
+            "{}-{}-{}-{:08x}.{}",
+            self.map,
+            self.first_face,
+            self.face_count,
+            self.subdivide_size.to_bits(),
+            EXTENSION,
+        )
+    }
+}
+
+/// A directory of cached brush model meshes.
+#[derive(Debug)]
+pub struct MeshCache {
+    dir: PathBuf,
+}
+
+impl MeshCache {
+    /// Uses `dir` as the cache directory. It is created when the first mesh is stored.
+    pub fn new<P>(dir: P) -> MeshCache
+    where
+        P: Into<PathBuf>,
+    {
+        MeshCache { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the cached mesh for `key`, if there is one.
+    ///
+    /// Entries written by a different version of the cache are ignored.
+    pub fn load(&self, key: &MeshCacheKey) -> Option<Vec<u8>> {
+        let mut data = fs::read(self.dir.join(key.file_name())).ok()?;
+        if data.len() < HEADER_LEN
+            || &data[..MAGIC.len()] != MAGIC
+            || data[MAGIC.len()..HEADER_LEN] != VERSION.to_le_bytes()
+        {
+            debug!("Ignoring stale mesh cache entry {}", key.file_name());
+            return None;
+        }
+
+        data.drain(..HEADER_LEN);
+        Some(data)
+    }
+
+    /// Stores the mesh for `key`, replacing any existing entry.
+    pub fn store(&self, key: &MeshCacheKey, mesh: &[u8]) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut data = Vec::with_capacity(HEADER_LEN + mesh.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(mesh);
+        fs_util::write_atomic(self.dir.join(key.file_name()), data)?;
+
+        Ok(())
+    }
+
+    /// Deletes every cached mesh and returns how many were removed.
+    pub fn flush(&self) -> Result<usize, Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(e) => e,
+            // nothing has been cached yet
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut count = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == EXTENSION) {
+                fs::remove_file(&path)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Implements the `cache_flush` command.
+pub fn cmd_cache_flush(
+    output: Rc<RefCell<ConsoleOutput>>,
+    cache: Rc<MeshCache>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => match cache.flush() {
+            Ok(count) => output
+                .borrow_mut()
+                .print(format!("Removed {} cached meshes", count)),
+            Err(e) => {
+                output
+                    .borrow_mut()
+                    .warn(format!("Couldn't flush {}: {}", cache.dir().display(), e))
+            }
+        },
+        _ => output.borrow_mut().print("usage: cache_flush"),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    fn key() -> MeshCacheKey {
+        MeshCacheKey {
+            map: MapDigest::new(b"maps/test.bsp"),
+            first_face: 0,
+            face_count: 10,
+            subdivide_size: 32.0,
+        }
+    }
+
+    fn test_cache(name: &str) -> MeshCache {
+        MeshCache::new(env::temp_dir().join(format!(
+            "richter-mesh-cache-{}-{}",
+            name,
+            std::process::id()
+        )))
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let cache = test_cache("store");
+        assert!(cache.load(&key()).is_none());
+
+        cache.store(&key(), &[1, 2, 3]).unwrap();
+        assert_eq!(cache.load(&key()).unwrap(), vec![1, 2, 3]);
+
+        // a different subdivision is a different mesh
+        let other = MeshCacheKey {
+            subdivide_size: 64.0,
+            ..key()
+        };
+        assert!(cache.load(&other).is_none());
+
+        // so is an edited map
+        let edited = MeshCacheKey {
+            map: MapDigest::new(b"maps/test.bsp (edited)"),
+            ..key()
+        };
+        assert!(cache.load(&edited).is_none());
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_ignores_other_versions() {
+        let cache = test_cache("version");
+        fs::create_dir_all(cache.dir()).unwrap();
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        data.push(1);
+        fs::write(cache.dir().join(key().file_name()), data).unwrap();
+        assert!(cache.load(&key()).is_none());
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_flush() {
+        let cache = test_cache("flush");
+        assert_eq!(cache.flush().unwrap(), 0);
+
+        cache.store(&key(), &[1]).unwrap();
+        cache
+            .store(
+                &MeshCacheKey {
+                    first_face: 10,
+                    ..key()
+                },
+                &[2],
+            )
+            .unwrap();
+
+        // files that aren't meshes are left alone
+        fs::write(cache.dir().join("notes.txt"), "keep").unwrap();
+
+        assert_eq!(cache.flush().unwrap(), 2);
+        assert!(cache.load(&key()).is_none());
+        assert!(cache.dir().join("notes.txt").exists());
+
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
@@ -5,7 +5,11 @@ use cgmath::{Matrix4, SquareMatrix as _, Vector3, Zero as _};
 use crate::{
     client::{
         entity::MAX_LIGHTS,
-        render::{pipeline::Pipeline, ui::quad::QuadPipeline, GraphicsState},
+        render::{
+            pipeline::{Pipeline, ShaderCompiler},
+            ui::quad::QuadPipeline,
+            GraphicsState,
+        },
     },
     common::util::any_as_bytes,
 };
@@ -86,12 +90,19 @@ pub struct PointLight {
     pub radius: f32,
 }
 
+/// Dynamic lights use linear falloff with no angle attenuation (`r_dynamic 1`).
+pub const LIGHT_MODE_CLASSIC: u32 = 1;
+
+/// Dynamic lights use smooth falloff and Lambertian shading (`r_dynamic 2`).
+pub const LIGHT_MODE_PER_PIXEL: u32 = 2;
+
 #[repr(C, align(256))]
 #[derive(Clone, Copy, Debug)]
 pub struct DeferredUniforms {
     pub inv_projection: [[f32; 4]; 4],
     pub light_count: u32,
-    pub _pad: [u32; 3],
+    pub light_mode: u32,
+    pub _pad: [u32; 2],
     pub lights: [PointLight; MAX_LIGHTS],
 }
 
@@ -104,7 +115,7 @@ pub struct DeferredPipeline {
 impl DeferredPipeline {
     pub fn new(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) -> DeferredPipeline {
         let (pipeline, bind_group_layouts) =
@@ -114,7 +125,8 @@ impl DeferredPipeline {
                 any_as_bytes(&DeferredUniforms {
                     inv_projection: Matrix4::identity().into(),
                     light_count: 0,
-                    _pad: [0; 3],
+                    light_mode: LIGHT_MODE_CLASSIC,
+                    _pad: [0; 2],
                     lights: [PointLight {
                         origin: Vector3::zero(),
                         radius: 0.0,
@@ -134,7 +146,7 @@ impl DeferredPipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) {
         let layout_refs: Vec<_> = self.bind_group_layouts.iter().collect();
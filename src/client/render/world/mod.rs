@@ -1,29 +1,36 @@
 pub mod alias;
 pub mod brush;
+pub mod cache;
 pub mod deferred;
 pub mod particle;
 pub mod postprocess;
+pub mod skybox;
 pub mod sprite;
 
-use std::{cell::RefCell, mem::size_of};
+use std::{cell::RefCell, collections::HashMap, mem::size_of, rc::Rc};
 
 use crate::{
     client::{
-        entity::particle::Particle,
+        entity::{particle::Particle, PlayerSkin},
         render::{
             pipeline::{Pipeline, PushConstantUpdate},
-            uniform::{DynamicUniformBufferBlock, UniformArrayFloat, UniformBool},
+            uniform::{BlockLifetime, DynamicUniformBufferBlock, UniformArrayFloat, UniformBool},
+            warp,
             world::{
-                alias::{AliasPipeline, AliasRenderer},
+                alias::{self, AliasPipeline, AliasRenderer, AliasSkin, Md3Renderer},
                 brush::{BrushPipeline, BrushRenderer, BrushRendererBuilder},
+                cache::MeshCache,
+                particle::ParticleStyle,
+                skybox::Skybox,
                 sprite::{SpritePipeline, SpriteRenderer},
             },
-            GraphicsState, DEPTH_ATTACHMENT_FORMAT, DIFFUSE_ATTACHMENT_FORMAT,
+            ColorblindMode, GraphicsState, DEPTH_ATTACHMENT_FORMAT, DIFFUSE_ATTACHMENT_FORMAT,
             LIGHT_ATTACHMENT_FORMAT, NORMAL_ATTACHMENT_FORMAT,
         },
         ClientEntity,
     },
     common::{
+        bsp::MapDigest,
         console::CvarRegistry,
         engine,
         math::Angles,
@@ -37,6 +44,9 @@ use bumpalo::Bump;
 use cgmath::{Euler, InnerSpace, Matrix4, SquareMatrix as _, Vector3, Vector4};
 use chrono::Duration;
 
+// fraction of the depth range used by the view model, as in the original engine
+const VIEW_MODEL_DEPTH_RANGE: f32 = 0.3;
+
 lazy_static! {
     static ref BIND_GROUP_LAYOUT_DESCRIPTOR_BINDINGS: [Vec<wgpu::BindGroupLayoutEntry>; 2] = [
         vec![
@@ -267,6 +277,8 @@ pub struct FrameUniforms {
 
     // TODO: pack flags into a bit string
     r_lightmap: UniformBool,
+    r_fullbright: UniformBool,
+    r_waterwarp: UniformBool,
 }
 
 #[repr(C, align(256))]
@@ -281,11 +293,48 @@ pub struct EntityUniforms {
 
 enum EntityRenderer {
     Alias(AliasRenderer),
+    Md3(Md3Renderer),
     Brush(BrushRenderer),
     Sprite(SpriteRenderer),
     None,
 }
 
+impl EntityRenderer {
+    fn new(
+        state: &GraphicsState,
+        model: &Model,
+        subdivide_size: f32,
+        mesh_cache: Option<(Rc<MeshCache>, MapDigest)>,
+    ) -> EntityRenderer {
+        match *model.kind() {
+            ModelKind::Alias(ref amodel) => {
+                EntityRenderer::Alias(AliasRenderer::new(state, amodel).unwrap())
+            }
+
+            ModelKind::Md3(ref md3_model) => {
+                EntityRenderer::Md3(Md3Renderer::new(state, md3_model).unwrap())
+            }
+
+            ModelKind::Brush(ref bmodel) => EntityRenderer::Brush(
+                BrushRendererBuilder::new(bmodel, false)
+                    .subdivide_size(subdivide_size)
+                    .mesh_cache(mesh_cache)
+                    .build(state)
+                    .unwrap(),
+            ),
+
+            ModelKind::Sprite(ref smodel) => {
+                EntityRenderer::Sprite(SpriteRenderer::new(&state, smodel))
+            }
+
+            _ => {
+                warn!("Non-brush renderers not implemented!");
+                EntityRenderer::None
+            }
+        }
+    }
+}
+
 /// Top-level renderer.
 pub struct WorldRenderer {
     worldmodel_renderer: BrushRenderer,
@@ -293,6 +342,11 @@ pub struct WorldRenderer {
 
     world_uniform_block: DynamicUniformBufferBlock<EntityUniforms>,
     entity_uniform_blocks: RefCell<Vec<DynamicUniformBufferBlock<EntityUniforms>>>,
+
+    // recolored player skins, keyed by player and model ID
+    player_skins: HashMap<(usize, usize), (PlayerSkin, Option<AliasSkin>)>,
+
+    skybox: Option<Skybox>,
 }
 
 impl WorldRenderer {
@@ -301,14 +355,39 @@ impl WorldRenderer {
         models: &[Model],
         worldmodel_id: usize,
         cvars: &mut CvarRegistry,
+        mesh_cache: Option<(Rc<MeshCache>, MapDigest)>,
     ) -> WorldRenderer {
         let mut worldmodel_renderer = None;
         let mut entity_renderers = Vec::new();
 
-        let world_uniform_block = state.entity_uniform_buffer_mut().allocate(EntityUniforms {
-            transform: Matrix4::identity(),
-            model: Matrix4::identity(),
-        });
+        let world_uniform_block = state.entity_uniform_buffer_mut().allocate(
+            BlockLifetime::Map,
+            EntityUniforms {
+                transform: Matrix4::identity(),
+                model: Matrix4::identity(),
+            },
+        );
+
+        let subdivide_size = cvars
+            .get_value("gl_subdivide_size")
+            .unwrap_or(warp::DEFAULT_SUBDIVIDE_SIZE);
+        let mesh_cache = match cvars.get_value("r_meshcache") {
+            Ok(v) if v != 0.0 => mesh_cache,
+            _ => None,
+        };
+
+        // the digest only covers the map file, so brush models loaded from other
+        // files (e.g. maps/b_batt0.bsp) are built without the cache
+        let world_bsp_data = match *models[worldmodel_id].kind() {
+            ModelKind::Brush(ref bmodel) => Some(bmodel.bsp_data()),
+            _ => None,
+        };
+        let map_mesh_cache = |model: &Model| match (&world_bsp_data, model.kind()) {
+            (Some(data), ModelKind::Brush(bmodel)) if Rc::ptr_eq(data, &bmodel.bsp_data()) => {
+                mesh_cache.clone()
+            }
+            _ => None,
+        };
 
         for (i, model) in models.iter().enumerate() {
             if i == worldmodel_id {
@@ -316,6 +395,8 @@ impl WorldRenderer {
                     ModelKind::Brush(ref bmodel) => {
                         worldmodel_renderer = Some(
                             BrushRendererBuilder::new(bmodel, true)
+                                .subdivide_size(subdivide_size)
+                                .mesh_cache(mesh_cache.clone())
                                 .build(state)
                                 .unwrap(),
                         );
@@ -323,29 +404,12 @@ impl WorldRenderer {
                     _ => panic!("Invalid worldmodel"),
                 }
             } else {
-                match *model.kind() {
-                    ModelKind::Alias(ref amodel) => entity_renderers.push(EntityRenderer::Alias(
-                        AliasRenderer::new(state, amodel).unwrap(),
-                    )),
-
-                    ModelKind::Brush(ref bmodel) => {
-                        entity_renderers.push(EntityRenderer::Brush(
-                            BrushRendererBuilder::new(bmodel, false)
-                                .build(state)
-                                .unwrap(),
-                        ));
-                    }
-
-                    ModelKind::Sprite(ref smodel) => {
-                        entity_renderers
-                            .push(EntityRenderer::Sprite(SpriteRenderer::new(&state, smodel)));
-                    }
-
-                    _ => {
-                        warn!("Non-brush renderers not implemented!");
-                        entity_renderers.push(EntityRenderer::None);
-                    }
-                }
+                entity_renderers.push(EntityRenderer::new(
+                    state,
+                    model,
+                    subdivide_size,
+                    map_mesh_cache(model),
+                ));
             }
         }
 
@@ -354,7 +418,80 @@ impl WorldRenderer {
             entity_renderers,
             world_uniform_block,
             entity_uniform_blocks: RefCell::new(Vec::new()),
+            player_skins: HashMap::new(),
+            skybox: None,
+        }
+    }
+
+    /// Rebuilds the renderer for a single model after it has been reloaded.
+    ///
+    /// Only alias, MD3 and sprite models can be reloaded, since brush models are
+    /// loaded with their map. Player skins built for the model are rebuilt on the
+    /// next call to `update_player_skins`.
+    pub fn reload_model(&mut self, state: &GraphicsState, model_id: usize, model: &Model) {
+        match *model.kind() {
+            ModelKind::Alias(_) | ModelKind::Md3(_) | ModelKind::Sprite(_) => (),
+            _ => {
+                warn!("Can't rebuild the renderer for {}", model.name());
+                return;
+            }
+        }
+
+        // entity renderers are looked up by model ID - 1
+        let renderer = match model_id
+            .checked_sub(1)
+            .and_then(|i| self.entity_renderers.get_mut(i))
+        {
+            Some(r) => r,
+            None => return,
+        };
+
+        *renderer = EntityRenderer::new(state, model, 0.0, None);
+        self.player_skins.retain(|&(_, id), _| id != model_id);
+    }
+
+    /// Replaces the world's scrolling sky with a sky box, or restores it if `skybox` is `None`.
+    pub fn set_skybox(&mut self, state: &GraphicsState, skybox: Option<Skybox>) {
+        self.worldmodel_renderer.set_skybox(state, skybox.as_ref());
+        self.skybox = skybox;
+    }
+
+    /// Builds skins for any players whose custom skin or colors have changed.
+    ///
+    /// Each player keeps one skin per model they're drawn with, which is only
+    /// rebuilt when their skin changes. Skins of players who are no longer
+    /// visible are released.
+    pub fn update_player_skins<'a, E>(&mut self, state: &GraphicsState, entities: E)
+    where
+        E: Iterator<Item = &'a ClientEntity>,
+    {
+        let mut player_skins = HashMap::new();
+
+        for ent in entities {
+            let player_skin = match ent.player_skin {
+                Some(ref s) => s,
+                None => continue,
+            };
+
+            let alias = match self.entity_renderers[ent.model_id() - 1] {
+                EntityRenderer::Alias(ref a) => a,
+                _ => continue,
+            };
+
+            let key = (player_skin.player_id, ent.model_id());
+            if player_skins.contains_key(&key) {
+                continue;
+            }
+
+            // reuse the skin from the last update if it hasn't changed
+            let skin = match self.player_skins.remove(&key) {
+                Some((ref prev, ref mut skin)) if prev == player_skin => skin.take(),
+                _ => alias.player_skin(state, player_skin),
+            };
+            player_skins.insert(key, (player_skin.clone(), skin));
         }
+
+        self.player_skins = player_skins;
     }
 
     pub fn update_uniform_buffers<'a, I>(
@@ -383,6 +520,8 @@ impl WorldRenderer {
                     camera_pos: camera.origin.extend(1.0),
                     time: engine::duration_to_f32(time),
                     r_lightmap: UniformBool::new(cvars.get_value("r_lightmap").unwrap() != 0.0),
+                    r_fullbright: UniformBool::new(cvars.get_value("r_fullbright").unwrap() != 0.0),
+                    r_waterwarp: UniformBool::new(cvars.get_value("r_waterwarp").unwrap() != 0.0),
                 })
             });
 
@@ -403,7 +542,9 @@ impl WorldRenderer {
 
             if ent_pos >= self.entity_uniform_blocks.borrow().len() {
                 // if we don't have enough blocks, get a new one
-                let block = state.entity_uniform_buffer_mut().allocate(ent_uniforms);
+                let block = state
+                    .entity_uniform_buffer_mut()
+                    .allocate(BlockLifetime::Map, ent_uniforms);
                 self.entity_uniform_blocks.borrow_mut().push(block);
             } else {
                 state
@@ -423,6 +564,7 @@ impl WorldRenderer {
         camera: &Camera,
         time: Duration,
         entities: E,
+        view_model: Option<&'a ClientEntity>,
         particles: P,
         lightstyle_values: &[f32],
         cvars: &CvarRegistry,
@@ -436,7 +578,7 @@ impl WorldRenderer {
             state,
             camera,
             time,
-            entities.clone(),
+            entities.clone().chain(view_model),
             lightstyle_values,
             cvars,
         );
@@ -447,6 +589,16 @@ impl WorldRenderer {
             &[],
         );
 
+        // with r_wateralpha below 1, liquids are blended in after everything opaque
+        let water_alpha = cvars
+            .get_value("r_wateralpha")
+            .unwrap_or(1.0)
+            .max(0.0)
+            .min(1.0);
+        let translucent_liquids = water_alpha < 1.0;
+
+        let lerp_models = cvars.get_value("r_lerpmodels").unwrap_or(1.0) != 0.0;
+
         // draw world
         info!("Drawing world");
         pass.set_pipeline(state.brush_pipeline().pipeline());
@@ -464,49 +616,157 @@ impl WorldRenderer {
             &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
             &[self.world_uniform_block.offset()],
         );
-        self.worldmodel_renderer.record_draw(state, pass, &bump, time, camera, 0);
+        self.worldmodel_renderer.record_draw(
+            state,
+            pass,
+            &bump,
+            time,
+            camera,
+            0,
+            translucent_liquids,
+        );
 
         // draw entities
         info!("Drawing entities");
+        let mut ent_count = 0;
         for (ent_pos, ent) in entities.enumerate() {
+            self.record_entity_draw(state, pass, bump, camera, time, ent_pos, ent, lerp_models);
+            ent_count += 1;
+        }
+
+        // draw the view model squeezed into the front of the depth range so it
+        // never clips into walls
+        if let Some(ent) = view_model {
+            info!("Drawing view model");
+            let size = state.initial_pass_target().size();
+            let (width, height) = (size.width as f32, size.height as f32);
+            pass.set_viewport(0.0, 0.0, width, height, 0.0, VIEW_MODEL_DEPTH_RANGE);
+            self.record_entity_draw(state, pass, bump, camera, time, ent_count, ent, lerp_models);
+            pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        }
+
+        // draw translucent liquids
+        if translucent_liquids {
+            info!("Drawing liquids");
+            pass.set_pipeline(state.brush_pipeline().liquid_pipeline());
+            BrushPipeline::set_push_constants(
+                pass,
+                Update(bump.alloc(brush::VertexPushConstants {
+                    transform: camera.view_projection(),
+                    model_view: camera.view(),
+                })),
+                Clear,
+                Clear,
+            );
             pass.set_bind_group(
                 BindGroupLayoutId::PerEntity as u32,
                 &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
-                &[self.entity_uniform_blocks.borrow()[ent_pos].offset()],
+                &[self.world_uniform_block.offset()],
             );
+            self.worldmodel_renderer
+                .record_draw_liquids(state, pass, &bump, time, 0, water_alpha);
+        }
 
-            match self.renderer_for_entity(&ent) {
-                EntityRenderer::Brush(ref bmodel) => {
-                    pass.set_pipeline(state.brush_pipeline().pipeline());
-                    BrushPipeline::set_push_constants(
-                        pass,
-                        Update(bump.alloc(brush::VertexPushConstants {
-                            transform: self.calculate_mvp_transform(camera, ent),
-                            model_view: self.calculate_mv_transform(camera, ent),
-                        })),
-                        Retain,
-                        Retain,
-                    );
-                    bmodel.record_draw(state, pass, &bump, time, camera, ent.frame_id);
-                }
-                EntityRenderer::Alias(ref alias) => {
-                    pass.set_pipeline(state.alias_pipeline().pipeline());
-                    AliasPipeline::set_push_constants(pass, Clear, Clear, Clear);
-                    alias.record_draw(state, pass, time, ent.get_frame_id(), ent.get_skin_id())
-                }
-                EntityRenderer::Sprite(ref sprite) => {
-                    pass.set_pipeline(state.sprite_pipeline().pipeline());
-                    SpritePipeline::set_push_constants(pass, Clear, Clear, Clear);
-                    sprite.record_draw(state, pass, ent.get_frame_id(), time)
-                }
-                _ => warn!("non-brush renderers not implemented!"),
-                // _ => unimplemented!(),
+        let colorblind = ColorblindMode::from_cvar(cvars.get_value("v_colorblind").unwrap_or(0.0));
+        let particle_style =
+            ParticleStyle::from_cvar(cvars.get_value("r_particles").unwrap_or(2.0));
+        state.particle_pipeline().record_draw(
+            pass,
+            &bump,
+            camera,
+            particles,
+            colorblind,
+            particle_style,
+        );
+    }
+
+    // Draws a single entity using the uniform block at `ent_pos`.
+    fn record_entity_draw<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        time: Duration,
+        ent_pos: usize,
+        ent: &'a ClientEntity,
+        lerp_models: bool,
+    ) {
+        use PushConstantUpdate::*;
+
+        pass.set_bind_group(
+            BindGroupLayoutId::PerEntity as u32,
+            &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
+            &[self.entity_uniform_blocks.borrow()[ent_pos].offset()],
+        );
+
+        // with r_lerpmodels 0, alias models snap between frames
+        let frame_blend = if lerp_models {
+            ent.frame_blend(time)
+        } else {
+            1.0
+        };
+
+        match self.renderer_for_entity(&ent) {
+            EntityRenderer::Brush(ref bmodel) => {
+                pass.set_pipeline(state.brush_pipeline().pipeline());
+                BrushPipeline::set_push_constants(
+                    pass,
+                    Update(bump.alloc(brush::VertexPushConstants {
+                        transform: self.calculate_mvp_transform(camera, ent),
+                        model_view: self.calculate_mv_transform(camera, ent),
+                    })),
+                    Retain,
+                    Retain,
+                );
+                bmodel.record_draw(state, pass, &bump, time, camera, ent.frame_id, false);
             }
+            EntityRenderer::Alias(ref alias) => {
+                pass.set_pipeline(state.alias_pipeline().pipeline());
+                AliasPipeline::set_push_constants(
+                    pass,
+                    Update(bump.alloc(alias::VertexPushConstants { blend: frame_blend })),
+                    Clear,
+                    Clear,
+                );
+                // offset the clock so models with random sync don't animate in lockstep
+                let skin = ent.player_skin.as_ref().and_then(|player_skin| {
+                    match self
+                        .player_skins
+                        .get(&(player_skin.player_id, ent.model_id()))?
+                    {
+                        (ref s, Some(ref skin)) if s == player_skin => Some(skin),
+                        _ => None,
+                    }
+                });
+                alias.record_draw(
+                    state,
+                    pass,
+                    time + ent.sync_base,
+                    ent.get_frame_id(),
+                    ent.prev_frame_id,
+                    ent.get_skin_id(),
+                    skin,
+                )
+            }
+            EntityRenderer::Md3(ref md3) => {
+                pass.set_pipeline(state.alias_pipeline().pipeline());
+                AliasPipeline::set_push_constants(
+                    pass,
+                    Update(bump.alloc(alias::VertexPushConstants { blend: frame_blend })),
+                    Clear,
+                    Clear,
+                );
+                md3.record_draw(state, pass, ent.get_frame_id(), ent.prev_frame_id)
+            }
+            EntityRenderer::Sprite(ref sprite) => {
+                pass.set_pipeline(state.sprite_pipeline().pipeline());
+                SpritePipeline::set_push_constants(pass, Clear, Clear, Clear);
+                sprite.record_draw(state, pass, ent.get_frame_id(), time + ent.sync_base)
+            }
+            _ => warn!("non-brush renderers not implemented!"),
+            // _ => unimplemented!(),
         }
-
-        state
-            .particle_pipeline()
-            .record_draw(pass, &bump, camera, particles);
     }
 
     fn renderer_for_entity(&self, ent: &ClientEntity) -> &EntityRenderer {
@@ -5,9 +5,9 @@ use crate::{
         entity::particle::Particle,
         render::{
             create_texture,
-            pipeline::{Pipeline, PushConstantUpdate},
+            pipeline::{Pipeline, PushConstantUpdate, ShaderCompiler},
             world::{Camera, WorldPipelineBase},
-            Palette, TextureData,
+            ColorblindMode, Palette, TextureData,
         },
     },
     common::{math::Angles, util::any_slice_as_bytes},
@@ -15,6 +15,7 @@ use crate::{
 
 use bumpalo::Bump;
 use cgmath::Matrix4;
+use num::FromPrimitive;
 
 lazy_static! {
     static ref BIND_GROUP_LAYOUT_DESCRIPTOR_BINDINGS: [Vec<wgpu::BindGroupLayoutEntry>; 1] = [
@@ -66,6 +67,30 @@ const PARTICLE_TEXTURE_PIXELS: [u8; 64] = [
     0, 0, 1, 1, 1, 1, 0, 0,
 ];
 
+/// How particles are drawn, selected by `r_particles`.
+///
+/// All styles share the same simulation; only the fragment shading differs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum ParticleStyle {
+    /// Solid palette-colored squares, as in the software renderer.
+    Square = 0,
+
+    /// Antialiased circles.
+    Round = 1,
+
+    /// Small textured sprites.
+    Textured = 2,
+}
+
+impl ParticleStyle {
+    /// Returns the style corresponding to the given value of `r_particles`.
+    ///
+    /// Unknown values select the textured style.
+    pub fn from_cvar(value: f32) -> ParticleStyle {
+        ParticleStyle::from_i32(value as i32).unwrap_or(ParticleStyle::Textured)
+    }
+}
+
 pub struct ParticlePipeline {
     pipeline: wgpu::RenderPipeline,
     bind_group_layouts: Vec<wgpu::BindGroupLayout>,
@@ -80,7 +105,7 @@ impl ParticlePipeline {
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
         palette: &Palette,
     ) -> ParticlePipeline {
@@ -163,7 +188,7 @@ impl ParticlePipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) {
         let layout_refs: Vec<_> = self.bind_group_layouts.iter().collect();
@@ -188,6 +213,8 @@ impl ParticlePipeline {
         bump: &'a Bump,
         camera: &Camera,
         particles: P,
+        colorblind: ColorblindMode,
+        style: ParticleStyle,
     ) where
         P: Iterator<Item = &'b Particle>,
     {
@@ -217,7 +244,8 @@ impl ParticlePipeline {
                 })),
                 Retain,
                 Update(bump.alloc(FragmentPushConstants {
-                    color: particle.color() as u32,
+                    color: colorblind.remap_index(particle.color()) as u32,
+                    style: style as u32,
                 })),
             );
 
@@ -234,6 +262,7 @@ pub struct VertexPushConstants {
 #[derive(Copy, Clone, Debug)]
 pub struct FragmentPushConstants {
     pub color: u32,
+    pub style: u32,
 }
 
 impl Pipeline for ParticlePipeline {
@@ -280,7 +309,18 @@ impl Pipeline for ParticlePipeline {
     }
 
     fn color_state_descriptors() -> Vec<wgpu::ColorStateDescriptor> {
-        WorldPipelineBase::color_state_descriptors()
+        let mut descs = WorldPipelineBase::color_state_descriptors();
+
+        // blend diffuse and light so that round particles have smooth edges
+        for attachment in &[0, 2] {
+            descs[*attachment].color_blend = wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            };
+        }
+
+        descs
     }
 
     fn depth_stencil_state_descriptor() -> Option<wgpu::DepthStencilStateDescriptor> {
@@ -1,7 +1,11 @@
 use std::mem::size_of;
 
 use crate::{
-    client::render::{pipeline::Pipeline, ui::quad::QuadPipeline, GraphicsState},
+    client::render::{
+        pipeline::{Pipeline, ShaderCompiler},
+        ui::quad::QuadPipeline,
+        GraphicsState,
+    },
     common::util::any_as_bytes,
 };
 
@@ -45,6 +49,9 @@ lazy_static! {
 #[derive(Clone, Copy, Debug)]
 pub struct PostProcessUniforms {
     pub color_shift: [f32; 4],
+
+    // x is the time in seconds, y is nonzero if the view should be warped
+    pub view_warp: [f32; 4],
 }
 
 pub struct PostProcessPipeline {
@@ -56,7 +63,7 @@ pub struct PostProcessPipeline {
 impl PostProcessPipeline {
     pub fn new(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) -> PostProcessPipeline {
         let (pipeline, bind_group_layouts) =
@@ -65,6 +72,7 @@ impl PostProcessPipeline {
             unsafe {
                 any_as_bytes(&PostProcessUniforms {
                     color_shift: [0.0; 4],
+                    view_warp: [0.0; 4],
                 })
             },
             wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
@@ -80,7 +88,7 @@ impl PostProcessPipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         sample_count: u32,
     ) {
         let layout_refs: Vec<_> = self.bind_group_layouts.iter().collect();
@@ -188,12 +196,27 @@ impl PostProcessRenderer {
         PostProcessRenderer { bind_group }
     }
 
-    pub fn update_uniform_buffers(&self, state: &GraphicsState, color_shift: [f32; 4]) {
-        // update color shift
+    /// Updates the color shift and view warp.
+    ///
+    /// `view_warp` is the current time in seconds if the view is inside a liquid.
+    pub fn update_uniform_buffers(
+        &self,
+        state: &GraphicsState,
+        color_shift: [f32; 4],
+        view_warp: Option<f32>,
+    ) {
+        let view_warp = match view_warp {
+            Some(time) => [time, 1.0, 0.0, 0.0],
+            None => [0.0; 4],
+        };
+
         state
             .queue()
             .write_buffer(state.postprocess_pipeline().uniform_buffer(), 0, unsafe {
-                any_as_bytes(&PostProcessUniforms { color_shift })
+                any_as_bytes(&PostProcessUniforms {
+                    color_shift,
+                    view_warp,
+                })
             });
     }
 
@@ -202,8 +225,9 @@ impl PostProcessRenderer {
         state: &'pass GraphicsState,
         pass: &mut wgpu::RenderPass<'pass>,
         color_shift: [f32; 4],
+        view_warp: Option<f32>,
     ) {
-        self.update_uniform_buffers(state, color_shift);
+        self.update_uniform_buffers(state, color_shift, view_warp);
         pass.set_pipeline(state.postprocess_pipeline().pipeline());
         pass.set_vertex_buffer(0, state.quad_pipeline().vertex_buffer().slice(..));
         pass.set_bind_group(0, &self.bind_group, &[]);
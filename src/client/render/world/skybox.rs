@@ -0,0 +1,231 @@
+// Copyright © 2020 Cormac O'Brien.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Six-sided sky boxes.
+//!
+//! A sky box is six square images, `gfx/env/<name><suffix>.tga` (or `.pcx`),
+//! one for each face of a cube surrounding the viewer. When one is loaded, sky
+//! surfaces show the sky box in the direction they're viewed from instead of
+//! the scrolling sky texture, so it appears infinitely far away.
+
+use crate::{
+    client::render::{GraphicsState, Palette, DIFFUSE_TEXTURE_FORMAT},
+    common::{pcx, tga, vfs::Vfs},
+};
+
+use failure::Error;
+
+/// The directory sky box images are loaded from.
+const SKYBOX_DIR: &str = "gfx/env";
+
+/// File name suffixes of the six faces, in texture layer order.
+///
+/// The layers face +X, -X, +Y, -Y, +Z and -Z in Quake coordinates. If this
+/// order is changed, `skybox_texcoord` in the brush shader must change with it.
+pub const SKYBOX_SUFFIXES: [&str; 6] = ["rt", "lf", "bk", "ft", "up", "dn"];
+
+const BYTES_PER_PIXEL: u32 = 4;
+
+// a single decoded face
+struct SkyboxFace {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+fn load_face(vfs: &Vfs, palette: &Palette, stem: &str) -> Result<SkyboxFace, Error> {
+    if let Ok(file) = vfs.open(format!("{}.tga", stem)) {
+        let image = tga::load(file)?;
+        return Ok(SkyboxFace {
+            width: image.width(),
+            height: image.height(),
+            rgba: image.rgba().to_vec(),
+        });
+    }
+
+    if let Ok(file) = vfs.open(format!("{}.pcx", stem)) {
+        let image = pcx::load(file)?;
+
+        // prefer the image's own palette over the game palette
+        let rgba = match image.palette() {
+            Some(colors) => {
+                let mut rgba = Vec::with_capacity(image.indices().len() * 4);
+                for index in image.indices() {
+                    let i = *index as usize * 3;
+                    rgba.extend_from_slice(&colors[i..i + 3]);
+                    rgba.push(0xFF);
+                }
+                rgba
+            }
+
+            None => palette.translate(image.indices()).0.rgba.into_owned(),
+        };
+
+        return Ok(SkyboxFace {
+            width: image.width(),
+            height: image.height(),
+            rgba,
+        });
+    }
+
+    bail!("Couldn't find {}.tga or {}.pcx", stem, stem)
+}
+
+// checks that the faces are square and the same size and concatenates them
+fn pack_faces(faces: &[SkyboxFace]) -> Result<(u32, Vec<u8>), Error> {
+    ensure!(!faces.is_empty(), "No sky box faces");
+    let size = faces[0].width;
+
+    let mut rgba = Vec::with_capacity(faces.iter().map(|f| f.rgba.len()).sum());
+    for (face, suffix) in faces.iter().zip(SKYBOX_SUFFIXES.iter()) {
+        ensure!(
+            face.width == size && face.height == size,
+            "Sky box face \"{}\" is {}x{}, expected {}x{}",
+            suffix,
+            face.width,
+            face.height,
+            size,
+            size
+        );
+        rgba.extend_from_slice(&face.rgba);
+    }
+
+    Ok((size, rgba))
+}
+
+/// Creates a six-layer texture from the concatenated RGBA data of each face.
+pub fn create_skybox_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: u32,
+    rgba: &[u8],
+) -> wgpu::Texture {
+    let extent = wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth: SKYBOX_SUFFIXES.len() as u32,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("sky box texture"),
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DIFFUSE_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED,
+    });
+
+    queue.write_texture(
+        wgpu::TextureCopyView {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        rgba,
+        wgpu::TextureDataLayout {
+            offset: 0,
+            bytes_per_row: size * BYTES_PER_PIXEL,
+            rows_per_image: size,
+        },
+        extent,
+    );
+
+    texture
+}
+
+/// A loaded sky box.
+pub struct Skybox {
+    name: String,
+    // kept alive for the view
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl Skybox {
+    /// Loads the six faces of the sky box with the given name.
+    pub fn load<S>(state: &GraphicsState, name: S) -> Result<Skybox, Error>
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+
+        let mut faces = Vec::with_capacity(SKYBOX_SUFFIXES.len());
+        for suffix in SKYBOX_SUFFIXES.iter() {
+            let stem = format!("{}/{}{}", SKYBOX_DIR, name, suffix);
+            faces.push(load_face(state.vfs(), state.palette(), &stem)?);
+        }
+
+        let (size, rgba) = pack_faces(&faces)?;
+        let texture = create_skybox_texture(state.device(), state.queue(), size, &rgba);
+
+        // layered 2D textures get an array view by default
+        let view = texture.create_default_view();
+
+        Ok(Skybox {
+            name: name.to_owned(),
+            texture,
+            view,
+        })
+    }
+
+    /// Returns the name the sky box was loaded with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn face(width: u32, height: u32, value: u8) -> SkyboxFace {
+        SkyboxFace {
+            width,
+            height,
+            rgba: vec![value; (width * height * BYTES_PER_PIXEL) as usize],
+        }
+    }
+
+    #[test]
+    fn test_pack_faces() {
+        let faces: Vec<_> = (0..6).map(|i| face(2, 2, i)).collect();
+        let (size, rgba) = pack_faces(&faces).unwrap();
+        assert_eq!(size, 2);
+        assert_eq!(rgba.len(), 6 * 16);
+
+        // faces are stored in layer order
+        assert_eq!(rgba[16 * 3], 3);
+    }
+
+    #[test]
+    fn test_pack_faces_mismatched() {
+        let mut faces: Vec<_> = (0..6).map(|i| face(2, 2, i)).collect();
+        faces[4] = face(4, 4, 4);
+        assert!(pack_faces(&faces).is_err());
+
+        faces[4] = face(2, 1, 4);
+        assert!(pack_faces(&faces).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use std::mem::size_of;
 use crate::{
     client::render::{
         world::{BindGroupLayoutId, WorldPipelineBase},
-        GraphicsState, Pipeline, TextureData,
+        GraphicsState, Pipeline, ShaderCompiler, TextureData,
     },
     common::{
         sprite::{SpriteFrame, SpriteKind, SpriteModel, SpriteSubframe},
@@ -54,7 +54,7 @@ pub struct SpritePipeline {
 impl SpritePipeline {
     pub fn new(
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         world_bind_group_layouts: &[wgpu::BindGroupLayout],
         sample_count: u32,
     ) -> SpritePipeline {
@@ -76,7 +76,7 @@ impl SpritePipeline {
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
-        compiler: &mut shaderc::Compiler,
+        compiler: &mut ShaderCompiler,
         world_bind_group_layouts: &[wgpu::BindGroupLayout],
         sample_count: u32,
     ) {
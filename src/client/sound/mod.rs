@@ -19,7 +19,12 @@
 // SOFTWARE.
 
 mod error;
+#[cfg(feature = "music")]
+mod music;
+
 pub use self::error::{SoundError, SoundErrorKind};
+#[cfg(feature = "music")]
+pub use self::music::MusicPlayer;
 
 use std::{
     cell::{Cell, RefCell},
@@ -34,6 +39,7 @@ use failure::ResultExt;
 use cgmath::{InnerSpace, Vector3};
 use failure::Error;
 use hound::{WavReader, WavWriter};
+use num::FromPrimitive;
 use rodio::{
     source::{Buffered, SamplesConverter},
     Decoder, Device, Sink, Source,
@@ -41,6 +47,51 @@ use rodio::{
 
 pub const DISTANCE_ATTENUATION_FACTOR: f32 = 0.001;
 
+/// Volume multiplier applied to sounds which are occluded by world geometry.
+pub const OCCLUSION_ATTENUATION_FACTOR: f32 = 0.35;
+
+/// Volume multiplier applied to all sounds while the listener is underwater.
+pub const UNDERWATER_ATTENUATION_FACTOR: f32 = 0.5;
+
+/// Distance within which sounds play at full volume under `DistanceModel::Inverse`.
+pub const INVERSE_REFERENCE_DISTANCE: f32 = 100.0;
+
+/// How sound volume falls off with distance, selected by `snd_distance_model`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum DistanceModel {
+    /// Volume falls linearly to silence, as in the original engine.
+    Linear = 0,
+
+    /// Volume falls off in inverse proportion to distance, so distant sounds
+    /// stay faintly audible.
+    Inverse = 1,
+}
+
+impl DistanceModel {
+    /// Returns the model corresponding to the given value of `snd_distance_model`.
+    ///
+    /// Unknown values select the linear model.
+    pub fn from_cvar(value: f32) -> DistanceModel {
+        DistanceModel::from_i32(value as i32).unwrap_or(DistanceModel::Linear)
+    }
+
+    /// Returns the volume multiplier for a sound `distance` units away.
+    ///
+    /// `attenuation` is the sound's own attenuation, which `rolloff` scales.
+    pub fn gain(self, distance: f32, attenuation: f32, rolloff: f32) -> f32 {
+        let attenuation = attenuation * rolloff;
+        match self {
+            DistanceModel::Linear => {
+                (1.0 - distance * attenuation * DISTANCE_ATTENUATION_FACTOR).max(0.0)
+            }
+            DistanceModel::Inverse => {
+                let excess = (distance - INVERSE_REFERENCE_DISTANCE).max(0.0);
+                INVERSE_REFERENCE_DISTANCE / (INVERSE_REFERENCE_DISTANCE + attenuation * excess)
+            }
+        }
+    }
+}
+
 /// Data needed for sound spatialization.
 ///
 /// This struct is updated every frame.
@@ -49,6 +100,9 @@ pub struct Listener {
     origin: Cell<Vector3<f32>>,
     left_ear: Cell<Vector3<f32>>,
     right_ear: Cell<Vector3<f32>>,
+    distance_model: Cell<DistanceModel>,
+    rolloff: Cell<f32>,
+    underwater: Cell<bool>,
 }
 
 impl Listener {
@@ -57,6 +111,9 @@ impl Listener {
             origin: Cell::new(Vector3::new(0.0, 0.0, 0.0)),
             left_ear: Cell::new(Vector3::new(0.0, 0.0, 0.0)),
             right_ear: Cell::new(Vector3::new(0.0, 0.0, 0.0)),
+            distance_model: Cell::new(DistanceModel::Linear),
+            rolloff: Cell::new(1.0),
+            underwater: Cell::new(false),
         }
     }
 
@@ -84,22 +141,61 @@ impl Listener {
         self.right_ear.set(new_origin);
     }
 
+    /// Sets how sounds fade with distance from the listener.
+    ///
+    /// `rolloff` scales every sound's attenuation.
+    pub fn set_distance_model(&self, model: DistanceModel, rolloff: f32) {
+        self.distance_model.set(model);
+        self.rolloff.set(rolloff);
+    }
+
+    /// Sets whether the listener is inside a liquid, which muffles every sound.
+    pub fn set_underwater(&self, underwater: bool) {
+        self.underwater.set(underwater);
+    }
+
     pub fn attenuate(
         &self,
         emitter_origin: Vector3<f32>,
         base_volume: f32,
         attenuation: f32,
     ) -> f32 {
-        let decay = (emitter_origin - self.origin.get()).magnitude()
-            * attenuation
-            * DISTANCE_ATTENUATION_FACTOR;
-        let volume = ((1.0 - decay) * base_volume).max(0.0);
-        volume
+        let distance = (emitter_origin - self.origin.get()).magnitude();
+        let gain = self
+            .distance_model
+            .get()
+            .gain(distance, attenuation, self.rolloff.get());
+        let volume = (gain * base_volume).max(0.0);
+        if self.underwater.get() {
+            volume * UNDERWATER_ATTENUATION_FACTOR
+        } else {
+            volume
+        }
+    }
+
+    /// Like `attenuate`, but additionally muffles the sound if `occluded` is true.
+    pub fn attenuate_occluded(
+        &self,
+        emitter_origin: Vector3<f32>,
+        base_volume: f32,
+        attenuation: f32,
+        occluded: bool,
+    ) -> f32 {
+        let volume = self.attenuate(emitter_origin, base_volume, attenuation);
+        if occluded {
+            volume * OCCLUSION_ATTENUATION_FACTOR
+        } else {
+            volume
+        }
     }
 }
 
 #[derive(Clone)]
-pub struct AudioSource(Buffered<SamplesConverter<Decoder<BufReader<Cursor<Vec<u8>>>>, f32>>);
+pub struct AudioSource(
+    Buffered<SamplesConverter<Decoder<BufReader<Cursor<Vec<u8>>>>, f32>>,
+    // size of the decoded WAV data in bytes
+    usize,
+);
 
 impl AudioSource {
     pub fn load<S>(vfs: &Vfs, name: S) -> Result<AudioSource, SoundError>
@@ -154,6 +250,7 @@ impl AudioSource {
             })?;
         }
 
+        let size = data.len();
         let src = Decoder::new(BufReader::new(Cursor::new(data)))
             .context(SoundErrorKind::DecodeFailed {
                 name: name.to_owned(),
@@ -161,7 +258,14 @@ impl AudioSource {
             .convert_samples()
             .buffered();
 
-        Ok(AudioSource(src))
+        Ok(AudioSource(src, size))
+    }
+
+    /// Returns the size in bytes of the sound's sample data.
+    ///
+    /// Clones of a source share its data.
+    pub fn memory_usage(&self) -> usize {
+        self.1
     }
 }
 
@@ -194,10 +298,20 @@ impl StaticSound {
         }
     }
 
-    pub fn update(&self, listener: &Listener) {
+    /// Returns the origin of this sound.
+    pub fn origin(&self) -> Vector3<f32> {
+        self.origin
+    }
+
+    pub fn update(&self, listener: &Listener, occluded: bool) {
         let sink = self.sink.borrow_mut();
 
-        sink.set_volume(listener.attenuate(self.origin, self.volume, self.attenuation));
+        sink.set_volume(listener.attenuate_occluded(
+            self.origin,
+            self.volume,
+            self.attenuation,
+            occluded,
+        ));
     }
 }
 
@@ -247,13 +361,14 @@ impl Channel {
         self.sink.replace(Some(new_sink));
     }
 
-    pub fn update(&self, ent_pos: Vector3<f32>, listener: &Listener) {
+    pub fn update(&self, ent_pos: Vector3<f32>, listener: &Listener, occluded: bool) {
         if let Some(ref sink) = *self.sink.borrow_mut() {
             // attenuate using quake coordinates since distance is the same either way
-            sink.set_volume(listener.attenuate(
+            sink.set_volume(listener.attenuate_occluded(
                 ent_pos,
                 self.master_vol.get(),
                 self.attenuation.get(),
+                occluded,
             ));
         };
     }
@@ -280,3 +395,52 @@ impl Channel {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_underwater_attenuation() {
+        let listener = Listener::new();
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        assert_eq!(listener.attenuate(origin, 1.0, 1.0), 1.0);
+
+        listener.set_underwater(true);
+        assert_eq!(
+            listener.attenuate(origin, 1.0, 1.0),
+            UNDERWATER_ATTENUATION_FACTOR
+        );
+        assert_eq!(
+            listener.attenuate_occluded(origin, 1.0, 1.0, true),
+            UNDERWATER_ATTENUATION_FACTOR * OCCLUSION_ATTENUATION_FACTOR
+        );
+    }
+
+    #[test]
+    fn test_distance_model_gain() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        let far = INVERSE_REFERENCE_DISTANCE + 1000.0;
+
+        let linear = DistanceModel::Linear;
+        assert_eq!(linear.gain(0.0, 1.0, 1.0), 1.0);
+        assert!(close(
+            linear.gain(INVERSE_REFERENCE_DISTANCE, 1.0, 1.0),
+            1.0 - INVERSE_REFERENCE_DISTANCE * DISTANCE_ATTENUATION_FACTOR
+        ));
+        assert_eq!(linear.gain(far, 1.0, 1.0), 0.0);
+
+        let inverse = DistanceModel::Inverse;
+        assert_eq!(inverse.gain(0.0, 1.0, 1.0), 1.0);
+        assert_eq!(inverse.gain(INVERSE_REFERENCE_DISTANCE, 1.0, 1.0), 1.0);
+        assert!(close(inverse.gain(far, 1.0, 1.0), 100.0 / 1100.0));
+
+        // distant sounds stay audible, and fade faster with more rolloff
+        assert!(inverse.gain(far, 1.0, 2.0) > 0.0);
+        assert!(inverse.gain(far, 1.0, 2.0) < inverse.gain(far, 1.0, 1.0));
+
+        // sounds without attenuation play at full volume everywhere
+        assert_eq!(linear.gain(far, 0.0, 1.0), 1.0);
+        assert_eq!(inverse.gain(far, 0.0, 1.0), 1.0);
+    }
+}
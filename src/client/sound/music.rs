@@ -0,0 +1,115 @@
+// Copyright © 2018 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Background music, standing in for the original game's CD audio.
+//!
+//! Quake played its soundtrack from CD tracks; ports instead ship the tracks as files named
+//! `music/trackNN.ogg` (or `.wav`), where `NN` is the CD track number.
+
+use std::{
+    io::{BufReader, Cursor, Read},
+    rc::Rc,
+};
+
+use crate::{
+    client::sound::{SoundError, SoundErrorKind},
+    common::vfs::Vfs,
+};
+
+use failure::ResultExt;
+use rodio::{Decoder, Device, Sink, Source};
+
+const TRACK_EXTENSIONS: &[&str] = &["ogg", "wav"];
+
+/// Plays one music track at a time.
+pub struct MusicPlayer {
+    device: Rc<Device>,
+    sink: Option<Sink>,
+    track: Option<u8>,
+}
+
+impl MusicPlayer {
+    pub fn new(device: Rc<Device>) -> MusicPlayer {
+        MusicPlayer {
+            device,
+            sink: None,
+            track: None,
+        }
+    }
+
+    /// Starts playing the given CD track, replacing any track already playing.
+    ///
+    /// If the track is already playing, it continues uninterrupted.
+    pub fn play(&mut self, vfs: &Vfs, track: u8, looping: bool) -> Result<(), SoundError> {
+        if self.track == Some(track) && self.sink.as_ref().map_or(false, |s| !s.empty()) {
+            return Ok(());
+        }
+
+        self.stop();
+
+        let (name, data) = load_track(vfs, track)?;
+        let source = Decoder::new(BufReader::new(Cursor::new(data)))
+            .context(SoundErrorKind::DecodeFailed { name })?
+            .convert_samples::<f32>();
+
+        let sink = Sink::new(&self.device);
+        if looping {
+            sink.append(source.buffered().repeat_infinite());
+        } else {
+            sink.append(source);
+        }
+
+        self.sink = Some(sink);
+        self.track = Some(track);
+        Ok(())
+    }
+
+    /// Stops the current track, if any.
+    pub fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        self.track = None;
+    }
+
+    /// Sets the music volume, where 1.0 is the track's original volume.
+    pub fn set_volume(&self, volume: f32) {
+        if let Some(ref sink) = self.sink {
+            sink.set_volume(volume.max(0.0));
+        }
+    }
+}
+
+/// Reads the first file found for the given track.
+fn load_track(vfs: &Vfs, track: u8) -> Result<(String, Vec<u8>), SoundError> {
+    let mut last_name = String::new();
+    for ext in TRACK_EXTENSIONS {
+        let name = format!("music/track{:02}.{}", track, ext);
+        if let Ok(mut file) = vfs.open(&name) {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)
+                .context(SoundErrorKind::Io { name: name.clone() })?;
+            return Ok((name, data));
+        }
+        last_name = name;
+    }
+
+    Err(SoundErrorKind::Io { name: last_name }.into())
+}
@@ -12,6 +12,12 @@ use cgmath::{Angle as _, Deg, Euler, InnerSpace as _, Matrix3, Vector3};
 use chrono::Duration;
 use super::IntermissionKind;
 
+// the largest step up the view is allowed to lag behind
+const STEP_SMOOTH_MAX: f32 = 12.0;
+
+// how long it takes the view to catch up to a step of `STEP_SMOOTH_MAX` units
+const STEP_SMOOTH_TIME: f32 = 0.1;
+
 pub struct View {
     // entity "holding" the camera
     entity_id: usize,
@@ -36,6 +42,24 @@ pub struct View {
 
     // punch angles from server
     punch_angles: Angles,
+
+    // whether the pitch is returning to `ideal_pitch`
+    pitch_drift: bool,
+
+    // whether mouse look was active on the previous frame
+    prev_mlook: bool,
+
+    // time spent running forward on the ground, used by lookspring
+    drift_move: f32,
+
+    // smoothed height of the view entity, lagging behind it when stepping up
+    step_z: Option<f32>,
+
+    // vertical offset applied to the view origin by step smoothing
+    step_offset: f32,
+
+    // vertical offset applied to the view origin by walking bob
+    bob: f32,
 }
 
 impl View {
@@ -49,6 +73,12 @@ impl View {
             damage_angles: Angles::zero(),
             damage_time: Duration::zero(),
             punch_angles: Angles::zero(),
+            pitch_drift: false,
+            prev_mlook: false,
+            drift_move: 0.0,
+            step_z: None,
+            step_offset: 0.0,
+            bob: 0.0,
         }
     }
 
@@ -93,6 +123,98 @@ impl View {
         self.input_angles = input_angles;
     }
 
+    /// Begin returning the view pitch to the ideal pitch.
+    pub fn start_pitch_drift(&mut self) {
+        self.pitch_drift = true;
+    }
+
+    /// Stop returning the view pitch to the ideal pitch.
+    pub fn stop_pitch_drift(&mut self) {
+        self.pitch_drift = false;
+    }
+
+    /// Recenter the view pitch once the player has run forward for a while.
+    ///
+    /// With `lookspring` and without mouse look, running forward at full speed
+    /// on the ground for `v_centermove` seconds starts the pitch drifting back
+    /// to the ideal pitch, so keyboard players don't have to center the view by
+    /// hand.
+    pub fn spring_pitch(
+        &mut self,
+        frame_time: Duration,
+        running: bool,
+        on_ground: bool,
+        mlook: bool,
+        lookspring: bool,
+        v_centermove: f32,
+    ) {
+        if !lookspring || mlook || !on_ground || !running {
+            self.drift_move = 0.0;
+            return;
+        }
+
+        self.drift_move += duration_to_f32(frame_time);
+        if self.drift_move > v_centermove {
+            self.start_pitch_drift();
+        }
+    }
+
+    /// Returns the vertical offset applied to the view origin by step smoothing.
+    pub fn step_offset(&self) -> f32 {
+        self.step_offset
+    }
+
+    /// Smooth out upward steps in the view entity's height.
+    ///
+    /// When the view entity moves up while on the ground (climbing stairs or
+    /// riding a lift), the view is allowed to lag behind by up to
+    /// `STEP_SMOOTH_MAX` units and catches up over roughly `STEP_SMOOTH_TIME`
+    /// seconds rather than snapping to the new height.
+    pub fn smooth_step(&mut self, origin_z: f32, on_ground: bool, frame_time: Duration) {
+        let step_z = match self.step_z {
+            Some(z) if on_ground && origin_z > z => {
+                let rate = STEP_SMOOTH_MAX / STEP_SMOOTH_TIME;
+                let dt = duration_to_f32(frame_time).max(0.0);
+                (z + rate * dt)
+                    .min(origin_z)
+                    .max(origin_z - STEP_SMOOTH_MAX)
+            }
+
+            // falling, jumping or stepping down snaps the view immediately
+            _ => origin_z,
+        };
+
+        self.step_z = Some(step_z);
+        self.step_offset = step_z - origin_z;
+    }
+
+    /// Discard any step smoothing in progress.
+    pub fn reset_step(&mut self) {
+        self.step_z = None;
+        self.step_offset = 0.0;
+    }
+
+    /// Returns the vertical offset applied to the view origin by walking bob.
+    pub fn bob(&self) -> f32 {
+        self.bob
+    }
+
+    /// Recalculate the walking bob from the view entity's velocity.
+    ///
+    /// The view doesn't bob during intermission.
+    pub fn update_bob(
+        &mut self,
+        time: Duration,
+        velocity: Vector3<f32>,
+        intermission: Option<&IntermissionKind>,
+        vars: BobVars,
+    ) {
+        self.bob = match intermission {
+            Some(_) => 0.0,
+            None => bob(time, velocity, vars),
+        };
+    }
+
     pub fn handle_input(
         &mut self,
         frame_time: Duration,
@@ -102,7 +224,9 @@ impl View {
         cl_anglespeedkey: f32,
         cl_pitchspeed: f32,
         cl_yawspeed: f32,
-        mouse_vars: MouseVars
+        v_centerspeed: f32,
+        mouse_vars: MouseVars,
+        joy_vars: JoyVars,
     ) {
         let frame_time_f32 = duration_to_f32(frame_time);
         let speed = if game_input.action_state(Action::Speed) {
@@ -116,6 +240,12 @@ impl View {
             return;
         }
 
+        // with lookspring, releasing mouse look recenters the view
+        if self.prev_mlook && !mlook && mouse_vars.lookspring {
+            self.start_pitch_drift();
+        }
+        self.prev_mlook = mlook;
+
         if !game_input.action_state(Action::Strafe) {
             let right_factor = game_input.action_state(Action::Right) as i32 as f32;
             let left_factor = game_input.action_state(Action::Left) as i32 as f32;
@@ -127,15 +257,63 @@ impl View {
         let lookdown_factor = game_input.action_state(Action::LookDown) as i32 as f32;
         self.input_angles.pitch += Deg(speed * cl_pitchspeed * (lookdown_factor - lookup_factor));
 
-        if mlook {
-            let pitch_factor = mouse_vars.m_pitch * mouse_vars.sensitivity;
+        if lookup_factor != 0.0 || lookdown_factor != 0.0 {
+            self.stop_pitch_drift();
+        }
+
+        // with +klook, forward and back look up and down instead of moving
+        if game_input.action_state(Action::KLook) {
+            let forward_factor = game_input.action_state(Action::Forward) as i32 as f32;
+            let back_factor = game_input.action_state(Action::Back) as i32 as f32;
+            self.input_angles.pitch += Deg(speed * cl_pitchspeed * (back_factor - forward_factor));
+
+            if forward_factor != 0.0 || back_factor != 0.0 {
+                self.stop_pitch_drift();
+            }
+        }
+
+        // mouse movement that doesn't turn the view moves the player instead (see
+        // `Client::handle_input`)
+        let strafe = game_input.action_state(Action::Strafe);
+        let (mouse_x, mouse_y) = game_input.filtered_mouse_delta(mouse_vars.m_filter);
+        if !strafe && !(mouse_vars.lookstrafe && mlook) {
             let yaw_factor = mouse_vars.m_yaw * mouse_vars.sensitivity;
-            self.input_angles.pitch += Deg(game_input.mouse_delta().1 as f32 * pitch_factor);
-            self.input_angles.yaw -= Deg(game_input.mouse_delta().0 as f32 * yaw_factor);
+            self.input_angles.yaw -= Deg(mouse_x as f32 * yaw_factor);
+            self.input_angles.yaw = self.input_angles.yaw.normalize();
         }
 
-        if lookup_factor != 0.0 || lookdown_factor != 0.0 {
-            // TODO: V_StopPitchDrift
+        if mlook && !strafe {
+            let mut pitch_factor = mouse_vars.m_pitch * mouse_vars.sensitivity;
+            if mouse_vars.m_invert {
+                pitch_factor = -pitch_factor;
+            }
+
+            self.input_angles.pitch += Deg(mouse_y as f32 * pitch_factor);
+
+            if mouse_y != 0.0 {
+                self.stop_pitch_drift();
+            }
+        }
+
+        // the look stick turns at up to joy_sensitivity_* degrees per second
+        let (look_x, look_y) = game_input.look_axes();
+        self.input_angles.yaw -= Deg(frame_time_f32 * joy_vars.joy_sensitivity_yaw * look_x);
+        self.input_angles.yaw = self.input_angles.yaw.normalize();
+        self.input_angles.pitch -= Deg(frame_time_f32 * joy_vars.joy_sensitivity_pitch * look_y);
+        if look_y != 0.0 {
+            self.stop_pitch_drift();
+        }
+
+        if self.pitch_drift {
+            let delta = self.ideal_pitch - self.input_angles.pitch;
+            let step = v_centerspeed * frame_time_f32;
+
+            if delta.0.abs() <= step {
+                self.input_angles.pitch = self.ideal_pitch;
+                self.stop_pitch_drift();
+            } else {
+                self.input_angles.pitch += Deg(step * delta.0.signum());
+            }
         }
 
         // clamp pitch to [-70, 80] and roll to [-50, 50]
@@ -157,13 +335,20 @@ impl View {
 
         // dmg_factor is at most 10.0
         let dmg_factor = (armor_dmg + health_dmg).min(20.0) / 2.0;
-        let dmg_vector = (view_ent_origin - src_origin).normalize();
-        let rot = view_ent_angles.mat3_quake();
 
-        let roll_factor = dmg_vector.dot(-rot.x);
+        // damage from inside the player has no direction, so it doesn't kick
+        let dmg_vector = src_origin - view_ent_origin;
+        let dmg_vector = match dmg_vector.magnitude2() {
+            m if m > 0.0 => dmg_vector.normalize(),
+            _ => Vector3::new(0.0, 0.0, 0.0),
+        };
+
+        // hits from the side roll the view, hits from the front or back pitch it
+        let (forward, right) = yaw_vectors(view_ent_angles.yaw);
+        let roll_factor = dmg_vector.dot(right);
         self.damage_angles.roll = Deg(dmg_factor * roll_factor * vars.v_kickroll);
 
-        let pitch_factor = dmg_vector.dot(rot.y);
+        let pitch_factor = dmg_vector.dot(forward);
         self.damage_angles.pitch = Deg(dmg_factor * pitch_factor * vars.v_kickpitch);
     }
 
@@ -182,7 +367,13 @@ impl View {
             yaw: Deg(0.0),
         };
 
-        let kick_factor = duration_to_f32(self.damage_time - time).max(0.0) / kick_vars.v_kicktime;
+        // damage kick fades out over v_kicktime seconds
+        let kick_factor = match kick_vars.v_kicktime {
+            t if t > 0.0 => (duration_to_f32(self.damage_time - time) / t)
+                .max(0.0)
+                .min(1.0),
+            _ => 0.0,
+        };
         let damage_angles = self.damage_angles * kick_factor;
 
         // always idle during intermission
@@ -194,19 +385,38 @@ impl View {
         self.input_angles + move_angles + damage_angles + self.punch_angles + idle_angles
     }
 
-    pub fn origin(&self) {
+    /// Returns the position of the eye of an entity at `entity_origin`.
+    ///
+    /// This includes the view height, step smoothing and walking bob.
+    pub fn origin(&self, entity_origin: Vector3<f32>) -> Vector3<f32> {
         // offset the view plane a tiny bit to keep it from intersecting liquid planes
         let view_plane_offset = Vector3::new(1.0 / 32.0, 1.0 / 32.0, 1.0 / 32.0);
+
+        entity_origin
+            + view_plane_offset
+            + Vector3::new(0.0, 0.0, self.view_height + self.step_offset + self.bob)
     }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct MouseVars {
+    pub m_filter: bool,
+    pub m_invert: bool,
     pub m_pitch: f32,
     pub m_yaw: f32,
+    pub m_forward: f32,
+    pub m_side: f32,
+    pub lookstrafe: bool,
+    pub lookspring: bool,
     pub sensitivity: f32,
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct JoyVars {
+    pub joy_sensitivity_pitch: f32,
+    pub joy_sensitivity_yaw: f32,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct KickVars {
     pub v_kickpitch: f32,
@@ -221,7 +431,15 @@ pub struct BobVars {
     pub cl_bobup: f32,
 }
 
+/// Returns the vertical view offset from walking at `velocity`.
+///
+/// The view rises over the first `cl_bobup` of each `cl_bobcycle` seconds and
+/// falls over the rest, scaled by horizontal speed and `cl_bob`.
 pub fn bob(time: Duration, velocity: Vector3<f32>, vars: BobVars) -> f32 {
+    if vars.cl_bobcycle <= 0.0 {
+        return 0.0;
+    }
+
     let time = duration_to_f32(time);
     let ratio = (time % vars.cl_bobcycle) / vars.cl_bobcycle;
     let cycle = if ratio < vars.cl_bobup {
@@ -237,19 +455,31 @@ pub fn bob(time: Duration, velocity: Vector3<f32>, vars: BobVars) -> f32 {
     bob.max(-7.0).min(4.0)
 }
 
+/// Returns the horizontal forward and right vectors for a view facing `yaw`.
+///
+/// Pitch and roll are ignored, so looking up or down doesn't change how
+/// sideways movement or damage from the side is measured.
+fn yaw_vectors(yaw: Deg<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let (sy, cy) = yaw.0.to_radians().sin_cos();
+    (Vector3::new(cy, sy, 0.0), Vector3::new(sy, -cy, 0.0))
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct RollVars {
     pub cl_rollangle: f32,
     pub cl_rollspeed: f32,
 }
 
+/// Returns the view roll from moving sideways at `velocity`.
+///
+/// The roll grows with sideways speed up to `cl_rollangle` degrees at `cl_rollspeed`.
 pub fn roll(angles: Angles, velocity: Vector3<f32>, vars: RollVars) -> Deg<f32> {
-    let rot = angles.mat3_quake();
-    let side = velocity.dot(rot.y);
+    let (_, right) = yaw_vectors(angles.yaw);
+    let side = velocity.dot(right);
     let sign = side.signum();
     let side_abs = side.abs();
 
-    let roll_abs = if side < vars.cl_rollspeed {
+    let roll_abs = if side_abs < vars.cl_rollspeed {
         side_abs * vars.cl_rollangle / vars.cl_rollspeed
     } else {
         vars.cl_rollangle
@@ -277,3 +507,132 @@ pub fn idle(time: Duration, vars: IdleVars) -> Angles {
 
     Angles { pitch, roll, yaw }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROLL_VARS: RollVars = RollVars {
+        cl_rollangle: 2.0,
+        cl_rollspeed: 200.0,
+    };
+
+    const BOB_VARS: BobVars = BobVars {
+        cl_bob: 0.02,
+        cl_bobcycle: 0.6,
+        cl_bobup: 0.5,
+    };
+
+    const KICK_VARS: KickVars = KickVars {
+        v_kickpitch: 0.6,
+        v_kickroll: 0.6,
+        v_kicktime: 0.5,
+    };
+
+    fn facing(pitch: f32, yaw: f32) -> Angles {
+        Angles {
+            pitch: Deg(pitch),
+            roll: Deg(0.0),
+            yaw: Deg(yaw),
+        }
+    }
+
+    fn assert_approx(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_roll() {
+        // facing +x, so -y is to the right
+        let right = Vector3::new(0.0, -100.0, 0.0);
+        assert_approx(roll(facing(0.0, 0.0), right, ROLL_VARS).0, 1.0);
+        assert_approx(roll(facing(0.0, 0.0), -right, ROLL_VARS).0, -1.0);
+        assert_approx(roll(facing(0.0, 0.0), right * 4.0, ROLL_VARS).0, 2.0);
+
+        // forward movement doesn't roll
+        let forward = Vector3::new(100.0, 0.0, 0.0);
+        assert_approx(roll(facing(0.0, 0.0), forward, ROLL_VARS).0, 0.0);
+
+        // looking up or down doesn't change the roll
+        assert_approx(roll(facing(60.0, 0.0), right, ROLL_VARS).0, 1.0);
+        assert_approx(roll(facing(-80.0, 0.0), right, ROLL_VARS).0, 1.0);
+
+        // facing +y, +x is to the right
+        assert_approx(roll(facing(30.0, 90.0), forward, ROLL_VARS).0, 1.0);
+    }
+
+    #[test]
+    fn test_bob() {
+        let velocity = Vector3::new(100.0, 0.0, 0.0);
+
+        // start of the cycle and the top of the rise
+        assert_approx(bob(Duration::zero(), velocity, BOB_VARS), 0.6);
+        assert_approx(bob(Duration::milliseconds(150), velocity, BOB_VARS), 2.0);
+
+        // bottom of the fall
+        assert_approx(bob(Duration::milliseconds(450), velocity, BOB_VARS), -0.8);
+
+        // standing still or moving vertically doesn't bob
+        let vertical = Vector3::new(0.0, 0.0, 1000.0);
+        assert_approx(bob(Duration::milliseconds(150), vertical, BOB_VARS), 0.0);
+
+        // the offset is clamped to [-7, 4]
+        assert_approx(
+            bob(Duration::milliseconds(150), velocity * 10.0, BOB_VARS),
+            4.0,
+        );
+        assert_approx(
+            bob(Duration::milliseconds(450), velocity * 10.0, BOB_VARS),
+            -7.0,
+        );
+
+        let no_cycle = BobVars {
+            cl_bobcycle: 0.0,
+            ..BOB_VARS
+        };
+        assert_approx(bob(Duration::milliseconds(150), velocity, no_cycle), 0.0);
+    }
+
+    #[test]
+    fn test_damage_kick_direction() {
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        let kick = |angles, src| {
+            let mut view = View::new();
+            view.handle_damage(Duration::zero(), 0.0, 10.0, origin, angles, src, KICK_VARS);
+            view.damage_angles
+        };
+
+        // facing +x, hit from the right rolls the view and doesn't pitch it
+        let right = Vector3::new(0.0, -10.0, 0.0);
+        let angles = kick(facing(0.0, 0.0), right);
+        assert_approx(angles.roll.0, 3.0);
+        assert_approx(angles.pitch.0, 0.0);
+
+        // hit from the front pitches the view and doesn't roll it
+        let front = Vector3::new(10.0, 0.0, 0.0);
+        let angles = kick(facing(0.0, 0.0), front);
+        assert_approx(angles.roll.0, 0.0);
+        assert_approx(angles.pitch.0, 3.0);
+
+        // the kick is the same when looking up or down
+        let angles = kick(facing(45.0, 0.0), front);
+        assert_approx(angles.pitch.0, 3.0);
+        let angles = kick(facing(-45.0, 0.0), right);
+        assert_approx(angles.roll.0, 3.0);
+
+        // facing +y, a hit from -x is from the left
+        let angles = kick(facing(0.0, 90.0), Vector3::new(-10.0, 0.0, 0.0));
+        assert_approx(angles.roll.0, -3.0);
+        assert_approx(angles.pitch.0, 0.0);
+
+        // damage from inside the player has no direction
+        let angles = kick(facing(0.0, 0.0), origin);
+        assert_approx(angles.roll.0, 0.0);
+        assert_approx(angles.pitch.0, 0.0);
+    }
+}
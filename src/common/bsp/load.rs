@@ -95,6 +95,29 @@ enum BspFileError {
     InvalidTextureFrameSpecifier(String),
     #[error("texture has primary animation with 0 frames: {0}")]
     EmptyPrimaryAnimation(String),
+    #[error("animated texture {name} is missing frame {frame}")]
+    MissingTextureFrame { name: String, frame: char },
+}
+
+/// Checks that the frames of an animation are numbered consecutively from `first`.
+///
+/// `frames` must already be sorted by name.
+fn check_texture_frames(
+    stem: &str,
+    frames: &[(usize, BspFileTexture)],
+    first: char,
+) -> Result<(), BspFileError> {
+    for (i, (_, texture)) in frames.iter().enumerate() {
+        let expected = (first as u8 + i as u8) as char;
+        if texture.name.chars().nth(1) != Some(expected) {
+            return Err(BspFileError::MissingTextureFrame {
+                name: stem.to_owned(),
+                frame: expected,
+            });
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -606,9 +629,9 @@ where
             Err(BspFileError::EmptyPrimaryAnimation(name.to_owned()))?;
         }
 
-        // TODO: ensure one-to-one frame specifiers
         // sort names in ascending order to get the frames ordered correctly
         pri.sort_unstable_by(|(_, tex), (_, other)| tex.name.cmp(&other.name));
+        check_texture_frames(&name, &pri, '0')?;
 
         // TODO: verify width and height?
         let width = pri[0].1.width;
@@ -633,6 +656,7 @@ where
             0 => None,
             _ => {
                 alt.sort_unstable_by(|(_, tex), (_, other)| tex.name.cmp(&other.name));
+                check_texture_frames(&name, &alt, 'a')?;
                 let mut alternate = Vec::new();
                 for (file_id, file_texture) in alt {
                     alt_corresp_file_ids.push(file_id);
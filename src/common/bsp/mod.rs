@@ -118,7 +118,7 @@
 
 mod load;
 
-use std::{collections::HashSet, error::Error, fmt, iter::Iterator, rc::Rc};
+use std::{collections::HashSet, error::Error, fmt, iter::Iterator, mem::size_of_val, rc::Rc};
 
 use crate::common::math::{Hyperplane, HyperplaneSide, LinePlaneIntersect};
 
@@ -241,6 +241,22 @@ impl BspTexture {
     pub fn kind(&self) -> &BspTextureKind {
         &self.kind
     }
+
+    /// Returns the number of bytes used by the mipmaps of every frame.
+    pub fn memory_usage(&self) -> usize {
+        let frame_size = |f: &BspTextureFrame| f.mipmaps.iter().map(|m| m.len()).sum::<usize>();
+        match self.kind {
+            BspTextureKind::Static(ref frame) => frame_size(frame),
+            BspTextureKind::Animated {
+                ref primary,
+                ref alternate,
+            } => primary
+                .iter()
+                .chain(alternate.iter().flatten())
+                .map(frame_size)
+                .sum(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -343,6 +359,18 @@ pub enum BspLeafContents {
     CurrentDown = 14,
 }
 
+impl BspLeafContents {
+    /// Returns whether this is water, slime, lava or a water current.
+    ///
+    /// Views inside a liquid are warped and their sound is muffled.
+    pub fn is_liquid(&self) -> bool {
+        match *self {
+            BspLeafContents::Empty | BspLeafContents::Solid => false,
+            _ => true,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum BspCollisionNodeChild {
     Node(usize),
@@ -715,6 +743,35 @@ pub struct BspData {
     pub(crate) hulls: [BspCollisionHull; MAX_HULLS],
 }
 
+/// Identifies a particular build of a map file, so data derived from it can be cached.
+///
+/// This is the file's length and a 64-bit FNV-1a hash of its contents. It is
+/// not compatible with the MD4 checksums used by QuakeWorld.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MapDigest {
+    len: u64,
+    hash: u64,
+}
+
+impl MapDigest {
+    pub fn new(data: &[u8]) -> MapDigest {
+        let hash = data.iter().fold(0xcbf29ce484222325, |hash: u64, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        MapDigest {
+            len: data.len() as u64,
+            hash,
+        }
+    }
+}
+
+impl fmt::Display for MapDigest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}-{:x}", self.hash, self.len)
+    }
+}
+
 impl BspData {
     pub fn planes(&self) -> &[Hyperplane] {
         &self.planes
@@ -780,6 +837,22 @@ impl BspData {
         }
     }
 
+    /// Returns the average light level of each of a face's light styles.
+    ///
+    /// Each entry pairs a light style with the mean of its lightmap samples, in the range
+    /// `[0, 255]`. Faces without a lightmap return an empty `Vec`.
+    pub fn face_light_levels(&self, face_id: usize) -> Vec<(u8, f32)> {
+        self.face_lightmaps(face_id)
+            .iter()
+            .zip(self.faces[face_id].light_styles.iter())
+            .map(|(lightmap, style)| {
+                let data = lightmap.data();
+                let sum: u32 = data.iter().map(|x| *x as u32).sum();
+                (*style, sum as f32 / data.len() as f32)
+            })
+            .collect()
+    }
+
     pub fn faces(&self) -> &[BspFace] {
         &self.faces
     }
@@ -808,6 +881,28 @@ impl BspData {
         &self.hulls
     }
 
+    /// Returns the approximate number of bytes used by the geometry, visibility and lighting data.
+    ///
+    /// Textures are not included; see `texture_memory_usage`.
+    pub fn memory_usage(&self) -> usize {
+        size_of_val(&**self.planes)
+            + size_of_val(&*self.vertices)
+            + size_of_val(&*self.visibility)
+            + size_of_val(&*self.render_nodes)
+            + size_of_val(&*self.texinfo)
+            + size_of_val(&*self.faces)
+            + size_of_val(&*self.lightmaps)
+            + size_of_val(&*self.leaves)
+            + size_of_val(&*self.facelist)
+            + size_of_val(&*self.edges)
+            + size_of_val(&*self.edgelist)
+    }
+
+    /// Returns the number of bytes used by the texture mipmaps.
+    pub fn texture_memory_usage(&self) -> usize {
+        self.textures.iter().map(|t| t.memory_usage()).sum()
+    }
+
     /// Locates the leaf containing the given position vector and returns its index.
     pub fn find_leaf<V>(&self, pos: V) -> usize
     where
@@ -1016,6 +1111,23 @@ mod test {
     use super::*;
     use cgmath::Zero;
 
+    #[test]
+    fn test_map_digest() {
+        // reference values for 64-bit FNV-1a
+        assert_eq!(MapDigest::new(b"").to_string(), "cbf29ce484222325-0");
+        assert_eq!(MapDigest::new(b"a").to_string(), "af63dc4c8601ec8c-1");
+        assert_ne!(MapDigest::new(b"maps/e1m1"), MapDigest::new(b"maps/e1m2"));
+    }
+
+    #[test]
+    fn test_is_liquid() {
+        assert!(!BspLeafContents::Empty.is_liquid());
+        assert!(!BspLeafContents::Solid.is_liquid());
+        assert!(BspLeafContents::Water.is_liquid());
+        assert!(BspLeafContents::Lava.is_liquid());
+        assert!(BspLeafContents::CurrentDown.is_liquid());
+    }
+
     #[test]
     fn test_hull_for_bounds() {
         let hull =
@@ -49,6 +49,12 @@ impl Display for ConsoleError {
 pub enum ConsoleErrorKind {
     #[fail(display = "Failed to parse cvar as a number: {} = {}", name, value)]
     CvarParseFailed { name: String, value: String },
+    #[fail(display = "Cvar {} = \"{}\" is not a valid {}", name, value, expected)]
+    CvarTypeMismatch {
+        name: String,
+        value: String,
+        expected: &'static str,
+    },
     #[fail(display = "Command already registered: {}", name)]
     DuplicateCommand { name: String },
     #[fail(display = "Cvar already registered: {}", name)]
@@ -22,15 +22,29 @@ mod error;
 pub use self::error::{ConsoleError, ConsoleErrorKind};
 
 use std::{
-    cell::{Ref, RefCell},
+    cell::{Cell, Ref, RefCell, RefMut},
     collections::{HashMap, VecDeque},
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
     iter::FromIterator,
-    rc::Rc,
+    mem::size_of,
+    path::Path,
+    rc::{Rc, Weak},
 };
 
-use crate::common::parse;
+use crate::common::{l10n, parse, vfs::Vfs};
 
-use failure::{Error, ResultExt};
+use chrono::{Duration, Local};
+use failure::Error;
+
+/// The maximum number of nested alias expansions.
+///
+/// This keeps self-referencing aliases from overflowing the stack.
+pub const MAX_ALIAS_DEPTH: usize = 16;
+
+/// The default number of lines kept in the console scrollback.
+pub const DEFAULT_SCROLLBACK: usize = 1024;
 
 /// Stores console commands.
 pub struct CmdRegistry {
@@ -94,7 +108,7 @@ impl CmdRegistry {
     /// Executes a command.
     ///
     /// Returns an error if no command with the specified name exists.
-    pub fn exec<S>(&mut self, name: S, args: &[&str]) -> Result<(), ConsoleError>
+    pub fn exec<S>(&self, name: S, args: &[&str]) -> Result<(), ConsoleError>
     where
         S: AsRef<str>,
     {
@@ -114,6 +128,45 @@ impl CmdRegistry {
     {
         self.cmds.contains_key(name.as_ref())
     }
+
+    /// Returns the names of all registered commands in sorted order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.cmds.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+/// Returns true if `name` matches `pattern`.
+///
+/// `*` matches any sequence of characters and `?` matches any single character. Matching is
+/// case-insensitive, like command and cvar lookup in the original engine's console.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    // position of the last `*` in the pattern and the name position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // let the last `*` absorb one more character
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
 /// A configuration variable.
@@ -123,7 +176,10 @@ struct Cvar {
     // Value of this variable
     val: String,
 
-    // If true, this variable should be archived in vars.rc
+    // Numeric value of this variable, cached whenever `val` changes. None if `val` isn't a number
+    num: Option<f32>,
+
+    // If true, this variable should be archived in config.cfg
     archive: bool,
 
     // If true:
@@ -133,10 +189,28 @@ struct Cvar {
 
     // The default value of this variable
     default: String,
+
+    // If true, this variable can't be set by commands from the server
+    user_only: bool,
+}
+
+/// A snapshot of a `Cvar`, as listed by `cvarlist`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CvarInfo {
+    pub name: String,
+    pub value: String,
+    pub default: String,
+    pub archive: bool,
+    pub notify: bool,
 }
 
+/// A callback invoked with the new value of a `Cvar` whenever it is set.
+pub type CvarObserver = Rc<dyn Fn(&str)>;
+
 pub struct CvarRegistry {
     cvars: RefCell<HashMap<String, Cvar>>,
+    observers: RefCell<HashMap<String, Vec<(usize, CvarObserver)>>>,
+    next_observer_id: Cell<usize>,
 }
 
 impl CvarRegistry {
@@ -144,6 +218,52 @@ impl CvarRegistry {
     pub fn new() -> CvarRegistry {
         CvarRegistry {
             cvars: RefCell::new(HashMap::new()),
+            observers: RefCell::new(HashMap::new()),
+            next_observer_id: Cell::new(0),
+        }
+    }
+
+    /// Register a callback to be invoked whenever the named `Cvar` is set.
+    ///
+    /// Returns an ID which can be passed to `remove_observer`. Callbacks fire on every
+    /// successful `set`, even if the value is unchanged, and may freely access the registry.
+    pub fn add_observer<S>(&self, name: S, observer: CvarObserver) -> Result<usize, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        if !self.contains(name) {
+            Err(ConsoleErrorKind::NoSuchCvar {
+                name: name.to_owned(),
+            })?;
+        }
+
+        let id = self.next_observer_id.get();
+        self.next_observer_id.set(id + 1);
+        self.observers
+            .borrow_mut()
+            .entry(name.to_owned())
+            .or_insert_with(Vec::new)
+            .push((id, observer));
+
+        Ok(id)
+    }
+
+    /// Remove a callback registered with `add_observer`.
+    ///
+    /// Returns `true` if an observer with the given ID was registered on the named `Cvar`.
+    pub fn remove_observer<S>(&self, name: S, id: usize) -> bool
+    where
+        S: AsRef<str>,
+    {
+        match self.observers.borrow_mut().get_mut(name.as_ref()) {
+            Some(list) => {
+                let len = list.len();
+                list.retain(|(obs_id, _)| *obs_id != id);
+                list.len() != len
+            }
+
+            None => false,
         }
     }
 
@@ -170,9 +290,11 @@ impl CvarRegistry {
                     name.to_owned(),
                     Cvar {
                         val: default.to_owned(),
+                        num: default.parse().ok(),
                         archive,
                         notify,
                         default: default.to_owned(),
+                        user_only: false,
                     },
                 );
             }
@@ -191,7 +313,7 @@ impl CvarRegistry {
 
     /// Register a new archived `Cvar` with the given name.
     ///
-    /// The value of this `Cvar` should be written to `vars.rc` whenever the game is closed or
+    /// The value of this `Cvar` should be written to `config.cfg` whenever the game is closed or
     /// `host_writeconfig` is issued.
     pub fn register_archive<S>(&self, name: S, default: S) -> Result<(), ConsoleError>
     where
@@ -214,7 +336,7 @@ impl CvarRegistry {
 
     /// Register a new notify + archived `Cvar` with the given name.
     ///
-    /// The value of this `Cvar` should be written to `vars.rc` whenever the game is closed or
+    /// The value of this `Cvar` should be written to `config.cfg` whenever the game is closed or
     /// `host_writeconfig` is issued.
     ///
     /// Additionally, when this `Cvar` is set:
@@ -227,6 +349,35 @@ impl CvarRegistry {
         self.register_impl(name, default, true, true)
     }
 
+    /// Marks a `Cvar` as user-only.
+    ///
+    /// User-only `Cvar`s can't be set by text the server stuffs into the console, whatever
+    /// `cl_allowstuffedcmds` is set to.
+    pub fn set_user_only<S>(&self, name: S) -> Result<(), ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        let mut cvars = self.cvars.borrow_mut();
+        let cvar = cvars.get_mut(name).ok_or(ConsoleErrorKind::NoSuchCvar {
+            name: name.to_owned(),
+        })?;
+        cvar.user_only = true;
+
+        Ok(())
+    }
+
+    /// Returns `true` if the named `Cvar` exists and is user-only.
+    pub fn is_user_only<S>(&self, name: S) -> bool
+    where
+        S: AsRef<str>,
+    {
+        self.cvars
+            .borrow()
+            .get(name.as_ref())
+            .map_or(false, |cvar| cvar.user_only)
+    }
+
     pub fn get<S>(&self, name: S) -> Result<String, ConsoleError>
     where
         S: AsRef<str>,
@@ -252,24 +403,82 @@ impl CvarRegistry {
             name: name.to_owned(),
         })?;
 
-        // try parsing as f32
-        let val_string = cvar.val.clone();
-        let val = match val_string.parse::<f32>() {
-            Ok(v) => Ok(v),
-            // if parse fails, reset to default valud and try again
-            Err(_) => {
-                cvar.val = cvar.default.clone();
-                cvar.val.parse::<f32>()
-            }
+        if let Some(v) = cvar.num {
+            return Ok(v);
         }
-        .context(ConsoleErrorKind::CvarParseFailed {
+
+        // if parse fails, reset to default value and try again
+        cvar.val = cvar.default.clone();
+        cvar.num = cvar.val.parse().ok();
+        let val = cvar.num.ok_or(ConsoleErrorKind::CvarParseFailed {
+            name: name.to_owned(),
+            value: cvar.val.clone(),
+        })?;
+
+        Ok(val)
+    }
+
+    fn get_typed<S, T, F>(&self, name: S, expected: &'static str, f: F) -> Result<T, ConsoleError>
+    where
+        S: AsRef<str>,
+        F: FnOnce(&Cvar) -> Option<T>,
+    {
+        let name = name.as_ref();
+        let cvars = self.cvars.borrow();
+        let cvar = cvars.get(name).ok_or(ConsoleErrorKind::NoSuchCvar {
+            name: name.to_owned(),
+        })?;
+
+        let val = f(cvar).ok_or(ConsoleErrorKind::CvarTypeMismatch {
             name: name.to_owned(),
             value: cvar.val.clone(),
+            expected,
         })?;
 
         Ok(val)
     }
 
+    /// Returns the value of a `Cvar` as an `f32`.
+    ///
+    /// Unlike `get_value`, this does not reset the `Cvar` to its default if the value isn't a
+    /// number.
+    pub fn get_f32<S>(&self, name: S) -> Result<f32, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        self.get_typed(name, "number", |cvar| cvar.num)
+    }
+
+    /// Returns the value of a `Cvar` as an `i32`.
+    ///
+    /// Fails if the value is not a whole number.
+    pub fn get_i32<S>(&self, name: S) -> Result<i32, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        self.get_typed(name, "integer", |cvar| {
+            cvar.num.filter(|v| v.fract() == 0.0).map(|v| v as i32)
+        })
+    }
+
+    /// Returns the value of a `Cvar` as a `bool`.
+    ///
+    /// Any nonzero number is `true`.
+    pub fn get_bool<S>(&self, name: S) -> Result<bool, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        self.get_typed(name, "boolean", |cvar| cvar.num.map(|v| v != 0.0))
+    }
+
+    /// Returns the value of a `Cvar` as a string.
+    pub fn get_str<S>(&self, name: S) -> Result<String, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        self.get_typed(name, "string", |cvar| Some(cvar.val.clone()))
+    }
+
     pub fn set<S>(&self, name: S, value: S) -> Result<(), ConsoleError>
     where
         S: AsRef<str>,
@@ -282,20 +491,128 @@ impl CvarRegistry {
                 name: name.as_ref().to_owned(),
             })?;
         cvar.val = value.as_ref().to_owned();
+        cvar.num = cvar.val.parse().ok();
         if cvar.notify {
             // TODO: update userinfo/serverinfo
             unimplemented!();
         }
 
+        // release the registry before notifying so observers can read (or set) cvars
+        drop(cvars);
+        let observers: Vec<CvarObserver> = match self.observers.borrow().get(name.as_ref()) {
+            Some(list) => list.iter().map(|(_, obs)| obs.clone()).collect(),
+            None => return Ok(()),
+        };
+
+        for observer in observers {
+            observer(value.as_ref());
+        }
+
         Ok(())
     }
 
+    /// Sets a `Cvar` to a numeric value.
+    pub fn set_f32<S>(&self, name: S, value: f32) -> Result<(), ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        self.set(name.as_ref(), &value.to_string())
+    }
+
+    /// Sets a `Cvar` to 0 if it is nonzero and 1 otherwise, returning the new value.
+    pub fn toggle<S>(&self, name: S) -> Result<bool, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        let new = !self.get_bool(name.as_ref())?;
+        self.set(name.as_ref(), if new { "1" } else { "0" })?;
+        Ok(new)
+    }
+
+    /// Adds `amount` to a numeric `Cvar`, returning the new value.
+    pub fn inc<S>(&self, name: S, amount: f32) -> Result<f32, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        let new = self.get_f32(name.as_ref())? + amount;
+        self.set_f32(name.as_ref(), new)?;
+        Ok(new)
+    }
+
+    /// Sets a `Cvar` to the value following its current one in `values`, returning the new value.
+    ///
+    /// If the current value isn't in `values`, the first value is used. Values are compared
+    /// numerically where possible, so `1` matches `1.0`.
+    pub fn cycle<S>(&self, name: S, values: &[&str]) -> Result<String, ConsoleError>
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        let current = self.get_str(name)?;
+        let current_num = current.parse::<f32>().ok();
+
+        let pos = values
+            .iter()
+            .position(|v| match (current_num, v.parse::<f32>()) {
+                (Some(c), Ok(n)) => c == n,
+                _ => current == *v,
+            });
+
+        let new = match pos {
+            Some(i) => values[(i + 1) % values.len()],
+            None => values[0],
+        };
+
+        self.set(name, new)?;
+        Ok(new.to_owned())
+    }
+
     pub fn contains<S>(&self, name: S) -> bool
     where
         S: AsRef<str>,
     {
         self.cvars.borrow().contains_key(name.as_ref())
     }
+
+    /// Returns a snapshot of every `Cvar` in sorted order.
+    pub fn list(&self) -> Vec<CvarInfo> {
+        let mut list: Vec<CvarInfo> = self
+            .cvars
+            .borrow()
+            .iter()
+            .map(|(name, cvar)| CvarInfo {
+                name: name.to_owned(),
+                value: cvar.val.clone(),
+                default: cvar.default.clone(),
+                archive: cvar.archive,
+                notify: cvar.notify,
+            })
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Returns a script which restores the values of all archived `Cvar`s when executed.
+    pub fn write_archive(&self) -> String {
+        let cvars = self.cvars.borrow();
+        let mut names: Vec<&String> = cvars
+            .iter()
+            .filter(|(_, cvar)| cvar.archive)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+
+        let mut script = String::new();
+        for name in names {
+            script.push_str(&format!(
+                "{} {}\n",
+                name,
+                parse::console::quote(&cvars[name].val)
+            ));
+        }
+
+        script
+    }
 }
 
 /// The line of text currently being edited in the console.
@@ -431,143 +748,811 @@ impl History {
     }
 }
 
-pub struct ConsoleOutput {
-    lines: VecDeque<Vec<char>>,
+/// A character position in the console output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputPosition {
+    /// The index of the line, where 0 is the most recent line.
+    pub line: usize,
+
+    /// The index of the character within the line.
+    pub column: usize,
 }
 
-impl ConsoleOutput {
-    pub fn new() -> ConsoleOutput {
-        ConsoleOutput {
-            lines: VecDeque::new(),
-        }
+impl OutputPosition {
+    /// Returns true if this position comes before `other` in reading order.
+    ///
+    /// Older lines have higher indices, so they are read first.
+    fn precedes(&self, other: &OutputPosition) -> bool {
+        self.line > other.line || (self.line == other.line && self.column < other.column)
     }
+}
 
-    pub fn push(&mut self, chars: Vec<char>) {
-        self.lines.push_front(chars);
-        // TODO: set maximum capacity and pop_back when we reach it
+/// A range of text selected in the console output.
+///
+/// Both ends of the selection are inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputSelection {
+    // the position where the selection was started
+    anchor: OutputPosition,
+
+    // the position the selection has been extended to
+    head: OutputPosition,
+}
+
+impl OutputSelection {
+    /// Returns the start and end of the selection in reading order.
+    pub fn bounds(&self) -> (OutputPosition, OutputPosition) {
+        if self.head.precedes(&self.anchor) {
+            (self.head, self.anchor)
+        } else {
+            (self.anchor, self.head)
+        }
     }
 
-    pub fn lines(&self) -> impl Iterator<Item = &[char]> {
-        self.lines.iter().map(|v| v.as_slice())
+    /// Returns true if the character at `pos` is part of the selection.
+    pub fn contains(&self, pos: OutputPosition) -> bool {
+        let (start, end) = self.bounds();
+        !pos.precedes(&start) && !end.precedes(&pos)
     }
 }
 
-pub struct Console {
-    cmds: Rc<RefCell<CmdRegistry>>,
-    cvars: Rc<RefCell<CvarRegistry>>,
-    aliases: Rc<RefCell<HashMap<String, String>>>,
+/// A destination for console output besides the scrollback buffer.
+pub trait OutputSink {
+    /// Receives a line of output as it is pushed.
+    fn write_line(&mut self, line: &str);
+}
 
-    input: ConsoleInput,
-    hist: History,
-    buffer: RefCell<String>,
-    output: Rc<RefCell<ConsoleOutput>>,
+/// An `OutputSink` which appends lines to a file, prefixed with the local time.
+pub struct LogFileSink {
+    file: File,
 }
 
-impl Console {
-    pub fn new(cmds: Rc<RefCell<CmdRegistry>>, cvars: Rc<RefCell<CvarRegistry>>) -> Console {
-        let output = Rc::new(RefCell::new(ConsoleOutput::new()));
-        let echo_output = output.clone();
-        cmds.borrow_mut()
-            .insert(
-                "echo",
-                Box::new(move |args| {
-                    let msg = match args.len() {
-                        0 => "",
-                        _ => args[0],
-                    };
+impl LogFileSink {
+    /// Opens the file at `path` for appending, creating it if necessary.
+    pub fn append<P>(path: P) -> Result<LogFileSink, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
 
-                    echo_output.borrow_mut().push(msg.chars().collect());
-                }),
-            )
-            .unwrap();
+        Ok(LogFileSink { file })
+    }
+}
 
-        let aliases: Rc<RefCell<HashMap<String, String>>> = Rc::new(RefCell::new(HashMap::new()));
-        let cmd_aliases = aliases.clone();
-        cmds.borrow_mut()
-            .insert(
-                "alias",
-                Box::new(move |args| match args.len() {
-                    0 => {
-                        for (name, script) in cmd_aliases.borrow().iter() {
-                            println!("    {}: {}", name, script);
-                        }
-                        println!("{} alias command(s)", cmd_aliases.borrow().len());
-                    }
+impl OutputSink for LogFileSink {
+    fn write_line(&mut self, line: &str) {
+        let stamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+        if let Err(e) = writeln!(self.file, "[{}] {}", stamp, line) {
+            warn!("Couldn't write to console log: {}", e);
+        }
+    }
+}
 
-                    2 => {
-                        let name = args[0].to_string();
-                        let script = args[1].to_string();
-                        let _ = cmd_aliases.borrow_mut().insert(name, script);
-                    }
+/// The severity of a line of console output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputLevel {
+    Info,
+    Warning,
+    Error,
+}
 
-                    _ => (),
-                }),
-            )
-            .unwrap();
+impl OutputLevel {
+    // text prepended to each line at this level
+    fn prefix(&self) -> &'static str {
+        match *self {
+            OutputLevel::Info => "",
+            OutputLevel::Warning => "Warning: ",
+            OutputLevel::Error => "Error: ",
+        }
+    }
 
-        Console {
-            cmds,
-            cvars,
-            aliases: aliases.clone(),
-            input: ConsoleInput::new(),
-            hist: History::new(),
-            buffer: RefCell::new(String::new()),
-            output: output.clone(),
+    /// Returns how many characters at the start of a line of length `len`
+    /// should be highlighted: the prefix of a warning, or all of an error.
+    pub fn highlight_len(&self, len: usize) -> usize {
+        match *self {
+            OutputLevel::Info => 0,
+            OutputLevel::Warning => self.prefix().len().min(len),
+            OutputLevel::Error => len,
         }
     }
+}
 
-    pub fn send_char(&mut self, c: char) -> Result<(), Error> {
-        match c {
-            // ignore grave and escape keys
-            '`' | '\x1b' => (),
+// a line of console output and the time it was pushed
+struct OutputLine {
+    text: Vec<char>,
+    time: Duration,
+    level: OutputLevel,
 
-            '\r' => {
-                // cap with a newline and push to the execution buffer
-                let mut entered = self.get_string();
-                entered.push('\n');
-                self.buffer.borrow_mut().push_str(&entered);
+    // number of times this line was pushed in a row, shown as an "(xN)" suffix
+    repeats: usize,
 
-                // add the current input to the history
-                self.hist.add_line(self.input.get_text());
+    // length of the text without the suffix
+    base_len: usize,
+}
 
-                // echo the input to console output
-                let mut input_echo: Vec<char> = vec![']'];
-                input_echo.append(&mut self.input.get_text());
-                self.output.borrow_mut().push(input_echo);
+/// The console's scrollback buffer.
+///
+/// Once `capacity` lines are stored, the oldest line is dropped for each new one.
+pub struct ConsoleOutput {
+    lines: VecDeque<OutputLine>,
+    capacity: usize,
 
-                // clear the input line
-                self.input.clear();
-            }
+    // time since the output was created, used to timestamp new lines
+    time: Duration,
 
-            '\x08' => self.input.backspace(),
-            '\x7f' => self.input.delete(),
+    // named destinations which also receive each line
+    sinks: Vec<(String, Box<dyn OutputSink>)>,
 
-            '\t' => warn!("Tab completion not implemented"), // TODO: tab completion
+    // number of lines the view is scrolled back from the most recent line
+    scroll: usize,
 
-            // TODO: we should probably restrict what characters are allowed
-            c => self.input.insert(c),
-        }
+    selection: Option<OutputSelection>,
+}
 
-        Ok(())
+impl ConsoleOutput {
+    pub fn new() -> ConsoleOutput {
+        ConsoleOutput::with_capacity(DEFAULT_SCROLLBACK)
     }
 
-    pub fn cursor(&self) -> usize {
-        self.input.curs
+    /// Constructs a new `ConsoleOutput` which keeps at most `capacity` lines.
+    pub fn with_capacity(capacity: usize) -> ConsoleOutput {
+        ConsoleOutput {
+            lines: VecDeque::new(),
+            capacity: capacity.max(1),
+            time: Duration::zero(),
+            sinks: Vec::new(),
+            scroll: 0,
+            selection: None,
+        }
     }
 
-    pub fn cursor_right(&mut self) {
-        self.input.cursor_right()
+    pub fn push(&mut self, chars: Vec<char>) {
+        self.push_level(chars, OutputLevel::Info);
     }
 
-    pub fn cursor_left(&mut self) {
-        self.input.cursor_left()
-    }
+    /// Pushes a line at the given level.
+    ///
+    /// A non-empty line identical to the most recent one is not stored again;
+    /// instead the most recent line gains an "(xN)" suffix counting the repeats.
+    /// Sinks still receive every line.
+    pub fn push_level(&mut self, chars: Vec<char>, level: OutputLevel) {
+        if !self.sinks.is_empty() {
+            let line: String = chars.iter().collect();
+            for (_, sink) in self.sinks.iter_mut() {
+                sink.write_line(&line);
+            }
+        }
 
-    pub fn history_up(&mut self) {
-        if let Some(line) = self.hist.line_up() {
-            self.input.set_text(&line);
+        if let Some(last) = self.lines.front_mut() {
+            if !chars.is_empty() && last.level == level && last.text[..last.base_len] == chars[..] {
+                last.repeats += 1;
+                last.text.truncate(last.base_len);
+                last.text.extend(format!(" (x{})", last.repeats).chars());
+                last.time = self.time;
+                return;
+            }
         }
-    }
+
+        self.lines.push_front(OutputLine {
+            base_len: chars.len(),
+            text: chars,
+            time: self.time,
+            level,
+            repeats: 1,
+        });
+
+        // keep the selection on the same text as older lines move up
+        if let Some(ref mut sel) = self.selection {
+            sel.anchor.line += 1;
+            sel.head.line += 1;
+        }
+
+        // likewise keep a scrolled-back view on the same text
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+
+        self.truncate();
+    }
+
+    /// Adds a sink which receives each line pushed from now on.
+    ///
+    /// Replaces any existing sink with the same name.
+    pub fn insert_sink<S>(&mut self, name: S, sink: Box<dyn OutputSink>)
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        self.remove_sink(name);
+        self.sinks.push((name.to_owned(), sink));
+    }
+
+    /// Removes the sink with the given name, if there is one.
+    pub fn remove_sink<S>(&mut self, name: S)
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        self.sinks.retain(|(sink_name, _)| sink_name != name);
+    }
+
+    /// Pushes each line of `text`, ignoring trailing newlines.
+    pub fn print<S>(&mut self, text: S)
+    where
+        S: AsRef<str>,
+    {
+        self.print_level(text, OutputLevel::Info);
+    }
+
+    /// Pushes each line of `text` as a warning, prefixed with "Warning: ".
+    pub fn warn<S>(&mut self, text: S)
+    where
+        S: AsRef<str>,
+    {
+        self.print_level(text, OutputLevel::Warning);
+    }
+
+    /// Pushes each line of `text` as an error, prefixed with "Error: ".
+    pub fn error<S>(&mut self, text: S)
+    where
+        S: AsRef<str>,
+    {
+        self.print_level(text, OutputLevel::Error);
+    }
+
+    fn print_level<S>(&mut self, text: S, level: OutputLevel)
+    where
+        S: AsRef<str>,
+    {
+        let text = text.as_ref();
+        for line in text.trim_end_matches('\n').split('\n') {
+            self.push_level(level.prefix().chars().chain(line.chars()).collect(), level);
+        }
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &[char]> {
+        self.lines.iter().map(|l| l.text.as_slice())
+    }
+
+    /// Returns the level of each line, in the same order as `lines`.
+    pub fn levels(&self) -> impl Iterator<Item = OutputLevel> + '_ {
+        self.lines.iter().map(|l| l.level)
+    }
+
+    /// Advances the clock used to timestamp new lines.
+    pub fn update(&mut self, frame_duration: Duration) {
+        self.time = self.time + frame_duration;
+    }
+
+    /// Returns the lines pushed within the last `max_age`, most recent first.
+    pub fn recent(&self, max_age: Duration) -> impl Iterator<Item = &[char]> {
+        let time = self.time;
+        self.lines
+            .iter()
+            .take_while(move |l| time - l.time < max_age)
+            .map(|l| l.text.as_slice())
+    }
+
+    /// Returns the maximum number of lines kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the maximum number of lines kept, dropping the oldest lines if necessary.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.truncate();
+    }
+
+    /// Returns the number of bytes used by the stored lines.
+    pub fn memory_usage(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|l| size_of::<OutputLine>() + l.text.capacity() * size_of::<char>())
+            .sum()
+    }
+
+    // drops lines beyond capacity along with any view or selection on them
+    fn truncate(&mut self) {
+        self.lines.truncate(self.capacity);
+
+        let len = self.lines.len();
+        self.scroll = self.scroll.min(len.saturating_sub(1));
+
+        let dropped = self
+            .selection
+            .map_or(false, |sel| sel.anchor.line >= len || sel.head.line >= len);
+        if dropped {
+            self.selection = None;
+        }
+    }
+
+    /// Returns the number of lines the view is scrolled back from the most recent line.
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Scrolls the view back by `lines`, stopping at the oldest line.
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.lines.len().saturating_sub(1));
+    }
+
+    /// Scrolls the view forward by `lines`, stopping at the most recent line.
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    /// Scrolls the view to the most recent line.
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// Returns the current selection, if any.
+    pub fn selection(&self) -> Option<OutputSelection> {
+        self.selection
+    }
+
+    /// Begins a new selection at `pos`, discarding any previous selection.
+    pub fn select_start(&mut self, pos: OutputPosition) {
+        self.selection = Some(OutputSelection {
+            anchor: pos,
+            head: pos,
+        });
+    }
+
+    /// Extends the current selection to `pos`.
+    ///
+    /// If there is no current selection, no change is made.
+    pub fn select_extend(&mut self, pos: OutputPosition) {
+        if let Some(ref mut sel) = self.selection {
+            sel.head = pos;
+        }
+    }
+
+    /// Discards the current selection.
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// Returns the text covered by the current selection.
+    ///
+    /// Lines are joined with newlines. Returns `None` if nothing is selected.
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection?.bounds();
+
+        let mut text = String::new();
+        for line_id in (end.line..=start.line).rev() {
+            let line = match self.lines.get(line_id) {
+                Some(l) => &l.text,
+                None => continue,
+            };
+
+            let first = if line_id == start.line {
+                start.column.min(line.len())
+            } else {
+                0
+            };
+            let last = if line_id == end.line {
+                (end.column + 1).min(line.len())
+            } else {
+                line.len()
+            };
+
+            if first < last {
+                text.extend(line[first..last].iter());
+            }
+
+            if line_id != end.line {
+                text.push('\n');
+            }
+        }
+
+        Some(text)
+    }
+}
+
+/// Commands which event hooks such as `cl_onconnect` may not execute.
+///
+/// Connecting runs the hooks again, so a hook that connects, directly or through
+/// a script it executes, would never stop.
+const HOOK_CMD_BLACKLIST: &[&str] = &["connect", "reconnect"];
+
+/// Commands which the server may execute.
+///
+/// These are the commands servers routinely stuff. Anything else, including aliases and
+/// `toggle`, `inc` and `cycle`, is refused. With `cl_allowstuffedcmds` set, the server may also
+/// set cvars directly, except for user-only ones.
+const SERVER_CMD_ALLOWLIST: &[&str] = &[
+    "bf",
+    "centerview",
+    "disconnect",
+    "echo",
+    "reconnect",
+    "say",
+    "say_team",
+    "wait",
+];
+
+/// A block of text in the execution buffer.
+struct CmdText {
+    source: CmdSource,
+
+    // number of alias expansions that produced this text since execution last
+    // yielded to `wait`
+    alias_depth: usize,
+
+    // whether this text was produced by an event hook
+    from_hook: bool,
+
+    text: String,
+}
+
+/// The origin of a command in the execution buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CmdSource {
+    /// Typed at the console or triggered by a key binding.
+    User,
+
+    /// Read from a script by `exec`.
+    Config,
+
+    /// Stuffed into the buffer by the server.
+    Server,
+}
+
+impl CmdSource {
+    /// Returns `true` if commands from this source may execute `cmd`.
+    ///
+    /// This is checked for every command as it runs, so commands produced by an alias are
+    /// checked with the privileges of whatever invoked the alias.
+    pub fn allows<S>(&self, cmd: S, cvars: &CvarRegistry) -> bool
+    where
+        S: AsRef<str>,
+    {
+        let cmd = cmd.as_ref();
+        match *self {
+            CmdSource::User => true,
+            CmdSource::Config => true,
+            CmdSource::Server => {
+                if SERVER_CMD_ALLOWLIST.contains(&cmd) {
+                    return true;
+                }
+
+                cvars.contains(cmd)
+                    && !cvars.is_user_only(cmd)
+                    && cvars.get_bool("cl_allowstuffedcmds").unwrap_or(false)
+            }
+        }
+    }
+}
+
+impl fmt::Display for CmdSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CmdSource::User => write!(f, "user"),
+            CmdSource::Config => write!(f, "config"),
+            CmdSource::Server => write!(f, "server"),
+        }
+    }
+}
+
+pub struct Console {
+    cmds: Rc<RefCell<CmdRegistry>>,
+    cvars: Rc<RefCell<CvarRegistry>>,
+    aliases: Rc<RefCell<HashMap<String, String>>>,
+    wait: Rc<Cell<bool>>,
+
+    // whether the command being executed came from an event hook
+    running_hook: Cell<bool>,
+
+    input: ConsoleInput,
+    hist: History,
+    buffer: RefCell<VecDeque<CmdText>>,
+    output: Rc<RefCell<ConsoleOutput>>,
+}
+
+impl Console {
+    pub fn new(cmds: Rc<RefCell<CmdRegistry>>, cvars: Rc<RefCell<CvarRegistry>>) -> Console {
+        let output = Rc::new(RefCell::new(ConsoleOutput::new()));
+        let echo_output = output.clone();
+        cmds.borrow_mut()
+            .insert(
+                "echo",
+                Box::new(move |args| {
+                    let msg = match args.len() {
+                        0 => "",
+                        _ => args[0],
+                    };
+
+                    echo_output.borrow_mut().push(msg.chars().collect());
+                }),
+            )
+            .unwrap();
+
+        let aliases: Rc<RefCell<HashMap<String, String>>> = Rc::new(RefCell::new(HashMap::new()));
+        let cmd_aliases = aliases.clone();
+        let alias_output = output.clone();
+        cmds.borrow_mut()
+            .insert(
+                "alias",
+                Box::new(move |args| match args.len() {
+                    0 => {
+                        let mut output = alias_output.borrow_mut();
+                        let aliases = cmd_aliases.borrow();
+                        let mut names: Vec<&String> = aliases.keys().collect();
+                        names.sort();
+                        for name in names {
+                            output.print(format!("    {}: {}", name, aliases[name]));
+                        }
+                        output.print(l10n::tr_args(
+                            "console.alias_count",
+                            &[&aliases.len().to_string()],
+                        ));
+                    }
+
+                    1 => match cmd_aliases.borrow().get(args[0]) {
+                        Some(script) => alias_output
+                            .borrow_mut()
+                            .print(format!("    {}: {}", args[0], script)),
+                        None => alias_output
+                            .borrow_mut()
+                            .print(l10n::tr_args("console.no_alias", &[args[0]])),
+                    },
+
+                    // a single argument is the script itself
+                    2 => {
+                        let _ = cmd_aliases
+                            .borrow_mut()
+                            .insert(args[0].to_string(), args[1].to_string());
+                    }
+
+                    // like the original engine, extra arguments are joined into the script.
+                    // Arguments that were quoted are quoted again so they survive reparsing.
+                    _ => {
+                        let name = args[0].to_string();
+                        let script = args[1..]
+                            .iter()
+                            .map(|arg| {
+                                if parse::console::needs_quotes(arg) {
+                                    parse::console::quote(arg)
+                                } else {
+                                    arg.to_string()
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        let _ = cmd_aliases.borrow_mut().insert(name, script);
+                    }
+                }),
+            )
+            .unwrap();
+
+        let unalias_aliases = aliases.clone();
+        let unalias_output = output.clone();
+        cmds.borrow_mut()
+            .insert(
+                "unalias",
+                Box::new(move |args| match args.len() {
+                    1 => {
+                        if unalias_aliases.borrow_mut().remove(args[0]).is_none() {
+                            unalias_output
+                                .borrow_mut()
+                                .print(l10n::tr_args("console.no_alias", &[args[0]]));
+                        }
+                    }
+
+                    _ => unalias_output
+                        .borrow_mut()
+                        .print(l10n::tr_args("console.usage", &["unalias <name>"])),
+                }),
+            )
+            .unwrap();
+
+        let toggle_cvars = cvars.clone();
+        let toggle_output = output.clone();
+        cmds.borrow_mut()
+            .insert(
+                "toggle",
+                Box::new(move |args| match args.len() {
+                    1 => {
+                        if let Err(e) = toggle_cvars.borrow().toggle(args[0]) {
+                            toggle_output.borrow_mut().print(format!("toggle: {}", e));
+                        }
+                    }
+
+                    _ => toggle_output
+                        .borrow_mut()
+                        .print(l10n::tr_args("console.usage", &["toggle <cvar>"])),
+                }),
+            )
+            .unwrap();
+
+        let inc_cvars = cvars.clone();
+        let inc_output = output.clone();
+        cmds.borrow_mut()
+            .insert(
+                "inc",
+                Box::new(move |args| {
+                    let amount = match args.len() {
+                        1 => 1.0,
+                        2 => match args[1].parse() {
+                            Ok(a) => a,
+                            Err(_) => {
+                                inc_output.borrow_mut().print(l10n::tr_args(
+                                    "console.not_a_number",
+                                    &["inc", args[1]],
+                                ));
+                                return;
+                            }
+                        },
+
+                        _ => {
+                            inc_output
+                                .borrow_mut()
+                                .print(l10n::tr_args("console.usage", &["inc <cvar> [amount]"]));
+                            return;
+                        }
+                    };
+
+                    if let Err(e) = inc_cvars.borrow().inc(args[0], amount) {
+                        inc_output.borrow_mut().print(format!("inc: {}", e));
+                    }
+                }),
+            )
+            .unwrap();
+
+        let cycle_cvars = cvars.clone();
+        let cycle_output = output.clone();
+        cmds.borrow_mut()
+            .insert(
+                "cycle",
+                Box::new(move |args| match args.len() {
+                    0 | 1 => cycle_output.borrow_mut().print(l10n::tr_args(
+                        "console.usage",
+                        &["cycle <cvar> <value> [value...]"],
+                    )),
+                    _ => {
+                        if let Err(e) = cycle_cvars.borrow().cycle(args[0], &args[1..]) {
+                            cycle_output.borrow_mut().print(format!("cycle: {}", e));
+                        }
+                    }
+                }),
+            )
+            .unwrap();
+
+        // the registry holds this command, so don't keep it alive from inside
+        let cmdlist_cmds = Rc::downgrade(&cmds);
+        cmds.borrow_mut()
+            .insert("cmdlist", cmd_cmdlist(cmdlist_cmds, output.clone()))
+            .unwrap();
+
+        let cvarlist_cvars = cvars.clone();
+        let cvarlist_output = output.clone();
+        cmds.borrow_mut()
+            .insert(
+                "cvarlist",
+                Box::new(move |args| {
+                    let mut output = cvarlist_output.borrow_mut();
+                    let pattern = match args.len() {
+                        0 => "*",
+                        1 => args[0],
+                        _ => {
+                            output.print(l10n::tr_args("console.usage", &["cvarlist [pattern]"]));
+                            return;
+                        }
+                    };
+
+                    let list = cvarlist_cvars.borrow().list();
+                    let mut count = 0;
+                    for cvar in list.iter().filter(|c| glob_match(pattern, &c.name)) {
+                        let flags = format!(
+                            "{}{}",
+                            if cvar.archive { '*' } else { ' ' },
+                            if cvar.notify { 's' } else { ' ' },
+                        );
+                        output.print(l10n::tr_args(
+                            "console.cvarlist_entry",
+                            &[&flags, &cvar.name, &cvar.value, &cvar.default],
+                        ));
+                        count += 1;
+                    }
+                    output.print(l10n::tr_args("console.cvar_count", &[&count.to_string()]));
+                }),
+            )
+            .unwrap();
+
+        cvars
+            .borrow()
+            .register_archive("con_scrollback", &DEFAULT_SCROLLBACK.to_string())
+            .unwrap();
+        let scrollback_output = output.clone();
+        cvars
+            .borrow()
+            .add_observer(
+                "con_scrollback",
+                Rc::new(move |val| match val.parse::<f32>() {
+                    Ok(n) if n >= 1.0 => scrollback_output.borrow_mut().set_capacity(n as usize),
+                    _ => scrollback_output
+                        .borrow_mut()
+                        .warn("con_scrollback must be at least 1"),
+                }),
+            )
+            .unwrap();
+
+        cvars.borrow().register("con_notifytime", "3").unwrap();
+
+        // defer the rest of the buffer to the next frame
+        let wait = Rc::new(Cell::new(false));
+        let cmd_wait = wait.clone();
+        cmds.borrow_mut()
+            .insert("wait", Box::new(move |_| cmd_wait.set(true)))
+            .unwrap();
+
+        Console {
+            cmds,
+            cvars,
+            aliases: aliases.clone(),
+            wait,
+            running_hook: Cell::new(false),
+            input: ConsoleInput::new(),
+            hist: History::new(),
+            buffer: RefCell::new(VecDeque::new()),
+            output: output.clone(),
+        }
+    }
+
+    pub fn send_char(&mut self, c: char) -> Result<(), Error> {
+        match c {
+            // ignore grave and escape keys
+            '`' | '\x1b' => (),
+
+            '\r' => {
+                // cap with a newline and push to the execution buffer
+                let mut entered = self.get_string();
+                entered.push('\n');
+                self.stuff_text(entered);
+
+                // add the current input to the history
+                self.hist.add_line(self.input.get_text());
+
+                // echo the input to console output
+                let mut input_echo: Vec<char> = vec![']'];
+                input_echo.append(&mut self.input.get_text());
+                self.output.borrow_mut().push(input_echo);
+
+                // clear the input line
+                self.input.clear();
+            }
+
+            '\x08' => self.input.backspace(),
+            '\x7f' => self.input.delete(),
+
+            '\t' => warn!("Tab completion not implemented"), // TODO: tab completion
+
+            // TODO: we should probably restrict what characters are allowed
+            c => self.input.insert(c),
+        }
+
+        Ok(())
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.input.curs
+    }
+
+    pub fn cursor_right(&mut self) {
+        self.input.cursor_right()
+    }
+
+    pub fn cursor_left(&mut self) {
+        self.input.cursor_left()
+    }
+
+    pub fn history_up(&mut self) {
+        if let Some(line) = self.hist.line_up() {
+            self.input.set_text(&line);
+        }
+    }
 
     pub fn history_down(&mut self) {
         if let Some(line) = self.hist.line_down() {
@@ -576,55 +1561,150 @@ impl Console {
     }
 
     /// Interprets the contents of the execution buffer.
+    ///
+    /// Commands are removed from the buffer one at a time, so text inserted by a command (e.g.
+    /// `exec` or an alias) runs before the rest of the buffer. If a command issues `wait`,
+    /// execution stops and the remaining text is left for the next call.
     pub fn execute(&self) {
-        let text = self.buffer.borrow().to_owned();
-        self.buffer.borrow_mut().clear();
+        loop {
+            let chunk = match self.buffer.borrow_mut().pop_front() {
+                Some(c) => c,
+                None => return,
+            };
+
+            let (rest, args) = match parse::console::next_command(&chunk.text) {
+                Ok(parsed) => parsed,
+
+                // blank text or a malformed line, skip to the next line
+                Err(_) => {
+                    let (line, rest) = match chunk.text.find('\n') {
+                        Some(i) => chunk.text.split_at(i + 1),
+                        None => (chunk.text.as_str(), ""),
+                    };
+
+                    if !line.trim().is_empty() {
+                        warn!("Couldn't parse command: {:?}", line.trim());
+                    }
 
-        let (_remaining, commands) = parse::commands(text.as_str()).unwrap();
+                    if !rest.is_empty() {
+                        self.push_front_chunk(&chunk, rest);
+                    }
+
+                    continue;
+                }
+            };
 
-        for command in commands.iter() {
-            debug!("{:?}", command);
+            // leave the rest of the text in the buffer so inserted text runs first
+            if !rest.trim().is_empty() {
+                self.push_front_chunk(&chunk, rest);
+            }
+
+            debug!("{:?}", args);
+            let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+            self.running_hook.set(chunk.from_hook);
+            self.execute_command(chunk.source, chunk.alias_depth, &args);
+            self.running_hook.set(false);
+
+            if self.wait.replace(false) {
+                // the recursion limit guards against expansion chains that never
+                // yield, so a chain that waits starts over on the next call
+                for chunk in self.buffer.borrow_mut().iter_mut() {
+                    chunk.alias_depth = 0;
+                }
+
+                return;
+            }
         }
+    }
 
-        for args in commands {
-            if let Some(arg_0) = args.get(0) {
-                let maybe_alias = self.aliases.borrow().get(*arg_0).map(|a| a.to_owned());
-                match maybe_alias {
-                    Some(a) => {
-                        self.stuff_text(a);
-                        self.execute();
-                    }
+    fn push_front_chunk(&self, chunk: &CmdText, text: &str) {
+        self.buffer.borrow_mut().push_front(CmdText {
+            source: chunk.source,
+            alias_depth: chunk.alias_depth,
+            from_hook: chunk.from_hook,
+            text: text.to_owned(),
+        });
+    }
 
-                    None => {
-                        let tail_args: Vec<&str> = args.iter().map(|s| s.as_ref()).skip(1).collect();
-
-                        if self.cmds.borrow().contains(arg_0) {
-                            self.cmds.borrow_mut().exec(arg_0, &tail_args).unwrap();
-                        } else if self.cvars.borrow().contains(arg_0) {
-                            // TODO error handling on cvar set
-                            match args.get(1) {
-                                Some(arg_1) => self.cvars.borrow_mut().set(arg_0, arg_1).unwrap(),
-                                None => {
-                                    let msg = format!(
-                                        "\"{}\" is \"{}\"",
-                                        arg_0,
-                                        self.cvars.borrow().get(arg_0).unwrap()
-                                    );
-                                    self.output
-                                        .borrow_mut()
-                                        .push(msg.as_str().chars().collect());
-                                }
-                            }
-                        } else {
-                            // TODO: try sending to server first
-                            self.output.borrow_mut().push(
-                                format!("Unrecognized command \"{}\"", arg_0)
-                                    .as_str()
-                                    .chars()
-                                    .collect(),
+    fn execute_command(&self, source: CmdSource, alias_depth: usize, args: &[&str]) {
+        let arg_0 = match args.get(0) {
+            Some(a) => *a,
+            None => return,
+        };
+
+        if self.cvars.borrow().get_value("cl_cmdlog").unwrap_or(0.0) != 0.0 {
+            self.output
+                .borrow_mut()
+                .print(format!("[{}] {}", source, args.join(" ")));
+        }
+
+        if !source.allows(arg_0, &self.cvars.borrow()) {
+            self.output.borrow_mut().push(
+                l10n::tr_args("console.restricted_command", &[arg_0, &source.to_string()])
+                    .chars()
+                    .collect(),
+            );
+            return;
+        }
+
+        if self.running_hook.get() && HOOK_CMD_BLACKLIST.contains(&arg_0) {
+            self.output.borrow_mut().push(
+                l10n::tr_args("console.hook_command", &[arg_0])
+                    .chars()
+                    .collect(),
+            );
+            return;
+        }
+
+        let maybe_alias = self.aliases.borrow().get(arg_0).map(|a| a.to_owned());
+        match maybe_alias {
+            Some(a) => {
+                if alias_depth >= MAX_ALIAS_DEPTH {
+                    self.output.borrow_mut().push(
+                        l10n::tr_args("console.alias_recursion", &[arg_0])
+                            .chars()
+                            .collect(),
+                    );
+                    return;
+                }
+
+                // aliases run with the privileges of whatever invoked them
+                let mut text = a;
+                text.push('\n');
+                self.buffer.borrow_mut().push_front(CmdText {
+                    source,
+                    alias_depth: alias_depth + 1,
+                    from_hook: self.running_hook.get(),
+                    text,
+                });
+            }
+
+            None => {
+                let tail_args = &args[1..];
+
+                if self.cmds.borrow().contains(arg_0) {
+                    self.cmds.borrow().exec(arg_0, tail_args).unwrap();
+                } else if self.cvars.borrow().contains(arg_0) {
+                    // TODO error handling on cvar set
+                    match args.get(1) {
+                        Some(arg_1) => self.cvars.borrow().set(arg_0, arg_1).unwrap(),
+                        None => {
+                            let msg = l10n::tr_args(
+                                "console.cvar_value",
+                                &[arg_0, &self.cvars.borrow().get(arg_0).unwrap()],
                             );
+                            self.output
+                                .borrow_mut()
+                                .push(msg.as_str().chars().collect());
                         }
                     }
+                } else {
+                    // TODO: try sending to server first
+                    self.output.borrow_mut().push(
+                        l10n::tr_args("console.unrecognized_command", &[arg_0])
+                            .chars()
+                            .collect(),
+                    );
                 }
             }
         }
@@ -642,18 +1722,736 @@ impl Console {
         )
     }
 
+    /// Inserts text at the front of the execution buffer.
+    ///
+    /// The text will be executed before anything already in the buffer. Text
+    /// inserted by a command run from an event hook is treated as part of the hook.
+    pub fn insert_text<S>(&self, source: CmdSource, text: S)
+    where
+        S: AsRef<str>,
+    {
+        debug!("insert_text ({}):\n{:?}", source, text.as_ref());
+        let mut text = text.as_ref().to_owned();
+
+        // in case the last line doesn't end with a newline
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+
+        self.buffer.borrow_mut().push_front(CmdText {
+            source,
+            alias_depth: 0,
+            from_hook: self.running_hook.get(),
+            text,
+        });
+    }
+
+    /// Appends text typed by the user to the execution buffer.
     pub fn stuff_text<S>(&self, text: S)
     where
         S: AsRef<str>,
     {
-        debug!("stuff_text:\n{:?}", text.as_ref());
-        self.buffer.borrow_mut().push_str(text.as_ref());
+        self.stuff_text_from(CmdSource::User, text);
+    }
+
+    /// Appends text from the given source to the execution buffer.
+    pub fn stuff_text_from<S>(&self, source: CmdSource, text: S)
+    where
+        S: AsRef<str>,
+    {
+        debug!("stuff_text ({}):\n{:?}", source, text.as_ref());
+        let mut text = text.as_ref().to_owned();
 
         // in case the last line doesn't end with a newline
-        self.buffer.borrow_mut().push_str("\n");
+        text.push('\n');
+
+        self.buffer.borrow_mut().push_back(CmdText {
+            source,
+            alias_depth: 0,
+            from_hook: self.running_hook.get(),
+            text,
+        });
+    }
+
+    /// Appends the script of an event hook, such as `cl_onconnect`, to the execution buffer.
+    ///
+    /// The script runs as a config, except that neither it nor anything it executes may
+    /// `connect` or `reconnect`.
+    pub fn stuff_hook_text<S>(&self, text: S)
+    where
+        S: AsRef<str>,
+    {
+        debug!("stuff_hook_text:\n{:?}", text.as_ref());
+        let mut text = text.as_ref().to_owned();
+        text.push('\n');
+
+        self.buffer.borrow_mut().push_back(CmdText {
+            source: CmdSource::Config,
+            alias_depth: 0,
+            from_hook: true,
+            text,
+        });
     }
 
     pub fn output(&self) -> Ref<ConsoleOutput> {
         self.output.borrow()
     }
+
+    pub fn output_mut(&self) -> RefMut<ConsoleOutput> {
+        self.output.borrow_mut()
+    }
+
+    /// Returns a shared handle to the output, for commands that print to the console.
+    ///
+    /// Commands are owned by the command registry, so they hold the output
+    /// rather than the console itself.
+    pub fn output_handle(&self) -> Rc<RefCell<ConsoleOutput>> {
+        self.output.clone()
+    }
+}
+
+/// Implements the `cmdlist` command.
+fn cmd_cmdlist(
+    cmds: Weak<RefCell<CmdRegistry>>,
+    output: Rc<RefCell<ConsoleOutput>>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        let mut output = output.borrow_mut();
+        let pattern = match args.len() {
+            0 => "*",
+            1 => args[0],
+            _ => {
+                output.print(l10n::tr_args("console.usage", &["cmdlist [pattern]"]));
+                return;
+            }
+        };
+
+        let cmds = match cmds.upgrade() {
+            Some(c) => c,
+            None => return,
+        };
+
+        let cmds = cmds.borrow();
+        let mut count = 0;
+        for name in cmds.names().into_iter().filter(|n| glob_match(pattern, n)) {
+            output.print(format!("    {}", name));
+            count += 1;
+        }
+        output.print(l10n::tr_args("console.cmd_count", &[&count.to_string()]));
+    })
+}
+
+/// Implements the `exec` command.
+///
+/// The named script is read from the virtual filesystem and inserted at the
+/// front of the console's execution buffer.
+pub fn cmd_exec(vfs: Rc<Vfs>, console: Weak<RefCell<Console>>) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| {
+        let console = match console.upgrade() {
+            Some(c) => c,
+            None => return,
+        };
+        let console = console.borrow();
+
+        match args.len() {
+            1 => {
+                let mut script_file = match vfs.open(args[0]) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        console
+                            .output_mut()
+                            .warn(format!("Couldn't exec {}: {}", args[0], e));
+                        return;
+                    }
+                };
+
+                let mut script = Vec::new();
+                if let Err(e) = script_file.read_to_end(&mut script) {
+                    console
+                        .output_mut()
+                        .warn(format!("Couldn't read {}: {}", args[0], e));
+                    return;
+                }
+
+                console.output_mut().print(format!("execing {}", args[0]));
+                console.insert_text(CmdSource::Config, String::from_utf8_lossy(&script));
+            }
+
+            _ => console
+                .output_mut()
+                .print("exec <filename>: execute a script file"),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn output_with_lines(lines: &[&str]) -> ConsoleOutput {
+        let mut output = ConsoleOutput::new();
+        for line in lines {
+            output.push(line.chars().collect());
+        }
+        output
+    }
+
+    #[test]
+    fn test_selected_text_single_line() {
+        let mut output = output_with_lines(&["hello world"]);
+        output.select_start(OutputPosition { line: 0, column: 6 });
+        output.select_extend(OutputPosition {
+            line: 0,
+            column: 10,
+        });
+        assert_eq!(output.selected_text().unwrap(), "world");
+    }
+
+    #[test]
+    fn test_selected_text_multi_line_reversed() {
+        let mut output = output_with_lines(&["first", "second", "third"]);
+
+        // drag from the newest line back up to the oldest
+        output.select_start(OutputPosition { line: 0, column: 2 });
+        output.select_extend(OutputPosition { line: 2, column: 3 });
+        assert_eq!(output.selected_text().unwrap(), "st\nsecond\nthi");
+    }
+
+    #[test]
+    fn test_selection_follows_pushed_lines() {
+        let mut output = output_with_lines(&["selected"]);
+        output.select_start(OutputPosition { line: 0, column: 0 });
+        output.select_extend(OutputPosition { line: 0, column: 7 });
+        output.push("newer".chars().collect());
+        assert_eq!(output.selected_text().unwrap(), "selected");
+    }
+
+    #[test]
+    fn test_alias_recursion_limit() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Console::new(cmds.clone(), cvars);
+
+        let count = Rc::new(Cell::new(0));
+        let cmd_count = count.clone();
+        cmds.borrow_mut()
+            .insert(
+                "count",
+                Box::new(move |_| cmd_count.set(cmd_count.get() + 1)),
+            )
+            .unwrap();
+
+        console.stuff_text("alias loop \"count; loop\"\nloop");
+        console.execute();
+
+        assert_eq!(count.get(), MAX_ALIAS_DEPTH);
+    }
+
+    #[test]
+    fn test_alias_wait_loop() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Console::new(cmds.clone(), cvars);
+
+        let count = Rc::new(Cell::new(0));
+        let cmd_count = count.clone();
+        cmds.borrow_mut()
+            .insert(
+                "count",
+                Box::new(move |_| cmd_count.set(cmd_count.get() + 1)),
+            )
+            .unwrap();
+
+        // a loop that waits each iteration may run for longer than the recursion limit
+        console.stuff_text("alias loop \"count; wait; loop\"\nloop");
+        for _ in 0..MAX_ALIAS_DEPTH * 2 {
+            console.execute();
+        }
+
+        assert_eq!(count.get(), MAX_ALIAS_DEPTH * 2);
+    }
+
+    #[test]
+    fn test_wait_defers_to_next_execute() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Console::new(cmds.clone(), cvars);
+
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let cmd_order = order.clone();
+        cmds.borrow_mut()
+            .insert(
+                "mark",
+                Box::new(move |args| cmd_order.borrow_mut().push(args[0].to_owned())),
+            )
+            .unwrap();
+
+        console.stuff_text("alias jump \"mark +jump; wait; mark -jump\"");
+        console.stuff_text("jump; mark after");
+        console.execute();
+        assert_eq!(*order.borrow(), vec!["+jump"]);
+
+        console.execute();
+        assert_eq!(*order.borrow(), vec!["+jump", "-jump", "after"]);
+    }
+
+    #[test]
+    fn test_alias_extra_args_quoted() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Console::new(cmds.clone(), cvars);
+
+        let said = Rc::new(RefCell::new(Vec::new()));
+        let cmd_said = said.clone();
+        cmds.borrow_mut()
+            .insert(
+                "say",
+                Box::new(move |args| {
+                    cmd_said
+                        .borrow_mut()
+                        .push(args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+                }),
+            )
+            .unwrap();
+
+        console.stuff_text(r#"alias greet say "hello there" "a;b" "" plain"#);
+        console.stuff_text("greet");
+        console.execute();
+
+        assert_eq!(
+            *said.borrow(),
+            vec![vec!["hello there", "a;b", "", "plain"]]
+        );
+    }
+
+    #[test]
+    fn test_unalias() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Console::new(cmds, cvars);
+
+        console.stuff_text("alias foo \"echo foo\"\nunalias foo");
+        console.execute();
+
+        assert!(console.aliases.borrow().get("foo").is_none());
+    }
+
+    #[test]
+    fn test_cvar_typed_accessors() {
+        let cvars = CvarRegistry::new();
+        cvars.register("host_maxfps", "72").unwrap();
+        cvars.register("sensitivity", "3.5").unwrap();
+        cvars.register("hostname", "UNNAMED").unwrap();
+
+        assert_eq!(cvars.get_i32("host_maxfps").unwrap(), 72);
+        assert_eq!(cvars.get_bool("host_maxfps").unwrap(), true);
+        assert_eq!(cvars.get_f32("sensitivity").unwrap(), 3.5);
+        assert_eq!(cvars.get_str("hostname").unwrap(), "UNNAMED");
+
+        // cached value follows assignment
+        cvars.set("host_maxfps", "0").unwrap();
+        assert_eq!(cvars.get_bool("host_maxfps").unwrap(), false);
+
+        let mismatch = |err: ConsoleError| match err.kind() {
+            ConsoleErrorKind::CvarTypeMismatch { expected, .. } => expected,
+            k => panic!("unexpected error: {}", k),
+        };
+        assert_eq!(
+            mismatch(cvars.get_i32("sensitivity").unwrap_err()),
+            "integer"
+        );
+        assert_eq!(mismatch(cvars.get_f32("hostname").unwrap_err()), "number");
+
+        // typed accessors don't reset invalid values
+        cvars.set("sensitivity", "fast").unwrap();
+        assert!(cvars.get_f32("sensitivity").is_err());
+        assert_eq!(cvars.get_str("sensitivity").unwrap(), "fast");
+        assert_eq!(cvars.get_value("sensitivity").unwrap(), 3.5);
+    }
+
+    #[test]
+    fn test_cvar_toggle_inc_cycle() {
+        let cvars = CvarRegistry::new();
+        cvars.register("r_drawviewmodel", "1").unwrap();
+        cvars.register("volume", "0.5").unwrap();
+        cvars.register("crosshair", "0").unwrap();
+        cvars.register("hostname", "UNNAMED").unwrap();
+
+        assert_eq!(cvars.toggle("r_drawviewmodel").unwrap(), false);
+        assert_eq!(cvars.toggle("r_drawviewmodel").unwrap(), true);
+        assert!(cvars.toggle("hostname").is_err());
+
+        assert_eq!(cvars.inc("volume", 0.25).unwrap(), 0.75);
+        assert_eq!(cvars.inc("volume", -0.75).unwrap(), 0.0);
+        assert_eq!(cvars.get_str("volume").unwrap(), "0");
+
+        assert_eq!(cvars.cycle("crosshair", &["0", "1", "2"]).unwrap(), "1");
+        assert_eq!(cvars.cycle("crosshair", &["0", "1", "2"]).unwrap(), "2");
+        assert_eq!(cvars.cycle("crosshair", &["0", "1", "2"]).unwrap(), "0");
+
+        // unlisted values start from the beginning
+        assert_eq!(cvars.cycle("hostname", &["a", "b"]).unwrap(), "a");
+        assert_eq!(cvars.cycle("hostname", &["a", "b"]).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "r_lerpmodels"));
+        assert!(glob_match("r_*", "r_lerpmodels"));
+        assert!(glob_match("R_*", "r_lerpmodels"));
+        assert!(!glob_match("r_*", "cl_bob"));
+        assert!(glob_match("*speed", "cl_forwardspeed"));
+        assert!(glob_match("cl_*speed*", "cl_anglespeedkey"));
+        assert!(glob_match("v_i?aw_*", "v_iyaw_cycle"));
+        assert!(!glob_match("v_i?aw", "v_iyaw_cycle"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "fov"));
+    }
+
+    #[test]
+    fn test_cvar_list() {
+        let cvars = CvarRegistry::new();
+        cvars.register("r_novis", "0").unwrap();
+        cvars.register_archive("sensitivity", "3").unwrap();
+        cvars.set("sensitivity", "5").unwrap();
+
+        assert_eq!(
+            cvars.list(),
+            vec![
+                CvarInfo {
+                    name: "r_novis".to_owned(),
+                    value: "0".to_owned(),
+                    default: "0".to_owned(),
+                    archive: false,
+                    notify: false,
+                },
+                CvarInfo {
+                    name: "sensitivity".to_owned(),
+                    value: "5".to_owned(),
+                    default: "3".to_owned(),
+                    archive: true,
+                    notify: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_capacity() {
+        let mut output = ConsoleOutput::with_capacity(3);
+        for line in &["a", "b", "c", "d"] {
+            output.push(line.chars().collect());
+        }
+
+        let lines: Vec<String> = output.lines().map(|l| l.iter().collect()).collect();
+        assert_eq!(lines, vec!["d", "c", "b"]);
+
+        output.select_start(OutputPosition { line: 2, column: 0 });
+        output.set_capacity(2);
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.selection().is_none());
+    }
+
+    #[test]
+    fn test_output_scroll() {
+        let mut output = output_with_lines(&["a", "b", "c", "d"]);
+
+        output.scroll_up(2);
+        assert_eq!(output.scroll(), 2);
+
+        // clamped to the oldest line
+        output.scroll_up(10);
+        assert_eq!(output.scroll(), 3);
+
+        // new output doesn't move a scrolled-back view
+        output.scroll_down(2);
+        output.push("e".chars().collect());
+        assert_eq!(output.scroll(), 2);
+
+        output.scroll_down(10);
+        assert_eq!(output.scroll(), 0);
+        output.push("f".chars().collect());
+        assert_eq!(output.scroll(), 0);
+    }
+
+    struct VecSink(Rc<RefCell<Vec<String>>>);
+
+    impl OutputSink for VecSink {
+        fn write_line(&mut self, line: &str) {
+            self.0.borrow_mut().push(line.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_output_sinks() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let mut output = ConsoleOutput::new();
+        output.print("before\n");
+
+        output.insert_sink("test", Box::new(VecSink(received.clone())));
+        output.print("one\ntwo\n");
+        output.remove_sink("test");
+        output.print("after\n");
+
+        assert_eq!(*received.borrow(), vec!["one", "two"]);
+        assert_eq!(output.lines().count(), 4);
+    }
+
+    #[test]
+    fn test_output_repeats() {
+        let mut output = ConsoleOutput::new();
+        output.error("missing texture\nmissing texture\n");
+        output.error("missing texture");
+        output.print("missing texture");
+        output.print("\n");
+        output.print("\n");
+
+        let lines: Vec<String> = output.lines().map(|l| l.iter().collect()).collect();
+        assert_eq!(
+            lines,
+            vec!["", "", "missing texture", "Error: missing texture (x3)"]
+        );
+    }
+
+    #[test]
+    fn test_output_levels() {
+        let mut output = ConsoleOutput::new();
+        output.warn("low memory");
+        output.error("out of memory");
+
+        let levels: Vec<OutputLevel> = output.levels().collect();
+        assert_eq!(levels, vec![OutputLevel::Error, OutputLevel::Warning]);
+        assert_eq!(OutputLevel::Warning.highlight_len(20), "Warning: ".len());
+        assert_eq!(OutputLevel::Error.highlight_len(20), 20);
+        assert_eq!(OutputLevel::Info.highlight_len(20), 0);
+    }
+
+    #[test]
+    fn test_output_recent() {
+        let mut output = ConsoleOutput::new();
+        output.print("old\n");
+        output.update(Duration::seconds(2));
+        output.print("new\nnewer\n");
+        output.update(Duration::seconds(1));
+
+        let recent: Vec<String> = output
+            .recent(Duration::seconds(2))
+            .map(|l| l.iter().collect())
+            .collect();
+        assert_eq!(recent, vec!["newer", "new"]);
+        assert_eq!(output.recent(Duration::seconds(4)).count(), 3);
+    }
+
+    #[test]
+    fn test_cvar_observers() {
+        let cvars = CvarRegistry::new();
+        cvars.register("gamma", "1").unwrap();
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let obs_seen = seen.clone();
+        let id = cvars
+            .add_observer(
+                "gamma",
+                Rc::new(move |val| obs_seen.borrow_mut().push(val.to_owned())),
+            )
+            .unwrap();
+
+        cvars.set("gamma", "0.8").unwrap();
+        cvars.set("gamma", "0.8").unwrap();
+        assert_eq!(*seen.borrow(), vec!["0.8", "0.8"]);
+
+        assert!(cvars.remove_observer("gamma", id));
+        cvars.set("gamma", "1.2").unwrap();
+        assert_eq!(seen.borrow().len(), 2);
+
+        assert!(cvars.add_observer("nonexistent", Rc::new(|_| ())).is_err());
+    }
+
+    #[test]
+    fn test_write_archive() {
+        let cvars = CvarRegistry::new();
+        cvars.register_archive("sensitivity", "3").unwrap();
+        cvars.register_archive("_cl_name", "player").unwrap();
+        cvars.register("r_drawviewmodel", "1").unwrap();
+        cvars.set("sensitivity", "5.5").unwrap();
+
+        assert_eq!(
+            cvars.write_archive(),
+            "_cl_name \"player\"\nsensitivity \"5.5\"\n"
+        );
+    }
+
+    #[test]
+    fn test_write_archive_round_trip() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        cvars
+            .borrow()
+            .register_archive("_cl_name", "player")
+            .unwrap();
+        cvars.borrow().register_archive("cl_onconnect", "").unwrap();
+        let console = Console::new(cmds, cvars.clone());
+
+        let name = r#"the "best" \o/"#;
+        cvars.borrow().set("_cl_name", name).unwrap();
+        let script = cvars.borrow().write_archive();
+
+        cvars.borrow().set("_cl_name", "player").unwrap();
+        cvars.borrow().set("cl_onconnect", "echo hi").unwrap();
+        console.stuff_text(script);
+        console.execute();
+
+        assert_eq!(cvars.borrow().get("_cl_name").unwrap(), name);
+        assert_eq!(cvars.borrow().get("cl_onconnect").unwrap(), "");
+    }
+
+    #[test]
+    fn test_cmd_source_restrictions() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Console::new(cmds.clone(), cvars);
+
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        for name in &["connect", "exec", "bf"] {
+            let cmd_ran = ran.clone();
+            let cmd_name = name.to_string();
+            cmds.borrow_mut()
+                .insert(
+                    *name,
+                    Box::new(move |_| cmd_ran.borrow_mut().push(cmd_name.clone())),
+                )
+                .unwrap();
+        }
+
+        console.stuff_text_from(CmdSource::Server, "connect localhost");
+        console.stuff_text_from(CmdSource::Server, "exec server.cfg");
+        console.execute();
+        assert!(ran.borrow().is_empty());
+
+        // configs such as autoexec.cfg may connect on purpose
+        console.stuff_text_from(CmdSource::Server, "bf");
+        console.stuff_text_from(CmdSource::Config, "connect localhost");
+        console.stuff_text_from(CmdSource::Config, "exec autoexec.cfg");
+        console.execute();
+        assert_eq!(*ran.borrow(), vec!["bf", "connect", "exec"]);
+    }
+
+    #[test]
+    fn test_hook_reentrancy() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Rc::new(RefCell::new(Console::new(cmds.clone(), cvars)));
+
+        let connects = Rc::new(Cell::new(0));
+        let c = connects.clone();
+        cmds.borrow_mut()
+            .insert("connect", Box::new(move |_| c.set(c.get() + 1)))
+            .unwrap();
+
+        // stands in for exec, which inserts a script from the filesystem
+        let exec_console = console.clone();
+        cmds.borrow_mut()
+            .insert(
+                "exec",
+                Box::new(move |_| {
+                    exec_console
+                        .borrow()
+                        .insert_text(CmdSource::Config, "connect localhost")
+                }),
+            )
+            .unwrap();
+
+        let console = console.borrow();
+        console.stuff_text("alias go \"connect localhost\"");
+        console.execute();
+
+        // hooks can't connect, whether directly, through an alias or through a script
+        console.stuff_hook_text("connect localhost; go; exec server.cfg");
+        console.execute();
+        assert_eq!(connects.get(), 0);
+
+        // the restriction ends with the hook
+        console.stuff_text_from(CmdSource::Config, "go");
+        console.stuff_text_from(CmdSource::Config, "exec server.cfg");
+        console.execute();
+        assert_eq!(connects.get(), 2);
+    }
+
+    #[test]
+    fn test_server_cvar_policy() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        {
+            let cvars = cvars.borrow();
+            cvars.register("cl_allowstuffedcmds", "0").unwrap();
+            cvars.set_user_only("cl_allowstuffedcmds").unwrap();
+            cvars.register("fov", "90").unwrap();
+            cvars.register("rcon_password", "").unwrap();
+            cvars.set_user_only("rcon_password").unwrap();
+        }
+        let console = Console::new(cmds, cvars.clone());
+
+        // nothing but the allowlist by default, including cvars set through toggle
+        console.stuff_text_from(
+            CmdSource::Server,
+            "fov 10; toggle cl_allowstuffedcmds; alias x \"fov 20\"",
+        );
+        console.execute();
+        assert_eq!(cvars.borrow().get("fov").unwrap(), "90");
+        assert!(!cvars.borrow().get_bool("cl_allowstuffedcmds").unwrap());
+
+        // with the policy set, the server may set cvars but not user-only ones
+        cvars.borrow().set("cl_allowstuffedcmds", "1").unwrap();
+        console.stuff_text_from(
+            CmdSource::Server,
+            "fov 10; rcon_password stolen; cl_allowstuffedcmds 0",
+        );
+        console.execute();
+        assert_eq!(cvars.borrow().get("fov").unwrap(), "10");
+        assert_eq!(cvars.borrow().get("rcon_password").unwrap(), "");
+        assert!(cvars.borrow().get_bool("cl_allowstuffedcmds").unwrap());
+    }
+
+    #[test]
+    fn test_insert_text_runs_first() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Console::new(cmds.clone(), cvars);
+
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let cmd_order = order.clone();
+        cmds.borrow_mut()
+            .insert(
+                "mark",
+                Box::new(move |args| cmd_order.borrow_mut().push(args[0].to_owned())),
+            )
+            .unwrap();
+
+        console.stuff_text("mark second");
+        console.insert_text(CmdSource::Config, "mark first");
+        console.execute();
+
+        assert_eq!(*order.borrow(), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_exec_doesnt_keep_console_alive() {
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new()));
+        let console = Rc::new(RefCell::new(Console::new(cmds.clone(), cvars)));
+
+        cmds.borrow_mut()
+            .insert(
+                "exec",
+                cmd_exec(Rc::new(Vfs::new()), Rc::downgrade(&console)),
+            )
+            .unwrap();
+
+        let weak = Rc::downgrade(&console);
+        drop(console);
+        assert!(weak.upgrade().is_none());
+
+        // the command outlives the console without panicking
+        cmds.borrow().exec("exec", &["autoexec.cfg"]).unwrap();
+    }
 }
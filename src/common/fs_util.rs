@@ -0,0 +1,159 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Crash-safe file writes.
+//!
+//! Files written through this module are first written to a temporary file
+//! next to the destination, flushed to disk, and then renamed over the
+//! destination. A crash partway through leaves the old file intact rather
+//! than a truncated one.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// A file whose contents replace the destination only when committed.
+///
+/// If an `AtomicFile` is dropped without calling `commit`, the temporary file
+/// is removed and the destination is left untouched.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    writer: BufWriter<File>,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Begins writing a new version of the file at `path`.
+    pub fn create<P>(path: P) -> io::Result<AtomicFile>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_owned();
+        let temp_path = temp_path(&path);
+        let writer = BufWriter::new(File::create(&temp_path)?);
+
+        Ok(AtomicFile {
+            path,
+            temp_path,
+            writer,
+            committed: false,
+        })
+    }
+
+    /// Returns the path of the destination file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes the written data to disk and moves it into place.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            // nothing useful to do if this fails
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Atomically replaces the contents of the file at `path` with `contents`.
+pub fn write_atomic<P, C>(path: P, contents: C) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    C: AsRef<[u8]>,
+{
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    file.commit()
+}
+
+// the temporary file lives in the same directory so the rename can't cross filesystems
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("richter-fs-util-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_contents() {
+        let dir = test_dir("replace");
+        let path = dir.join("config.cfg");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, "new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!temp_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_uncommitted_file_leaves_destination() {
+        let dir = test_dir("uncommitted");
+        let path = dir.join("config.cfg");
+        fs::write(&path, "old").unwrap();
+
+        {
+            let mut file = AtomicFile::create(&path).unwrap();
+            file.write_all(b"partial").unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(!temp_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -37,6 +37,10 @@ pub trait Program: Sized {
     );
 
     fn frame(&mut self, frame_duration: Duration);
+
+    /// Returns true if the program should exit after the current frame.
+    fn quit_requested(&self) -> bool;
+
     fn shutdown(&mut self);
     fn cvars(&self) -> Ref<CvarRegistry>;
     fn cvars_mut(&self) -> RefMut<CvarRegistry>;
@@ -87,12 +91,18 @@ where
                 *control_flow = ControlFlow::Exit;
             }
 
-            Event::MainEventsCleared => self.frame(),
+            Event::MainEventsCleared => {
+                self.frame();
+
+                if self.program.quit_requested() {
+                    self.program.shutdown();
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+
             Event::Suspended | Event::Resumed => unimplemented!(),
             Event::LoopDestroyed => {
-                // TODO:
-                // - host_writeconfig
-                // - others...
+                // TODO: other cleanup
             }
 
             e => self.program.handle_event(e, _target, control_flow),
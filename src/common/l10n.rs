@@ -0,0 +1,362 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Localized string tables.
+//!
+//! User-facing text is looked up by message ID in a string table loaded from
+//! `strings/<lang>.toml` in the virtual filesystem. Nested TOML tables are
+//! flattened into dotted IDs, so
+//!
+//! ```toml
+//! [menu.main]
+//! quit = "Quit"
+//! ```
+//!
+//! defines the message `menu.main.quit`. Any message missing from the loaded
+//! language falls back to the built-in English text.
+
+use std::{collections::HashMap, io::Read, sync::RwLock};
+
+use crate::common::vfs::Vfs;
+
+use failure::{Error, ResultExt};
+
+/// Built-in English text, used when a message is missing from the loaded table.
+const ENGLISH: &[(&str, &str)] = &[
+    ("bind.binding", "\"{0}\" = \"{1}\""),
+    ("bind.not_bound", "\"{0}\" is not bound"),
+    ("bind.invalid_key", "\"{0}\" isn't a valid key"),
+    (
+        "bind.bind_help",
+        "bind [key] (command): attach a command to a key",
+    ),
+    (
+        "bind.cbind_help",
+        "cbind [game|spectator|demo] [key] (command): attach a command to a key in a specific context",
+    ),
+    (
+        "bind.unbind_help",
+        "unbind [key]: remove the binding for a key",
+    ),
+    (
+        "bookmark.demo_only",
+        "Camera bookmarks are only available during demo playback",
+    ),
+    ("bookmark.entry", "{0}: ({1}) pitch {2} yaw {3}"),
+    ("bookmark.invalid_duration", "Invalid duration \"{0}\""),
+    ("bookmark.no_camera", "No camera to save"),
+    ("bookmark.not_found", "No camera bookmark named \"{0}\""),
+    ("client.not_connected", "Can't \"{0}\", not connected"),
+    ("connect.failed", "Couldn't connect: {0}"),
+    ("connect.lost", "Connection lost: {0}"),
+    ("connect.reconnect_failed", "reconnect: {0}"),
+    (
+        "console.alias_recursion",
+        "Alias recursion limit exceeded at \"{0}\"",
+    ),
+    ("console.alias_count", "{0} alias command(s)"),
+    ("console.cmd_count", "{0} command(s)"),
+    ("console.cvar_count", "{0} cvar(s)"),
+    ("console.cvar_value", "\"{0}\" is \"{1}\""),
+    ("console.cvarlist_entry", "{0} {1} \"{2}\" (default \"{3}\")"),
+    ("console.no_alias", "No alias named \"{0}\""),
+    ("console.not_a_number", "{0}: \"{1}\" is not a number"),
+    ("console.usage", "usage: {0}"),
+    (
+        "console.hook_command",
+        "\"{0}\" can't be executed from event hooks",
+    ),
+    (
+        "console.restricted_command",
+        "\"{0}\" can't be executed from {1} commands",
+    ),
+    (
+        "console.unrecognized_command",
+        "Unrecognized command \"{0}\"",
+    ),
+    ("hud.identify", "#{0} frame {1} skin {2} effects {3}"),
+    ("hud.loading.entities", "Receiving entities"),
+    ("hud.loading.loading", "Loading"),
+    ("hud.loading.player_info", "Sending player info"),
+    ("hud.loading.resources", "Loading resources {0}/{1}"),
+    ("hud.loading.server_info", "Waiting for server info"),
+    ("hud.loading.spawning", "Spawning"),
+    ("lightdump.failed", "Couldn't write light levels: {0}"),
+    ("lightdump.no_map", "r_lightdump: no map loaded"),
+    ("lightdump.not_brush", "r_lightdump: world model is not a brush model"),
+    ("lightdump.wrote", "Wrote light levels to {0}"),
+    ("menu.main.single_player", "Single Player"),
+    ("menu.main.multiplayer", "Multiplayer"),
+    ("menu.main.options", "Options"),
+    ("menu.main.help", "Help/Ordering"),
+    ("menu.main.quit", "Quit"),
+    ("menu.sp.new_game", "New Game"),
+    (
+        "menu.sp.no_server",
+        "Single player games need a local server, which isn't supported yet. Use `connect <address>` to join a server instead.",
+    ),
+    ("menu.mp.join_game", "Join a Game"),
+    ("menu.mp.tcp", "TCP"),
+    ("menu.mp.new_game", "New Game"),
+    ("menu.mp.host.game_type", "Game Type"),
+    ("menu.mp.host.coop", "Cooperative"),
+    ("menu.mp.host.deathmatch", "Deathmatch"),
+    ("menu.mp.host.skill", "Skill"),
+    ("menu.mp.host.skill_easy", "Easy"),
+    ("menu.mp.host.skill_normal", "Normal"),
+    ("menu.mp.host.skill_hard", "Hard"),
+    ("menu.mp.host.skill_nightmare", "Nightmare"),
+    ("menu.mp.host.max_players", "Max players"),
+    ("menu.mp.host.frag_limit", "Frag Limit"),
+    ("menu.mp.host.time_limit", "Time Limit"),
+    ("menu.mp.host.none", "None"),
+    ("menu.options.go_to_console", "Go to console"),
+    ("menu.options.reset_defaults", "Reset to defaults"),
+    ("menu.options.render_scale", "Render scale"),
+    ("menu.options.screen_size", "Screen Size"),
+    ("menu.options.brightness", "Brightness"),
+    ("menu.options.mouse_speed", "Mouse Speed"),
+    ("menu.options.cd_volume", "CD music volume"),
+    ("menu.options.sound_volume", "Sound volume"),
+    ("menu.options.always_run", "Always run"),
+    ("menu.options.invert_mouse", "Invert mouse"),
+    ("menu.options.lookspring", "Lookspring"),
+    ("menu.options.lookstrafe", "Lookstrafe"),
+    ("menu.options.joy_preset", "Gamepad sticks"),
+    ("menu.options.joy_preset_default", "Default"),
+    ("menu.options.joy_preset_southpaw", "Southpaw"),
+    ("menu.options.joy_curve", "Gamepad curve"),
+    ("menu.options.joy_curve_linear", "Linear"),
+    ("menu.options.joy_curve_squared", "Squared"),
+    ("menu.options.joy_curve_custom", "Custom"),
+    ("menu.options.mods", "Mods"),
+    ("menu.mods.base_game", "Base game"),
+    ("menu.quit.confirm", "Yes, quit"),
+    ("net.already_banned", "{0} is already banned"),
+    ("net.ban_save_failed", "Couldn't save the ban list: {0}"),
+    ("net.banned", "You have been banned."),
+    ("net.no_bans", "No addresses are banned"),
+    ("net.not_banned", "{0} is not banned"),
+    ("oob.ack", "Ping reply received"),
+    ("oob.bad_address", "Couldn't resolve \"{0}\""),
+    ("oob.failed", "packet failed: {0}"),
+    ("oob.info_entry", "{0}: {1}"),
+    ("rcon.bad_address", "Couldn't resolve rcon_address \"{0}\""),
+    ("rcon.failed", "rcon failed: {0}"),
+    (
+        "rcon.no_password",
+        "You must set rcon_password before issuing an rcon command.",
+    ),
+    (
+        "rcon.no_server",
+        "You must either be connected or set rcon_address to issue rcon commands",
+    ),
+];
+
+lazy_static! {
+    static ref STRINGS: RwLock<StringTable> = RwLock::new(StringTable::english());
+}
+
+/// A table mapping message IDs to localized text.
+pub struct StringTable {
+    lang: String,
+    strings: HashMap<String, String>,
+}
+
+impl StringTable {
+    /// Returns the built-in English string table.
+    pub fn english() -> StringTable {
+        StringTable {
+            lang: "en".to_owned(),
+            strings: ENGLISH
+                .iter()
+                .map(|(id, text)| (id.to_string(), text.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Loads the string table for `lang` from `strings/<lang>.toml`.
+    ///
+    /// Messages not defined by the file are filled in with the built-in English text.
+    pub fn load<S>(vfs: &Vfs, lang: S) -> Result<StringTable, Error>
+    where
+        S: AsRef<str>,
+    {
+        let lang = lang.as_ref();
+        let path = format!("strings/{}.toml", lang);
+
+        let mut src = String::new();
+        vfs.open(&path)?
+            .read_to_string(&mut src)
+            .context(format!("Couldn't read {}", path))?;
+        let value: toml::Value = src.parse().context(format!("Couldn't parse {}", path))?;
+
+        let mut table = StringTable::english();
+        table.lang = lang.to_owned();
+        table.flatten("", &value);
+
+        Ok(table)
+    }
+
+    // insert every string in `value` under its dotted ID
+    fn flatten(&mut self, prefix: &str, value: &toml::Value) {
+        match value {
+            toml::Value::String(s) => {
+                self.strings.insert(prefix.to_owned(), s.to_owned());
+            }
+
+            toml::Value::Table(t) => {
+                for (key, val) in t.iter() {
+                    let id = if prefix.is_empty() {
+                        key.to_owned()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    self.flatten(&id, val);
+                }
+            }
+
+            _ => warn!("Ignoring non-string message {}", prefix),
+        }
+    }
+
+    /// Returns the language of this table.
+    pub fn lang(&self) -> &str {
+        &self.lang
+    }
+
+    /// Returns the text for the given message ID.
+    ///
+    /// If no such message exists, the ID itself is returned so that the
+    /// missing message is easy to spot.
+    pub fn get<'a>(&'a self, id: &'a str) -> &'a str {
+        match self.strings.get(id) {
+            Some(s) => s,
+            None => {
+                debug!("No localized text for {}", id);
+                id
+            }
+        }
+    }
+
+    /// Returns the text for the given message ID with positional arguments filled in.
+    ///
+    /// Occurrences of `{0}`, `{1}`, etc. are replaced with the corresponding
+    /// element of `args`. Arguments are inserted verbatim, so placeholders
+    /// inside an argument are left alone. Placeholders without a matching
+    /// argument are kept as they are.
+    pub fn format(&self, id: &str, args: &[&str]) -> String {
+        let template = self.get(id);
+        let mut text = String::with_capacity(template.len());
+
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let arg = rest
+                .find('}')
+                .and_then(|end| rest[1..end].parse::<usize>().ok().map(|i| (i, end)))
+                .and_then(|(i, end)| args.get(i).map(|arg| (arg, end)));
+
+            match arg {
+                Some((arg, end)) => {
+                    text.push_str(arg);
+                    rest = &rest[end + 1..];
+                }
+
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+
+        text
+    }
+}
+
+/// Replaces the active string table with the one for `lang`.
+///
+/// If the table can't be loaded, the built-in English text remains active.
+pub fn set_language<S>(vfs: &Vfs, lang: S) -> Result<(), Error>
+where
+    S: AsRef<str>,
+{
+    let table = StringTable::load(vfs, lang)?;
+    *STRINGS.write().unwrap() = table;
+    Ok(())
+}
+
+/// Returns the localized text for the given message ID.
+pub fn tr(id: &str) -> String {
+    STRINGS.read().unwrap().get(id).to_owned()
+}
+
+/// Returns the localized text for the given message ID with arguments filled in.
+///
+/// See `StringTable::format`.
+pub fn tr_args(id: &str, args: &[&str]) -> String {
+    STRINGS.read().unwrap().format(id, args)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_flatten_nested_tables() {
+        let value: toml::Value = "[menu.main]\nquit = \"Beenden\"\n".parse().unwrap();
+        let mut table = StringTable::english();
+        table.flatten("", &value);
+
+        assert_eq!(table.get("menu.main.quit"), "Beenden");
+
+        // untranslated messages fall back to English
+        assert_eq!(table.get("menu.main.options"), "Options");
+    }
+
+    #[test]
+    fn test_format_args() {
+        let table = StringTable::english();
+        assert_eq!(
+            table.format("console.cvar_value", &["fov", "90"]),
+            "\"fov\" is \"90\""
+        );
+    }
+
+    #[test]
+    fn test_format_args_verbatim() {
+        let table = StringTable::english();
+
+        // a placeholder typed by the user isn't substituted again
+        assert_eq!(
+            table.format("console.cvar_value", &["{1}", "90"]),
+            "\"{1}\" is \"90\""
+        );
+
+        // placeholders without an argument are kept
+        assert_eq!(
+            table.format("console.cvar_value", &["fov"]),
+            "\"fov\" is \"{1}\""
+        );
+    }
+}
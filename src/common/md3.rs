@@ -0,0 +1,585 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Loader for Quake III MD3 models.
+//!
+//! MD3 models are used by community replacement content packs. Each model is
+//! made up of one or more surfaces, each of which has its own skin and its own
+//! set of per-frame vertex positions and normals.
+
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use crate::common::vfs::Vfs;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use cgmath::Vector3;
+use failure::Error;
+
+pub const MAGIC: i32 =
+    ('I' as i32) << 0 | ('D' as i32) << 8 | ('P' as i32) << 16 | ('3' as i32) << 24;
+pub const VERSION: i32 = 15;
+
+const MAX_QPATH: usize = 64;
+const FRAME_NAME_LEN: usize = 16;
+
+// vertex positions are stored as fixed-point values in units of 1/64
+const XYZ_SCALE: f32 = 1.0 / 64.0;
+
+// sizes of the records stored in each lump
+const FRAME_SIZE: u64 = 3 * 12 + 4 + FRAME_NAME_LEN as u64;
+const SURFACE_HEADER_SIZE: u64 = 4 + MAX_QPATH as u64 + 4 + 4 * 4 + 5 * 4;
+const SHADER_SIZE: u64 = MAX_QPATH as u64 + 4;
+const TRIANGLE_SIZE: u64 = 3 * 4;
+const TEXCOORD_SIZE: u64 = 2 * 4;
+const VERTEX_SIZE: u64 = 4 * 2;
+
+#[derive(Clone, Debug)]
+pub struct Md3Frame {
+    name: String,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    origin: Vector3<f32>,
+    radius: f32,
+}
+
+impl Md3Frame {
+    /// Returns the name of this frame.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the minimum extent of this frame relative to the model origin.
+    pub fn min(&self) -> Vector3<f32> {
+        self.min
+    }
+
+    /// Returns the maximum extent of this frame relative to the model origin.
+    pub fn max(&self) -> Vector3<f32> {
+        self.max
+    }
+
+    /// Returns the local origin of this frame.
+    pub fn origin(&self) -> Vector3<f32> {
+        self.origin
+    }
+
+    /// Returns the radius of this frame's bounding sphere.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Md3Vertex {
+    position: Vector3<f32>,
+    normal: Vector3<f32>,
+}
+
+impl Md3Vertex {
+    /// Returns the position of this vertex relative to the model origin.
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    /// Returns the unit normal of this vertex.
+    pub fn normal(&self) -> Vector3<f32> {
+        self.normal
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Md3Surface {
+    name: String,
+    shaders: Box<[String]>,
+    triangles: Box<[[u32; 3]]>,
+    texcoords: Box<[[f32; 2]]>,
+    frames: Box<[Box<[Md3Vertex]>]>,
+}
+
+impl Md3Surface {
+    /// Returns the name of this surface.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the names of the skins which may be applied to this surface.
+    pub fn shaders(&self) -> &[String] {
+        &self.shaders
+    }
+
+    /// Returns the triangles of this surface as indices into the vertex list.
+    pub fn triangles(&self) -> &[[u32; 3]] {
+        &self.triangles
+    }
+
+    /// Returns the texture coordinates of each vertex.
+    pub fn texcoords(&self) -> &[[f32; 2]] {
+        &self.texcoords
+    }
+
+    /// Returns the vertices of this surface for each frame.
+    pub fn frames(&self) -> &[Box<[Md3Vertex]>] {
+        &self.frames
+    }
+}
+
+#[derive(Debug)]
+pub struct Md3Model {
+    name: String,
+    frames: Box<[Md3Frame]>,
+    surfaces: Box<[Md3Surface]>,
+}
+
+impl Md3Model {
+    /// Returns the name stored in the model header.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn frames(&self) -> &[Md3Frame] {
+        &self.frames
+    }
+
+    pub fn surfaces(&self) -> &[Md3Surface] {
+        &self.surfaces
+    }
+}
+
+/// Returns the path of the MD3 replacement for the given MDL path.
+pub fn replacement_path<S>(mdl_path: S) -> Option<String>
+where
+    S: AsRef<str>,
+{
+    let mdl_path = mdl_path.as_ref();
+    if mdl_path.ends_with(".mdl") {
+        Some(format!("{}.md3", &mdl_path[..mdl_path.len() - 4]))
+    } else {
+        None
+    }
+}
+
+/// Decodes a normal stored as a pair of 8-bit spherical coordinates.
+pub fn decode_normal(encoded: i16) -> Vector3<f32> {
+    use std::f32::consts::PI;
+
+    let lat = ((encoded >> 8) & 0xFF) as f32 * 2.0 * PI / 255.0;
+    let lng = (encoded & 0xFF) as f32 * 2.0 * PI / 255.0;
+
+    Vector3::new(lat.cos() * lng.sin(), lat.sin() * lng.sin(), lng.cos())
+}
+
+fn read_name<R>(reader: &mut R, len: usize) -> Result<String, Error>
+where
+    R: Read,
+{
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(len);
+    Ok(String::from_utf8(bytes[..end].to_vec())?)
+}
+
+fn read_vector3<R>(reader: &mut R) -> Result<Vector3<f32>, Error>
+where
+    R: Read,
+{
+    Ok(Vector3::new(
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+    ))
+}
+
+fn read_count<R>(reader: &mut R, what: &str) -> Result<usize, Error>
+where
+    R: Read,
+{
+    let count = reader.read_i32::<LittleEndian>()?;
+    ensure!(count >= 0, "Negative {} count ({})", what, count);
+    Ok(count as usize)
+}
+
+fn read_offset<R>(reader: &mut R) -> Result<u64, Error>
+where
+    R: Read,
+{
+    let offset = reader.read_i32::<LittleEndian>()?;
+    ensure!(offset >= 0, "Negative offset ({})", offset);
+    Ok(offset as u64)
+}
+
+// Checks that `count` records of `size` bytes starting at `offset` are inside the file.
+//
+// Counts come straight from the file, so this has to pass before any of them
+// are used to reserve memory.
+fn check_lump(
+    file_len: u64,
+    offset: u64,
+    count: usize,
+    size: u64,
+    what: &str,
+) -> Result<(), Error> {
+    let end = (count as u64)
+        .checked_mul(size)
+        .and_then(|len| offset.checked_add(len));
+    ensure!(
+        end.map_or(false, |end| end <= file_len),
+        "MD3 {} data ({} at offset {}) extends past end of file ({} bytes)",
+        what,
+        count,
+        offset,
+        file_len
+    );
+    Ok(())
+}
+
+fn load_surface<R>(
+    reader: &mut R,
+    file_len: u64,
+    start: u64,
+    frame_count: usize,
+) -> Result<(Md3Surface, u64), Error>
+where
+    R: Read + Seek,
+{
+    reader.seek(SeekFrom::Start(start))?;
+
+    let magic = reader.read_i32::<LittleEndian>()?;
+    ensure!(
+        magic == MAGIC,
+        "Bad MD3 surface magic number (got {}, should be {})",
+        magic,
+        MAGIC
+    );
+
+    let name = read_name(reader, MAX_QPATH)?;
+    let _flags = reader.read_i32::<LittleEndian>()?;
+
+    let surf_frame_count = read_count(reader, "surface frame")?;
+    ensure!(
+        surf_frame_count == frame_count,
+        "Surface {} has {} frames, model has {}",
+        name,
+        surf_frame_count,
+        frame_count
+    );
+
+    let shader_count = read_count(reader, "shader")?;
+    let vertex_count = read_count(reader, "vertex")?;
+    let triangle_count = read_count(reader, "triangle")?;
+    let ofs_triangles = read_offset(reader)?;
+    let ofs_shaders = read_offset(reader)?;
+    let ofs_st = read_offset(reader)?;
+    let ofs_xyznormal = read_offset(reader)?;
+    let ofs_end = read_offset(reader)?;
+
+    check_lump(
+        file_len,
+        start + ofs_shaders,
+        shader_count,
+        SHADER_SIZE,
+        "shader",
+    )?;
+    check_lump(
+        file_len,
+        start + ofs_triangles,
+        triangle_count,
+        TRIANGLE_SIZE,
+        "triangle",
+    )?;
+    check_lump(
+        file_len,
+        start + ofs_st,
+        vertex_count,
+        TEXCOORD_SIZE,
+        "texcoord",
+    )?;
+    let frame_vertex_count = vertex_count
+        .checked_mul(frame_count)
+        .ok_or_else(|| format_err!("Too many vertices in surface {}", name))?;
+    check_lump(
+        file_len,
+        start + ofs_xyznormal,
+        frame_vertex_count,
+        VERTEX_SIZE,
+        "vertex",
+    )?;
+
+    reader.seek(SeekFrom::Start(start + ofs_shaders))?;
+    let mut shaders = Vec::with_capacity(shader_count);
+    for _ in 0..shader_count {
+        shaders.push(read_name(reader, MAX_QPATH)?);
+        let _shader_index = reader.read_i32::<LittleEndian>()?;
+    }
+
+    reader.seek(SeekFrom::Start(start + ofs_triangles))?;
+    let mut triangles = Vec::with_capacity(triangle_count);
+    for _ in 0..triangle_count {
+        let mut indices = [0; 3];
+        for i in 0..3 {
+            let index = reader.read_i32::<LittleEndian>()?;
+            ensure!(
+                index >= 0 && (index as usize) < vertex_count,
+                "Invalid vertex index ({}) in surface {}",
+                index,
+                name
+            );
+            indices[i] = index as u32;
+        }
+        triangles.push(indices);
+    }
+
+    reader.seek(SeekFrom::Start(start + ofs_st))?;
+    let mut texcoords = Vec::with_capacity(vertex_count);
+    for _ in 0..vertex_count {
+        texcoords.push([
+            reader.read_f32::<LittleEndian>()?,
+            reader.read_f32::<LittleEndian>()?,
+        ]);
+    }
+
+    reader.seek(SeekFrom::Start(start + ofs_xyznormal))?;
+    let mut frames = Vec::with_capacity(frame_count);
+    for _ in 0..frame_count {
+        let mut vertices = Vec::with_capacity(vertex_count);
+        for _ in 0..vertex_count {
+            let position = Vector3::new(
+                reader.read_i16::<LittleEndian>()? as f32 * XYZ_SCALE,
+                reader.read_i16::<LittleEndian>()? as f32 * XYZ_SCALE,
+                reader.read_i16::<LittleEndian>()? as f32 * XYZ_SCALE,
+            );
+            let normal = decode_normal(reader.read_i16::<LittleEndian>()?);
+            vertices.push(Md3Vertex { position, normal });
+        }
+        frames.push(vertices.into_boxed_slice());
+    }
+
+    Ok((
+        Md3Surface {
+            name,
+            shaders: shaders.into_boxed_slice(),
+            triangles: triangles.into_boxed_slice(),
+            texcoords: texcoords.into_boxed_slice(),
+            frames: frames.into_boxed_slice(),
+        },
+        start + ofs_end,
+    ))
+}
+
+pub fn load<R>(data: R) -> Result<Md3Model, Error>
+where
+    R: Read + Seek,
+{
+    let mut reader = BufReader::new(data);
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let magic = reader.read_i32::<LittleEndian>()?;
+    ensure!(
+        magic == MAGIC,
+        "Bad MD3 magic number (got {}, should be {})",
+        magic,
+        MAGIC
+    );
+
+    let version = reader.read_i32::<LittleEndian>()?;
+    ensure!(
+        version == VERSION,
+        "Bad MD3 version (got {}, should be {})",
+        version,
+        VERSION
+    );
+
+    let name = read_name(&mut reader, MAX_QPATH)?;
+    let _flags = reader.read_i32::<LittleEndian>()?;
+
+    let frame_count = read_count(&mut reader, "frame")?;
+    ensure!(frame_count > 0, "MD3 model {} has no frames", name);
+    let _tag_count = read_count(&mut reader, "tag")?;
+    let surface_count = read_count(&mut reader, "surface")?;
+    let _skin_count = read_count(&mut reader, "skin")?;
+
+    let ofs_frames = read_offset(&mut reader)?;
+    let _ofs_tags = read_offset(&mut reader)?;
+    let ofs_surfaces = read_offset(&mut reader)?;
+    let _ofs_eof = read_offset(&mut reader)?;
+
+    check_lump(file_len, ofs_frames, frame_count, FRAME_SIZE, "frame")?;
+    reader.seek(SeekFrom::Start(ofs_frames))?;
+    let mut frames = Vec::with_capacity(frame_count);
+    for _ in 0..frame_count {
+        let min = read_vector3(&mut reader)?;
+        let max = read_vector3(&mut reader)?;
+        let origin = read_vector3(&mut reader)?;
+        let radius = reader.read_f32::<LittleEndian>()?;
+        let name = read_name(&mut reader, FRAME_NAME_LEN)?;
+        debug!("MD3 frame name: {}", name);
+
+        frames.push(Md3Frame {
+            name,
+            min,
+            max,
+            origin,
+            radius,
+        });
+    }
+
+    // surfaces are stored back to back, each one recording the offset of its end
+    check_lump(
+        file_len,
+        ofs_surfaces,
+        surface_count,
+        SURFACE_HEADER_SIZE,
+        "surface",
+    )?;
+    let mut surfaces = Vec::with_capacity(surface_count);
+    let mut surface_start = ofs_surfaces;
+    for _ in 0..surface_count {
+        let (surface, surface_end) =
+            load_surface(&mut reader, file_len, surface_start, frame_count)?;
+        surfaces.push(surface);
+        surface_start = surface_end;
+    }
+
+    Ok(Md3Model {
+        name,
+        frames: frames.into_boxed_slice(),
+        surfaces: surfaces.into_boxed_slice(),
+    })
+}
+
+/// Returns the path of the image loaded for the given surface shader.
+///
+/// Only PNG images are supported, so the extension of `shader` is replaced.
+pub fn skin_path<S>(shader: S) -> String
+where
+    S: AsRef<str>,
+{
+    let shader = shader.as_ref();
+    match shader.rfind('.') {
+        Some(dot) if !shader[dot..].contains('/') => format!("{}.png", &shader[..dot]),
+        _ => format!("{}.png", shader),
+    }
+}
+
+/// Loads a surface skin as 8-bit RGBA data.
+///
+/// Only PNG images are supported. If `shader` names an image in some other
+/// format, a PNG with the same base name is tried instead.
+///
+/// Returns `(width, height, rgba)`.
+pub fn load_skin<S>(vfs: &Vfs, shader: S) -> Result<(u32, u32, Vec<u8>), Error>
+where
+    S: AsRef<str>,
+{
+    let path = skin_path(shader);
+
+    let mut decoder = png::Decoder::new(vfs.open(&path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    let rgba = match info.color_type {
+        png::ColorType::RGBA => data,
+        png::ColorType::RGB => data
+            .chunks(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => data.iter().flat_map(|l| vec![*l, *l, *l, 0xFF]).collect(),
+        png::ColorType::Indexed => bail!("Indexed PNG was not expanded: {}", path),
+    };
+
+    Ok((info.width, info.height, rgba))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_replacement_path() {
+        assert_eq!(
+            replacement_path("progs/player.mdl"),
+            Some("progs/player.md3".to_owned())
+        );
+        assert_eq!(replacement_path("progs/s_light.spr"), None);
+    }
+
+    #[test]
+    fn test_skin_path() {
+        assert_eq!(skin_path("models/player.tga"), "models/player.png");
+        assert_eq!(skin_path("models/player"), "models/player.png");
+        assert_eq!(skin_path("models.v2/player"), "models.v2/player.png");
+    }
+
+    // builds a model with one empty frame and no surfaces
+    fn build_model(frame_count: i32) -> Vec<u8> {
+        use byteorder::WriteBytesExt;
+
+        let header_size = 4 + 4 + MAX_QPATH + 4 + 4 * 4 + 4 * 4;
+
+        let mut data = Vec::new();
+        data.write_i32::<LittleEndian>(MAGIC).unwrap();
+        data.write_i32::<LittleEndian>(VERSION).unwrap();
+        data.extend_from_slice(&[0; MAX_QPATH]);
+        data.write_i32::<LittleEndian>(0).unwrap();
+
+        // frames, tags, surfaces, skins
+        for count in &[frame_count, 0, 0, 0] {
+            data.write_i32::<LittleEndian>(*count).unwrap();
+        }
+
+        // frames, tags, surfaces, end of file
+        let end = header_size as i32 + FRAME_SIZE as i32;
+        for offset in &[header_size as i32, end, end, end] {
+            data.write_i32::<LittleEndian>(*offset).unwrap();
+        }
+
+        data.resize(end as usize, 0);
+        data
+    }
+
+    #[test]
+    fn test_load_counts() {
+        let model = load(Cursor::new(build_model(1))).unwrap();
+        assert_eq!(model.frames().len(), 1);
+
+        // counts that don't fit in the file are rejected before anything is allocated
+        assert!(load(Cursor::new(build_model(i32::max_value()))).is_err());
+        assert!(load(Cursor::new(build_model(2))).is_err());
+    }
+
+    #[test]
+    fn test_decode_normal() {
+        // latitude and longitude of zero point straight up
+        let up = decode_normal(0);
+        assert!((up.z - 1.0).abs() < 1e-6);
+
+        // longitude of pi points straight down
+        let down = decode_normal(128);
+        assert!(down.z < -0.99);
+    }
+}
@@ -224,6 +224,7 @@ pub struct AliasModel {
     texcoords: Box<[Texcoord]>,
     polygons: Box<[IndexedPolygon]>,
     keyframes: Box<[Keyframe]>,
+    sync_type: SyncType,
     flags: ModelFlags,
 }
 
@@ -260,6 +261,11 @@ impl AliasModel {
         &self.keyframes
     }
 
+    /// Returns whether instances of this model animate in step or from random offsets.
+    pub fn sync_type(&self) -> SyncType {
+        self.sync_type
+    }
+
     pub fn flags(&self) -> ModelFlags {
         self.flags
     }
@@ -344,7 +350,10 @@ where
         keyframe_count
     );
 
-    let _sync_type = SyncType::from_i32(reader.read_i32::<LittleEndian>()?);
+    let sync_type = match SyncType::from_i32(reader.read_i32::<LittleEndian>()?) {
+        Some(s) => s,
+        None => bail!("Invalid sync type for alias model"),
+    };
 
     let flags_bits = reader.read_i32::<LittleEndian>()?;
     ensure!(flags_bits >= 0, "Invalid flag bits for alias model");
@@ -542,11 +551,15 @@ where
 
                 reader.read_u8()?; // discard vertex normal
 
+                // the file stores the time at which each subframe ends, so convert those to
+                // per-subframe durations
                 let mut durations = Vec::new();
+                let mut prev_end = 0.0;
                 for _ in 0..subframe_count {
-                    durations.push(engine::duration_from_f32(
-                        reader.read_f32::<LittleEndian>()?,
-                    ));
+                    let end = reader.read_f32::<LittleEndian>()?;
+                    ensure!(end > prev_end, "Subframe intervals must be increasing");
+                    durations.push(engine::duration_from_f32(end - prev_end));
+                    prev_end = end;
                 }
 
                 let mut subframes = Vec::new();
@@ -627,6 +640,7 @@ where
         texcoords: texcoords.into_boxed_slice(),
         polygons: polygons.into_boxed_slice(),
         keyframes: keyframes.into_boxed_slice(),
+        sync_type,
         flags,
     })
 }
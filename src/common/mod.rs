@@ -22,14 +22,21 @@ pub mod alloc;
 pub mod bsp;
 pub mod console;
 pub mod engine;
+pub mod fs_util;
+#[cfg(feature = "client")]
 pub mod host;
+pub mod l10n;
 pub mod math;
+pub mod md3;
 pub mod mdl;
 pub mod model;
+pub mod mods;
 pub mod net;
 pub mod pak;
 pub mod parse;
+pub mod pcx;
 pub mod sprite;
+pub mod tga;
 pub mod util;
 pub mod vfs;
 pub mod wad;
@@ -15,12 +15,20 @@
 // DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::common::{bsp::BspModel, mdl, mdl::AliasModel, sprite, sprite::SpriteModel, vfs::Vfs};
+use crate::common::{
+    bsp::BspModel,
+    md3::{self, Md3Model},
+    mdl,
+    mdl::AliasModel,
+    sprite,
+    sprite::SpriteModel,
+    vfs::Vfs,
+};
 
 use cgmath::Vector3;
 use failure::Error;
 
-#[derive(Debug, FromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum SyncType {
     Sync = 0,
     Rand = 1,
@@ -52,6 +60,7 @@ pub enum ModelKind {
     None,
     Brush(BspModel),
     Alias(AliasModel),
+    Md3(Md3Model),
     Sprite(SpriteModel),
 }
 
@@ -77,10 +86,27 @@ impl Model {
         if name.ends_with(".bsp") {
             panic!("BSP files may contain multiple models, use bsp::load for this");
         } else if name.ends_with(".mdl") {
-            Ok(Model::from_alias_model(
-                name.to_owned(),
-                mdl::load(vfs.open(name)?)?,
-            ))
+            let alias_model = mdl::load(vfs.open(name)?)?;
+
+            // prefer replacement content if there is any
+            if let Some(md3_name) = md3::replacement_path(name) {
+                if let Ok(md3_file) = vfs.open(&md3_name) {
+                    match md3::load(md3_file) {
+                        Ok(md3_model) => {
+                            debug!("Using {} in place of {}", md3_name, name);
+                            return Ok(Model::from_md3_model(
+                                name.to_owned(),
+                                md3_model,
+                                alias_model.flags(),
+                            ));
+                        }
+
+                        Err(e) => warn!("Couldn't load {}, using {}: {}", md3_name, name, e),
+                    }
+                }
+            }
+
+            Ok(Model::from_alias_model(name.to_owned(), alias_model))
         } else if name.ends_with(".spr") {
             Ok(Model::from_sprite_model(
                 name.to_owned(),
@@ -117,6 +143,21 @@ impl Model {
         }
     }
 
+    /// Construct a new generic model from an MD3 model.
+    ///
+    /// MD3 models don't carry Quake model flags, so these are taken from the
+    /// alias model being replaced.
+    pub fn from_md3_model<S>(name: S, md3_model: Md3Model, flags: ModelFlags) -> Model
+    where
+        S: AsRef<str>,
+    {
+        Model {
+            name: name.as_ref().to_owned(),
+            kind: ModelKind::Md3(md3_model),
+            flags,
+        }
+    }
+
     /// Construct a new generic model from a sprite model.
     pub fn from_sprite_model<S>(name: S, sprite_model: SpriteModel) -> Model
     where
@@ -134,6 +175,32 @@ impl Model {
         &self.name
     }
 
+    /// Returns the paths of the files this model was loaded from.
+    ///
+    /// Alias models include their MD3 replacement, whether or not one was found. Brush models
+    /// are loaded with their map and return nothing.
+    pub fn source_files(&self) -> Vec<String> {
+        let mut files = Vec::new();
+
+        match self.kind {
+            ModelKind::None | ModelKind::Brush(_) => return files,
+            ModelKind::Sprite(_) => files.push(self.name.clone()),
+            ModelKind::Alias(_) => {
+                files.push(self.name.clone());
+                files.extend(md3::replacement_path(&self.name));
+            }
+            ModelKind::Md3(ref md3_model) => {
+                files.push(self.name.clone());
+                files.extend(md3::replacement_path(&self.name));
+                for surface in md3_model.surfaces() {
+                    files.extend(surface.shaders().iter().map(md3::skin_path));
+                }
+            }
+        }
+
+        files
+    }
+
     /// Return the minimum extent of this model.
     pub fn min(&self) -> Vector3<f32> {
         debug!("Retrieving min of model {}", self.name);
@@ -144,7 +211,7 @@ impl Model {
 
             // TODO: maybe change this?
             // https://github.com/id-Software/Quake/blob/master/WinQuake/gl_model.c#L1625
-            ModelKind::Alias(_) => Vector3::new(-16.0, -16.0, -16.0),
+            ModelKind::Alias(_) | ModelKind::Md3(_) => Vector3::new(-16.0, -16.0, -16.0),
         }
     }
 
@@ -158,7 +225,7 @@ impl Model {
 
             // TODO: maybe change this?
             // https://github.com/id-Software/Quake/blob/master/WinQuake/gl_model.c#L1625
-            ModelKind::Alias(_) => Vector3::new(16.0, 16.0, 16.0),
+            ModelKind::Alias(_) | ModelKind::Md3(_) => Vector3::new(16.0, 16.0, 16.0),
         }
     }
 
@@ -168,8 +235,8 @@ impl Model {
             ModelKind::Brush(_) => SyncType::Sync,
             // TODO: expose sync_type in Sprite and reflect it here
             ModelKind::Sprite(ref _smodel) => SyncType::Sync,
-            // TODO: expose sync_type in Mdl and reflect it here
-            ModelKind::Alias(ref _amodel) => SyncType::Sync,
+            ModelKind::Alias(ref amodel) => amodel.sync_type(),
+            ModelKind::Md3(_) => SyncType::Sync,
         }
     }
 
@@ -0,0 +1,198 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Discovery of mod directories installed alongside the base game.
+//!
+//! Mods are siblings of the base game directory, as `hipnotic` is of `id1`.
+//! A directory counts as a mod if it contains `pak0.pak` or `progs.dat`.
+//!
+//! A mod may describe itself with a `modinfo.txt` file of `key: value` lines:
+//!
+//! ```text
+//! title: Scourge of Armagon
+//! author: Hipnotic Interactive
+//! description: Mission pack 1
+//! ```
+//!
+//! Unknown keys, blank lines and lines starting with `#` are ignored.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the optional metadata file in a mod directory.
+pub const MOD_INFO_FILE: &str = "modinfo.txt";
+
+// files which mark a directory as containing game data
+const GAME_FILES: &[&str] = &["pak0.pak", "progs.dat"];
+
+/// Metadata read from a mod's `modinfo.txt`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+}
+
+impl ModInfo {
+    /// Parses the contents of a `modinfo.txt` file.
+    pub fn parse(text: &str) -> ModInfo {
+        let mut info = ModInfo::default();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.find(':') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => continue,
+            };
+
+            if value.is_empty() {
+                continue;
+            }
+
+            let field = match key.to_lowercase().as_str() {
+                "title" => &mut info.title,
+                "author" => &mut info.author,
+                "description" => &mut info.description,
+                _ => continue,
+            };
+
+            *field = Some(value.to_owned());
+        }
+
+        info
+    }
+}
+
+/// A mod directory found next to the base game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModDir {
+    /// The directory name, as passed to the `game` command.
+    pub name: String,
+    pub path: PathBuf,
+    pub info: ModInfo,
+}
+
+impl ModDir {
+    /// Returns the mod's title, or its directory name if it doesn't have one.
+    pub fn title(&self) -> &str {
+        self.info.title.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Returns true if `dir` contains game data.
+pub fn is_game_dir(dir: &Path) -> bool {
+    GAME_FILES.iter().any(|name| dir.join(name).is_file())
+}
+
+/// Returns the mod directories installed alongside `basedir`, sorted by name.
+///
+/// Directories that can't be read are skipped.
+pub fn discover(basedir: &Path) -> Vec<ModDir> {
+    let parent = match basedir.parent() {
+        Some(p) => p,
+        None => return Vec::new(),
+    };
+
+    let entries = match fs::read_dir(parent) {
+        Ok(e) => e,
+        Err(e) => {
+            warn!("Couldn't search {} for mods: {}", parent.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut mods: Vec<ModDir> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && path.file_name() != basedir.file_name())
+        .filter(|path| is_game_dir(path))
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.to_owned();
+            let info = match fs::read_to_string(path.join(MOD_INFO_FILE)) {
+                Ok(text) => ModInfo::parse(&text),
+                Err(_) => ModInfo::default(),
+            };
+
+            Some(ModDir { name, path, info })
+        })
+        .collect();
+
+    mods.sort_by(|a, b| a.name.cmp(&b.name));
+    mods
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn test_parse_mod_info() {
+        let info = ModInfo::parse(
+            "# installed from the CD\n\
+             Title: Scourge of Armagon\n\
+             author:Hipnotic Interactive\n\
+             \n\
+             description:\n\
+             version: 1.0\n",
+        );
+
+        assert_eq!(
+            info,
+            ModInfo {
+                title: Some("Scourge of Armagon".to_owned()),
+                author: Some("Hipnotic Interactive".to_owned()),
+                description: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_discover() {
+        let root = env::temp_dir().join(format!("richter-mods-{}", std::process::id()));
+        let basedir = root.join("id1");
+        for dir in &["id1", "hipnotic", "rogue", "screenshots"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        fs::write(basedir.join("pak0.pak"), "").unwrap();
+        fs::write(root.join("hipnotic/pak0.pak"), "").unwrap();
+        fs::write(root.join("rogue/progs.dat"), "").unwrap();
+        fs::write(
+            root.join("rogue").join(MOD_INFO_FILE),
+            "title: Dissolution of Eternity\n",
+        )
+        .unwrap();
+
+        let mods = discover(&basedir);
+        let names: Vec<_> = mods.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["hipnotic", "rogue"]);
+        assert_eq!(mods[0].title(), "hipnotic");
+        assert_eq!(mods[1].title(), "Dissolution of Eternity");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -19,18 +19,26 @@
 // SOFTWARE.
 
 use std::{
+    cell::RefCell,
     io::{BufReader, Cursor, ErrorKind},
     mem::size_of,
     net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    time::Instant,
 };
 
 use crate::common::{
-    net::{NetError, QSocket, MAX_MESSAGE},
+    l10n,
+    net::{
+        filter::{BanList, ConnectFilter, IpRange, Refusal},
+        NetError, QSocket, MAX_MESSAGE,
+    },
     util,
 };
 
 use byteorder::{LittleEndian, NetworkEndian, ReadBytesExt, WriteBytesExt};
 use chrono::Duration;
+use failure::Error;
 use num::FromPrimitive;
 
 pub const CONNECT_PROTOCOL_VERSION: u8 = 3;
@@ -86,6 +94,9 @@ pub enum RequestCode {
     ServerInfo = 2,
     PlayerInfo = 3,
     RuleInfo = 4,
+
+    // ProQuake extension
+    Rcon = 5,
 }
 
 #[derive(Debug)]
@@ -196,6 +207,55 @@ impl ConnectPacket for RequestRuleInfo {
     }
 }
 
+/// A request to execute a console command on the server.
+#[derive(Debug)]
+pub struct RequestRcon {
+    pub password: String,
+    pub command: String,
+}
+
+impl RequestRcon {
+    /// Returns `true` if this request's password matches `rcon_password`.
+    ///
+    /// An empty `rcon_password` disables remote commands entirely.
+    pub fn authenticate<S>(&self, rcon_password: S) -> bool
+    where
+        S: AsRef<str>,
+    {
+        let rcon_password = rcon_password.as_ref();
+        !rcon_password.is_empty() && self.password == rcon_password
+    }
+}
+
+impl ConnectPacket for RequestRcon {
+    fn code(&self) -> u8 {
+        RequestCode::Rcon as u8
+    }
+
+    fn content_len(&self) -> usize {
+        let mut len = 0;
+
+        // password and terminating zero byte
+        len += self.password.len() + size_of::<u8>();
+
+        // command and terminating zero byte
+        len += self.command.len() + size_of::<u8>();
+
+        len
+    }
+
+    fn write_content<W>(&self, writer: &mut W) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        writer.write(self.password.as_bytes())?;
+        writer.write_u8(0)?;
+        writer.write(self.command.as_bytes())?;
+        writer.write_u8(0)?;
+        Ok(())
+    }
+}
+
 /// A request from a client to retrieve information from or connect to the server.
 #[derive(Debug)]
 pub enum Request {
@@ -203,6 +263,7 @@ pub enum Request {
     ServerInfo(RequestServerInfo),
     PlayerInfo(RequestPlayerInfo),
     RuleInfo(RequestRuleInfo),
+    Rcon(RequestRcon),
 }
 
 impl Request {
@@ -237,6 +298,17 @@ impl Request {
             prev_cvar: prev_cvar.as_ref().to_string(),
         })
     }
+
+    pub fn rcon<S, T>(password: S, command: T) -> Request
+    where
+        S: AsRef<str>,
+        T: AsRef<str>,
+    {
+        Request::Rcon(RequestRcon {
+            password: password.as_ref().to_owned(),
+            command: command.as_ref().to_owned(),
+        })
+    }
 }
 
 impl ConnectPacket for Request {
@@ -247,6 +319,7 @@ impl ConnectPacket for Request {
             ServerInfo(ref s) => s.code(),
             PlayerInfo(ref p) => p.code(),
             RuleInfo(ref r) => r.code(),
+            Rcon(ref r) => r.code(),
         }
     }
 
@@ -257,6 +330,7 @@ impl ConnectPacket for Request {
             ServerInfo(ref s) => s.content_len(),
             PlayerInfo(ref p) => p.content_len(),
             RuleInfo(ref r) => r.content_len(),
+            Rcon(ref r) => r.content_len(),
         }
     }
 
@@ -270,6 +344,7 @@ impl ConnectPacket for Request {
             ServerInfo(ref s) => s.write_content(writer),
             PlayerInfo(ref p) => p.write_content(writer),
             RuleInfo(ref r) => r.write_content(writer),
+            Rcon(ref r) => r.write_content(writer),
         }
     }
 }
@@ -281,6 +356,9 @@ pub enum ResponseCode {
     ServerInfo = 0x83,
     PlayerInfo = 0x84,
     RuleInfo = 0x85,
+
+    // ProQuake extension
+    Rcon = 0x86,
 }
 
 #[derive(Debug)]
@@ -478,6 +556,32 @@ impl ConnectPacket for ResponseRuleInfo {
     }
 }
 
+/// The console output of a remote command.
+#[derive(Debug)]
+pub struct ResponseRcon {
+    pub message: String,
+}
+
+impl ConnectPacket for ResponseRcon {
+    fn code(&self) -> u8 {
+        ResponseCode::Rcon as u8
+    }
+
+    fn content_len(&self) -> usize {
+        // message plus terminating zero byte
+        self.message.len() + size_of::<u8>()
+    }
+
+    fn write_content<W>(&self, writer: &mut W) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        writer.write(self.message.as_bytes())?;
+        writer.write_u8(0)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub enum Response {
     Accept(ResponseAccept),
@@ -485,6 +589,7 @@ pub enum Response {
     ServerInfo(ResponseServerInfo),
     PlayerInfo(ResponsePlayerInfo),
     RuleInfo(ResponseRuleInfo),
+    Rcon(ResponseRcon),
 }
 
 impl ConnectPacket for Response {
@@ -496,6 +601,7 @@ impl ConnectPacket for Response {
             ServerInfo(ref s) => s.code(),
             PlayerInfo(ref p) => p.code(),
             RuleInfo(ref r) => r.code(),
+            Rcon(ref r) => r.code(),
         }
     }
 
@@ -507,6 +613,7 @@ impl ConnectPacket for Response {
             ServerInfo(ref s) => s.content_len(),
             PlayerInfo(ref p) => p.content_len(),
             RuleInfo(ref r) => r.content_len(),
+            Rcon(ref r) => r.content_len(),
         }
     }
 
@@ -521,6 +628,7 @@ impl ConnectPacket for Response {
             ServerInfo(ref s) => s.write_content(writer),
             PlayerInfo(ref p) => p.write_content(writer),
             RuleInfo(ref r) => r.write_content(writer),
+            Rcon(ref r) => r.write_content(writer),
         }
     }
 }
@@ -528,17 +636,85 @@ impl ConnectPacket for Response {
 /// A socket that listens for new connections or queries.
 pub struct ConnectListener {
     socket: UdpSocket,
+    filter: RefCell<ConnectFilter>,
+
+    // where the ban list is saved when it changes, if anywhere
+    ban_file: Option<PathBuf>,
 }
 
 impl ConnectListener {
     /// Creates a `ConnectListener` from the given address.
+    ///
+    /// The listener starts with no bans, and changes to its ban list aren't saved.
     pub fn bind<A>(addr: A) -> Result<ConnectListener, NetError>
     where
         A: ToSocketAddrs,
     {
         let socket = UdpSocket::bind(addr)?;
 
-        Ok(ConnectListener { socket })
+        Ok(ConnectListener {
+            socket,
+            filter: RefCell::new(ConnectFilter::new(BanList::new())),
+            ban_file: None,
+        })
+    }
+
+    /// Creates a `ConnectListener` from the given address with the bans in `ban_file`.
+    ///
+    /// A missing ban file is treated as empty. The file is rewritten whenever the ban list
+    /// changes.
+    pub fn bind_with_ban_file<A, P>(addr: A, ban_file: P) -> Result<ConnectListener, NetError>
+    where
+        A: ToSocketAddrs,
+        P: AsRef<Path>,
+    {
+        let ban_file = ban_file.as_ref();
+        let bans = BanList::load(ban_file).map_err(|e| {
+            NetError::with_msg(format!("Couldn't load {}: {}", ban_file.display(), e))
+        })?;
+
+        let mut listener = ConnectListener::bind(addr)?;
+        listener.filter.get_mut().bans = bans;
+        listener.ban_file = Some(ban_file.to_owned());
+        Ok(listener)
+    }
+
+    /// Replaces the list of addresses whose requests are refused.
+    pub fn set_bans(&self, bans: BanList) -> Result<(), Error> {
+        self.filter.borrow_mut().bans = bans;
+        self.save_bans()
+    }
+
+    /// Bans a range of addresses. Returns false if it was already banned.
+    pub fn add_ban(&self, range: IpRange) -> Result<bool, Error> {
+        if !self.filter.borrow_mut().bans.add(range) {
+            return Ok(false);
+        }
+
+        self.save_bans()?;
+        Ok(true)
+    }
+
+    /// Lifts the ban on a range of addresses. Returns false if it wasn't banned.
+    pub fn remove_ban(&self, range: &IpRange) -> Result<bool, Error> {
+        if !self.filter.borrow_mut().bans.remove(range) {
+            return Ok(false);
+        }
+
+        self.save_bans()?;
+        Ok(true)
+    }
+
+    /// Returns the banned address ranges.
+    pub fn bans(&self) -> Vec<IpRange> {
+        self.filter.borrow().bans.iter().cloned().collect()
+    }
+
+    fn save_bans(&self) -> Result<(), Error> {
+        match self.ban_file {
+            Some(ref path) => self.filter.borrow().bans.save(path),
+            None => Ok(()),
+        }
     }
 
     /// Receives a request and returns it along with its remote address.
@@ -547,13 +723,28 @@ impl ConnectListener {
         // allocated at https://github.com/id-Software/Quake/blob/master/WinQuake/net_main.c#L851
         let mut recv_buf = [0u8; MAX_MESSAGE];
         let (len, remote) = self.socket.recv_from(&mut recv_buf)?;
+
+        let refusal = self
+            .filter
+            .borrow_mut()
+            .check(remote.ip(), Instant::now())
+            .err();
+
+        // don't bother parsing requests from flooding clients
+        if refusal == Some(Refusal::RateLimited) {
+            return Err(NetError::with_msg(format!(
+                "Too many requests from {}",
+                remote.ip()
+            )));
+        }
+
         let mut reader = BufReader::new(&recv_buf[..len]);
 
         let control = reader.read_i32::<NetworkEndian>()?;
 
-        // TODO: figure out what a control value of -1 means
+        // connectionless packets are handled by `net::oob`
         if control == -1 {
-            return Err(NetError::with_msg("Control value is -1"));
+            return Err(NetError::with_msg("Unexpected connectionless packet"));
         }
 
         // high 4 bits must be 0x8000 (CONNECT_CONTROL)
@@ -609,8 +800,33 @@ impl ConnectListener {
                 let prev_cvar = util::read_cstring(&mut reader).unwrap();
                 Request::RuleInfo(RequestRuleInfo { prev_cvar })
             }
+
+            RequestCode::Rcon => {
+                let password = util::read_cstring(&mut reader)
+                    .map_err(|e| NetError::with_msg(format!("Invalid rcon password: {}", e)))?;
+                let command = util::read_cstring(&mut reader)
+                    .map_err(|e| NetError::with_msg(format!("Invalid rcon command: {}", e)))?;
+                Request::Rcon(RequestRcon { password, command })
+            }
         };
 
+        if refusal == Some(Refusal::Banned) {
+            // let banned players know why they can't connect
+            if let Request::Connect(_) = request {
+                self.send_response(
+                    Response::Reject(ResponseReject {
+                        message: l10n::tr("net.banned"),
+                    }),
+                    remote,
+                )?;
+            }
+
+            return Err(NetError::with_msg(format!(
+                "Request from banned address {}",
+                remote.ip()
+            )));
+        }
+
         Ok((request, remote))
     }
 
@@ -634,6 +850,12 @@ impl ConnectSocket {
         Ok(ConnectSocket { socket })
     }
 
+    /// Makes `recv_response` return `None` immediately when no response is waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NetError> {
+        self.socket.set_nonblocking(nonblocking)?;
+        Ok(())
+    }
+
     pub fn into_qsocket(self, remote: SocketAddr) -> QSocket {
         QSocket::new(self.socket, remote)
     }
@@ -670,9 +892,9 @@ impl ConnectSocket {
 
         let control = reader.read_i32::<NetworkEndian>()?;
 
-        // TODO: figure out what a control value of -1 means
+        // connectionless packets are handled by `net::oob`
         if control == -1 {
-            return Err(NetError::with_msg("Control value is -1"));
+            return Err(NetError::with_msg("Unexpected connectionless packet"));
         }
 
         // high 4 bits must be 0x8000 (CONNECT_CONTROL)
@@ -734,6 +956,12 @@ impl ConnectSocket {
 
             ResponseCode::PlayerInfo => unimplemented!(),
             ResponseCode::RuleInfo => unimplemented!(),
+
+            ResponseCode::Rcon => {
+                let message = util::read_cstring(&mut reader)
+                    .map_err(|e| NetError::with_msg(format!("Invalid rcon reply: {}", e)))?;
+                Response::Rcon(ResponseRcon { message })
+            }
         };
 
         Ok(Some((response, remote)))
@@ -788,6 +1016,45 @@ mod test {
         assert_eq!(packet_len, packet.len());
     }
 
+    #[test]
+    fn test_request_rcon_packet_len() {
+        let request_rcon = RequestRcon {
+            password: String::from("secret"),
+            command: String::from("map e1m1"),
+        };
+        let packet_len = request_rcon.packet_len() as usize;
+        let packet = request_rcon.to_bytes().unwrap();
+        assert_eq!(packet_len, packet.len());
+    }
+
+    #[test]
+    fn test_request_rcon_authenticate() {
+        let request_rcon = RequestRcon {
+            password: String::new(),
+            command: String::from("quit"),
+        };
+
+        // an unset password must not let everyone in
+        assert!(!request_rcon.authenticate(""));
+        assert!(!request_rcon.authenticate("secret"));
+
+        let request_rcon = RequestRcon {
+            password: String::from("secret"),
+            ..request_rcon
+        };
+        assert!(request_rcon.authenticate("secret"));
+    }
+
+    #[test]
+    fn test_response_rcon_packet_len() {
+        let response_rcon = ResponseRcon {
+            message: String::from("map changed"),
+        };
+        let packet_len = response_rcon.packet_len() as usize;
+        let packet = response_rcon.to_bytes().unwrap();
+        assert_eq!(packet_len, packet.len());
+    }
+
     #[test]
     fn test_response_accept_packet_len() {
         let response_accept = ResponseAccept { port: 26000 };
@@ -840,4 +1107,23 @@ mod test {
     fn test_connect_listener_bind() {
         let _listener = ConnectListener::bind("127.0.0.1:26000").unwrap();
     }
+
+    #[test]
+    fn test_recv_request_rcon_invalid_utf8() {
+        let listener = ConnectListener::bind("127.0.0.1:26001").unwrap();
+
+        // corrupt the first byte of the password
+        let mut packet = RequestRcon {
+            password: String::from("secret"),
+            command: String::from("quit"),
+        }
+        .to_bytes()
+        .unwrap();
+        packet[5] = 0xFF;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(&packet, "127.0.0.1:26001").unwrap();
+
+        assert!(listener.recv_request().is_err());
+    }
 }
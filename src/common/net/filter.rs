@@ -0,0 +1,524 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Filtering of connection requests by address.
+//!
+//! Servers keep a list of banned addresses and address ranges (in CIDR
+//! notation, e.g. `192.168.0.0/16`), which is saved to a ban file one range
+//! per line, and limit how often a single address may send requests so a
+//! misbehaving client can't flood the listener.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs::File,
+    io::{ErrorKind, Read},
+    net::IpAddr,
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::common::{
+    console::{CmdRegistry, ConsoleError, ConsoleOutput},
+    fs_util, l10n,
+    net::connect::ConnectListener,
+};
+
+use failure::Error;
+
+/// The name of the ban file.
+pub const BAN_FILE: &str = "bans.txt";
+
+/// The default number of requests an address may send in `DEFAULT_RATE_WINDOW`.
+pub const DEFAULT_RATE_LIMIT: usize = 10;
+
+/// The window over which requests are counted against `DEFAULT_RATE_LIMIT`.
+pub const DEFAULT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// A block of IP addresses sharing a common prefix.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IpRange {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Returns the range containing only `addr`.
+    pub fn single(addr: IpAddr) -> IpRange {
+        IpRange {
+            addr,
+            prefix_len: match addr {
+                IpAddr::V4(_) => 32,
+                IpAddr::V6(_) => 128,
+            },
+        }
+    }
+
+    /// Returns true if `addr` is in this range.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(a)) => {
+                let mask = u32::max_value()
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(a) & mask
+            }
+
+            (IpAddr::V6(net), IpAddr::V6(a)) => {
+                let mask = u128::max_value()
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(a) & mask
+            }
+
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<IpRange, Error> {
+        let (addr, prefix_len) = match s.find('/') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = match addr.parse() {
+            Ok(a) => a,
+            Err(_) => bail!("Invalid IP address: {}", addr),
+        };
+
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix_len = match prefix_len {
+            Some(p) => match p.parse::<u8>() {
+                Ok(len) if len <= max_len => len,
+                _ => bail!("Invalid prefix length: {}", p),
+            },
+            None => max_len,
+        };
+
+        Ok(IpRange { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == IpRange::single(self.addr) {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{}/{}", self.addr, self.prefix_len)
+        }
+    }
+}
+
+/// A list of banned address ranges.
+#[derive(Clone, Debug, Default)]
+pub struct BanList {
+    ranges: Vec<IpRange>,
+}
+
+impl BanList {
+    pub fn new() -> BanList {
+        BanList::default()
+    }
+
+    /// Parses a ban file: one range per line, with `//` starting a comment.
+    pub fn parse(text: &str) -> Result<BanList, Error> {
+        let mut ranges = Vec::new();
+        for line in text.lines() {
+            let line = match line.find("//") {
+                Some(i) => &line[..i],
+                None => line,
+            }
+            .trim();
+
+            if !line.is_empty() {
+                ranges.push(line.parse()?);
+            }
+        }
+
+        Ok(BanList { ranges })
+    }
+
+    /// Loads the ban file at `path`. A missing file is an empty list.
+    pub fn load<P>(path: P) -> Result<BanList, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BanList::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        BanList::parse(&text)
+    }
+
+    /// Writes the list to `path` in the format read by `load`.
+    pub fn save<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        fs_util::write_atomic(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Adds a range to the list. Returns false if it was already banned.
+    pub fn add(&mut self, range: IpRange) -> bool {
+        if self.ranges.contains(&range) {
+            return false;
+        }
+
+        self.ranges.push(range);
+        true
+    }
+
+    /// Removes a range from the list. Returns false if it wasn't banned.
+    pub fn remove(&mut self, range: &IpRange) -> bool {
+        let len = self.ranges.len();
+        self.ranges.retain(|r| r != range);
+        self.ranges.len() != len
+    }
+
+    /// Returns true if `addr` is in any banned range.
+    pub fn is_banned(&self, addr: IpAddr) -> bool {
+        self.ranges.iter().any(|r| r.contains(addr))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &IpRange> {
+        self.ranges.iter()
+    }
+}
+
+impl fmt::Display for BanList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for range in self.ranges.iter() {
+            writeln!(f, "{}", range)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Limits the number of requests accepted from each address.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_requests: usize,
+    window: Duration,
+
+    // times of the recent requests from each address
+    history: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Allows up to `max_requests` per address in any period of length `window`.
+    pub fn new(max_requests: usize, window: Duration) -> RateLimiter {
+        RateLimiter {
+            max_requests,
+            window,
+            history: HashMap::new(),
+        }
+    }
+
+    /// Records a request from `addr` at time `now`. Returns false if it exceeds the limit.
+    ///
+    /// Only accepted requests are recorded, so a flooding address can't keep
+    /// itself locked out and the history for it stays bounded.
+    pub fn allow(&mut self, addr: IpAddr, now: Instant) -> bool {
+        self.prune(now);
+
+        let recent = self.history.entry(addr).or_insert_with(VecDeque::new);
+        if recent.len() >= self.max_requests {
+            return false;
+        }
+
+        recent.push_back(now);
+        true
+    }
+
+    // drops requests that have left the window and forgets idle addresses
+    fn prune(&mut self, now: Instant) {
+        let window = self.window;
+        self.history.retain(|_, recent| {
+            while let Some(t) = recent.front() {
+                if now.duration_since(*t) < window {
+                    break;
+                }
+
+                recent.pop_front();
+            }
+
+            !recent.is_empty()
+        });
+    }
+}
+
+/// Why a connection request was refused.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Refusal {
+    Banned,
+    RateLimited,
+}
+
+/// The checks applied to every connection request.
+#[derive(Debug)]
+pub struct ConnectFilter {
+    pub bans: BanList,
+    pub limiter: RateLimiter,
+}
+
+impl ConnectFilter {
+    pub fn new(bans: BanList) -> ConnectFilter {
+        ConnectFilter {
+            bans,
+            limiter: RateLimiter::new(DEFAULT_RATE_LIMIT, DEFAULT_RATE_WINDOW),
+        }
+    }
+
+    /// Checks a request from `addr` received at time `now`.
+    pub fn check(&mut self, addr: IpAddr, now: Instant) -> Result<(), Refusal> {
+        if self.bans.is_banned(addr) {
+            return Err(Refusal::Banned);
+        }
+
+        if !self.limiter.allow(addr, now) {
+            return Err(Refusal::RateLimited);
+        }
+
+        Ok(())
+    }
+}
+
+/// Registers the `addban`, `removeban` and `listbans` commands for `listener`.
+pub fn register_commands(
+    cmds: &mut CmdRegistry,
+    output: Rc<RefCell<ConsoleOutput>>,
+    listener: Rc<ConnectListener>,
+) -> Result<(), ConsoleError> {
+    cmds.insert("addban", cmd_addban(output.clone(), listener.clone()))?;
+    cmds.insert("removeban", cmd_removeban(output.clone(), listener.clone()))?;
+    cmds.insert("listbans", cmd_listbans(output, listener))?;
+    Ok(())
+}
+
+/// Implements the `addban` command.
+pub fn cmd_addban(
+    output: Rc<RefCell<ConsoleOutput>>,
+    listener: Rc<ConnectListener>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        1 => match args[0].parse::<IpRange>() {
+            Ok(range) => match listener.add_ban(range) {
+                Ok(true) => (),
+                Ok(false) => output
+                    .borrow_mut()
+                    .print(l10n::tr_args("net.already_banned", &[&range.to_string()])),
+                Err(e) => output
+                    .borrow_mut()
+                    .error(l10n::tr_args("net.ban_save_failed", &[&e.to_string()])),
+            },
+            Err(e) => output.borrow_mut().error(e.to_string()),
+        },
+        _ => output.borrow_mut().print(l10n::tr_args(
+            "console.usage",
+            &["addban <address>[/<prefix length>]"],
+        )),
+    })
+}
+
+/// Implements the `removeban` command.
+pub fn cmd_removeban(
+    output: Rc<RefCell<ConsoleOutput>>,
+    listener: Rc<ConnectListener>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        1 => match args[0].parse::<IpRange>() {
+            Ok(range) => match listener.remove_ban(&range) {
+                Ok(true) => (),
+                Ok(false) => output
+                    .borrow_mut()
+                    .print(l10n::tr_args("net.not_banned", &[&range.to_string()])),
+                Err(e) => output
+                    .borrow_mut()
+                    .error(l10n::tr_args("net.ban_save_failed", &[&e.to_string()])),
+            },
+            Err(e) => output.borrow_mut().error(e.to_string()),
+        },
+        _ => output.borrow_mut().print(l10n::tr_args(
+            "console.usage",
+            &["removeban <address>[/<prefix length>]"],
+        )),
+    })
+}
+
+/// Implements the `listbans` command.
+pub fn cmd_listbans(
+    output: Rc<RefCell<ConsoleOutput>>,
+    listener: Rc<ConnectListener>,
+) -> Box<dyn Fn(&[&str])> {
+    Box::new(move |args| match args.len() {
+        0 => {
+            let bans = listener.bans();
+            let mut output = output.borrow_mut();
+            if bans.is_empty() {
+                output.print(l10n::tr("net.no_bans"));
+            }
+
+            for range in bans {
+                output.print(range.to_string());
+            }
+        }
+        _ => output
+            .borrow_mut()
+            .print(l10n::tr_args("console.usage", &["listbans"])),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{env, fs};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ip_range_contains() {
+        let range: IpRange = "192.168.0.0/16".parse().unwrap();
+        assert!(range.contains(ip("192.168.40.2")));
+        assert!(!range.contains(ip("192.169.0.1")));
+        assert!(!range.contains(ip("::1")));
+
+        let single: IpRange = "10.0.0.1".parse().unwrap();
+        assert!(single.contains(ip("10.0.0.1")));
+        assert!(!single.contains(ip("10.0.0.2")));
+
+        let all: IpRange = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(ip("8.8.8.8")));
+
+        let v6: IpRange = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(ip("2001:db8:1::5")));
+        assert!(!v6.contains(ip("2001:db9::5")));
+    }
+
+    #[test]
+    fn test_ip_range_parse_errors() {
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("10.0.0/8".parse::<IpRange>().is_err());
+        assert!("10.0.0.0/".parse::<IpRange>().is_err());
+    }
+
+    #[test]
+    fn test_ban_list_round_trip() {
+        let bans = BanList::parse("// griefers\n10.0.0.1\n\n172.16.0.0/12 // vpn\n").unwrap();
+        assert!(bans.is_banned(ip("172.20.1.1")));
+        assert!(!bans.is_banned(ip("10.0.0.2")));
+        assert_eq!(bans.to_string(), "10.0.0.1\n172.16.0.0/12\n");
+
+        let reparsed = BanList::parse(&bans.to_string()).unwrap();
+        assert_eq!(reparsed.ranges, bans.ranges);
+    }
+
+    #[test]
+    fn test_ban_list_add_remove() {
+        let mut bans = BanList::new();
+        let range: IpRange = "10.0.0.0/8".parse().unwrap();
+        assert!(bans.add(range));
+        assert!(!bans.add(range));
+        assert!(bans.is_banned(ip("10.1.2.3")));
+        assert!(bans.remove(&range));
+        assert!(!bans.remove(&range));
+        assert!(!bans.is_banned(ip("10.1.2.3")));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let addr = ip("10.0.0.1");
+
+        assert!(limiter.allow(addr, start));
+        assert!(limiter.allow(addr, start + Duration::from_secs(1)));
+        assert!(!limiter.allow(addr, start + Duration::from_secs(2)));
+
+        // other addresses are unaffected
+        assert!(limiter.allow(ip("10.0.0.2"), start + Duration::from_secs(2)));
+
+        // rejected requests aren't recorded, so only the first has left the window
+        assert!(limiter.allow(addr, start + Duration::from_secs(10)));
+        assert!(!limiter.allow(addr, start + Duration::from_secs(10)));
+        assert!(limiter.allow(addr, start + Duration::from_secs(11)));
+
+        // idle addresses are forgotten
+        limiter.allow(ip("10.0.0.3"), start + Duration::from_secs(30));
+        assert_eq!(limiter.history.len(), 1);
+    }
+
+    #[test]
+    fn test_ban_commands_save_ban_file() {
+        let dir = env::temp_dir().join(format!("richter-bans-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(BAN_FILE);
+        fs::write(&path, "10.0.0.1\n").unwrap();
+
+        let listener = Rc::new(ConnectListener::bind_with_ban_file("127.0.0.1:0", &path).unwrap());
+        assert_eq!(listener.bans(), vec!["10.0.0.1".parse().unwrap()]);
+
+        let mut cmds = CmdRegistry::new();
+        let output = Rc::new(RefCell::new(ConsoleOutput::new()));
+        register_commands(&mut cmds, output, listener.clone()).unwrap();
+
+        cmds.exec("addban", &["192.168.0.0/16"]).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "10.0.0.1\n192.168.0.0/16\n"
+        );
+
+        cmds.exec("removeban", &["10.0.0.1"]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "192.168.0.0/16\n");
+
+        // a malformed range leaves the list alone
+        cmds.exec("addban", &["10.0.0/8"]).unwrap();
+        assert_eq!(listener.bans().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_connect_filter() {
+        let mut filter = ConnectFilter::new(BanList::parse("10.0.0.0/8").unwrap());
+        let now = Instant::now();
+        assert_eq!(filter.check(ip("10.9.9.9"), now), Err(Refusal::Banned));
+        assert_eq!(filter.check(ip("11.0.0.1"), now), Ok(()));
+    }
+}
@@ -21,6 +21,10 @@
 // TODO: need to figure out an equivalence relation for read_/write_coord and read_/write_angle
 
 pub mod connect;
+pub mod filter;
+#[cfg(feature = "qw")]
+pub mod oob;
+mod protocol;
 
 use std::{
     collections::VecDeque,
@@ -37,6 +41,8 @@ use cgmath::{Deg, Vector3, Zero};
 use chrono::Duration;
 use num::FromPrimitive;
 
+pub use self::protocol::{AngleEncoding, CoordEncoding, Protocol};
+
 const MAX_MESSAGE: usize = 8192;
 const MAX_DATAGRAM: usize = 1024;
 const HEADER_SIZE: usize = 8;
@@ -63,6 +69,9 @@ pub const MAX_ITEMS: usize = 32;
 
 pub const DEFAULT_VIEWHEIGHT: f32 = 22.0;
 
+/// The UDP port servers listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 26000;
+
 #[derive(Debug)]
 pub enum NetError {
     Io(::std::io::Error),
@@ -235,6 +244,16 @@ impl PlayerColor {
     pub fn bits(&self) -> u8 {
         self.top << 4 | (self.bottom & 0x0F)
     }
+
+    /// Returns the shirt color.
+    pub fn top(&self) -> u8 {
+        self.top
+    }
+
+    /// Returns the pants color.
+    pub fn bottom(&self) -> u8 {
+        self.bottom
+    }
 }
 
 impl ::std::convert::From<u8> for PlayerColor {
@@ -330,7 +349,7 @@ pub enum TempEntity {
 }
 
 impl TempEntity {
-    pub fn read_temp_entity<R>(reader: &mut R) -> Result<TempEntity, NetError>
+    pub fn read_temp_entity<R>(reader: &mut R, protocol: Protocol) -> Result<TempEntity, NetError>
     where
         R: BufRead + ReadBytesExt,
     {
@@ -370,10 +389,10 @@ impl TempEntity {
                     Code::Teleport => PointEntityKind::Teleport,
                     _ => unreachable!(),
                 },
-                origin: read_coord_vector3(reader)?,
+                origin: protocol.read_coord_vector3(reader)?,
             },
             Code::ColorExplosion => {
-                let origin = read_coord_vector3(reader)?;
+                let origin = protocol.read_coord_vector3(reader)?;
                 let color_start = reader.read_u8()?;
                 let color_len = reader.read_u8()?;
 
@@ -395,19 +414,19 @@ impl TempEntity {
                     },
                 },
                 entity_id: reader.read_i16::<LittleEndian>()?,
-                start: read_coord_vector3(reader)?,
-                end: read_coord_vector3(reader)?,
+                start: protocol.read_coord_vector3(reader)?,
+                end: protocol.read_coord_vector3(reader)?,
             },
             Code::Grapple => Beam {
                 kind: BeamEntityKind::Grapple,
                 entity_id: reader.read_i16::<LittleEndian>()?,
-                start: read_coord_vector3(reader)?,
-                end: read_coord_vector3(reader)?,
+                start: protocol.read_coord_vector3(reader)?,
+                end: protocol.read_coord_vector3(reader)?,
             },
         })
     }
 
-    pub fn write_temp_entity<W>(&self, writer: &mut W) -> Result<(), NetError>
+    pub fn write_temp_entity<W>(&self, writer: &mut W, protocol: Protocol) -> Result<(), NetError>
     where
         W: WriteBytesExt,
     {
@@ -453,7 +472,7 @@ impl TempEntity {
                     }
                 };
 
-                write_coord_vector3(writer, origin)?;
+                protocol.write_coord_vector3(writer, origin)?;
             }
 
             TempEntity::Beam {
@@ -474,8 +493,8 @@ impl TempEntity {
                 };
                 writer.write_i16::<LittleEndian>(entity_id)?;
                 writer.write_u8(code as u8)?;
-                write_coord_vector3(writer, start)?;
-                write_coord_vector3(writer, end)?;
+                protocol.write_coord_vector3(writer, start)?;
+                protocol.write_coord_vector3(writer, end)?;
             }
         }
 
@@ -567,6 +586,86 @@ impl EntityUpdate {
             colormap: self.colormap.unwrap_or(baseline.colormap),
         }
     }
+
+    /// Returns the flags describing which fields of this update are present.
+    pub fn flags(&self) -> UpdateFlags {
+        let mut flags = UpdateFlags::SIGNAL;
+
+        let fields = [
+            (self.ent_id > 0xFF, UpdateFlags::LONG_ENTITY),
+            (self.model_id.is_some(), UpdateFlags::MODEL),
+            (self.frame_id.is_some(), UpdateFlags::FRAME),
+            (self.colormap.is_some(), UpdateFlags::COLORMAP),
+            (self.skin_id.is_some(), UpdateFlags::SKIN),
+            (self.effects.is_some(), UpdateFlags::EFFECTS),
+            (self.origin_x.is_some(), UpdateFlags::ORIGIN_X),
+            (self.pitch.is_some(), UpdateFlags::PITCH),
+            (self.origin_y.is_some(), UpdateFlags::ORIGIN_Y),
+            (self.yaw.is_some(), UpdateFlags::YAW),
+            (self.origin_z.is_some(), UpdateFlags::ORIGIN_Z),
+            (self.roll.is_some(), UpdateFlags::ROLL),
+            (self.no_lerp, UpdateFlags::NO_LERP),
+        ];
+
+        for (present, flag) in fields.iter() {
+            if *present {
+                flags |= *flag;
+            }
+        }
+
+        if flags.bits() > 0xFF {
+            flags |= UpdateFlags::MORE_BITS;
+        }
+
+        flags
+    }
+
+    /// Writes this update as a fast update command.
+    pub fn serialize<W>(&self, writer: &mut W, protocol: Protocol) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        let flags = self.flags();
+
+        // the low byte of the flags takes the place of the command code
+        writer.write_u8(flags.bits() as u8 | FAST_UPDATE_FLAG)?;
+        if flags.contains(UpdateFlags::MORE_BITS) {
+            writer.write_u8((flags.bits() >> 8) as u8)?;
+        }
+
+        if flags.contains(UpdateFlags::LONG_ENTITY) {
+            writer.write_u16::<LittleEndian>(self.ent_id)?;
+        } else {
+            writer.write_u8(self.ent_id as u8)?;
+        }
+
+        for byte in [self.model_id, self.frame_id, self.colormap, self.skin_id].iter() {
+            if let Some(b) = byte {
+                writer.write_u8(*b)?;
+            }
+        }
+
+        if let Some(effects) = self.effects {
+            writer.write_u8(effects.bits())?;
+        }
+
+        let components = [
+            (self.origin_x, self.pitch),
+            (self.origin_y, self.yaw),
+            (self.origin_z, self.roll),
+        ];
+        for (coord, angle) in components.iter() {
+            if let Some(c) = coord {
+                protocol.write_coord(writer, *c)?;
+            }
+
+            if let Some(a) = angle {
+                protocol.write_angle(writer, *a)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// A trait for in-game server and client network commands.
@@ -825,7 +924,7 @@ impl ServerCmd {
         code as u8
     }
 
-    pub fn deserialize<R>(reader: &mut R) -> Result<Option<ServerCmd>, NetError>
+    pub fn deserialize<R>(reader: &mut R, protocol: Protocol) -> Result<Option<ServerCmd>, NetError>
     where
         R: BufRead + ReadBytesExt,
     {
@@ -908,42 +1007,42 @@ impl ServerCmd {
 
             let origin_x;
             if update_flags.contains(UpdateFlags::ORIGIN_X) {
-                origin_x = Some(read_coord(reader)?);
+                origin_x = Some(protocol.read_coord(reader)?);
             } else {
                 origin_x = None;
             }
 
             let pitch;
             if update_flags.contains(UpdateFlags::PITCH) {
-                pitch = Some(read_angle(reader)?);
+                pitch = Some(protocol.read_angle(reader)?);
             } else {
                 pitch = None;
             }
 
             let origin_y;
             if update_flags.contains(UpdateFlags::ORIGIN_Y) {
-                origin_y = Some(read_coord(reader)?);
+                origin_y = Some(protocol.read_coord(reader)?);
             } else {
                 origin_y = None;
             }
 
             let yaw;
             if update_flags.contains(UpdateFlags::YAW) {
-                yaw = Some(read_angle(reader)?);
+                yaw = Some(protocol.read_angle(reader)?);
             } else {
                 yaw = None;
             }
 
             let origin_z;
             if update_flags.contains(UpdateFlags::ORIGIN_Z) {
-                origin_z = Some(read_coord(reader)?);
+                origin_z = Some(protocol.read_coord(reader)?);
             } else {
                 origin_z = None;
             }
 
             let roll;
             if update_flags.contains(UpdateFlags::ROLL) {
-                roll = Some(read_angle(reader)?);
+                roll = Some(protocol.read_angle(reader)?);
             } else {
                 roll = None;
             }
@@ -1035,9 +1134,9 @@ impl ServerCmd {
                 let channel = (entity_channel & 0b111) as i8;
                 let sound_id = reader.read_u8()?;
                 let position = Vector3::new(
-                    read_coord(reader)?,
-                    read_coord(reader)?,
-                    read_coord(reader)?,
+                    protocol.read_coord(reader)?,
+                    protocol.read_coord(reader)?,
+                    protocol.read_coord(reader)?,
                 );
 
                 ServerCmd::Sound {
@@ -1075,9 +1174,9 @@ impl ServerCmd {
 
             ServerCmdCode::SetAngle => {
                 let angles = Vector3::new(
-                    read_angle(reader)?,
-                    read_angle(reader)?,
-                    read_angle(reader)?,
+                    protocol.read_angle(reader)?,
+                    protocol.read_angle(reader)?,
+                    protocol.read_angle(reader)?,
                 );
 
                 ServerCmd::SetAngle { angles }
@@ -1286,7 +1385,7 @@ impl ServerCmd {
             }
 
             ServerCmdCode::Particle => {
-                let origin = read_coord_vector3(reader)?;
+                let origin = protocol.read_coord_vector3(reader)?;
 
                 let mut direction = Vector3::zero();
                 for i in 0..3 {
@@ -1307,7 +1406,7 @@ impl ServerCmd {
             ServerCmdCode::Damage => {
                 let armor = reader.read_u8()?;
                 let blood = reader.read_u8()?;
-                let source = read_coord_vector3(reader)?;
+                let source = protocol.read_coord_vector3(reader)?;
 
                 ServerCmd::Damage {
                     armor,
@@ -1325,8 +1424,8 @@ impl ServerCmd {
                 let mut origin = Vector3::zero();
                 let mut angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
                 for i in 0..3 {
-                    origin[i] = read_coord(reader)?;
-                    angles[i] = read_angle(reader)?;
+                    origin[i] = protocol.read_coord(reader)?;
+                    angles[i] = protocol.read_angle(reader)?;
                 }
 
                 ServerCmd::SpawnStatic {
@@ -1349,8 +1448,8 @@ impl ServerCmd {
                 let mut origin = Vector3::zero();
                 let mut angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
                 for i in 0..3 {
-                    origin[i] = read_coord(reader)?;
-                    angles[i] = read_angle(reader)?;
+                    origin[i] = protocol.read_coord(reader)?;
+                    angles[i] = protocol.read_angle(reader)?;
                 }
 
                 ServerCmd::SpawnBaseline {
@@ -1365,7 +1464,7 @@ impl ServerCmd {
             }
 
             ServerCmdCode::TempEntity => {
-                let temp_entity = TempEntity::read_temp_entity(reader)?;
+                let temp_entity = TempEntity::read_temp_entity(reader, protocol)?;
 
                 ServerCmd::TempEntity { temp_entity }
             }
@@ -1408,7 +1507,7 @@ impl ServerCmd {
             ServerCmdCode::FoundSecret => ServerCmd::FoundSecret,
 
             ServerCmdCode::SpawnStaticSound => {
-                let origin = read_coord_vector3(reader)?;
+                let origin = protocol.read_coord_vector3(reader)?;
                 let sound_id = reader.read_u8()?;
                 let volume = reader.read_u8()?;
                 let attenuation = reader.read_u8()?;
@@ -1453,10 +1552,15 @@ impl ServerCmd {
         Ok(Some(cmd))
     }
 
-    pub fn serialize<W>(&self, writer: &mut W) -> Result<(), NetError>
+    pub fn serialize<W>(&self, writer: &mut W, protocol: Protocol) -> Result<(), NetError>
     where
         W: WriteBytesExt,
     {
+        // fast updates have no command code
+        if let ServerCmd::FastUpdate(ref update) = *self {
+            return update.serialize(writer, protocol);
+        }
+
         writer.write_u8(self.code())?;
 
         match *self {
@@ -1510,7 +1614,7 @@ impl ServerCmd {
                 writer.write_u8(sound_id)?;
 
                 for component in 0..3 {
-                    write_coord(writer, position[component])?;
+                    protocol.write_coord(writer, position[component])?;
                 }
             }
 
@@ -1526,7 +1630,7 @@ impl ServerCmd {
                 writer.write_u8(0)?;
             }
 
-            ServerCmd::SetAngle { angles } => write_angle_vector3(writer, angles)?,
+            ServerCmd::SetAngle { angles } => protocol.write_angle_vector3(writer, angles)?,
 
             ServerCmd::ServerInfo {
                 protocol_version,
@@ -1712,7 +1816,7 @@ impl ServerCmd {
                 count,
                 color,
             } => {
-                write_coord_vector3(writer, origin)?;
+                protocol.write_coord_vector3(writer, origin)?;
 
                 for i in 0..3 {
                     writer.write_i8(match direction[i] * PARTICLE_DIRECTION_WRITE_FACTOR {
@@ -1733,7 +1837,7 @@ impl ServerCmd {
             } => {
                 writer.write_u8(armor)?;
                 writer.write_u8(blood)?;
-                write_coord_vector3(writer, source)?;
+                protocol.write_coord_vector3(writer, source)?;
             }
 
             ServerCmd::SpawnStatic {
@@ -1750,8 +1854,8 @@ impl ServerCmd {
                 writer.write_u8(skin_id)?;
 
                 for i in 0..3 {
-                    write_coord(writer, origin[i])?;
-                    write_angle(writer, angles[i])?;
+                    protocol.write_coord(writer, origin[i])?;
+                    protocol.write_angle(writer, angles[i])?;
                 }
             }
 
@@ -1771,13 +1875,13 @@ impl ServerCmd {
                 writer.write_u8(skin_id)?;
 
                 for i in 0..3 {
-                    write_coord(writer, origin[i])?;
-                    write_angle(writer, angles[i])?;
+                    protocol.write_coord(writer, origin[i])?;
+                    protocol.write_angle(writer, angles[i])?;
                 }
             }
 
             ServerCmd::TempEntity { ref temp_entity } => {
-                temp_entity.write_temp_entity(writer)?;
+                temp_entity.write_temp_entity(writer, protocol)?;
             }
 
             ServerCmd::SetPause { paused } => {
@@ -1804,7 +1908,7 @@ impl ServerCmd {
                 volume,
                 attenuation,
             } => {
-                write_coord_vector3(writer, origin)?;
+                protocol.write_coord_vector3(writer, origin)?;
                 writer.write_u8(sound_id)?;
                 writer.write_u8(volume)?;
                 writer.write_u8(attenuation)?;
@@ -1829,8 +1933,7 @@ impl ServerCmd {
                 writer.write_u8(0)?;
             }
 
-            // TODO
-            ServerCmd::FastUpdate(_) => unimplemented!(),
+            ServerCmd::FastUpdate(_) => unreachable!(),
         }
 
         Ok(())
@@ -1876,7 +1979,7 @@ impl ClientCmd {
         }
     }
 
-    pub fn deserialize<R>(reader: &mut R) -> Result<ClientCmd, NetError>
+    pub fn deserialize<R>(reader: &mut R, protocol: Protocol) -> Result<ClientCmd, NetError>
     where
         R: ReadBytesExt + BufRead,
     {
@@ -1898,9 +2001,9 @@ impl ClientCmd {
             ClientCmdCode::Move => {
                 let send_time = engine::duration_from_f32(reader.read_f32::<LittleEndian>()?);
                 let angles = Vector3::new(
-                    read_angle(reader)?,
-                    read_angle(reader)?,
-                    read_angle(reader)?,
+                    protocol.read_angle(reader)?,
+                    protocol.read_angle(reader)?,
+                    protocol.read_angle(reader)?,
                 );
                 let fwd_move = reader.read_i16::<LittleEndian>()?;
                 let side_move = reader.read_i16::<LittleEndian>()?;
@@ -1935,7 +2038,7 @@ impl ClientCmd {
         Ok(cmd)
     }
 
-    pub fn serialize<W>(&self, writer: &mut W) -> Result<(), NetError>
+    pub fn serialize<W>(&self, writer: &mut W, protocol: Protocol) -> Result<(), NetError>
     where
         W: WriteBytesExt,
     {
@@ -1955,7 +2058,7 @@ impl ClientCmd {
                 impulse,
             } => {
                 writer.write_f32::<LittleEndian>(engine::duration_to_f32(send_time))?;
-                write_angle_vector3(writer, angles)?;
+                protocol.write_angle_vector3(writer, angles)?;
                 writer.write_i16::<LittleEndian>(fwd_move)?;
                 writer.write_i16::<LittleEndian>(side_move)?;
                 writer.write_i16::<LittleEndian>(up_move)?;
@@ -2308,80 +2411,6 @@ impl QSocket {
     }
 }
 
-fn read_coord<R>(reader: &mut R) -> Result<f32, NetError>
-where
-    R: BufRead + ReadBytesExt,
-{
-    Ok(reader.read_i16::<LittleEndian>()? as f32 / 8.0)
-}
-
-fn read_coord_vector3<R>(reader: &mut R) -> Result<Vector3<f32>, NetError>
-where
-    R: BufRead + ReadBytesExt,
-{
-    Ok(Vector3::new(
-        read_coord(reader)?,
-        read_coord(reader)?,
-        read_coord(reader)?,
-    ))
-}
-
-fn write_coord<W>(writer: &mut W, coord: f32) -> Result<(), NetError>
-where
-    W: WriteBytesExt,
-{
-    writer.write_i16::<LittleEndian>((coord * 8.0) as i16)?;
-    Ok(())
-}
-
-fn write_coord_vector3<W>(writer: &mut W, coords: Vector3<f32>) -> Result<(), NetError>
-where
-    W: WriteBytesExt,
-{
-    for coord in &coords[..] {
-        write_coord(writer, *coord)?;
-    }
-
-    Ok(())
-}
-
-fn read_angle<R>(reader: &mut R) -> Result<Deg<f32>, NetError>
-where
-    R: BufRead + ReadBytesExt,
-{
-    Ok(Deg(reader.read_i8()? as f32 * (360.0 / 256.0)))
-}
-
-fn read_angle_vector3<R>(reader: &mut R) -> Result<Vector3<Deg<f32>>, NetError>
-where
-    R: BufRead + ReadBytesExt,
-{
-    Ok(Vector3::new(
-        read_angle(reader)?,
-        read_angle(reader)?,
-        read_angle(reader)?,
-    ))
-}
-
-fn write_angle<W>(writer: &mut W, angle: Deg<f32>) -> Result<(), NetError>
-where
-    W: WriteBytesExt,
-{
-    writer.write_u8(((angle.0 as i32 * 256 / 360) & 0xFF) as u8)?;
-    Ok(())
-}
-
-fn write_angle_vector3<W>(writer: &mut W, angles: Vector3<Deg<f32>>) -> Result<(), NetError>
-where
-    W: WriteBytesExt,
-{
-    for angle in &angles[..] {
-        write_angle(writer, *angle)?;
-    }
-
-    Ok(())
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -2396,9 +2425,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2408,9 +2439,11 @@ mod test {
         let src = ServerCmd::Version { version: 42 };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2420,9 +2453,11 @@ mod test {
         let src = ServerCmd::SetView { ent_id: 17 };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2432,9 +2467,11 @@ mod test {
         let src = ServerCmd::Time { time: 23.07 };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2446,9 +2483,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2460,9 +2499,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2479,9 +2520,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2494,9 +2537,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2509,9 +2554,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2524,9 +2571,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2539,9 +2588,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2554,9 +2605,11 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2565,9 +2618,11 @@ mod test {
     fn test_server_cmd_set_pause_read_write_eq() {
         let src = ServerCmd::SetPause { paused: true };
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2578,9 +2633,11 @@ mod test {
             stage: SignOnStage::Begin,
         };
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2591,9 +2648,11 @@ mod test {
             text: String::from("Center print test"),
         };
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2604,9 +2663,11 @@ mod test {
             text: String::from("Finale test"),
         };
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2615,9 +2676,11 @@ mod test {
     fn test_server_cmd_cd_track_read_write_eq() {
         let src = ServerCmd::CdTrack { track: 5, loop_: 1 };
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2628,9 +2691,38 @@ mod test {
             text: String::from("Cutscene test"),
         };
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(src, dst);
+    }
+
+    #[test]
+    fn test_server_cmd_fast_update_read_write_eq() {
+        let src = ServerCmd::FastUpdate(EntityUpdate {
+            ent_id: 300,
+            model_id: Some(12),
+            frame_id: None,
+            colormap: Some(1),
+            skin_id: None,
+            effects: Some(EntityEffects::MUZZLE_FLASH),
+            origin_x: Some(16.0),
+            pitch: None,
+            origin_y: Some(-32.5),
+            yaw: Some(Deg(90.0)),
+            origin_z: None,
+            roll: None,
+            no_lerp: true,
+        });
+        let mut packet = Vec::new();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+        let dst = ServerCmd::deserialize(&mut reader, Protocol::NETQUAKE)
+            .unwrap()
+            .unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2641,9 +2733,9 @@ mod test {
             cmd: String::from("StringCmd test"),
         };
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ClientCmd::deserialize(&mut reader).unwrap();
+        let dst = ClientCmd::deserialize(&mut reader, Protocol::NETQUAKE).unwrap();
 
         assert_eq!(src, dst);
     }
@@ -2662,9 +2754,9 @@ mod test {
         };
 
         let mut packet = Vec::new();
-        src.serialize(&mut packet).unwrap();
+        src.serialize(&mut packet, Protocol::NETQUAKE).unwrap();
         let mut reader = BufReader::new(packet.as_slice());
-        let dst = ClientCmd::deserialize(&mut reader).unwrap();
+        let dst = ClientCmd::deserialize(&mut reader, Protocol::NETQUAKE).unwrap();
 
         assert_eq!(src, dst);
     }
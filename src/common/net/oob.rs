@@ -0,0 +1,459 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Connectionless ("out-of-band") packets.
+//!
+//! These packets begin with four `0xFF` bytes followed by a line of text, as in QuakeWorld and
+//! its descendants. They're used to query servers without connecting to them, and to issue remote
+//! console commands as QuakeWorld's `rcon` does.
+
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use crate::common::net::{connect::RequestRcon, NetError, MAX_MESSAGE};
+
+use chrono::Duration;
+
+/// The header which marks a packet as connectionless.
+pub const OOB_HEADER: [u8; 4] = [0xFF; 4];
+
+// response prefixes
+const A2A_ACK: u8 = b'l';
+const A2C_PRINT: u8 = b'n';
+const INFO_RESPONSE: &str = "infoResponse\n";
+
+/// Returns true if `packet` is a connectionless packet.
+pub fn is_oob(packet: &[u8]) -> bool {
+    packet.starts_with(&OOB_HEADER)
+}
+
+// strips the header from a connectionless packet and returns its text
+fn oob_text(packet: &[u8]) -> Result<String, NetError> {
+    if !is_oob(packet) {
+        return Err(NetError::InvalidData(String::from(
+            "connectionless packet header",
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&packet[OOB_HEADER.len()..]).into_owned())
+}
+
+fn to_oob_bytes<S>(text: S) -> Vec<u8>
+where
+    S: AsRef<str>,
+{
+    let mut packet = OOB_HEADER.to_vec();
+    packet.extend_from_slice(text.as_ref().as_bytes());
+    packet
+}
+
+/// A connectionless request sent to a server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OobRequest {
+    /// Checks that the server is reachable. Answered with `OobResponse::Ack`.
+    Ping,
+
+    /// Requests a printable summary of the server and its players.
+    Status,
+
+    /// Requests the server's info string. The challenge is echoed back in the response.
+    GetInfo { challenge: Option<String> },
+
+    /// Executes a console command on the server if `password` matches its `rcon_password`.
+    Rcon { password: String, command: String },
+}
+
+impl OobRequest {
+    /// Generates the byte representation of this request for transmission.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        to_oob_bytes(match *self {
+            OobRequest::Ping => String::from("ping"),
+            OobRequest::Status => String::from("status"),
+            OobRequest::GetInfo {
+                challenge: Some(ref c),
+            } => format!("getinfo {}", c),
+            OobRequest::GetInfo { challenge: None } => String::from("getinfo"),
+            OobRequest::Rcon {
+                ref password,
+                ref command,
+            } => format!("rcon {} {}", password, command),
+        })
+    }
+
+    /// Parses a request from a received packet.
+    pub fn from_bytes(packet: &[u8]) -> Result<OobRequest, NetError> {
+        OobRequest::parse(&oob_text(packet)?)
+    }
+
+    /// Parses a request from its text, without the connectionless header.
+    pub fn parse(text: &str) -> Result<OobRequest, NetError> {
+        let text = text.trim_end_matches(|c| c == '\n' || c == '\0');
+        let mut words = text.splitn(2, ' ');
+        let name = words.next().unwrap_or("");
+        let args = words.next().map(str::trim).unwrap_or("");
+
+        match name {
+            "ping" => Ok(OobRequest::Ping),
+            "status" => Ok(OobRequest::Status),
+            "getinfo" => Ok(OobRequest::GetInfo {
+                challenge: match args {
+                    "" => None,
+                    c => Some(c.to_owned()),
+                },
+            }),
+            "rcon" => {
+                let mut rcon_args = args.splitn(2, ' ');
+                Ok(OobRequest::Rcon {
+                    password: rcon_args.next().unwrap_or("").to_owned(),
+                    command: rcon_args.next().unwrap_or("").to_owned(),
+                })
+            }
+            _ => Err(NetError::InvalidData(format!(
+                "connectionless request \"{}\"",
+                name
+            ))),
+        }
+    }
+}
+
+/// A connectionless response sent by a server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OobResponse {
+    /// Answers a `Ping`.
+    Ack,
+
+    /// Text to be printed by the client, in response to `Status` or `Rcon`.
+    Print(String),
+
+    /// The server's info string as a list of key-value pairs, in response to `GetInfo`.
+    Info {
+        challenge: Option<String>,
+        info: Vec<(String, String)>,
+    },
+}
+
+impl OobResponse {
+    /// Generates the byte representation of this response for transmission.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            OobResponse::Ack => to_oob_bytes((A2A_ACK as char).to_string()),
+            OobResponse::Print(ref text) => to_oob_bytes(format!("{}{}", A2C_PRINT as char, text)),
+            OobResponse::Info {
+                ref challenge,
+                ref info,
+            } => {
+                let mut text = String::from(INFO_RESPONSE);
+                let challenge = challenge.as_ref().map(|c| ("challenge", c.as_str()));
+                for (key, value) in challenge
+                    .into_iter()
+                    .chain(info.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+                {
+                    text.push('\\');
+                    text.push_str(key);
+                    text.push('\\');
+                    text.push_str(value);
+                }
+
+                to_oob_bytes(text)
+            }
+        }
+    }
+
+    /// Parses a response from a received packet.
+    pub fn from_bytes(packet: &[u8]) -> Result<OobResponse, NetError> {
+        let text = oob_text(packet)?;
+
+        if text.starts_with(INFO_RESPONSE) {
+            let mut challenge = None;
+            let mut info = Vec::new();
+
+            // the info string begins with a separator, so skip the empty first field
+            let mut fields = text[INFO_RESPONSE.len()..].split('\\').skip(1);
+            while let Some(key) = fields.next() {
+                let value = fields.next().unwrap_or("").to_owned();
+                match key {
+                    "challenge" => challenge = Some(value),
+                    k => info.push((k.to_owned(), value)),
+                }
+            }
+
+            return Ok(OobResponse::Info { challenge, info });
+        }
+
+        match text.as_bytes().first() {
+            Some(&A2A_ACK) => Ok(OobResponse::Ack),
+            Some(&A2C_PRINT) => Ok(OobResponse::Print(text[1..].to_owned())),
+            _ => Err(NetError::InvalidData(format!(
+                "connectionless response \"{}\"",
+                text
+            ))),
+        }
+    }
+}
+
+/// The server-side state needed to answer connectionless requests.
+pub trait OobServer {
+    /// Returns the server's info string, e.g. `hostname`, `mapname` and `clients`.
+    fn info(&self) -> Vec<(String, String)>;
+
+    /// Returns a printable summary of the server and its players.
+    fn status(&self) -> String;
+
+    /// Returns the value of `rcon_password`. Remote commands are refused if it's empty.
+    fn rcon_password(&self) -> String;
+
+    /// Executes a console command and returns its output.
+    fn execute(&mut self, command: &str) -> String;
+}
+
+/// Answers a connectionless request.
+///
+/// Returns `None` if the request should be ignored.
+pub fn handle_request<S>(server: &mut S, request: OobRequest) -> Option<OobResponse>
+where
+    S: OobServer,
+{
+    match request {
+        OobRequest::Ping => Some(OobResponse::Ack),
+        OobRequest::Status => Some(OobResponse::Print(server.status())),
+        OobRequest::GetInfo { challenge } => Some(OobResponse::Info {
+            challenge,
+            info: server.info(),
+        }),
+        OobRequest::Rcon { password, command } => {
+            let request = RequestRcon { password, command };
+            if request.authenticate(server.rcon_password()) {
+                Some(OobResponse::Print(server.execute(&request.command)))
+            } else {
+                Some(OobResponse::Print(String::from("Bad rcon_password.\n")))
+            }
+        }
+    }
+}
+
+/// A socket which sends and receives connectionless packets.
+pub struct OobSocket {
+    socket: UdpSocket,
+}
+
+impl OobSocket {
+    pub fn bind<A>(local: A) -> Result<OobSocket, NetError>
+    where
+        A: ToSocketAddrs,
+    {
+        let socket = UdpSocket::bind(local)?;
+
+        Ok(OobSocket { socket })
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NetError> {
+        self.socket.set_nonblocking(nonblocking)?;
+        Ok(())
+    }
+
+    /// Send an `OobRequest` to the server at the specified address.
+    pub fn send_request(&self, request: &OobRequest, remote: SocketAddr) -> Result<(), NetError> {
+        self.socket.send_to(&request.to_bytes(), remote)?;
+        Ok(())
+    }
+
+    /// Send an `OobResponse` to the client at the specified address.
+    pub fn send_response(
+        &self,
+        response: &OobResponse,
+        remote: SocketAddr,
+    ) -> Result<(), NetError> {
+        self.socket.send_to(&response.to_bytes(), remote)?;
+        Ok(())
+    }
+
+    /// Receives a request and returns it along with its remote address.
+    pub fn recv_request(&self) -> Result<(OobRequest, SocketAddr), NetError> {
+        let mut recv_buf = [0u8; MAX_MESSAGE];
+        let (len, remote) = self.socket.recv_from(&mut recv_buf)?;
+        let request = OobRequest::from_bytes(&recv_buf[..len])?;
+
+        Ok((request, remote))
+    }
+
+    /// Receive an `OobResponse` from a server.
+    ///
+    /// If `timeout` is not `None`, the operation times out after the specified duration and the
+    /// function returns `None`.
+    pub fn recv_response(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<(OobResponse, SocketAddr)>, NetError> {
+        let mut recv_buf = [0u8; MAX_MESSAGE];
+
+        // if a timeout was specified, apply it for this recv
+        self.socket
+            .set_read_timeout(timeout.map(|d| d.to_std().unwrap()))?;
+        let (len, remote) = match self.socket.recv_from(&mut recv_buf) {
+            Err(e) => match e.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => return Ok(None),
+                _ => return Err(NetError::from(e)),
+            },
+            Ok(ret) => ret,
+        };
+        self.socket.set_read_timeout(None)?;
+
+        let response = OobResponse::from_bytes(&recv_buf[..len])?;
+
+        Ok(Some((response, remote)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestServer {
+        rcon_password: String,
+        executed: Vec<String>,
+    }
+
+    impl TestServer {
+        fn new() -> TestServer {
+            TestServer {
+                rcon_password: String::new(),
+                executed: Vec::new(),
+            }
+        }
+    }
+
+    impl OobServer for TestServer {
+        fn info(&self) -> Vec<(String, String)> {
+            vec![(String::from("mapname"), String::from("e1m1"))]
+        }
+
+        fn status(&self) -> String {
+            String::from("map: e1m1\n")
+        }
+
+        fn rcon_password(&self) -> String {
+            self.rcon_password.clone()
+        }
+
+        fn execute(&mut self, command: &str) -> String {
+            self.executed.push(command.to_owned());
+            format!("executed {}\n", command)
+        }
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let requests = vec![
+            OobRequest::Ping,
+            OobRequest::Status,
+            OobRequest::GetInfo { challenge: None },
+            OobRequest::GetInfo {
+                challenge: Some(String::from("1234")),
+            },
+            OobRequest::Rcon {
+                password: String::from("hunter2"),
+                command: String::from("map e1m2"),
+            },
+        ];
+
+        for request in requests {
+            let bytes = request.to_bytes();
+            assert!(is_oob(&bytes));
+            assert_eq!(OobRequest::from_bytes(&bytes).unwrap(), request);
+        }
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let responses = vec![
+            OobResponse::Ack,
+            OobResponse::Print(String::from("hello\n")),
+            OobResponse::Info {
+                challenge: Some(String::from("1234")),
+                info: vec![
+                    (String::from("hostname"), String::from("richter")),
+                    (String::from("mapname"), String::from("e1m1")),
+                ],
+            },
+        ];
+
+        for response in responses {
+            let bytes = response.to_bytes();
+            assert_eq!(OobResponse::from_bytes(&bytes).unwrap(), response);
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_requires_header() {
+        assert!(OobRequest::from_bytes(b"ping").is_err());
+        assert!(OobResponse::from_bytes(b"l").is_err());
+    }
+
+    #[test]
+    fn test_handle_request() {
+        let mut server = TestServer::new();
+        assert_eq!(
+            handle_request(&mut server, OobRequest::Ping),
+            Some(OobResponse::Ack)
+        );
+        assert_eq!(
+            handle_request(&mut server, OobRequest::Status),
+            Some(OobResponse::Print(String::from("map: e1m1\n")))
+        );
+        assert_eq!(
+            handle_request(
+                &mut server,
+                OobRequest::GetInfo {
+                    challenge: Some(String::from("1234")),
+                },
+            ),
+            Some(OobResponse::Info {
+                challenge: Some(String::from("1234")),
+                info: vec![(String::from("mapname"), String::from("e1m1"))],
+            })
+        );
+    }
+
+    #[test]
+    fn test_handle_request_rcon() {
+        let mut server = TestServer::new();
+        let request = OobRequest::Rcon {
+            password: String::new(),
+            command: String::from("quit"),
+        };
+
+        // an empty rcon_password disables remote commands
+        assert_eq!(
+            handle_request(&mut server, request.clone()),
+            Some(OobResponse::Print(String::from("Bad rcon_password.\n")))
+        );
+        assert!(server.executed.is_empty());
+
+        server.rcon_password = String::from("hunter2");
+        let request = OobRequest::parse("rcon hunter2 quit").unwrap();
+        assert_eq!(
+            handle_request(&mut server, request),
+            Some(OobResponse::Print(String::from("executed quit\n")))
+        );
+        assert_eq!(server.executed, vec![String::from("quit")]);
+    }
+}
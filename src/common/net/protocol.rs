@@ -0,0 +1,249 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Encodings of coordinates and angles, which differ between network protocols.
+
+use std::io::BufRead;
+
+use crate::common::net::{NetError, PROTOCOL_VERSION};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use cgmath::{Deg, Vector3};
+
+/// The encoding of angles on the wire.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AngleEncoding {
+    /// 8-bit angles in units of 360/256 degrees.
+    Byte,
+
+    /// 16-bit angles in units of 360/65536 degrees.
+    Short,
+
+    /// 32-bit floating-point degrees.
+    Float,
+}
+
+/// The encoding of world coordinates on the wire.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CoordEncoding {
+    /// 16-bit 13.3 fixed-point coordinates.
+    Short,
+
+    /// 24-bit coordinates, a 16-bit integer part followed by an 8-bit fraction.
+    Fixed24,
+
+    /// 32-bit floating-point coordinates.
+    Float,
+}
+
+/// Describes how a network protocol encodes coordinates and angles.
+///
+/// Both the client parser and the server writer go through a `Protocol` so that supporting a new
+/// protocol only means describing its encodings.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Protocol {
+    /// The version number sent in `svc_serverinfo` and `svc_version`.
+    pub version: i32,
+
+    pub coord: CoordEncoding,
+    pub angle: AngleEncoding,
+}
+
+impl Protocol {
+    /// The original NetQuake protocol.
+    pub const NETQUAKE: Protocol = Protocol {
+        version: PROTOCOL_VERSION as i32,
+        coord: CoordEncoding::Short,
+        angle: AngleEncoding::Byte,
+    };
+
+    /// Returns the protocol with the given version number, or `None` if it isn't supported.
+    pub fn from_version(version: i32) -> Option<Protocol> {
+        match version {
+            v if v == Protocol::NETQUAKE.version => Some(Protocol::NETQUAKE),
+            _ => None,
+        }
+    }
+
+    pub fn read_coord<R>(&self, reader: &mut R) -> Result<f32, NetError>
+    where
+        R: BufRead + ReadBytesExt,
+    {
+        Ok(match self.coord {
+            CoordEncoding::Short => reader.read_i16::<LittleEndian>()? as f32 / 8.0,
+            CoordEncoding::Fixed24 => {
+                let int = reader.read_i16::<LittleEndian>()? as f32;
+                int + reader.read_u8()? as f32 / 255.0
+            }
+            CoordEncoding::Float => reader.read_f32::<LittleEndian>()?,
+        })
+    }
+
+    pub fn read_coord_vector3<R>(&self, reader: &mut R) -> Result<Vector3<f32>, NetError>
+    where
+        R: BufRead + ReadBytesExt,
+    {
+        Ok(Vector3::new(
+            self.read_coord(reader)?,
+            self.read_coord(reader)?,
+            self.read_coord(reader)?,
+        ))
+    }
+
+    pub fn write_coord<W>(&self, writer: &mut W, coord: f32) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        match self.coord {
+            CoordEncoding::Short => writer.write_i16::<LittleEndian>((coord * 8.0) as i16)?,
+            CoordEncoding::Fixed24 => {
+                let int = coord.floor();
+                writer.write_i16::<LittleEndian>(int as i16)?;
+                writer.write_u8(((coord - int) * 255.0) as u8)?;
+            }
+            CoordEncoding::Float => writer.write_f32::<LittleEndian>(coord)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn write_coord_vector3<W>(
+        &self,
+        writer: &mut W,
+        coords: Vector3<f32>,
+    ) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        for coord in &coords[..] {
+            self.write_coord(writer, *coord)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_angle<R>(&self, reader: &mut R) -> Result<Deg<f32>, NetError>
+    where
+        R: BufRead + ReadBytesExt,
+    {
+        Ok(Deg(match self.angle {
+            AngleEncoding::Byte => reader.read_i8()? as f32 * (360.0 / 256.0),
+            AngleEncoding::Short => reader.read_i16::<LittleEndian>()? as f32 * (360.0 / 65536.0),
+            AngleEncoding::Float => reader.read_f32::<LittleEndian>()?,
+        }))
+    }
+
+    pub fn read_angle_vector3<R>(&self, reader: &mut R) -> Result<Vector3<Deg<f32>>, NetError>
+    where
+        R: BufRead + ReadBytesExt,
+    {
+        Ok(Vector3::new(
+            self.read_angle(reader)?,
+            self.read_angle(reader)?,
+            self.read_angle(reader)?,
+        ))
+    }
+
+    pub fn write_angle<W>(&self, writer: &mut W, angle: Deg<f32>) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        match self.angle {
+            AngleEncoding::Byte => writer.write_u8(((angle.0 as i32 * 256 / 360) & 0xFF) as u8)?,
+            AngleEncoding::Short => writer
+                .write_u16::<LittleEndian>(((angle.0 * 65536.0 / 360.0) as i32 & 0xFFFF) as u16)?,
+            AngleEncoding::Float => writer.write_f32::<LittleEndian>(angle.0)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn write_angle_vector3<W>(
+        &self,
+        writer: &mut W,
+        angles: Vector3<Deg<f32>>,
+    ) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        for angle in &angles[..] {
+            self.write_angle(writer, *angle)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Protocol {
+    fn default() -> Protocol {
+        Protocol::NETQUAKE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::BufReader;
+
+    fn protocol(coord: CoordEncoding, angle: AngleEncoding) -> Protocol {
+        Protocol {
+            version: 0,
+            coord,
+            angle,
+        }
+    }
+
+    #[test]
+    fn test_coord_read_write() {
+        for &(coord, value, expected, size) in &[
+            (CoordEncoding::Short, 1234.5, 1234.5, 2),
+            (CoordEncoding::Short, -8.125, -8.125, 2),
+            (CoordEncoding::Fixed24, 1234.5, 1234.0 + 127.0 / 255.0, 3),
+            (CoordEncoding::Float, 1234.567, 1234.567, 4),
+        ] {
+            let protocol = protocol(coord, AngleEncoding::Byte);
+            let mut packet = Vec::new();
+            protocol.write_coord(&mut packet, value).unwrap();
+            assert_eq!(packet.len(), size);
+
+            let mut reader = BufReader::new(packet.as_slice());
+            assert_eq!(protocol.read_coord(&mut reader).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_angle_read_write() {
+        for &(angle, size) in &[
+            (AngleEncoding::Byte, 1),
+            (AngleEncoding::Short, 2),
+            (AngleEncoding::Float, 4),
+        ] {
+            let protocol = protocol(CoordEncoding::Short, angle);
+            let src = Vector3::new(Deg(90.0), Deg(-90.0), Deg(0.0));
+            let mut packet = Vec::new();
+            protocol.write_angle_vector3(&mut packet, src).unwrap();
+            assert_eq!(packet.len(), 3 * size);
+
+            let mut reader = BufReader::new(packet.as_slice());
+            assert_eq!(protocol.read_angle_vector3(&mut reader).unwrap(), src);
+        }
+    }
+}
@@ -125,4 +125,9 @@ impl Pak {
     pub fn iter<'a>(&self) -> Iter<String, impl AsRef<[u8]>> {
         self.0.iter()
     }
+
+    /// Returns the number of bytes used by the file names and contents.
+    pub fn memory_usage(&self) -> usize {
+        self.0.iter().map(|(k, v)| k.len() + v.len()).sum()
+    }
 }
@@ -15,13 +15,13 @@
 // DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::common::parse::quoted;
+use std::borrow::Cow;
 
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{line_ending, not_line_ending, one_of, space0},
-    combinator::{opt, recognize},
+    combinator::{map, opt, recognize},
     multi::{many0, many1},
     sequence::{delimited, preceded, terminated, tuple},
 };
@@ -94,18 +94,111 @@ pub fn basic_arg(input: &str) -> nom::IResult<&str, &str> {
     }
 }
 
+/// Match a quoted argument, returning its contents.
+///
+/// Within the quotes, `\"` stands for a quote and `\\` for a backslash. Any other backslash is
+/// kept as is, so paths don't need escaping. The contents may be empty.
+pub fn quoted_arg(input: &str) -> nom::IResult<&str, Cow<str>> {
+    let fail = || Err(nom::Err::Error((input, nom::error::ErrorKind::Escaped)));
+
+    let contents = match input.strip_prefix('"') {
+        Some(c) => c,
+        None => return fail(),
+    };
+
+    let mut escaped = false;
+    let mut chars = contents.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                let (matched, rest) = (&contents[..i], &contents[i + 1..]);
+                let value = if escaped {
+                    Cow::Owned(unescape(matched))
+                } else {
+                    Cow::Borrowed(matched)
+                };
+
+                return Ok((rest, value));
+            }
+
+            '\\' if contents[i + 1..].starts_with(|n: char| n == '"' || n == '\\') => {
+                escaped = true;
+                chars.next();
+            }
+
+            c if !c.is_ascii() || c.is_ascii_control() => break,
+            _ => (),
+        }
+    }
+
+    // no closing quote before the end of the line
+    fail()
+}
+
+// resolves the escapes accepted by `quoted_arg`
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next)) if next == '"' || next == '\\' => {
+                unescaped.push(next);
+                chars.next();
+            }
+
+            (c, _) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Returns whether `text` must be quoted to be read back as a single argument.
+pub fn needs_quotes<S>(text: S) -> bool
+where
+    S: AsRef<str>,
+{
+    let text = text.as_ref();
+    text.is_empty()
+        || text
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == ';' || c == '\\')
+        || text.starts_with("//")
+}
+
+/// Returns `text` as a quoted argument, escaping any quotes and backslashes.
+///
+/// This is the inverse of `quoted_arg`, for writing scripts which are read back by the console.
+pub fn quote<S>(text: S) -> String
+where
+    S: AsRef<str>,
+{
+    let text = text.as_ref();
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+
+    quoted
+}
+
 /// Match a basic argument or a quoted string.
-pub fn arg(input: &str) -> nom::IResult<&str, &str> {
-    alt((quoted, basic_arg))(input)
+pub fn arg(input: &str) -> nom::IResult<&str, Cow<str>> {
+    alt((quoted_arg, map(basic_arg, Cow::Borrowed)))(input)
 }
 
 /// Match a command terminator.
 ///
 /// Commands can be terminated by either:
-/// - A semicolon (`";"`), or
+/// - A semicolon (`";"`), optionally preceded by non-newline whitespace, or
 /// - An empty line (see `empty_line`)
 pub fn command_terminator(input: &str) -> nom::IResult<&str, &str> {
-    alt((empty_line, tag(";")))(input)
+    alt((empty_line, recognize(preceded(space0, tag(";")))))(input)
 }
 
 /// Match a single command.
@@ -114,11 +207,16 @@ pub fn command_terminator(input: &str) -> nom::IResult<&str, &str> {
 /// - Zero or more leading non-newline whitespace characters
 /// - One or more arguments, separated by non-newline whitespace characters
 /// - A command terminator (see `command_terminator`)
-pub fn command(input: &str) -> nom::IResult<&str, Vec<&str>> {
+pub fn command(input: &str) -> nom::IResult<&str, Vec<Cow<str>>> {
     terminated(many1(preceded(space0, arg)), command_terminator)(input)
 }
 
-pub fn commands(input: &str) -> nom::IResult<&str, Vec<Vec<&str>>> {
+/// Match the first command in a block of text, skipping any leading empty lines.
+pub fn next_command(input: &str) -> nom::IResult<&str, Vec<Cow<str>>> {
+    preceded(many0(empty_line), command)(input)
+}
+
+pub fn commands(input: &str) -> nom::IResult<&str, Vec<Vec<Cow<str>>>> {
     delimited(
         many0(empty_line),
         many0(terminated(command, many0(empty_line))),
@@ -160,34 +258,97 @@ mod test {
         assert_eq!(result, Ok((";", "semicolon_terminated")));
     }
 
+    // compares parsed arguments, which may be borrowed or unescaped, with plain strings
+    fn args<'a>(result: nom::IResult<&'a str, Vec<Cow<str>>>) -> (&'a str, Vec<String>) {
+        let (rest, args) = result.unwrap();
+        (rest, args.into_iter().map(Cow::into_owned).collect())
+    }
+
     #[test]
     fn test_arg_basic() {
         let result = arg("basic_arg \t;");
-        assert_eq!(result, Ok((" \t;", "basic_arg")));
+        assert_eq!(result, Ok((" \t;", Cow::Borrowed("basic_arg"))));
     }
 
     #[test]
     fn test_quoted_arg() {
         let result = arg("\"quoted argument\";\n");
-        assert_eq!(result, Ok((";\n", "quoted argument")));
+        assert_eq!(result, Ok((";\n", Cow::Borrowed("quoted argument"))));
+    }
+
+    #[test]
+    fn test_quoted_arg_escapes() {
+        let result = quoted_arg(r#""say \"hi\" C:\quake\\" rest"#);
+        assert_eq!(
+            result,
+            Ok((" rest", Cow::Owned(r#"say "hi" C:\quake\"#.to_owned())))
+        );
+
+        assert_eq!(quoted_arg("\"\";"), Ok((";", Cow::Borrowed(""))));
+        assert!(quoted_arg("\"unterminated\n").is_err());
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        for text in &["", "plain", "say \"hi\"", r"C:\quake\", r#"\""#] {
+            let quoted = quote(text);
+            assert_eq!(quoted_arg(&quoted), Ok(("", Cow::Borrowed(*text))));
+        }
+    }
+
+    #[test]
+    fn test_needs_quotes() {
+        assert!(!needs_quotes("+jump"));
+        assert!(!needs_quotes("impulse"));
+        for text in &[
+            "",
+            "two words",
+            "a;b",
+            "say \"hi\"",
+            "//comment",
+            r"C:\quake",
+        ] {
+            assert!(needs_quotes(text), "{:?}", text);
+        }
     }
 
     #[test]
     fn test_command_basic() {
         let result = command("arg_0 arg_1;\n");
-        assert_eq!(result, Ok(("\n", vec!["arg_0", "arg_1"])));
+        assert_eq!(args(result), ("\n", vec!["arg_0".into(), "arg_1".into()]));
     }
 
     #[test]
     fn test_command_quoted() {
         let result = command("bind \"space\" \"+jump\";\n");
-        assert_eq!(result, Ok(("\n", vec!["bind", "space", "+jump"])));
+        assert_eq!(
+            args(result),
+            ("\n", vec!["bind".into(), "space".into(), "+jump".into()])
+        );
+    }
+
+    #[test]
+    fn test_command_semicolon_after_space() {
+        let result = command("+jump ; wait;\n");
+        assert_eq!(args(result), (" wait;\n", vec!["+jump".into()]));
+    }
+
+    #[test]
+    fn test_next_command() {
+        let result = next_command("\n// comment\nfirst a; second b\n");
+        assert_eq!(
+            args(result),
+            (" second b\n", vec!["first".into(), "a".into()])
+        );
     }
 
     #[test]
     fn test_command_comment() {
         let result = command("bind \"space\" \"+jump\" // bind space to jump\n\n");
-        assert_eq!(result, Ok(("\n", vec!["bind", "space", "+jump"])));
+        assert_eq!(
+            args(result),
+            ("\n", vec!["bind".into(), "space".into(), "+jump".into()])
+        );
     }
 
     #[test]
@@ -218,7 +379,8 @@ startdemos demo1 demo2 demo3
             vec!["startdemos", "demo1", "demo2", "demo3"],
         ];
 
-        let result = commands(script);
-        assert_eq!(result, Ok(("", expected)));
+        let (rest, result) = commands(script).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(result, expected);
     }
 }
@@ -22,10 +22,10 @@ use cgmath::Vector3;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_while1},
-    character::complete::{alphanumeric1, one_of, space1},
-    combinator::map,
-    sequence::{delimited, tuple},
+    character::complete::space1,
+    sequence::delimited,
 };
+#[cfg(feature = "client")]
 use winit::event::ElementState;
 
 pub use self::{console::commands, map::entities};
@@ -42,7 +42,14 @@ pub fn quoted(input: &str) -> nom::IResult<&str, &str> {
     delimited(tag("\""), string_contents, tag("\""))(input)
 }
 
+#[cfg(feature = "client")]
 pub fn action(input: &str) -> nom::IResult<&str, (ElementState, &str)> {
+    use nom::{
+        character::complete::{alphanumeric1, one_of},
+        combinator::map,
+        sequence::tuple,
+    };
+
     tuple((
         map(one_of("+-"), |c| match c {
             '+' => ElementState::Pressed,
@@ -130,6 +137,7 @@ mod tests {
         assert_eq!(quoted(s), Ok(("", "hello")))
     }
 
+    #[cfg(feature = "client")]
     #[test]
     fn test_action() {
         let s = "+up";
@@ -0,0 +1,286 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Loader for 8-bit PCX images.
+//!
+//! Custom player skins (`skins/*.pcx`) are distributed in this format. Only
+//! single-plane, 8 bits-per-pixel, run-length encoded images are supported,
+//! which covers every skin made for Quake.
+
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use failure::Error;
+
+const MANUFACTURER: u8 = 0x0A;
+const ENCODING_RLE: u8 = 1;
+
+// the header is always padded out to this size
+const HEADER_SIZE: usize = 128;
+
+// marks the start of the 256-color palette at the end of the file
+const PALETTE_MARKER: u8 = 0x0C;
+const PALETTE_SIZE: usize = 768;
+
+// bytes with the top two bits set are run lengths
+const RUN_MASK: u8 = 0xC0;
+
+// the longest run a single byte pair can encode
+const MAX_RUN: usize = !RUN_MASK as usize;
+
+/// A decoded 8-bit PCX image.
+#[derive(Clone, Debug)]
+pub struct Pcx {
+    width: u32,
+    height: u32,
+    indices: Vec<u8>,
+    palette: Option<Vec<u8>>,
+}
+
+impl Pcx {
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the palette indices of the image in row-major order.
+    pub fn indices(&self) -> &[u8] {
+        &self.indices
+    }
+
+    /// Returns the 256-color RGB palette stored with the image, if present.
+    ///
+    /// Quake skins are always drawn with the game palette, so this is only
+    /// informational.
+    pub fn palette(&self) -> Option<&[u8]> {
+        self.palette.as_ref().map(|p| p.as_slice())
+    }
+
+    /// Returns the top-left `width` by `height` pixels of the image.
+    ///
+    /// Fails if the image is smaller than the requested size.
+    pub fn crop(&self, width: u32, height: u32) -> Result<Vec<u8>, Error> {
+        ensure!(
+            width <= self.width && height <= self.height,
+            "PCX image ({}x{}) is smaller than {}x{}",
+            self.width,
+            self.height,
+            width,
+            height
+        );
+
+        Ok(self
+            .indices
+            .chunks(self.width as usize)
+            .take(height as usize)
+            .flat_map(|line| line[..width as usize].iter().cloned())
+            .collect())
+    }
+}
+
+/// Decodes an 8-bit PCX image.
+pub fn load<R>(mut data: R) -> Result<Pcx, Error>
+where
+    R: Read,
+{
+    let manufacturer = data.read_u8()?;
+    ensure!(
+        manufacturer == MANUFACTURER,
+        "Bad PCX manufacturer byte (got {:#x})",
+        manufacturer
+    );
+    let _version = data.read_u8()?;
+    let encoding = data.read_u8()?;
+    ensure!(
+        encoding == ENCODING_RLE,
+        "Unsupported PCX encoding {}",
+        encoding
+    );
+    let bits_per_pixel = data.read_u8()?;
+    ensure!(
+        bits_per_pixel == 8,
+        "Unsupported PCX bit depth {}",
+        bits_per_pixel
+    );
+
+    let x_min = data.read_u16::<LittleEndian>()?;
+    let y_min = data.read_u16::<LittleEndian>()?;
+    let x_max = data.read_u16::<LittleEndian>()?;
+    let y_max = data.read_u16::<LittleEndian>()?;
+    ensure!(
+        x_max >= x_min && y_max >= y_min,
+        "Bad PCX dimensions ({}, {}) to ({}, {})",
+        x_min,
+        y_min,
+        x_max,
+        y_max
+    );
+    let width = (x_max - x_min) as u32 + 1;
+    let height = (y_max - y_min) as u32 + 1;
+
+    // skip resolution and 16-color palette
+    let mut skip = [0; 52];
+    data.read_exact(&mut skip)?;
+
+    let _reserved = data.read_u8()?;
+    let planes = data.read_u8()?;
+    ensure!(planes == 1, "Unsupported PCX plane count {}", planes);
+    let bytes_per_line = data.read_u16::<LittleEndian>()? as usize;
+    ensure!(
+        bytes_per_line >= width as usize,
+        "PCX line length ({}) is shorter than its width ({})",
+        bytes_per_line,
+        width
+    );
+
+    // everything else in the header is padding
+    let mut padding = [0; HEADER_SIZE - 68];
+    data.read_exact(&mut padding)?;
+
+    let mut body = Vec::new();
+    data.read_to_end(&mut body)?;
+
+    // runs may cross line boundaries, so decode all lines at once and then
+    // drop the padding at the end of each line
+    let decoded_len = bytes_per_line * height as usize;
+    ensure!(
+        decoded_len <= body.len() * MAX_RUN,
+        "PCX image data is too short for a {}x{} image",
+        width,
+        height
+    );
+    let mut decoded = Vec::with_capacity(decoded_len);
+    let mut pos = 0;
+    while decoded.len() < decoded_len {
+        ensure!(pos < body.len(), "Unexpected end of PCX image data");
+        let byte = body[pos];
+        pos += 1;
+
+        let (run, value) = if byte & RUN_MASK == RUN_MASK {
+            ensure!(pos < body.len(), "Unexpected end of PCX image data");
+            let value = body[pos];
+            pos += 1;
+            ((byte & !RUN_MASK) as usize, value)
+        } else {
+            (1, byte)
+        };
+
+        for _ in 0..run.min(decoded_len - decoded.len()) {
+            decoded.push(value);
+        }
+    }
+
+    let indices = decoded
+        .chunks(bytes_per_line)
+        .flat_map(|line| line[..width as usize].iter().cloned())
+        .collect();
+
+    // the palette, if present, is the last 769 bytes of the file
+    let rest = &body[pos..];
+    let palette = match rest.len().checked_sub(PALETTE_SIZE + 1) {
+        Some(start) if rest[start] == PALETTE_MARKER => Some(rest[start + 1..].to_vec()),
+        _ => None,
+    };
+
+    Ok(Pcx {
+        width,
+        height,
+        indices,
+        palette,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use byteorder::WriteBytesExt;
+
+    fn header(width: u16, height: u16, bytes_per_line: u16) -> Vec<u8> {
+        let mut header = vec![MANUFACTURER, 5, ENCODING_RLE, 8];
+        for value in &[0, 0, width - 1, height - 1] {
+            header.write_u16::<LittleEndian>(*value).unwrap();
+        }
+        header.extend_from_slice(&[0; 52]);
+        header.push(0);
+        header.push(1);
+        header.write_u16::<LittleEndian>(bytes_per_line).unwrap();
+        header.resize(HEADER_SIZE, 0);
+        header
+    }
+
+    #[test]
+    fn test_load_rle() {
+        let mut data = header(3, 2, 4);
+
+        // first line: a run of two 7s, a literal 9 and a padding byte
+        data.extend_from_slice(&[0xC2, 7, 9, 0]);
+
+        // second line: a run spanning the whole line, padding included
+        data.extend_from_slice(&[0xC4, 0xD0]);
+
+        let pcx = load(data.as_slice()).unwrap();
+        assert_eq!(pcx.width(), 3);
+        assert_eq!(pcx.height(), 2);
+        assert_eq!(pcx.indices(), &[7, 7, 9, 0xD0, 0xD0, 0xD0]);
+        assert!(pcx.palette().is_none());
+    }
+
+    #[test]
+    fn test_load_palette() {
+        let mut data = header(1, 1, 2);
+        data.extend_from_slice(&[5, 0]);
+        data.push(PALETTE_MARKER);
+        data.extend((0..PALETTE_SIZE).map(|i| i as u8));
+
+        let pcx = load(data.as_slice()).unwrap();
+        assert_eq!(pcx.indices(), &[5]);
+        assert_eq!(pcx.palette().unwrap()[..3], [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let mut data = header(4, 4, 4);
+        data.extend_from_slice(&[0xC4, 1]);
+        assert!(load(data.as_slice()).is_err());
+
+        // a huge image with almost no data is rejected before decoding
+        let mut data = header(u16::max_value(), u16::max_value(), u16::max_value());
+        data.extend_from_slice(&[0xFF, 1]);
+        assert!(load(data.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut data = header(3, 2, 4);
+        data.extend_from_slice(&[1, 2, 3, 0, 4, 5, 6, 0]);
+        let pcx = load(data.as_slice()).unwrap();
+
+        assert_eq!(pcx.crop(3, 2).unwrap(), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(pcx.crop(2, 1).unwrap(), vec![1, 2]);
+        assert!(pcx.crop(4, 2).is_err());
+        assert!(pcx.crop(3, 3).is_err());
+    }
+}
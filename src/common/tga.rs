@@ -0,0 +1,231 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Loader for truecolor TGA images.
+//!
+//! Skybox faces (`gfx/env/*.tga`) are distributed in this format. Only
+//! uncompressed and run-length encoded 24- and 32-bit images are supported.
+
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use failure::Error;
+
+const IMAGE_TYPE_TRUECOLOR: u8 = 2;
+const IMAGE_TYPE_TRUECOLOR_RLE: u8 = 10;
+
+// set in the image descriptor if rows are stored top-to-bottom
+const DESCRIPTOR_TOP_ORIGIN: u8 = 0x20;
+
+// set in an RLE packet header if the packet is a run of one repeated pixel
+const PACKET_RUN: u8 = 0x80;
+
+/// A decoded TGA image.
+#[derive(Clone, Debug)]
+pub struct Tga {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Tga {
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the RGBA pixels of the image in row-major order, top row first.
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+}
+
+/// Decodes a truecolor TGA image.
+pub fn load<R>(mut data: R) -> Result<Tga, Error>
+where
+    R: Read,
+{
+    let id_len = data.read_u8()?;
+    let color_map_type = data.read_u8()?;
+    ensure!(
+        color_map_type == 0,
+        "Unsupported TGA color map type {}",
+        color_map_type
+    );
+    let image_type = data.read_u8()?;
+    ensure!(
+        image_type == IMAGE_TYPE_TRUECOLOR || image_type == IMAGE_TYPE_TRUECOLOR_RLE,
+        "Unsupported TGA image type {}",
+        image_type
+    );
+
+    // skip color map specification and origin
+    let mut skip = [0; 9];
+    data.read_exact(&mut skip)?;
+
+    let width = data.read_u16::<LittleEndian>()? as u32;
+    let height = data.read_u16::<LittleEndian>()? as u32;
+    let bits_per_pixel = data.read_u8()?;
+    ensure!(
+        bits_per_pixel == 24 || bits_per_pixel == 32,
+        "Unsupported TGA bit depth {}",
+        bits_per_pixel
+    );
+    let descriptor = data.read_u8()?;
+
+    let mut id = vec![0; id_len as usize];
+    data.read_exact(&mut id)?;
+
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    let pixel_count = width as usize * height as usize;
+
+    // make sure the image data could actually hold that many pixels before
+    // allocating space for them. An RLE packet holds at most 128 pixels
+    let mut body = Vec::new();
+    data.read_to_end(&mut body)?;
+    let max_pixels = match image_type {
+        IMAGE_TYPE_TRUECOLOR => body.len() / bytes_per_pixel,
+        _ => body.len() / (1 + bytes_per_pixel) * 128,
+    };
+    ensure!(
+        pixel_count <= max_pixels,
+        "TGA image data is too short for a {}x{} image",
+        width,
+        height
+    );
+    let mut data = body.as_slice();
+
+    // pixels are stored as BGR(A)
+    let read_pixel = |data: &mut &[u8]| -> Result<[u8; 4], Error> {
+        let mut bgra = [0, 0, 0, 0xFF];
+        data.read_exact(&mut bgra[..bytes_per_pixel])?;
+        Ok([bgra[2], bgra[1], bgra[0], bgra[3]])
+    };
+
+    let mut pixels = Vec::with_capacity(pixel_count);
+    if image_type == IMAGE_TYPE_TRUECOLOR {
+        for _ in 0..pixel_count {
+            pixels.push(read_pixel(&mut data)?);
+        }
+    } else {
+        // runs may cross line boundaries
+        while pixels.len() < pixel_count {
+            let header = data.read_u8()?;
+            let count = ((header & !PACKET_RUN) as usize + 1).min(pixel_count - pixels.len());
+            if header & PACKET_RUN != 0 {
+                let pixel = read_pixel(&mut data)?;
+                for _ in 0..count {
+                    pixels.push(pixel);
+                }
+            } else {
+                for _ in 0..count {
+                    pixels.push(read_pixel(&mut data)?);
+                }
+            }
+        }
+    }
+
+    // rows are stored bottom-to-top unless the descriptor says otherwise
+    let mut rows: Vec<_> = pixels.chunks(width.max(1) as usize).collect();
+    if descriptor & DESCRIPTOR_TOP_ORIGIN == 0 {
+        rows.reverse();
+    }
+
+    let rgba = rows
+        .into_iter()
+        .flat_map(|row| row.iter().flat_map(|p| p.iter().cloned()))
+        .collect();
+
+    Ok(Tga {
+        width,
+        height,
+        rgba,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use byteorder::WriteBytesExt;
+
+    fn header(image_type: u8, width: u16, height: u16, bpp: u8, descriptor: u8) -> Vec<u8> {
+        let mut header = vec![0, 0, image_type];
+        header.extend_from_slice(&[0; 9]);
+        header.write_u16::<LittleEndian>(width).unwrap();
+        header.write_u16::<LittleEndian>(height).unwrap();
+        header.push(bpp);
+        header.push(descriptor);
+        header
+    }
+
+    #[test]
+    fn test_load_uncompressed_bottom_origin() {
+        let mut data = header(IMAGE_TYPE_TRUECOLOR, 2, 2, 24, 0);
+        // bottom row, then top row, in BGR order
+        data.extend_from_slice(&[3, 2, 1, 6, 5, 4]);
+        data.extend_from_slice(&[9, 8, 7, 12, 11, 10]);
+
+        let tga = load(&data[..]).unwrap();
+        assert_eq!(tga.width(), 2);
+        assert_eq!(tga.height(), 2);
+        assert_eq!(
+            tga.rgba(),
+            &[7, 8, 9, 0xFF, 10, 11, 12, 0xFF, 1, 2, 3, 0xFF, 4, 5, 6, 0xFF][..]
+        );
+    }
+
+    #[test]
+    fn test_load_rle_top_origin() {
+        let mut data = header(IMAGE_TYPE_TRUECOLOR_RLE, 3, 1, 32, DESCRIPTOR_TOP_ORIGIN);
+        // a run of two pixels followed by one raw pixel
+        data.extend_from_slice(&[PACKET_RUN | 1, 3, 2, 1, 0x80]);
+        data.extend_from_slice(&[0, 6, 5, 4, 0x40]);
+
+        let tga = load(&data[..]).unwrap();
+        assert_eq!(
+            tga.rgba(),
+            &[1, 2, 3, 0x80, 1, 2, 3, 0x80, 4, 5, 6, 0x40][..]
+        );
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let mut data = header(IMAGE_TYPE_TRUECOLOR, 2, 2, 24, 0);
+        data.extend_from_slice(&[1, 2, 3]);
+        assert!(load(&data[..]).is_err());
+
+        // a huge image with a single RLE packet is rejected before decoding
+        let mut data = header(IMAGE_TYPE_TRUECOLOR_RLE, 0xFFFF, 0xFFFF, 24, 0);
+        data.extend_from_slice(&[PACKET_RUN | 0x7F, 1, 2, 3]);
+        assert!(load(&data[..]).is_err());
+    }
+
+    #[test]
+    fn test_load_color_mapped_unsupported() {
+        let data = header(1, 1, 1, 8, 0);
+        assert!(load(&data[..]).is_err());
+    }
+}
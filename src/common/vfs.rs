@@ -16,9 +16,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
 use std::{
+    collections::BTreeMap,
     convert::From,
     fmt::{self, Display},
-    fs::File,
+    fs::{self, File},
     io::{Cursor, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
 };
@@ -77,10 +78,72 @@ pub enum VfsErrorKind {
 }
 
 enum VfsComponent {
-    Pak(Pak),
+    Pak(PathBuf, Pak),
     Directory(PathBuf),
 }
 
+impl VfsComponent {
+    fn name(&self) -> String {
+        match self {
+            VfsComponent::Pak(path, _) => path.display().to_string(),
+            VfsComponent::Directory(path) => format!("{}/ (directory)", path.display()),
+        }
+    }
+
+    // Returns the virtual paths of all files provided by this component.
+    fn file_names(&self) -> Vec<String> {
+        match self {
+            VfsComponent::Pak(_, pak) => pak.iter().map(|(name, _)| name.clone()).collect(),
+            VfsComponent::Directory(path) => {
+                let mut names = Vec::new();
+                list_directory(path, "", &mut names);
+                names
+            }
+        }
+    }
+}
+
+// Recursively collects the paths of files under `dir`, relative to the
+// directory originally passed in and separated by forward slashes.
+fn list_directory(dir: &Path, prefix: &str, names: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => list_directory(&entry.path(), &format!("{}/", name), names),
+            Ok(_) => names.push(name),
+            Err(_) => (),
+        }
+    }
+}
+
+/// A file provided by more than one mounted source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VfsConflict {
+    /// The virtual path of the file.
+    pub path: String,
+
+    /// The sources providing the file, in priority order. The first is the one
+    /// that is actually loaded; the rest are shadowed by it.
+    pub sources: Vec<String>,
+}
+
+impl Display for VfsConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.path)?;
+        for (i, source) in self.sources.iter().enumerate() {
+            let label = if i == 0 { "used" } else { "shadowed" };
+            writeln!(f, "    {:>8}: {}", label, source)?;
+        }
+
+        Ok(())
+    }
+}
+
 pub struct Vfs {
     components: Vec<VfsComponent>,
 }
@@ -98,12 +161,12 @@ impl Vfs {
     {
         let path = path.as_ref();
 
-        self.components
-            .push(VfsComponent::Pak(Pak::new(path).context(
-                VfsErrorKind::PakfileNotLoaded {
-                    path: path.to_string_lossy().into_owned(),
-                },
-            )?));
+        self.components.push(VfsComponent::Pak(
+            path.to_path_buf(),
+            Pak::new(path).context(VfsErrorKind::PakfileNotLoaded {
+                path: path.to_string_lossy().into_owned(),
+            })?,
+        ));
 
         Ok(())
     }
@@ -126,9 +189,8 @@ impl Vfs {
 
         // iterate in reverse so later PAKs overwrite earlier ones
         for c in self.components.iter().rev() {
-
             match c {
-                VfsComponent::Pak(pak) => {
+                VfsComponent::Pak(_, pak) => {
                     if let Ok(f) = pak.open(vp) {
                         return Ok(VirtualFile::PakBacked(Cursor::new(f)));
                     }
@@ -145,7 +207,43 @@ impl Vfs {
             }
         }
 
-        Err(VfsErrorKind::NoSuchFile { path: vp.to_owned() })?
+        Err(VfsErrorKind::NoSuchFile {
+            path: vp.to_owned(),
+        })?
+    }
+
+    /// Returns the number of bytes held in memory by loaded PAK archives.
+    pub fn memory_usage(&self) -> usize {
+        self.components
+            .iter()
+            .map(|c| match c {
+                VfsComponent::Pak(_, pak) => pak.memory_usage(),
+                VfsComponent::Directory(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Returns every file provided by more than one source, sorted by path.
+    ///
+    /// Sources are listed in the order `open` searches them, so the first
+    /// source of each conflict is the one whose file is loaded.
+    pub fn conflicts(&self) -> Vec<VfsConflict> {
+        let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for c in self.components.iter().rev() {
+            let name = c.name();
+            for file in c.file_names() {
+                sources
+                    .entry(file)
+                    .or_insert_with(Vec::new)
+                    .push(name.clone());
+            }
+        }
+
+        sources
+            .into_iter()
+            .filter(|(_, s)| s.len() > 1)
+            .map(|(path, sources)| VfsConflict { path, sources })
+            .collect()
     }
 }
 
@@ -171,3 +269,91 @@ impl<'a> Seek for VirtualFile<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{env, io::Write};
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    // writes a PAK archive containing the given files
+    fn write_pak(path: &Path, files: &[(&str, &str)]) {
+        let header_size = 12;
+        let data_size: usize = files.iter().map(|(_, data)| data.len()).sum();
+
+        let mut pak = Vec::new();
+        pak.extend_from_slice(b"PACK");
+        pak.write_i32::<LittleEndian>((header_size + data_size) as i32)
+            .unwrap();
+        pak.write_i32::<LittleEndian>((files.len() * 64) as i32)
+            .unwrap();
+
+        for (_, data) in files {
+            pak.extend_from_slice(data.as_bytes());
+        }
+
+        let mut offset = header_size;
+        for (name, data) in files {
+            let mut name_bytes = [0u8; 56];
+            name_bytes[..name.len()].copy_from_slice(name.as_bytes());
+            pak.extend_from_slice(&name_bytes);
+            pak.write_i32::<LittleEndian>(offset as i32).unwrap();
+            pak.write_i32::<LittleEndian>(data.len() as i32).unwrap();
+            offset += data.len();
+        }
+
+        File::create(path).unwrap().write_all(&pak).unwrap();
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let root = env::temp_dir().join(format!("richter-vfs-conflicts-{}", std::process::id()));
+        let dir = root.join("id1");
+        fs::create_dir_all(dir.join("gfx")).unwrap();
+
+        let pak_path = root.join("pak0.pak");
+        write_pak(
+            &pak_path,
+            &[
+                ("default.cfg", "pak"),
+                ("gfx/conback.lmp", "pak"),
+                ("progs.dat", "pak"),
+            ],
+        );
+        fs::write(dir.join("default.cfg"), "dir").unwrap();
+        fs::write(dir.join("gfx/conback.lmp"), "dir").unwrap();
+        fs::write(dir.join("autoexec.cfg"), "dir").unwrap();
+
+        let mut vfs = Vfs::new();
+        vfs.add_pakfile(&pak_path).unwrap();
+        vfs.add_directory(&dir).unwrap();
+
+        let dir_name = format!("{}/ (directory)", dir.display());
+        let pak_name = pak_path.display().to_string();
+        assert_eq!(
+            vfs.conflicts(),
+            vec![
+                VfsConflict {
+                    path: String::from("default.cfg"),
+                    sources: vec![dir_name.clone(), pak_name.clone()],
+                },
+                VfsConflict {
+                    path: String::from("gfx/conback.lmp"),
+                    sources: vec![dir_name, pak_name],
+                },
+            ]
+        );
+
+        // the first source listed is the one that's loaded
+        let mut contents = String::new();
+        vfs.open("default.cfg")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "dir");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -35,9 +35,12 @@ extern crate num;
 extern crate num_derive;
 extern crate rand;
 extern crate regex;
+#[cfg(feature = "client")]
 extern crate rodio;
+#[cfg(feature = "client")]
 extern crate winit;
 
+#[cfg(feature = "client")]
 pub mod client;
 pub mod common;
 pub mod server;
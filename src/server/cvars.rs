@@ -0,0 +1,37 @@
+// Copyright © 2018 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::common::console::{ConsoleError, CvarRegistry};
+
+pub fn register_cvars(cvars: &CvarRegistry) -> Result<(), ConsoleError> {
+    cvars.register("coop", "0")?;
+    cvars.register("deathmatch", "0")?;
+    cvars.register("fraglimit", "0")?;
+    cvars.register("maxplayers", "1")?;
+    cvars.register("skill", "1")?;
+    cvars.register("sv_gravity", "800")?;
+    cvars.register_archive("sv_maplist", "")?;
+    cvars.register_archive("sv_maplistfile", "maplist.txt")?;
+    cvars.register_archive("sv_mapvote", "0")?;
+    cvars.register("teamplay", "0")?;
+    cvars.register("timelimit", "0")?;
+
+    Ok(())
+}
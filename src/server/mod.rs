@@ -15,20 +15,48 @@
 // DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+mod cvars;
+pub mod multicast;
 pub mod progs;
+pub mod rotation;
+pub mod update;
 pub mod world;
 
+pub use self::cvars::register_cvars;
+
 use std::{
     io::{Cursor, Seek, SeekFrom},
     rc::Rc,
 };
 
-use self::progs::{EntityId, StringId, StringTable};
+use crate::common::{
+    console::CvarRegistry,
+    net::{NetError, Protocol, ServerCmd, TempEntity},
+    vfs::Vfs,
+};
+
+use self::{
+    multicast::{MulticastTarget, Visibility},
+    progs::{
+        EntityId, ExecutionContext, GlobalAddrEntity, GlobalAddrFloat, GlobalAddrFunction, Globals,
+        ProgsError, StringId, StringTable, NUM_SPAWN_ARGS,
+    },
+    rotation::{limit_reached, parse_vote, MapRotation, MapVote},
+    update::{PendingUpdate, UpdateScheduler},
+    world::{FieldAddrFloat, World},
+};
 
 use byteorder::WriteBytesExt;
+use cgmath::{Deg, Vector3};
+use chrono::Duration;
+use failure::Error;
 
 const MAX_DATAGRAM: usize = 1024;
 const MAX_LIGHTSTYLES: usize = 64;
+const MAX_SOUND_CHANNEL: i32 = 7;
+const MAX_SOUND_ATTENUATION: f32 = 4.0;
+const DEFAULT_SOUND_VOLUME: u8 = 255;
+const DEFAULT_SOUND_ATTENUATION: f32 = 1.0;
 
 pub enum ClientSlot {
     Disconnected,
@@ -38,6 +66,77 @@ pub enum ClientSlot {
 pub struct ClientInGame {
     privileged: bool,
     entity_id: EntityId,
+
+    // parm1-16, carried over to the next level
+    spawn_args: [f32; NUM_SPAWN_ARGS],
+
+    // entity updates that didn't fit in the last datagram
+    updates: UpdateScheduler,
+
+    // unreliable messages to be sent to the client at the end of the frame
+    datagram: Vec<u8>,
+}
+
+impl ClientInGame {
+    pub fn new(
+        entity_id: EntityId,
+        privileged: bool,
+        spawn_args: [f32; NUM_SPAWN_ARGS],
+    ) -> ClientInGame {
+        ClientInGame {
+            privileged,
+            entity_id,
+            spawn_args,
+            updates: UpdateScheduler::new(),
+            datagram: Vec::with_capacity(MAX_DATAGRAM),
+        }
+    }
+
+    /// Returns the unreliable messages queued for this client this frame.
+    pub fn datagram(&self) -> &[u8] {
+        &self.datagram
+    }
+
+    /// Clears the client's datagram after it has been sent.
+    pub fn clear_datagram(&mut self) {
+        self.datagram.clear();
+    }
+
+    /// Appends an unreliable message to the client's datagram.
+    ///
+    /// If the message doesn't fit in the datagram it's dropped and `false` is returned.
+    pub fn write_unreliable(&mut self, message: &[u8]) -> bool {
+        if self.datagram.len() + message.len() > MAX_DATAGRAM {
+            return false;
+        }
+
+        self.datagram.extend_from_slice(message);
+        true
+    }
+
+    /// Appends entity updates to the client's datagram.
+    ///
+    /// Updates that would exceed `MAX_DATAGRAM` are held back and prioritized on the next frame
+    /// (see `UpdateScheduler`). Returns the number of bytes written.
+    pub fn write_entity_updates(
+        &mut self,
+        protocol: Protocol,
+        updates: Vec<PendingUpdate>,
+    ) -> Result<usize, NetError> {
+        let budget = MAX_DATAGRAM.saturating_sub(self.datagram.len());
+        self.updates
+            .write_updates(&mut self.datagram, protocol, updates, budget)
+    }
+
+    /// Returns the scheduler for this client's entity updates.
+    pub fn updates_mut(&mut self) -> &mut UpdateScheduler {
+        &mut self.updates
+    }
+
+    /// Returns the spawn arguments saved when the client last changed levels.
+    pub fn spawn_args(&self) -> &[f32; NUM_SPAWN_ARGS] {
+        &self.spawn_args
+    }
 }
 
 bitflags! {
@@ -57,6 +156,330 @@ pub struct ServerStatics {
 
     client_slot_count: usize,
     client_slots: Vec<ClientSlot>,
+
+    // episode completion and rune flags, carried over to the next level. This
+    // is kept as the raw global so bits unknown to `ServerFlags` survive.
+    server_flags: f32,
+
+    // maps to cycle through, reloaded as each level starts
+    rotation: MapRotation,
+
+    // votes for the next map, cleared at each level change
+    map_vote: MapVote,
+}
+
+impl ServerStatics {
+    pub fn new(client_slot_limit: usize) -> ServerStatics {
+        let mut client_slots = Vec::with_capacity(client_slot_limit);
+        for _ in 0..client_slot_limit {
+            client_slots.push(ClientSlot::Disconnected);
+        }
+
+        ServerStatics {
+            client_slot_limit,
+            client_slot_count: 0,
+            client_slots,
+            server_flags: 0.0,
+            rotation: MapRotation::default(),
+            map_vote: MapVote::new(),
+        }
+    }
+
+    /// Places a client in the given slot.
+    ///
+    /// Returns the client back if the slot doesn't exist or is already taken.
+    pub fn connect_client(
+        &mut self,
+        slot_id: usize,
+        client: ClientInGame,
+    ) -> Result<(), ClientInGame> {
+        match self.client_slots.get(slot_id) {
+            Some(ClientSlot::Disconnected) => (),
+            _ => return Err(client),
+        }
+
+        self.client_slots[slot_id] = ClientSlot::InGame(client);
+        self.client_slot_count += 1;
+        Ok(())
+    }
+
+    /// Frees the given client slot.
+    pub fn disconnect_client(&mut self, slot_id: usize) {
+        if let Some(slot) = self.client_slots.get_mut(slot_id) {
+            if let ClientSlot::InGame(_) = *slot {
+                *slot = ClientSlot::Disconnected;
+                self.client_slot_count -= 1;
+                self.map_vote.withdraw(slot_id);
+            }
+        }
+    }
+
+    /// Returns the server flags saved at the last level change.
+    ///
+    /// These should be written to the `serverflags` global when the next level is loaded.
+    pub fn server_flags(&self) -> f32 {
+        self.server_flags
+    }
+
+    /// Returns the spawn arguments of each client slot, for use by the next level.
+    ///
+    /// Disconnected slots have all arguments set to zero.
+    pub fn spawn_args(&self) -> Vec<[f32; NUM_SPAWN_ARGS]> {
+        self.client_slots
+            .iter()
+            .map(|slot| match slot {
+                ClientSlot::InGame(client) => client.spawn_args,
+                ClientSlot::Disconnected => [0.0; NUM_SPAWN_ARGS],
+            })
+            .collect()
+    }
+
+    /// Saves each client's spawn arguments and the server flags before a level change.
+    ///
+    /// This calls `SetChangeParms` with `self` set to each client's entity so the progs can pack
+    /// the player's health, ammo and inventory into `parm1` through `parm16`.
+    pub fn save_spawn_args(
+        &mut self,
+        execution_context: &mut ExecutionContext,
+        globals: &mut Globals,
+        world: &mut World,
+        cvars: &mut CvarRegistry,
+        server: &mut Server,
+        vfs: &Vfs,
+    ) -> Result<(), ProgsError> {
+        self.server_flags = globals.get_float(GlobalAddrFloat::ServerFlags as i16)?;
+
+        let set_change_args = globals.get_function_id(GlobalAddrFunction::SetChangeArgs as i16)?;
+        for slot in self.client_slots.iter_mut() {
+            if let ClientSlot::InGame(ref mut client) = *slot {
+                globals.put_entity_id(client.entity_id, GlobalAddrEntity::Self_ as i16)?;
+                execution_context.execute_program(
+                    globals,
+                    world,
+                    cvars,
+                    server,
+                    vfs,
+                    set_change_args,
+                )?;
+                client.spawn_args = globals.get_spawn_args()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ends the current level if the progs asked for a level change.
+    ///
+    /// If `changelevel` was called, each client's spawn arguments are saved as by
+    /// `save_spawn_args` and the name of the next level is returned. The next level's `Server`
+    /// should then be constructed with `self.spawn_args()`.
+    ///
+    /// Dropped entity updates are forgotten, since entity numbers aren't kept across levels.
+    pub fn change_level(
+        &mut self,
+        execution_context: &mut ExecutionContext,
+        globals: &mut Globals,
+        world: &mut World,
+        cvars: &mut CvarRegistry,
+        server: &mut Server,
+        vfs: &Vfs,
+    ) -> Result<Option<String>, ProgsError> {
+        let level = match server.pending_level() {
+            Some(l) => l.to_owned(),
+            None => return Ok(None),
+        };
+
+        self.save_spawn_args(execution_context, globals, world, cvars, server, vfs)?;
+        self.map_vote.clear();
+        for slot in self.client_slots.iter_mut() {
+            if let ClientSlot::InGame(ref mut client) = *slot {
+                client.updates.clear();
+            }
+        }
+
+        Ok(Some(level))
+    }
+
+    /// Reloads the map rotation from `sv_maplist` or `sv_maplistfile`.
+    ///
+    /// This should be called as each level starts, so that changes to the rotation take effect
+    /// at the next level.
+    pub fn load_map_rotation(&mut self, cvars: &CvarRegistry, vfs: &Vfs) -> Result<(), Error> {
+        self.rotation = MapRotation::load(cvars, vfs)?;
+        Ok(())
+    }
+
+    /// Moves on to the next map once `timelimit` or `fraglimit` is reached.
+    ///
+    /// A map that won a vote is played next; otherwise the map after `current_map` in the
+    /// rotation is. If the rotation is empty, the progs are left to end the level themselves.
+    pub fn check_level_limits(
+        &mut self,
+        server: &mut Server,
+        world: &World,
+        cvars: &CvarRegistry,
+        level_time: Duration,
+        current_map: &str,
+    ) -> Result<(), ProgsError> {
+        if self.rotation.is_empty() {
+            return Ok(());
+        }
+
+        let mut top_frags = 0;
+        for slot in self.client_slots.iter() {
+            if let ClientSlot::InGame(ref client) = *slot {
+                let entity = world.try_get_entity(client.entity_id)?;
+                let frags = entity.get_float(FieldAddrFloat::Frags as i16)? as i32;
+                top_frags = top_frags.max(frags);
+            }
+        }
+
+        // unregistered limits are treated as disabled
+        let timelimit = cvars.get_value("timelimit").unwrap_or(0.0);
+        let fraglimit = cvars.get_value("fraglimit").unwrap_or(0.0);
+        if !limit_reached(level_time, timelimit, top_frags, fraglimit) {
+            return Ok(());
+        }
+
+        let next_map = match self.map_vote.winner(self.client_slot_count) {
+            Some(map) => Some(map),
+            None => self.rotation.next_map(current_map),
+        };
+
+        if let Some(map) = next_map {
+            server.change_level(map.to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Handles a `say` command from the client in the given slot.
+    ///
+    /// If `sv_mapvote` is set and the message is `votemap <map>`, it's counted as a vote and
+    /// `true` is returned. Once more than half of the connected clients agree on a map, the level
+    /// changes to it.
+    pub fn client_say(
+        &mut self,
+        slot_id: usize,
+        text: &str,
+        cvars: &CvarRegistry,
+        server: &mut Server,
+    ) -> Result<bool, Error> {
+        if cvars.get_value("sv_mapvote")? == 0.0 {
+            return Ok(false);
+        }
+
+        let map = match parse_vote(text) {
+            Some(m) => m,
+            None => return Ok(false),
+        };
+
+        self.map_vote.cast(&self.rotation, slot_id, map)?;
+        if let Some(winner) = self.map_vote.winner(self.client_slot_count) {
+            server.change_level(winner.to_owned());
+        }
+
+        Ok(true)
+    }
+
+    /// Copies the sounds and temporary entities queued this frame into the datagrams of the
+    /// clients that can perceive them.
+    ///
+    /// Each client is located by its view origin (`origin + view_ofs`). This should be called
+    /// before entity updates are written so the updates are fit into the space that remains.
+    pub fn send_multicasts(
+        &mut self,
+        server: &mut Server,
+        world: &World,
+    ) -> Result<(), ProgsError> {
+        let multicasts = server.take_multicasts();
+        if multicasts.is_empty() {
+            return Ok(());
+        }
+
+        let mut view_origins = Vec::with_capacity(self.client_slots.len());
+        for slot in self.client_slots.iter() {
+            if let ClientSlot::InGame(ref client) = *slot {
+                let entity = world.try_get_entity(client.entity_id)?;
+                view_origins.push(Some(entity.origin()? + entity.view_offset()?));
+            } else {
+                view_origins.push(None);
+            }
+        }
+
+        let visibility = server.visibility(world)?;
+        for multicast in multicasts {
+            let audience = visibility.audience(multicast.origin, multicast.target);
+            for (slot, view_origin) in self.client_slots.iter_mut().zip(view_origins.iter()) {
+                if let (ClientSlot::InGame(client), Some(view_origin)) = (slot, view_origin) {
+                    if visibility.includes(&audience, *view_origin)
+                        && !client.write_unreliable(&multicast.message)
+                    {
+                        debug!("Datagram overflow, dropping multicast");
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the spawn arguments for a client starting a new game.
+///
+/// This calls `SetNewParms`, which sets `parm1` through `parm16` to the starting inventory.
+pub fn new_spawn_args(
+    execution_context: &mut ExecutionContext,
+    globals: &mut Globals,
+    world: &mut World,
+    cvars: &mut CvarRegistry,
+    server: &mut Server,
+    vfs: &Vfs,
+) -> Result<[f32; NUM_SPAWN_ARGS], ProgsError> {
+    let set_new_args = globals.get_function_id(GlobalAddrFunction::SetNewArgs as i16)?;
+    execution_context.execute_program(globals, world, cvars, server, vfs, set_new_args)?;
+    Ok(globals.get_spawn_args()?)
+}
+
+/// Spawns a client's player entity in the current level.
+///
+/// `parm1` through `parm16` are set to the spawn arguments the client carried over from the
+/// previous level before `ClientConnect` and `PutClientInServer` are called, so the progs can
+/// restore the player's health and inventory.
+pub fn put_client_in_server(
+    execution_context: &mut ExecutionContext,
+    globals: &mut Globals,
+    world: &mut World,
+    cvars: &mut CvarRegistry,
+    server: &mut Server,
+    vfs: &Vfs,
+    entity_id: EntityId,
+) -> Result<(), ProgsError> {
+    let args = match server.client_spawn_args(entity_id) {
+        Some(a) => *a,
+        None => {
+            return Err(ProgsError::with_msg(format!(
+                "entity {:?} is not a client",
+                entity_id
+            )))
+        }
+    };
+    globals.put_spawn_args(&args)?;
+    globals.put_entity_id(entity_id, GlobalAddrEntity::Self_ as i16)?;
+
+    let client_connect = globals.get_function_id(GlobalAddrFunction::ClientConnect as i16)?;
+    execution_context.execute_program(globals, world, cvars, server, vfs, client_connect)?;
+
+    let put_client_in_server =
+        globals.get_function_id(GlobalAddrFunction::PutClientInServer as i16)?;
+    execution_context.execute_program(globals, world, cvars, server, vfs, put_client_in_server)
+}
+
+// a message waiting to be sent to the clients that can see or hear its origin
+struct Multicast {
+    origin: Vector3<f32>,
+    target: MulticastTarget,
+    message: Vec<u8>,
 }
 
 pub struct Server {
@@ -65,10 +488,31 @@ pub struct Server {
     model_precache: Vec<String>,
     lightstyles: [StringId; MAX_LIGHTSTYLES],
     datagram: Cursor<Box<[u8]>>,
+
+    // encoding of coordinates and angles sent to clients
+    protocol: Protocol,
+
+    // spawn arguments of each client slot, saved at the end of the previous level
+    client_spawn_args: Vec<[f32; NUM_SPAWN_ARGS]>,
+
+    // the level requested by the `changelevel` builtin, if any
+    pending_level: Option<String>,
+
+    // sounds and temporary entities queued this frame
+    multicasts: Vec<Multicast>,
+
+    // loaded from the world model when first needed
+    visibility: Option<Visibility>,
 }
 
 impl Server {
-    pub fn new(string_table: Rc<StringTable>) -> Server {
+    /// Constructs a new `Server` for a level.
+    ///
+    /// `client_spawn_args` should be the result of `ServerStatics::spawn_args()`.
+    pub fn new(
+        string_table: Rc<StringTable>,
+        client_spawn_args: Vec<[f32; NUM_SPAWN_ARGS]>,
+    ) -> Server {
         let mut sound_precache = Vec::new();
         sound_precache.push(String::new()); // sound 0 is none
 
@@ -81,9 +525,46 @@ impl Server {
             model_precache,
             lightstyles: [StringId(0); MAX_LIGHTSTYLES],
             datagram: Cursor::new(Box::new([0; MAX_DATAGRAM])),
+            protocol: Protocol::NETQUAKE,
+            client_spawn_args,
+            pending_level: None,
+            multicasts: Vec::new(),
+            visibility: None,
         }
     }
 
+    /// Returns the spawn arguments of the client controlling the given entity.
+    ///
+    /// Client entities immediately follow the world entity, so client slot 0 controls entity 1.
+    pub fn client_spawn_args(&self, entity_id: EntityId) -> Option<&[f32; NUM_SPAWN_ARGS]> {
+        entity_id
+            .0
+            .checked_sub(1)
+            .and_then(|slot_id| self.client_spawn_args.get(slot_id))
+    }
+
+    /// Requests a change to the given level.
+    ///
+    /// Only the first request made on a level is honored, since a trigger may fire more than once
+    /// before the level ends.
+    pub fn change_level(&mut self, level: String) {
+        if let Some(ref pending) = self.pending_level {
+            debug!(
+                "Ignoring changelevel to {}: already changing to {}",
+                level, pending
+            );
+            return;
+        }
+
+        debug!("Changing level to {}", level);
+        self.pending_level = Some(level);
+    }
+
+    /// Returns the level requested by the progs, if any.
+    pub fn pending_level(&self) -> Option<&str> {
+        self.pending_level.as_ref().map(|s| s.as_str())
+    }
+
     pub fn precache_sound(&mut self, name_id: StringId) {
         let name = self.string_table.get(name_id).unwrap();
 
@@ -146,7 +627,254 @@ impl Server {
         self.datagram.seek(SeekFrom::Start(0)).unwrap();
     }
 
+    /// Returns the protocol used to encode messages to clients.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Writes a coordinate to the datagram using the server's protocol.
+    pub fn write_coord(&mut self, coord: f32) -> Result<(), NetError> {
+        self.protocol.write_coord(&mut self.datagram, coord)
+    }
+
+    /// Writes an angle to the datagram using the server's protocol.
+    pub fn write_angle(&mut self, angle: Deg<f32>) -> Result<(), NetError> {
+        self.protocol.write_angle(&mut self.datagram, angle)
+    }
+
+    /// Queues a message for the clients that can see or hear `origin`.
+    ///
+    /// Messages are sent by `ServerStatics::send_multicasts`.
+    pub fn multicast(
+        &mut self,
+        origin: Vector3<f32>,
+        target: MulticastTarget,
+        cmd: &ServerCmd,
+    ) -> Result<(), NetError> {
+        let mut message = Vec::new();
+        cmd.serialize(&mut message, self.protocol)?;
+        self.multicasts.push(Multicast {
+            origin,
+            target,
+            message,
+        });
+
+        Ok(())
+    }
+
+    /// Spawns a temporary entity for every client that can see it.
+    ///
+    /// Beams are made visible from their starting point.
+    pub fn spawn_temp_entity(&mut self, temp_entity: TempEntity) -> Result<(), NetError> {
+        let origin = match temp_entity {
+            TempEntity::Point { origin, .. } => origin,
+            TempEntity::Beam { start, .. } => start,
+        };
+
+        self.multicast(
+            origin,
+            MulticastTarget::Pvs,
+            &ServerCmd::TempEntity { temp_entity },
+        )
+    }
+
+    fn take_multicasts(&mut self) -> Vec<Multicast> {
+        ::std::mem::replace(&mut self.multicasts, Vec::new())
+    }
+
+    fn visibility(&mut self, world: &World) -> Result<&mut Visibility, ProgsError> {
+        if self.visibility.is_none() {
+            self.visibility = Some(Visibility::new(world.world_model()?));
+        }
+
+        Ok(self.visibility.as_mut().unwrap())
+    }
+
+    /// Starts a sound on an entity for every client within earshot.
+    ///
+    /// The sound is played from the center of the entity's bounding box. `volume` is in the range
+    /// `[0, 1]`.
+    pub fn start_sound(
+        &mut self,
+        world: &World,
+        entity_id: EntityId,
+        channel: i32,
+        sound_id: usize,
+        volume: f32,
+        attenuation: f32,
+    ) -> Result<(), ProgsError> {
+        if channel < 0 || channel > MAX_SOUND_CHANNEL {
+            return Err(ProgsError::with_msg(format!(
+                "sound: invalid channel ({})",
+                channel
+            )));
+        }
+
+        let volume = (volume * 255.0) as i32;
+        if volume < 0 || volume > 255 {
+            return Err(ProgsError::with_msg(format!(
+                "sound: invalid volume ({})",
+                volume
+            )));
+        }
+
+        if attenuation < 0.0 || attenuation > MAX_SOUND_ATTENUATION {
+            return Err(ProgsError::with_msg(format!(
+                "sound: invalid attenuation ({})",
+                attenuation
+            )));
+        }
+
+        let entity = world.try_get_entity(entity_id)?;
+        let position = entity.origin()? + (entity.min()? + entity.max()?) * 0.5;
+
+        let cmd = ServerCmd::Sound {
+            volume: match volume as u8 {
+                DEFAULT_SOUND_VOLUME => None,
+                v => Some(v),
+            },
+            attenuation: if attenuation == DEFAULT_SOUND_ATTENUATION {
+                None
+            } else {
+                Some(attenuation)
+            },
+            entity_id: entity_id.0 as u16,
+            channel: channel as i8,
+            sound_id: sound_id as u8,
+            position,
+        };
+
+        self.multicast(position, MulticastTarget::Phs, &cmd)
+            .map_err(|e| ProgsError::with_msg(format!("sound: {}", e)))
+    }
+
     pub fn set_lightstyle(&mut self, lightstyle_index: usize, lightstyle_val_id: StringId) {
         self.lightstyles[lightstyle_index] = lightstyle_val_id;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn server(client_spawn_args: Vec<[f32; NUM_SPAWN_ARGS]>) -> Server {
+        Server::new(Rc::new(StringTable::new(Vec::new())), client_spawn_args)
+    }
+
+    #[test]
+    fn test_client_spawn_args() {
+        let mut args = [0.0; NUM_SPAWN_ARGS];
+        args[0] = 100.0;
+        let server = server(vec![[0.0; NUM_SPAWN_ARGS], args]);
+
+        // the world entity isn't a client
+        assert!(server.client_spawn_args(EntityId(0)).is_none());
+        assert_eq!(server.client_spawn_args(EntityId(2)), Some(&args));
+        assert!(server.client_spawn_args(EntityId(3)).is_none());
+    }
+
+    #[test]
+    fn test_change_level_first_wins() {
+        let mut server = server(Vec::new());
+        assert_eq!(server.pending_level(), None);
+
+        server.change_level("e1m2".to_owned());
+        server.change_level("start".to_owned());
+        assert_eq!(server.pending_level(), Some("e1m2"));
+    }
+
+    #[test]
+    fn test_multicast_queue() {
+        let mut server = server(Vec::new());
+        let cmd = ServerCmd::Time { time: 1.0 };
+        server
+            .multicast(Vector3::new(0.0, 0.0, 0.0), MulticastTarget::All, &cmd)
+            .unwrap();
+
+        let mut expected = Vec::new();
+        cmd.serialize(&mut expected, server.protocol()).unwrap();
+
+        let multicasts = server.take_multicasts();
+        assert_eq!(multicasts.len(), 1);
+        assert_eq!(multicasts[0].message, expected);
+        assert!(server.take_multicasts().is_empty());
+    }
+
+    #[test]
+    fn test_write_unreliable_overflow() {
+        let mut client = ClientInGame::new(EntityId(1), false, [0.0; NUM_SPAWN_ARGS]);
+
+        assert!(client.write_unreliable(&[0; MAX_DATAGRAM - 1]));
+        assert!(!client.write_unreliable(&[0; 2]));
+        assert_eq!(client.datagram().len(), MAX_DATAGRAM - 1);
+        assert!(client.write_unreliable(&[0; 1]));
+    }
+
+    #[test]
+    fn test_client_say_votemap() {
+        let cvars = CvarRegistry::new();
+        register_cvars(&cvars).unwrap();
+        cvars.set("sv_maplist", "dm1 dm2 dm3").unwrap();
+
+        let mut statics = ServerStatics::new(3);
+        statics.load_map_rotation(&cvars, &Vfs::new()).unwrap();
+        for slot_id in 0..3 {
+            let client = ClientInGame::new(EntityId(slot_id + 1), false, [0.0; NUM_SPAWN_ARGS]);
+            assert!(statics.connect_client(slot_id, client).is_ok());
+        }
+
+        let mut server = server(Vec::new());
+
+        // voting is off by default
+        assert!(!statics
+            .client_say(0, "votemap dm3", &cvars, &mut server)
+            .unwrap());
+
+        cvars.set("sv_mapvote", "1").unwrap();
+        assert!(!statics.client_say(0, "hello", &cvars, &mut server).unwrap());
+        assert!(statics
+            .client_say(0, "votemap e1m1", &cvars, &mut server)
+            .is_err());
+        assert!(statics
+            .client_say(0, "votemap dm3", &cvars, &mut server)
+            .unwrap());
+        assert_eq!(server.pending_level(), None);
+
+        // a disconnected client's vote no longer counts
+        statics.disconnect_client(0);
+        assert!(statics
+            .client_say(1, "votemap dm3", &cvars, &mut server)
+            .unwrap());
+        assert_eq!(server.pending_level(), None);
+
+        assert!(statics
+            .client_say(2, "votemap dm3", &cvars, &mut server)
+            .unwrap());
+        assert_eq!(server.pending_level(), Some("dm3"));
+    }
+
+    #[test]
+    fn test_statics_spawn_args() {
+        let mut statics = ServerStatics::new(3);
+        let mut args = [0.0; NUM_SPAWN_ARGS];
+        args[3] = 25.0;
+
+        assert!(statics
+            .connect_client(1, ClientInGame::new(EntityId(2), false, args))
+            .is_ok());
+        assert!(statics
+            .connect_client(1, ClientInGame::new(EntityId(2), false, args))
+            .is_err());
+        assert!(statics
+            .connect_client(3, ClientInGame::new(EntityId(4), false, args))
+            .is_err());
+
+        assert_eq!(
+            statics.spawn_args(),
+            vec![[0.0; NUM_SPAWN_ARGS], args, [0.0; NUM_SPAWN_ARGS]]
+        );
+
+        statics.disconnect_client(1);
+        assert_eq!(statics.spawn_args(), vec![[0.0; NUM_SPAWN_ARGS]; 3]);
+    }
+}
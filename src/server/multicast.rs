@@ -0,0 +1,201 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Filtering of sounds and temporary entities by visibility.
+//!
+//! Effects are only sent to the clients that could perceive them, as in
+//! QuakeWorld's `multicast`:
+//!
+//! - a PVS send reaches clients in a leaf potentially visible from the origin;
+//! - a PHS (potentially hearable set) send reaches clients in a leaf visible
+//!   from any leaf that's visible from the origin, so sounds carry around one
+//!   corner.
+//!
+//! If the origin is outside the map or the map has no visibility data, every
+//! client is reached.
+
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::common::bsp::{BspData, BspModel};
+
+use cgmath::Vector3;
+
+/// Which clients a multicast is sent to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MulticastTarget {
+    /// Every client, regardless of position.
+    All,
+
+    /// Clients that could see the origin.
+    Pvs,
+
+    /// Clients that could hear the origin.
+    Phs,
+}
+
+/// The leaves reached by a multicast from a particular origin.
+#[derive(Clone, Debug)]
+pub enum Audience {
+    Everyone,
+    Leaves(Rc<HashSet<usize>>),
+}
+
+impl Audience {
+    /// Returns true if a client in leaf `leaf_id` is reached.
+    pub fn includes_leaf(&self, leaf_id: usize) -> bool {
+        match *self {
+            Audience::Everyone => true,
+            Audience::Leaves(ref leaves) => leaves.contains(&leaf_id),
+        }
+    }
+}
+
+/// The visibility data of the current level.
+///
+/// The PHS of each leaf is computed the first time a sound is heard from it and cached until the
+/// level ends.
+pub struct Visibility {
+    bsp_data: Rc<BspData>,
+    leaf_count: usize,
+    phs: HashMap<usize, Rc<HashSet<usize>>>,
+}
+
+impl Visibility {
+    /// Constructs the visibility data for a level from its world model.
+    pub fn new(world_model: &BspModel) -> Visibility {
+        Visibility {
+            bsp_data: world_model.bsp_data(),
+            leaf_count: world_model.leaf_count,
+            phs: HashMap::new(),
+        }
+    }
+
+    /// Determines which leaves a multicast from `origin` reaches.
+    pub fn audience(&mut self, origin: Vector3<f32>, target: MulticastTarget) -> Audience {
+        if target == MulticastTarget::All {
+            return Audience::Everyone;
+        }
+
+        let leaf_id = self.bsp_data.find_leaf(origin);
+        if !has_vis(&self.bsp_data, leaf_id) {
+            return Audience::Everyone;
+        }
+
+        match target {
+            MulticastTarget::All => unreachable!(),
+            MulticastTarget::Pvs => Audience::Leaves(Rc::new(
+                self.bsp_data
+                    .get_pvs(leaf_id, self.leaf_count)
+                    .into_iter()
+                    .collect(),
+            )),
+            MulticastTarget::Phs => {
+                let bsp = &self.bsp_data;
+                let leaf_count = self.leaf_count;
+                let phs = self.phs.entry(leaf_id).or_insert_with(|| {
+                    Rc::new(hearable_leaves(&bsp.get_pvs(leaf_id, leaf_count), |id| {
+                        if has_vis(bsp, id) {
+                            Some(bsp.get_pvs(id, leaf_count))
+                        } else {
+                            None
+                        }
+                    }))
+                });
+
+                Audience::Leaves(phs.clone())
+            }
+        }
+    }
+
+    /// Returns true if a client viewing from `view_origin` is reached by `audience`.
+    pub fn includes(&self, audience: &Audience, view_origin: Vector3<f32>) -> bool {
+        match *audience {
+            Audience::Everyone => true,
+            Audience::Leaves(_) => audience.includes_leaf(self.bsp_data.find_leaf(view_origin)),
+        }
+    }
+}
+
+// leaf 0 is outside the map, and leaves without visibility data can see everything
+fn has_vis(bsp: &BspData, leaf_id: usize) -> bool {
+    leaf_id != 0 && bsp.leaves()[leaf_id].vis_offset.is_some()
+}
+
+/// Computes the potentially hearable set from the PVS of the origin leaf.
+///
+/// `pvs_of` returns the PVS of a leaf, or `None` if that leaf can see everything, in which case
+/// it's skipped rather than making the whole map hearable.
+pub fn hearable_leaves<F>(pvs: &[usize], pvs_of: F) -> HashSet<usize>
+where
+    F: Fn(usize) -> Option<Vec<usize>>,
+{
+    let mut leaves: HashSet<usize> = pvs.iter().cloned().collect();
+    for leaf_id in pvs {
+        if let Some(visible) = pvs_of(*leaf_id) {
+            leaves.extend(visible);
+        }
+    }
+
+    leaves
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hearable_leaves() {
+        // a corridor of leaves 1 - 2 - 3 - 4, each seeing only its neighbors
+        let pvs_of = |id: usize| -> Option<Vec<usize>> {
+            Some(
+                (id.saturating_sub(1).max(1)..=(id + 1).min(4))
+                    .filter(|l| *l != id)
+                    .collect(),
+            )
+        };
+
+        let phs = hearable_leaves(&pvs_of(1).unwrap(), pvs_of);
+        let mut phs: Vec<_> = phs.into_iter().collect();
+        phs.sort();
+
+        // leaf 1 sees 2, which sees 1 and 3; 4 is out of earshot
+        assert_eq!(phs, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_hearable_leaves_skips_unvised() {
+        let phs = hearable_leaves(&[2, 5], |id| match id {
+            2 => Some(vec![3]),
+            _ => None,
+        });
+        let mut phs: Vec<_> = phs.into_iter().collect();
+        phs.sort();
+
+        assert_eq!(phs, vec![2, 3, 5]);
+    }
+
+    #[test]
+    fn test_audience_includes_leaf() {
+        let audience = Audience::Leaves(Rc::new(vec![3, 7].into_iter().collect()));
+        assert!(audience.includes_leaf(7));
+        assert!(!audience.includes_leaf(4));
+        assert!(Audience::Everyone.includes_leaf(4));
+    }
+}
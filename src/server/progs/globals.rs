@@ -45,6 +45,9 @@ pub const GLOBAL_ADDR_ARG_6: usize = 22;
 #[allow(dead_code)]
 pub const GLOBAL_ADDR_ARG_7: usize = 25;
 
+/// The number of spawn arguments (`parm1` through `parm16`) carried between levels.
+pub const NUM_SPAWN_ARGS: usize = 16;
+
 #[derive(Debug)]
 pub enum GlobalsError {
     Io(::std::io::Error),
@@ -414,6 +417,26 @@ impl Globals {
         Ok(())
     }
 
+    /// Loads the spawn arguments (`parm1` through `parm16`).
+    pub fn get_spawn_args(&self) -> Result<[f32; NUM_SPAWN_ARGS], GlobalsError> {
+        let mut args = [0.0; NUM_SPAWN_ARGS];
+
+        for i in 0..NUM_SPAWN_ARGS {
+            args[i] = self.get_float(GlobalAddrFloat::Arg0 as i16 + i as i16)?;
+        }
+
+        Ok(args)
+    }
+
+    /// Stores the spawn arguments (`parm1` through `parm16`).
+    pub fn put_spawn_args(&mut self, args: &[f32; NUM_SPAWN_ARGS]) -> Result<(), GlobalsError> {
+        for i in 0..NUM_SPAWN_ARGS {
+            self.put_float(args[i], GlobalAddrFloat::Arg0 as i16 + i as i16)?;
+        }
+
+        Ok(())
+    }
+
     // TODO: typecheck these with QPointer?
 
     pub fn get_entity_field(&self, addr: i16) -> Result<i32, GlobalsError> {
@@ -564,6 +587,27 @@ mod test {
         assert_eq!(Matrix3::from_angle_z(Deg(90.0)), result);
     }
 
+    #[test]
+    fn test_spawn_args() {
+        let mut globals = Globals::new(
+            Rc::new(StringTable::new(Vec::new())),
+            Box::new([]),
+            vec![[0; 4]; GLOBAL_DYNAMIC_START].into_boxed_slice(),
+        );
+
+        let mut args = [0.0; NUM_SPAWN_ARGS];
+        for i in 0..NUM_SPAWN_ARGS {
+            args[i] = i as f32 * 10.0;
+        }
+
+        globals.put_spawn_args(&args).unwrap();
+        assert_eq!(globals.get_spawn_args().unwrap(), args);
+        assert_eq!(
+            globals.get_float(GlobalAddrFloat::Arg15 as i16).unwrap(),
+            150.0
+        );
+    }
+
     #[test]
     fn test_make_vectors_roll() {
         let roll_90 = [0.0, 0.0, 90.0];
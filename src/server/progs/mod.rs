@@ -124,7 +124,7 @@ use self::{
     functions::{BuiltinFunctionId, FunctionDef, FunctionKind, Statement, MAX_ARGS},
     globals::{
         GLOBAL_ADDR_ARG_0, GLOBAL_ADDR_ARG_1, GLOBAL_ADDR_ARG_2, GLOBAL_ADDR_ARG_3,
-        GLOBAL_ADDR_RETURN, GLOBAL_STATIC_COUNT, GLOBAL_STATIC_START,
+        GLOBAL_ADDR_ARG_4, GLOBAL_ADDR_RETURN, GLOBAL_STATIC_COUNT, GLOBAL_STATIC_START,
     },
     ops::Opcode,
 };
@@ -132,7 +132,7 @@ pub use self::{
     functions::{FunctionId, Functions},
     globals::{
         GlobalAddrEntity, GlobalAddrFloat, GlobalAddrFunction, GlobalAddrVector, Globals,
-        GlobalsError,
+        GlobalsError, NUM_SPAWN_ARGS,
     },
 };
 
@@ -891,7 +891,30 @@ impl ExecutionContext {
                             Random => {
                                 globals.put_float(rand::random(), GLOBAL_ADDR_RETURN as i16)?;
                             }
-                            Sound => unimplemented!(),
+                            Sound => {
+                                let e_id = globals.get_entity_id(GLOBAL_ADDR_ARG_0 as i16)?;
+                                let channel = globals.get_float(GLOBAL_ADDR_ARG_1 as i16)?;
+                                let name = globals.get_string_id(GLOBAL_ADDR_ARG_2 as i16)?;
+                                let volume = globals.get_float(GLOBAL_ADDR_ARG_3 as i16)?;
+                                let attenuation = globals.get_float(GLOBAL_ADDR_ARG_4 as i16)?;
+
+                                // TODO: replace with `?` syntax once `server` has a proper error type
+                                let sound_id = match server.sound_precache_lookup(name) {
+                                    Ok(i) => i,
+                                    Err(_) => {
+                                        return Err(ProgsError::with_msg("sound not precached"))
+                                    }
+                                };
+
+                                server.start_sound(
+                                    world,
+                                    e_id,
+                                    channel as i32,
+                                    sound_id,
+                                    volume,
+                                    attenuation,
+                                )?;
+                            }
                             Normalize => unimplemented!(),
                             Error => unimplemented!(),
                             ObjError => unimplemented!(),
@@ -1013,7 +1036,11 @@ impl ExecutionContext {
                             MoveToGoal => unimplemented!(),
                             PrecacheFile => unimplemented!(),
                             MakeStatic => unimplemented!(),
-                            ChangeLevel => unimplemented!(),
+                            ChangeLevel => {
+                                let s_id = globals.get_string_id(GLOBAL_ADDR_ARG_0 as i16)?;
+                                let level = self.string_table.get(s_id).unwrap();
+                                server.change_level(level);
+                            }
                             CvarSet => {
                                 let var_id = globals.get_string_id(GLOBAL_ADDR_ARG_0 as i16)?;
                                 let var = self.string_table.get(var_id).unwrap();
@@ -1041,7 +1068,19 @@ impl ExecutionContext {
                             PrecacheModel2 => unimplemented!(),
                             PrecacheSound2 => unimplemented!(),
                             PrecacheFile2 => unimplemented!(),
-                            SetSpawnArgs => unimplemented!(),
+                            SetSpawnArgs => {
+                                let e_id = globals.get_entity_id(GLOBAL_ADDR_ARG_0 as i16)?;
+                                let args = match server.client_spawn_args(e_id) {
+                                    Some(a) => *a,
+                                    None => {
+                                        return Err(ProgsError::with_msg(
+                                            "setspawnparms: entity is not a client",
+                                        ))
+                                    }
+                                };
+
+                                globals.put_spawn_args(&args)?;
+                            }
                         }
                         debug!("Returning from built-in function {}", name);
                     } else {
@@ -0,0 +1,221 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Map rotation and voting.
+//!
+//! The rotation is read from `sv_maplist`, a whitespace-separated list of map
+//! names, or if that's empty, from the file named by `sv_maplistfile` (one map
+//! per line, `//` starts a comment). When `timelimit` (in minutes) or
+//! `fraglimit` is reached, the server moves on to the map after the current
+//! one.
+//!
+//! With `sv_mapvote` set, players can also `say votemap <map>` to vote for a
+//! map in the rotation. Once more than half of the connected players agree,
+//! the level changes to that map.
+
+use std::{collections::HashMap, io::Read};
+
+use crate::common::{console::CvarRegistry, vfs::Vfs};
+
+use chrono::Duration;
+use failure::Error;
+
+/// An ordered list of maps to cycle through.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MapRotation {
+    maps: Vec<String>,
+}
+
+impl MapRotation {
+    /// Parses a list of map names separated by whitespace or newlines.
+    pub fn parse(text: &str) -> MapRotation {
+        let maps = text
+            .lines()
+            .map(|line| match line.find("//") {
+                Some(i) => &line[..i],
+                None => line,
+            })
+            .flat_map(|line| line.split_whitespace())
+            .map(|map| map.to_owned())
+            .collect();
+
+        MapRotation { maps }
+    }
+
+    /// Loads the rotation from `sv_maplist`, falling back to `sv_maplistfile`.
+    ///
+    /// If neither names any maps, the rotation is empty.
+    pub fn load(cvars: &CvarRegistry, vfs: &Vfs) -> Result<MapRotation, Error> {
+        let rotation = MapRotation::parse(&cvars.get_str("sv_maplist")?);
+        if !rotation.is_empty() {
+            return Ok(rotation);
+        }
+
+        let path = cvars.get_str("sv_maplistfile")?;
+        if path.is_empty() {
+            return Ok(rotation);
+        }
+
+        let mut file = match vfs.open(&path) {
+            Ok(f) => f,
+            Err(_) => return Ok(rotation),
+        };
+
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        Ok(MapRotation::parse(&text))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    pub fn contains(&self, map: &str) -> bool {
+        self.maps.iter().any(|m| m == map)
+    }
+
+    /// Returns the map after `current`, wrapping around at the end.
+    ///
+    /// If `current` isn't in the rotation, the rotation starts from the top.
+    pub fn next_map(&self, current: &str) -> Option<&str> {
+        let next_id = match self.maps.iter().position(|m| m == current) {
+            Some(i) => (i + 1) % self.maps.len(),
+            None => 0,
+        };
+
+        self.maps.get(next_id).map(|m| m.as_str())
+    }
+}
+
+/// Returns true if the level should end because of `timelimit` or `fraglimit`.
+///
+/// A limit of zero is disabled.
+pub fn limit_reached(level_time: Duration, timelimit: f32, top_frags: i32, fraglimit: f32) -> bool {
+    let time_up = timelimit > 0.0 && level_time >= Duration::seconds((timelimit * 60.0) as i64);
+    let frags_up = fraglimit > 0.0 && top_frags >= fraglimit as i32;
+    time_up || frags_up
+}
+
+/// Extracts the requested map from a `votemap` chat message.
+///
+/// Accepts the text of a `say` command with or without surrounding quotes.
+pub fn parse_vote(text: &str) -> Option<&str> {
+    let mut words = text.trim().trim_matches('"').split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("votemap"), Some(map), None) => Some(map),
+        _ => None,
+    }
+}
+
+/// Votes cast for the next map.
+#[derive(Debug, Default)]
+pub struct MapVote {
+    // client slot -> map voted for
+    votes: HashMap<usize, String>,
+}
+
+impl MapVote {
+    pub fn new() -> MapVote {
+        MapVote::default()
+    }
+
+    /// Records a vote, replacing any earlier vote by the same client.
+    pub fn cast(
+        &mut self,
+        rotation: &MapRotation,
+        client_id: usize,
+        map: &str,
+    ) -> Result<(), Error> {
+        ensure!(rotation.contains(map), "{} is not in the map rotation", map);
+        self.votes.insert(client_id, map.to_owned());
+        Ok(())
+    }
+
+    /// Discards the vote of a client that disconnected.
+    pub fn withdraw(&mut self, client_id: usize) {
+        self.votes.remove(&client_id);
+    }
+
+    /// Returns the number of votes for `map`.
+    pub fn count(&self, map: &str) -> usize {
+        self.votes.values().filter(|m| *m == map).count()
+    }
+
+    /// Returns the map chosen by more than half of `client_count` players, if any.
+    pub fn winner(&self, client_count: usize) -> Option<&str> {
+        self.votes
+            .values()
+            .find(|map| self.count(map) * 2 > client_count)
+            .map(|m| m.as_str())
+    }
+
+    /// Clears all votes, e.g. when the level changes.
+    pub fn clear(&mut self) {
+        self.votes.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_rotation() {
+        let rotation = MapRotation::parse("dm1 dm2 // the classics\ndm6\n\n// e1m7\n");
+        assert_eq!(rotation.next_map("dm1"), Some("dm2"));
+        assert_eq!(rotation.next_map("dm6"), Some("dm1"));
+        assert_eq!(rotation.next_map("start"), Some("dm1"));
+        assert!(!rotation.contains("e1m7"));
+        assert_eq!(MapRotation::parse("").next_map("dm1"), None);
+    }
+
+    #[test]
+    fn test_limit_reached() {
+        assert!(!limit_reached(Duration::minutes(30), 0.0, 100, 0.0));
+        assert!(limit_reached(Duration::minutes(10), 10.0, 0, 0.0));
+        assert!(!limit_reached(Duration::minutes(9), 10.0, 19, 20.0));
+        assert!(limit_reached(Duration::minutes(9), 10.0, 20, 20.0));
+    }
+
+    #[test]
+    fn test_parse_vote() {
+        assert_eq!(parse_vote("votemap dm3"), Some("dm3"));
+        assert_eq!(parse_vote("\"votemap dm3\""), Some("dm3"));
+        assert_eq!(parse_vote("votemap"), None);
+        assert_eq!(parse_vote("I want to votemap dm3"), None);
+    }
+
+    #[test]
+    fn test_map_vote_majority() {
+        let rotation = MapRotation::parse("dm1 dm2 dm3");
+        let mut vote = MapVote::new();
+
+        assert!(vote.cast(&rotation, 0, "e1m1").is_err());
+
+        vote.cast(&rotation, 0, "dm2").unwrap();
+        vote.cast(&rotation, 1, "dm2").unwrap();
+        vote.cast(&rotation, 2, "dm3").unwrap();
+        assert_eq!(vote.winner(4), None);
+
+        // changing a vote replaces the old one
+        vote.cast(&rotation, 2, "dm2").unwrap();
+        assert_eq!(vote.winner(4), Some("dm2"));
+
+        vote.withdraw(2);
+        assert_eq!(vote.winner(4), None);
+    }
+}
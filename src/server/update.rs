@@ -0,0 +1,223 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Prioritization of entity updates that don't all fit in a client's datagram.
+//!
+//! On busy maps the updates for every visible entity can exceed the datagram
+//! limit. Rather than truncating wherever the limit happens to fall, updates
+//! are sent nearest-first, and entities whose updates didn't fit are sent
+//! ahead of everything else on the next frame, longest-waiting first. This
+//! keeps nearby monsters current and ensures distant ones can't go stale
+//! indefinitely.
+
+use std::{cmp::Ordering, collections::HashMap, io::Write};
+
+use crate::common::net::{EntityUpdate, NetError, Protocol};
+
+/// An entity update waiting to be sent to a client.
+#[derive(Clone, Debug)]
+pub struct PendingUpdate {
+    pub update: EntityUpdate,
+
+    /// Distance from the client's view origin to the entity.
+    pub distance: f32,
+}
+
+/// Tracks which entity updates a client missed because of the datagram limit.
+#[derive(Debug, Default)]
+pub struct UpdateScheduler {
+    // entities whose last update didn't fit, with the number of frames since
+    // their last update was sent
+    dropped: HashMap<u16, u32>,
+}
+
+impl UpdateScheduler {
+    pub fn new() -> UpdateScheduler {
+        UpdateScheduler::default()
+    }
+
+    /// Returns true if the last update for `ent_id` was dropped.
+    pub fn was_dropped(&self, ent_id: u16) -> bool {
+        self.dropped.contains_key(&ent_id)
+    }
+
+    /// Forgets all dropped updates, e.g. when the client changes levels.
+    pub fn clear(&mut self) {
+        self.dropped.clear();
+    }
+
+    /// Writes as many of `updates` as fit in `budget` bytes.
+    ///
+    /// Updates dropped on previous frames are written first, those missed for
+    /// the most frames leading, then the rest in order of distance. Updates
+    /// that don't fit are skipped (a smaller one further down may still fit)
+    /// and remembered for the next frame. Entities without an update this
+    /// frame are forgotten, since they're no longer visible to the client.
+    /// Returns the number of bytes written.
+    pub fn write_updates<W>(
+        &mut self,
+        writer: &mut W,
+        protocol: Protocol,
+        mut updates: Vec<PendingUpdate>,
+        budget: usize,
+    ) -> Result<usize, NetError>
+    where
+        W: Write,
+    {
+        let dropped = &self.dropped;
+        updates.sort_by(|a, b| {
+            let a_missed = dropped.get(&a.update.ent_id).cloned().unwrap_or(0);
+            let b_missed = dropped.get(&b.update.ent_id).cloned().unwrap_or(0);
+            b_missed.cmp(&a_missed).then(
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .unwrap_or(Ordering::Equal),
+            )
+        });
+
+        let mut dropped = HashMap::new();
+        let mut written = 0;
+        let mut buf = Vec::new();
+        for pending in updates {
+            let ent_id = pending.update.ent_id;
+
+            buf.clear();
+            pending.update.serialize(&mut buf, protocol)?;
+
+            if written + buf.len() > budget {
+                let missed = self.dropped.get(&ent_id).cloned().unwrap_or(0);
+                dropped.insert(ent_id, missed + 1);
+                continue;
+            }
+
+            writer.write_all(&buf)?;
+            written += buf.len();
+        }
+
+        self.dropped = dropped;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pending(ent_id: u16, distance: f32) -> PendingUpdate {
+        PendingUpdate {
+            update: EntityUpdate {
+                ent_id,
+                model_id: None,
+                frame_id: None,
+                colormap: None,
+                skin_id: None,
+                effects: None,
+                origin_x: Some(0.0),
+                pitch: None,
+                origin_y: Some(0.0),
+                yaw: None,
+                origin_z: Some(0.0),
+                roll: None,
+                no_lerp: false,
+            },
+            distance,
+        }
+    }
+
+    // flags byte, entity byte and three short coordinates
+    const UPDATE_SIZE: usize = 8;
+
+    #[test]
+    fn test_nearest_updates_fit() {
+        let mut scheduler = UpdateScheduler::new();
+        let mut packet = Vec::new();
+        let updates = vec![pending(1, 500.0), pending(2, 10.0), pending(3, 100.0)];
+
+        let written = scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, 2 * UPDATE_SIZE)
+            .unwrap();
+
+        assert_eq!(written, 2 * UPDATE_SIZE);
+        assert_eq!(packet[1], 2);
+        assert_eq!(packet[UPDATE_SIZE + 1], 3);
+        assert!(scheduler.was_dropped(1));
+        assert!(!scheduler.was_dropped(2));
+    }
+
+    #[test]
+    fn test_dropped_updates_go_first() {
+        let mut scheduler = UpdateScheduler::new();
+        let mut packet = Vec::new();
+        let updates = vec![pending(1, 500.0), pending(2, 10.0)];
+        scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, UPDATE_SIZE)
+            .unwrap();
+        assert!(scheduler.was_dropped(1));
+
+        packet.clear();
+        let updates = vec![pending(1, 500.0), pending(2, 10.0)];
+        scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, UPDATE_SIZE)
+            .unwrap();
+        assert_eq!(packet[1], 1);
+        assert!(!scheduler.was_dropped(1));
+        assert!(scheduler.was_dropped(2));
+    }
+
+    #[test]
+    fn test_longest_dropped_goes_first() {
+        let mut scheduler = UpdateScheduler::new();
+        let mut packet = Vec::new();
+
+        // 3 misses two frames, 2 misses one
+        let updates = vec![pending(1, 10.0), pending(3, 500.0)];
+        scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, UPDATE_SIZE)
+            .unwrap();
+        let updates = vec![pending(2, 100.0), pending(3, 500.0)];
+        scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, 0)
+            .unwrap();
+
+        packet.clear();
+        let updates = vec![pending(2, 100.0), pending(3, 500.0)];
+        scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, UPDATE_SIZE)
+            .unwrap();
+        assert_eq!(packet[1], 3);
+        assert!(scheduler.was_dropped(2));
+    }
+
+    #[test]
+    fn test_invisible_entities_are_forgotten() {
+        let mut scheduler = UpdateScheduler::new();
+        let mut packet = Vec::new();
+        let updates = vec![pending(1, 10.0), pending(2, 500.0)];
+        scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, 0)
+            .unwrap();
+        assert!(scheduler.was_dropped(2));
+
+        // entity 2 left the client's view
+        let updates = vec![pending(1, 10.0)];
+        scheduler
+            .write_updates(&mut packet, Protocol::NETQUAKE, updates, UPDATE_SIZE)
+            .unwrap();
+        assert!(!scheduler.was_dropped(1));
+        assert!(!scheduler.was_dropped(2));
+    }
+}
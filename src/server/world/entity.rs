@@ -572,6 +572,11 @@ impl Entity {
         Ok(self.get_vector(FieldAddrVector::Origin as i16)?.into())
     }
 
+    /// Returns the position of the entity's eyes relative to its origin.
+    pub fn view_offset(&self) -> Result<Vector3<f32>, EntityError> {
+        Ok(self.get_vector(FieldAddrVector::ViewOffset as i16)?.into())
+    }
+
     pub fn min(&self) -> Result<Vector3<f32>, EntityError> {
         Ok(self.get_vector(FieldAddrVector::Mins as i16)?.into())
     }
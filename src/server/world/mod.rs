@@ -38,7 +38,7 @@ pub use self::{
 use crate::{
     common::{
         bsp,
-        bsp::{BspCollisionHull, BspLeafContents},
+        bsp::{BspCollisionHull, BspLeafContents, BspModel},
         console::CvarRegistry,
         engine, mdl,
         model::{Model, ModelKind},
@@ -308,6 +308,14 @@ impl World {
         }
     }
 
+    /// Returns the BSP model of the level itself.
+    pub fn world_model(&self) -> Result<&BspModel, ProgsError> {
+        match *self.models[1].kind() {
+            ModelKind::Brush(ref bmodel) => Ok(bmodel),
+            _ => Err(ProgsError::with_msg("world model is not a brush model")),
+        }
+    }
+
     fn find_def<S>(&self, name: S) -> Result<&FieldDef, ProgsError>
     where
         S: AsRef<str>,